@@ -0,0 +1,215 @@
+//! ブートローダーの致命的エラー
+//!
+//! GOPの初期化前に失敗すると画面には何も表示されず、実機では単なるハングに見えます。
+//! すべてのエラー経路はここの `fail` を通り、エラーコードと対処方法を
+//! UEFIコンソール（赤字）とシリアルの両方に出力してから停止します。
+
+use core::fmt::Write;
+
+use vitros_common::uefi::{self, EfiStatus};
+
+use crate::serial::SerialWriter;
+
+/// ブートローダーの致命的エラー
+///
+/// UEFI関数の失敗に起因するものは、そのステータスを保持します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootError {
+    /// Graphics Output Protocolが見つからない
+    GopNotFound(EfiStatus),
+    /// Simple File System Protocolが見つからない
+    FileSystemNotFound(EfiStatus),
+    /// ESPのルートボリュームを開けない
+    OpenVolumeFailed(EfiStatus),
+    /// kernel.elfが見つからない
+    KernelNotFound(EfiStatus),
+    /// kernel.elfの読み込みに失敗
+    KernelReadFailed(EfiStatus),
+    /// kernel.elfが有効なELF64ではない
+    InvalidKernelElf,
+    /// メモリマップの取得に失敗
+    MemoryMapFailed(EfiStatus),
+    /// メモリマップが確保したバッファに収まらない
+    MemoryMapTooLarge { required: usize, available: usize },
+    /// ExitBootServicesに失敗
+    ExitBootServicesFailed(EfiStatus),
+    /// initrd.imgの読み込みに失敗
+    InitrdReadFailed(EfiStatus),
+    /// initrd.img用のメモリを確保できない
+    InitrdAllocFailed(EfiStatus),
+    /// UEFIが割り当てたinitrdの領域がカーネルのロード先と重なった
+    InitrdOverlapsKernel,
+    /// ESPにkernel.elfがなく、ネットワークブートも使えない
+    NetworkUnavailable(EfiStatus),
+    /// TFTPでの取得に失敗
+    NetworkReadFailed(EfiStatus),
+    /// カーネルのロード先（ELFの物理アドレス）を確保できない
+    KernelAllocFailed(EfiStatus),
+    /// BootInfo用のメモリを確保できない
+    BootInfoAllocFailed(EfiStatus),
+}
+
+impl BootError {
+    /// エラーコード（画面の写真やシリアルログから特定しやすいように固定）
+    pub fn code(&self) -> u32 {
+        match self {
+            BootError::GopNotFound(_) => 1,
+            BootError::FileSystemNotFound(_) => 2,
+            BootError::OpenVolumeFailed(_) => 3,
+            BootError::KernelNotFound(_) => 4,
+            BootError::KernelReadFailed(_) => 5,
+            BootError::InvalidKernelElf => 6,
+            BootError::MemoryMapFailed(_) => 7,
+            BootError::MemoryMapTooLarge { .. } => 8,
+            BootError::ExitBootServicesFailed(_) => 9,
+            BootError::InitrdReadFailed(_) => 10,
+            BootError::InitrdAllocFailed(_) => 11,
+            BootError::InitrdOverlapsKernel => 12,
+            BootError::NetworkUnavailable(_) => 13,
+            BootError::NetworkReadFailed(_) => 14,
+            BootError::KernelAllocFailed(_) => 15,
+            BootError::BootInfoAllocFailed(_) => 16,
+        }
+    }
+
+    /// 原因となったUEFI関数のステータス
+    pub fn status(&self) -> Option<EfiStatus> {
+        match *self {
+            BootError::GopNotFound(status)
+            | BootError::FileSystemNotFound(status)
+            | BootError::OpenVolumeFailed(status)
+            | BootError::KernelNotFound(status)
+            | BootError::KernelReadFailed(status)
+            | BootError::MemoryMapFailed(status)
+            | BootError::ExitBootServicesFailed(status)
+            | BootError::InitrdReadFailed(status)
+            | BootError::InitrdAllocFailed(status)
+            | BootError::NetworkUnavailable(status)
+            | BootError::NetworkReadFailed(status)
+            | BootError::KernelAllocFailed(status)
+            | BootError::BootInfoAllocFailed(status) => Some(status),
+            BootError::InvalidKernelElf
+            | BootError::MemoryMapTooLarge { .. }
+            | BootError::InitrdOverlapsKernel => None,
+        }
+    }
+
+    /// エラーの説明
+    pub fn message(&self) -> &'static str {
+        match self {
+            BootError::GopNotFound(_) => "Graphics Output Protocol not found",
+            BootError::FileSystemNotFound(_) => "Simple File System Protocol not found",
+            BootError::OpenVolumeFailed(_) => "Failed to open the boot volume",
+            BootError::KernelNotFound(_) => "kernel.elf not found",
+            BootError::KernelReadFailed(_) => "Failed to read kernel.elf",
+            BootError::InvalidKernelElf => "kernel.elf is not a valid ELF64 image",
+            BootError::MemoryMapFailed(_) => "Failed to get the UEFI memory map",
+            BootError::MemoryMapTooLarge { .. } => "UEFI memory map does not fit in the buffer",
+            BootError::ExitBootServicesFailed(_) => "ExitBootServices failed",
+            BootError::InitrdReadFailed(_) => "Failed to read initrd.img",
+            BootError::InitrdAllocFailed(_) => "Failed to allocate memory for initrd.img",
+            BootError::InitrdOverlapsKernel => "initrd.img was placed over the kernel image",
+            BootError::NetworkUnavailable(_) => {
+                "kernel.elf not found on the ESP and network boot is unavailable"
+            }
+            BootError::NetworkReadFailed(_) => "Failed to fetch a file over TFTP",
+            BootError::KernelAllocFailed(_) => "Kernel load address is not available",
+            BootError::BootInfoAllocFailed(_) => "Failed to allocate memory for BootInfo",
+        }
+    }
+
+    /// 推奨される対処方法
+    pub fn hint(&self) -> &'static str {
+        match self {
+            BootError::GopNotFound(_) => {
+                "Enable a UEFI graphics adapter (QEMU: use -vga std, not -nographic alone)"
+            }
+            BootError::FileSystemNotFound(_) | BootError::OpenVolumeFailed(_) => {
+                "Boot from a FAT-formatted EFI System Partition"
+            }
+            BootError::KernelNotFound(_) => {
+                "Copy kernel.elf to the root of the EFI System Partition"
+            }
+            BootError::KernelReadFailed(_) => {
+                "Check the boot media for errors and rewrite kernel.elf"
+            }
+            BootError::InvalidKernelElf => "Rebuild the kernel for x86_64-unknown-none",
+            BootError::MemoryMapFailed(_) | BootError::MemoryMapTooLarge { .. } => {
+                "Reduce the number of memory regions (fewer devices) or report a firmware bug"
+            }
+            BootError::ExitBootServicesFailed(_) => {
+                "Retry the boot; the firmware changed the memory map during handoff"
+            }
+            BootError::InitrdReadFailed(_) => {
+                "Rewrite initrd.img, or remove it to boot without an initramfs"
+            }
+            BootError::InitrdAllocFailed(_) => "Shrink initrd.img or give the machine more memory",
+            BootError::InitrdOverlapsKernel => "Shrink initrd.img or give the machine more memory",
+            BootError::NetworkUnavailable(_) => {
+                "Copy kernel.elf to the ESP, or enable PXE with a DHCP server that sets next-server"
+            }
+            BootError::NetworkReadFailed(_) => {
+                "Check that the TFTP server is reachable and serves kernel.elf from its root"
+            }
+            BootError::KernelAllocFailed(_) => {
+                "The firmware uses the kernel's physical load address; report the memory map"
+            }
+            BootError::BootInfoAllocFailed(_) => "Give the machine more memory below 4GB",
+        }
+    }
+}
+
+impl core::fmt::Display for BootError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "E{:02}: {}", self.code(), self.message())?;
+        if let BootError::MemoryMapTooLarge {
+            required,
+            available,
+        } = self
+        {
+            write!(f, " (required {}, available {})", required, available)?;
+        }
+        Ok(())
+    }
+}
+
+/// 致命的エラーをUEFIコンソールとシリアルに出力して停止
+///
+/// ExitBootServices前にのみ呼び出せます（ConOutを使用するため）。
+///
+pub fn fail(error: BootError) -> ! {
+    let status = error.status();
+    let mut serial = SerialWriter;
+    let _ = writeln!(serial, "\n[BOOT ERROR] {}", error);
+    if let Some(status) = status {
+        let _ = writeln!(
+            serial,
+            "  status: 0x{:X} ({})",
+            status,
+            uefi::status_name(status)
+        );
+    }
+    let _ = writeln!(serial, "  hint: {}", error.hint());
+
+    crate::hide_splash();
+    crate::set_con_attribute(uefi::efi_text_attr(
+        uefi::EFI_LIGHTRED,
+        uefi::EFI_BACKGROUND_BLACK,
+    ));
+    crate::println_con("");
+    println_uefi!("[BOOT ERROR] {}", error);
+    if let Some(status) = status {
+        println_uefi!("  status: 0x{:X} ({})", status, uefi::status_name(status));
+    }
+    crate::set_con_attribute(uefi::efi_text_attr(
+        uefi::EFI_LIGHTGRAY,
+        uefi::EFI_BACKGROUND_BLACK,
+    ));
+    println_uefi!("  hint: {}", error.hint());
+    crate::println_con("System halted.");
+
+    loop {
+        // SAFETY: hltは次の割り込みまでCPUを停止するだけ
+        unsafe { core::arch::asm!("hlt") }
+    }
+}
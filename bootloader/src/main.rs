@@ -1,12 +1,18 @@
 #![no_std]
 #![no_main]
 
+use boot_error::BootError;
 use core::fmt::Write;
 #[cfg(not(test))]
 use core::panic::PanicInfo;
-use vitros_common::boot_info::{BootInfo, FramebufferInfo, MemoryRegion};
+use vitros_common::boot_info::{
+    BootInfo, CMDLINE_MAX, FramebufferInfo, KernelSegment, MAX_KERNEL_SEGMENTS, MemoryRegion,
+};
+use vitros_common::cmdline;
+use vitros_common::display_mode::{self, ModeCandidate, ModeRequest};
 use vitros_common::elf::{Elf64Header, Elf64ProgramHeader, PT_LOAD};
 use vitros_common::uefi::*;
+use vitros_common::utf16::{self, Utf16Buf};
 
 // BOOT_INFOを静的変数として配置
 // リンカがアドレスを決定し、物理アドレスをカーネルに渡す
@@ -15,21 +21,28 @@ static mut BOOT_INFO: BootInfo = BootInfo::new();
 // グローバルなConOut（初期化後に設定）
 static mut CON_OUT: Option<*mut EfiSimpleTextOutputProtocol> = None;
 
+// 起動画面の表示中か（メッセージはConOutではなくシリアルに出力する）
+static mut SPLASH_SHOWN: bool = false;
+
 // ConOutに文字列を出力するヘルパー関数
 fn print_con(s: &str) {
     unsafe {
+        if SPLASH_SHOWN {
+            let _ = serial::SerialWriter.write_str(s);
+            return;
+        }
         if let Some(con_out) = CON_OUT {
+            // バッファに収まらない分は続けて出力する
             let mut buffer = [0u16; 256];
-            let mut len = 0;
-            for c in s.chars() {
-                if len >= buffer.len() - 1 {
+            let mut rest = s;
+            while !rest.is_empty() {
+                let (_, consumed) = utf16::encode_truncated(rest, &mut buffer);
+                if consumed == 0 {
                     break;
                 }
-                buffer[len] = c as u16;
-                len += 1;
+                ((*con_out).output_string)(con_out, buffer.as_ptr());
+                rest = &rest[consumed..];
             }
-            buffer[len] = 0; // null terminator
-            ((*con_out).output_string)(con_out, buffer.as_ptr());
         }
     }
 }
@@ -40,6 +53,15 @@ fn println_con(s: &str) {
     print_con("\r\n");
 }
 
+// ConOutのテキスト属性（前景色・背景色）を設定
+fn set_con_attribute(attribute: usize) {
+    unsafe {
+        if let Some(con_out) = CON_OUT {
+            ((*con_out).set_attribute)(con_out, attribute);
+        }
+    }
+}
+
 // 固定サイズバッファを使ったフォーマット出力
 struct BufWriter {
     buf: [u8; 512],
@@ -74,15 +96,35 @@ impl Write for BufWriter {
 macro_rules! println_uefi {
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        let mut buf = BufWriter::new();
+        let mut buf = $crate::BufWriter::new();
         let _ = write!(buf, $($arg)*);
-        println_con(buf.as_str());
+        $crate::println_con(buf.as_str());
     }};
 }
 
+// 起動画面の表示をやめ、以降のメッセージをConOutに出力する
+fn hide_splash() {
+    unsafe {
+        if !SPLASH_SHOWN {
+            return;
+        }
+        SPLASH_SHOWN = false;
+        if let Some(con_out) = CON_OUT {
+            ((*con_out).clear_screen)(con_out);
+        }
+    }
+}
+
+// 以下のモジュールはprintln_uefi!を使用するため、マクロ定義の後で宣言する
+mod boot_error;
+mod netboot;
+mod serial;
+mod splash;
+
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    let _ = writeln!(serial::SerialWriter, "\n!!! BOOTLOADER PANIC !!!\n{}", info);
     println_con("\n!!! BOOTLOADER PANIC !!!");
     println_uefi!("{}", info);
     loop {
@@ -119,6 +161,38 @@ const PAGE_HUGE: u64 = 1 << 7;
 // カーネル仮想アドレスベース
 const KERNEL_VMA: u64 = 0xFFFF800000000000;
 
+// 読み込むファイル（ESPまたはTFTPのルート）
+const KERNEL_FILE: &str = "kernel.elf";
+const INITRD_FILE: &str = "initrd.img";
+const CMDLINE_FILE: &str = "cmdline.txt";
+
+// cmdline.txtの最大サイズ（コメントを含む）
+const CMDLINE_FILE_MAX: usize = 4096;
+
+// ExitBootServicesの最大試行回数（メモリマップが変わって失敗した場合に再試行する）
+const EXIT_BOOT_SERVICES_ATTEMPTS: u32 = 5;
+
+// 表示モードの要求（ビルド時の環境変数 VITROS_RESOLUTION: `<幅>x<高さ>`、`max`、`current`）
+const RESOLUTION: &str = match option_env!("VITROS_RESOLUTION") {
+    Some(resolution) => resolution,
+    None => DEFAULT_RESOLUTION,
+};
+
+// VITROS_RESOLUTIONを指定しない場合の表示モード
+const DEFAULT_RESOLUTION: &str = "1920x1080";
+
+// 起動画面を使わず、起動中のログを画面に表示するか（ビルド時の環境変数 VITROS_BOOT_VERBOSE=1）
+const VERBOSE: bool = match option_env!("VITROS_BOOT_VERBOSE") {
+    Some(value) => matches!(value.as_bytes(), b"1"),
+    None => false,
+};
+
+// UEFIに渡すファイル名の最大長（NUL終端を含む）
+const PATH_LEN: usize = 256;
+
+// 表示するファームウェアベンダー名の最大長（UTF-16のコードユニット数）
+const FIRMWARE_VENDOR_MAX: usize = 64;
+
 // ページテーブル構造体（4KBアラインメント）
 #[repr(C, align(4096))]
 struct PageTable {
@@ -251,6 +325,7 @@ extern "efiapi" fn efi_main(
     }
 
     println_con("=== VitrOS Bootloader ===");
+    print_firmware_vendor(system_table);
     println_uefi!("[INFO] UEFI ConOut initialized");
     println_uefi!("[INFO] Locating Graphics Output Protocol...");
 
@@ -270,16 +345,16 @@ extern "efiapi" fn efi_main(
     };
 
     if status != EFI_SUCCESS {
-        println_uefi!("[ERROR] Failed to locate GOP!");
-        loop {
-            unsafe { core::arch::asm!("hlt") }
-        }
+        boot_error::fail(BootError::GopNotFound(status));
     }
 
     println_uefi!("[INFO] GOP found successfully");
 
+    // 要求に近い表示モードに切り替える（失敗してもファームウェアのモードで続行する）
+    set_gop_mode(boot_services, gop);
+
     // SAFETY: GOP から有効なフレームバッファ情報を取得
-    let (fb_base, fb_size, width, height) = unsafe {
+    let (fb_base, fb_size, width, height, stride, pixel_format, pixel_bitmask) = unsafe {
         let mode = (*gop).mode;
         let mode_info = (*mode).info;
         (
@@ -287,9 +362,20 @@ extern "efiapi" fn efi_main(
             (*mode).frame_buffer_size,
             (*mode_info).horizontal_resolution,
             (*mode_info).vertical_resolution,
+            (*mode_info).pixels_per_scan_line,
+            (*mode_info).pixel_format,
+            (*mode_info).pixel_information,
         )
     };
 
+    println_uefi!(
+        "[INFO] GOP mode: {}x{} (stride {}), pixel format: {}",
+        width,
+        height,
+        stride,
+        pixel_format
+    );
+
     // 画面クリア（ConOut使用）
     unsafe {
         if let Some(con_out) = CON_OUT {
@@ -297,6 +383,42 @@ extern "efiapi" fn efi_main(
         }
     }
 
+    // カーネルとinitramfsの読み込み元を決める（ESPを優先し、なければネットワーク）
+    let source = select_boot_source(boot_services).unwrap_or_else(|e| boot_error::fail(e));
+
+    // initramfsを読み込む（メモリマップに反映させるため、メモリマップ取得前に行う）
+    let initrd = load_initrd(boot_services, &source).unwrap_or_else(|e| boot_error::fail(e));
+    match initrd {
+        Some((addr, size)) => {
+            println_uefi!("[INFO] initrd.img loaded at 0x{:X} ({} bytes)", addr, size)
+        }
+        None => println_uefi!("[INFO] No initrd.img found, booting without initramfs"),
+    }
+
+    // カーネルコマンドラインを読み込む（cmdline.txt、なければロードオプション）
+    let mut cmdline_buf = [0u8; CMDLINE_MAX];
+    let cmdline_len = load_cmdline(image_handle, boot_services, &source, &mut cmdline_buf);
+    if cmdline_len != 0 {
+        println_uefi!(
+            "[INFO] Kernel command line: {}",
+            core::str::from_utf8(&cmdline_buf[..cmdline_len]).unwrap_or("")
+        );
+    }
+
+    // 起動画面を表示（以降のメッセージはシリアルにのみ出力される）
+    let fb_info = FramebufferInfo {
+        base: fb_base,
+        size: fb_size as u64,
+        width,
+        height,
+        stride,
+        pixel_format,
+        pixel_bitmask,
+    };
+    if !VERBOSE && splash::show(&fb_info, initrd) {
+        unsafe { SPLASH_SHOWN = true };
+    }
+
     println_uefi!("\nVitrOS - Memory Map\n");
 
     // メモリマップを取得
@@ -334,14 +456,17 @@ extern "efiapi" fn efi_main(
     // BOOT_INFOを静的変数から取得
     let boot_info = unsafe { &mut *core::ptr::addr_of_mut!(BOOT_INFO) };
 
+    if let Some((addr, size)) = initrd {
+        boot_info.initrd_address = addr;
+        boot_info.initrd_size = size;
+    }
+    boot_info.cmdline = cmdline_buf;
+    boot_info.cmdline_len = cmdline_len;
+
     // フレームバッファ情報を設定
-    boot_info.framebuffer = FramebufferInfo {
-        base: fb_base,
-        size: fb_size as u64,
-        width,
-        height,
-        stride: width,
-    };
+    boot_info.framebuffer = fb_info;
+    // 起動画面を表示できなかった場合は、カーネルもログを表示する
+    boot_info.verbose = unsafe { !SPLASH_SHOWN };
 
     // RSDP (ACPI Root System Description Pointer) を UEFI Configuration Table から取得
     unsafe {
@@ -399,134 +524,100 @@ extern "efiapi" fn efi_main(
         }
 
         println_uefi!("\nTotal entries: {}", entry_count);
-
-        // BootInfo にメモリマップをコピー
-        for i in 0..entry_count.min(boot_info.memory_map.len()) {
-            let offset = i * descriptor_size;
-            let desc = unsafe { &*(buffer.as_ptr().add(offset) as *const EfiMemoryDescriptor) };
-
-            boot_info.memory_map[i] = MemoryRegion {
-                start: desc.physical_start,
-                size: desc.number_of_pages * 4096,
-                region_type: desc.r#type,
-            };
-        }
-        boot_info.memory_map_count = entry_count.min(boot_info.memory_map.len());
-
-        // メモリマップを解析して最大物理アドレスを計算
-        let max_phys_addr = analyze_memory_map(&buffer[..map_size], entry_count, descriptor_size);
-        boot_info.max_physical_address = max_phys_addr;
-
-        let boot_info_addr = core::ptr::addr_of!(BOOT_INFO) as u64;
-        println_uefi!("[INFO] BOOT_INFO at 0x{:X}", boot_info_addr);
-        println_uefi!(
-            "[INFO] BOOT_INFO.memory_map_count = {}",
-            boot_info.memory_map_count
-        );
-        println_uefi!(
-            "[INFO] BOOT_INFO.max_physical_address = 0x{:X} ({} MB)",
-            boot_info.max_physical_address,
-            boot_info.max_physical_address / (1024 * 1024)
-        );
-        println_uefi!(
-            "[INFO] BOOT_INFO.memory_map[0]: start=0x{:X}, size=0x{:X}, type={}",
-            boot_info.memory_map[0].start,
-            boot_info.memory_map[0].size,
-            boot_info.memory_map[0].region_type
-        );
+        // カーネルに渡すメモリマップは、カーネルとBootInfoを確保した後にExitBootServices直前のものを使う
     }
 
     // カーネルをロード (ブートサービス終了前に実行)
     println_uefi!("[INFO] Loading kernel from ELF...");
-    let kernel_entry = load_kernel_elf(image_handle, boot_services);
-    if kernel_entry == 0 {
-        println_uefi!("[ERROR] Failed to load kernel!");
-        loop {
-            unsafe { core::arch::asm!("hlt") }
-        }
-    }
+    let initrd =
+        (boot_info.initrd_size != 0).then_some((boot_info.initrd_address, boot_info.initrd_size));
+    let kernel_entry = load_kernel_elf(image_handle, boot_services, &source, initrd, boot_info)
+        .unwrap_or_else(|e| boot_error::fail(e));
     println_uefi!("[INFO] Kernel entry point: 0x{:X}", kernel_entry);
 
+    // BootInfoの転送先を確保（カーネルのメモリマップ上でEfiLoaderDataとして扱われる）
+    let boot_info_phys_addr =
+        allocate_boot_info(boot_services).unwrap_or_else(|e| boot_error::fail(e));
+    println_uefi!(
+        "[INFO] BootInfo will be placed at 0x{:X}",
+        boot_info_phys_addr
+    );
+
     // カーネルロード後にメモリマップが変更されているので、再取得
     println_uefi!("[INFO] Updating memory map before ExitBootServices...");
 
-    // まず必要なサイズを取得
-    map_size = 0;
-    unsafe {
-        ((*boot_services).get_memory_map)(
-            &mut map_size,
-            core::ptr::null_mut(),
-            &mut map_key,
-            &mut descriptor_size,
-            &mut descriptor_version,
-        );
-    }
-
-    map_size += descriptor_size;
-
-    if map_size > buffer.len() {
-        println_uefi!(
-            "[ERROR] Memory map too large! Required: {}, Available: {}",
-            map_size,
-            buffer.len()
-        );
-        loop {
-            unsafe { core::arch::asm!("hlt") }
+    // ファームウェアはGetMemoryMapとExitBootServicesの間にメモリマップを変更することがある
+    // （タイマーイベントやコンソール出力など）。その場合ExitBootServicesはEFI_INVALID_PARAMETERを
+    // 返すので、メモリマップを取り直して再試行する。取得から終了までの間はメモリの確保や
+    // コンソール出力など、メモリマップを変えうるBoot Servicesを一切呼ばない
+    let mut retries = 0;
+    loop {
+        map_size = buffer.len();
+        // SAFETY: bufferはmap_sizeバイト書き込める
+        let status = unsafe {
+            ((*boot_services).get_memory_map)(
+                &mut map_size,
+                buffer.as_mut_ptr() as *mut EfiMemoryDescriptor,
+                &mut map_key,
+                &mut descriptor_size,
+                &mut descriptor_version,
+            )
+        };
+        if status == EFI_BUFFER_TOO_SMALL {
+            boot_error::fail(BootError::MemoryMapTooLarge {
+                required: map_size,
+                available: buffer.len(),
+            });
         }
-    }
-
-    let status = unsafe {
-        ((*boot_services).get_memory_map)(
-            &mut map_size,
-            buffer.as_mut_ptr() as *mut EfiMemoryDescriptor,
-            &mut map_key,
-            &mut descriptor_size,
-            &mut descriptor_version,
-        )
-    };
-    if status != EFI_SUCCESS {
-        println_uefi!(
-            "[ERROR] Failed to get updated memory map! Status: 0x{:X}",
-            status
-        );
-        loop {
-            unsafe { core::arch::asm!("hlt") }
+        if status != EFI_SUCCESS {
+            boot_error::fail(BootError::MemoryMapFailed(status));
         }
-    }
 
-    // SAFETY: UEFI 関数呼び出し - ブートサービス終了
-    // GetMemoryMap後はBoot Serviceを使用しない（MapKeyが無効になるため）
-    let status = unsafe { ((*boot_services).exit_boot_services)(image_handle, map_key) };
-
-    if status != EFI_SUCCESS {
-        // ExitBootServicesが失敗した場合は、まだBootServicesが有効なのでConOutが使える
-        println_uefi!(
-            "[ERROR] Failed to exit boot services! Status: 0x{:X}",
-            status
-        );
-        loop {
-            unsafe { core::arch::asm!("hlt") }
+        // SAFETY: UEFI 関数呼び出し - ブートサービス終了
+        let status = unsafe { ((*boot_services).exit_boot_services)(image_handle, map_key) };
+        if status == EFI_SUCCESS {
+            break;
+        }
+        retries += 1;
+        if status != EFI_INVALID_PARAMETER || retries >= EXIT_BOOT_SERVICES_ATTEMPTS {
+            // ExitBootServicesが失敗した場合は、まだBootServicesが有効なのでConOutが使える
+            boot_error::fail(BootError::ExitBootServicesFailed(status));
         }
     }
 
     // ExitBootServices成功 - ここから先はBoot Servicesは使用不可
+    if retries > 0 {
+        let _ = writeln!(
+            serial::SerialWriter,
+            "[INFO] ExitBootServices succeeded after {} retries (memory map changed)",
+            retries
+        );
+    }
 
-    // BOOT_INFOを低位アドレス（4GB未満）にコピー
-    // UEFIがMMIOホールの影響でBOOT_INFOを高位アドレス（4GB以上）に配置する可能性があるため、
-    // カーネルが確実にアクセスできる低位アドレスにコピーする
+    // 最終的なメモリマップをBootInfoに格納（カーネル・initrd・BootInfoの領域はEfiLoaderData）
+    store_memory_map(boot_info, &buffer[..map_size], descriptor_size);
+    let _ = writeln!(
+        serial::SerialWriter,
+        "[INFO] Memory map: {} entries, max physical address 0x{:X}",
+        boot_info.memory_map_count,
+        boot_info.max_physical_address
+    );
+
+    // BOOT_INFOを確保済みの低位アドレス（4GB未満）にコピー
+    // BOOT_INFO自体はブートローダーのイメージ内にあり、UEFIがMMIOホールの影響で
+    // 高位アドレス（4GB以上）に配置する可能性があるため、カーネルが確実にアクセスできる領域に移す
     //
     // TODO: 将来的にはMMIOホールによって分断されたメモリ領域を適切にマッピングし、
     //       高位アドレスに配置されたデータにもアクセスできるようにする。
     //       参照: https://github.com/jugeeeemu-tech/VitrOS/issues/5
-    const LOW_BOOT_INFO_ADDR: u64 = 0x80000; // 512KB（安全な低位アドレス）
-    let boot_info_phys_addr = unsafe {
+    // SAFETY: 転送先はallocate_boot_infoでBootInfoの大きさ分確保した領域
+    unsafe {
         core::ptr::copy_nonoverlapping(
             core::ptr::addr_of!(BOOT_INFO),
-            LOW_BOOT_INFO_ADDR as *mut BootInfo,
+            boot_info_phys_addr as *mut BootInfo,
             1,
         );
-        LOW_BOOT_INFO_ADDR
-    };
+    }
 
     // ページテーブルをセットアップ（UEFIメモリマップに基づいて必要な範囲のみマッピング）
     let pml4_addr = unsafe { setup_initial_page_tables(boot_info.max_physical_address) };
@@ -543,8 +634,59 @@ extern "efiapi" fn efi_main(
     kernel_fn(boot_info_phys_addr);
 }
 
-/// ELFファイルからカーネルをロード
-fn load_kernel_elf(_image_handle: EfiHandle, boot_services: *mut EfiBootServices) -> u64 {
+/// BootInfoの転送先を4GB未満に確保
+///
+/// # Returns
+/// 確保した領域の物理アドレス
+///
+/// # Errors
+/// 4GB未満に空きがない場合
+fn allocate_boot_info(boot_services: *mut EfiBootServices) -> Result<u64, BootError> {
+    const BELOW_4GB: u64 = 0xFFFF_FFFF;
+    let pages = core::mem::size_of::<BootInfo>().div_ceil(4096);
+    let mut addr = BELOW_4GB;
+    // SAFETY: UEFI 関数の呼び出し
+    let status = unsafe {
+        ((*boot_services).allocate_pages)(
+            EFI_ALLOCATE_MAX_ADDRESS,
+            EFI_LOADER_DATA,
+            pages,
+            &mut addr,
+        )
+    };
+    if status != EFI_SUCCESS {
+        return Err(BootError::BootInfoAllocFailed(status));
+    }
+    Ok(addr)
+}
+
+/// UEFIメモリマップをBootInfoに格納し、マッピングが必要な最大物理アドレスを求める
+///
+/// ExitBootServicesの後にも呼び出せます（Boot Servicesを使用しない）。
+fn store_memory_map(boot_info: &mut BootInfo, map: &[u8], descriptor_size: usize) {
+    let entry_count = map.len() / descriptor_size;
+    let count = entry_count.min(boot_info.memory_map.len());
+    for i in 0..count {
+        // SAFETY: バッファ内の有効なメモリディスクリプタを参照
+        let desc =
+            unsafe { &*(map.as_ptr().add(i * descriptor_size) as *const EfiMemoryDescriptor) };
+        boot_info.memory_map[i] = MemoryRegion {
+            start: desc.physical_start,
+            size: desc.number_of_pages * 4096,
+            region_type: desc.r#type,
+        };
+    }
+    boot_info.memory_map_count = count;
+    boot_info.max_physical_address = analyze_memory_map(map, entry_count, descriptor_size);
+}
+
+/// ブートボリューム（ESP）のルートディレクトリを開く
+///
+/// # Errors
+/// Simple File System Protocolが見つからない、またはボリュームを開けない場合
+fn open_root_volume(
+    boot_services: *mut EfiBootServices,
+) -> Result<*mut EfiFileProtocol, BootError> {
     // Simple File System Protocolを直接検索
     let mut sfs: *mut EfiSimpleFileSystemProtocol = core::ptr::null_mut();
     let status = unsafe {
@@ -555,85 +697,586 @@ fn load_kernel_elf(_image_handle: EfiHandle, boot_services: *mut EfiBootServices
         )
     };
     if status != EFI_SUCCESS {
-        println_uefi!("[ERROR] Failed to locate Simple File System Protocol");
-        return 0;
+        return Err(BootError::FileSystemNotFound(status));
     }
 
     // ルートディレクトリを開く
     let mut root: *mut EfiFileProtocol = core::ptr::null_mut();
     let status = unsafe { ((*sfs).open_volume)(sfs, &mut root) };
     if status != EFI_SUCCESS {
-        println_uefi!("[ERROR] Failed to open root volume");
-        return 0;
+        return Err(BootError::OpenVolumeFailed(status));
     }
+    Ok(root)
+}
+
+/// カーネルとinitrdの読み込み元
+enum BootSource {
+    /// ESP（既定）
+    Esp,
+    /// PXE Base CodeによるTFTP
+    Network(netboot::NetBoot),
+}
 
-    // kernel.elfを開く
-    let kernel_name = to_utf16("kernel.elf");
-    let mut kernel_file: *mut EfiFileProtocol = core::ptr::null_mut();
+/// 読み込み元を決める
+///
+/// ESPにkernel.elfがあればESPから、なければネットワークから読み込みます。
+///
+/// # Errors
+/// ESPにkernel.elfがなく、ネットワークブートも使えない場合
+fn select_boot_source(boot_services: *mut EfiBootServices) -> Result<BootSource, BootError> {
+    if let Ok(root) = open_root_volume(boot_services) {
+        let found = open_file(root, KERNEL_FILE).map(|file| unsafe { ((*file).close)(file) });
+        unsafe { ((*root).close)(root) };
+        if found.is_ok() {
+            return Ok(BootSource::Esp);
+        }
+    }
+
+    println_uefi!("[INFO] kernel.elf not found on the ESP, trying network boot...");
+    netboot::NetBoot::open(boot_services).map(BootSource::Network)
+}
+
+/// initramfsイメージ（initrd.img）を読み込む
+///
+/// イメージはAllocatePagesで確保したEfiLoaderData領域に置かれるため、
+/// カーネルのフレームアロケータが空き領域として扱うことはありません。
+///
+/// # Returns
+/// (物理アドレス, サイズ)。initrd.imgが存在しなければNone
+///
+/// # Errors
+/// ファイルの読み込み、またはメモリの確保に失敗した場合
+fn load_initrd(
+    boot_services: *mut EfiBootServices,
+    source: &BootSource,
+) -> Result<Option<(u64, u64)>, BootError> {
+    let net = match source {
+        BootSource::Esp => return load_initrd_from_esp(boot_services),
+        BootSource::Network(net) => net,
+    };
+    let size = match net.file_size(INITRD_FILE)? {
+        Some(size) if size > 0 => size,
+        _ => return Ok(None),
+    };
+    let (addr, pages) = allocate_initrd(boot_services, size)?;
+    if let Err(e) = net.read(INITRD_FILE, addr as *mut u8, size) {
+        unsafe { ((*boot_services).free_pages)(addr, pages) };
+        return Err(e);
+    }
+    Ok(Some((addr, size)))
+}
+
+/// initrd用の領域をAllocatePagesで確保
+///
+/// # Returns
+/// (物理アドレス, ページ数)
+fn allocate_initrd(
+    boot_services: *mut EfiBootServices,
+    size: u64,
+) -> Result<(u64, usize), BootError> {
+    let pages = size.div_ceil(4096) as usize;
+    let mut addr: u64 = 0;
     let status = unsafe {
-        ((*root).open)(
-            root,
-            &mut kernel_file,
-            kernel_name.as_ptr(),
-            EFI_FILE_MODE_READ,
-            0,
-        )
+        ((*boot_services).allocate_pages)(EFI_ALLOCATE_ANY_PAGES, EFI_LOADER_DATA, pages, &mut addr)
     };
     if status != EFI_SUCCESS {
-        println_uefi!("[ERROR] Failed to open kernel.elf");
-        return 0;
+        return Err(BootError::InitrdAllocFailed(status));
     }
+    Ok((addr, pages))
+}
+
+/// カーネルコマンドラインを読み込む
+///
+/// 読み込み元の `cmdline.txt` を優先し、なければイメージのロードオプション（UEFIシェルの
+/// 引数やブートエントリのオプション）を使います。どちらもなければ空のままです。
+/// 読み込みに失敗しても起動は続けます。
+///
+/// # Returns
+/// `buf` に書き込んだバイト数
+fn load_cmdline(
+    image_handle: EfiHandle,
+    boot_services: *mut EfiBootServices,
+    source: &BootSource,
+    buf: &mut [u8; CMDLINE_MAX],
+) -> usize {
+    let mut file = [0u8; CMDLINE_FILE_MAX];
+    let text = match read_cmdline_file(boot_services, source, &mut file) {
+        Ok(Some(len)) => core::str::from_utf8(&file[..len]).unwrap_or_else(|_| {
+            println_uefi!("[WARN] {} is not valid UTF-8, ignoring", CMDLINE_FILE);
+            ""
+        }),
+        Ok(None) => {
+            let mut options = [0u8; CMDLINE_MAX * 3];
+            let text = load_options(image_handle, boot_services, &mut options);
+            return cmdline::normalize(text, buf);
+        }
+        Err(status) => {
+            println_uefi!(
+                "[WARN] Failed to read {}: {}",
+                CMDLINE_FILE,
+                status_name(status)
+            );
+            ""
+        }
+    };
+    cmdline::normalize(text, buf)
+}
+
+/// 読み込み元からcmdline.txtを読み込む
+///
+/// # Returns
+/// 読み込んだバイト数。ファイルが存在しなければNone
+///
+/// # Errors
+/// ファイルの読み込みに失敗した、または `buf` に収まらない場合のステータス
+fn read_cmdline_file(
+    boot_services: *mut EfiBootServices,
+    source: &BootSource,
+    buf: &mut [u8; CMDLINE_FILE_MAX],
+) -> Result<Option<usize>, EfiStatus> {
+    if let BootSource::Network(net) = source {
+        return match net.file_size(CMDLINE_FILE) {
+            Ok(Some(size)) if size as usize <= buf.len() => net
+                .read(CMDLINE_FILE, buf.as_mut_ptr(), size)
+                .map(|()| Some(size as usize))
+                .map_err(|e| e.status().unwrap_or(EFI_LOAD_ERROR)),
+            Ok(Some(_)) => Err(EFI_BUFFER_TOO_SMALL),
+            Ok(None) | Err(_) => Ok(None),
+        };
+    }
+
+    let Ok(root) = open_root_volume(boot_services) else {
+        return Ok(None);
+    };
+    let result = match open_file(root, CMDLINE_FILE) {
+        Ok(file) => {
+            let mut size = buf.len();
+            // SAFETY: fileは開いたファイル、bufはsizeバイト書き込める
+            let status = unsafe {
+                let status = ((*file).read)(file, &mut size, buf.as_mut_ptr() as *mut _);
+                ((*file).close)(file);
+                status
+            };
+            if status == EFI_SUCCESS {
+                Ok(Some(size))
+            } else {
+                Err(status)
+            }
+        }
+        Err(_) => Ok(None),
+    };
+    unsafe { ((*root).close)(root) };
+    result
+}
 
-    // ファイルを一時バッファに読み込む (最大2MB - staticを使用)
-    static mut FILE_BUFFER: [u8; 2 * 1024 * 1024] = [0; 2 * 1024 * 1024];
-    let file_buffer = unsafe { &mut *core::ptr::addr_of_mut!(FILE_BUFFER) };
-    let mut file_size = file_buffer.len();
+/// イメージのロードオプションをUTF-8で取得
+///
+/// UEFIシェルから起動した場合、先頭にはイメージ自身のパスが入るため取り除きます。
+fn load_options(
+    image_handle: EfiHandle,
+    boot_services: *mut EfiBootServices,
+    buf: &mut [u8],
+) -> &str {
+    let mut loaded_image: *mut EfiLoadedImageProtocol = core::ptr::null_mut();
+    // SAFETY: UEFI 関数の呼び出し
     let status = unsafe {
-        ((*kernel_file).read)(
-            kernel_file,
-            &mut file_size,
-            file_buffer.as_mut_ptr() as *mut core::ffi::c_void,
+        ((*boot_services).handle_protocol)(
+            image_handle,
+            &EFI_LOADED_IMAGE_PROTOCOL_GUID,
+            &mut loaded_image as *mut *mut _ as *mut *mut core::ffi::c_void,
         )
     };
+    if status != EFI_SUCCESS || loaded_image.is_null() {
+        return "";
+    }
+    // SAFETY: Loaded Image Protocolはファームウェアが管理する有効な構造体
+    let (options, size) = unsafe {
+        (
+            (*loaded_image).load_options as *const u16,
+            (*loaded_image).load_options_size as usize,
+        )
+    };
+    if options.is_null() || size < 2 {
+        return "";
+    }
+    // SAFETY: load_optionsはload_options_sizeバイト読み出せる
+    let units = unsafe { core::slice::from_raw_parts(options, size / 2) };
+    let Ok(text) = utf16::decode(units, buf) else {
+        println_uefi!("[WARN] Load options are too long, ignoring");
+        return "";
+    };
+    let text = text.trim();
+    let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let is_image_path =
+        first.len() >= 4 && first.as_bytes()[first.len() - 4..].eq_ignore_ascii_case(b".efi");
+    if is_image_path { rest } else { text }
+}
+
+/// ESPからinitrd.imgを読み込む
+fn load_initrd_from_esp(
+    boot_services: *mut EfiBootServices,
+) -> Result<Option<(u64, u64)>, BootError> {
+    let root = open_root_volume(boot_services)?;
+
+    let Ok(file) = open_file(root, INITRD_FILE) else {
+        unsafe { ((*root).close)(root) };
+        return Ok(None);
+    };
+
+    let result = match file_size(file) {
+        Err(status) => Err(BootError::InitrdReadFailed(status)),
+        Ok(0) => Ok(None),
+        Ok(size) => read_initrd(boot_services, file, size).map(|addr| Some((addr, size))),
+    };
+
     unsafe {
-        ((*kernel_file).close)(kernel_file);
+        ((*file).close)(file);
         ((*root).close)(root);
     }
+    result
+}
 
+/// 開いたinitrd.imgをAllocatePagesで確保した領域に読み込む
+///
+/// # Returns
+/// 読み込み先の物理アドレス
+fn read_initrd(
+    boot_services: *mut EfiBootServices,
+    file: *mut EfiFileProtocol,
+    size: u64,
+) -> Result<u64, BootError> {
+    let (addr, pages) = allocate_initrd(boot_services, size)?;
+
+    let mut read_size = size as usize;
+    let status = unsafe {
+        let status = ((*file).set_position)(file, 0);
+        if status == EFI_SUCCESS {
+            ((*file).read)(file, &mut read_size, addr as *mut core::ffi::c_void)
+        } else {
+            status
+        }
+    };
+    if status != EFI_SUCCESS || read_size as u64 != size {
+        unsafe { ((*boot_services).free_pages)(addr, pages) };
+        return Err(BootError::InitrdReadFailed(status));
+    }
+    Ok(addr)
+}
+
+/// 開いたファイルのサイズをEFI_FILE_INFOから取得
+///
+/// # Errors
+/// GetInfoに失敗した場合のステータス
+fn file_size(file: *mut EfiFileProtocol) -> Result<u64, EfiStatus> {
+    // EFI_FILE_INFOの後にファイル名（UTF-16）が続くため、その分も含めて確保する
+    let mut buffer = [0u64; (core::mem::size_of::<EfiFileInfo>() + PATH_LEN * 2) / 8];
+    let mut size = core::mem::size_of_val(&buffer);
+    // SAFETY: fileは開いたファイル、bufferはsizeバイト書き込めてEfiFileInfoの境界に揃っている
+    let status = unsafe {
+        ((*file).get_info)(
+            file,
+            &EFI_FILE_INFO_ID,
+            &mut size,
+            buffer.as_mut_ptr() as *mut core::ffi::c_void,
+        )
+    };
     if status != EFI_SUCCESS {
-        println_uefi!("[ERROR] Failed to read kernel file");
-        return 0;
+        return Err(status);
     }
+    // SAFETY: GetInfoが成功したのでbufferの先頭は有効なEFI_FILE_INFO
+    Ok(unsafe { (*(buffer.as_ptr() as *const EfiFileInfo)).file_size })
+}
 
-    println_uefi!("[INFO] Kernel loaded: {} bytes", file_size);
+/// 読み込み元のkernel.elf
+///
+/// ESPのファイルはシークできるため、ELFヘッダーとLOADセグメントを必要な位置から直接読み込みます。
+/// TFTPはシークできないため、ファイルサイズ分をAllocatePagesで確保して全体を一度だけ読み込みます。
+enum KernelImage {
+    /// ESPで開いたファイル
+    File {
+        root: *mut EfiFileProtocol,
+        file: *mut EfiFileProtocol,
+        size: u64,
+    },
+    /// AllocatePagesで確保した領域に読み込んだファイル全体
+    Memory { addr: u64, size: u64 },
+}
+
+impl KernelImage {
+    /// ESPのkernel.elfを開く
+    ///
+    /// # Errors
+    /// ボリューム・ファイルを開けない、またはサイズを取得できない場合
+    fn open_esp(boot_services: *mut EfiBootServices) -> Result<Self, BootError> {
+        let root = open_root_volume(boot_services)?;
+        let file = match open_file(root, KERNEL_FILE) {
+            Ok(file) => file,
+            Err(status) => {
+                unsafe { ((*root).close)(root) };
+                return Err(BootError::KernelNotFound(status));
+            }
+        };
+        match file_size(file) {
+            Ok(size) => Ok(Self::File { root, file, size }),
+            Err(status) => {
+                unsafe {
+                    ((*file).close)(file);
+                    ((*root).close)(root);
+                }
+                Err(BootError::KernelReadFailed(status))
+            }
+        }
+    }
+
+    /// TFTPでkernel.elf全体を取得する
+    ///
+    /// # Errors
+    /// ファイルがない、メモリを確保できない、または取得に失敗した場合
+    fn fetch(
+        boot_services: *mut EfiBootServices,
+        net: &netboot::NetBoot,
+    ) -> Result<Self, BootError> {
+        let size = net
+            .file_size(KERNEL_FILE)?
+            .ok_or(BootError::NetworkReadFailed(EFI_NOT_FOUND))?;
+        let mut addr: u64 = 0;
+        // SAFETY: UEFI 関数の呼び出し
+        let status = unsafe {
+            ((*boot_services).allocate_pages)(
+                EFI_ALLOCATE_ANY_PAGES,
+                EFI_LOADER_DATA,
+                size.div_ceil(4096) as usize,
+                &mut addr,
+            )
+        };
+        if status != EFI_SUCCESS {
+            return Err(BootError::KernelReadFailed(status));
+        }
+        let image = Self::Memory { addr, size };
+        if let Err(e) = net.read(KERNEL_FILE, addr as *mut u8, size) {
+            image.close(boot_services);
+            return Err(e);
+        }
+        Ok(image)
+    }
+
+    /// ファイルサイズ（バイト）
+    fn size(&self) -> u64 {
+        match *self {
+            Self::File { size, .. } | Self::Memory { size, .. } => size,
+        }
+    }
 
+    /// ファイルの `offset` から `len` バイトを `dst` に読み込む
+    ///
+    /// # Errors
+    /// * `BootError::InvalidKernelElf` - 範囲がファイルの外にある場合
+    /// * `BootError::KernelReadFailed` - 読み込みに失敗した場合
+    ///
+    /// # Safety
+    /// `dst` は `len` バイト書き込めること
+    unsafe fn read_at(&self, offset: u64, dst: *mut u8, len: usize) -> Result<(), BootError> {
+        if offset
+            .checked_add(len as u64)
+            .is_none_or(|end| end > self.size())
+        {
+            return Err(BootError::InvalidKernelElf);
+        }
+        match *self {
+            Self::File { file, .. } => {
+                let mut read_size = len;
+                // SAFETY: fileは開いたファイル、dstはlenバイト書き込める（呼び出し元が保証）
+                let status = unsafe {
+                    let status = ((*file).set_position)(file, offset);
+                    if status == EFI_SUCCESS {
+                        ((*file).read)(file, &mut read_size, dst as *mut core::ffi::c_void)
+                    } else {
+                        status
+                    }
+                };
+                if status != EFI_SUCCESS {
+                    return Err(BootError::KernelReadFailed(status));
+                }
+                if read_size != len {
+                    return Err(BootError::KernelReadFailed(EFI_DEVICE_ERROR));
+                }
+            }
+            // SAFETY: 範囲は確保した領域内（上で検証済み）
+            Self::Memory { addr, .. } => unsafe {
+                core::ptr::copy_nonoverlapping((addr + offset) as *const u8, dst, len);
+            },
+        }
+        Ok(())
+    }
+
+    /// 構造体を1つ読み込む
+    ///
+    /// # Errors
+    /// `read_at` と同じ
+    fn read_struct<T: Copy>(&self, offset: u64) -> Result<T, BootError> {
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        // SAFETY: valueはsize_of::<T>()バイト書き込め、ELFの構造体はどのビット列も有効
+        unsafe {
+            self.read_at(
+                offset,
+                value.as_mut_ptr() as *mut u8,
+                core::mem::size_of::<T>(),
+            )?;
+            Ok(value.assume_init())
+        }
+    }
+
+    /// ファイルを閉じ、TFTPで取得した場合は領域を解放する
+    fn close(self, boot_services: *mut EfiBootServices) {
+        match self {
+            Self::File { root, file, .. } => unsafe {
+                ((*file).close)(file);
+                ((*root).close)(root);
+            },
+            Self::Memory { addr, size } => unsafe {
+                ((*boot_services).free_pages)(addr, size.div_ceil(4096) as usize);
+            },
+        }
+    }
+}
+
+/// ELFファイルからカーネルをロード
+///
+/// # Arguments
+/// * `source` - kernel.elfの読み込み元
+/// * `initrd` - 読み込み済みのinitrdの範囲（物理アドレス, サイズ）。カーネルと重ならないか検証する
+/// * `boot_info` - LOADセグメントの範囲と属性を記録する
+///
+/// # Returns
+/// カーネルのエントリポイント（物理アドレス）
+///
+/// # Errors
+/// ファイルシステム・ネットワークからの読み込み、ELFの検証に失敗した場合
+fn load_kernel_elf(
+    _image_handle: EfiHandle,
+    boot_services: *mut EfiBootServices,
+    source: &BootSource,
+    initrd: Option<(u64, u64)>,
+    boot_info: &mut BootInfo,
+) -> Result<u64, BootError> {
+    let image = match source {
+        BootSource::Esp => KernelImage::open_esp(boot_services)?,
+        BootSource::Network(net) => KernelImage::fetch(boot_services, net)?,
+    };
+    println_uefi!("[INFO] Kernel image: {} bytes", image.size());
+    let result = place_kernel_segments(boot_services, &image, initrd, boot_info);
+    image.close(boot_services);
+    result
+}
+
+/// ELFヘッダーを検証してLOADセグメントを配置
+///
+/// LOADセグメントの物理アドレス（`p_paddr`）全体をUEFIのAllocatePagesで
+/// EfiLoaderDataとして確保してからコピーするため、カーネルのメモリマップ上でも使用中になります。
+/// セグメントはファイルから確保した領域へ直接読み込み、ファイル全体を別のバッファに置くことはしません。
+/// 各セグメントの範囲と属性（p_flags）は `boot_info` に記録し、カーネルがページの保護に使います。
+///
+/// # Errors
+/// * `BootError::InvalidKernelElf` - ELF64として不正な場合
+/// * `BootError::InitrdOverlapsKernel` - initrdがロード先と重なっている場合
+/// * `BootError::KernelAllocFailed` - ロード先をファームウェアが使用している場合
+/// * `BootError::KernelReadFailed` - セグメントの読み込みに失敗した場合
+fn place_kernel_segments(
+    boot_services: *mut EfiBootServices,
+    image: &KernelImage,
+    initrd: Option<(u64, u64)>,
+    boot_info: &mut BootInfo,
+) -> Result<u64, BootError> {
     // ELFヘッダーを検証
-    let elf_header = unsafe { &*(file_buffer.as_ptr() as *const Elf64Header) };
+    let elf_header: Elf64Header = image.read_struct(0)?;
     if !elf_header.is_valid() {
-        println_uefi!("[ERROR] Invalid ELF header");
-        return 0;
+        return Err(BootError::InvalidKernelElf);
+    }
+
+    let program_header = |i: u16| {
+        let ph_offset =
+            elf_header.e_phoff + (i as u64 * core::mem::size_of::<Elf64ProgramHeader>() as u64);
+        image.read_struct::<Elf64ProgramHeader>(ph_offset)
+    };
+
+    // LOADセグメント全体を覆うページ単位の物理アドレス範囲を求める
+    let (mut start, mut end) = (u64::MAX, 0);
+    for i in 0..elf_header.e_phnum {
+        let ph = program_header(i)?;
+        if ph.p_type != PT_LOAD || ph.p_memsz == 0 {
+            continue;
+        }
+        // ファイル上の大きさがメモリ上の大きさを超えると確保した範囲の外に書き込んでしまう
+        let Some(segment_end) = ph.p_paddr.checked_add(ph.p_memsz) else {
+            return Err(BootError::InvalidKernelElf);
+        };
+        if ph.p_filesz > ph.p_memsz {
+            return Err(BootError::InvalidKernelElf);
+        }
+        start = start.min(ph.p_paddr);
+        end = end.max(segment_end);
+    }
+    if start >= end {
+        return Err(BootError::InvalidKernelElf);
+    }
+    let start = start & !0xFFF;
+    let end = end.next_multiple_of(4096);
+
+    // initrdはカーネルより先に確保されるため、ロード先と重なっていないか確認する
+    if let Some((initrd_addr, initrd_size)) = initrd
+        && start < initrd_addr + initrd_size
+        && initrd_addr < end
+    {
+        return Err(BootError::InitrdOverlapsKernel);
+    }
+
+    let mut alloc_addr = start;
+    // SAFETY: UEFI 関数の呼び出し
+    let status = unsafe {
+        ((*boot_services).allocate_pages)(
+            EFI_ALLOCATE_ADDRESS,
+            EFI_LOADER_DATA,
+            ((end - start) / 4096) as usize,
+            &mut alloc_addr,
+        )
+    };
+    if status != EFI_SUCCESS {
+        return Err(BootError::KernelAllocFailed(status));
     }
+    println_uefi!(
+        "[INFO] Kernel region allocated: 0x{:X} - 0x{:X}",
+        start,
+        end
+    );
 
     // プログラムヘッダーを処理してLOADセグメントをメモリにコピー
     // 最初のLOADセグメントから仮想/物理アドレスのオフセットを計算
     let mut kernel_virt_offset: Option<u64> = None;
+    let mut segment_count = 0;
 
     for i in 0..elf_header.e_phnum {
-        let ph_offset =
-            elf_header.e_phoff as usize + (i as usize * core::mem::size_of::<Elf64ProgramHeader>());
-        let ph = unsafe { &*(file_buffer.as_ptr().add(ph_offset) as *const Elf64ProgramHeader) };
+        let ph = program_header(i)?;
+        if ph.p_type == PT_LOAD && ph.p_memsz > 0 {
+            // セグメントの属性を記録（収まらない場合はカーネルが保護を諦める）
+            if let Some(segment) = boot_info.kernel_segments.get_mut(segment_count) {
+                *segment = KernelSegment {
+                    start: ph.p_paddr,
+                    size: ph.p_memsz,
+                    flags: ph.p_flags,
+                };
+            }
+            segment_count += 1;
 
-        if ph.p_type == PT_LOAD {
             // 最初のLOADセグメントから仮想/物理アドレスのオフセットを記録
             if kernel_virt_offset.is_none() && ph.p_vaddr != ph.p_paddr {
                 kernel_virt_offset = Some(ph.p_vaddr - ph.p_paddr);
             }
 
-            // ファイルからメモリにコピー
+            // ファイルからロード先に直接読み込む
+            // SAFETY: 読み込み先は上で確保した範囲内（p_filesz <= p_memszを検証済み）
             unsafe {
-                let src = file_buffer.as_ptr().add(ph.p_offset as usize);
                 let dst = ph.p_paddr as *mut u8;
-                core::ptr::copy_nonoverlapping(src, dst, ph.p_filesz as usize);
+                image.read_at(ph.p_offset, dst, ph.p_filesz as usize)?;
 
                 // 残りをゼロクリア (BSS領域)
                 if ph.p_memsz > ph.p_filesz {
@@ -647,23 +1290,132 @@ fn load_kernel_elf(_image_handle: EfiHandle, boot_services: *mut EfiBootServices
         }
     }
 
+    boot_info.kernel_segment_count = if segment_count <= MAX_KERNEL_SEGMENTS {
+        segment_count
+    } else {
+        println_uefi!(
+            "[WARN] Kernel has {} LOAD segments (max {}); permissions not recorded",
+            segment_count,
+            MAX_KERNEL_SEGMENTS
+        );
+        0
+    };
+
     // エントリポイントを物理アドレスに変換
     // カーネルが高位アドレスでリンクされている場合、仮想アドレスを物理アドレスに変換
-    if let Some(offset) = kernel_virt_offset {
+    Ok(if let Some(offset) = kernel_virt_offset {
         elf_header.e_entry - offset
     } else {
         elf_header.e_entry
+    })
+}
+
+/// ファイル名をUEFIに渡すNUL終端のUTF-16に変換
+///
+/// # Errors
+/// 長すぎる場合は `EFI_BUFFER_TOO_SMALL`、NULを含む場合は `EFI_INVALID_PARAMETER`
+fn to_utf16(s: &str) -> Result<Utf16Buf<PATH_LEN>, EfiStatus> {
+    Utf16Buf::new(s).map_err(|e| match e {
+        utf16::Utf16Error::BufferTooSmall => EFI_BUFFER_TOO_SMALL,
+        utf16::Utf16Error::InteriorNul => EFI_INVALID_PARAMETER,
+    })
+}
+
+/// ディレクトリからファイルを読み取り用に開く
+///
+/// # Errors
+/// 名前を変換できない、またはファイルを開けない場合のステータス
+fn open_file(dir: *mut EfiFileProtocol, name: &str) -> Result<*mut EfiFileProtocol, EfiStatus> {
+    let name = to_utf16(name)?;
+    let mut file: *mut EfiFileProtocol = core::ptr::null_mut();
+    // SAFETY: dirは開いているディレクトリ、nameはNUL終端されている
+    let status = unsafe { ((*dir).open)(dir, &mut file, name.as_ptr(), EFI_FILE_MODE_READ, 0) };
+    if status != EFI_SUCCESS {
+        return Err(status);
     }
+    Ok(file)
 }
 
-/// 文字列をUTF-16に変換
-fn to_utf16(s: &str) -> [u16; 32] {
-    let mut buf = [0u16; 32];
-    for (i, c) in s.chars().enumerate() {
-        if i >= 31 {
-            break;
+/// ファームウェアベンダー名を表示
+// 要求に最も近いGOPモードを選んで設定
+//
+// 設定に失敗した場合はファームウェアが設定したモードのまま続行する。
+fn set_gop_mode(boot_services: *mut EfiBootServices, gop: *mut EfiGraphicsOutputProtocol) {
+    let request = ModeRequest::parse(RESOLUTION).unwrap_or_else(|| {
+        println_uefi!(
+            "[WARN] Invalid VITROS_RESOLUTION '{}', using {}",
+            RESOLUTION,
+            DEFAULT_RESOLUTION
+        );
+        ModeRequest::parse(DEFAULT_RESOLUTION).unwrap_or(ModeRequest::Current)
+    });
+    // SAFETY: gopはlocate_protocolで取得した有効なプロトコル
+    let (max_mode, current) = unsafe { ((*(*gop).mode).max_mode, (*(*gop).mode).mode) };
+    let candidates = (0..max_mode).filter_map(|number| query_gop_mode(boot_services, gop, number));
+    let Some(number) = display_mode::choose(candidates, request) else {
+        return;
+    };
+    if number == current {
+        return;
+    }
+    // SAFETY: numberはquery_modeが成功したモード番号
+    let status = unsafe { ((*gop).set_mode)(gop, number) };
+    if status != EFI_SUCCESS {
+        println_uefi!(
+            "[WARN] GOP SetMode({}) failed: {}, keeping mode {}",
+            number,
+            status_name(status),
+            current
+        );
+    }
+}
+
+// GOPモードの情報を取得
+fn query_gop_mode(
+    boot_services: *mut EfiBootServices,
+    gop: *mut EfiGraphicsOutputProtocol,
+    number: u32,
+) -> Option<ModeCandidate> {
+    let mut size = 0usize;
+    let mut info: *mut EfiGraphicsOutputModeInformation = core::ptr::null_mut();
+    // SAFETY: UEFI 関数の呼び出し。成功時のinfoはファームウェアが確保した有効な領域
+    unsafe {
+        let status = ((*gop).query_mode)(gop, number, &mut size, &mut info);
+        if status != EFI_SUCCESS || info.is_null() {
+            return None;
         }
-        buf[i] = c as u16;
+        let candidate = ModeCandidate {
+            number,
+            width: (*info).horizontal_resolution,
+            height: (*info).vertical_resolution,
+            pixel_format: (*info).pixel_format,
+            pixels_per_scan_line: (*info).pixels_per_scan_line,
+        };
+        ((*boot_services).free_pool)(info as *mut core::ffi::c_void);
+        Some(candidate)
+    }
+}
+
+fn print_firmware_vendor(system_table: *mut EfiSystemTable) {
+    // SAFETY: システムテーブルはファームウェアが渡した有効なポインタ
+    let (vendor, revision) = unsafe {
+        (
+            (*system_table).firmware_vendor,
+            (*system_table).firmware_revision,
+        )
+    };
+    if vendor.is_null() {
+        return;
+    }
+    // SAFETY: firmware_vendorはNUL終端の文字列（長さは念のため制限する）
+    let units = unsafe { utf16::from_ptr(vendor, FIRMWARE_VENDOR_MAX) };
+    let mut buf = [0u8; FIRMWARE_VENDOR_MAX * 3];
+    if let Ok(name) = utf16::decode(units, &mut buf) {
+        println_uefi!(
+            "[INFO] Firmware: {} (revision {}.{})",
+            name,
+            revision >> 16,
+            revision & 0xFFFF
+        );
     }
-    buf
 }
@@ -0,0 +1,215 @@
+//! ネットワークブート（PXE Base Code / TFTP）
+//!
+//! 書き込み可能なディスクのないラボ機向けに、ESPにkernel.elfがない場合は
+//! UEFIのPXE Base Code Protocolを使い、DHCPで得たブートサーバーからTFTPで
+//! kernel.elfとinitrd.imgを取得します。ファイルはESPと同じくTFTPのルートに置きます。
+//!
+//! PXEでブートローダー自身を読み込んだ場合はファームウェアがDHCPを済ませているため、
+//! その結果（DHCP ACK、またはProxyDHCPの応答）をそのまま使います。
+
+use vitros_common::uefi::*;
+
+use crate::boot_error::BootError;
+
+/// DHCP・TFTPの試行回数
+const MAX_ATTEMPTS: u32 = 3;
+
+/// 再試行までの待ち時間（マイクロ秒）
+const RETRY_DELAY_US: usize = 1_000_000;
+
+/// ファイル名の最大長（NUL終端を含む）
+const MAX_FILENAME: usize = 64;
+
+/// 初期化済みのPXE Base Code Protocolとブートサーバー
+pub struct NetBoot {
+    boot_services: *mut EfiBootServices,
+    pxe: *mut EfiPxeBaseCodeProtocol,
+    server: EfiIpAddress,
+}
+
+impl NetBoot {
+    /// PXE Base Code Protocolを検索し、必要ならDHCPを実行してブートサーバーを決める
+    ///
+    /// # Errors
+    /// * `BootError::NetworkUnavailable` - プロトコルがない、またはDHCPに失敗した場合
+    pub fn open(boot_services: *mut EfiBootServices) -> Result<Self, BootError> {
+        let mut pxe: *mut EfiPxeBaseCodeProtocol = core::ptr::null_mut();
+        let status = unsafe {
+            ((*boot_services).locate_protocol)(
+                &EFI_PXE_BASE_CODE_PROTOCOL_GUID,
+                core::ptr::null_mut(),
+                &mut pxe as *mut *mut _ as *mut *mut core::ffi::c_void,
+            )
+        };
+        if status != EFI_SUCCESS {
+            return Err(BootError::NetworkUnavailable(status));
+        }
+
+        // SAFETY: modeはプロトコルが有効な間ファームウェアが保持する
+        let mode = unsafe { (*pxe).mode };
+        if !unsafe { (*mode).started } {
+            let status = unsafe { ((*pxe).start)(pxe, false) };
+            if status != EFI_SUCCESS && status != EFI_ALREADY_STARTED {
+                return Err(BootError::NetworkUnavailable(status));
+            }
+        }
+        if !unsafe { (*mode).dhcp_ack_received } {
+            let status = retry(boot_services, "DHCP", || unsafe {
+                ((*pxe).dhcp)(pxe, false)
+            });
+            if status != EFI_SUCCESS {
+                return Err(BootError::NetworkUnavailable(status));
+            }
+        }
+
+        // ProxyDHCPがあれば、ブートサーバーはそちらが通知する
+        // SAFETY: DHCPが完了しており、パケットはファームウェアが書き込み済み
+        let packet = unsafe {
+            if (*mode).proxy_offer_received {
+                &(*mode).proxy_offer
+            } else {
+                &(*mode).dhcp_ack
+            }
+        };
+        let mut server = EfiIpAddress { addr: [0; 16] };
+        server.addr[..4]
+            .copy_from_slice(&packet.raw[DHCPV4_SIADDR_OFFSET..DHCPV4_SIADDR_OFFSET + 4]);
+        if server.addr[..4] == [0; 4] {
+            return Err(BootError::NetworkUnavailable(EFI_NOT_FOUND));
+        }
+
+        let station = unsafe { (*mode).station_ip.addr };
+        println_uefi!(
+            "[INFO] Network boot: station {}.{}.{}.{}, server {}.{}.{}.{}",
+            station[0],
+            station[1],
+            station[2],
+            station[3],
+            server.addr[0],
+            server.addr[1],
+            server.addr[2],
+            server.addr[3]
+        );
+        Ok(Self {
+            boot_services,
+            pxe,
+            server,
+        })
+    }
+
+    /// ファイルのサイズを取得
+    ///
+    /// # Returns
+    /// サイズ。サーバーにファイルがなければNone
+    ///
+    /// # Errors
+    /// * `BootError::NetworkReadFailed` - 再試行してもサーバーから応答がない場合
+    pub fn file_size(&self, name: &str) -> Result<Option<u64>, BootError> {
+        let filename = to_ascii(name);
+        let mut size: u64 = 0;
+        let status = retry(self.boot_services, name, || unsafe {
+            ((*self.pxe).mtftp)(
+                self.pxe,
+                EFI_PXE_BASE_CODE_TFTP_GET_FILE_SIZE,
+                core::ptr::null_mut(),
+                false,
+                &mut size,
+                core::ptr::null(),
+                &self.server,
+                filename.as_ptr(),
+                core::ptr::null(),
+                false,
+            )
+        });
+        match status {
+            EFI_SUCCESS => Ok(Some(size)),
+            // サーバーがファイルなしと応答した
+            EFI_TFTP_ERROR | EFI_NOT_FOUND => Ok(None),
+            status => Err(BootError::NetworkReadFailed(status)),
+        }
+    }
+
+    /// ファイルを読み込む
+    ///
+    /// # Arguments
+    /// * `name` - ファイル名
+    /// * `buffer` - 読み込み先
+    /// * `size` - `file_size` で取得したサイズ（`buffer` はこれ以上の大きさであること）
+    ///
+    /// # Errors
+    /// * `BootError::NetworkReadFailed` - 再試行しても読み込めない場合
+    pub fn read(&self, name: &str, buffer: *mut u8, size: u64) -> Result<(), BootError> {
+        let filename = to_ascii(name);
+        println_uefi!(
+            "[INFO] Fetching {} ({} KB) over TFTP...",
+            name,
+            size.div_ceil(1024)
+        );
+        let mut read_size = size;
+        let status = retry(self.boot_services, name, || {
+            read_size = size;
+            unsafe {
+                ((*self.pxe).mtftp)(
+                    self.pxe,
+                    EFI_PXE_BASE_CODE_TFTP_READ_FILE,
+                    buffer as *mut core::ffi::c_void,
+                    false,
+                    &mut read_size,
+                    core::ptr::null(),
+                    &self.server,
+                    filename.as_ptr(),
+                    core::ptr::null(),
+                    false,
+                )
+            }
+        });
+        if status != EFI_SUCCESS {
+            return Err(BootError::NetworkReadFailed(status));
+        }
+        if read_size != size {
+            return Err(BootError::NetworkReadFailed(EFI_BUFFER_TOO_SMALL));
+        }
+        println_uefi!("[INFO] Fetched {}", name);
+        Ok(())
+    }
+}
+
+/// 一時的な失敗（タイムアウトなど）の間は間隔を空けて再試行
+///
+/// # Returns
+/// 最後の試行のステータス
+fn retry(
+    boot_services: *mut EfiBootServices,
+    what: &str,
+    mut op: impl FnMut() -> EfiStatus,
+) -> EfiStatus {
+    let mut status = EFI_SUCCESS;
+    for attempt in 1..=MAX_ATTEMPTS {
+        status = op();
+        if !matches!(
+            status,
+            EFI_TIMEOUT | EFI_NO_RESPONSE | EFI_ICMP_ERROR | EFI_DEVICE_ERROR
+        ) {
+            break;
+        }
+        println_uefi!(
+            "[WARN] {}: {} (attempt {}/{})",
+            what,
+            status_name(status),
+            attempt,
+            MAX_ATTEMPTS
+        );
+        if attempt < MAX_ATTEMPTS {
+            unsafe { ((*boot_services).stall)(RETRY_DELAY_US) };
+        }
+    }
+    status
+}
+
+/// ファイル名をNUL終端のASCIIに変換
+fn to_ascii(s: &str) -> [u8; MAX_FILENAME] {
+    let mut buf = [0u8; MAX_FILENAME];
+    let len = s.len().min(MAX_FILENAME - 1);
+    buf[..len].copy_from_slice(&s.as_bytes()[..len]);
+    buf
+}
@@ -0,0 +1,75 @@
+//! ブートローダー用の最小限のシリアル出力（COM1）
+//!
+//! GOPやConOutが使えない環境（ヘッドレスの実機やQEMUの-nographic）でも
+//! エラーを確認できるよう、致命的なエラーはシリアルにも出力します。
+//! ポートの設定はファームウェアが済ませている前提で、送信のみ行います。
+
+use core::arch::asm;
+use core::fmt;
+
+/// COM1のベースI/Oポート
+const COM1: u16 = 0x3F8;
+
+/// Line Status Registerのオフセット
+const LINE_STATUS: u16 = 5;
+
+/// 送信保持レジスタが空（THRE）
+const LSR_THR_EMPTY: u8 = 0x20;
+
+/// 送信可能になるまでの最大ポーリング回数（UARTが存在しない場合に固まらないため）
+const TRANSMIT_SPIN_LIMIT: u32 = 100_000;
+
+unsafe fn outb(port: u16, value: u8) {
+    unsafe {
+        asm!(
+            "out dx, al",
+            in("dx") port,
+            in("al") value,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    unsafe {
+        asm!(
+            "in al, dx",
+            in("dx") port,
+            out("al") value,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    value
+}
+
+/// COM1への書き込み
+pub struct SerialWriter;
+
+impl SerialWriter {
+    fn write_byte(&self, byte: u8) {
+        // SAFETY: COM1はPC互換機の標準I/Oポートであり、読み書きによる副作用は送信のみ
+        unsafe {
+            for _ in 0..TRANSMIT_SPIN_LIMIT {
+                if inb(COM1 + LINE_STATUS) & LSR_THR_EMPTY != 0 {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+            outb(COM1, byte);
+        }
+    }
+}
+
+impl fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            // 端末での表示が崩れないよう改行はCRLFにする
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
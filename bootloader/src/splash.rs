@@ -0,0 +1,99 @@
+//! 起動画面の描画（GOPのフレームバッファ）
+//!
+//! initrdに `logo.bmp` があればロゴとして中央に描き、その下に進捗バーを置きます。
+//! 起動画面を表示した後のメッセージはシリアルにのみ出力します（画面を上書きしないため）。
+//! 描画できないピクセルフォーマット（ビットマスク指定）ではテキスト表示のまま続行します。
+
+use vitros_common::bmp::Bitmap;
+use vitros_common::boot_info::FramebufferInfo;
+use vitros_common::boot_progress::{self, Rect, SplashTarget, Stage};
+use vitros_common::cpio::{CpioReader, EntryKind};
+use vitros_common::uefi::{
+    EFI_PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR,
+    EFI_PIXEL_RED_GREEN_BLUE_RESERVED_8BIT_PER_COLOR,
+};
+
+/// GOPのフレームバッファへの描画
+struct GopTarget {
+    base: *mut u32,
+    width: u32,
+    height: u32,
+    stride: u32,
+    /// 0x00BBGGRR形式（赤と青を入れ替えて書き込む）
+    swap_red_blue: bool,
+}
+
+impl GopTarget {
+    fn native(&self, color: u32) -> u32 {
+        if self.swap_red_blue {
+            (color & 0x00FF00) | (color >> 16 & 0xFF) | (color & 0xFF) << 16
+        } else {
+            color
+        }
+    }
+
+    fn put_native(&mut self, x: u32, y: u32, native: u32) {
+        let offset = y as usize * self.stride as usize + x as usize;
+        // SAFETY: 座標は画面内（boot_progressが保証）で、フレームバッファは
+        // stride × height ピクセル分マップされている
+        unsafe { self.base.add(offset).write_volatile(native) };
+    }
+}
+
+impl SplashTarget for GopTarget {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u32) {
+        let native = self.native(color);
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                self.put_native(x, y, native);
+            }
+        }
+    }
+
+    fn put_pixel(&mut self, x: u32, y: u32, color: u32) {
+        self.put_native(x, y, self.native(color));
+    }
+}
+
+/// initrdからロゴ画像を探す
+fn find_logo(initrd: Option<(u64, u64)>) -> Option<Bitmap<'static>> {
+    let (addr, size) = initrd?;
+    // SAFETY: initrdはAllocatePagesで確保して読み込んだ領域で、カーネルに渡った後も解放されない
+    let archive = unsafe { core::slice::from_raw_parts(addr as *const u8, size as usize) };
+    let entry = CpioReader::new(archive)
+        .map_while(Result::ok)
+        .find(|e| e.kind == EntryKind::File && e.name == boot_progress::LOGO_FILE)?;
+    match Bitmap::parse(entry.data) {
+        Ok(logo) => Some(logo),
+        Err(e) => {
+            println_uefi!("[WARN] {}: {}", boot_progress::LOGO_FILE, e);
+            None
+        }
+    }
+}
+
+/// 起動画面を描く
+///
+/// # Returns
+/// 描画した場合は`true`。フレームバッファのフォーマットに対応していない場合は`false`
+pub fn show(fb: &FramebufferInfo, initrd: Option<(u64, u64)>) -> bool {
+    let swap_red_blue = match fb.pixel_format {
+        EFI_PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR => false,
+        EFI_PIXEL_RED_GREEN_BLUE_RESERVED_8BIT_PER_COLOR => true,
+        _ => return false,
+    };
+    let logo = find_logo(initrd);
+    let mut target = GopTarget {
+        base: fb.base as *mut u32,
+        width: fb.width,
+        height: fb.height,
+        stride: fb.stride,
+        swap_red_blue,
+    };
+    boot_progress::draw_splash(&mut target, logo.as_ref(), Stage::Bootloader);
+    true
+}
@@ -0,0 +1,279 @@
+//! BMP（Windowsビットマップ）画像の読み取り
+//!
+//! 起動画面のロゴのような小さな画像を、コピーせずにファイルのデータから直接読み取ります。
+//! 非圧縮（BI_RGB）の24ビット・32ビットと、各色が8ビット幅でバイト境界に揃った
+//! 32ビットのBI_BITFIELDSに対応します。画素は下の行から格納されるのが普通ですが、
+//! 高さが負の（上の行から格納された）画像も読み取れます。アルファ値は無視します。
+
+/// ファイルヘッダのマジック
+const MAGIC: [u8; 2] = *b"BM";
+/// ファイルヘッダ（BITMAPFILEHEADER）の長さ
+const FILE_HEADER_SIZE: usize = 14;
+/// 対応する最小の情報ヘッダ（BITMAPINFOHEADER）の長さ
+const INFO_HEADER_SIZE: u32 = 40;
+/// 非圧縮
+const BI_RGB: u32 = 0;
+/// ビットマスク指定
+const BI_BITFIELDS: u32 = 3;
+
+/// 対応する画像の最大の幅・高さ
+pub const MAX_DIMENSION: u32 = 4096;
+
+/// BMP画像のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BmpError {
+    /// マジックが "BM" ではない
+    BadMagic,
+    /// ヘッダのフィールドが矛盾している
+    BadHeader,
+    /// 対応していない形式（ビット数・圧縮方式・ビットマスク）
+    Unsupported {
+        bits_per_pixel: u16,
+        compression: u32,
+    },
+    /// 幅・高さが0、または `MAX_DIMENSION` を超えている
+    UnsupportedSize { width: u32, height: u32 },
+    /// 画素データが途中で終わっている
+    Truncated,
+}
+
+impl core::fmt::Display for BmpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            BmpError::BadMagic => write!(f, "Not a BMP image"),
+            BmpError::BadHeader => write!(f, "Malformed BMP header"),
+            BmpError::Unsupported {
+                bits_per_pixel,
+                compression,
+            } => write!(
+                f,
+                "Unsupported BMP format ({} bpp, compression {})",
+                bits_per_pixel, compression
+            ),
+            BmpError::UnsupportedSize { width, height } => write!(
+                f,
+                "Unsupported BMP size {}x{} (max {}x{})",
+                width, height, MAX_DIMENSION, MAX_DIMENSION
+            ),
+            BmpError::Truncated => write!(f, "BMP image is truncated"),
+        }
+    }
+}
+
+/// 解析済みのBMP画像
+#[derive(Debug, Clone, Copy)]
+pub struct Bitmap<'a> {
+    /// 画素データ（`row_size` × `height` バイト）
+    pixels: &'a [u8],
+    width: u32,
+    height: u32,
+    /// 1行のバイト数（4バイト境界にパディングされる）
+    row_size: usize,
+    /// 1画素のバイト数（3または4）
+    bytes_per_pixel: usize,
+    /// 上の行から格納されているか
+    top_down: bool,
+    /// 赤・緑・青の位置（ビット）
+    shifts: [u8; 3],
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// 8ビット幅でバイト境界に揃ったマスクの位置を返す
+fn byte_mask_shift(mask: u32) -> Option<u8> {
+    let shift = mask.trailing_zeros();
+    (shift.is_multiple_of(8) && mask >> shift == 0xFF).then_some(shift as u8)
+}
+
+impl<'a> Bitmap<'a> {
+    /// BMP画像を解析
+    ///
+    /// # Errors
+    /// * `BmpError::BadMagic` - BMPのマジックがない場合
+    /// * `BmpError::BadHeader` - ヘッダが短い、または矛盾している場合
+    /// * `BmpError::Unsupported` - 対応していないビット数・圧縮方式の場合
+    /// * `BmpError::UnsupportedSize` - 画像が空、または大きすぎる場合
+    /// * `BmpError::Truncated` - 画素データが途中で終わっている場合
+    pub fn parse(data: &'a [u8]) -> Result<Self, BmpError> {
+        if !data.starts_with(&MAGIC) {
+            return Err(BmpError::BadMagic);
+        }
+        let header = |offset| read_u32(data, offset).ok_or(BmpError::BadHeader);
+        let pixel_offset = header(10)? as usize;
+        let info_size = header(FILE_HEADER_SIZE)?;
+        if info_size < INFO_HEADER_SIZE {
+            return Err(BmpError::BadHeader);
+        }
+        let width = header(FILE_HEADER_SIZE + 4)? as i32;
+        let height = header(FILE_HEADER_SIZE + 8)? as i32;
+        let bits_per_pixel = read_u16(data, FILE_HEADER_SIZE + 14).ok_or(BmpError::BadHeader)?;
+        let compression = header(FILE_HEADER_SIZE + 16)?;
+        let unsupported = BmpError::Unsupported {
+            bits_per_pixel,
+            compression,
+        };
+
+        let shifts = match (bits_per_pixel, compression) {
+            (24 | 32, BI_RGB) => [16, 8, 0],
+            (32, BI_BITFIELDS) => {
+                // マスクは情報ヘッダの直後（V4/V5ヘッダではヘッダ内の同じ位置）にある
+                let mask = |index: usize| header(FILE_HEADER_SIZE + 40 + index * 4);
+                let shift = |index| mask(index).map(|m| byte_mask_shift(m).ok_or(unsupported));
+                [shift(0)??, shift(1)??, shift(2)??]
+            }
+            _ => return Err(unsupported),
+        };
+
+        let (width_abs, height_abs) = (width.unsigned_abs(), height.unsigned_abs());
+        if width <= 0 || height_abs == 0 || width_abs > MAX_DIMENSION || height_abs > MAX_DIMENSION
+        {
+            return Err(BmpError::UnsupportedSize {
+                width: width_abs,
+                height: height_abs,
+            });
+        }
+
+        let bytes_per_pixel = bits_per_pixel as usize / 8;
+        let row_size = (width_abs as usize * bytes_per_pixel).next_multiple_of(4);
+        let pixels = data
+            .get(pixel_offset..)
+            .and_then(|rest| rest.get(..row_size * height_abs as usize))
+            .ok_or(BmpError::Truncated)?;
+
+        Ok(Self {
+            pixels,
+            width: width_abs,
+            height: height_abs,
+            row_size,
+            bytes_per_pixel,
+            top_down: height < 0,
+            shifts,
+        })
+    }
+
+    /// 幅（ピクセル）
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// 高さ（ピクセル）
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// 画素の色を取得
+    ///
+    /// # Arguments
+    /// * `x`, `y` - 左上を原点とする座標
+    ///
+    /// # Returns
+    /// 0x00RRGGBB形式の色。範囲外の場合はNone
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let row = if self.top_down {
+            y
+        } else {
+            self.height - 1 - y
+        };
+        let offset = row as usize * self.row_size + x as usize * self.bytes_per_pixel;
+        let bytes = &self.pixels[offset..offset + self.bytes_per_pixel];
+        let mut raw = [0u8; 4];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        let raw = u32::from_le_bytes(raw);
+        let [red, green, blue] = self.shifts.map(|shift| (raw >> shift) & 0xFF);
+        Some(red << 16 | green << 8 | blue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ヘッダと画素データからBMPファイルを組み立てる
+    fn build(
+        width: i32,
+        height: i32,
+        bits_per_pixel: u16,
+        compression: u32,
+        masks: &[u32],
+        pixels: &[u8],
+    ) -> ([u8; 256], usize) {
+        let mut file = [0u8; 256];
+        let pixel_offset = FILE_HEADER_SIZE + 40 + masks.len() * 4;
+        file[..2].copy_from_slice(&MAGIC);
+        file[10..14].copy_from_slice(&(pixel_offset as u32).to_le_bytes());
+        file[14..18].copy_from_slice(&INFO_HEADER_SIZE.to_le_bytes());
+        file[18..22].copy_from_slice(&width.to_le_bytes());
+        file[22..26].copy_from_slice(&height.to_le_bytes());
+        file[26..28].copy_from_slice(&1u16.to_le_bytes());
+        file[28..30].copy_from_slice(&bits_per_pixel.to_le_bytes());
+        file[30..34].copy_from_slice(&compression.to_le_bytes());
+        for (i, mask) in masks.iter().enumerate() {
+            let offset = FILE_HEADER_SIZE + 40 + i * 4;
+            file[offset..offset + 4].copy_from_slice(&mask.to_le_bytes());
+        }
+        file[pixel_offset..pixel_offset + pixels.len()].copy_from_slice(pixels);
+        (file, pixel_offset + pixels.len())
+    }
+
+    #[test]
+    fn test_parse_24bit_bottom_up_with_padding() {
+        // 2×2、1行6バイト + パディング2バイト。下の行（青・白）から格納
+        #[rustfmt::skip]
+        let pixels = [
+            0xFF, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0, 0,
+            0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0, 0,
+        ];
+        let (file, len) = build(2, 2, 24, BI_RGB, &[], &pixels);
+        let bitmap = Bitmap::parse(&file[..len]).unwrap();
+        assert_eq!((bitmap.width(), bitmap.height()), (2, 2));
+        assert_eq!(bitmap.pixel(0, 0), Some(0xFF0000));
+        assert_eq!(bitmap.pixel(1, 0), Some(0x00FF00));
+        assert_eq!(bitmap.pixel(0, 1), Some(0x0000FF));
+        assert_eq!(bitmap.pixel(1, 1), Some(0xFFFFFF));
+        assert_eq!(bitmap.pixel(2, 0), None);
+
+        assert_eq!(
+            Bitmap::parse(&file[..len - 1]).unwrap_err(),
+            BmpError::Truncated
+        );
+    }
+
+    #[test]
+    fn test_parse_32bit_top_down_bitfields() {
+        // R, G, B, Aの順に並ぶマスク（0xAABBGGRR）
+        let masks = [0x0000_00FF, 0x0000_FF00, 0x00FF_0000];
+        let pixels = [0x12, 0x34, 0x56, 0xFF];
+        let (file, len) = build(1, -1, 32, BI_BITFIELDS, &masks, &pixels);
+        let bitmap = Bitmap::parse(&file[..len]).unwrap();
+        assert_eq!(bitmap.pixel(0, 0), Some(0x123456));
+    }
+
+    #[test]
+    fn test_parse_rejects_unsupported() {
+        let (file, len) = build(1, 1, 8, BI_RGB, &[], &[0; 4]);
+        assert_eq!(
+            Bitmap::parse(&file[..len]).unwrap_err(),
+            BmpError::Unsupported {
+                bits_per_pixel: 8,
+                compression: BI_RGB
+            }
+        );
+        let (file, len) = build(0, 1, 24, BI_RGB, &[], &[]);
+        assert!(matches!(
+            Bitmap::parse(&file[..len]),
+            Err(BmpError::UnsupportedSize { .. })
+        ));
+        assert_eq!(Bitmap::parse(b"GIF89a").unwrap_err(), BmpError::BadMagic);
+    }
+}
@@ -8,7 +8,12 @@ pub struct FramebufferInfo {
     pub size: u64,
     pub width: u32,
     pub height: u32,
+    /// 1行のピクセル数（GOPのpixels_per_scan_line。widthより大きいことがある）
     pub stride: u32,
+    /// GOPのピクセルフォーマット（EFI_PIXEL_* 定数）
+    pub pixel_format: u32,
+    /// EFI_PIXEL_BIT_MASK の場合の各色マスク（赤、緑、青、予約）
+    pub pixel_bitmask: [u32; 4],
 }
 
 #[repr(C)]
@@ -21,6 +26,24 @@ pub struct MemoryRegion {
 
 pub const MAX_MEMORY_REGIONS: usize = 256;
 
+/// カーネルコマンドラインの最大長（バイト）
+pub const CMDLINE_MAX: usize = 512;
+
+/// 記録するカーネルのLOADセグメントの最大数
+pub const MAX_KERNEL_SEGMENTS: usize = 8;
+
+/// カーネルのLOADセグメント（カーネルがページの保護属性を決めるのに使う）
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct KernelSegment {
+    /// 物理アドレス
+    pub start: u64,
+    /// メモリ上の大きさ（バイト）
+    pub size: u64,
+    /// ELFのp_flags（PF_R / PF_W / PF_X）
+    pub flags: u32,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct BootInfo {
@@ -30,6 +53,20 @@ pub struct BootInfo {
     pub rsdp_address: u64,
     /// マッピングが必要な最大物理アドレス（UEFIメモリマップから計算）
     pub max_physical_address: u64,
+    /// initramfsイメージの物理アドレス（読み込んでいなければ0）
+    pub initrd_address: u64,
+    /// initramfsイメージのサイズ（バイト）
+    pub initrd_size: u64,
+    /// 起動画面を使わず、起動中のログを画面に表示するか
+    pub verbose: bool,
+    /// カーネルコマンドライン（UTF-8、先頭 `cmdline_len` バイト）
+    pub cmdline: [u8; CMDLINE_MAX],
+    /// カーネルコマンドラインの長さ（バイト）
+    pub cmdline_len: usize,
+    /// カーネルのLOADセグメント（先頭 `kernel_segment_count` 個）
+    pub kernel_segments: [KernelSegment; MAX_KERNEL_SEGMENTS],
+    /// 記録したLOADセグメントの数（収まらなかった場合は0）
+    pub kernel_segment_count: usize,
 }
 
 impl BootInfo {
@@ -41,6 +78,8 @@ impl BootInfo {
                 width: 0,
                 height: 0,
                 stride: 0,
+                pixel_format: 0,
+                pixel_bitmask: [0; 4],
             },
             memory_map: [MemoryRegion {
                 start: 0,
@@ -50,8 +89,30 @@ impl BootInfo {
             memory_map_count: 0,
             rsdp_address: 0,
             max_physical_address: 0,
+            initrd_address: 0,
+            initrd_size: 0,
+            verbose: false,
+            cmdline: [0; CMDLINE_MAX],
+            cmdline_len: 0,
+            kernel_segments: [KernelSegment {
+                start: 0,
+                size: 0,
+                flags: 0,
+            }; MAX_KERNEL_SEGMENTS],
+            kernel_segment_count: 0,
         }
     }
+
+    /// カーネルコマンドラインを取得（UTF-8でない場合は空文字列）
+    pub fn cmdline(&self) -> &str {
+        let len = self.cmdline_len.min(CMDLINE_MAX);
+        core::str::from_utf8(&self.cmdline[..len]).unwrap_or("")
+    }
+
+    /// カーネルのLOADセグメントを取得
+    pub fn kernel_segments(&self) -> &[KernelSegment] {
+        &self.kernel_segments[..self.kernel_segment_count.min(MAX_KERNEL_SEGMENTS)]
+    }
 }
 
 impl Default for BootInfo {
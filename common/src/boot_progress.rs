@@ -0,0 +1,244 @@
+//! 起動画面（スプラッシュ）と起動の進捗
+//!
+//! ブートローダーとカーネルが同じ見た目で描けるよう、起動の段階・画面上の配置・描画を
+//! ここにまとめています。描画先は `SplashTarget` を実装したもの（GOPのフレームバッファ、
+//! カーネルのフレームバッファ）で、色は0x00RRGGBB形式で渡します。
+//!
+//! 画面の中央にロゴ（initrdの `logo.bmp`、なければ省略）を置き、その下に進捗バーを描きます。
+//! 進捗バーは段階が完了するたびに伸び、最後の段階で右端に達します。
+
+use crate::bmp::Bitmap;
+
+/// initrd内のロゴ画像のパス
+pub const LOGO_FILE: &str = "logo.bmp";
+
+/// 背景色
+const BACKGROUND: u32 = 0x000000;
+/// 進捗バーの未到達部分の色
+const BAR_TRACK: u32 = 0x303030;
+/// 進捗バーの到達部分の色
+const BAR_FILL: u32 = 0xE0E0E0;
+
+/// 進捗バーの高さ
+const BAR_HEIGHT: u32 = 6;
+/// 進捗バーの最大幅
+const BAR_MAX_WIDTH: u32 = 320;
+/// ロゴと進捗バーの間隔
+const BAR_GAP: u32 = 24;
+
+/// 起動の段階（完了した順）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Stage {
+    /// ブートローダー（カーネルの読み込みまで）
+    Bootloader = 0,
+    /// GDTの初期化
+    Gdt = 1,
+    /// IDTの初期化
+    Idt = 2,
+    /// ACPIテーブルの解析
+    Acpi = 3,
+    /// Local APICの初期化
+    Apic = 4,
+    /// ヒープの初期化
+    Heap = 5,
+    /// タスクの起動（Compositorが画面を引き継ぐ直前）
+    Tasks = 6,
+}
+
+impl Stage {
+    /// 全ての段階（完了する順）
+    pub const ALL: [Stage; 7] = [
+        Stage::Bootloader,
+        Stage::Gdt,
+        Stage::Idt,
+        Stage::Acpi,
+        Stage::Apic,
+        Stage::Heap,
+        Stage::Tasks,
+    ];
+
+    /// 数値から変換
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    /// 段階の名前
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Bootloader => "bootloader",
+            Stage::Gdt => "gdt",
+            Stage::Idt => "idt",
+            Stage::Acpi => "acpi",
+            Stage::Apic => "apic",
+            Stage::Heap => "heap",
+            Stage::Tasks => "tasks",
+        }
+    }
+
+    /// この段階が完了した時点の進捗（パーセント）
+    pub fn percent(self) -> u32 {
+        (self as u32 + 1) * 100 / Self::ALL.len() as u32
+    }
+}
+
+/// 画面上の矩形
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 起動画面の配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// ロゴの位置（ロゴがない、または画面に収まらない場合はNone）
+    pub logo: Option<Rect>,
+    /// 進捗バーの位置
+    pub bar: Rect,
+}
+
+impl Layout {
+    /// 画面の大きさとロゴの大きさから配置を決める
+    ///
+    /// ロゴは画面の中央より上、進捗バーはその下に置きます。ロゴが画面の上半分に
+    /// 収まらない場合はロゴを省略し、進捗バーを画面の中央に置きます。
+    pub fn new(screen_width: u32, screen_height: u32, logo: Option<(u32, u32)>) -> Self {
+        let center_y = screen_height / 2;
+        let logo = logo
+            .filter(|&(width, height)| width <= screen_width && height <= center_y)
+            .map(|(width, height)| Rect {
+                x: (screen_width - width) / 2,
+                y: center_y - height,
+                width,
+                height,
+            });
+        let bar_width = BAR_MAX_WIDTH.min(screen_width / 2);
+        let bar_y = match logo {
+            Some(_) => center_y + BAR_GAP,
+            None => center_y.saturating_sub(BAR_HEIGHT / 2),
+        };
+        Self {
+            logo,
+            bar: Rect {
+                x: (screen_width - bar_width) / 2,
+                y: bar_y.min(screen_height.saturating_sub(BAR_HEIGHT)),
+                width: bar_width,
+                height: BAR_HEIGHT.min(screen_height),
+            },
+        }
+    }
+
+    /// 段階が完了した時点の進捗バーの到達部分の幅
+    pub fn filled_width(&self, stage: Stage) -> u32 {
+        self.bar.width * stage.percent() / 100
+    }
+}
+
+/// 起動画面の描画先
+pub trait SplashTarget {
+    /// 画面の大きさ（幅, 高さ）
+    fn size(&self) -> (u32, u32);
+
+    /// 矩形を塗りつぶす（画面内に収まる矩形のみ渡される）
+    fn fill_rect(&mut self, rect: Rect, color: u32);
+
+    /// 1画素を描く（画面内の座標のみ渡される）
+    fn put_pixel(&mut self, x: u32, y: u32, color: u32);
+}
+
+/// 起動画面の配置を求める
+pub fn layout(target: &impl SplashTarget, logo: Option<&Bitmap>) -> Layout {
+    let (width, height) = target.size();
+    Layout::new(width, height, logo.map(|l| (l.width(), l.height())))
+}
+
+/// 画面全体に起動画面（背景、ロゴ、進捗バー）を描く
+pub fn draw_splash(target: &mut impl SplashTarget, logo: Option<&Bitmap>, stage: Stage) {
+    let (width, height) = target.size();
+    target.fill_rect(
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        },
+        BACKGROUND,
+    );
+    let layout = layout(target, logo);
+    if let (Some(rect), Some(logo)) = (layout.logo, logo) {
+        for y in 0..rect.height {
+            for x in 0..rect.width {
+                if let Some(color) = logo.pixel(x, y) {
+                    target.put_pixel(rect.x + x, rect.y + y, color);
+                }
+            }
+        }
+    }
+    draw_progress(target, &layout, stage);
+}
+
+/// 進捗バーを描き直す
+pub fn draw_progress(target: &mut impl SplashTarget, layout: &Layout, stage: Stage) {
+    let bar = layout.bar;
+    let filled = layout.filled_width(stage);
+    if filled > 0 {
+        target.fill_rect(
+            Rect {
+                width: filled,
+                ..bar
+            },
+            BAR_FILL,
+        );
+    }
+    if filled < bar.width {
+        target.fill_rect(
+            Rect {
+                x: bar.x + filled,
+                width: bar.width - filled,
+                ..bar
+            },
+            BAR_TRACK,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_percent_reaches_100() {
+        assert_eq!(Stage::from_u8(Stage::Heap as u8), Some(Stage::Heap));
+        assert_eq!(Stage::from_u8(Stage::ALL.len() as u8), None);
+        assert!(
+            Stage::ALL
+                .windows(2)
+                .all(|w| w[0].percent() < w[1].percent())
+        );
+        assert_eq!(Stage::Tasks.percent(), 100);
+    }
+
+    #[test]
+    fn test_layout_centers_logo_above_bar() {
+        let layout = Layout::new(1920, 1080, Some((200, 100)));
+        assert_eq!(
+            layout.logo,
+            Some(Rect {
+                x: 860,
+                y: 440,
+                width: 200,
+                height: 100
+            })
+        );
+        assert_eq!((layout.bar.x, layout.bar.y), (800, 540 + BAR_GAP));
+        assert_eq!(layout.filled_width(Stage::Tasks), layout.bar.width);
+
+        // 上半分に収まらないロゴは省略し、進捗バーを中央に置く
+        let layout = Layout::new(640, 480, Some((200, 300)));
+        assert_eq!(layout.logo, None);
+        assert_eq!(layout.bar.y, 240 - BAR_HEIGHT / 2);
+    }
+}
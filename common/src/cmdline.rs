@@ -0,0 +1,158 @@
+//! カーネルコマンドラインの解析
+//!
+//! コマンドラインは空白で区切った `key=value`（値あり）と `key`（値なしのフラグ）の並びです。
+//! 値を二重引用符で囲むと空白を含められます（`title="Vitr OS"`）。同じキーが複数回
+//! 現れた場合は最後のものが有効です。
+//!
+//! ブートローダーは `cmdline.txt` の内容を `normalize` で1行にまとめてからカーネルへ渡します。
+
+/// コマンドラインの1項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Param<'a> {
+    /// キー
+    pub key: &'a str,
+    /// 値（フラグの場合はNone）
+    pub value: Option<&'a str>,
+}
+
+/// 解析済みのコマンドライン（元の文字列を借用する）
+#[derive(Debug, Clone, Copy)]
+pub struct Cmdline<'a> {
+    text: &'a str,
+}
+
+impl<'a> Cmdline<'a> {
+    /// 文字列をコマンドラインとして扱う
+    pub const fn new(text: &'a str) -> Self {
+        Self { text }
+    }
+
+    /// 元の文字列
+    pub fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    /// 項目を先頭から順に返す
+    pub fn params(&self) -> impl Iterator<Item = Param<'a>> + 'a {
+        items(self.text).map(parse_item)
+    }
+
+    /// キーの値を取得
+    ///
+    /// # Returns
+    /// 値。フラグとして指定された場合は空文字列、指定されていない場合はNone
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.params()
+            .filter(|p| p.key == key)
+            .last()
+            .map(|p| p.value.unwrap_or(""))
+    }
+
+    /// キーが指定されているか（値の有無を問わない）
+    pub fn has(&self, key: &str) -> bool {
+        self.params().any(|p| p.key == key)
+    }
+}
+
+/// 引用符の外の空白で区切った項目（引用符を含む元の文字列）を返す
+fn items(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        let text = rest.trim_start();
+        if text.is_empty() {
+            return None;
+        }
+        let mut quoted = false;
+        let end = text
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c.is_whitespace() && !quoted
+            })
+            .map_or(text.len(), |(i, _)| i);
+        let (item, tail) = text.split_at(end);
+        rest = tail;
+        Some(item)
+    })
+}
+
+/// 1項目を解析（値を囲む引用符は外す）
+fn parse_item(item: &str) -> Param<'_> {
+    match item.split_once('=') {
+        Some((key, value)) => {
+            let value = value
+                .strip_prefix('"')
+                .map(|v| v.strip_suffix('"').unwrap_or(v))
+                .unwrap_or(value);
+            Param {
+                key,
+                value: Some(value),
+            }
+        }
+        None => Param {
+            key: item,
+            value: None,
+        },
+    }
+}
+
+/// 設定ファイル形式のテキストを1行のコマンドラインにまとめる
+///
+/// `#` で始まる行はコメントとして除き、改行を含む空白の連続は1つの空白にします。
+/// `buf` に収まらない場合は項目の途中で切らず、収まる項目までにします。
+///
+/// # Returns
+/// `buf` に書き込んだバイト数
+pub fn normalize(text: &str, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'));
+    for item in lines.flat_map(items) {
+        let separator = usize::from(len != 0);
+        if len + separator + item.len() > buf.len() {
+            break;
+        }
+        if separator != 0 {
+            buf[len] = b' ';
+            len += 1;
+        }
+        buf[len..len + item.len()].copy_from_slice(item.as_bytes());
+        len += item.len();
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_values_and_flags() {
+        let cmdline = Cmdline::new(r#" loglevel=debug  quiet title="Vitr OS" loglevel=warn "#);
+        assert_eq!(cmdline.get("loglevel"), Some("warn"));
+        assert_eq!(cmdline.get("quiet"), Some(""));
+        assert_eq!(cmdline.get("title"), Some("Vitr OS"));
+        assert_eq!(cmdline.get("missing"), None);
+        assert!(cmdline.has("quiet"));
+        assert_eq!(cmdline.params().count(), 4);
+    }
+
+    #[test]
+    fn test_normalize_joins_lines_and_skips_comments() {
+        let text = "# boot options\nloglevel=debug\r\n\n  timer_hz=100   title=\"a b\"\n";
+        let mut buf = [0u8; 64];
+        let len = normalize(text, &mut buf);
+        assert_eq!(
+            core::str::from_utf8(&buf[..len]),
+            Ok(r#"loglevel=debug timer_hz=100 title="a b""#)
+        );
+
+        // 収まらない項目は途中で切らずに落とす
+        let mut small = [0u8; 20];
+        let len = normalize(text, &mut small);
+        assert_eq!(core::str::from_utf8(&small[..len]), Ok("loglevel=debug"));
+    }
+}
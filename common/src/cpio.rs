@@ -0,0 +1,252 @@
+//! cpio（newc形式）アーカイブの読み取り
+//!
+//! initramfsで一般的な `find . | cpio -o -H newc` の出力を、コピーせずに走査します。
+//! 各エントリの名前とデータはアーカイブのスライスを借用します。
+
+/// newc形式のマジック
+const MAGIC: &[u8; 6] = b"070701";
+
+/// ヘッダ長（マジック + 8桁の16進数 × 13フィールド）
+const HEADER_SIZE: usize = 110;
+
+/// アーカイブ終端を表すエントリ名
+const TRAILER: &str = "TRAILER!!!";
+
+/// ファイル種別のマスク
+const S_IFMT: u32 = 0o170000;
+/// ディレクトリ
+const S_IFDIR: u32 = 0o040000;
+/// 通常ファイル
+const S_IFREG: u32 = 0o100000;
+
+/// cpioアーカイブのエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpioError {
+    /// マジックがnewc形式ではない
+    BadMagic,
+    /// ヘッダのフィールドが16進数ではない
+    BadHeader,
+    /// エントリ名がUTF-8ではない、またはNUL終端されていない
+    BadName,
+    /// アーカイブが途中で終わっている
+    Truncated,
+}
+
+impl core::fmt::Display for CpioError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            CpioError::BadMagic => write!(f, "Not a newc cpio archive"),
+            CpioError::BadHeader => write!(f, "Malformed cpio header"),
+            CpioError::BadName => write!(f, "Malformed cpio entry name"),
+            CpioError::Truncated => write!(f, "cpio archive is truncated"),
+        }
+    }
+}
+
+/// エントリの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// 通常ファイル
+    File,
+    /// ディレクトリ
+    Directory,
+    /// その他（シンボリックリンク、デバイスファイルなど）
+    Other,
+}
+
+/// アーカイブ内の1エントリ
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    /// パス（先頭の "./" や "/" は含まない）
+    pub name: &'a str,
+    /// 種類
+    pub kind: EntryKind,
+    /// データ（ディレクトリでは空）
+    pub data: &'a [u8],
+}
+
+/// エントリを順に返すイテレータ
+///
+/// 不正なヘッダを検出した場合はエラーを1度返して終了します。
+pub struct CpioReader<'a> {
+    archive: &'a [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> CpioReader<'a> {
+    /// アーカイブの先頭から読み取りを開始
+    pub fn new(archive: &'a [u8]) -> Self {
+        Self {
+            archive,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    fn parse_next(&mut self) -> Result<Option<Entry<'a>>, CpioError> {
+        let header = self
+            .archive
+            .get(self.offset..self.offset + HEADER_SIZE)
+            .ok_or(CpioError::Truncated)?;
+        if &header[..6] != MAGIC {
+            return Err(CpioError::BadMagic);
+        }
+        let field = |index: usize| -> Result<u32, CpioError> {
+            let start = 6 + index * 8;
+            let text = core::str::from_utf8(&header[start..start + 8])
+                .map_err(|_| CpioError::BadHeader)?;
+            u32::from_str_radix(text, 16).map_err(|_| CpioError::BadHeader)
+        };
+        let mode = field(1)?;
+        let file_size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name_start = self.offset + HEADER_SIZE;
+        let name_bytes = self
+            .archive
+            .get(name_start..name_start + name_size)
+            .ok_or(CpioError::Truncated)?;
+        let (&nul, name_bytes) = name_bytes.split_last().ok_or(CpioError::BadName)?;
+        if nul != 0 {
+            return Err(CpioError::BadName);
+        }
+        let name = core::str::from_utf8(name_bytes).map_err(|_| CpioError::BadName)?;
+
+        // ヘッダ+名前、データはそれぞれ4バイト境界にパディングされる
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let data = self
+            .archive
+            .get(data_start..data_start + file_size)
+            .ok_or(CpioError::Truncated)?;
+        self.offset = (data_start + file_size).next_multiple_of(4);
+
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let kind = match mode & S_IFMT {
+            S_IFREG => EntryKind::File,
+            S_IFDIR => EntryKind::Directory,
+            _ => EntryKind::Other,
+        };
+        let name = name.trim_start_matches("./").trim_start_matches('/');
+        Ok(Some(Entry { name, kind, data }))
+    }
+}
+
+impl<'a> Iterator for CpioReader<'a> {
+    type Item = Result<Entry<'a>, CpioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.parse_next() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(out: &mut [u8], len: &mut usize, bytes: &[u8]) {
+        out[*len..*len + bytes.len()].copy_from_slice(bytes);
+        *len += bytes.len();
+    }
+
+    fn pad(out: &mut [u8], len: &mut usize) {
+        while !len.is_multiple_of(4) {
+            put(out, len, &[0]);
+        }
+    }
+
+    /// テスト用に1エントリ分のnewcレコードを書き込む
+    fn push_entry(out: &mut [u8], len: &mut usize, name: &str, mode: u32, data: &[u8]) {
+        put(out, len, MAGIC);
+        let name_size = name.len() as u32 + 1;
+        let fields = [
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            name_size,
+            0,
+        ];
+        for value in fields {
+            let mut hex = [0u8; 8];
+            for (i, byte) in hex.iter_mut().enumerate() {
+                let nibble = (value >> ((7 - i) * 4)) & 0xF;
+                *byte = b"0123456789ABCDEF"[nibble as usize];
+            }
+            put(out, len, &hex);
+        }
+        put(out, len, name.as_bytes());
+        put(out, len, &[0]);
+        pad(out, len);
+        put(out, len, data);
+        pad(out, len);
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let mut archive = [0u8; 1024];
+        let mut len = 0;
+        push_entry(&mut archive, &mut len, ".", S_IFDIR | 0o755, &[]);
+        push_entry(&mut archive, &mut len, "etc", S_IFDIR | 0o755, &[]);
+        push_entry(
+            &mut archive,
+            &mut len,
+            "./etc/motd",
+            S_IFREG | 0o644,
+            b"hello",
+        );
+        push_entry(&mut archive, &mut len, TRAILER, 0, &[]);
+
+        let mut reader = CpioReader::new(&archive[..len]);
+        let root = reader.next().unwrap().unwrap();
+        assert_eq!(root.name, ".");
+        assert_eq!(root.kind, EntryKind::Directory);
+        let etc = reader.next().unwrap().unwrap();
+        assert_eq!((etc.name, etc.kind), ("etc", EntryKind::Directory));
+        let motd = reader.next().unwrap().unwrap();
+        assert_eq!((motd.name, motd.kind), ("etc/motd", EntryKind::File));
+        assert_eq!(motd.data, b"hello");
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_bad_magic_and_truncation() {
+        let mut reader = CpioReader::new(b"070707garbage");
+        assert_eq!(reader.next().unwrap().unwrap_err(), CpioError::Truncated);
+        assert!(reader.next().is_none());
+
+        let mut archive = [0u8; 256];
+        let mut len = 0;
+        push_entry(&mut archive, &mut len, "a", S_IFREG, b"data");
+        archive[0] = b'1';
+        let mut reader = CpioReader::new(&archive[..len]);
+        assert_eq!(reader.next().unwrap().unwrap_err(), CpioError::BadMagic);
+
+        archive[0] = b'0';
+        let mut reader = CpioReader::new(&archive[..len - 4]);
+        assert_eq!(reader.next().unwrap().unwrap_err(), CpioError::Truncated);
+    }
+}
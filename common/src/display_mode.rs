@@ -0,0 +1,169 @@
+//! GOPの表示モードの選択
+//!
+//! ブートローダーはGOPが列挙するモードの中から、要求（解像度の指定・最大・現在のまま）に
+//! 最も近いものを選んで設定します。選択の規則はファームウェアに依存しないため、
+//! ここで単体テストできる形にしています。
+//!
+//! # 選択の規則
+//! - フレームバッファに直接描画できないモード（BLT専用・未知のフォーマット）は選ばない
+//! - 解像度の指定: 一致するモード、なければ指定に収まる最大のモード、
+//!   それもなければ最小のモード
+//! - 最大: 画素数が最大のモード
+//! - 同じ条件なら、1行の幅（pixels_per_scan_line）が横の解像度と等しいモードを優先し
+//!   （行末の余白の分だけメモリと転送が無駄になるため）、それでも同じならモード番号の小さい方
+
+use core::cmp::Reverse;
+
+use crate::uefi::EFI_PIXEL_BIT_MASK;
+
+/// 表示モードの要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeRequest {
+    /// ファームウェアが設定したモードのまま
+    Current,
+    /// 画素数が最大のモード
+    Highest,
+    /// 指定した解像度（なければ近いもの）
+    Resolution { width: u32, height: u32 },
+}
+
+impl ModeRequest {
+    /// 文字列から変換（`current`、`max`、`<幅>x<高さ>`）
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "current" => Some(ModeRequest::Current),
+            "max" => Some(ModeRequest::Highest),
+            _ => {
+                let (width, height) = s.split_once('x')?;
+                let width = width.parse().ok().filter(|&w| w > 0)?;
+                let height = height.parse().ok().filter(|&h| h > 0)?;
+                Some(ModeRequest::Resolution { width, height })
+            }
+        }
+    }
+}
+
+/// 選択の候補となるモード（EFI_GRAPHICS_OUTPUT_MODE_INFORMATIONの要約）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeCandidate {
+    /// GOPのモード番号
+    pub number: u32,
+    /// 横の解像度
+    pub width: u32,
+    /// 縦の解像度
+    pub height: u32,
+    /// ピクセルフォーマット（EFI_PIXEL_* 定数）
+    pub pixel_format: u32,
+    /// 1行のピクセル数
+    pub pixels_per_scan_line: u32,
+}
+
+impl ModeCandidate {
+    fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// フレームバッファに直接描画できるか
+    fn is_drawable(&self) -> bool {
+        self.pixel_format <= EFI_PIXEL_BIT_MASK
+    }
+
+    /// 1行の幅が横の解像度と等しいか
+    fn is_packed(&self) -> bool {
+        self.pixels_per_scan_line == self.width
+    }
+}
+
+/// 要求に最も近いモードを選ぶ
+///
+/// # Returns
+/// 選んだモード番号。`ModeRequest::Current` の場合と、描画できるモードがない場合はNone
+pub fn choose(
+    candidates: impl IntoIterator<Item = ModeCandidate>,
+    request: ModeRequest,
+) -> Option<u32> {
+    let drawable = candidates.into_iter().filter(ModeCandidate::is_drawable);
+    let best = match request {
+        ModeRequest::Current => None,
+        ModeRequest::Highest => {
+            drawable.max_by_key(|c| (c.area(), c.is_packed(), Reverse(c.number)))
+        }
+        ModeRequest::Resolution { width, height } => drawable.max_by_key(|c| {
+            let exact = c.width == width && c.height == height;
+            let fits = c.width <= width && c.height <= height;
+            // 収まるものは大きい方、収まらないものは小さい方を優先
+            let size = if fits {
+                c.area() as i64
+            } else {
+                -(c.area() as i64)
+            };
+            (exact, fits, size, c.is_packed(), Reverse(c.number))
+        }),
+    };
+    best.map(|c| c.number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uefi::{EFI_PIXEL_BLT_ONLY, EFI_PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR};
+
+    const fn mode(number: u32, width: u32, height: u32) -> ModeCandidate {
+        ModeCandidate {
+            number,
+            width,
+            height,
+            pixel_format: EFI_PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR,
+            pixels_per_scan_line: width,
+        }
+    }
+
+    const MODES: [ModeCandidate; 4] = [
+        mode(0, 640, 480),
+        mode(1, 1280, 800),
+        mode(2, 1920, 1080),
+        ModeCandidate {
+            pixel_format: EFI_PIXEL_BLT_ONLY,
+            ..mode(3, 2560, 1600)
+        },
+    ];
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(ModeRequest::parse("max"), Some(ModeRequest::Highest));
+        assert_eq!(
+            ModeRequest::parse("1920x1080"),
+            Some(ModeRequest::Resolution {
+                width: 1920,
+                height: 1080
+            })
+        );
+        assert_eq!(ModeRequest::parse("1920x0"), None);
+        assert_eq!(ModeRequest::parse("wide"), None);
+    }
+
+    #[test]
+    fn test_choose_resolution_falls_back_to_largest_fitting() {
+        let exact = ModeRequest::parse("1920x1080").unwrap();
+        assert_eq!(choose(MODES, exact), Some(2));
+        let between = ModeRequest::parse("1600x900").unwrap();
+        assert_eq!(choose(MODES, between), Some(1));
+        let tiny = ModeRequest::parse("320x200").unwrap();
+        assert_eq!(choose(MODES, tiny), Some(0));
+    }
+
+    #[test]
+    fn test_choose_highest_skips_blt_only_and_prefers_packed() {
+        assert_eq!(choose(MODES, ModeRequest::Highest), Some(2));
+        assert_eq!(choose(MODES, ModeRequest::Current), None);
+
+        let padded = ModeCandidate {
+            pixels_per_scan_line: 2048,
+            ..mode(4, 1920, 1080)
+        };
+        assert_eq!(
+            choose([padded, mode(5, 1920, 1080)], ModeRequest::Highest),
+            Some(5)
+        );
+    }
+}
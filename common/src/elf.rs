@@ -2,8 +2,16 @@
 
 pub const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
 pub const ELF_CLASS_64: u8 = 2;
+pub const ELF_DATA_LSB: u8 = 1;
+pub const ET_EXEC: u16 = 2;
+pub const EM_X86_64: u16 = 62;
 pub const PT_LOAD: u32 = 1;
 
+// プログラムヘッダのp_flags
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Elf64Header {
@@ -1,5 +1,13 @@
 #![no_std]
 
+pub mod bmp;
 pub mod boot_info;
+pub mod boot_progress;
+pub mod cmdline;
+pub mod cpio;
+pub mod display_mode;
 pub mod elf;
+pub mod lz4;
+pub mod psf;
 pub mod uefi;
+pub mod utf16;
@@ -0,0 +1,291 @@
+//! LZ4ブロック形式の圧縮・展開
+//!
+//! ヒープを使わない単純な貪欲法のLZ4ブロック圧縮と、その展開を提供します。
+//! 入力は64KB未満に限定し、ハッシュテーブルをスタック上の小さな配列で済ませています
+//! （カーネルのタスクスタックは16KBのため）。出力はLZ4ブロック形式と互換です。
+
+/// 最小一致長
+const MIN_MATCH: usize = 4;
+
+/// 末尾のこのバイト数は必ずリテラルとして出力する（LZ4ブロック形式の制約）
+const LAST_LITERALS: usize = 5;
+
+/// 最後の一致はブロック末尾からこのバイト数以上前で始まる必要がある
+const MF_LIMIT: usize = 12;
+
+/// 圧縮できる入力の最大長（オフセットを16bitで表すため）
+pub const MAX_INPUT_SIZE: usize = 0xFFFF;
+
+/// ハッシュテーブルのビット数
+const HASH_BITS: u32 = 10;
+
+/// LZ4展開のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lz4Error {
+    /// 入力が途中で終わっている
+    InputTruncated,
+    /// 展開結果が出力バッファに収まらない
+    OutputOverflow,
+    /// 不正な一致オフセット
+    InvalidOffset,
+}
+
+impl core::fmt::Display for Lz4Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Lz4Error::InputTruncated => write!(f, "LZ4 input is truncated"),
+            Lz4Error::OutputOverflow => write!(f, "LZ4 output buffer is too small"),
+            Lz4Error::InvalidOffset => write!(f, "LZ4 match offset is invalid"),
+        }
+    }
+}
+
+/// 最悪ケースの圧縮後サイズ（圧縮できないデータ）
+pub const fn compress_bound(input_len: usize) -> usize {
+    input_len + input_len / 255 + 16
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// 長さの拡張バイト（255の連続 + 残り）を書き込む
+fn write_length(output: &mut [u8], out: &mut usize, mut len: usize) -> Option<()> {
+    while len >= 255 {
+        *output.get_mut(*out)? = 255;
+        *out += 1;
+        len -= 255;
+    }
+    *output.get_mut(*out)? = len as u8;
+    *out += 1;
+    Some(())
+}
+
+/// シーケンス（リテラル + 一致）を1つ書き込む
+fn write_sequence(
+    output: &mut [u8],
+    out: &mut usize,
+    literals: &[u8],
+    matched: Option<(usize, usize)>,
+) -> Option<()> {
+    let token_pos = *out;
+    *out += 1;
+
+    let lit_len = literals.len();
+    let mut token = (lit_len.min(15) as u8) << 4;
+    if lit_len >= 15 {
+        write_length(output, out, lit_len - 15)?;
+    }
+    output
+        .get_mut(*out..*out + lit_len)?
+        .copy_from_slice(literals);
+    *out += lit_len;
+
+    if let Some((offset, match_len)) = matched {
+        output
+            .get_mut(*out..*out + 2)?
+            .copy_from_slice(&(offset as u16).to_le_bytes());
+        *out += 2;
+
+        let ml = match_len - MIN_MATCH;
+        token |= ml.min(15) as u8;
+        if ml >= 15 {
+            write_length(output, out, ml - 15)?;
+        }
+    }
+
+    *output.get_mut(token_pos)? = token;
+    Some(())
+}
+
+/// 入力をLZ4ブロック形式で圧縮
+///
+/// # Arguments
+/// * `input` - 圧縮するデータ（`MAX_INPUT_SIZE` 以下）
+/// * `output` - 出力先
+///
+/// # Returns
+/// 圧縮後のバイト数。入力が大きすぎる場合や出力に収まらない場合はNone
+pub fn compress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if input.len() > MAX_INPUT_SIZE {
+        return None;
+    }
+
+    let mut table = [0u16; 1 << HASH_BITS];
+    let mut out = 0;
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - LAST_LITERALS;
+        let search_end = input.len() - MF_LIMIT;
+
+        while pos < search_end {
+            let seq = read_u32(input, pos);
+            let h = hash(seq);
+            let candidate = table[h] as usize;
+            table[h] = pos as u16;
+
+            if candidate < pos && read_u32(input, candidate) == seq {
+                let mut match_len = MIN_MATCH;
+                while pos + match_len < match_limit
+                    && input[candidate + match_len] == input[pos + match_len]
+                {
+                    match_len += 1;
+                }
+
+                write_sequence(
+                    output,
+                    &mut out,
+                    &input[anchor..pos],
+                    Some((pos - candidate, match_len)),
+                )?;
+                pos += match_len;
+                anchor = pos;
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
+    // 残りはすべてリテラル
+    write_sequence(output, &mut out, &input[anchor..], None)?;
+    Some(out)
+}
+
+/// LZ4ブロックを展開
+///
+/// # Arguments
+/// * `input` - 圧縮データ
+/// * `output` - 展開先
+///
+/// # Returns
+/// 展開後のバイト数
+///
+/// # Errors
+/// * `Lz4Error::InputTruncated` - 入力が途中で終わっている場合
+/// * `Lz4Error::OutputOverflow` - 出力バッファに収まらない場合
+/// * `Lz4Error::InvalidOffset` - 一致オフセットが0または出力済み範囲外の場合
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, Lz4Error> {
+    let mut ip = 0;
+    let mut op = 0;
+
+    let read_byte = |ip: &mut usize| -> Result<u8, Lz4Error> {
+        let byte = *input.get(*ip).ok_or(Lz4Error::InputTruncated)?;
+        *ip += 1;
+        Ok(byte)
+    };
+
+    loop {
+        let token = read_byte(&mut ip)?;
+
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            loop {
+                let b = read_byte(&mut ip)?;
+                lit_len += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        let literals = input
+            .get(ip..ip + lit_len)
+            .ok_or(Lz4Error::InputTruncated)?;
+        output
+            .get_mut(op..op + lit_len)
+            .ok_or(Lz4Error::OutputOverflow)?
+            .copy_from_slice(literals);
+        ip += lit_len;
+        op += lit_len;
+
+        // 最後のシーケンスはリテラルのみ
+        if ip == input.len() {
+            return Ok(op);
+        }
+
+        let offset = u16::from_le_bytes([read_byte(&mut ip)?, read_byte(&mut ip)?]) as usize;
+        if offset == 0 || offset > op {
+            return Err(Lz4Error::InvalidOffset);
+        }
+
+        let mut match_len = (token & 0x0F) as usize;
+        if match_len == 15 {
+            loop {
+                let b = read_byte(&mut ip)?;
+                match_len += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        match_len += MIN_MATCH;
+
+        if op + match_len > output.len() {
+            return Err(Lz4Error::OutputOverflow);
+        }
+        // 一致範囲は出力位置と重なり得るため1バイトずつコピー
+        for i in 0..match_len {
+            output[op + i] = output[op - offset + i];
+        }
+        op += match_len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(input: &[u8]) -> usize {
+        let mut compressed = [0u8; compress_bound(4096)];
+        let size = compress(input, &mut compressed).expect("compress failed");
+        let mut restored = [0u8; 4096];
+        let len = decompress(&compressed[..size], &mut restored).expect("decompress failed");
+        assert_eq!(&restored[..len], input);
+        size
+    }
+
+    #[test]
+    fn test_roundtrip_zero_page() {
+        let size = roundtrip(&[0u8; 4096]);
+        assert!(size < 64);
+    }
+
+    #[test]
+    fn test_roundtrip_text_and_noise() {
+        let mut page = [0u8; 4096];
+        let mut state = 0x1234_5678u32;
+        for (i, byte) in page.iter_mut().enumerate() {
+            *byte = if i < 2048 {
+                b"vitrOS zram "[i % 12]
+            } else {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            };
+        }
+        roundtrip(&page);
+        roundtrip(&page[..13]);
+        roundtrip(&[]);
+    }
+
+    #[test]
+    fn test_output_too_small() {
+        let mut out = [0u8; 8];
+        assert_eq!(
+            compress(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14], &mut out),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_offset() {
+        // リテラル1バイト + オフセット2（出力済み1バイトを超える）
+        let input = [0x10, b'a', 0x02, 0x00];
+        let mut out = [0u8; 16];
+        assert_eq!(decompress(&input, &mut out), Err(Lz4Error::InvalidOffset));
+    }
+}
@@ -0,0 +1,443 @@
+//! PSF（PC Screen Font）ビットマップフォントの読み取り
+//!
+//! Linuxのコンソールフォント（`/usr/share/consolefonts` など）で使われるPSF1とPSF2を、
+//! コピーせずに解析します。グリフはフォントのデータを借用します。
+//!
+//! グリフの各行は `(width + 7) / 8` バイトで、最上位ビットが左端のピクセルです。
+//! Unicodeテーブルがあれば文字からグリフ番号を引けます。ないフォントは
+//! 文字コードをそのままグリフ番号として扱います。
+
+/// PSF1のマジック
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+/// PSF1のヘッダ長
+const PSF1_HEADER_SIZE: usize = 4;
+/// PSF1: グリフが512個ある
+const PSF1_MODE512: u8 = 0x01;
+/// PSF1: Unicodeテーブルがある
+const PSF1_MODEHASTAB: u8 = 0x02;
+/// PSF1: Unicodeテーブルに文字の並び（合成文字）がある
+const PSF1_MODEHASSEQ: u8 = 0x04;
+/// PSF1のUnicodeテーブルでグリフ1個分の区切り
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+/// PSF1のUnicodeテーブルで文字の並びの開始
+const PSF1_STARTSEQ: u16 = 0xFFFE;
+
+/// PSF2のマジック
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+/// PSF2のヘッダ長（固定部分）
+const PSF2_HEADER_SIZE: usize = 32;
+/// PSF2: Unicodeテーブルがある
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+/// PSF2のUnicodeテーブルでグリフ1個分の区切り
+const PSF2_SEPARATOR: u8 = 0xFF;
+/// PSF2のUnicodeテーブルで文字の並びの開始
+const PSF2_STARTSEQ: u8 = 0xFE;
+
+/// 対応するグリフの最大幅（1行を `u32` で表せる幅）
+pub const MAX_WIDTH: u32 = 32;
+
+/// 対応するグリフの最大高さ
+pub const MAX_HEIGHT: u32 = 64;
+
+/// PSFフォントのエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsfError {
+    /// マジックがPSF1でもPSF2でもない
+    BadMagic,
+    /// ヘッダのフィールドが矛盾している（グリフのバイト数が幅・高さと合わないなど）
+    BadHeader,
+    /// グリフの大きさが対応範囲（`MAX_WIDTH` × `MAX_HEIGHT`）を超えている、または0
+    UnsupportedSize { width: u32, height: u32 },
+    /// データが途中で終わっている
+    Truncated,
+}
+
+impl core::fmt::Display for PsfError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PsfError::BadMagic => write!(f, "Not a PSF1/PSF2 font"),
+            PsfError::BadHeader => write!(f, "Malformed PSF header"),
+            PsfError::UnsupportedSize { width, height } => write!(
+                f,
+                "Unsupported glyph size {}x{} (max {}x{})",
+                width, height, MAX_WIDTH, MAX_HEIGHT
+            ),
+            PsfError::Truncated => write!(f, "PSF font is truncated"),
+        }
+    }
+}
+
+/// PSFの版
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsfVersion {
+    Psf1,
+    Psf2,
+}
+
+/// 解析済みのPSFフォント
+#[derive(Debug, Clone, Copy)]
+pub struct PsfFont<'a> {
+    version: PsfVersion,
+    /// グリフのビットマップ（`glyph_count` × `bytes_per_glyph` バイト）
+    glyphs: &'a [u8],
+    /// Unicodeテーブル（なければNone）
+    unicode_table: Option<&'a [u8]>,
+    width: u32,
+    height: u32,
+    glyph_count: u32,
+    bytes_per_glyph: usize,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl<'a> PsfFont<'a> {
+    /// PSF1またはPSF2のフォントを解析
+    ///
+    /// # Errors
+    /// * `PsfError::BadMagic` - PSFのマジックがない場合
+    /// * `PsfError::BadHeader` - ヘッダが矛盾している場合
+    /// * `PsfError::UnsupportedSize` - グリフが大きすぎる、または空の場合
+    /// * `PsfError::Truncated` - グリフの途中でデータが終わっている場合
+    pub fn parse(data: &'a [u8]) -> Result<Self, PsfError> {
+        if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        } else if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else {
+            Err(PsfError::BadMagic)
+        }
+    }
+
+    fn parse_psf1(data: &'a [u8]) -> Result<Self, PsfError> {
+        if data.len() < PSF1_HEADER_SIZE {
+            return Err(PsfError::Truncated);
+        }
+        let mode = data[2];
+        let height = data[3] as u32;
+        let glyph_count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+        let has_table = mode & (PSF1_MODEHASTAB | PSF1_MODEHASSEQ) != 0;
+        Self::from_parts(
+            PsfVersion::Psf1,
+            data,
+            PSF1_HEADER_SIZE,
+            8,
+            height,
+            glyph_count,
+            height as usize,
+            has_table,
+        )
+    }
+
+    fn parse_psf2(data: &'a [u8]) -> Result<Self, PsfError> {
+        let field = |index: usize| read_u32(data, 4 + index * 4).ok_or(PsfError::Truncated);
+        let header_size = field(1)? as usize;
+        let flags = field(2)?;
+        let glyph_count = field(3)?;
+        let bytes_per_glyph = field(4)? as usize;
+        let height = field(5)?;
+        let width = field(6)?;
+        if header_size < PSF2_HEADER_SIZE {
+            return Err(PsfError::BadHeader);
+        }
+        Self::from_parts(
+            PsfVersion::Psf2,
+            data,
+            header_size,
+            width,
+            height,
+            glyph_count,
+            bytes_per_glyph,
+            flags & PSF2_HAS_UNICODE_TABLE != 0,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        version: PsfVersion,
+        data: &'a [u8],
+        header_size: usize,
+        width: u32,
+        height: u32,
+        glyph_count: u32,
+        bytes_per_glyph: usize,
+        has_table: bool,
+    ) -> Result<Self, PsfError> {
+        if width == 0 || height == 0 || width > MAX_WIDTH || height > MAX_HEIGHT {
+            return Err(PsfError::UnsupportedSize { width, height });
+        }
+        let bytes_per_row = width.div_ceil(8) as usize;
+        if bytes_per_glyph != bytes_per_row * height as usize || glyph_count == 0 {
+            return Err(PsfError::BadHeader);
+        }
+        let glyphs_len = (glyph_count as usize)
+            .checked_mul(bytes_per_glyph)
+            .ok_or(PsfError::BadHeader)?;
+        let glyphs_end = header_size
+            .checked_add(glyphs_len)
+            .ok_or(PsfError::BadHeader)?;
+        let glyphs = data
+            .get(header_size..glyphs_end)
+            .ok_or(PsfError::Truncated)?;
+        Ok(Self {
+            version,
+            glyphs,
+            unicode_table: has_table.then(|| &data[glyphs_end..]),
+            width,
+            height,
+            glyph_count,
+            bytes_per_glyph,
+        })
+    }
+
+    /// PSFの版
+    pub fn version(&self) -> PsfVersion {
+        self.version
+    }
+
+    /// グリフの幅（ピクセル）
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// グリフの高さ（ピクセル）
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// グリフの個数
+    pub fn glyph_count(&self) -> u32 {
+        self.glyph_count
+    }
+
+    /// Unicodeテーブルがあるか
+    pub fn has_unicode_table(&self) -> bool {
+        self.unicode_table.is_some()
+    }
+
+    /// グリフ1行分のビットマップ（bit 0が左端）
+    ///
+    /// # Returns
+    /// グリフ番号か行が範囲外ならNone
+    pub fn row(&self, glyph: u32, row: u32) -> Option<u32> {
+        if glyph >= self.glyph_count || row >= self.height {
+            return None;
+        }
+        let bytes_per_row = self.width.div_ceil(8) as usize;
+        let start = glyph as usize * self.bytes_per_glyph + row as usize * bytes_per_row;
+        let bytes = &self.glyphs[start..start + bytes_per_row];
+        // PSFは最上位ビットが左端なので、各バイトを反転してbit 0を左端にする
+        Some(bytes.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte.reverse_bits() as u32) << (i * 8)
+        }))
+    }
+
+    /// Unicodeテーブルの（文字, グリフ番号）の組を順に返す
+    ///
+    /// テーブルがないフォントでは何も返しません。文字の並び（合成文字）は読み飛ばし、
+    /// 不正な値やテーブルの途中の終端があればそこで終了します。
+    pub fn unicode_entries(&self) -> UnicodeEntries<'a> {
+        UnicodeEntries {
+            version: self.version,
+            table: self.unicode_table.unwrap_or(&[]),
+            offset: 0,
+            glyph: 0,
+            glyph_count: self.glyph_count,
+            in_sequence: false,
+        }
+    }
+}
+
+/// Unicodeテーブルのイテレータ
+pub struct UnicodeEntries<'a> {
+    version: PsfVersion,
+    table: &'a [u8],
+    offset: usize,
+    glyph: u32,
+    glyph_count: u32,
+    /// 文字の並びの途中（次の区切りまで読み飛ばす）
+    in_sequence: bool,
+}
+
+/// テーブルの次の要素
+enum TableItem {
+    Char(char),
+    Separator,
+    StartSequence,
+}
+
+impl UnicodeEntries<'_> {
+    fn next_item(&mut self) -> Option<TableItem> {
+        let rest = self.table.get(self.offset..)?;
+        match self.version {
+            PsfVersion::Psf1 => {
+                let bytes = rest.get(..2)?;
+                self.offset += 2;
+                match u16::from_le_bytes([bytes[0], bytes[1]]) {
+                    PSF1_SEPARATOR => Some(TableItem::Separator),
+                    PSF1_STARTSEQ => Some(TableItem::StartSequence),
+                    code => char::from_u32(code as u32).map(TableItem::Char),
+                }
+            }
+            PsfVersion::Psf2 => {
+                let first = *rest.first()?;
+                match first {
+                    PSF2_SEPARATOR => {
+                        self.offset += 1;
+                        Some(TableItem::Separator)
+                    }
+                    PSF2_STARTSEQ => {
+                        self.offset += 1;
+                        Some(TableItem::StartSequence)
+                    }
+                    _ => {
+                        let len = match first {
+                            0x00..=0x7F => 1,
+                            0xC0..=0xDF => 2,
+                            0xE0..=0xEF => 3,
+                            0xF0..=0xF7 => 4,
+                            _ => return None,
+                        };
+                        let ch = core::str::from_utf8(rest.get(..len)?)
+                            .ok()?
+                            .chars()
+                            .next()?;
+                        self.offset += len;
+                        Some(TableItem::Char(ch))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for UnicodeEntries<'_> {
+    type Item = (char, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.glyph < self.glyph_count {
+            match self.next_item()? {
+                TableItem::Separator => {
+                    self.glyph += 1;
+                    self.in_sequence = false;
+                }
+                TableItem::StartSequence => self.in_sequence = true,
+                TableItem::Char(_) if self.in_sequence => {}
+                TableItem::Char(ch) => return Some((ch, self.glyph)),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用のPSF2フォント（10x2ピクセル、グリフ2個、Unicodeテーブル付き）を書き込む
+    fn build_psf2(out: &mut [u8; 64]) -> usize {
+        let header: [u32; 7] = [0, 32, PSF2_HAS_UNICODE_TABLE, 2, 4, 2, 10];
+        out[..4].copy_from_slice(&PSF2_MAGIC);
+        for (i, value) in header.iter().enumerate() {
+            out[4 + i * 4..8 + i * 4].copy_from_slice(&value.to_le_bytes());
+        }
+        // グリフ0: 1行目は左端、2行目は右端（10ピクセル目）
+        out[32..36].copy_from_slice(&[0x80, 0x00, 0x00, 0x40]);
+        // グリフ1: 全面
+        out[36..40].copy_from_slice(&[0xFF, 0xC0, 0xFF, 0xC0]);
+        // グリフ0 = 'A' と 'Ä'（2バイトのUTF-8）、グリフ1 = 'B' と並び "e\u{301}"
+        let table = [
+            b'A',
+            0xC3,
+            0x84,
+            PSF2_SEPARATOR,
+            b'B',
+            PSF2_STARTSEQ,
+            b'e',
+            0xCC,
+            0x81,
+            PSF2_SEPARATOR,
+        ];
+        out[40..40 + table.len()].copy_from_slice(&table);
+        40 + table.len()
+    }
+
+    #[test]
+    fn test_parses_psf2_header_and_rows() {
+        let mut buf = [0u8; 64];
+        let len = build_psf2(&mut buf);
+        let font = PsfFont::parse(&buf[..len]).unwrap();
+        assert_eq!(font.version(), PsfVersion::Psf2);
+        assert_eq!(
+            (font.width(), font.height(), font.glyph_count()),
+            (10, 2, 2)
+        );
+        assert_eq!(font.row(0, 0), Some(0b1));
+        assert_eq!(font.row(0, 1), Some(1 << 9));
+        assert_eq!(font.row(1, 0), Some(0x3FF));
+        assert_eq!(font.row(2, 0), None);
+        assert_eq!(font.row(0, 2), None);
+    }
+
+    #[test]
+    fn test_reads_psf2_unicode_table() {
+        let mut buf = [0u8; 64];
+        let len = build_psf2(&mut buf);
+        let font = PsfFont::parse(&buf[..len]).unwrap();
+        let mut entries = font.unicode_entries();
+        assert_eq!(entries.next(), Some(('A', 0)));
+        assert_eq!(entries.next(), Some(('Ä', 0)));
+        assert_eq!(entries.next(), Some(('B', 1)));
+        assert_eq!(entries.next(), None);
+    }
+
+    #[test]
+    fn test_parses_psf1_with_table() {
+        // 8x1ピクセル、グリフ256個、Unicodeテーブル付き
+        let mut buf = [0u8; 4 + 256 + 8];
+        buf[..4].copy_from_slice(&[0x36, 0x04, PSF1_MODEHASTAB, 1]);
+        buf[4 + 65] = 0x81;
+        // グリフ0 = U+263A、グリフ1は区切りのみ
+        buf[260..268].copy_from_slice(&[0x3A, 0x26, 0xFF, 0xFF, 0xFF, 0xFF, 0x41, 0x00]);
+        let font = PsfFont::parse(&buf).unwrap();
+        assert_eq!(font.version(), PsfVersion::Psf1);
+        assert_eq!(
+            (font.width(), font.height(), font.glyph_count()),
+            (8, 1, 256)
+        );
+        assert_eq!(font.row(65, 0), Some(0x81));
+        let mut entries = font.unicode_entries();
+        assert_eq!(entries.next(), Some(('\u{263A}', 0)));
+        assert_eq!(entries.next(), Some(('A', 2)));
+        assert_eq!(entries.next(), None);
+    }
+
+    #[test]
+    fn test_rejects_bad_fonts() {
+        assert_eq!(PsfFont::parse(b"nope").unwrap_err(), PsfError::BadMagic);
+        assert_eq!(
+            PsfFont::parse(&[0x36, 0x04, 0, 16]).unwrap_err(),
+            PsfError::Truncated
+        );
+        let mut buf = [0u8; 64];
+        let len = build_psf2(&mut buf);
+        // グリフのバイト数が幅・高さと合わない
+        buf[20] = 5;
+        assert_eq!(
+            PsfFont::parse(&buf[..len]).unwrap_err(),
+            PsfError::BadHeader
+        );
+        buf[20] = 4;
+        // 幅が大きすぎる
+        buf[28] = 33;
+        assert_eq!(
+            PsfFont::parse(&buf[..len]).unwrap_err(),
+            PsfError::UnsupportedSize {
+                width: 33,
+                height: 2
+            }
+        );
+    }
+}
@@ -5,6 +5,43 @@ pub type EfiStatus = usize;
 // EFIステータスコード
 pub const EFI_SUCCESS: EfiStatus = 0;
 
+/// エラーを表すステータスの最上位ビット
+pub const EFI_ERROR_BIT: EfiStatus = 1 << (usize::BITS - 1);
+pub const EFI_LOAD_ERROR: EfiStatus = EFI_ERROR_BIT | 1;
+pub const EFI_INVALID_PARAMETER: EfiStatus = EFI_ERROR_BIT | 2;
+pub const EFI_UNSUPPORTED: EfiStatus = EFI_ERROR_BIT | 3;
+pub const EFI_BUFFER_TOO_SMALL: EfiStatus = EFI_ERROR_BIT | 5;
+pub const EFI_DEVICE_ERROR: EfiStatus = EFI_ERROR_BIT | 7;
+pub const EFI_OUT_OF_RESOURCES: EfiStatus = EFI_ERROR_BIT | 9;
+pub const EFI_NOT_FOUND: EfiStatus = EFI_ERROR_BIT | 14;
+pub const EFI_NO_RESPONSE: EfiStatus = EFI_ERROR_BIT | 16;
+pub const EFI_TIMEOUT: EfiStatus = EFI_ERROR_BIT | 18;
+pub const EFI_NOT_STARTED: EfiStatus = EFI_ERROR_BIT | 19;
+pub const EFI_ALREADY_STARTED: EfiStatus = EFI_ERROR_BIT | 20;
+pub const EFI_ICMP_ERROR: EfiStatus = EFI_ERROR_BIT | 22;
+pub const EFI_TFTP_ERROR: EfiStatus = EFI_ERROR_BIT | 23;
+
+/// EFIステータスコードの名前を取得（表示用）
+pub fn status_name(status: EfiStatus) -> &'static str {
+    match status {
+        EFI_SUCCESS => "EFI_SUCCESS",
+        EFI_LOAD_ERROR => "EFI_LOAD_ERROR",
+        EFI_INVALID_PARAMETER => "EFI_INVALID_PARAMETER",
+        EFI_UNSUPPORTED => "EFI_UNSUPPORTED",
+        EFI_BUFFER_TOO_SMALL => "EFI_BUFFER_TOO_SMALL",
+        EFI_DEVICE_ERROR => "EFI_DEVICE_ERROR",
+        EFI_OUT_OF_RESOURCES => "EFI_OUT_OF_RESOURCES",
+        EFI_NOT_FOUND => "EFI_NOT_FOUND",
+        EFI_NO_RESPONSE => "EFI_NO_RESPONSE",
+        EFI_TIMEOUT => "EFI_TIMEOUT",
+        EFI_NOT_STARTED => "EFI_NOT_STARTED",
+        EFI_ALREADY_STARTED => "EFI_ALREADY_STARTED",
+        EFI_ICMP_ERROR => "EFI_ICMP_ERROR",
+        EFI_TFTP_ERROR => "EFI_TFTP_ERROR",
+        _ => "unknown status",
+    }
+}
+
 // GUID (プロトコル識別子)
 #[repr(C)]
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -51,6 +88,12 @@ pub struct EfiTableHeader {
 
 const _: () = assert!(core::mem::size_of::<EfiTableHeader>() == 24);
 
+// GOPピクセルフォーマット（EFI_GRAPHICS_PIXEL_FORMAT）
+pub const EFI_PIXEL_RED_GREEN_BLUE_RESERVED_8BIT_PER_COLOR: u32 = 0;
+pub const EFI_PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR: u32 = 1;
+pub const EFI_PIXEL_BIT_MASK: u32 = 2;
+pub const EFI_PIXEL_BLT_ONLY: u32 = 3;
+
 // Graphics Output Protocol Mode Information
 #[repr(C)]
 pub struct EfiGraphicsOutputModeInformation {
@@ -76,8 +119,16 @@ pub struct EfiGraphicsOutputProtocolMode {
 // Graphics Output Protocol
 #[repr(C)]
 pub struct EfiGraphicsOutputProtocol {
-    pub query_mode: usize,
-    pub set_mode: usize,
+    pub query_mode: extern "efiapi" fn(
+        *mut EfiGraphicsOutputProtocol,             // This
+        u32,                                        // ModeNumber
+        *mut usize,                                 // SizeOfInfo
+        *mut *mut EfiGraphicsOutputModeInformation, // Info（呼び出し元がFreePoolで解放）
+    ) -> EfiStatus,
+    pub set_mode: extern "efiapi" fn(
+        *mut EfiGraphicsOutputProtocol, // This
+        u32,                            // ModeNumber
+    ) -> EfiStatus,
     pub blt: usize,
     pub mode: *mut EfiGraphicsOutputProtocolMode,
 }
@@ -101,16 +152,48 @@ pub struct EfiSimpleTextOutputProtocol {
     pub reset: extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, bool) -> EfiStatus,
     pub output_string:
         extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, *const u16) -> EfiStatus,
-    pub test_string: usize,
-    pub query_mode: usize,
-    pub set_mode: usize,
-    pub set_attribute: usize,
+    pub test_string: extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, *const u16) -> EfiStatus,
+    pub query_mode: extern "efiapi" fn(
+        *mut EfiSimpleTextOutputProtocol,
+        usize,
+        *mut usize,
+        *mut usize,
+    ) -> EfiStatus,
+    pub set_mode: extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, usize) -> EfiStatus,
+    pub set_attribute: extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, usize) -> EfiStatus,
     pub clear_screen: extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol) -> EfiStatus,
-    pub set_cursor_position: usize,
-    pub enable_cursor: usize,
+    pub set_cursor_position:
+        extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, usize, usize) -> EfiStatus,
+    pub enable_cursor: extern "efiapi" fn(*mut EfiSimpleTextOutputProtocol, bool) -> EfiStatus,
     pub mode: *mut SimpleTextOutputMode,
 }
 
+const _: () = assert!(core::mem::offset_of!(EfiSimpleTextOutputProtocol, mode) == 72);
+
+// テキスト属性（前景色）
+pub const EFI_BLACK: usize = 0x00;
+pub const EFI_BLUE: usize = 0x01;
+pub const EFI_GREEN: usize = 0x02;
+pub const EFI_CYAN: usize = 0x03;
+pub const EFI_RED: usize = 0x04;
+pub const EFI_MAGENTA: usize = 0x05;
+pub const EFI_BROWN: usize = 0x06;
+pub const EFI_LIGHTGRAY: usize = 0x07;
+pub const EFI_DARKGRAY: usize = 0x08;
+pub const EFI_LIGHTRED: usize = 0x0C;
+pub const EFI_YELLOW: usize = 0x0E;
+pub const EFI_WHITE: usize = 0x0F;
+
+// テキスト属性（背景色）
+pub const EFI_BACKGROUND_BLACK: usize = 0x00;
+pub const EFI_BACKGROUND_BLUE: usize = 0x10;
+pub const EFI_BACKGROUND_RED: usize = 0x40;
+
+/// 前景色と背景色からテキスト属性を作成（EFI_TEXT_ATTR）
+pub const fn efi_text_attr(foreground: usize, background: usize) -> usize {
+    foreground | background
+}
+
 // メモリタイプ
 pub const EFI_RESERVED_MEMORY_TYPE: u32 = 0;
 pub const EFI_LOADER_CODE: u32 = 1;
@@ -141,7 +224,17 @@ pub struct EfiMemoryDescriptor {
 #[repr(C)]
 pub struct EfiBootServices {
     pub hdr: EfiTableHeader,
-    _pad1: [usize; 4], // 1-4: RaiseTPL, RestoreTPL, AllocatePages, FreePages
+    _pad1: [usize; 2], // 1-2: RaiseTPL, RestoreTPL
+    pub allocate_pages: extern "efiapi" fn(
+        u32,      // Type (EFI_ALLOCATE_*)
+        u32,      // MemoryType
+        usize,    // Pages
+        *mut u64, // Memory
+    ) -> EfiStatus,
+    pub free_pages: extern "efiapi" fn(
+        u64,   // Memory
+        usize, // Pages
+    ) -> EfiStatus,
     pub get_memory_map: extern "efiapi" fn(
         *mut usize,               // MemoryMapSize
         *mut EfiMemoryDescriptor, // MemoryMap
@@ -149,18 +242,26 @@ pub struct EfiBootServices {
         *mut usize,               // DescriptorSize
         *mut u32,                 // DescriptorVersion
     ) -> EfiStatus,
-    _pad2: [usize; 21], // 6-26: その他の関数
+    _pad2: [usize; 1], // 5: AllocatePool
+    pub free_pool: extern "efiapi" fn(
+        *mut core::ffi::c_void, // Buffer
+    ) -> EfiStatus,
+    _pad2_rest: [usize; 19], // 7-25: その他の関数
     pub exit_boot_services: extern "efiapi" fn(
         EfiHandle, // ImageHandle
         usize,     // MapKey
     ) -> EfiStatus,
-    _pad3: [usize; 8], // 28-35: その他の関数
+    _pad3: [usize; 1], // 28: GetNextMonotonicCount
+    pub stall: extern "efiapi" fn(
+        usize, // Microseconds
+    ) -> EfiStatus,
+    _pad4: [usize; 6], // 30-35: その他の関数
     pub handle_protocol: extern "efiapi" fn(
         EfiHandle,                   // Handle
         *const EfiGuid,              // Protocol
         *mut *mut core::ffi::c_void, // Interface
     ) -> EfiStatus,
-    _pad4: [usize; 1], // 37: その他の関数
+    _pad5: [usize; 1], // 37: その他の関数
     pub locate_protocol: extern "efiapi" fn(
         *const EfiGuid,
         *mut core::ffi::c_void,
@@ -168,9 +269,17 @@ pub struct EfiBootServices {
     ) -> EfiStatus,
 }
 
+// AllocatePagesの割り当て方法（EFI_ALLOCATE_TYPE）
+pub const EFI_ALLOCATE_ANY_PAGES: u32 = 0;
+pub const EFI_ALLOCATE_MAX_ADDRESS: u32 = 1;
+pub const EFI_ALLOCATE_ADDRESS: u32 = 2;
+
 const _: () = {
+    assert!(core::mem::offset_of!(EfiBootServices, allocate_pages) == 40);
     assert!(core::mem::offset_of!(EfiBootServices, get_memory_map) == 56);
+    assert!(core::mem::offset_of!(EfiBootServices, free_pool) == 72);
     assert!(core::mem::offset_of!(EfiBootServices, exit_boot_services) == 232);
+    assert!(core::mem::offset_of!(EfiBootServices, stall) == 248);
     assert!(core::mem::offset_of!(EfiBootServices, handle_protocol) == 304);
     assert!(core::mem::offset_of!(EfiBootServices, locate_protocol) == 320);
 };
@@ -221,6 +330,48 @@ pub const EFI_LOADED_IMAGE_PROTOCOL_GUID: EfiGuid = EfiGuid {
 // File open modes
 pub const EFI_FILE_MODE_READ: u64 = 0x0000000000000001;
 
+/// SetPositionでファイル末尾へ移動する位置
+pub const EFI_FILE_POSITION_END: u64 = u64::MAX;
+
+// File Info GUID（GetInfoで取得する情報の種類）
+pub const EFI_FILE_INFO_ID: EfiGuid = EfiGuid {
+    data1: 0x09576e92,
+    data2: 0x6d3f,
+    data3: 0x11d2,
+    data4: [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+};
+
+// Time
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct EfiTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub pad1: u8,
+    pub nanosecond: u32,
+    pub time_zone: i16,
+    pub daylight: u8,
+    pub pad2: u8,
+}
+
+/// File Info（可変長のファイル名が後に続く）
+#[repr(C)]
+pub struct EfiFileInfo {
+    pub size: u64,
+    pub file_size: u64,
+    pub physical_size: u64,
+    pub create_time: EfiTime,
+    pub last_access_time: EfiTime,
+    pub modification_time: EfiTime,
+    pub attribute: u64,
+}
+
+const _: () = assert!(core::mem::size_of::<EfiFileInfo>() == 80);
+
 // File Protocol
 #[repr(C)]
 pub struct EfiFileProtocol {
@@ -240,9 +391,14 @@ pub struct EfiFileProtocol {
         *mut core::ffi::c_void, // Buffer
     ) -> EfiStatus,
     pub write: usize,
-    pub get_position: usize,
-    pub set_position: usize,
-    pub get_info: usize,
+    pub get_position: extern "efiapi" fn(*mut EfiFileProtocol, *mut u64) -> EfiStatus,
+    pub set_position: extern "efiapi" fn(*mut EfiFileProtocol, u64) -> EfiStatus,
+    pub get_info: extern "efiapi" fn(
+        *mut EfiFileProtocol,   // This
+        *const EfiGuid,         // InformationType
+        *mut usize,             // BufferSize
+        *mut core::ffi::c_void, // Buffer
+    ) -> EfiStatus,
     pub set_info: usize,
     pub flush: usize,
 }
@@ -264,5 +420,113 @@ pub struct EfiLoadedImageProtocol {
     pub parent_handle: EfiHandle,
     pub system_table: *mut EfiSystemTable,
     pub device_handle: EfiHandle,
+    pub file_path: *mut core::ffi::c_void,
+    pub reserved: *mut core::ffi::c_void,
+    /// ロードオプションのバイト数
+    pub load_options_size: u32,
+    /// ロードオプション（UEFIシェルやブートエントリが渡すUTF-16の文字列）
+    pub load_options: *mut core::ffi::c_void,
+    // ... 他のフィールドは省略
+}
+
+// PXE Base Code Protocol GUID
+pub const EFI_PXE_BASE_CODE_PROTOCOL_GUID: EfiGuid = EfiGuid {
+    data1: 0x03c4e603,
+    data2: 0xac28,
+    data3: 0x11d3,
+    data4: [0x9a, 0x2d, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+};
+
+// MTFTPの操作（EFI_PXE_BASE_CODE_TFTP_OPCODE）
+pub const EFI_PXE_BASE_CODE_TFTP_GET_FILE_SIZE: u32 = 1;
+pub const EFI_PXE_BASE_CODE_TFTP_READ_FILE: u32 = 2;
+
+// IPアドレス（EFI_IP_ADDRESS、IPv4は先頭4バイト）
+#[repr(C, align(4))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EfiIpAddress {
+    pub addr: [u8; 16],
+}
+
+// DHCP/PXEパケット（EFI_PXE_BASE_CODE_PACKET）
+#[repr(C, align(4))]
+pub struct EfiPxeBaseCodePacket {
+    pub raw: [u8; 1472],
+}
+
+/// DHCPv4パケット内のsiaddr（次に使うサーバー）のオフセット
+pub const DHCPV4_SIADDR_OFFSET: usize = 20;
+
+// PXE Base Code Mode（先頭のみ、ファームウェアが所有するため参照のみ）
+#[repr(C)]
+pub struct EfiPxeBaseCodeMode {
+    pub started: bool,
+    pub ipv6_available: bool,
+    pub ipv6_supported: bool,
+    pub using_ipv6: bool,
+    pub bis_supported: bool,
+    pub bis_detected: bool,
+    pub auto_arp: bool,
+    pub send_guid: bool,
+    pub dhcp_discover_valid: bool,
+    pub dhcp_ack_received: bool,
+    pub proxy_offer_received: bool,
+    pub pxe_discover_valid: bool,
+    pub pxe_reply_received: bool,
+    pub pxe_bis_reply_received: bool,
+    pub icmp_error_received: bool,
+    pub tftp_error_received: bool,
+    pub make_callbacks: bool,
+    pub ttl: u8,
+    pub tos: u8,
+    pub station_ip: EfiIpAddress,
+    pub subnet_mask: EfiIpAddress,
+    pub dhcp_discover: EfiPxeBaseCodePacket,
+    pub dhcp_ack: EfiPxeBaseCodePacket,
+    pub proxy_offer: EfiPxeBaseCodePacket,
     // ... 他のフィールドは省略
 }
+
+const _: () = {
+    assert!(core::mem::offset_of!(EfiPxeBaseCodeMode, station_ip) == 20);
+    assert!(core::mem::offset_of!(EfiPxeBaseCodeMode, dhcp_ack) == 1524);
+    assert!(core::mem::offset_of!(EfiPxeBaseCodeMode, proxy_offer) == 2996);
+};
+
+// PXE Base Code Protocol
+#[repr(C)]
+pub struct EfiPxeBaseCodeProtocol {
+    pub revision: u64,
+    pub start: extern "efiapi" fn(
+        *mut EfiPxeBaseCodeProtocol, // This
+        bool,                        // UseIpv6
+    ) -> EfiStatus,
+    pub stop: usize,
+    pub dhcp: extern "efiapi" fn(
+        *mut EfiPxeBaseCodeProtocol, // This
+        bool,                        // SortOffers
+    ) -> EfiStatus,
+    pub discover: usize,
+    pub mtftp: extern "efiapi" fn(
+        *mut EfiPxeBaseCodeProtocol, // This
+        u32,                         // Operation
+        *mut core::ffi::c_void,      // BufferPtr
+        bool,                        // Overwrite
+        *mut u64,                    // BufferSize
+        *const usize,                // BlockSize
+        *const EfiIpAddress,         // ServerIp
+        *const u8,                   // Filename (ASCII)
+        *const core::ffi::c_void,    // Info
+        bool,                        // DontUseBuffer
+    ) -> EfiStatus,
+    pub udp_write: usize,
+    pub udp_read: usize,
+    pub set_ip_filter: usize,
+    pub arp: usize,
+    pub set_parameters: usize,
+    pub set_station_ip: usize,
+    pub set_packets: usize,
+    pub mode: *mut EfiPxeBaseCodeMode,
+}
+
+const _: () = assert!(core::mem::offset_of!(EfiPxeBaseCodeProtocol, mode) == 104);
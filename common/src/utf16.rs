@@ -0,0 +1,223 @@
+//! UTF-16の変換
+//!
+//! UEFIの文字列（CHAR16）はNUL終端のUTF-16です。ファイル名などを渡すための
+//! UTF-8 → UTF-16と、ファームウェアベンダー名やファイル情報などを読むための
+//! UTF-16 → UTF-8の変換を、ヒープを使わずに呼び出し側のバッファで行います。
+//!
+//! BMP外の文字はサロゲートペアに変換します。UTF-16側の不正なサロゲート
+//! （対になっていないもの）はU+FFFDに置き換えます。
+
+/// UTF-16変換のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Utf16Error {
+    /// 出力バッファに収まらない
+    BufferTooSmall,
+    /// 文字列の途中にNULがある（UEFIに渡すと途中で切れてしまう）
+    InteriorNul,
+}
+
+impl core::fmt::Display for Utf16Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Utf16Error::BufferTooSmall => write!(f, "String does not fit in the buffer"),
+            Utf16Error::InteriorNul => write!(f, "String contains a NUL character"),
+        }
+    }
+}
+
+/// UTF-8の文字列をNUL終端のUTF-16に変換
+///
+/// # Returns
+/// 書き込んだコードユニット数（NUL終端を含まない）
+///
+/// # Errors
+/// * `Utf16Error::BufferTooSmall` - NUL終端を含めて `buf` に収まらない場合（途中で切り捨てない）
+/// * `Utf16Error::InteriorNul` - 文字列がNULを含む場合
+pub fn encode(s: &str, buf: &mut [u16]) -> Result<usize, Utf16Error> {
+    let mut len = 0;
+    for c in s.chars() {
+        if c == '\0' {
+            return Err(Utf16Error::InteriorNul);
+        }
+        let units = c.len_utf16();
+        // NUL終端の分を残す
+        if len + units >= buf.len() {
+            return Err(Utf16Error::BufferTooSmall);
+        }
+        c.encode_utf16(&mut buf[len..len + units]);
+        len += units;
+    }
+    *buf.get_mut(len).ok_or(Utf16Error::BufferTooSmall)? = 0;
+    Ok(len)
+}
+
+/// UTF-8の文字列を、収まる分だけNUL終端のUTF-16に変換（画面表示用）
+///
+/// サロゲートペアの途中では切らず、NULは `?` に置き換えます。
+///
+/// # Returns
+/// (書き込んだコードユニット数（NUL終端を含まない）, 変換した `s` のバイト数)
+pub fn encode_truncated(s: &str, buf: &mut [u16]) -> (usize, usize) {
+    let Some(capacity) = buf.len().checked_sub(1) else {
+        return (0, 0);
+    };
+    let mut len = 0;
+    let mut consumed = 0;
+    for c in s.chars() {
+        let c = if c == '\0' { '?' } else { c };
+        let units = c.len_utf16();
+        if len + units > capacity {
+            break;
+        }
+        c.encode_utf16(&mut buf[len..len + units]);
+        len += units;
+        consumed += c.len_utf8();
+    }
+    buf[len] = 0;
+    (len, consumed)
+}
+
+/// 固定長のNUL終端UTF-16文字列（UEFIに渡すファイル名など）
+#[derive(Clone, Copy)]
+pub struct Utf16Buf<const N: usize> {
+    units: [u16; N],
+    len: usize,
+}
+
+impl<const N: usize> Utf16Buf<N> {
+    /// UTF-8の文字列から作成
+    ///
+    /// # Errors
+    /// `encode` と同じ
+    pub fn new(s: &str) -> Result<Self, Utf16Error> {
+        let mut units = [0; N];
+        let len = encode(s, &mut units)?;
+        Ok(Self { units, len })
+    }
+
+    /// NUL終端された文字列へのポインタ
+    pub fn as_ptr(&self) -> *const u16 {
+        self.units.as_ptr()
+    }
+
+    /// コードユニット列（NUL終端を含まない）
+    pub fn as_slice(&self) -> &[u16] {
+        &self.units[..self.len]
+    }
+}
+
+/// UTF-16をUTF-8に変換
+///
+/// 最初のNULまでを変換し、不正なサロゲートはU+FFFDに置き換えます。
+///
+/// # Errors
+/// * `Utf16Error::BufferTooSmall` - `buf` に収まらない場合
+pub fn decode<'a>(units: &[u16], buf: &'a mut [u8]) -> Result<&'a str, Utf16Error> {
+    let end = units.iter().position(|&u| u == 0).unwrap_or(units.len());
+    let mut len = 0;
+    for c in char::decode_utf16(units[..end].iter().copied()) {
+        let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+        let target = buf
+            .get_mut(len..len + c.len_utf8())
+            .ok_or(Utf16Error::BufferTooSmall)?;
+        c.encode_utf8(target);
+        len += c.len_utf8();
+    }
+    // 文字単位で書き込んでいるため、常に有効なUTF-8
+    core::str::from_utf8(&buf[..len]).map_err(|_| Utf16Error::BufferTooSmall)
+}
+
+/// NUL終端のUTF-16文字列をスライスとして借用
+///
+/// NULが `max_len` 個以内に見つからない場合は `max_len` 個で打ち切ります。
+///
+/// # Safety
+/// `ptr` はnullでないこと。NULまたは `max_len` 個のどちらか短い方まで読み出せ、
+/// 返すスライスを使う間は書き換えられないこと。
+pub unsafe fn from_ptr<'a>(ptr: *const u16, max_len: usize) -> &'a [u16] {
+    let mut len = 0;
+    // SAFETY: 呼び出し側がNULまたはmax_len個まで読めることを保証する
+    while len < max_len && unsafe { *ptr.add(len) } != 0 {
+        len += 1;
+    }
+    // SAFETY: 同上
+    unsafe { core::slice::from_raw_parts(ptr, len) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_ascii_with_terminator() {
+        let mut buf = [0xFFFF; 8];
+        assert_eq!(encode("abc", &mut buf), Ok(3));
+        assert_eq!(&buf[..4], &[0x61, 0x62, 0x63, 0]);
+    }
+
+    #[test]
+    fn test_encode_surrogate_pair() {
+        let mut buf = [0; 4];
+        assert_eq!(encode("\u{1F600}", &mut buf), Ok(2));
+        assert_eq!(&buf[..3], &[0xD83D, 0xDE00, 0]);
+    }
+
+    #[test]
+    fn test_encode_rejects_overflow_and_nul() {
+        let mut buf = [0; 3];
+        // 3文字 + NUL終端は入らない
+        assert_eq!(encode("abc", &mut buf), Err(Utf16Error::BufferTooSmall));
+        // サロゲートペアとNUL終端で3ユニット必要
+        assert_eq!(
+            encode("\u{1F600}", &mut buf[..2]),
+            Err(Utf16Error::BufferTooSmall)
+        );
+        assert_eq!(encode("a\0b", &mut buf), Err(Utf16Error::InteriorNul));
+        assert_eq!(encode("", &mut []), Err(Utf16Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn test_encode_long_name_is_not_truncated() {
+        let name = "a_very_long_kernel_file_name_over_31.elf";
+        let mut buf = [0; 64];
+        let len = encode(name, &mut buf).unwrap();
+        assert_eq!(len, name.len());
+        let mut out = [0; 64];
+        assert_eq!(decode(&buf, &mut out), Ok(name));
+    }
+
+    #[test]
+    fn test_encode_truncated_keeps_pairs_whole() {
+        let mut buf = [0; 4];
+        // "a" + サロゲートペアで3ユニット、次の文字は入らない
+        assert_eq!(encode_truncated("a\u{1F600}b", &mut buf), (3, 5));
+        assert_eq!(buf, [0x61, 0xD83D, 0xDE00, 0]);
+        let mut buf = [0; 3];
+        assert_eq!(encode_truncated("a\u{1F600}", &mut buf), (1, 1));
+        assert_eq!(&buf[..2], &[0x61, 0]);
+    }
+
+    #[test]
+    fn test_decode_stops_at_nul_and_replaces_lone_surrogates() {
+        let units = [0x45, 0xD83D, 0xDE00, 0xDC00, 0x46, 0, 0x47];
+        let mut out = [0; 16];
+        assert_eq!(decode(&units, &mut out), Ok("E\u{1F600}\u{FFFD}F"));
+    }
+
+    #[test]
+    fn test_decode_rejects_small_buffer() {
+        let units = [0x3042, 0];
+        let mut out = [0; 2];
+        assert_eq!(decode(&units, &mut out), Err(Utf16Error::BufferTooSmall));
+    }
+
+    #[test]
+    fn test_buf_round_trip() {
+        let name = Utf16Buf::<16>::new("initrd.img").unwrap();
+        assert_eq!(name.as_slice().len(), 10);
+        // SAFETY: Utf16BufはNUL終端されている
+        let units = unsafe { from_ptr(name.as_ptr(), 16) };
+        let mut out = [0; 16];
+        assert_eq!(decode(units, &mut out), Ok("initrd.img"));
+    }
+}
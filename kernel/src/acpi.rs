@@ -108,6 +108,17 @@ struct MadtIoApic {
     global_system_interrupt_base: u32,
 }
 
+/// MADT エントリ: Interrupt Source Override
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct MadtInterruptSourceOverride {
+    header: MadtEntryHeader,
+    bus: u8, // 0 = ISA
+    source: u8,
+    global_system_interrupt: u32,
+    flags: u16,
+}
+
 /// MADT (Multiple APIC Description Table) テーブル
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -139,6 +150,70 @@ struct HpetTable {
     page_protection: u8,
 }
 
+/// FADT (Fixed ACPI Description Table) テーブル
+///
+/// 電源管理に使用するフィールドのみ定義しています（X_DSDTまで）。
+/// ACPI 1.0のFADTはこれより短いため、X_DSDTはテーブル長を確認してから参照します。
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct Fadt {
+    header: AcpiTableHeader,
+    firmware_ctrl: u32,
+    dsdt: u32,
+    reserved: u8,
+    preferred_pm_profile: u8,
+    sci_int: u16,
+    smi_cmd: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_req: u8,
+    pstate_cnt: u8,
+    pm1a_evt_blk: u32,
+    pm1b_evt_blk: u32,
+    pm1a_cnt_blk: u32,
+    pm1b_cnt_blk: u32,
+    pm2_cnt_blk: u32,
+    pm_tmr_blk: u32,
+    gpe0_blk: u32,
+    gpe1_blk: u32,
+    pm1_evt_len: u8,
+    pm1_cnt_len: u8,
+    pm2_cnt_len: u8,
+    pm_tmr_len: u8,
+    gpe0_blk_len: u8,
+    gpe1_blk_len: u8,
+    gpe1_base: u8,
+    cst_cnt: u8,
+    p_lvl2_lat: u16,
+    p_lvl3_lat: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alrm: u8,
+    mon_alrm: u8,
+    century: u8,
+    iapc_boot_arch: u16,
+    reserved2: u8,
+    flags: u32,
+    reset_reg: HpetAddress,
+    reset_value: u8,
+    arm_boot_arch: u16,
+    fadt_minor_version: u8,
+    x_firmware_ctrl: u64,
+    x_dsdt: u64,
+}
+
+/// FADT Flags: 電源ボタンがコントロールメソッド方式（0なら固定機能）
+const FADT_PWR_BUTTON: u32 = 1 << 4;
+
+/// AML: NameOp
+const AML_NAME_OP: u8 = 0x08;
+/// AML: PackageOp
+const AML_PACKAGE_OP: u8 = 0x12;
+/// AML: BytePrefix
+const AML_BYTE_PREFIX: u8 = 0x0A;
+
 /// HPET Base Address (ACPI Generic Address Structure)
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -290,6 +365,10 @@ fn parse_xsdt(xsdt_phys_addr: u64) {
         else if table_header.signature_str() == "HPET" {
             parse_hpet(table_phys_addr);
         }
+        // FADT テーブルを見つけたら解析
+        else if table_header.signature_str() == "FACP" {
+            parse_fadt(table_phys_addr);
+        }
     }
 }
 
@@ -347,6 +426,10 @@ fn parse_rsdt(rsdt_phys_addr: u64) {
         else if table_header.signature_str() == "HPET" {
             parse_hpet(table_phys_addr);
         }
+        // FADT テーブルを見つけたら解析
+        else if table_header.signature_str() == "FACP" {
+            parse_fadt(table_phys_addr);
+        }
     }
 }
 
@@ -409,6 +492,7 @@ fn parse_madt(madt_phys_addr: u64) {
                         acpi_id,
                         apic_id
                     );
+                    crate::smp::register_cpu(apic_id);
                 }
             }
             1 => {
@@ -426,6 +510,26 @@ fn parse_madt(madt_phys_addr: u64) {
                     io_apic_address,
                     gsi_base
                 );
+
+                if let Err(e) =
+                    crate::ioapic::register(io_apic_id, io_apic_address as u64, gsi_base)
+                {
+                    info!("  Failed to register I/O APIC: {}", e);
+                }
+            }
+            2 => {
+                // Interrupt Source Override
+                let override_entry =
+                    unsafe { &*(current_addr as *const MadtInterruptSourceOverride) };
+                let source = override_entry.source;
+                let gsi = override_entry.global_system_interrupt;
+                let override_flags = override_entry.flags;
+
+                info!(
+                    "  Interrupt Override: IRQ {} -> GSI {}, Flags=0x{:04X}",
+                    source, gsi, override_flags
+                );
+                crate::ioapic::register_override(source, gsi, override_flags);
             }
             _ => {
                 // その他のエントリタイプはスキップ
@@ -534,3 +638,150 @@ fn parse_hpet(hpet_phys_addr: u64) {
     // HPETモジュールを初期化
     crate::hpet::init(base_address);
 }
+
+/// FADT (Fixed ACPI Description Table) を解析
+///
+/// 電源管理レジスタの位置とSCIの割り込み番号を取得し、DSDTからS5（ソフトオフ）の
+/// スリープタイプを探して `power` モジュールに通知します。
+fn parse_fadt(fadt_phys_addr: u64) {
+    if fadt_phys_addr == 0 {
+        return;
+    }
+
+    // 物理アドレスを高位仮想アドレスに変換
+    let fadt_virt_addr = KERNEL_VIRTUAL_BASE + fadt_phys_addr;
+    let header = unsafe { &*(fadt_virt_addr as *const AcpiTableHeader) };
+
+    // チェックサムを検証
+    if !header.verify_checksum() {
+        info!("FADT checksum verification failed");
+        return;
+    }
+
+    // ACPI 1.0のFADTはX_DSDTを含まないため、足りない部分は0として読む
+    let table_length = header.length as usize;
+    let mut raw = [0u8; core::mem::size_of::<Fadt>()];
+    let copy_len = table_length.min(raw.len());
+    // SAFETY: テーブルはヘッダのlengthバイト分マップされている
+    unsafe {
+        core::ptr::copy_nonoverlapping(fadt_virt_addr as *const u8, raw.as_mut_ptr(), copy_len);
+    }
+    // SAFETY: Fadtはpackedなので任意のバイト列から読み取れる
+    let fadt = unsafe { (raw.as_ptr() as *const Fadt).read_unaligned() };
+
+    // packed struct のフィールドはローカル変数にコピー
+    let sci_int = fadt.sci_int;
+    let smi_cmd = fadt.smi_cmd;
+    let pm1a_evt_blk = fadt.pm1a_evt_blk;
+    let pm1a_cnt_blk = fadt.pm1a_cnt_blk;
+    let gpe0_blk = fadt.gpe0_blk;
+    let gpe1_blk = fadt.gpe1_blk;
+    let flags = fadt.flags;
+    let x_dsdt = fadt.x_dsdt;
+    let dsdt_phys_addr = if x_dsdt != 0 {
+        x_dsdt
+    } else {
+        fadt.dsdt as u64
+    };
+
+    info!("FADT found:");
+    info!("  SCI Interrupt: {}", sci_int);
+    info!(
+        "  SMI Command: 0x{:X}, ACPI Enable: 0x{:02X}",
+        smi_cmd, fadt.acpi_enable
+    );
+    info!(
+        "  PM1a Event: 0x{:X} (len {}), PM1a Control: 0x{:X}",
+        pm1a_evt_blk, fadt.pm1_evt_len, pm1a_cnt_blk
+    );
+    info!(
+        "  GPE0: 0x{:X} (len {}), GPE1: 0x{:X} (len {}, base {})",
+        gpe0_blk, fadt.gpe0_blk_len, gpe1_blk, fadt.gpe1_blk_len, fadt.gpe1_base
+    );
+    info!("  DSDT: 0x{:016X}", dsdt_phys_addr);
+    info!("  RTC Century Register: 0x{:02X}", fadt.century);
+    crate::datetime::set_century_register(fadt.century);
+
+    let s5_sleep_type = find_s5_sleep_type(dsdt_phys_addr);
+    match s5_sleep_type {
+        Some((a, b)) => info!("  \\_S5: SLP_TYPa={}, SLP_TYPb={}", a, b),
+        None => info!("  \\_S5 not found in DSDT; soft-off unavailable"),
+    }
+
+    // I/Oポート空間のブロックのみサポート（レガシーフィールドは常にI/Oポート）
+    crate::power::set_pm_info(crate::power::PmInfo {
+        sci_int,
+        smi_cmd: smi_cmd as u16,
+        acpi_enable: fadt.acpi_enable,
+        pm1a_evt_blk: pm1a_evt_blk as u16,
+        pm1b_evt_blk: fadt.pm1b_evt_blk as u16,
+        pm1_evt_len: fadt.pm1_evt_len,
+        pm1a_cnt_blk: pm1a_cnt_blk as u16,
+        pm1b_cnt_blk: fadt.pm1b_cnt_blk as u16,
+        gpe0_blk: gpe0_blk as u16,
+        gpe0_blk_len: fadt.gpe0_blk_len,
+        gpe1_blk: gpe1_blk as u16,
+        gpe1_blk_len: fadt.gpe1_blk_len,
+        gpe1_base: fadt.gpe1_base,
+        fixed_power_button: flags & FADT_PWR_BUTTON == 0,
+        s5_sleep_type,
+    });
+}
+
+/// DSDTから`\_S5`オブジェクトを探し、S5のスリープタイプを取得
+///
+/// AMLインタプリタは持たないため、`Name(_S5, Package() {a, b, ...})` の
+/// バイト列を直接探す簡易的な方法を用います。
+///
+/// # Returns
+/// (SLP_TYPa, SLP_TYPb)。見つからない場合はNone
+fn find_s5_sleep_type(dsdt_phys_addr: u64) -> Option<(u8, u8)> {
+    if dsdt_phys_addr == 0 {
+        return None;
+    }
+
+    let dsdt_virt_addr = KERNEL_VIRTUAL_BASE + dsdt_phys_addr;
+    let header = unsafe { &*(dsdt_virt_addr as *const AcpiTableHeader) };
+    if header.signature_str() != "DSDT" || !header.verify_checksum() {
+        return None;
+    }
+
+    let header_size = core::mem::size_of::<AcpiTableHeader>();
+    // SAFETY: テーブルはヘッダのlengthバイト分マップされている
+    let aml = unsafe {
+        core::slice::from_raw_parts(
+            (dsdt_virt_addr + header_size as u64) as *const u8,
+            (header.length as usize).saturating_sub(header_size),
+        )
+    };
+
+    let pos = aml.windows(4).enumerate().find_map(|(i, name)| {
+        // NameOp "_S5_" または NameOp "\_S5_" の形のみを対象にする
+        let is_name = (i >= 1 && aml[i - 1] == AML_NAME_OP)
+            || (i >= 2 && aml[i - 2] == AML_NAME_OP && aml[i - 1] == b'\\');
+        (name == b"_S5_" && is_name).then_some(i + 4)
+    })?;
+
+    let mut p = pos;
+    if *aml.get(p)? != AML_PACKAGE_OP {
+        return None;
+    }
+    p += 1;
+    // PkgLength: 先頭バイトのbit 6-7が後続バイト数
+    p += ((aml.get(p)? >> 6) & 0x3) as usize + 1;
+    // NumElements
+    p += 1;
+
+    let read_element = |p: &mut usize| -> Option<u8> {
+        if *aml.get(*p)? == AML_BYTE_PREFIX {
+            *p += 1;
+        }
+        // ZeroOp(0x00)/OneOp(0x01)はそのまま値として扱える
+        let value = *aml.get(*p)?;
+        *p += 1;
+        Some(value)
+    };
+    let slp_typ_a = read_element(&mut p)?;
+    let slp_typ_b = read_element(&mut p)?;
+    Some((slp_typ_a & 0x7, slp_typ_b & 0x7))
+}
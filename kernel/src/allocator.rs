@@ -2,13 +2,28 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::cell::UnsafeCell;
 use core::ptr::{NonNull, null_mut};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
+use spin::Mutex;
+
+use crate::frame_allocator;
 use crate::info;
 use crate::io::without_interrupts;
+use crate::paging::{self, PAGE_SIZE};
 
 // サイズクラス（8バイト～4096バイト）
-const SIZE_CLASSES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
-const NUM_SIZE_CLASSES: usize = SIZE_CLASSES.len();
+pub const SIZE_CLASSES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+pub const NUM_SIZE_CLASSES: usize = SIZE_CLASSES.len();
+
+/// サイズに対応するサイズクラスのインデックスを取得
+///
+/// 4KBを超えるサイズは大きなサイズ用アロケータを表す `NUM_SIZE_CLASSES` を返す
+pub fn size_class_index(size: usize) -> usize {
+    SIZE_CLASSES
+        .iter()
+        .position(|&s| s >= size)
+        .unwrap_or(NUM_SIZE_CLASSES)
+}
 
 // 空きブロックのリンクリストノード
 #[repr(C)]
@@ -16,72 +31,393 @@ struct FreeNode {
     next: Option<NonNull<FreeNode>>,
 }
 
+// 起動後に追加するスラブ1つのページ数
+//
+// ブロックの後ろに所有者タグ（1ブロック1バイト）を置くため、4096バイトのクラスでも
+// 無駄が1ブロック分（1/8）に収まる大きさにする
+const GROWN_SLAB_PAGES: usize = 8;
+const GROWN_SLAB_SIZE: usize = GROWN_SLAB_PAGES * PAGE_SIZE;
+
+// サイズクラスごとに追加できるスラブの最大数（GROWN_SLAB_SIZE × 128 = 4MB）
+const MAX_GROWN_SLABS: usize = 128;
+
+// フレームアロケータから追加したスラブ
+//
+// 空きブロックはスラブごとのフリーリストで管理し、全ブロックが空いたスラブは
+// フレームアロケータへ返す
+#[derive(Clone, Copy)]
+struct GrownSlab {
+    // スラブの先頭アドレス（仮想アドレス）
+    start: usize,
+    // ブロック数（所有者タグはブロックの直後に並ぶ）
+    blocks: usize,
+    // 使用中のブロック数
+    in_use: usize,
+    free_list: Option<NonNull<FreeNode>>,
+}
+
+impl GrownSlab {
+    const EMPTY: Self = Self {
+        start: 0,
+        blocks: 0,
+        in_use: 0,
+        free_list: None,
+    };
+
+    // ブロックを含むか
+    fn contains(&self, addr: usize, block_size: usize) -> bool {
+        addr >= self.start && addr < self.start + self.blocks * block_size
+    }
+
+    // ブロックの所有者タグへのポインタ
+    fn owner_tag(&self, addr: usize, block_size: usize) -> *mut u8 {
+        let index = (addr - self.start) / block_size;
+        (self.start + self.blocks * block_size + index) as *mut u8
+    }
+}
+
+// サイズクラスごとの追加スラブの一覧
+struct GrownSlabs {
+    slabs: [GrownSlab; MAX_GROWN_SLABS],
+    count: usize,
+}
+
+impl GrownSlabs {
+    const fn new() -> Self {
+        Self {
+            slabs: [GrownSlab::EMPTY; MAX_GROWN_SLABS],
+            count: 0,
+        }
+    }
+
+    // ブロックを含むスラブのインデックス
+    fn find(&self, addr: usize, block_size: usize) -> Option<usize> {
+        self.slabs[..self.count]
+            .iter()
+            .position(|slab| slab.contains(addr, block_size))
+    }
+
+    // 全ブロックが空いているスラブの数
+    fn empty_count(&self) -> usize {
+        self.slabs[..self.count]
+            .iter()
+            .filter(|slab| slab.in_use == 0)
+            .count()
+    }
+}
+
 // サイズクラスごとのスラブキャッシュ
+//
+// フリーリストと追加スラブの一覧は複数のCPUから同時に操作されるため、それぞれロックで保護する。
+// 2つのロックを同時には保持しない
 struct SlabCache {
-    free_list: UnsafeCell<Option<NonNull<FreeNode>>>,
+    // 初期化時のスラブの空きブロック
+    free_list: Mutex<Option<NonNull<FreeNode>>>,
     block_size: usize,
+    // スラブ領域の先頭アドレス（所有者タグのインデックス計算用）
+    region_start: UnsafeCell<usize>,
+    // ブロックごとの所有者タグ（heap_quotaの計上スロット番号、1ブロック1バイト）
+    owner_tags: UnsafeCell<*mut u8>,
+    // 所有者タグの数（= スラブのブロック数）
+    owner_tag_count: UnsafeCell<usize>,
+    // 初期化時のスラブが尽きたときにフレームアロケータから追加したスラブ
+    grown: Mutex<GrownSlabs>,
+    // 統計: 追加スラブの数
+    grown_slabs: AtomicUsize,
+    // 統計: スラブを追加した回数
+    grows: AtomicU64,
+    // 統計: スラブをフレームアロケータへ返した回数
+    shrinks: AtomicU64,
+    // 統計: このクラスから割り当てた回数
+    allocs: AtomicU64,
+    // 統計: このクラスへ解放した回数
+    frees: AtomicU64,
+    // 統計: 使用中のブロック数の最大値
+    peak_blocks: AtomicUsize,
 }
 
 impl SlabCache {
     const fn new(block_size: usize) -> Self {
         Self {
-            free_list: UnsafeCell::new(None),
+            free_list: Mutex::new(None),
             block_size,
+            region_start: UnsafeCell::new(0),
+            owner_tags: UnsafeCell::new(null_mut()),
+            owner_tag_count: UnsafeCell::new(0),
+            grown: Mutex::new(GrownSlabs::new()),
+            grown_slabs: AtomicUsize::new(0),
+            grows: AtomicU64::new(0),
+            shrinks: AtomicU64::new(0),
+            allocs: AtomicU64::new(0),
+            frees: AtomicU64::new(0),
+            peak_blocks: AtomicUsize::new(0),
         }
     }
 
-    // ブロックを割り当て
-    unsafe fn allocate(&self) -> Option<NonNull<u8>> {
-        without_interrupts(|| unsafe {
-            let free_list = &mut *self.free_list.get();
+    // 使用中のブロック数
+    //
+    // 大きなサイズ用領域から補ったブロックは解放時にこのクラスのフリーリストへ入るため、
+    // 解放回数が割り当て回数を上回ることがある
+    fn blocks_in_use(&self) -> usize {
+        let allocs = self.allocs.load(Ordering::Relaxed);
+        let frees = self.frees.load(Ordering::Relaxed);
+        allocs.saturating_sub(frees) as usize
+    }
+
+    // 追加スラブ1つのブロック数
+    fn grown_slab_blocks(&self) -> usize {
+        GROWN_SLAB_SIZE / (self.block_size + 1)
+    }
+
+    // 割り当てを統計に記録
+    fn count_alloc(&self) {
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        self.peak_blocks
+            .fetch_max(self.blocks_in_use(), Ordering::Relaxed);
+    }
+
+    // 初期化時のスラブ上のブロックの所有者タグへのポインタ（スラブ領域外のブロックはNone）
+    unsafe fn owner_tag(&self, ptr: *mut u8) -> Option<*mut u8> {
+        unsafe {
+            let tags = *self.owner_tags.get();
+            let start = *self.region_start.get();
+            let addr = ptr as usize;
+            if tags.is_null() || addr < start {
+                return None;
+            }
+            let index = (addr - start) / self.block_size;
+            (index < *self.owner_tag_count.get()).then(|| tags.add(index))
+        }
+    }
 
+    // ptrがこのクラスのブロックの先頭か（初期化時のスラブまたは追加スラブ）
+    fn is_block(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+        // SAFETY: region_startとowner_tag_countは初期化時にのみ書き込まれる
+        let (start, count) = unsafe { (*self.region_start.get(), *self.owner_tag_count.get()) };
+        if addr >= start && addr < start + count * self.block_size {
+            return (addr - start).is_multiple_of(self.block_size);
+        }
+        without_interrupts(|| {
+            let grown = self.grown.lock();
+            grown.find(addr, self.block_size).is_some_and(|index| {
+                (addr - grown.slabs[index].start).is_multiple_of(self.block_size)
+            })
+        })
+    }
+
+    // ブロックを割り当て、所有者タグにslotを記録
+    //
+    // 初期化時のスラブ、追加スラブの順に探し、どちらも尽きていればスラブを追加する
+    unsafe fn allocate(&self, slot: u8) -> Option<NonNull<u8>> {
+        without_interrupts(|| unsafe {
+            let mut free_list = self.free_list.lock();
             if let Some(node) = *free_list {
                 // フリーリストから取り出す
                 let ptr = node.as_ptr() as *mut u8;
                 *free_list = (*node.as_ptr()).next;
-                NonNull::new(ptr)
-            } else {
-                // フリーリストが空の場合はNone（後でラージアロケータにフォールバック）
-                None
+                drop(free_list);
+                if let Some(tag) = self.owner_tag(ptr) {
+                    // SAFETY: tagはこのブロック専用のタグで、ブロックを所有している間は他から触れられない
+                    *tag = slot;
+                }
+                return NonNull::new(ptr);
             }
+            drop(free_list);
+
+            let mut grown = self.grown.lock();
+            let count = grown.count;
+            let index = match grown.slabs[..count]
+                .iter()
+                .position(|slab| slab.free_list.is_some())
+            {
+                Some(index) => index,
+                // 追加スラブも尽きていればフレームアロケータから補充
+                // （補充できなければNone、後でラージアロケータにフォールバック）
+                None => self.grow(&mut grown)?,
+            };
+
+            let slab = &mut grown.slabs[index];
+            let node = slab.free_list?;
+            slab.free_list = (*node.as_ptr()).next;
+            slab.in_use += 1;
+            let ptr = node.as_ptr() as *mut u8;
+            *slab.owner_tag(ptr as usize, self.block_size) = slot;
+            NonNull::new(ptr)
         })
     }
 
+    // フレームアロケータからスラブを追加
+    //
+    // # Returns
+    // 追加したスラブのインデックス。一覧が満杯、フレームが足りない、または緊急状態ならNone
+    unsafe fn grow(&self, grown: &mut GrownSlabs) -> Option<usize> {
+        // 緊急状態ではフレームアロケータのロックに触れない
+        if grown.count == MAX_GROWN_SLABS || crate::emergency::in_emergency() {
+            return None;
+        }
+        let phys = frame_allocator::alloc_contiguous(GROWN_SLAB_PAGES)?;
+        let Ok(start) = paging::phys_to_virt(phys) else {
+            let _ = frame_allocator::free_contiguous(phys, GROWN_SLAB_PAGES);
+            return None;
+        };
+        let start = start as usize;
+
+        let blocks = self.grown_slab_blocks();
+        let mut slab = GrownSlab {
+            start,
+            blocks,
+            in_use: 0,
+            free_list: None,
+        };
+        // 先頭のブロックから取り出されるよう、後ろから積む
+        for i in (0..blocks).rev() {
+            let node = (start + i * self.block_size) as *mut FreeNode;
+            // SAFETY: nodeは取得したばかりのフレーム上にあり、他から参照されていない
+            unsafe { (*node).next = slab.free_list };
+            slab.free_list = NonNull::new(node);
+        }
+
+        let index = grown.count;
+        grown.slabs[index] = slab;
+        grown.count += 1;
+        self.grown_slabs.store(grown.count, Ordering::Relaxed);
+        self.grows.fetch_add(1, Ordering::Relaxed);
+        Some(index)
+    }
+
     // ブロックを解放
-    unsafe fn deallocate(&self, ptr: *mut u8) {
+    //
+    // 追加スラブのブロックで、そのスラブの全ブロックが空けばフレームアロケータへ返す。
+    // ただし割り当てと解放を繰り返すたびにフレームを往復させないよう、空のスラブを1つだけ残す
+    //
+    // # Returns
+    // ブロックの所有者タグの値（所有者タグを持たないブロックはNone）
+    unsafe fn deallocate(&self, ptr: *mut u8) -> Option<u8> {
         without_interrupts(|| unsafe {
-            let free_list = &mut *self.free_list.get();
+            if let Some(tag) = self.owner_tag(ptr) {
+                let slot = *tag;
+                self.push_free(ptr);
+                return Some(slot);
+            }
+
+            let addr = ptr as usize;
+            let mut grown = self.grown.lock();
+            let Some(index) = grown.find(addr, self.block_size) else {
+                // 大きなサイズ用領域から補ったブロックは初期化時のスラブのフリーリストへ
+                drop(grown);
+                self.push_free(ptr);
+                return None;
+            };
+
+            let slab = &mut grown.slabs[index];
+            let slot = *slab.owner_tag(addr, self.block_size);
             let node = ptr as *mut FreeNode;
+            (*node).next = slab.free_list;
+            slab.free_list = NonNull::new(node);
+            slab.in_use -= 1;
 
-            // フリーリストの先頭に追加
-            (*node).next = *free_list;
-            *free_list = NonNull::new(node);
+            if slab.in_use == 0 && grown.empty_count() > 1 && !crate::emergency::in_emergency() {
+                self.release(&mut grown, index);
+            }
+            Some(slot)
         })
     }
 
+    // 全ブロックが空いた追加スラブをフレームアロケータへ返す
+    fn release(&self, grown: &mut GrownSlabs, index: usize) {
+        let start = grown.slabs[index].start;
+        grown.count -= 1;
+        grown.slabs[index] = grown.slabs[grown.count];
+        grown.slabs[grown.count] = GrownSlab::EMPTY;
+        self.grown_slabs.store(grown.count, Ordering::Relaxed);
+        self.shrinks.fetch_add(1, Ordering::Relaxed);
+
+        // startはphys_to_virtで変換したアドレスなので戻せる。
+        // アロケータの内部ではログを出せないため、失敗した場合（二重解放）はフレームを手放すだけにする
+        if let Ok(phys) = paging::virt_to_phys(start as u64) {
+            let _ = frame_allocator::free_contiguous(phys, GROWN_SLAB_PAGES);
+        }
+    }
+
+    // 初期化時のスラブのフリーリストの先頭にブロックを追加
+    unsafe fn push_free(&self, ptr: *mut u8) {
+        let mut free_list = self.free_list.lock();
+        let node = ptr as *mut FreeNode;
+        // SAFETY: 呼び出し元が解放済みのブロックを渡し、ロックを保持している間はリストを他から触れられない
+        unsafe { (*node).next = *free_list };
+        *free_list = NonNull::new(node);
+    }
+
     // スラブを追加（大きなメモリブロックを小さなブロックに分割）
     unsafe fn add_slab(&self, slab_start: usize, slab_size: usize) {
         let num_blocks = slab_size / self.block_size;
 
         for i in 0..num_blocks {
             let block_addr = slab_start + i * self.block_size;
-            unsafe {
-                self.deallocate(block_addr as *mut u8);
-            }
+            without_interrupts(|| unsafe {
+                self.push_free(block_addr as *mut u8);
+            });
         }
     }
 }
 
+// 大きなサイズ用領域の割り当ての単位（割り当ての先頭はこの倍数に揃える）
+const LARGE_GRANULE: usize = 64;
+
+// 割り当てタグ: 割り当ての先頭ではない（または解放済み）
+const LARGE_TAG_NONE: u8 = 0;
+
+// 割り当てタグ: 4KBを超える割り当て（スラブの補充はサイズクラスのインデックス + 1）
+const LARGE_TAG_LARGE: u8 = u8::MAX;
+
+// 大きなサイズ用のバンプアロケータの領域
+//
+// 割り当ての先頭ごとにタグ（LARGE_GRANULEごとに1バイト）を記録し、解放時に
+// 実際に割り当てたポインタかどうかを確かめる
+struct LargeRegion {
+    start: usize,
+    next: usize,
+    end: usize,
+    // 割り当てタグの表の先頭アドレス（領域の手前に置く）
+    tags: usize,
+}
+
+impl LargeRegion {
+    // 割り当て済みの範囲でLARGE_GRANULEに揃ったアドレスのタグへのポインタ
+    fn tag(&self, addr: usize) -> Option<*mut u8> {
+        if addr < self.start
+            || addr >= self.next
+            || !(addr - self.start).is_multiple_of(LARGE_GRANULE)
+        {
+            return None;
+        }
+        Some((self.tags + (addr - self.start) / LARGE_GRANULE) as *mut u8)
+    }
+}
+
 // スラブアロケータ本体
 pub struct SlabAllocator {
     caches: [SlabCache; NUM_SIZE_CLASSES],
     // TODO: 大きなサイズ用のバンプアロケータ（解放不可）
     // 将来的にはバディアロケータまたはリンクリストアロケータに置き換える
     // Issue: https://github.com/jugeeeemu-tech/vitrOS/issues/1
-    #[cfg(feature = "visualize-allocator")]
-    large_alloc_start: UnsafeCell<usize>,
-    large_alloc_next: UnsafeCell<usize>,
-    large_alloc_end: UnsafeCell<usize>,
+    large: Mutex<LargeRegion>,
+    // 統計: 大きなサイズ用領域から割り当てた回数
+    large_allocs: AtomicU64,
+    // 統計: 大きなサイズ用領域の割り当てを解放した回数（領域は再利用されない）
+    large_frees: AtomicU64,
+    // 統計: 大きなサイズ用領域の使用量と総量（ロックを取らずに読めるよう別に持つ）
+    large_used: AtomicUsize,
+    large_total: AtomicUsize,
+    // 統計: 割り当て中のバイト数（要求サイズの合計）
+    bytes_in_use: AtomicUsize,
+    // 統計: 割り当て中のバイト数の最大値
+    peak_bytes: AtomicUsize,
+    // 統計: 割り当てに失敗した回数（クォータ超過・障害注入を含む）
+    failures: AtomicU64,
+    // 統計: このアロケータのブロックでないポインタの解放を拒否した回数
+    invalid_frees: AtomicU64,
 }
 
 impl SlabAllocator {
@@ -99,10 +435,57 @@ impl SlabAllocator {
                 SlabCache::new(SIZE_CLASSES[8]),
                 SlabCache::new(SIZE_CLASSES[9]),
             ],
-            #[cfg(feature = "visualize-allocator")]
-            large_alloc_start: UnsafeCell::new(0),
-            large_alloc_next: UnsafeCell::new(0),
-            large_alloc_end: UnsafeCell::new(0),
+            large: Mutex::new(LargeRegion {
+                start: 0,
+                next: 0,
+                end: 0,
+                tags: 0,
+            }),
+            large_allocs: AtomicU64::new(0),
+            large_frees: AtomicU64::new(0),
+            large_used: AtomicUsize::new(0),
+            large_total: AtomicUsize::new(0),
+            bytes_in_use: AtomicUsize::new(0),
+            peak_bytes: AtomicUsize::new(0),
+            failures: AtomicU64::new(0),
+            invalid_frees: AtomicU64::new(0),
+        }
+    }
+
+    // 割り当て中のバイト数を統計に加算
+    fn count_bytes_allocated(&self, size: usize) {
+        let in_use = self.bytes_in_use.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes.fetch_max(in_use, Ordering::Relaxed);
+    }
+
+    // 統計を取得
+    fn stats(&self) -> HeapStats {
+        let (large_used, large_total) = self.large_alloc_usage();
+        HeapStats {
+            classes: core::array::from_fn(|i| {
+                let cache = &self.caches[i];
+                SizeClassStats {
+                    block_size: cache.block_size,
+                    // SAFETY: owner_tag_countは初期化時に一度だけ書き込まれる
+                    capacity: unsafe { *cache.owner_tag_count.get() }
+                        + cache.grown_slabs.load(Ordering::Relaxed) * cache.grown_slab_blocks(),
+                    grown_slabs: cache.grown_slabs.load(Ordering::Relaxed),
+                    grows: cache.grows.load(Ordering::Relaxed),
+                    shrinks: cache.shrinks.load(Ordering::Relaxed),
+                    allocs: cache.allocs.load(Ordering::Relaxed),
+                    frees: cache.frees.load(Ordering::Relaxed),
+                    in_use: cache.blocks_in_use(),
+                    peak: cache.peak_blocks.load(Ordering::Relaxed),
+                }
+            }),
+            large_allocs: self.large_allocs.load(Ordering::Relaxed),
+            large_frees: self.large_frees.load(Ordering::Relaxed),
+            large_used,
+            large_total,
+            bytes_in_use: self.bytes_in_use.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            invalid_frees: self.invalid_frees.load(Ordering::Relaxed),
         }
     }
 
@@ -120,29 +503,51 @@ impl SlabAllocator {
         let slab_region_size = heap_size / 2;
         let large_region_start = heap_start + slab_region_size;
 
+        // 所有者タグ表は大きなサイズ用領域の先頭に置く
+        let mut tag_next = large_region_start;
+
         // 各サイズクラスにスラブを割り当て
         let mut current = heap_start;
         for (i, &size) in SIZE_CLASSES.iter().enumerate() {
             let slab_size = slab_region_size / NUM_SIZE_CLASSES;
             let aligned_size = align_down(slab_size, size);
+            let num_blocks = aligned_size / size;
 
             unsafe {
                 self.caches[i].add_slab(current, aligned_size);
+
+                // 全ブロックをスロット0（未計上）で初期化
+                core::ptr::write_bytes(tag_next as *mut u8, 0, num_blocks);
+                *self.caches[i].region_start.get() = current;
+                *self.caches[i].owner_tags.get() = tag_next as *mut u8;
+                *self.caches[i].owner_tag_count.get() = num_blocks;
             }
 
             current += aligned_size;
-            info!("  Size class {:4}B: {} blocks", size, aligned_size / size);
+            tag_next += num_blocks;
+            info!("  Size class {:4}B: {} blocks", size, num_blocks);
         }
+        // 大きなサイズ用領域の割り当てタグ表は所有者タグ表の後ろに置く
+        let large_tags = tag_next;
+        let large_tag_count = (heap_start + heap_size - large_tags) / LARGE_GRANULE;
+        // SAFETY: タグ表はヒープ内で、まだどこにも割り当てていない
+        unsafe { core::ptr::write_bytes(large_tags as *mut u8, LARGE_TAG_NONE, large_tag_count) };
+        let large_region_start = align_up(
+            large_tags + large_tag_count,
+            SIZE_CLASSES[NUM_SIZE_CLASSES - 1],
+        );
 
         // 大きなサイズ用の領域を初期化
-        unsafe {
-            #[cfg(feature = "visualize-allocator")]
-            {
-                *self.large_alloc_start.get() = large_region_start;
-            }
-            *self.large_alloc_next.get() = large_region_start;
-            *self.large_alloc_end.get() = heap_start + heap_size;
-        }
+        *self.large.lock() = LargeRegion {
+            start: large_region_start,
+            next: large_region_start,
+            end: heap_start + heap_size,
+            tags: large_tags,
+        };
+        self.large_total.store(
+            heap_start + heap_size - large_region_start,
+            Ordering::Relaxed,
+        );
 
         info!("Slab Allocator initialized successfully");
     }
@@ -153,81 +558,218 @@ impl SlabAllocator {
     }
 
     // 大きなサイズ用のアロケート（バンプアロケータ）
-    unsafe fn allocate_large(&self, layout: Layout) -> Option<NonNull<u8>> {
-        without_interrupts(|| unsafe {
-            let next = *self.large_alloc_next.get();
-            let end = *self.large_alloc_end.get();
+    //
+    // 割り当ての先頭にtagを記録する
+    unsafe fn allocate_large(&self, size: usize, align: usize, tag: u8) -> Option<NonNull<u8>> {
+        without_interrupts(|| {
+            let mut large = self.large.lock();
+            let alloc_start = align_up(large.next, align.max(LARGE_GRANULE));
+            let alloc_end = alloc_start.saturating_add(size);
 
-            let alloc_start = align_up(next, layout.align());
-            let alloc_end = alloc_start.saturating_add(layout.size());
-
-            if alloc_end > end {
+            if alloc_end > large.end {
                 None
             } else {
-                *self.large_alloc_next.get() = alloc_end;
+                large.next = alloc_end;
+                self.large_used
+                    .store(alloc_end - large.start, Ordering::Relaxed);
+                if let Some(entry) = large.tag(alloc_start) {
+                    // SAFETY: entryはタグ表内を指し、ロックを保持している
+                    unsafe { *entry = tag };
+                }
                 NonNull::new(alloc_start as *mut u8)
             }
         })
     }
+
+    // 大きなサイズ用領域の割り当てタグ（割り当て済みの範囲でLARGE_GRANULEに揃っていなければNone）
+    fn large_tag(&self, addr: usize) -> Option<u8> {
+        without_interrupts(|| {
+            let large = self.large.lock();
+            // SAFETY: tagはタグ表内を指し、ロックを保持している
+            large.tag(addr).map(|tag| unsafe { *tag })
+        })
+    }
+
+    // 大きなサイズ用領域の割り当てタグを消す
+    fn clear_large_tag(&self, addr: usize) {
+        without_interrupts(|| {
+            let large = self.large.lock();
+            if let Some(tag) = large.tag(addr) {
+                // SAFETY: tagはタグ表内を指し、ロックを保持している
+                unsafe { *tag = LARGE_TAG_NONE };
+            }
+        })
+    }
+
+    // ptrがlayoutで割り当てたブロックとして解放できるか
+    //
+    // サイズクラスのブロックは、そのクラスのスラブ上でブロック境界にあること。
+    // スラブが尽きて大きなサイズ用領域から補ったブロックは、そのクラスの補充として
+    // 割り当てた先頭で、ブロックサイズに揃っていること。
+    // 4KBを超える割り当ては、大きなサイズ用領域で割り当てた先頭であること
+    fn is_valid_free(&self, ptr: *mut u8, size: usize) -> bool {
+        let addr = ptr as usize;
+        let Some(class_idx) = Self::size_to_class(size) else {
+            return self.large_tag(addr) == Some(LARGE_TAG_LARGE);
+        };
+        let cache = &self.caches[class_idx];
+        cache.is_block(ptr)
+            || (addr.is_multiple_of(cache.block_size)
+                && self.large_tag(addr) == Some(class_idx as u8 + 1))
+    }
+
+    // 不正な解放を記録（debug-allocatorではパニック）
+    //
+    // フリーリストを壊さないよう、ブロックは解放せずに捨てる
+    fn reject_free(&self, ptr: *mut u8, size: usize) {
+        self.invalid_frees.fetch_add(1, Ordering::Relaxed);
+        let class = SIZE_CLASSES
+            .get(size_class_index(size))
+            .copied()
+            .unwrap_or(0);
+
+        #[cfg(feature = "debug-allocator")]
+        panic!(
+            "invalid free of 0x{:X} (size {}, class {}B): not a block of this size class",
+            ptr as usize, size, class
+        );
+
+        #[cfg(not(feature = "debug-allocator"))]
+        crate::warn!(
+            "Ignoring invalid free of 0x{:X} (size {}, class {}B): not a block of this size class",
+            ptr as usize,
+            size,
+            class
+        );
+    }
+
+    // 大きなサイズ用領域の使用状況 (使用量, 総量)
+    //
+    // パニック時にロック保持中のコードを割り込んでいても読めるよう、ロックは取らない
+    fn large_alloc_usage(&self) -> (usize, usize) {
+        (
+            self.large_used.load(Ordering::Relaxed),
+            self.large_total.load(Ordering::Relaxed),
+        )
+    }
 }
 
-// =============================================================================
-// 可視化機能専用のメソッド
-// cargo build --features visualize でビルドした場合のみ有効
-// =============================================================================
-#[cfg(feature = "visualize-allocator")]
 impl SlabAllocator {
-    // デバッグ: サイズクラスごとの空きブロック数をカウント
-    pub fn count_free_blocks(&self, class_idx: usize) -> usize {
-        if class_idx >= NUM_SIZE_CLASSES {
-            return 0;
+    // ブロックを割り当て（debug-allocatorではレッドゾーンを含めたサイズで呼ばれる）
+    unsafe fn alloc_block(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size().max(layout.align());
+
+        // 緊急状態（パニック・例外処理中）は、ヒープやクォータのロックに触れずに予約から割り当てる
+        // 予約が尽きた場合のみ通常の経路を試す
+        if crate::emergency::in_emergency() {
+            let ptr = crate::emergency::allocate(layout);
+            if !ptr.is_null() {
+                return ptr;
+            }
         }
 
-        unsafe {
-            let free_list = &*self.caches[class_idx].free_list.get();
-            let mut count = 0;
-            let mut current = *free_list;
+        // 障害注入: 予約された回数だけ割り当てを失敗させる
+        if crate::fault_inject::should_fail_alloc(size_class_index(size)) {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            return null_mut();
+        }
 
-            while let Some(node) = current {
-                count += 1;
-                current = (*node.as_ptr()).next;
-            }
+        // 現在のタスクに計上（クォータ超過ならこのタスクの割り当てだけを失敗させる）
+        let Some(slot) = crate::heap_quota::charge(size) else {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            return null_mut();
+        };
 
-            count
+        // サイズクラスを探す
+        let class = Self::size_to_class(size);
+        if let Some(class_idx) = class
+            && let Some(ptr) = unsafe { self.caches[class_idx].allocate(slot) }
+        {
+            self.caches[class_idx].count_alloc();
+            self.count_bytes_allocated(size);
+            #[cfg(feature = "visualize-allocator")]
+            events::record(events::AllocEventKind::Alloc, class_idx, ptr.as_ptr(), size);
+            return ptr.as_ptr();
+        }
+
+        // スラブから割り当てできない場合（スラブを追加できないときを含む）は大きなサイズ用アロケータを使用
+        // 大きなサイズ用の領域は解放されないため、計上もそのまま残す
+        // スラブの補充は解放後にそのクラスのブロックとして再利用するため、ブロックサイズで割り当てる
+        let (large_size, large_align, tag) = match class {
+            Some(class_idx) => (
+                SIZE_CLASSES[class_idx],
+                SIZE_CLASSES[class_idx],
+                class_idx as u8 + 1,
+            ),
+            None => (layout.size(), layout.align(), LARGE_TAG_LARGE),
+        };
+        match unsafe { self.allocate_large(large_size, large_align, tag) } {
+            Some(ptr) => {
+                self.large_allocs.fetch_add(1, Ordering::Relaxed);
+                self.count_bytes_allocated(size);
+                #[cfg(feature = "visualize-allocator")]
+                events::record(
+                    events::AllocEventKind::Alloc,
+                    NUM_SIZE_CLASSES,
+                    ptr.as_ptr(),
+                    size,
+                );
+                ptr.as_ptr()
+            }
+            None => {
+                crate::heap_quota::uncharge(slot, size);
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                null_mut()
+            }
         }
     }
 
-    // デバッグ: 大きなサイズ用領域の使用状況 (使用量, 総量)
-    pub fn large_alloc_usage(&self) -> (usize, usize) {
-        unsafe {
-            let start = *self.large_alloc_start.get();
-            let next = *self.large_alloc_next.get();
-            let end = *self.large_alloc_end.get();
+    // ブロックを解放
+    unsafe fn dealloc_block(&self, ptr: *mut u8, layout: Layout) {
+        // 緊急用予約からの割り当て（緊急状態を抜けた後の解放もここに来る）
+        if crate::emergency::owns(ptr) {
+            crate::emergency::deallocate(ptr);
+            return;
+        }
 
-            let used = next - start; // 使用済み
-            let total = end - start; // 総容量
+        let size = layout.size().max(layout.align());
+        // 誤ったLayoutでの解放や二重に計算したポインタをフリーリストへ入れると、
+        // 後の割り当てで別のオブジェクトと重なるため、ここで拒否する
+        if !self.is_valid_free(ptr, size) {
+            self.reject_free(ptr, size);
+            return;
+        }
+        self.bytes_in_use.fetch_sub(size, Ordering::Relaxed);
 
-            (used, total)
+        // サイズクラスに該当する場合は解放
+        if let Some(class_idx) = Self::size_to_class(size) {
+            // 割り当てたタスクの使用量から差し引く
+            if let Some(slot) = unsafe { self.caches[class_idx].deallocate(ptr) } {
+                crate::heap_quota::uncharge(slot, size);
+            }
+            self.caches[class_idx].frees.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "visualize-allocator")]
+            events::record(events::AllocEventKind::Free, class_idx, ptr, size);
+        } else {
+            // 二重解放を検出できるようタグを消す（領域は再利用しない）
+            self.clear_large_tag(ptr as usize);
+            self.large_frees.fetch_add(1, Ordering::Relaxed);
         }
+        // TODO: 大きなサイズの解放は無視（バンプアロケータ部分）
+        // 4KB超のメモリは解放できない - バディアロケータ実装が必要
     }
 }
 
 // GlobalAlloc トレイトを実装
 unsafe impl GlobalAlloc for SlabAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let size = layout.size().max(layout.align());
+        #[cfg(feature = "debug-allocator")]
+        return unsafe { redzone::alloc(self, layout) };
 
-        // サイズクラスを探す
-        if let Some(class_idx) = Self::size_to_class(size)
-            && let Some(ptr) = unsafe { self.caches[class_idx].allocate() }
-        {
-            return ptr.as_ptr();
+        #[cfg(not(feature = "debug-allocator"))]
+        unsafe {
+            self.alloc_block(layout)
         }
-
-        // スラブから割り当てできない場合は大きなサイズ用アロケータを使用
-        unsafe { self.allocate_large(layout) }
-            .map(|ptr| ptr.as_ptr())
-            .unwrap_or(null_mut())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
@@ -237,20 +779,21 @@ unsafe impl GlobalAlloc for SlabAllocator {
             return;
         }
 
-        let size = layout.size().max(layout.align());
+        #[cfg(feature = "debug-allocator")]
+        unsafe {
+            redzone::dealloc(self, ptr, layout)
+        }
 
-        // サイズクラスに該当する場合は解放
-        if let Some(class_idx) = Self::size_to_class(size) {
-            unsafe {
-                self.caches[class_idx].deallocate(ptr);
-            }
+        #[cfg(not(feature = "debug-allocator"))]
+        unsafe {
+            self.dealloc_block(ptr, layout)
         }
-        // TODO: 大きなサイズの解放は無視（バンプアロケータ部分）
-        // 4KB超のメモリは解放できない - バディアロケータ実装が必要
     }
 }
 
 // Sync を実装（グローバルで使用するため）
+// SAFETY: フリーリスト・追加スラブ・大きなサイズ用領域はロックで保護し、
+// 所有者タグ表の位置はAPを起動する前のinitでのみ書き込む
 unsafe impl Sync for SlabAllocator {}
 
 // アドレスをアラインメントに合わせて切り上げ
@@ -274,16 +817,369 @@ pub unsafe fn init_heap(heap_start: usize, heap_size: usize) {
     }
 }
 
+/// 大きなサイズ用領域の使用状況 (使用量, 総量)
+///
+/// ロックも割り込み禁止も使わずに読むため、パニック時など割り当て処理の途中でも呼び出せます。
+pub fn large_usage() -> (usize, usize) {
+    ALLOCATOR.large_alloc_usage()
+}
+
+/// サイズクラスごとの統計
+#[derive(Debug, Clone, Copy)]
+pub struct SizeClassStats {
+    /// ブロックサイズ（バイト）
+    pub block_size: usize,
+    /// スラブのブロック数（追加スラブを含む）
+    pub capacity: usize,
+    /// フレームアロケータから追加したスラブの数
+    pub grown_slabs: usize,
+    /// スラブを追加した回数
+    pub grows: u64,
+    /// 空になった追加スラブをフレームアロケータへ返した回数
+    pub shrinks: u64,
+    /// 割り当て回数
+    pub allocs: u64,
+    /// 解放回数
+    pub frees: u64,
+    /// 使用中のブロック数
+    pub in_use: usize,
+    /// 使用中のブロック数の最大値
+    pub peak: usize,
+}
+
+/// ヒープの統計
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// サイズクラスごとの統計（`SIZE_CLASSES` の順）
+    pub classes: [SizeClassStats; NUM_SIZE_CLASSES],
+    /// 大きなサイズ用領域からの割り当て回数（スラブを追加できなかったときの補充を含む）
+    pub large_allocs: u64,
+    /// 大きなサイズ用領域の割り当てを解放した回数（領域は再利用されない）
+    pub large_frees: u64,
+    /// 大きなサイズ用領域の使用量（バイト）
+    pub large_used: usize,
+    /// 大きなサイズ用領域の総量（バイト）
+    pub large_total: usize,
+    /// 割り当て中のバイト数（要求サイズの合計、緊急用予約を除く）
+    pub bytes_in_use: usize,
+    /// 割り当て中のバイト数の最大値
+    pub peak_bytes: usize,
+    /// 割り当てに失敗した回数（クォータ超過・障害注入を含む）
+    pub failures: u64,
+    /// 解放を拒否した回数（別のサイズクラスのブロックやブロック境界でないポインタ）
+    pub invalid_frees: u64,
+}
+
+/// ヒープの統計を取得
+///
+/// 統計はアトミック変数で数えているため、ロックを取らずにどこからでも呼び出せます。
+/// 各値は個別に読むため、割り当て処理と並行して呼び出すと値どうしが僅かにずれることがあります。
+pub fn stats() -> HeapStats {
+    ALLOCATOR.stats()
+}
+
 // =============================================================================
-// 可視化機能専用の内部アクセス関数
-// visualization.rsからのみ呼ばれる想定
+// ヒープ破壊の検出（debug-allocatorフィーチャー専用）
+// 各割り当ての前後にレッドゾーンを置き、解放したメモリをポイズンで埋める
 // =============================================================================
-#[cfg(feature = "visualize-allocator")]
-pub(crate) fn get_allocator_internal() -> &'static SlabAllocator {
-    &ALLOCATOR
+#[cfg(feature = "debug-allocator")]
+pub mod redzone {
+    //! レッドゾーンとポイズンによるヒープ破壊の検出
+    //!
+    //! 割り当てごとに、要求されたサイズの前後へカナリア値で埋めたレッドゾーンを付けて
+    //! ブロックを確保します。解放時にレッドゾーンと記録したサイズを確認し、書き換えられていれば
+    //! アドレスとサイズクラスを付けてパニックします。確認後は本体を `0xDEAD` の繰り返しで埋めるため、
+    //! 解放後のメモリを読んだコードはポインタや長さとして `0xADDEADDE...` を受け取り、
+    //! すぐに異常と分かります。
+    //!
+    //! ブロックのレイアウト（前レッドゾーンはアラインメント以上の長さ）:
+    //! ```text
+    //! [要求サイズ(8B) | カナリア] [本体] [カナリア(REDZONE)]
+    //! ^ブロックの先頭              ^呼び出し元へ返すアドレス
+    //! ```
+    //! 解放済みブロックのフリーリストのリンクは先頭の8バイトに書き込まれるため、
+    //! 二重解放も記録したサイズの不一致として検出されます。
+
+    use core::alloc::Layout;
+    use core::fmt;
+    use core::ptr::null_mut;
+
+    use super::{SIZE_CLASSES, SlabAllocator, size_class_index};
+
+    /// 後ろのレッドゾーン、および前のレッドゾーンの最小の長さ（バイト）
+    pub const REDZONE: usize = 16;
+
+    /// 前のレッドゾーンの先頭に記録する要求サイズの長さ
+    const HEADER: usize = size_of::<usize>();
+
+    /// レッドゾーンを埋めるカナリア値
+    pub const CANARY: u8 = 0xCA;
+
+    /// 解放したメモリを埋めるパターン
+    pub const POISON: [u8; 2] = [0xDE, 0xAD];
+
+    /// 前のレッドゾーンの長さ（返すアドレスのアラインメントを保つ）
+    fn front_size(layout: &Layout) -> usize {
+        REDZONE.max(layout.align())
+    }
+
+    /// レッドゾーンを含めたブロックのレイアウト
+    fn outer_layout(layout: &Layout) -> Option<Layout> {
+        let size = front_size(layout)
+            .checked_add(layout.size())?
+            .checked_add(REDZONE)?;
+        Layout::from_size_align(size, layout.align()).ok()
+    }
+
+    /// パニックメッセージ用のサイズクラス名
+    struct ClassName(usize);
+
+    impl fmt::Display for ClassName {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match SIZE_CLASSES.get(size_class_index(self.0)) {
+                Some(block_size) => write!(f, "{}B", block_size),
+                None => write!(f, "large"),
+            }
+        }
+    }
+
+    /// 破壊を検出したのでパニック
+    fn corrupted(what: &str, ptr: *mut u8, layout: &Layout, outer: &Layout) -> ! {
+        panic!(
+            "heap corruption: {} at 0x{:X} (size {}, class {})",
+            what,
+            ptr as usize,
+            layout.size(),
+            ClassName(outer.size().max(outer.align()))
+        );
+    }
+
+    /// レッドゾーン付きで割り当て
+    pub(super) unsafe fn alloc(allocator: &SlabAllocator, layout: Layout) -> *mut u8 {
+        let Some(outer) = outer_layout(&layout) else {
+            return null_mut();
+        };
+        let block = unsafe { allocator.alloc_block(outer) };
+        if block.is_null() {
+            return block;
+        }
+        let front = front_size(&layout);
+        // SAFETY: blockはouterの大きさで確保したばかりで、front + size + REDZONEバイトを所有している
+        unsafe {
+            core::ptr::write_bytes(block, CANARY, front);
+            (block as *mut usize).write_unaligned(layout.size());
+            let ptr = block.add(front);
+            core::ptr::write_bytes(ptr.add(layout.size()), CANARY, REDZONE);
+            ptr
+        }
+    }
+
+    /// レッドゾーンを確認し、本体をポイズンで埋めて解放
+    ///
+    /// # Panics
+    /// レッドゾーンまたは記録したサイズが書き換えられていた場合
+    pub(super) unsafe fn dealloc(allocator: &SlabAllocator, ptr: *mut u8, layout: Layout) {
+        let Some(outer) = outer_layout(&layout) else {
+            corrupted("impossible layout", ptr, &layout, &layout);
+        };
+        let front = front_size(&layout);
+        // SAFETY: ptrはallocが返したアドレスで、前後のレッドゾーンも同じブロックに含まれる
+        unsafe {
+            let block = ptr.sub(front);
+            if (block as *const usize).read_unaligned() != layout.size() {
+                corrupted(
+                    "size header overwritten (underflow or double free)",
+                    ptr,
+                    &layout,
+                    &outer,
+                );
+            }
+            let head = core::slice::from_raw_parts(block.add(HEADER), front - HEADER);
+            if head.iter().any(|&byte| byte != CANARY) {
+                corrupted("front redzone overwritten", ptr, &layout, &outer);
+            }
+            let tail = core::slice::from_raw_parts(ptr.add(layout.size()), REDZONE);
+            if tail.iter().any(|&byte| byte != CANARY) {
+                corrupted("rear redzone overwritten", ptr, &layout, &outer);
+            }
+
+            for i in 0..layout.size() {
+                ptr.add(i).write(POISON[i % POISON.len()]);
+            }
+            allocator.dealloc_block(block, outer);
+        }
+    }
 }
 
+// =============================================================================
+// 割り当てイベント（可視化機能専用）
+// visualize-allocatorフィーチャーが有効な場合のみ、割り当て・解放のたびにイベントを記録する
+// =============================================================================
 #[cfg(feature = "visualize-allocator")]
-pub(crate) fn get_size_classes_internal() -> &'static [usize] {
-    SIZE_CLASSES
+pub mod events {
+    //! アロケータの割り当て・解放イベント
+    //!
+    //! イベントはアロケータの内部で記録するため、ヒープを使わない固定長のリングバッファに
+    //! 積みます。満杯のときは新しいイベントを捨てて件数だけを数え、アロケータを待たせません。
+    //! 取りこぼしが発生した場合、購読側は `snapshot` で状態を取り直します。
+
+    use core::ptr::NonNull;
+
+    use spin::Mutex;
+
+    use super::{ALLOCATOR, FreeNode, NUM_SIZE_CLASSES};
+    use crate::io::without_interrupts;
+
+    /// リングバッファに保持できるイベント数
+    pub const CAPACITY: usize = 512;
+
+    /// イベントの種類
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum AllocEventKind {
+        Alloc,
+        Free,
+    }
+
+    /// 割り当て・解放イベント
+    #[derive(Debug, Clone, Copy)]
+    pub struct AllocEvent {
+        pub kind: AllocEventKind,
+        /// サイズクラスのインデックス（大きなサイズ用領域は `NUM_SIZE_CLASSES`）
+        pub class: usize,
+        /// ブロックの先頭アドレス
+        pub addr: usize,
+        /// 要求サイズ（アラインメントで切り上げた値）
+        #[allow(dead_code)]
+        pub size: usize,
+    }
+
+    impl AllocEvent {
+        /// 配列の初期化用
+        pub const EMPTY: Self = Self {
+            kind: AllocEventKind::Alloc,
+            class: 0,
+            addr: 0,
+            size: 0,
+        };
+    }
+
+    /// 固定長のイベントキュー
+    struct EventRing {
+        events: [AllocEvent; CAPACITY],
+        /// 最も古いイベントの位置
+        head: usize,
+        /// 保持しているイベント数
+        len: usize,
+        /// 満杯で捨てたイベントの累計
+        dropped: u64,
+    }
+
+    static EVENTS: Mutex<EventRing> = Mutex::new(EventRing {
+        events: [AllocEvent::EMPTY; CAPACITY],
+        head: 0,
+        len: 0,
+        dropped: 0,
+    });
+
+    /// イベントを記録（満杯なら捨てる）
+    pub(super) fn record(kind: AllocEventKind, class: usize, addr: *mut u8, size: usize) {
+        without_interrupts(|| {
+            let mut ring = EVENTS.lock();
+            if ring.len == CAPACITY {
+                ring.dropped += 1;
+                return;
+            }
+            let tail = (ring.head + ring.len) % CAPACITY;
+            ring.events[tail] = AllocEvent {
+                kind,
+                class,
+                addr: addr as usize,
+                size,
+            };
+            ring.len += 1;
+        })
+    }
+
+    /// 記録されたイベントを古い順に取り出す
+    ///
+    /// # Returns
+    /// `buf` に書き込んだイベント数
+    pub fn take(buf: &mut [AllocEvent]) -> usize {
+        without_interrupts(|| {
+            let mut ring = EVENTS.lock();
+            let count = buf.len().min(ring.len);
+            for slot in buf.iter_mut().take(count) {
+                *slot = ring.events[ring.head];
+                ring.head = (ring.head + 1) % CAPACITY;
+            }
+            ring.len -= count;
+            count
+        })
+    }
+
+    /// 満杯で捨てたイベントの累計
+    pub fn dropped() -> u64 {
+        without_interrupts(|| EVENTS.lock().dropped)
+    }
+
+    /// サイズクラスのスラブの配置
+    #[derive(Debug, Clone, Copy)]
+    pub struct SlabInfo {
+        pub block_size: usize,
+        /// スラブ領域の先頭アドレス
+        pub region_start: usize,
+        /// ブロック数
+        pub block_count: usize,
+    }
+
+    impl SlabInfo {
+        /// アドレスからブロック番号を取得（スラブ領域外ならNone）
+        pub fn block_index(&self, addr: usize) -> Option<usize> {
+            let index = addr.checked_sub(self.region_start)? / self.block_size;
+            (index < self.block_count).then_some(index)
+        }
+    }
+
+    /// サイズクラスのスラブの配置を取得
+    pub fn slab_info(class: usize) -> SlabInfo {
+        let cache = &ALLOCATOR.caches[class];
+        // SAFETY: region_startとowner_tag_countは初期化時にのみ書き込まれる
+        unsafe {
+            SlabInfo {
+                block_size: cache.block_size,
+                region_start: *cache.region_start.get(),
+                block_count: *cache.owner_tag_count.get(),
+            }
+        }
+    }
+
+    /// 現在の空きブロックを列挙し、未処理のイベントを破棄する
+    ///
+    /// 割り込みを無効にしたまま全フリーリストを走査するため、列挙した状態と
+    /// 以降に記録されるイベントの間に抜けや重複はありません。
+    ///
+    /// # Arguments
+    /// * `mark_free` - 空きブロックごとに (サイズクラス, ブロック番号) で呼ばれる。
+    ///   割り込み無効中に呼ばれるため、ヒープを確保してはならない
+    pub fn snapshot(mut mark_free: impl FnMut(usize, usize)) {
+        without_interrupts(|| {
+            let mut ring = EVENTS.lock();
+            ring.head = 0;
+            ring.len = 0;
+
+            for class in 0..NUM_SIZE_CLASSES {
+                let info = slab_info(class);
+                // 走査中はロックを保持し、他のCPUからフリーリストを変更させない
+                let free_list = ALLOCATOR.caches[class].free_list.lock();
+                let mut current: Option<NonNull<FreeNode>> = *free_list;
+                while let Some(node) = current {
+                    if let Some(index) = info.block_index(node.as_ptr() as usize) {
+                        mark_free(class, index);
+                    }
+                    // SAFETY: フリーリストのノードは空きブロック上の有効なFreeNode
+                    current = unsafe { (*node.as_ptr()).next };
+                }
+            }
+        })
+    }
 }
@@ -1,344 +1,373 @@
-// =============================================================================
-// メモリアロケータ可視化機能
-// cargo build --release --features visualize-allocator でビルドした場合のみ有効
-// =============================================================================
-
-extern crate alloc;
-
-use crate::allocator::{self, SlabAllocator};
-use crate::graphics::{FramebufferWriter, draw_rect, draw_string};
-use crate::info;
-use alloc::format;
-use core::arch::asm;
+//! メモリアロケータ可視化機能
+//!
+//! cargo build --release --features visualize-allocator でビルドした場合のみ有効
+//!
+//! アロケータが記録する割り当て・解放イベント（`allocator::events`）を購読し、
+//! サイズクラスごとのブロックの使用状況をライブで表示します。
+//! 起動時に一度だけフリーリストからスナップショットを取り、以降はイベントで
+//! 変化したセルだけを描き直すため、他のタスクが動作している間も正確な状態を表示できます。
+//! イベントの取りこぼしを検出した場合はスナップショットを取り直して全体を描き直します。
+
+use alloc::vec;
+use alloc::vec::Vec;
 use core::fmt::Write;
 
-// =============================================================================
-// アロケータへのアクセス関数
-// =============================================================================
+use crate::allocator::events::{self, AllocEvent, AllocEventKind, SlabInfo};
+use crate::allocator::{NUM_SIZE_CLASSES, SIZE_CLASSES};
+use crate::graphics::{CELL_HEIGHT, Color, Region, TaskWriter, compositor, theme};
+use crate::{hpet, sched};
 
-pub fn get_allocator() -> &'static SlabAllocator {
-    allocator::get_allocator_internal()
-}
+/// 表示領域の幅
+const VIEW_WIDTH: u32 = 450;
 
-pub fn get_size_classes() -> &'static [usize] {
-    allocator::get_size_classes_internal()
-}
+/// 表示領域の高さ
+const VIEW_HEIGHT: u32 = 290;
 
-// =============================================================================
-// ユーティリティ関数
-// =============================================================================
+/// 画面端からのマージン
+const MARGIN: u32 = 10;
 
-// アドレスをアラインメントに合わせて切り下げ
-fn align_down(addr: usize, align: usize) -> usize {
-    addr & !(align - 1)
-}
+/// 表示領域の上端（デバッグオーバーレイの下）
+const VIEW_TOP: u32 = 90;
 
-// =============================================================================
-// 描画関数
-// =============================================================================
+/// 1サイズクラスのグリッドの一辺のセル数
+const GRID_CELLS: usize = 20;
 
-// 画面左側にコードスニペットを表示
-pub fn draw_code_snippet(writer: &mut FramebufferWriter, code_lines: &[&str]) {
-    let fb_base = writer.fb_base;
-    let screen_width = writer.width;
+/// セルの一辺（ピクセル）
+const CELL_SIZE: u32 = 3;
 
-    // 左側の領域をクリア
-    unsafe {
-        draw_rect(fb_base, screen_width, 0, 280, 400, 320, 0x000000);
-    }
+/// セルの間隔を含めた一辺（ピクセル）
+const CELL_PITCH: u32 = CELL_SIZE + 1;
 
-    let start_x = 10;
-    let mut y = 290;
+/// グリッドの一辺（ピクセル）
+const GRID_PIXELS: u32 = GRID_CELLS as u32 * CELL_PITCH;
 
-    // タイトル
-    unsafe {
-        draw_string(fb_base, screen_width, start_x, y, "Code:", 0xFFFF00);
-    }
-    y += 15;
+/// 1行に並べるグリッド数
+const GRIDS_PER_ROW: usize = 5;
 
-    // コード行を描画
-    for line in code_lines {
-        unsafe {
-            draw_string(fb_base, screen_width, start_x, y, line, 0x00FFFF);
-        }
-        y += 10;
-    }
+/// グリッドの横方向の間隔（ピクセル）
+const GRID_GAP_X: u32 = 8;
+
+/// グリッド1段の高さ（ラベル + グリッド + 使用率）
+const GRID_ROW_HEIGHT: u32 = CELL_HEIGHT as u32 + 2 + GRID_PIXELS + CELL_HEIGHT as u32 + 8;
+
+/// 最初のグリッド段の上端
+const GRIDS_TOP: u32 = CELL_HEIGHT as u32 + 6;
+
+/// 更新間隔（ミリ秒）
+const UPDATE_INTERVAL_MS: u64 = 33;
+
+/// 変化がないときに統計を描き直す間隔（ミリ秒）
+const STATUS_INTERVAL_MS: u64 = 1000;
+
+/// 空きブロックの色
+const FREE_COLOR: Color = Color::GREEN;
+
+/// 使用中ブロックの色
+const USED_COLOR: Color = Color::RED;
+
+/// 1つのサイズクラスの表示状態
+struct ClassView {
+    info: SlabInfo,
+    /// ブロックごとの使用中フラグ（1ビット1ブロック）
+    used: Vec<u64>,
+    /// セルごとの使用中ブロック数
+    cell_used: Vec<u32>,
+    /// 再描画が必要なセル
+    dirty: Vec<bool>,
+    /// 使用中ブロック数
+    used_count: usize,
+    /// 最後に描画した使用率（%）
+    drawn_percent: Option<usize>,
+    /// 1セルが表すブロック数
+    blocks_per_cell: usize,
 }
 
-// 複数のサイズクラスをコンパクトに並べて表示
-pub fn draw_memory_grids_multi(writer: &mut FramebufferWriter, title: &str) {
-    let allocator = get_allocator();
-    let size_classes = get_size_classes();
+impl ClassView {
+    fn new(info: SlabInfo) -> Self {
+        let cells = GRID_CELLS * GRID_CELLS;
+        Self {
+            info,
+            used: vec![0; info.block_count.div_ceil(64)],
+            cell_used: vec![0; cells],
+            dirty: vec![false; cells],
+            used_count: 0,
+            drawn_percent: None,
+            blocks_per_cell: info.block_count.div_ceil(cells).max(1),
+        }
+    }
 
-    let fb_base = writer.fb_base;
-    let screen_width = writer.width;
+    /// 全ブロックを使用中にする（スナップショットで空きブロックを取り除く前の状態）
+    fn mark_all_used(&mut self) {
+        self.used.fill(u64::MAX);
+    }
 
-    // 右側の領域をクリア（x=400以降）
-    unsafe {
-        draw_rect(fb_base, screen_width, 400, 280, 624, 320, 0x000000);
+    /// ブロックを空きにする（スナップショット用、割り込み無効中に呼ばれるため確保しない）
+    fn mark_free(&mut self, index: usize) {
+        self.used[index / 64] &= !(1 << (index % 64));
     }
 
-    // タイトルを描画
-    unsafe {
-        draw_string(fb_base, screen_width, 410, 290, title, 0xFFFF00);
+    /// ビットマップからセルごとの集計を作り直し、全セルを再描画対象にする
+    fn recount(&mut self) {
+        self.cell_used.fill(0);
+        self.used_count = 0;
+        for index in 0..self.info.block_count {
+            if self.is_used(index) {
+                self.cell_used[index / self.blocks_per_cell] += 1;
+                self.used_count += 1;
+            }
+        }
+        self.dirty.fill(true);
+        self.drawn_percent = None;
     }
 
-    let heap_size = 256 * 1024; // 256KB
+    fn is_used(&self, index: usize) -> bool {
+        self.used[index / 64] & (1 << (index % 64)) != 0
+    }
 
-    // 各サイズクラスを3列で並べて表示（最大6個まで）
-    let grid_cols_per_class = 20; // 各グリッドは20x20セル
-    let cell_size = 3; // 各セル3x3ピクセル
-    let grid_pixel_size = grid_cols_per_class * (cell_size + 1); // 約80ピクセル
+    /// イベントを反映（状態が変わらないイベントは無視）
+    fn apply(&mut self, kind: AllocEventKind, addr: usize) {
+        let Some(index) = self.info.block_index(addr) else {
+            return;
+        };
+        let used = kind == AllocEventKind::Alloc;
+        if self.is_used(index) == used {
+            return;
+        }
+        self.used[index / 64] ^= 1 << (index % 64);
+        let cell = index / self.blocks_per_cell;
+        if used {
+            self.cell_used[cell] += 1;
+            self.used_count += 1;
+        } else {
+            self.cell_used[cell] -= 1;
+            self.used_count -= 1;
+        }
+        self.dirty[cell] = true;
+    }
 
-    let start_x = 410;
-    let start_y = 310;
-    let classes_to_show = 6.min(size_classes.len()); // 画面に収まる範囲で6個まで
+    /// セルが表すブロック数（末尾のセルは少ない場合がある）
+    fn cell_blocks(&self, cell: usize) -> usize {
+        let start = cell * self.blocks_per_cell;
+        self.info
+            .block_count
+            .saturating_sub(start)
+            .min(self.blocks_per_cell)
+    }
 
-    for class_idx in 0..classes_to_show {
-        let size = size_classes[class_idx];
-        let slab_size = (heap_size / 2) / size_classes.len();
-        let aligned_size = align_down(slab_size, size);
-        let total_blocks = aligned_size / size;
+    fn usage_percent(&self) -> usize {
+        (self.used_count * 100)
+            .checked_div(self.info.block_count)
+            .unwrap_or(0)
+    }
+}
 
-        let free_count = allocator.count_free_blocks(class_idx);
-        let used_count = total_blocks - free_count;
+/// 可視化タスクの状態
+struct AllocatorView {
+    classes: Vec<ClassView>,
+    /// 処理したイベントの累計
+    processed: u64,
+    /// 最後に確認したイベントの取りこぼし数
+    dropped: u64,
+    /// 最後に描画した統計 (大きなサイズ用領域の使用量, 取りこぼし数)
+    drawn_status: Option<(usize, u64)>,
+    /// 最後に統計を描画した時刻（ミリ秒）
+    status_drawn_ms: u64,
+}
 
-        // グリッドの位置を計算（3列レイアウト）
-        let col = class_idx % 3;
-        let row = class_idx / 3;
-        let grid_x = start_x + col * (grid_pixel_size + 20);
-        let grid_y = start_y + row * (grid_pixel_size + 35);
+impl AllocatorView {
+    fn new() -> Self {
+        Self {
+            classes: (0..NUM_SIZE_CLASSES)
+                .map(|class| ClassView::new(events::slab_info(class)))
+                .collect(),
+            processed: 0,
+            dropped: events::dropped(),
+            drawn_status: None,
+            status_drawn_ms: 0,
+        }
+    }
 
-        // サイズクラスラベル
-        let label = format!("{}B", size);
-        unsafe {
-            draw_string(fb_base, screen_width, grid_x, grid_y - 12, &label, 0xFFFFFF);
+    /// フリーリストから状態を取り直す
+    fn resync(&mut self) {
+        for view in &mut self.classes {
+            view.mark_all_used();
         }
+        let classes = &mut self.classes;
+        events::snapshot(|class, index| classes[class].mark_free(index));
+        for view in &mut self.classes {
+            view.recount();
+        }
+        self.drawn_status = None;
+    }
 
-        // グリッドを描画（最大400ブロックまで = 20x20）
-        let max_display = (grid_cols_per_class * grid_cols_per_class).min(total_blocks);
+    /// 溜まっているイベントを反映
+    ///
+    /// 他のタスクが割り当てを続けても1回の更新が終わるよう、
+    /// 処理するのはリングバッファ1周分までとします。
+    fn drain_events(&mut self, buf: &mut [AllocEvent]) {
+        let mut remaining = events::CAPACITY;
+        while remaining > 0 {
+            let limit = buf.len().min(remaining);
+            let count = events::take(&mut buf[..limit]);
+            if count == 0 {
+                break;
+            }
+            for event in &buf[..count] {
+                if let Some(view) = self.classes.get_mut(event.class) {
+                    view.apply(event.kind, event.addr);
+                }
+            }
+            self.processed += count as u64;
+            remaining -= count;
+        }
+    }
 
-        for i in 0..max_display {
-            let grid_row = i / grid_cols_per_class;
-            let grid_col = i % grid_cols_per_class;
+    /// 静的な部分（タイトル・ラベル・凡例）を描画
+    fn draw_frame(&self, writer: &mut TaskWriter) {
+        writer.clear_themed();
+        writer.set_color(theme::accent());
+        let _ = write!(writer, "Slab allocator (live)");
+        writer.set_color(theme::foreground());
+        for (class, size) in SIZE_CLASSES.iter().enumerate() {
+            let (x, y) = grid_origin(class);
+            writer.set_position(x, y - CELL_HEIGHT as u32 - 2);
+            let _ = write!(writer, "{}B", size);
+        }
 
-            let x = grid_x + grid_col * (cell_size + 1);
-            let y = grid_y + grid_row * (cell_size + 1);
+        let legend_y = VIEW_HEIGHT - 2 * CELL_HEIGHT as u32;
+        writer.fill_rect(0, legend_y, 8, 8, USED_COLOR);
+        writer.set_position(12, legend_y);
+        let _ = write!(writer, "Used");
+        writer.fill_rect(60, legend_y, 8, 8, FREE_COLOR);
+        writer.set_position(72, legend_y);
+        let _ = write!(writer, "Free");
+    }
 
-            let color = if i < used_count {
-                0xFF0000 // 赤: 使用中
-            } else {
-                0x00FF00 // 緑: 空き
-            };
+    /// 変化したセルと数値だけを描画
+    fn draw_changes(&mut self, writer: &mut TaskWriter) {
+        for (class, view) in self.classes.iter_mut().enumerate() {
+            let (grid_x, grid_y) = grid_origin(class);
+            for cell in 0..view.dirty.len() {
+                if !core::mem::take(&mut view.dirty[cell]) {
+                    continue;
+                }
+                let blocks = view.cell_blocks(cell);
+                if blocks == 0 {
+                    continue;
+                }
+                // セル内の使用中ブロックの割合で色を混ぜる
+                let ratio = (view.cell_used[cell] as usize * 255 / blocks) as u8;
+                let x = grid_x + (cell % GRID_CELLS) as u32 * CELL_PITCH;
+                let y = grid_y + (cell / GRID_CELLS) as u32 * CELL_PITCH;
+                writer.fill_rect(
+                    x,
+                    y,
+                    CELL_SIZE,
+                    CELL_SIZE,
+                    FREE_COLOR.lerp(USED_COLOR, ratio),
+                );
+            }
 
-            unsafe {
-                draw_rect(fb_base, screen_width, x, y, cell_size, cell_size, color);
+            let percent = view.usage_percent();
+            if view.drawn_percent != Some(percent) {
+                let y = grid_y + GRID_PIXELS + 2;
+                writer.fill_rect(
+                    grid_x,
+                    y,
+                    GRID_PIXELS,
+                    CELL_HEIGHT as u32,
+                    theme::background(),
+                );
+                writer.set_color(Color::GRAY);
+                writer.set_position(grid_x, y);
+                let _ = write!(writer, "{}%", percent);
+                view.drawn_percent = Some(percent);
             }
         }
 
-        // 使用率を表示
-        let usage_pct = if total_blocks > 0 {
-            (used_count * 100) / total_blocks
-        } else {
-            0
-        };
-        let usage = format!("{}%", usage_pct);
-        unsafe {
-            draw_string(
-                fb_base,
-                screen_width,
-                grid_x + 25,
-                grid_y + grid_pixel_size + 3,
-                &usage,
-                0xAAAAAA,
-            );
+        // 大きなサイズ用領域とイベントの統計
+        // 描画自体も割り当てを行いイベント数が毎回変わるため、数値の変化だけでは描き直さない
+        let (large_used, large_total) = crate::allocator::large_usage();
+        let now_ms = hpet::elapsed_ms();
+        if self.drawn_status == Some((large_used, self.dropped))
+            && now_ms.saturating_sub(self.status_drawn_ms) < STATUS_INTERVAL_MS
+        {
+            return;
         }
-    }
-
-    // 凡例
-    let legend_y = start_y + 2 * (grid_pixel_size + 35) + 5;
-    unsafe {
-        draw_rect(fb_base, screen_width, start_x, legend_y, 8, 8, 0xFF0000);
-        draw_string(
-            fb_base,
-            screen_width,
-            start_x + 12,
-            legend_y,
-            "Used",
-            0xFFFFFF,
+        let status_y = GRIDS_TOP + 2 * GRID_ROW_HEIGHT;
+        writer.fill_rect(
+            0,
+            status_y,
+            VIEW_WIDTH,
+            2 * CELL_HEIGHT as u32,
+            theme::background(),
         );
-        draw_rect(
-            fb_base,
-            screen_width,
-            start_x + 60,
-            legend_y,
-            8,
-            8,
-            0x00FF00,
+        writer.set_color(theme::foreground());
+        writer.set_position(0, status_y);
+        let _ = write!(
+            writer,
+            "Large: {} / {} KB",
+            large_used / 1024,
+            large_total / 1024
         );
-        draw_string(
-            fb_base,
-            screen_width,
-            start_x + 72,
-            legend_y,
-            "Free",
-            0xFFFFFF,
+        writer.set_position(0, status_y + CELL_HEIGHT as u32);
+        let _ = write!(
+            writer,
+            "Events: {}  Dropped: {}",
+            self.processed, self.dropped
         );
+        self.drawn_status = Some((large_used, self.dropped));
+        self.status_drawn_ms = now_ms;
     }
 }
 
-// =============================================================================
-// 可視化テスト実行
-// =============================================================================
-
-/// メモリアロケータの可視化テストを実行
-pub fn run_visualization_tests(writer: &mut FramebufferWriter) {
-    // スラブアロケータの可視化（複数サイズクラス表示）
-    let _ = writeln!(writer, "=== Memory Allocator Visualization ===");
-
-    // 初期状態を表示
-    draw_code_snippet(writer, &["// Initial state", "// No allocations yet"]);
-    draw_memory_grids_multi(writer, "Initial State");
-    crate::hpet::delay_ms(5000);
-
-    // テスト1: 16Bクラス
-    info!("\n=== Test 1: Vec<u8> (16B class) ===");
-
-    let vec1: alloc::vec::Vec<u8> = (0..12).collect();
-
-    draw_code_snippet(
-        writer,
-        &[
-            "let vec1: Vec<u8>",
-            "  = (0..12).collect();",
-            "",
-            "// 12 x u8 = 12B",
-            "// -> 16B size class",
-        ],
-    );
-    draw_memory_grids_multi(writer, "After 16B alloc");
-    info!("Allocated Vec<u8> (12 elements = 12B -> 16B)");
-    crate::hpet::delay_ms(5000);
-
-    // テスト2: 64Bクラス
-    info!("\n=== Test 2: Vec<u8> (64B class) ===");
-
-    let vec2: alloc::vec::Vec<u8> = (0..50).collect();
-
-    draw_code_snippet(
-        writer,
-        &[
-            "let vec2: Vec<u8>",
-            "  = (0..50).collect();",
-            "",
-            "// 50 x u8 = 50B",
-            "// -> 64B size class",
-        ],
-    );
-    draw_memory_grids_multi(writer, "After 16B + 64B");
-    info!("Allocated Vec<u8> (50 elements = 50B -> 64B)");
-    crate::hpet::delay_ms(5000);
-
-    // テスト3: 128Bクラス
-    info!("\n=== Test 3: Vec<u64> (128B class) ===");
-
-    let vec3: alloc::vec::Vec<u64> = (0..10).collect();
-
-    draw_code_snippet(
-        writer,
-        &[
-            "let vec3: Vec<u64>",
-            "  = (0..10).collect();",
-            "",
-            "// 10 x u64 = 80B",
-            "// -> 128B size class",
-        ],
-    );
-    draw_memory_grids_multi(writer, "16B+64B+128B");
-    info!("Allocated Vec<u64> (10 elements = 80B -> 128B)");
-    crate::hpet::delay_ms(5000);
-
-    // テスト4: 256Bクラスを追加
-    info!("\n=== Test 4: Vec<u64> (256B class) ===");
-
-    let vec4: alloc::vec::Vec<u8> = (0..25).collect();
-
-    draw_code_snippet(
-        writer,
-        &[
-            "let vec4: Vec<u64>",
-            "  = (0..25).collect();",
-            "",
-            "// 25 x u64 = 200B",
-            "// -> 256B size class",
-        ],
-    );
-    draw_memory_grids_multi(writer, "8B+16B+64B+128B");
-    info!("Allocated Vec<u8> (25 elements = 200B -> 256B)");
-    crate::hpet::delay_ms(5000);
-
-    // テスト5: 8Bクラスを追加
-    info!("\n=== Test 5: Vec<u8> (8B class) ===");
-
-    let vec5: alloc::vec::Vec<u8> = (0..8).collect();
-
-    draw_code_snippet(
-        writer,
-        &[
-            "let vec5: Vec<u8>",
-            "  = (0..8).collect();",
-            "",
-            "// 8 x u8 = 8B",
-            "// -> 8B size class",
-        ],
-    );
-    draw_memory_grids_multi(writer, "All 5 sizes");
-    info!("Allocated Vec<u64> (8 elements = 8B -> 8B)");
-    crate::hpet::delay_ms(5000);
-
-    // テスト6: 64Bと256Bを解放
-    info!("\n=== Test 6: Free 64B and 256B ===");
-
-    drop(vec2);
-    drop(vec4);
-
-    draw_code_snippet(
-        writer,
-        &[
-            "drop(vec2);",
-            "drop(vec4);",
-            "",
-            "// Freed 64B and 256B",
-            "// 8B + 16B + 128B remain",
-        ],
-    );
-    draw_memory_grids_multi(writer, "After freeing 2");
-    info!("Freed 64B and 256B blocks");
-    crate::hpet::delay_ms(5000);
-
-    // テスト7: 全て解放
-    info!("\n=== Test 7: Free all ===");
-
-    drop(vec1);
-    drop(vec3);
-    drop(vec5);
-
-    draw_code_snippet(
-        writer,
-        &[
-            "drop(vec1);",
-            "drop(vec3);",
-            "drop(vec5);",
-            "",
-            "// All freed!",
-        ],
+/// サイズクラスのグリッドの左上（ローカル座標）
+fn grid_origin(class: usize) -> (u32, u32) {
+    let col = (class % GRIDS_PER_ROW) as u32;
+    let row = (class / GRIDS_PER_ROW) as u32;
+    (
+        col * (GRID_PIXELS + GRID_GAP_X),
+        GRIDS_TOP + CELL_HEIGHT as u32 + 2 + row * GRID_ROW_HEIGHT,
+    )
+}
+
+/// アロケータ可視化タスクのエントリポイント
+pub extern "C" fn allocator_visualization_task() -> ! {
+    crate::info!("[AllocViz] Started");
+
+    let (screen_width, _screen_height) = compositor::screen_size();
+    let region = Region::new(
+        screen_width.saturating_sub(VIEW_WIDTH + MARGIN),
+        VIEW_TOP,
+        VIEW_WIDTH,
+        VIEW_HEIGHT,
     );
-    draw_memory_grids_multi(writer, "All freed");
-    info!("All blocks freed");
-    crate::hpet::delay_ms(5000);
+    let buffer =
+        compositor::register_writer(region).expect("Failed to register allocator visualization");
+    let mut writer = TaskWriter::new(buffer, theme::foreground());
+
+    // イベントバッファとビットマップは先に確保しておく（スナップショット中は確保できない）
+    let mut buf = [AllocEvent::EMPTY; 64];
+    let mut view = AllocatorView::new();
+    view.resync();
+    view.draw_frame(&mut writer);
+
     loop {
-        unsafe {
-            asm!("hlt");
+        let dropped = events::dropped();
+        if dropped != view.dropped {
+            // 取りこぼしがあると差分では正しい状態に戻れないため、取り直す
+            crate::warn!(
+                "[AllocViz] {} events dropped, resyncing",
+                dropped - view.dropped
+            );
+            view.dropped = dropped;
+            view.resync();
+            view.draw_frame(&mut writer);
+        } else {
+            view.drain_events(&mut buf);
         }
+        view.draw_changes(&mut writer);
+        writer.flush();
+        sched::sleep_ms(UPDATE_INTERVAL_MS);
     }
 }
@@ -6,8 +6,10 @@ use core::arch::asm;
 use core::ptr::{read_volatile, write_volatile};
 use core::sync::atomic::{AtomicU32, Ordering};
 
+use crate::boot_health;
 use crate::hpet;
-use crate::paging::KERNEL_VIRTUAL_BASE;
+use crate::iotrace::{self, Device, Direction};
+use crate::paging::{self, CacheMode, KERNEL_VIRTUAL_BASE, PAGE_SIZE};
 use crate::pit;
 
 /// APIC操作のエラー型
@@ -35,14 +37,19 @@ impl core::fmt::Display for ApicError {
     }
 }
 
+/// Local APICレジスタの物理アドレス
+const APIC_PHYS_BASE: u64 = 0xFEE00000;
+
 /// Local APICのベースアドレス（高位仮想アドレス）
-/// 物理アドレス 0xFEE00000 を高位仮想アドレス経由でアクセス
-const APIC_BASE: u64 = KERNEL_VIRTUAL_BASE + 0xFEE00000;
+/// 物理アドレス 0xFEE00000 を高位仮想アドレス経由でアクセス（init() でマップする）
+const APIC_BASE: u64 = KERNEL_VIRTUAL_BASE + APIC_PHYS_BASE;
 
 /// Local APICレジスタのオフセット
 mod registers {
     /// Spurious Interrupt Vector Register
     pub const SPURIOUS_INTERRUPT_VECTOR: u32 = 0xF0;
+    /// Local APIC ID Register
+    pub const ID: u32 = 0x20;
     /// End of Interrupt Register
     pub const EOI: u32 = 0xB0;
     /// Timer LVT (Local Vector Table) Register
@@ -53,6 +60,26 @@ mod registers {
     pub const TIMER_INITIAL_COUNT: u32 = 0x380;
     /// Timer Current Count Register
     pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+    /// Interrupt Command Register（下位32ビット、書き込みでIPIを送信）
+    pub const ICR_LOW: u32 = 0x300;
+    /// Interrupt Command Register（上位32ビット、送信先のAPIC ID）
+    pub const ICR_HIGH: u32 = 0x310;
+}
+
+/// ICRのビット
+mod icr {
+    /// Delivery Mode: Fixed
+    pub const DELIVERY_FIXED: u32 = 0b000 << 8;
+    /// Delivery Mode: NMI
+    pub const DELIVERY_NMI: u32 = 0b100 << 8;
+    /// Delivery Mode: INIT
+    pub const DELIVERY_INIT: u32 = 0b101 << 8;
+    /// Delivery Mode: Start Up
+    pub const DELIVERY_STARTUP: u32 = 0b110 << 8;
+    /// Delivery Status: 送信中
+    pub const SEND_PENDING: u32 = 1 << 12;
+    /// Level: Assert
+    pub const LEVEL_ASSERT: u32 = 1 << 14;
 }
 
 /// Local APICレジスタへの書き込み
@@ -68,6 +95,13 @@ unsafe fn write_apic_register(offset: u32, value: u32) {
     unsafe {
         write_volatile(addr, value);
     }
+    iotrace::mmio(
+        Device::Apic,
+        addr as u64,
+        32,
+        value as u64,
+        Direction::Write,
+    );
 }
 
 /// Local APICレジスタからの読み込み
@@ -79,7 +113,9 @@ unsafe fn read_apic_register(offset: u32) -> u32 {
     let addr = (APIC_BASE + offset as u64) as *const u32;
     // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
     // APICレジスタはメモリマップドI/Oであり、read_volatileで読み込む必要がある。
-    unsafe { read_volatile(addr) }
+    let value = unsafe { read_volatile(addr) };
+    iotrace::mmio(Device::Apic, addr as u64, 32, value as u64, Direction::Read);
+    value
 }
 
 /// MSR (Model Specific Register) の読み込み
@@ -150,6 +186,89 @@ pub fn enable_apic() {
     }
 }
 
+/// 現在のCPUのLocal APIC IDを取得
+pub fn local_apic_id() -> u8 {
+    // SAFETY: IDレジスタの読み込みは副作用がなく、APICは初期化時に有効化済み。
+    // bits 24-31: APIC ID
+    unsafe { (read_apic_register(registers::ID) >> 24) as u8 }
+}
+
+/// 指定したLocal APICへIPIを送信し、送信完了を待つ
+///
+/// # Safety
+/// `command`が有効なICRの値であり、送信先のCPUがそのIPIを受け取ってよい状態であること
+unsafe fn send_ipi(apic_id: u8, command: u32) {
+    // SAFETY: 呼び出し元がcommandの妥当性を保証する。ICR_HIGHを先に書き、
+    // ICR_LOWへの書き込みで送信が開始される
+    unsafe {
+        write_apic_register(registers::ICR_HIGH, (apic_id as u32) << 24);
+        write_apic_register(registers::ICR_LOW, command);
+        while read_apic_register(registers::ICR_LOW) & icr::SEND_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// INIT IPIを送信（送信先のCPUはwait-for-SIPI状態になる）
+///
+/// # Safety
+/// 送信先は起動中のカーネルが動作していないCPUであること（実行中の処理はすべて失われる）
+pub unsafe fn send_init_ipi(apic_id: u8) {
+    // SAFETY: 呼び出し元が送信先をリセットしてよいことを保証する
+    unsafe { send_ipi(apic_id, icr::DELIVERY_INIT | icr::LEVEL_ASSERT) };
+}
+
+/// Startup IPI (SIPI) を送信
+///
+/// 送信先のCPUはリアルモードで物理アドレス `vector * 0x1000` から実行を開始します。
+///
+/// # Safety
+/// 送信先はINIT IPIを受け取った直後のCPUであり、開始アドレスに有効なコードが置かれていること
+pub unsafe fn send_startup_ipi(apic_id: u8, vector: u8) {
+    // SAFETY: 呼び出し元が開始アドレスのコードを用意していることを保証する
+    unsafe {
+        send_ipi(
+            apic_id,
+            icr::DELIVERY_STARTUP | icr::LEVEL_ASSERT | vector as u32,
+        )
+    };
+}
+
+/// 指定したベクタの割り込み（Fixed IPI）を送信
+///
+/// 送信先のCPUでは、IDTに登録された `vector` のハンドラが実行されます。
+///
+/// # Arguments
+/// * `apic_id` - 送信先のLocal APIC ID
+/// * `vector` - 割り込みベクタ番号（ハンドラを登録済みであること）
+pub fn send_fixed_ipi(apic_id: u8, vector: u8) {
+    // ICR_HIGHとICR_LOWの書き込みの間に、割り込みハンドラが別のIPIを送らないようにする
+    crate::io::without_interrupts(|| {
+        // SAFETY: Fixedモードの割り込みは送信先の実行中の処理を中断するだけで、
+        // ハンドラから復帰すれば処理は継続される
+        unsafe {
+            send_ipi(
+                apic_id,
+                icr::DELIVERY_FIXED | icr::LEVEL_ASSERT | vector as u32,
+            )
+        };
+    });
+}
+
+/// NMIを送信
+///
+/// 送信先のCPUが割り込みを無効にしていても、IDTのNMIハンドラが実行されます。
+///
+/// # Arguments
+/// * `apic_id` - 送信先のLocal APIC ID
+pub fn send_nmi_ipi(apic_id: u8) {
+    crate::io::without_interrupts(|| {
+        // SAFETY: NMIは送信先の実行中の処理を中断するだけで、ハンドラから復帰すれば
+        // 処理は継続される
+        unsafe { send_ipi(apic_id, icr::DELIVERY_NMI | icr::LEVEL_ASSERT) };
+    });
+}
+
 /// タイマー割り込みベクタ番号
 pub const TIMER_INTERRUPT_VECTOR: u8 = 32;
 
@@ -210,70 +329,141 @@ unsafe fn measure_apic_ticks_pit(ms: u32) -> u32 {
     unsafe { measure_apic_ticks(|| pit::sleep_ms(ms)) }
 }
 
+/// キャリブレーション1回あたりの測定時間（ミリ秒）
+const CALIBRATION_MS: u32 = 50;
+
+/// HPETとPITの測定結果の乖離を警告する閾値（ベーシスポイント、100 = 1%）
+const DIVERGENCE_WARN_BP: u32 = 100;
+
+/// APIC Timerのキャリブレーションに使用したタイムソース
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationSource {
+    /// HPET（高精度、ACPIで周波数が既知）
+    Hpet,
+    /// 8254 PIT（HPETが無い場合のフォールバック）
+    Pit,
+}
+
+impl core::fmt::Display for CalibrationSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            CalibrationSource::Hpet => write!(f, "HPET"),
+            CalibrationSource::Pit => write!(f, "PIT"),
+        }
+    }
+}
+
+/// HPETでAPIC Timerの周波数を測定（Hz）
+///
+/// HPETは高精度なので1回の測定で十分です。
+///
+/// # Safety
+/// APICが有効化されていること、HPETが初期化済みであること
+unsafe fn calibrate_with_hpet() -> u32 {
+    // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
+    let ticks = unsafe { measure_apic_ticks_hpet(CALIBRATION_MS as u64) };
+    ticks * (1000 / CALIBRATION_MS)
+}
+
+/// PITでAPIC Timerの周波数を測定（Hz）
+///
+/// PITはポーリングの揺らぎが大きいため、`N`回測定して中央値を採用します。
+///
+/// # Safety
+/// APICが有効化されていること
+unsafe fn calibrate_with_pit<const N: usize>() -> u32 {
+    let mut measurements = [0u32; N];
+
+    for measurement in measurements.iter_mut() {
+        // SAFETY: 呼び出し元がAPICの有効化を保証する。
+        *measurement = unsafe { measure_apic_ticks_pit(CALIBRATION_MS) };
+    }
+
+    // ソートして中央値を取る（外れ値の影響を排除）
+    measurements.sort_unstable();
+    let multiplier = 1000 / CALIBRATION_MS;
+    crate::info!(
+        "  PIT measurements: {:?}",
+        measurements.map(|t| t * multiplier)
+    );
+    measurements[N / 2] * multiplier
+}
+
+/// 2つの周波数の乖離をベーシスポイント（0.01%単位）で求める
+///
+/// `reference`を基準とした相対誤差を返します。基準が0の場合は`u32::MAX`を返します。
+fn divergence_bp(reference: u32, other: u32) -> u32 {
+    if reference == 0 {
+        return u32::MAX;
+    }
+    let diff = (reference as u64).abs_diff(other as u64);
+    (diff * 10_000 / reference as u64).min(u32::MAX as u64) as u32
+}
+
 /// APIC Timerをキャリブレーション
 ///
-/// HPET（利用可能な場合）またはPITを使ってAPIC Timerの周波数を測定します。
-/// HPETは高精度なので1回測定、PITは5回測定して中央値を採用します。
+/// HPETが利用可能な場合はHPETで測定し、PITでも測定して結果を突き合わせます
+/// （乖離が1%を超える場合は警告）。HPETが無い場合はPITで5回測定して中央値を採用します。
+/// 採用したタイムソースは起動ヘルスレポートに記録されます。
 /// この関数は割り込みが無効な状態で呼び出す必要があります。
 ///
 /// # Errors
 /// * `ApicError::CalibrationFailed` - キャリブレーションに失敗した場合（周波数が0など）
 pub fn calibrate_timer() -> Result<(), ApicError> {
-    let ticks_per_second = if hpet::is_available() {
-        // HPETが利用可能: 高精度なので1回測定で十分
-        const CALIBRATION_MS: u64 = 50;
-
+    let (source, ticks_per_second, divergence) = if hpet::is_available() {
         crate::info!("Calibrating APIC Timer using HPET...");
 
+        // SAFETY: enable_apic()呼び出し後、かつHPET初期化済みであることが前提
+        let hpet_hz = unsafe { calibrate_with_hpet() };
+
+        // PITでも測定してHPETの値を検証する（ファームウェアのHPET周期が
+        // 誤っている場合に気付けるようにする）
         // SAFETY: enable_apic()呼び出し後であることが前提
-        let ticks = unsafe { measure_apic_ticks_hpet(CALIBRATION_MS) };
-        let ticks_per_second = ticks * (1000 / CALIBRATION_MS as u32);
+        let pit_hz = unsafe { calibrate_with_pit::<3>() };
+        let divergence = divergence_bp(hpet_hz, pit_hz);
 
         crate::info!(
-            "APIC Timer calibrated (HPET): {} Hz ({} ticks in {}ms)",
-            ticks_per_second,
-            ticks,
-            CALIBRATION_MS
+            "APIC Timer calibrated (HPET): {} Hz (PIT cross-check: {} Hz, divergence {}.{:02}%)",
+            hpet_hz,
+            pit_hz,
+            divergence / 100,
+            divergence % 100
         );
+        if divergence > DIVERGENCE_WARN_BP {
+            crate::warn!(
+                "APIC Timer: HPET and PIT calibration diverge by more than {}% ({} Hz vs {} Hz)",
+                DIVERGENCE_WARN_BP / 100,
+                hpet_hz,
+                pit_hz
+            );
+        }
 
-        ticks_per_second
+        (CalibrationSource::Hpet, hpet_hz, Some(divergence))
     } else {
-        // PITを使用: 精度向上のため5回測定して中央値
         const MEASUREMENTS: usize = 5;
-        const CALIBRATION_MS: u32 = 50;
 
         crate::info!(
             "Calibrating APIC Timer using PIT ({} measurements)...",
             MEASUREMENTS
         );
 
-        let mut measurements = [0u32; MEASUREMENTS];
-
-        for measurement in measurements.iter_mut() {
-            // SAFETY: enable_apic()呼び出し後であることが前提
-            *measurement = unsafe { measure_apic_ticks_pit(CALIBRATION_MS) };
-        }
+        // SAFETY: enable_apic()呼び出し後であることが前提
+        let pit_hz = unsafe { calibrate_with_pit::<MEASUREMENTS>() };
 
-        // ソートして中央値を取る（外れ値の影響を排除）
-        measurements.sort_unstable();
-        let median_ticks = measurements[MEASUREMENTS / 2];
-        let multiplier = 1000 / CALIBRATION_MS;
-        let ticks_per_second = median_ticks * multiplier;
+        crate::info!("APIC Timer calibrated (PIT): {} Hz", pit_hz);
 
-        crate::info!(
-            "APIC Timer calibrated (PIT): {} Hz (median: {} ticks in {}ms)",
-            ticks_per_second,
-            median_ticks,
-            CALIBRATION_MS
-        );
-        crate::info!("  measurements: {:?}", measurements.map(|t| t * multiplier));
-
-        ticks_per_second
+        (CalibrationSource::Pit, pit_hz, None)
     };
 
     // バス周波数を保存（分周比16を考慮した実効周波数）
     APIC_TIMER_FREQUENCY.store(ticks_per_second, Ordering::SeqCst);
 
+    boot_health::record_timer_calibration(boot_health::TimerCalibration {
+        source,
+        frequency_hz: ticks_per_second,
+        divergence_bp: divergence,
+    });
+
     // 周波数が0の場合はエラー
     if ticks_per_second == 0 {
         return Err(ApicError::CalibrationFailed);
@@ -363,9 +553,17 @@ fn disable_legacy_pic() {
 }
 
 /// Local APICを初期化
-pub fn init() {
+///
+/// # Errors
+/// * `ApicError::InitFailed` - レジスタ領域をマップできない場合
+pub fn init() -> Result<(), ApicError> {
+    // レジスタ領域は直接マッピングに含まれないため、最初にマップする（APも同じマッピングを使う）
+    paging::map_mmio(APIC_PHYS_BASE, PAGE_SIZE as u64, CacheMode::Uncacheable)
+        .map_err(|_| ApicError::InitFailed)?;
+
     // まずレガシーPICを無効化
     disable_legacy_pic();
     enable_apic();
     // タイマーは別途 init_timer() で初期化
+    Ok(())
 }
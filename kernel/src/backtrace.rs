@@ -0,0 +1,84 @@
+//! フレームポインタによるバックトレース
+//!
+//! カーネルは `-C force-frame-pointers=yes` でビルドするため、各関数のフレームは
+//! `[rbp]` = 呼び出し元のrbp、`[rbp + 8]` = 戻り先アドレス の連結リストになっています。
+//! これを辿って呼び出し履歴を求め、`ksyms` で関数名に変換します。
+//!
+//! パニック時に使うため、ヒープもロックも使いません。壊れたスタックを辿って
+//! フォルトしないよう、ページテーブルは参照せずに次のいずれかで打ち切ります。
+//! - rbpが0、または8バイト境界でない
+//! - rbpが開始時のrbpから `MAX_STACK_SPAN` 以上離れている
+//! - 次のrbpが現在のrbp以下（呼び出し元のフレームは必ず上位アドレスにある）
+//! - 戻り先アドレスがカーネルの `.text` の外
+
+use core::fmt;
+
+use crate::ksyms;
+
+/// 辿るフレームの最大数
+pub const MAX_FRAMES: usize = 32;
+
+/// 開始時のrbpから辿る範囲（カーネルスタックより十分大きい値）
+const MAX_STACK_SPAN: u64 = 64 * 1024;
+
+unsafe extern "C" {
+    // リンカスクリプトで定義される .text の範囲
+    static __text_start: u8;
+    static __text_end: u8;
+}
+
+/// アドレスがカーネルのコード内か
+pub fn is_kernel_text(addr: u64) -> bool {
+    let start = &raw const __text_start as u64;
+    let end = &raw const __text_end as u64;
+    (start..end).contains(&addr)
+}
+
+/// rbpから呼び出し元を辿り、戻り先アドレスを新しい順に渡す
+///
+/// # Arguments
+/// * `rbp` - 開始するフレームのrbp
+/// * `f` - 各フレームの戻り先アドレスを受け取るクロージャ
+///
+/// # Returns
+/// 辿ったフレーム数
+pub fn walk(rbp: u64, mut f: impl FnMut(u64)) -> usize {
+    let limit = rbp.saturating_add(MAX_STACK_SPAN);
+    let mut frame = rbp;
+    let mut count = 0;
+    while count < MAX_FRAMES && frame != 0 && frame.is_multiple_of(8) && frame < limit - 16 {
+        // SAFETY: frameは開始時のrbp（実行中のスタック）から上位へ単調に進んだ8バイト境界の
+        // アドレスで、範囲を `MAX_STACK_SPAN` に制限している
+        let (next, return_addr) = unsafe {
+            (
+                core::ptr::read_volatile(frame as *const u64),
+                core::ptr::read_volatile((frame + 8) as *const u64),
+            )
+        };
+        if !is_kernel_text(return_addr) {
+            break;
+        }
+        f(return_addr);
+        count += 1;
+        if next <= frame {
+            break;
+        }
+        frame = next;
+    }
+    count
+}
+
+/// アドレスを `関数名+オフセット` で表示する（シンボル表がなければアドレスのみ）
+pub struct Frame(pub u64);
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016X}", self.0)?;
+        // 戻り先は呼び出し命令の次（関数の末尾の呼び出しでは次の関数の先頭）のため、
+        // 1バイト前で検索する
+        match ksyms::lookup(self.0.saturating_sub(1)) {
+            Some(symbol) => write!(f, " {}+0x{:x}", symbol.name, symbol.offset + 1),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! レガシーIDE（ATA PIO）ドライバ
+//!
+//! レガシーIDEコントローラ（プライマリチャネル: I/Oポート0x1F0/0x3F6）上のディスクに対して、
+//! IDENTIFYと28ビットLBAの読み書きを提供します。
+//!
+//! q35のICH9はAHCIのみでレガシーポートを持たないため、`scripts/launch_qemu.sh`は
+//! `piix3-ide`コントローラにディスクイメージを接続して起動します。
+//!
+//! AHCI/NVMe/virtioが揃うまでのフォールバック用のため、転送はすべてポーリングで行い、
+//! デバイス側で割り込み（IRQ14）を無効化（nIEN）します。
+
+use alloc::boxed::Box;
+use alloc::string::String;
+
+use super::{BlockDevice, BlockError, SECTOR_SIZE, check_range};
+use crate::io::{port_read_u8, port_read_u16, port_write_u8, port_write_u16};
+use crate::{info, warn};
+
+/// プライマリチャネルのコマンドブロックI/Oベース
+const PRIMARY_IO_BASE: u16 = 0x1F0;
+/// プライマリチャネルのコントロールブロックI/Oベース
+const PRIMARY_CTRL_BASE: u16 = 0x3F6;
+
+// コマンドブロックレジスタ（I/Oベースからのオフセット）
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+// ステータスレジスタのビット
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+// デバイスコントロールレジスタのビット
+const CTRL_NIEN: u8 = 1 << 1;
+
+// ATAコマンド
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_CACHE_FLUSH: u8 = 0xE7;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+/// 28ビットLBAで表現できる最大セクタ数
+const LBA28_MAX_SECTORS: u64 = 1 << 28;
+
+/// 1コマンドで転送する最大セクタ数（セクタカウントレジスタは8ビット、0は256を意味する）
+const MAX_SECTORS_PER_COMMAND: u64 = 256;
+
+/// ステータスポーリングの最大反復回数
+const POLL_LIMIT: u32 = 1_000_000;
+
+/// ATAチャネル上のドライブ位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DrivePosition {
+    Master,
+    Slave,
+}
+
+impl DrivePosition {
+    /// ドライブ/ヘッドレジスタの選択ビット（LBAモード）
+    fn select_bits(self) -> u8 {
+        match self {
+            DrivePosition::Master => 0xE0,
+            DrivePosition::Slave => 0xF0,
+        }
+    }
+}
+
+/// ATA PIOディスク
+pub struct AtaDisk {
+    name: String,
+    io_base: u16,
+    ctrl_base: u16,
+    position: DrivePosition,
+    sectors: u64,
+}
+
+impl AtaDisk {
+    fn reg(&self, offset: u16) -> u16 {
+        self.io_base + offset
+    }
+
+    /// 代替ステータスレジスタを4回読んで約400nsの待機を行う
+    fn delay_400ns(&self) {
+        for _ in 0..4 {
+            // SAFETY: 代替ステータスレジスタの読み込みは副作用を持たない
+            unsafe {
+                port_read_u8(self.ctrl_base);
+            }
+        }
+    }
+
+    /// BSYが解除されるまで待機
+    fn wait_not_busy(&self) -> Result<u8, BlockError> {
+        for _ in 0..POLL_LIMIT {
+            // SAFETY: ステータスレジスタの読み込み
+            let status = unsafe { port_read_u8(self.reg(REG_STATUS)) };
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+            core::hint::spin_loop();
+        }
+        Err(BlockError::Timeout)
+    }
+
+    /// データ転送準備（DRQ）が整うまで待機
+    fn wait_drq(&self) -> Result<(), BlockError> {
+        for _ in 0..POLL_LIMIT {
+            // SAFETY: ステータスレジスタの読み込み
+            let status = unsafe { port_read_u8(self.reg(REG_STATUS)) };
+            if status & STATUS_BSY != 0 {
+                core::hint::spin_loop();
+                continue;
+            }
+            if status & (STATUS_ERR | STATUS_DF) != 0 {
+                return Err(BlockError::DeviceError);
+            }
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(BlockError::Timeout)
+    }
+
+    /// LBAとセクタ数を設定してコマンドを発行
+    fn issue_lba28(&self, lba: u64, count: u64, command: u8) -> Result<(), BlockError> {
+        self.wait_not_busy()?;
+        // SAFETY: プライマリチャネルのコマンドブロックレジスタへの書き込み
+        unsafe {
+            port_write_u8(
+                self.reg(REG_DRIVE),
+                self.position.select_bits() | ((lba >> 24) & 0x0F) as u8,
+            );
+        }
+        self.delay_400ns();
+        // SAFETY: 同上（セクタ数256は0として書き込む）
+        unsafe {
+            port_write_u8(self.reg(REG_SECTOR_COUNT), count as u8);
+            port_write_u8(self.reg(REG_LBA_LOW), lba as u8);
+            port_write_u8(self.reg(REG_LBA_MID), (lba >> 8) as u8);
+            port_write_u8(self.reg(REG_LBA_HIGH), (lba >> 16) as u8);
+            port_write_u8(self.reg(REG_COMMAND), command);
+        }
+        Ok(())
+    }
+
+    /// IDENTIFY DEVICEでドライブを検出
+    ///
+    /// # Returns
+    /// ATAディスクが存在すればSome。ATAPIデバイスや未接続の場合はNone
+    fn identify(io_base: u16, ctrl_base: u16, position: DrivePosition, name: &str) -> Option<Self> {
+        let mut disk = Self {
+            name: String::from(name),
+            io_base,
+            ctrl_base,
+            position,
+            sectors: 0,
+        };
+
+        // SAFETY: プライマリチャネルのレジスタアクセス
+        let status = unsafe {
+            // ポーリングで動作するため、デバイスからの割り込み（IRQ14）を無効化
+            port_write_u8(ctrl_base, CTRL_NIEN);
+            port_write_u8(disk.reg(REG_DRIVE), position.select_bits() & 0xB0);
+            disk.delay_400ns();
+            port_write_u8(disk.reg(REG_SECTOR_COUNT), 0);
+            port_write_u8(disk.reg(REG_LBA_LOW), 0);
+            port_write_u8(disk.reg(REG_LBA_MID), 0);
+            port_write_u8(disk.reg(REG_LBA_HIGH), 0);
+            port_write_u8(disk.reg(REG_COMMAND), CMD_IDENTIFY);
+            port_read_u8(disk.reg(REG_STATUS))
+        };
+
+        // 0: ドライブなし、0xFF: フローティングバス（コントローラなし）
+        if status == 0 || status == 0xFF {
+            return None;
+        }
+        disk.wait_not_busy().ok()?;
+
+        // LBA Mid/Highが非ゼロならATAPIまたはSATA（このドライバの対象外）
+        // SAFETY: シグネチャレジスタの読み込み
+        let (mid, high) = unsafe {
+            (
+                port_read_u8(disk.reg(REG_LBA_MID)),
+                port_read_u8(disk.reg(REG_LBA_HIGH)),
+            )
+        };
+        if mid != 0 || high != 0 {
+            return None;
+        }
+        disk.wait_drq().ok()?;
+
+        let mut identify = [0u16; 256];
+        for word in identify.iter_mut() {
+            // SAFETY: DRQがセットされているのでデータレジスタから読み込める
+            *word = unsafe { port_read_u16(disk.reg(REG_DATA)) };
+        }
+
+        // ワード60-61: 28ビットLBAでアドレス可能なセクタ数
+        disk.sectors = (identify[60] as u64) | ((identify[61] as u64) << 16);
+        if disk.sectors == 0 {
+            warn!("ATA {}: drive does not support LBA28", name);
+            return None;
+        }
+
+        // ワード27-46: モデル名（各ワードは上位バイトが先）
+        let mut model = [0u8; 40];
+        for (i, word) in identify[27..47].iter().enumerate() {
+            model[i * 2] = (word >> 8) as u8;
+            model[i * 2 + 1] = *word as u8;
+        }
+        let model = core::str::from_utf8(&model).unwrap_or("?").trim();
+        info!(
+            "ATA {}: \"{}\", {} sectors ({} MB)",
+            name,
+            model,
+            disk.sectors,
+            disk.sectors * SECTOR_SIZE as u64 / 1024 / 1024
+        );
+
+        Some(disk)
+    }
+
+    /// 直前のコマンドでエラーが発生していればエラーレジスタを記録
+    fn report_error(&self, op: &str, lba: u64) {
+        // SAFETY: エラーレジスタの読み込み
+        let err = unsafe { port_read_u8(self.reg(REG_ERROR)) };
+        warn!(
+            "ATA {}: {} failed at LBA {} (error=0x{:02X})",
+            self.name, op, lba, err
+        );
+    }
+}
+
+impl BlockDevice for AtaDisk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors.min(LBA28_MAX_SECTORS)
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let total = check_range(self, lba, buf.len())?;
+
+        let mut done = 0;
+        while done < total {
+            let count = (total - done).min(MAX_SECTORS_PER_COMMAND);
+            let cur_lba = lba + done;
+            self.issue_lba28(cur_lba, count, CMD_READ_SECTORS)?;
+
+            for sector in 0..count {
+                self.delay_400ns();
+                if let Err(e) = self.wait_drq() {
+                    self.report_error("read", cur_lba + sector);
+                    return Err(e);
+                }
+                let offset = ((done + sector) as usize) * SECTOR_SIZE;
+                for chunk in buf[offset..offset + SECTOR_SIZE].chunks_exact_mut(2) {
+                    // SAFETY: DRQがセットされているのでデータレジスタから読み込める
+                    let word = unsafe { port_read_u16(self.reg(REG_DATA)) };
+                    chunk.copy_from_slice(&word.to_le_bytes());
+                }
+            }
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        let total = check_range(self, lba, buf.len())?;
+
+        let mut done = 0;
+        while done < total {
+            let count = (total - done).min(MAX_SECTORS_PER_COMMAND);
+            let cur_lba = lba + done;
+            self.issue_lba28(cur_lba, count, CMD_WRITE_SECTORS)?;
+
+            for sector in 0..count {
+                self.delay_400ns();
+                if let Err(e) = self.wait_drq() {
+                    self.report_error("write", cur_lba + sector);
+                    return Err(e);
+                }
+                let offset = ((done + sector) as usize) * SECTOR_SIZE;
+                for chunk in buf[offset..offset + SECTOR_SIZE].chunks_exact(2) {
+                    let word = u16::from_le_bytes([chunk[0], chunk[1]]);
+                    // SAFETY: DRQがセットされているのでデータレジスタに書き込める
+                    unsafe { port_write_u16(self.reg(REG_DATA), word) };
+                }
+            }
+            done += count;
+        }
+
+        // 書き込みキャッシュを確実にディスクへ反映
+        self.flush()
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        self.wait_not_busy()?;
+        // SAFETY: プライマリチャネルのレジスタアクセス
+        unsafe {
+            port_write_u8(self.reg(REG_DRIVE), self.position.select_bits());
+            port_write_u8(self.reg(REG_COMMAND), CMD_CACHE_FLUSH);
+        }
+        self.delay_400ns();
+        let status = self.wait_not_busy()?;
+        if status & (STATUS_ERR | STATUS_DF) != 0 {
+            self.report_error("flush", 0);
+            return Err(BlockError::DeviceError);
+        }
+        Ok(())
+    }
+}
+
+/// プライマリチャネルのマスター/スレーブを検出してブロックデバイスとして登録
+pub fn probe() {
+    let drives = [
+        (DrivePosition::Master, "ata0"),
+        (DrivePosition::Slave, "ata1"),
+    ];
+
+    let mut found = 0;
+    for (position, name) in drives {
+        if let Some(disk) = AtaDisk::identify(PRIMARY_IO_BASE, PRIMARY_CTRL_BASE, position, name) {
+            super::register(Box::new(disk));
+            found += 1;
+        }
+    }
+
+    if found == 0 {
+        info!("ATA: no drives found on primary channel");
+    }
+}
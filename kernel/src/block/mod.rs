@@ -0,0 +1,267 @@
+//! ブロックデバイス層
+//!
+//! ストレージドライバ共通の `BlockDevice` トレイトと、検出したデバイスを
+//! 保持するレジストリを提供します。ファイルシステムはデバイス番号で
+//! デバイスを指定してアクセスします。
+//!
+//! # モジュール構成
+//! - `ata`: レガシーIDE（ATA PIO）ドライバ
+//! - `virtio_blk`: VirtIOブロックデバイスドライバ（PCI）
+//! - `ramdisk`: 物理フレーム上のRAMディスク（テスト用）
+
+pub mod ata;
+pub mod ramdisk;
+pub mod virtio_blk;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::sync::BlockingMutex;
+use crate::worker_pool::{self, JobPriority, WorkerPoolError};
+use crate::{info, warn};
+
+/// 標準的なセクタサイズ（バイト）
+pub const SECTOR_SIZE: usize = 512;
+
+/// ブロックデバイス操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// 指定したデバイス番号のデバイスが存在しない
+    NoDevice,
+    /// ブロック範囲がデバイス容量を超えている
+    OutOfRange,
+    /// バッファ長がブロックサイズの倍数でない
+    InvalidBufferSize,
+    /// デバイスがエラーを報告した
+    DeviceError,
+    /// デバイスが応答しない
+    Timeout,
+}
+
+impl core::fmt::Display for BlockError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            BlockError::NoDevice => write!(f, "No such block device"),
+            BlockError::OutOfRange => write!(f, "Block range is out of device bounds"),
+            BlockError::InvalidBufferSize => {
+                write!(f, "Buffer size is not a multiple of block size")
+            }
+            BlockError::DeviceError => write!(f, "Device reported an error"),
+            BlockError::Timeout => write!(f, "Device did not respond"),
+        }
+    }
+}
+
+/// ブロックデバイスの共通インターフェース
+pub trait BlockDevice: Send {
+    /// デバイス名（"ata0" など）
+    fn name(&self) -> &str;
+
+    /// 1ブロックのサイズ（バイト）
+    fn block_size(&self) -> usize;
+
+    /// ブロック数
+    fn block_count(&self) -> u64;
+
+    /// 連続したブロックを読み込む
+    ///
+    /// # Arguments
+    /// * `lba` - 先頭ブロック番号
+    /// * `buf` - 読み込み先（長さはブロックサイズの倍数）
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// 連続したブロックを書き込む
+    ///
+    /// # Arguments
+    /// * `lba` - 先頭ブロック番号
+    /// * `buf` - 書き込むデータ（長さはブロックサイズの倍数）
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    /// 書き込みキャッシュをデバイスに反映
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(())
+    }
+}
+
+/// 要求されたブロック範囲とバッファ長を検証
+///
+/// # Returns
+/// 転送するブロック数
+///
+/// # Errors
+/// * `BlockError::InvalidBufferSize` - バッファ長がブロックサイズの倍数でない場合
+/// * `BlockError::OutOfRange` - 範囲がデバイス容量を超える場合
+pub fn check_range(dev: &dyn BlockDevice, lba: u64, buf_len: usize) -> Result<u64, BlockError> {
+    let block_size = dev.block_size();
+    if !buf_len.is_multiple_of(block_size) {
+        return Err(BlockError::InvalidBufferSize);
+    }
+    let count = (buf_len / block_size) as u64;
+    let end = lba.checked_add(count).ok_or(BlockError::OutOfRange)?;
+    if end > dev.block_count() {
+        return Err(BlockError::OutOfRange);
+    }
+    Ok(count)
+}
+
+/// 登録済みブロックデバイスの概要
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct BlockDeviceInfo {
+    /// デバイス番号
+    pub index: usize,
+    /// デバイス名
+    pub name: String,
+    /// ブロックサイズ（バイト）
+    pub block_size: usize,
+    /// ブロック数
+    pub block_count: u64,
+}
+
+/// 登録済みブロックデバイス
+///
+/// PIO転送は時間がかかるため、割り込みを止めるスピンロックではなくBlockingMutexで保護する
+static DEVICES: BlockingMutex<Vec<Box<dyn BlockDevice>>> = BlockingMutex::new(Vec::new());
+
+/// ブロックデバイスを登録
+///
+/// # Returns
+/// 割り当てたデバイス番号
+pub fn register(dev: Box<dyn BlockDevice>) -> usize {
+    info!(
+        "Block device registered: {} ({} blocks x {} bytes)",
+        dev.name(),
+        dev.block_count(),
+        dev.block_size()
+    );
+    let mut devices = DEVICES.lock();
+    devices.push(dev);
+    devices.len() - 1
+}
+
+/// 登録済みデバイスの一覧を取得
+#[allow(dead_code)]
+pub fn devices() -> Vec<BlockDeviceInfo> {
+    DEVICES
+        .lock()
+        .iter()
+        .enumerate()
+        .map(|(index, dev)| BlockDeviceInfo {
+            index,
+            name: String::from(dev.name()),
+            block_size: dev.block_size(),
+            block_count: dev.block_count(),
+        })
+        .collect()
+}
+
+/// 指定したデバイスからブロックを読み込む
+///
+/// # Arguments
+/// * `index` - デバイス番号
+/// * `lba` - 先頭ブロック番号
+/// * `buf` - 読み込み先（長さはブロックサイズの倍数）
+///
+/// # Errors
+/// * `BlockError::NoDevice` - デバイスが存在しない場合
+/// * その他 - デバイスドライバが返したエラー
+#[allow(dead_code)]
+pub fn read(index: usize, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+    let mut devices = DEVICES.lock();
+    let dev = devices.get_mut(index).ok_or(BlockError::NoDevice)?;
+    dev.read_blocks(lba, buf)
+}
+
+/// 指定したデバイスを排他的に使用して処理を行う
+///
+/// 複数回の読み書きをまとめて行う処理（フォーマットなど）で使用します。
+///
+/// # Errors
+/// * `BlockError::NoDevice` - デバイスが存在しない場合
+pub fn with_device<R>(
+    index: usize,
+    f: impl FnOnce(&mut dyn BlockDevice) -> R,
+) -> Result<R, BlockError> {
+    let mut devices = DEVICES.lock();
+    let dev = devices.get_mut(index).ok_or(BlockError::NoDevice)?;
+    Ok(f(dev.as_mut()))
+}
+
+/// 指定したデバイスにブロックを書き込む
+///
+/// # Arguments
+/// * `index` - デバイス番号
+/// * `lba` - 先頭ブロック番号
+/// * `buf` - 書き込むデータ（長さはブロックサイズの倍数）
+///
+/// # Errors
+/// * `BlockError::NoDevice` - デバイスが存在しない場合
+/// * その他 - デバイスドライバが返したエラー
+#[allow(dead_code)]
+pub fn write(index: usize, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+    let mut devices = DEVICES.lock();
+    let dev = devices.get_mut(index).ok_or(BlockError::NoDevice)?;
+    dev.write_blocks(lba, buf)
+}
+
+/// 指定したデバイスの書き込みキャッシュを反映
+///
+/// # Errors
+/// * `BlockError::NoDevice` - デバイスが存在しない場合
+/// * その他 - デバイスドライバが返したエラー
+#[allow(dead_code)]
+pub fn flush(index: usize) -> Result<(), BlockError> {
+    let mut devices = DEVICES.lock();
+    let dev = devices.get_mut(index).ok_or(BlockError::NoDevice)?;
+    dev.flush()
+}
+
+/// 全デバイスの書き込みキャッシュを反映
+///
+/// # Returns
+/// フラッシュに失敗したデバイス数
+pub fn flush_all() -> usize {
+    let mut failed = 0;
+    for dev in devices() {
+        if let Err(e) = flush(dev.index) {
+            warn!("Failed to flush {}: {}", dev.name, e);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// 全デバイスの書き戻しをワーカープールに投入する
+///
+/// PIO転送でのフラッシュは時間がかかるため、呼び出し元をブロックせず
+/// 低優先度のジョブとして実行します。
+///
+/// # Errors
+/// `worker_pool::submit` と同じ
+pub fn schedule_writeback() -> Result<(), WorkerPoolError> {
+    worker_pool::submit(
+        JobPriority::Low,
+        Box::new(|| {
+            let failed = flush_all();
+            info!("Block writeback finished ({} failed)", failed);
+        }),
+    )
+}
+
+/// レジストリのロックを指定時間保持する
+///
+/// 障害注入でロック競合を発生させるために使用します。保持中の読み書きはブロックされます。
+pub fn hold_registry_lock(ms: u64) {
+    let _devices = DEVICES.lock();
+    crate::sched::sleep_ms(ms);
+}
+
+/// ブロックデバイス層を初期化し、ドライバのプローブを行う
+///
+/// ヒープ初期化後に呼び出します。
+pub fn init() {
+    ata::probe();
+    virtio_blk::probe();
+}
@@ -0,0 +1,230 @@
+//! RAMディスク
+//!
+//! 物理フレームを並べたメモリ上のブロックデバイスです。実ストレージのドライバが
+//! なくても、ファイルシステムやブロックキャッシュをメモリ上で開発・テストできます。
+//!
+//! 起動時のサイズは設定 `ramdisk.size`（KB、0なら作成しない）で指定し、
+//! initramfsに `SEED_PATH` があればその内容を先頭に書き込みます。
+//! フレームは不連続でよく、ブロックの読み書きはページ単位に分割して直接マッピング経由で行います。
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{BlockDevice, BlockError, SECTOR_SIZE, check_range};
+use crate::paging::{self, PAGE_SIZE};
+use crate::{frame_allocator, fs, info, warn};
+
+/// 起動時のRAMディスクの初期内容（initramfs内のディスクイメージ）
+pub const SEED_PATH: &str = "/initrd/ramdisk.img";
+
+/// RAMディスクの最大サイズ（KB、256MB）
+pub const MAX_SIZE_KB: usize = 256 * 1024;
+
+/// 1ページあたりのブロック数
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / SECTOR_SIZE;
+
+/// 起動時に作成するRAMディスクのサイズ（KB、0なら作成しない）
+static BOOT_SIZE_KB: AtomicUsize = AtomicUsize::new(0);
+
+/// 作成済みのRAMディスクの数（デバイス名の番号）
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// RAMディスク操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamDiskError {
+    /// サイズが0、または `MAX_SIZE_KB` を超えている
+    InvalidSize,
+    /// 初期内容がディスクより大きい
+    SeedTooLarge,
+    /// 物理フレームを確保できない
+    OutOfFrames,
+}
+
+impl core::fmt::Display for RamDiskError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            RamDiskError::InvalidSize => {
+                write!(f, "Size must be between 1 and {} KB", MAX_SIZE_KB)
+            }
+            RamDiskError::SeedTooLarge => write!(f, "Seed image is larger than the disk"),
+            RamDiskError::OutOfFrames => write!(f, "Out of physical frames"),
+        }
+    }
+}
+
+/// RAMディスク
+pub struct RamDisk {
+    /// デバイス名（"ram0" など）
+    name: String,
+    /// 内容を保持する物理フレーム（ページ順）
+    frames: Vec<u64>,
+}
+
+impl RamDisk {
+    /// ゼロで埋めたRAMディスクを作成（ブロックデバイスには登録しない）
+    ///
+    /// # Arguments
+    /// * `name` - デバイス名
+    /// * `size_kb` - サイズ（KB、ページ単位に切り上げ）
+    ///
+    /// # Errors
+    /// * `RamDiskError::InvalidSize` - サイズが0、または `MAX_SIZE_KB` を超える場合
+    /// * `RamDiskError::OutOfFrames` - 物理フレームを確保できない場合
+    pub fn new(name: &str, size_kb: usize) -> Result<Self, RamDiskError> {
+        if size_kb == 0 || size_kb > MAX_SIZE_KB {
+            return Err(RamDiskError::InvalidSize);
+        }
+        let pages = (size_kb * 1024).div_ceil(PAGE_SIZE);
+        let mut disk = Self {
+            name: String::from(name),
+            frames: Vec::new(),
+        };
+        disk.frames
+            .try_reserve_exact(pages)
+            .map_err(|_| RamDiskError::OutOfFrames)?;
+        for _ in 0..pages {
+            // 確保に失敗した場合、確保済みのフレームはdropで解放される
+            let frame = frame_allocator::alloc_frame().ok_or(RamDiskError::OutOfFrames)?;
+            disk.frames.push(frame);
+            disk.page_mut(disk.frames.len() - 1).fill(0);
+        }
+        Ok(disk)
+    }
+
+    /// 先頭から初期内容を書き込む
+    ///
+    /// # Errors
+    /// * `RamDiskError::SeedTooLarge` - 内容がディスクより大きい場合
+    pub fn seed(&mut self, image: &[u8]) -> Result<(), RamDiskError> {
+        if image.len() > self.frames.len() * PAGE_SIZE {
+            return Err(RamDiskError::SeedTooLarge);
+        }
+        for (index, chunk) in image.chunks(PAGE_SIZE).enumerate() {
+            self.page_mut(index)[..chunk.len()].copy_from_slice(chunk);
+        }
+        Ok(())
+    }
+
+    /// ページの内容（直接マッピング経由）
+    fn page(&self, index: usize) -> &[u8] {
+        let virt = paging::phys_to_virt(self.frames[index])
+            .expect("RAM disk frames are in the direct map");
+        // SAFETY: フレームはこのRAMディスクが所有しており、直接マッピングで1ページ分アクセスできる
+        unsafe { core::slice::from_raw_parts(virt as *const u8, PAGE_SIZE) }
+    }
+
+    /// ページの内容（直接マッピング経由、書き込み用）
+    fn page_mut(&mut self, index: usize) -> &mut [u8] {
+        let virt = paging::phys_to_virt(self.frames[index])
+            .expect("RAM disk frames are in the direct map");
+        // SAFETY: フレームはこのRAMディスクが所有しており、&mut selfにより排他的にアクセスできる
+        unsafe { core::slice::from_raw_parts_mut(virt as *mut u8, PAGE_SIZE) }
+    }
+}
+
+impl Drop for RamDisk {
+    fn drop(&mut self) {
+        for &frame in &self.frames {
+            let _ = frame_allocator::free_frame(frame);
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.frames.len() * BLOCKS_PER_PAGE) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_range(self, lba, buf.len())?;
+        let mut offset = lba as usize * SECTOR_SIZE;
+        for chunk in buf.chunks_mut(SECTOR_SIZE) {
+            let page = self.page(offset / PAGE_SIZE);
+            let start = offset % PAGE_SIZE;
+            chunk.copy_from_slice(&page[start..start + SECTOR_SIZE]);
+            offset += SECTOR_SIZE;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_range(self, lba, buf.len())?;
+        let mut offset = lba as usize * SECTOR_SIZE;
+        for chunk in buf.chunks(SECTOR_SIZE) {
+            let start = offset % PAGE_SIZE;
+            self.page_mut(offset / PAGE_SIZE)[start..start + SECTOR_SIZE].copy_from_slice(chunk);
+            offset += SECTOR_SIZE;
+        }
+        Ok(())
+    }
+}
+
+/// 起動時に作成するRAMディスクのサイズ（KB）
+pub fn boot_size_kb() -> usize {
+    BOOT_SIZE_KB.load(Ordering::Relaxed)
+}
+
+/// 起動時に作成するRAMディスクのサイズを設定（次回の起動から有効）
+///
+/// # Errors
+/// * `RamDiskError::InvalidSize` - `MAX_SIZE_KB` を超える場合
+pub fn set_boot_size_kb(size_kb: usize) -> Result<(), RamDiskError> {
+    if size_kb > MAX_SIZE_KB {
+        return Err(RamDiskError::InvalidSize);
+    }
+    BOOT_SIZE_KB.store(size_kb, Ordering::Relaxed);
+    Ok(())
+}
+
+/// RAMディスクを作成してブロックデバイスとして登録
+///
+/// # Arguments
+/// * `size_kb` - サイズ（KB）
+/// * `seed` - 先頭に書き込む初期内容
+///
+/// # Returns
+/// 割り当てたデバイス番号
+///
+/// # Errors
+/// * `RamDiskError::InvalidSize` - サイズが0、または `MAX_SIZE_KB` を超える場合
+/// * `RamDiskError::SeedTooLarge` - 初期内容がディスクより大きい場合
+/// * `RamDiskError::OutOfFrames` - 物理フレームを確保できない場合
+pub fn create(size_kb: usize, seed: Option<&[u8]>) -> Result<usize, RamDiskError> {
+    let name = format!("ram{}", NEXT_ID.load(Ordering::Relaxed));
+    let mut disk = RamDisk::new(&name, size_kb)?;
+    if let Some(image) = seed {
+        disk.seed(image)?;
+    }
+    NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    Ok(super::register(alloc::boxed::Box::new(disk)))
+}
+
+/// 起動時のRAMディスクを作成
+///
+/// initramfsに `SEED_PATH` があれば、サイズはイメージが収まるよう切り上げます。
+pub fn init() {
+    let seed = fs::read_to_vec(SEED_PATH).ok();
+    let seed_kb = seed.as_ref().map_or(0, |image| image.len().div_ceil(1024));
+    let size_kb = boot_size_kb().max(seed_kb);
+    if size_kb == 0 {
+        return;
+    }
+    match create(size_kb, seed.as_deref()) {
+        Ok(index) => info!(
+            "RAM disk created: device {} ({} KB, seeded {} KB)",
+            index, size_kb, seed_kb
+        ),
+        Err(e) => warn!("Failed to create RAM disk: {}", e),
+    }
+}
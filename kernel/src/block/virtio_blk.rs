@@ -0,0 +1,528 @@
+//! VirtIO ブロックデバイスドライバ（レガシーPCIインターフェース）
+//!
+//! QEMUの `-drive if=virtio` で接続されるディスク（1AF4:1001、トランジショナルデバイス）を
+//! BAR0のI/Oポート経由のレガシーインターフェースで操作します。
+//! 仮想キューは1本（requestq）のみ使用し、1要求ずつ発行して完了をポーリングで待ちます。
+//!
+//! 呼び出し元のバッファは物理的に連続している保証がないため、
+//! データはフレームアロケータから確保したバウンスバッファ経由で転送します。
+//!
+//! モダン専用デバイス（1AF4:1042）はPCIケイパビリティ経由のMMIO設定が必要なため未対応です。
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{Ordering, fence};
+
+use super::{BlockDevice, BlockError, SECTOR_SIZE, check_range};
+use crate::frame_allocator;
+use crate::io::{
+    port_read_u8, port_read_u16, port_read_u32, port_write_u8, port_write_u16, port_write_u32,
+};
+use crate::iotrace::{self, Device};
+use crate::paging::{PAGE_SIZE, phys_to_virt};
+use crate::pci::{self, PciDevice};
+use crate::{info, warn};
+
+/// VirtIOのベンダーID
+const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+/// トランジショナルなvirtio-blkのデバイスID
+const VIRTIO_BLK_LEGACY_DEVICE_ID: u16 = 0x1001;
+/// モダン専用のvirtio-blkのデバイスID
+const VIRTIO_BLK_MODERN_DEVICE_ID: u16 = 0x1042;
+
+// レガシーインターフェースのレジスタ（BAR0からのオフセット）
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_ADDRESS: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_DEVICE_STATUS: u16 = 0x12;
+const REG_ISR_STATUS: u16 = 0x13;
+/// デバイス固有の設定領域（MSI-X無効時）
+const REG_DEVICE_CONFIG: u16 = 0x14;
+/// レガシーレジスタ領域のサイズ（virtio-blkの設定領域を含む）
+const REGISTER_SPACE_SIZE: u16 = 0x40;
+
+// デバイスステータスのビット
+const STATUS_ACKNOWLEDGE: u8 = 1 << 0;
+const STATUS_DRIVER: u8 = 1 << 1;
+const STATUS_DRIVER_OK: u8 = 1 << 2;
+const STATUS_FAILED: u8 = 1 << 7;
+
+// virtio-blkの機能ビット
+const FEATURE_RO: u32 = 1 << 5;
+const FEATURE_FLUSH: u32 = 1 << 9;
+
+// 要求タイプ
+const REQUEST_IN: u32 = 0;
+const REQUEST_OUT: u32 = 1;
+const REQUEST_FLUSH: u32 = 4;
+
+// 要求の完了ステータス
+const REQUEST_STATUS_OK: u8 = 0;
+
+// 記述子のフラグ
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+/// レガシーインターフェースの仮想キューのアラインメント（キューアドレスはこの単位で指定）
+const QUEUE_ALIGN: usize = 4096;
+
+/// バウンスバッファのセクタ数（1要求で転送する最大セクタ数）
+const BOUNCE_SECTORS: usize = 128;
+
+/// 完了ポーリングの最大反復回数
+const POLL_LIMIT: u32 = 10_000_000;
+
+/// 仮想キューの記述子
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// virtio-blkの要求ヘッダ
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RequestHeader {
+    request_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// フレームアロケータから確保した、DMAに使用する物理的に連続した領域
+struct DmaRegion {
+    phys_base: u64,
+    virt_base: u64,
+    frame_count: usize,
+}
+
+impl DmaRegion {
+    /// 指定バイト数の領域を確保し、0で初期化
+    fn new(bytes: usize) -> Option<Self> {
+        let frame_count = bytes.div_ceil(PAGE_SIZE);
+        let phys_base = frame_allocator::alloc_contiguous(frame_count)?;
+        let Ok(virt_base) = phys_to_virt(phys_base) else {
+            let _ = frame_allocator::free_contiguous(phys_base, frame_count);
+            return None;
+        };
+        // SAFETY: 確保したフレームは直接マッピングされており、排他的に所有している
+        unsafe { core::ptr::write_bytes(virt_base as *mut u8, 0, frame_count * PAGE_SIZE) };
+        Some(Self {
+            phys_base,
+            virt_base,
+            frame_count,
+        })
+    }
+
+    /// 先頭からのオフセットにあるオブジェクトへのポインタ
+    fn ptr_at<T>(&self, offset: usize) -> *mut T {
+        (self.virt_base + offset as u64) as *mut T
+    }
+
+    /// 先頭からのオフセットの物理アドレス
+    fn phys_at(&self, offset: usize) -> u64 {
+        self.phys_base + offset as u64
+    }
+
+    /// 領域全体のバイトスライス
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: virt_baseはframe_count * PAGE_SIZEバイトの確保済み領域を指している
+        unsafe {
+            core::slice::from_raw_parts_mut(self.virt_base as *mut u8, self.frame_count * PAGE_SIZE)
+        }
+    }
+}
+
+impl Drop for DmaRegion {
+    fn drop(&mut self) {
+        if let Err(e) = frame_allocator::free_contiguous(self.phys_base, self.frame_count) {
+            warn!(
+                "virtio-blk: failed to free DMA frames at 0x{:X}: {}",
+                self.phys_base, e
+            );
+        }
+    }
+}
+
+/// レガシーレイアウトの仮想キュー
+///
+/// 記述子テーブル・Availableリング・（ページ境界に揃えた）Usedリングを
+/// 1つの連続領域に配置します。
+struct Virtqueue {
+    region: DmaRegion,
+    size: u16,
+    avail_offset: usize,
+    used_offset: usize,
+    /// 最後に確認したUsedリングのインデックス
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    /// キューサイズに応じた領域を確保
+    fn new(size: u16) -> Option<Self> {
+        let n = size as usize;
+        let avail_offset = n * core::mem::size_of::<Descriptor>();
+        // Availableリング: flags, idx, ring[n], used_event
+        let used_offset = (avail_offset + 2 * (3 + n)).next_multiple_of(QUEUE_ALIGN);
+        // Usedリング: flags, idx, ring[n] (id: u32, len: u32), avail_event
+        let total = used_offset + (6 + 8 * n).next_multiple_of(QUEUE_ALIGN);
+        Some(Self {
+            region: DmaRegion::new(total)?,
+            size,
+            avail_offset,
+            used_offset,
+            last_used_idx: 0,
+        })
+    }
+
+    /// 記述子を書き込む
+    fn set_descriptor(&mut self, index: u16, desc: Descriptor) {
+        let ptr = self.region.ptr_at::<Descriptor>(0);
+        // SAFETY: index < sizeであり、記述子テーブル内に収まる
+        unsafe { write_volatile(ptr.add(index as usize), desc) };
+    }
+
+    /// 記述子チェーンの先頭をAvailableリングに追加
+    fn push_available(&mut self, head: u16) {
+        let idx_ptr = self.region.ptr_at::<u16>(self.avail_offset + 2);
+        let ring_ptr = self.region.ptr_at::<u16>(self.avail_offset + 4);
+        // SAFETY: Availableリング内のアクセス
+        unsafe {
+            let idx = read_volatile(idx_ptr);
+            write_volatile(ring_ptr.add((idx % self.size) as usize), head);
+            // リングへの書き込みがidxの更新より先にデバイスから見えるようにする
+            fence(Ordering::SeqCst);
+            write_volatile(idx_ptr, idx.wrapping_add(1));
+        }
+    }
+
+    /// Usedリングに新しい完了があれば取り出す
+    fn pop_used(&mut self) -> bool {
+        let idx_ptr = self.region.ptr_at::<u16>(self.used_offset + 2);
+        // SAFETY: Usedリング内のアクセス
+        let used_idx = unsafe { read_volatile(idx_ptr) };
+        if used_idx == self.last_used_idx {
+            return false;
+        }
+        fence(Ordering::SeqCst);
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        true
+    }
+}
+
+/// VirtIOブロックデバイス
+pub struct VirtioBlk {
+    name: String,
+    io_base: u16,
+    sectors: u64,
+    read_only: bool,
+    supports_flush: bool,
+    queue: Virtqueue,
+    /// 要求ヘッダ（オフセット0）と完了ステータス（オフセット16）
+    request: DmaRegion,
+    /// データ転送用のバウンスバッファ
+    bounce: DmaRegion,
+}
+
+impl VirtioBlk {
+    /// 要求ヘッダのオフセット
+    const HEADER_OFFSET: usize = 0;
+    /// 完了ステータスのオフセット
+    const STATUS_OFFSET: usize = core::mem::size_of::<RequestHeader>();
+
+    /// デバイスを初期化
+    ///
+    /// # Returns
+    /// 初期化に成功すればSome。失敗時はデバイスにFAILEDを設定してNone
+    fn init(dev: &PciDevice, name: String) -> Option<Self> {
+        let Some(io_base) = dev.io_bar(0) else {
+            warn!("virtio-blk {}: BAR0 is not an I/O BAR", name);
+            return None;
+        };
+        dev.enable_bus_master();
+        if let Err(e) = iotrace::register_ports(Device::VirtioBlk, io_base, REGISTER_SPACE_SIZE) {
+            warn!("virtio-blk {}: iotrace: {}", name, e);
+        }
+
+        // SAFETY: io_baseはvirtioデバイスのBAR0（レガシーレジスタ）
+        let status = |value: u8| unsafe { port_write_u8(io_base + REG_DEVICE_STATUS, value) };
+        let fail = || {
+            status(STATUS_FAILED);
+            None
+        };
+
+        // リセット → ACKNOWLEDGE → DRIVER
+        status(0);
+        status(STATUS_ACKNOWLEDGE);
+        status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        // SAFETY: 同上
+        let features = unsafe { port_read_u32(io_base + REG_DEVICE_FEATURES) };
+        let accepted = features & (FEATURE_RO | FEATURE_FLUSH);
+        // SAFETY: 同上
+        unsafe { port_write_u32(io_base + REG_GUEST_FEATURES, accepted) };
+
+        // requestq（キュー0）を設定
+        // SAFETY: 同上
+        let queue_size = unsafe {
+            port_write_u16(io_base + REG_QUEUE_SELECT, 0);
+            port_read_u16(io_base + REG_QUEUE_SIZE)
+        };
+        if queue_size < 3 {
+            warn!("virtio-blk {}: queue 0 unavailable", name);
+            return fail();
+        }
+        let Some(queue) = Virtqueue::new(queue_size) else {
+            warn!("virtio-blk {}: failed to allocate virtqueue", name);
+            return fail();
+        };
+        let (Some(request), Some(bounce)) = (
+            DmaRegion::new(PAGE_SIZE),
+            DmaRegion::new(BOUNCE_SECTORS * SECTOR_SIZE),
+        ) else {
+            warn!("virtio-blk {}: failed to allocate DMA buffers", name);
+            return fail();
+        };
+        // SAFETY: 同上（キューアドレスはQUEUE_ALIGN単位のページ番号）
+        unsafe {
+            port_write_u32(
+                io_base + REG_QUEUE_ADDRESS,
+                (queue.region.phys_base / QUEUE_ALIGN as u64) as u32,
+            );
+        }
+
+        // 容量（512バイトセクタ数）はデバイス設定領域の先頭
+        // SAFETY: 同上
+        let sectors = unsafe {
+            let low = port_read_u32(io_base + REG_DEVICE_CONFIG) as u64;
+            let high = port_read_u32(io_base + REG_DEVICE_CONFIG + 4) as u64;
+            (high << 32) | low
+        };
+
+        status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_DRIVER_OK);
+
+        let disk = Self {
+            name,
+            io_base,
+            sectors,
+            read_only: accepted & FEATURE_RO != 0,
+            supports_flush: accepted & FEATURE_FLUSH != 0,
+            queue,
+            request,
+            bounce,
+        };
+        info!(
+            "virtio-blk {}: {} sectors ({} MB), queue size {}{}",
+            disk.name,
+            disk.sectors,
+            disk.sectors * SECTOR_SIZE as u64 / 1024 / 1024,
+            queue_size,
+            if disk.read_only { ", read-only" } else { "" }
+        );
+        Some(disk)
+    }
+
+    /// 要求を1つ発行し、完了をポーリングで待つ
+    ///
+    /// データはバウンスバッファの先頭 `data_len` バイトを使用します。
+    fn submit(
+        &mut self,
+        request_type: u32,
+        sector: u64,
+        data_len: usize,
+    ) -> Result<(), BlockError> {
+        // SAFETY: 要求領域はヘッダとステータスを保持できるサイズ
+        unsafe {
+            write_volatile(
+                self.request.ptr_at::<RequestHeader>(Self::HEADER_OFFSET),
+                RequestHeader {
+                    request_type,
+                    reserved: 0,
+                    sector,
+                },
+            );
+            // デバイスが書き換えなかった場合に成功と誤認しないよう、無効な値で初期化
+            write_volatile(self.request.ptr_at::<u8>(Self::STATUS_OFFSET), 0xFF);
+        }
+
+        // 記述子チェーン: ヘッダ → (データ) → ステータス
+        let status_desc = if data_len > 0 { 2 } else { 1 };
+        self.queue.set_descriptor(
+            0,
+            Descriptor {
+                addr: self.request.phys_at(Self::HEADER_OFFSET),
+                len: core::mem::size_of::<RequestHeader>() as u32,
+                flags: DESC_F_NEXT,
+                next: 1,
+            },
+        );
+        if data_len > 0 {
+            // 読み込み時はデバイスがデータ領域に書き込む
+            let write_flag = if request_type == REQUEST_IN {
+                DESC_F_WRITE
+            } else {
+                0
+            };
+            self.queue.set_descriptor(
+                1,
+                Descriptor {
+                    addr: self.bounce.phys_at(0),
+                    len: data_len as u32,
+                    flags: DESC_F_NEXT | write_flag,
+                    next: 2,
+                },
+            );
+        }
+        self.queue.set_descriptor(
+            status_desc,
+            Descriptor {
+                addr: self.request.phys_at(Self::STATUS_OFFSET),
+                len: 1,
+                flags: DESC_F_WRITE,
+                next: 0,
+            },
+        );
+
+        self.queue.push_available(0);
+        fence(Ordering::SeqCst);
+        // SAFETY: io_baseはvirtioデバイスのBAR0
+        unsafe { port_write_u16(self.io_base + REG_QUEUE_NOTIFY, 0) };
+
+        let mut completed = false;
+        for _ in 0..POLL_LIMIT {
+            if self.queue.pop_used() {
+                completed = true;
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        // 割り込みは使用しないが、ISRステータスは読み込みでクリアしておく
+        // SAFETY: 同上
+        unsafe { port_read_u8(self.io_base + REG_ISR_STATUS) };
+
+        if !completed {
+            warn!(
+                "virtio-blk {}: request timed out at sector {}",
+                self.name, sector
+            );
+            return Err(BlockError::Timeout);
+        }
+        // SAFETY: 同上（完了後なのでデバイスは書き込みを終えている）
+        let status = unsafe { read_volatile(self.request.ptr_at::<u8>(Self::STATUS_OFFSET)) };
+        if status != REQUEST_STATUS_OK {
+            warn!(
+                "virtio-blk {}: request type {} failed at sector {} (status={})",
+                self.name, request_type, sector, status
+            );
+            return Err(BlockError::DeviceError);
+        }
+        Ok(())
+    }
+
+    /// 連続したセクタを読み込む
+    ///
+    /// # Arguments
+    /// * `sector` - 先頭セクタ番号
+    /// * `buf` - 読み込み先（長さはセクタサイズの倍数）
+    ///
+    /// # Errors
+    /// * `BlockError::InvalidBufferSize` / `BlockError::OutOfRange` - 範囲が不正な場合
+    /// * `BlockError::DeviceError` - デバイスがエラーを報告した場合
+    /// * `BlockError::Timeout` - 完了しなかった場合
+    pub fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_range(self, sector, buf.len())?;
+        for (i, chunk) in buf.chunks_mut(BOUNCE_SECTORS * SECTOR_SIZE).enumerate() {
+            let cur = sector + (i * BOUNCE_SECTORS) as u64;
+            self.submit(REQUEST_IN, cur, chunk.len())?;
+            chunk.copy_from_slice(&self.bounce.as_mut_slice()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    /// 連続したセクタを書き込む
+    ///
+    /// # Arguments
+    /// * `sector` - 先頭セクタ番号
+    /// * `buf` - 書き込むデータ（長さはセクタサイズの倍数）
+    ///
+    /// # Errors
+    /// * `BlockError::DeviceError` - 読み取り専用デバイスの場合、またはデバイスがエラーを報告した場合
+    /// * その他 - `read_sectors` と同じ
+    pub fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_range(self, sector, buf.len())?;
+        if self.read_only {
+            return Err(BlockError::DeviceError);
+        }
+        for (i, chunk) in buf.chunks(BOUNCE_SECTORS * SECTOR_SIZE).enumerate() {
+            let cur = sector + (i * BOUNCE_SECTORS) as u64;
+            self.bounce.as_mut_slice()[..chunk.len()].copy_from_slice(chunk);
+            self.submit(REQUEST_OUT, cur, chunk.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.read_sectors(lba, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.write_sectors(lba, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        // FLUSH非対応のデバイスはライトスルーとして扱う
+        if !self.supports_flush {
+            return Ok(());
+        }
+        self.submit(REQUEST_FLUSH, 0, 0)
+    }
+}
+
+/// PCIバス上のvirtio-blkデバイスを検出してブロックデバイスとして登録
+pub fn probe() {
+    let mut found: u8 = 0;
+    pci::for_each_device(|dev| {
+        if dev.vendor_id != VIRTIO_VENDOR_ID {
+            return;
+        }
+        match dev.device_id {
+            VIRTIO_BLK_LEGACY_DEVICE_ID => {
+                let name = format!("vd{}", (b'a' + found) as char);
+                if let Some(disk) = VirtioBlk::init(dev, name) {
+                    super::register(Box::new(disk));
+                    found += 1;
+                }
+            }
+            VIRTIO_BLK_MODERN_DEVICE_ID => warn!(
+                "virtio-blk [{:02X}:{:02X}.{}]: modern-only device is not supported",
+                dev.bus, dev.device, dev.function
+            ),
+            _ => {}
+        }
+    });
+
+    if found == 0 {
+        info!("virtio-blk: no devices found");
+    }
+}
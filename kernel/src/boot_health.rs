@@ -0,0 +1,60 @@
+//! 起動ヘルスレポート
+//!
+//! 起動処理中に選択された構成（タイマーのキャリブレーション元など）を記録し、
+//! 起動完了時にまとめてシリアルに出力します。
+
+use spin::Mutex;
+
+use crate::apic::CalibrationSource;
+
+/// APIC Timerキャリブレーションの結果
+#[derive(Debug, Clone, Copy)]
+pub struct TimerCalibration {
+    /// 採用したタイムソース
+    pub source: CalibrationSource,
+    /// 測定したAPIC Timer周波数（Hz）
+    pub frequency_hz: u32,
+    /// HPETとPITの測定値の乖離（ベーシスポイント）。両方で測定した場合のみ
+    pub divergence_bp: Option<u32>,
+}
+
+/// 起動ヘルスレポートの内容
+#[derive(Debug, Clone, Copy)]
+pub struct BootHealth {
+    /// APIC Timerのキャリブレーション結果（未実施ならNone）
+    pub timer_calibration: Option<TimerCalibration>,
+}
+
+static BOOT_HEALTH: Mutex<BootHealth> = Mutex::new(BootHealth {
+    timer_calibration: None,
+});
+
+/// APIC Timerのキャリブレーション結果を記録
+pub fn record_timer_calibration(calibration: TimerCalibration) {
+    BOOT_HEALTH.lock().timer_calibration = Some(calibration);
+}
+
+/// 現在の起動ヘルスレポートのスナップショットを取得
+pub fn snapshot() -> BootHealth {
+    *BOOT_HEALTH.lock()
+}
+
+/// 起動ヘルスレポートをシリアルに出力
+pub fn report() {
+    let health = snapshot();
+
+    crate::info!("=== Boot Health Report ===");
+    match health.timer_calibration {
+        Some(cal) => {
+            crate::info!(
+                "  Timer calibration: {} ({} Hz)",
+                cal.source,
+                cal.frequency_hz
+            );
+            if let Some(bp) = cal.divergence_bp {
+                crate::info!("  HPET/PIT divergence: {}.{:02}%", bp / 100, bp % 100);
+            }
+        }
+        None => crate::warn!("  Timer calibration: not performed"),
+    }
+}
@@ -0,0 +1,140 @@
+//! 起動画面（スプラッシュ）
+//!
+//! ブートローダーが描いた起動画面を引き継ぎ、初期化の節目（GDT、IDT、ACPI、APIC、ヒープ、
+//! タスク）ごとに進捗バーを伸ばします。見た目と段階の定義はブートローダーと共通
+//! （`vitros_common::boot_progress`）です。
+//!
+//! フレームバッファを使えるようになる前に完了した段階は記録だけしておき、`init` で
+//! 起動画面を描き直すときにまとめて反映します。Compositorが画面を引き継ぐ前に `finish` で
+//! 表示をやめます。ブートローダーが起動画面を表示しなかった場合（`VITROS_BOOT_VERBOSE=1`、
+//! 描画できないピクセルフォーマット）は何も描かず、従来どおりfbconにログを表示します。
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use vitros_common::bmp::Bitmap;
+use vitros_common::boot_info::BootInfo;
+use vitros_common::boot_progress::{self, Layout, Rect, SplashTarget, Stage};
+use vitros_common::cpio::{CpioReader, EntryKind};
+
+use crate::graphics::{Color, draw_rect, pixel_format};
+use crate::sync::IrqSpinlock;
+use crate::{fs, info, warn};
+
+/// 完了した最後の段階
+static STAGE: AtomicU8 = AtomicU8::new(Stage::Bootloader as u8);
+
+/// 表示中の起動画面（`finish` の後はNone）
+static SPLASH: IrqSpinlock<Option<Splash>> = IrqSpinlock::new(None);
+
+/// 起動画面の描画先（フレームバッファ）
+struct Splash {
+    fb_base: u64,
+    width: u32,
+    height: u32,
+    stride: u32,
+    layout: Layout,
+}
+
+impl SplashTarget for Splash {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u32) {
+        // SAFETY: 矩形は画面内（boot_progressが保証）で、initの呼び出し元が
+        // フレームバッファの有効性を保証する
+        unsafe {
+            draw_rect(
+                self.fb_base,
+                self.stride,
+                rect.x as usize,
+                rect.y as usize,
+                rect.width as usize,
+                rect.height as usize,
+                Color::from_rgb_u32(color),
+            )
+        };
+    }
+
+    fn put_pixel(&mut self, x: u32, y: u32, color: u32) {
+        let offset = y as usize * self.stride as usize + x as usize;
+        let native = pixel_format::to_native(Color::from_rgb_u32(color));
+        // SAFETY: 座標は画面内で、フレームバッファは stride × height ピクセル分マップされている
+        unsafe {
+            (self.fb_base as *mut u32)
+                .add(offset)
+                .write_volatile(native)
+        };
+    }
+}
+
+/// 完了した最後の段階を取得
+fn current_stage() -> Stage {
+    Stage::from_u8(STAGE.load(Ordering::Relaxed)).unwrap_or(Stage::Bootloader)
+}
+
+/// initrdからロゴ画像を探す
+fn find_logo(boot_info: &BootInfo) -> Option<Bitmap<'static>> {
+    let archive = fs::initrd_image(boot_info)?;
+    let entry = CpioReader::new(archive)
+        .map_while(Result::ok)
+        .find(|e| e.kind == EntryKind::File && e.name == boot_progress::LOGO_FILE)?;
+    Bitmap::parse(entry.data)
+        .inspect_err(|e| warn!("Boot splash: {}: {}", boot_progress::LOGO_FILE, e))
+        .ok()
+}
+
+/// 起動画面を描き、以降の進捗を画面に反映する
+///
+/// # Returns
+/// 起動画面を表示した場合は`true`。ログを表示する設定の場合は`false`
+///
+/// # Safety
+/// `fb_base` は1行 `stride` ピクセル × `height` 行の有効なフレームバッファで、
+/// `finish` を呼ぶまで他から描画されないこと
+pub unsafe fn init(boot_info: &BootInfo, fb_base: u64) -> bool {
+    if boot_info.verbose {
+        return false;
+    }
+    let fb = &boot_info.framebuffer;
+    let logo = find_logo(boot_info);
+    let mut splash = Splash {
+        fb_base,
+        width: fb.width,
+        height: fb.height,
+        stride: fb.stride,
+        layout: Layout::new(
+            fb.width,
+            fb.height,
+            logo.as_ref().map(|l| (l.width(), l.height())),
+        ),
+    };
+    boot_progress::draw_splash(&mut splash, logo.as_ref(), current_stage());
+    *SPLASH.lock() = Some(splash);
+    info!(
+        "Boot splash shown ({})",
+        if logo.is_some() {
+            "with logo"
+        } else {
+            "no logo"
+        }
+    );
+    true
+}
+
+/// 段階の完了を記録し、起動画面の表示中なら進捗バーを伸ばす
+pub fn advance(stage: Stage) {
+    if STAGE.fetch_max(stage as u8, Ordering::Relaxed) >= stage as u8 {
+        return;
+    }
+    if let Some(splash) = SPLASH.lock().as_mut() {
+        let layout = splash.layout;
+        boot_progress::draw_progress(splash, &layout, stage);
+    }
+}
+
+/// 最後の段階まで進め、表示をやめる（Compositorが画面を所有する前に呼ぶ）
+pub fn finish() {
+    advance(Stage::Tasks);
+    SPLASH.lock().take();
+}
@@ -0,0 +1,200 @@
+//! 高分解能の単調増加クロック
+//!
+//! Invariant TSC（周波数が一定で、省電力状態でも止まらないTSC）が使えれば、起動時に
+//! HPET（なければPIT）で周波数を較正して時刻源にします。TSCはRDTSCの1命令で読めるため、
+//! MMIOを読むHPETより大幅に低コストです。Invariant TSCがなければHPETを、HPETもなければ
+//! タイマーtickを時刻源にします。
+//!
+//! 固定時間のビジーウェイト（`delay_us` / `delay_ms`）もここで提供します。起動直後から使え、
+//! 時刻源が決まるまではHPET（なければPIT）を、TSCの較正後はRDTSCを使います。
+//!
+//! 各CPUのTSCは同期している前提です（Invariant TSCを持つCPUとQEMUでは電源投入時に揃う）。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use crate::{hpet, pit, timer};
+
+/// 較正でTSCを計測する時間（ミリ秒）
+const CALIBRATION_MS: u64 = 10;
+
+/// PITで1回に待機する最大時間（マイクロ秒、16bitカウンタの上限約54.9ms未満）
+const PIT_MAX_DELAY_US: u64 = 50_000;
+
+/// 時刻源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    /// タイマーtick（`timer::frequency_hz` の分解能）
+    Tick = 0,
+    /// HPETのメインカウンタ
+    Hpet = 1,
+    /// Invariant TSC
+    Tsc = 2,
+}
+
+impl ClockSource {
+    /// 表示名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClockSource::Tick => "tick",
+            ClockSource::Hpet => "HPET",
+            ClockSource::Tsc => "TSC",
+        }
+    }
+}
+
+/// 現在の時刻源
+static SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Tick as u8);
+
+/// 較正したTSCの周波数（Hz）
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// TSCの差分をナノ秒に変換する係数（ns = delta * TSC_MULT >> 32）
+static TSC_MULT: AtomicU64 = AtomicU64::new(0);
+
+/// 較正終了時のTSC
+static TSC_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// 較正終了時の単調時刻（ナノ秒）。HPETがあればその経過時間に揃える
+static NS_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// TSCを読み込む
+fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    // SAFETY: RDTSCはx86_64で常に使用でき、Ring 0では制限されない
+    unsafe {
+        asm!(
+            "rdtsc",
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ((high as u64) << 32) | low as u64
+}
+
+/// TSCの周波数を較正（Hz）
+///
+/// HPETが利用できればその経過時間を、できなければPITの待機時間を基準にします。
+fn calibrate_tsc() -> u64 {
+    if hpet::is_available() {
+        let start_ns = hpet::elapsed_ns();
+        let start_tsc = rdtsc();
+        hpet::delay_ms(CALIBRATION_MS);
+        let elapsed_tsc = rdtsc() - start_tsc;
+        let elapsed_ns = hpet::elapsed_ns() - start_ns;
+        (elapsed_tsc as u128 * 1_000_000_000 / elapsed_ns.max(1) as u128) as u64
+    } else {
+        let start_tsc = rdtsc();
+        pit::sleep_ms(CALIBRATION_MS as u32);
+        (rdtsc() - start_tsc) * (1000 / CALIBRATION_MS)
+    }
+}
+
+/// クロックを初期化して時刻源を選択
+///
+/// HPETの初期化後、APの起動前にBSPで呼び出します。較正の間（`CALIBRATION_MS`）待機します。
+pub fn init() {
+    let source = if crate::cpu::features().invariant_tsc {
+        let hz = calibrate_tsc();
+        TSC_HZ.store(hz, Ordering::Relaxed);
+        TSC_MULT.store(
+            ((1_000_000_000u128 << 32) / hz.max(1) as u128) as u64,
+            Ordering::Relaxed,
+        );
+        NS_BASE.store(hpet::elapsed_ns(), Ordering::Relaxed);
+        TSC_BASE.store(rdtsc(), Ordering::Relaxed);
+        ClockSource::Tsc
+    } else if hpet::is_available() {
+        ClockSource::Hpet
+    } else {
+        ClockSource::Tick
+    };
+    SOURCE.store(source as u8, Ordering::Release);
+
+    match source {
+        ClockSource::Tsc => crate::info!(
+            "Clock: invariant TSC at {} MHz",
+            TSC_HZ.load(Ordering::Relaxed) / 1_000_000
+        ),
+        _ => crate::info!("Clock: {} (invariant TSC not available)", source.as_str()),
+    }
+}
+
+/// 現在の時刻源
+pub fn source() -> ClockSource {
+    match SOURCE.load(Ordering::Acquire) {
+        2 => ClockSource::Tsc,
+        1 => ClockSource::Hpet,
+        _ => ClockSource::Tick,
+    }
+}
+
+/// 較正したTSCの周波数（Hz）
+///
+/// # Returns
+/// 時刻源がTSCでない場合はNone
+#[allow(dead_code)]
+pub fn tsc_frequency() -> Option<u64> {
+    (source() == ClockSource::Tsc).then(|| TSC_HZ.load(Ordering::Relaxed))
+}
+
+/// 単調増加する時刻を取得（ナノ秒）
+///
+/// 起点は時刻源により異なるため、差分の計算にのみ使用してください。
+/// 割り込みコンテキストからも呼び出せます。
+pub fn monotonic_ns() -> u64 {
+    match source() {
+        ClockSource::Tsc => {
+            let delta = rdtsc().saturating_sub(TSC_BASE.load(Ordering::Relaxed));
+            let mult = TSC_MULT.load(Ordering::Relaxed);
+            NS_BASE.load(Ordering::Relaxed) + ((delta as u128 * mult as u128) >> 32) as u64
+        }
+        ClockSource::Hpet => hpet::elapsed_ns(),
+        ClockSource::Tick => tick_ns() * timer::current_tick(),
+    }
+}
+
+/// タイマー1tickの長さ（ナノ秒）。タイマーの初期化前は0
+pub fn tick_ns() -> u64 {
+    match timer::frequency_hz() {
+        0 => 0,
+        hz => 1_000_000_000 / hz,
+    }
+}
+
+/// 指定マイクロ秒間待機（ビジーウェイト）
+///
+/// CPUを手放さずに待つため、割り込み無効状態やタイマーの初期化前でも使えます。
+/// 待機の基準は次の順に選びます。
+/// - 時刻源がTSC（`init` の較正後）: RDTSC
+/// - HPETが初期化済み: HPETのメインカウンタ
+/// - それ以外（起動直後）: PITのカウンタのポーリング
+///
+/// スケジューラの動作中に長く待つ場合は `sched::sleep_ms` などを使用してください。
+pub fn delay_us(us: u64) {
+    if source() == ClockSource::Tsc {
+        let hz = TSC_HZ.load(Ordering::Relaxed);
+        let target = (us as u128 * hz as u128 / 1_000_000) as u64;
+        let start = rdtsc();
+        while rdtsc().wrapping_sub(start) < target {
+            core::hint::spin_loop();
+        }
+    } else if hpet::is_available() {
+        hpet::delay_us(us);
+    } else {
+        let mut remaining = us;
+        while remaining > 0 {
+            let chunk = remaining.min(PIT_MAX_DELAY_US);
+            pit::udelay(chunk as u32);
+            remaining -= chunk;
+        }
+    }
+}
+
+/// 指定ミリ秒間待機（ビジーウェイト、`delay_us` を参照）
+pub fn delay_ms(ms: u64) {
+    delay_us(ms * 1_000);
+}
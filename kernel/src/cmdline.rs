@@ -0,0 +1,59 @@
+//! カーネルコマンドライン
+//!
+//! ブートローダーが `BootInfo` で渡したコマンドライン（ESPの `cmdline.txt`、なければ
+//! イメージのロードオプション）を保持し、再コンパイルせずに起動時の動作を変えられるようにします。
+//! 書式は `vitros_common::cmdline` を参照してください。
+//!
+//! # 認識するキー
+//! - `loglevel=<trace|debug|info|warn|error>` - 既定のログレベル
+//! - `timer_hz=<n>` - タイマー割り込みの周波数（既定は250Hz）
+//! - `ktest=<シナリオ名|all>` - 起動後にktestのシナリオを実行
+
+use vitros_common::boot_info::BootInfo;
+use vitros_common::cmdline::Cmdline;
+
+use crate::sync::IrqSpinlock;
+use crate::{info, warn};
+
+/// コマンドライン（BootInfoは起動後も解放されないため借用したまま保持する）
+static CMDLINE: IrqSpinlock<&'static str> = IrqSpinlock::new("");
+
+/// コマンドラインを取り込む（起動の最初に呼ぶ）
+pub fn init(boot_info: &'static BootInfo) {
+    let text = boot_info.cmdline();
+    *CMDLINE.lock() = text;
+    if !text.is_empty() {
+        info!("Command line: {}", text);
+    }
+}
+
+/// コマンドライン全体を取得
+pub fn as_str() -> &'static str {
+    *CMDLINE.lock()
+}
+
+/// キーの値を取得
+///
+/// # Returns
+/// 値。フラグとして指定された場合は空文字列、指定されていない場合はNone
+pub fn get(key: &str) -> Option<&'static str> {
+    Cmdline::new(as_str()).get(key)
+}
+
+/// キーが指定されているか
+#[allow(dead_code)]
+pub fn has(key: &str) -> bool {
+    Cmdline::new(as_str()).has(key)
+}
+
+/// キーの値を数値として取得
+///
+/// # Returns
+/// 指定されていない、または数値として解釈できない場合（警告を出す）はNone
+pub fn number(key: &str) -> Option<u64> {
+    let value = get(key)?;
+    value
+        .parse()
+        .inspect_err(|_| warn!("cmdline: {}={} is not a number, ignoring", key, value))
+        .ok()
+}
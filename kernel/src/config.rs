@@ -0,0 +1,178 @@
+//! カーネル設定レジストリ
+//!
+//! 実行時に変更できる設定をキー（`subsystem.name`）で一覧化し、文字列で読み書きします。
+//! 値の実体は各サブシステムが保持し、ここには読み書き関数のみを登録します。
+//! シェルの `config` コマンドから使用します。
+
+use alloc::string::{String, ToString};
+
+use crate::block::ramdisk;
+use crate::graphics::color::Color;
+use crate::graphics::compositor::{self, PacingSource};
+use crate::graphics::theme::{self, ThemeRole};
+use crate::sched::{self, PolicyKind};
+
+/// 設定操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// 存在しないキー
+    UnknownKey,
+    /// 値の形式が不正、または範囲外
+    InvalidValue,
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ConfigError::UnknownKey => write!(f, "Unknown configuration key"),
+            ConfigError::InvalidValue => write!(f, "Invalid value"),
+        }
+    }
+}
+
+/// 設定項目の定義
+pub struct Setting {
+    /// キー（`subsystem.name`）
+    pub key: &'static str,
+    /// 1行の説明（受け付ける値）
+    pub help: &'static str,
+    /// 現在値を文字列で取得
+    get: fn() -> String,
+    /// 文字列から値を設定
+    set: fn(&str) -> Result<(), ConfigError>,
+}
+
+/// 設定項目の一覧
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "compositor.fps",
+        help: "Target frame rate (30, 60, 120)",
+        get: || compositor::pacing_config().target_fps.to_string(),
+        set: |value| {
+            let fps = value.parse().map_err(|_| ConfigError::InvalidValue)?;
+            compositor::set_target_fps(fps).map_err(|_| ConfigError::InvalidValue)
+        },
+    },
+    Setting {
+        key: "compositor.pacing",
+        help: "Frame pacing source (sleep, deadline, damage, vsync)",
+        get: || compositor::pacing_config().source.as_str().to_string(),
+        set: |value| {
+            let source = PacingSource::from_name(value).ok_or(ConfigError::InvalidValue)?;
+            compositor::set_pacing_source(source);
+            Ok(())
+        },
+    },
+    Setting {
+        key: "compositor.skip",
+        help: "Maximum frames skipped to catch up",
+        get: || compositor::pacing_config().max_frame_skip.to_string(),
+        set: |value| {
+            let skip = value.parse().map_err(|_| ConfigError::InvalidValue)?;
+            compositor::set_max_frame_skip(skip).map_err(|_| ConfigError::InvalidValue)
+        },
+    },
+    Setting {
+        key: "ramdisk.size",
+        help: "RAM disk size in KB created at boot (0 = none)",
+        get: || ramdisk::boot_size_kb().to_string(),
+        set: |value| {
+            let size_kb = value.parse().map_err(|_| ConfigError::InvalidValue)?;
+            ramdisk::set_boot_size_kb(size_kb).map_err(|_| ConfigError::InvalidValue)
+        },
+    },
+    Setting {
+        key: "sched.policy",
+        help: "Normal class scheduling policy (cfs, rr, lottery)",
+        get: || sched::normal_policy_name().to_string(),
+        set: |value| {
+            let kind = PolicyKind::from_name(value).ok_or(ConfigError::InvalidValue)?;
+            sched::set_normal_policy(kind);
+            Ok(())
+        },
+    },
+    Setting {
+        key: "theme",
+        help: "Apply a theme preset (dark, light)",
+        get: current_preset,
+        set: |value| {
+            if theme::apply_preset(value) {
+                Ok(())
+            } else {
+                Err(ConfigError::InvalidValue)
+            }
+        },
+    },
+    Setting {
+        key: "theme.background",
+        help: "Background color (#RRGGBB or name)",
+        get: || theme::color(ThemeRole::Background).to_string(),
+        set: |value| set_theme_color(ThemeRole::Background, value),
+    },
+    Setting {
+        key: "theme.foreground",
+        help: "Text color (#RRGGBB or name)",
+        get: || theme::color(ThemeRole::Foreground).to_string(),
+        set: |value| set_theme_color(ThemeRole::Foreground, value),
+    },
+    Setting {
+        key: "theme.accent",
+        help: "Heading and prompt color (#RRGGBB or name)",
+        get: || theme::color(ThemeRole::Accent).to_string(),
+        set: |value| set_theme_color(ThemeRole::Accent, value),
+    },
+    Setting {
+        key: "theme.error",
+        help: "Error and panic color (#RRGGBB or name)",
+        get: || theme::color(ThemeRole::Error).to_string(),
+        set: |value| set_theme_color(ThemeRole::Error, value),
+    },
+];
+
+/// 現在のテーマが一致するプリセット名（なければ "custom"）
+fn current_preset() -> String {
+    let current = theme::current();
+    theme::PRESETS
+        .iter()
+        .find(|(_, preset)| *preset == current)
+        .map(|(name, _)| (*name).to_string())
+        .unwrap_or_else(|| "custom".to_string())
+}
+
+fn set_theme_color(role: ThemeRole, value: &str) -> Result<(), ConfigError> {
+    let color = Color::parse(value).ok_or(ConfigError::InvalidValue)?;
+    theme::set_color(role, color);
+    Ok(())
+}
+
+fn find(key: &str) -> Result<&'static Setting, ConfigError> {
+    SETTINGS
+        .iter()
+        .find(|setting| setting.key == key)
+        .ok_or(ConfigError::UnknownKey)
+}
+
+/// 設定値を取得
+///
+/// # Errors
+/// * `ConfigError::UnknownKey` - 存在しないキーの場合
+pub fn get(key: &str) -> Result<String, ConfigError> {
+    Ok((find(key)?.get)())
+}
+
+/// 設定値を変更
+///
+/// # Errors
+/// * `ConfigError::UnknownKey` - 存在しないキーの場合
+/// * `ConfigError::InvalidValue` - 値を解釈できない、または範囲外の場合
+pub fn set(key: &str, value: &str) -> Result<(), ConfigError> {
+    (find(key)?.set)(value)
+}
+
+impl Setting {
+    /// 現在値を文字列で取得
+    pub fn value(&self) -> String {
+        (self.get)()
+    }
+}
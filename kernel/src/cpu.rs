@@ -0,0 +1,360 @@
+//! CPUの機能検出（CPUID）
+//!
+//! 起動時にCPUIDを一度だけ実行し、カーネルが参照する機能を `CpuFeatures` に記録します。
+//! 各サブシステムはCPUIDを直接実行せず、`features()` の値で処理を切り替えます。
+//! すべてのCPUが同じ機能を持つ（APもBSPと同じ）ことを前提とします。
+//!
+//! ここで記録するのはCPUが対応しているかどうかだけです。XSAVEで保存する拡張状態
+//! （AVXのYMMレジスタなど）は `init_extended_state` で各CPUに設定し、タスク切り替えは
+//! その設定に従ってxsave/xrstorまたはfxsave/fxrstorを使います。
+
+use core::arch::asm;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::info;
+
+/// CPUIDの基本リーフ: 機能フラグ
+const LEAF_FEATURES: u32 = 0x1;
+/// CPUIDの基本リーフ: 拡張機能フラグ（サブリーフ0）
+const LEAF_EXTENDED_FEATURES: u32 = 0x7;
+/// CPUIDの基本リーフ: XSAVEの状態コンポーネント
+const LEAF_XSAVE: u32 = 0xD;
+/// CPUIDの拡張リーフ: 最大の拡張リーフ番号
+const LEAF_MAX_EXTENDED: u32 = 0x8000_0000;
+/// CPUIDの拡張リーフ: 拡張機能フラグ
+const LEAF_EXT_FEATURES: u32 = 0x8000_0001;
+/// CPUIDの拡張リーフ: 電源管理（Invariant TSC）
+const LEAF_POWER_MANAGEMENT: u32 = 0x8000_0007;
+
+/// CPU機能の検出に関するエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// カーネルの動作に必要な機能がない
+    MissingFeature(&'static str),
+}
+
+impl core::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            CpuError::MissingFeature(name) => write!(f, "CPU does not support {}", name),
+        }
+    }
+}
+
+/// CPUIDで検出したCPUの機能
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuFeatures {
+    /// ベンダー文字列（"GenuineIntel"、"AuthenticAMD"など）
+    pub vendor: [u8; 12],
+    /// 基本リーフの最大番号
+    pub max_leaf: u32,
+    /// 拡張リーフの最大番号
+    pub max_extended_leaf: u32,
+    /// fxsave/fxrstor（タスク切り替えでSIMDレジスタを保存する）
+    pub fxsr: bool,
+    /// Local APIC
+    pub apic: bool,
+    /// x2APIC（MSR経由のLocal APICアクセス）
+    pub x2apic: bool,
+    /// Page Attribute Table
+    pub pat: bool,
+    /// Machine Check Exception
+    pub mce: bool,
+    /// Machine Check Architecture
+    pub mca: bool,
+    /// SSE2
+    pub sse2: bool,
+    /// SSE3
+    pub sse3: bool,
+    /// SSSE3
+    pub ssse3: bool,
+    /// SSE4.1
+    pub sse4_1: bool,
+    /// SSE4.2
+    pub sse4_2: bool,
+    /// AVX
+    pub avx: bool,
+    /// AVX2
+    pub avx2: bool,
+    /// xsave/xrstor
+    pub xsave: bool,
+    /// RDRAND命令
+    pub rdrand: bool,
+    /// RDSEED命令
+    pub rdseed: bool,
+    /// 実行禁止ビット（EFER.NXE）
+    pub nx: bool,
+    /// 1GBページ
+    pub page_1g: bool,
+    /// 周波数が電源状態によらず一定のTSC
+    pub invariant_tsc: bool,
+}
+
+impl CpuFeatures {
+    /// CPUIDを実行して機能を検出
+    fn detect() -> Self {
+        let vendor_leaf = __cpuid(0);
+        let mut vendor = [0u8; 12];
+        vendor[..4].copy_from_slice(&vendor_leaf.ebx.to_le_bytes());
+        vendor[4..8].copy_from_slice(&vendor_leaf.edx.to_le_bytes());
+        vendor[8..].copy_from_slice(&vendor_leaf.ecx.to_le_bytes());
+        let max_leaf = vendor_leaf.eax;
+
+        let basic = __cpuid(LEAF_FEATURES);
+        let bit = |reg: u32, n: u32| reg & (1 << n) != 0;
+        // リーフ7がなければ、その機能はすべて未対応として扱う
+        let extended_ebx = if max_leaf >= LEAF_EXTENDED_FEATURES {
+            __cpuid(LEAF_EXTENDED_FEATURES).ebx
+        } else {
+            0
+        };
+        // 拡張リーフ0x80000001はx86_64では常に存在する
+        let max_extended_leaf = __cpuid(LEAF_MAX_EXTENDED).eax;
+        let ext = __cpuid(LEAF_EXT_FEATURES);
+        let invariant_tsc = max_extended_leaf >= LEAF_POWER_MANAGEMENT
+            && bit(__cpuid(LEAF_POWER_MANAGEMENT).edx, 8);
+
+        Self {
+            vendor,
+            max_leaf,
+            max_extended_leaf,
+            fxsr: bit(basic.edx, 24),
+            apic: bit(basic.edx, 9),
+            x2apic: bit(basic.ecx, 21),
+            pat: bit(basic.edx, 16),
+            mce: bit(basic.edx, 7),
+            mca: bit(basic.edx, 14),
+            sse2: bit(basic.edx, 26),
+            sse3: bit(basic.ecx, 0),
+            ssse3: bit(basic.ecx, 9),
+            sse4_1: bit(basic.ecx, 19),
+            sse4_2: bit(basic.ecx, 20),
+            avx: bit(basic.ecx, 28),
+            avx2: bit(extended_ebx, 5),
+            xsave: bit(basic.ecx, 26),
+            rdrand: bit(basic.ecx, 30),
+            rdseed: bit(extended_ebx, 18),
+            nx: bit(ext.edx, 20),
+            page_1g: bit(ext.edx, 26),
+            invariant_tsc,
+        }
+    }
+
+    /// ベンダー文字列
+    pub fn vendor_str(&self) -> &str {
+        core::str::from_utf8(&self.vendor).unwrap_or("unknown")
+    }
+
+    /// 機能名と対応の有無の一覧（表示用）
+    pub fn flags(&self) -> [(&'static str, bool); 19] {
+        [
+            ("fxsr", self.fxsr),
+            ("apic", self.apic),
+            ("x2apic", self.x2apic),
+            ("pat", self.pat),
+            ("mce", self.mce),
+            ("mca", self.mca),
+            ("sse2", self.sse2),
+            ("sse3", self.sse3),
+            ("ssse3", self.ssse3),
+            ("sse4.1", self.sse4_1),
+            ("sse4.2", self.sse4_2),
+            ("avx", self.avx),
+            ("avx2", self.avx2),
+            ("xsave", self.xsave),
+            ("rdrand", self.rdrand),
+            ("rdseed", self.rdseed),
+            ("nx", self.nx),
+            ("pdpe1gb", self.page_1g),
+            ("invtsc", self.invariant_tsc),
+        ]
+    }
+}
+
+/// 対応している機能名を空白区切りで表示する（ヒープを使わずにログへ出力するため）
+struct SupportedFlags<'a>(&'a CpuFeatures);
+
+impl core::fmt::Display for SupportedFlags<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for (name, supported) in self.0.flags() {
+            if supported {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 検出済みのCPU機能
+static FEATURES: spin::Once<CpuFeatures> = spin::Once::new();
+
+/// CPUの機能（初回の呼び出しで検出する）
+pub fn features() -> &'static CpuFeatures {
+    FEATURES.call_once(CpuFeatures::detect)
+}
+
+/// CPUの機能を検出してログに出力し、カーネルに必要な機能があるか確認
+///
+/// タスク切り替えのfxsave、割り込みのLocal APIC、SIMD転送のSSE2を前提とするため、
+/// これらがなければエラーを返します。起動の最初に一度呼び出します。
+///
+/// # Errors
+/// * `CpuError::MissingFeature` - 必要な機能がない場合
+pub fn init() -> Result<&'static CpuFeatures, CpuError> {
+    let features = features();
+    info!(
+        "CPU: {} (max leaf 0x{:X}, max extended leaf 0x{:X})",
+        features.vendor_str(),
+        features.max_leaf,
+        features.max_extended_leaf
+    );
+    info!("CPU features:{}", SupportedFlags(features));
+
+    let required = [
+        ("fxsr", features.fxsr),
+        ("apic", features.apic),
+        ("sse2", features.sse2),
+    ];
+    for (name, supported) in required {
+        if !supported {
+            return Err(CpuError::MissingFeature(name));
+        }
+    }
+    Ok(features)
+}
+
+/// XCR0: x87 FPUの状態
+const XCR0_X87: u64 = 1 << 0;
+/// XCR0: SSEの状態（XMMレジスタとMXCSR）
+const XCR0_SSE: u64 = 1 << 1;
+/// XCR0: AVXの状態（YMMレジスタの上位128ビット）
+pub const XCR0_AVX: u64 = 1 << 2;
+/// XCR0: AVX-512の状態（オペマスク、ZMM0〜15の上位256ビット、ZMM16〜31）
+/// 3つは同時に有効にする必要がある
+const XCR0_AVX512: u64 = 0b111 << 5;
+
+/// CR4.OSXSAVE（xsave/xrstor、xgetbv/xsetbvとXCR0で有効にした拡張状態を使えるようにする）
+const CR4_OSXSAVE: u64 = 1 << 18;
+
+/// fxsaveの保存領域の大きさ
+const FXSAVE_AREA_SIZE: usize = 512;
+
+/// タスクごとの拡張状態の保存領域の上限（これを超えるCPUではfxsaveを使う）
+pub const MAX_EXTENDED_STATE_SIZE: usize = 4096;
+
+/// タスク切り替えでxsave/xrstorを使うか（falseならfxsave/fxrstor）
+///
+/// `switch_context` がアセンブリから参照する。
+pub(crate) static XSAVE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// XCR0に設定する値（最初に `init_extended_state` を呼んだCPUで決め、全CPUで同じ値を使う）
+static XCR0_VALUE: spin::Once<u64> = spin::Once::new();
+
+/// タスクごとの拡張状態の保存領域の大きさ
+static EXTENDED_STATE_SIZE: AtomicUsize = AtomicUsize::new(FXSAVE_AREA_SIZE);
+
+/// 現在のCPUでXSAVEによる拡張状態の保存を有効にする
+///
+/// XSAVEに対応していれば、CR4.OSXSAVEを立ててXCR0にx87・SSEと、対応していればAVX・AVX-512を
+/// 設定します。対応していない（または保存領域が大きすぎる）場合はCR4.OSXSAVEを下ろし、
+/// ファームウェアが有効にしたAVXをタスク切り替えで壊さないよう、AVXを使えなくします。
+///
+/// タスクを作成する前にBSPで、各APでは起動直後（最初のタスク切り替えより前）に呼び出します。
+pub fn init_extended_state() {
+    let xcr0 = *XCR0_VALUE.call_once(|| {
+        let xcr0 = choose_xcr0();
+        if xcr0 != 0 {
+            // SAFETY: choose_xcr0がXSAVEへの対応と、値がCPUの対応範囲内であることを確認済み
+            unsafe { enable_xsave(xcr0) };
+            let size = __cpuid_count(LEAF_XSAVE, 0).ebx as usize;
+            if size <= MAX_EXTENDED_STATE_SIZE {
+                EXTENDED_STATE_SIZE.store(size, Ordering::Relaxed);
+                XSAVE_ENABLED.store(true, Ordering::Release);
+                info!(
+                    "CPU: task switch saves extended state with xsave (XCR0=0x{:X}, {} bytes)",
+                    xcr0, size
+                );
+                return xcr0;
+            }
+            crate::warn!(
+                "CPU: xsave area too large ({} bytes); falling back to fxsave",
+                size
+            );
+        }
+        info!("CPU: task switch saves extended state with fxsave (AVX disabled)");
+        0
+    });
+
+    if xcr0 != 0 {
+        // SAFETY: 同じ値をBSPで設定済みで、APもBSPと同じ機能を持つ
+        unsafe { enable_xsave(xcr0) };
+    } else {
+        disable_xsave();
+    }
+}
+
+/// XCR0に設定する拡張状態を決める（XSAVEを使わない場合は0）
+fn choose_xcr0() -> u64 {
+    let features = features();
+    if !features.xsave || features.max_leaf < LEAF_XSAVE {
+        return 0;
+    }
+    let leaf = __cpuid_count(LEAF_XSAVE, 0);
+    let supported = (leaf.edx as u64) << 32 | leaf.eax as u64;
+    let mut xcr0 = XCR0_X87 | XCR0_SSE;
+    if features.avx && supported & XCR0_AVX != 0 {
+        xcr0 |= XCR0_AVX;
+        if supported & XCR0_AVX512 == XCR0_AVX512 {
+            xcr0 |= XCR0_AVX512;
+        }
+    }
+    xcr0
+}
+
+/// CR4.OSXSAVEを立ててXCR0を設定
+///
+/// # Safety
+/// CPUがXSAVEに対応し、`xcr0` がCPUID 0xDで報告された範囲内であること
+unsafe fn enable_xsave(xcr0: u64) {
+    // SAFETY: 呼び出し元がXSAVEへの対応と値の妥当性を保証する。
+    // カーネルはSIMDの状態を使わない設定でビルドされているため、XCR0の変更で失われる状態はない
+    unsafe {
+        let cr4: u64;
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        asm!("mov cr4, {}", in(reg) cr4 | CR4_OSXSAVE, options(nostack, preserves_flags));
+        asm!(
+            "xsetbv",
+            in("ecx") 0,
+            in("eax") xcr0 as u32,
+            in("edx") (xcr0 >> 32) as u32,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+/// CR4.OSXSAVEを下ろす（AVXなどXCR0で有効にする拡張状態が使えなくなる）
+fn disable_xsave() {
+    // SAFETY: CR4.OSXSAVEを下ろしても、fxsaveで保存するx87/SSEの状態には影響しない
+    unsafe {
+        let cr4: u64;
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        if cr4 & CR4_OSXSAVE != 0 {
+            asm!("mov cr4, {}", in(reg) cr4 & !CR4_OSXSAVE, options(nostack, preserves_flags));
+        }
+    }
+}
+
+/// タスク切り替えで保存する拡張状態（XCR0の値、fxsaveを使う場合は0）
+pub fn xsave_components() -> u64 {
+    if XSAVE_ENABLED.load(Ordering::Acquire) {
+        XCR0_VALUE.get().copied().unwrap_or(0)
+    } else {
+        0
+    }
+}
+
+/// タスクごとの拡張状態の保存領域の大きさ（バイト）
+pub fn extended_state_size() -> usize {
+    EXTENDED_STATE_SIZE.load(Ordering::Relaxed)
+}
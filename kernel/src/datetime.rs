@@ -0,0 +1,309 @@
+//! 実時間（壁時計）
+//!
+//! 起動時にCMOS RTCから日時を読み取り、以後は単調増加クロック（`clock`）の経過時間で
+//! 進めます。RTCは秒単位の分解能しかなく読み出しも遅いため、起動後は読み直しません。
+//!
+//! RTCの時刻はUTCとして扱います（QEMUの既定）。世紀はFADTのCENTURYフィールドが示す
+//! CMOSレジスタから読み、なければ2000年代とみなします。
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+
+use crate::clock;
+use crate::io::{port_read_u8, port_write_u8, without_interrupts};
+
+/// CMOSのアドレスポート
+const CMOS_ADDRESS: u16 = 0x70;
+/// CMOSのデータポート
+const CMOS_DATA: u16 = 0x71;
+/// アドレスポートのNMI無効化ビット（レジスタ選択時に立てておく）
+const CMOS_NMI_DISABLE: u8 = 0x80;
+
+/// RTCレジスタ
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0A;
+const RTC_STATUS_B: u8 = 0x0B;
+
+/// Status A: 更新中（この間は値が不定）
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Status B: 24時間表記
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Status B: バイナリ表記（0ならBCD）
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// 12時間表記の時レジスタのPMビット
+const HOUR_PM: u8 = 1 << 7;
+
+/// 1日の秒数
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// 読み出しが一致するまでの再試行回数
+const READ_RETRIES: usize = 8;
+
+/// FADTが示す世紀レジスタ（0なら無し）
+static CENTURY_REGISTER: AtomicU8 = AtomicU8::new(0);
+
+/// 初期化済みか
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// 起動時に読んだRTCの時刻（UNIX時間、秒）
+static BASE_UNIX_SECS: AtomicU64 = AtomicU64::new(0);
+
+/// RTCを読んだ時点の単調時刻（ナノ秒）
+static BASE_MONO_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 日時のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeError {
+    /// RTCの値が更新中に変わり続け、一貫した値を読めなかった
+    RtcUnstable,
+    /// RTCの値が日時として不正
+    InvalidRtcValue,
+}
+
+impl fmt::Display for DateTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DateTimeError::RtcUnstable => write!(f, "RTC did not return a stable reading"),
+            DateTimeError::InvalidRtcValue => write!(f, "RTC holds an invalid date"),
+        }
+    }
+}
+
+/// 日時（UTC）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 月（1〜12）
+    pub month: u8,
+    /// 日（1〜31）
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// UNIX時間（1970-01-01 00:00:00 UTCからの秒数）から変換
+    pub fn from_unix(secs: u64) -> Self {
+        let days = secs / SECONDS_PER_DAY;
+        let rem = secs % SECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+
+    /// UNIX時間（秒）に変換
+    pub fn to_unix(self) -> u64 {
+        days_from_civil(self.year, self.month, self.day) * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    /// 各フィールドが範囲内か（1970年以降のみ扱う）
+    fn is_valid(&self) -> bool {
+        self.year >= 1970
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// 1970-01-01からの日数（1970年以降の日付のみ）
+///
+/// 3月始まりの暦で400年周期を数える方法（Howard Hinnantのdays_from_civil）
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let y = year as u64 - if month <= 2 { 1 } else { 0 };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month as u64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as u64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    // 0000-03-01から1970-01-01までの日数
+    era * 146_097 + doe - 719_468
+}
+
+/// 1970-01-01からの日数を年月日に変換（`days_from_civil` の逆）
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as u16;
+    (year, month, day)
+}
+
+/// CMOSレジスタを読む
+fn cmos_read(register: u8) -> u8 {
+    // SAFETY: CMOSのアドレス・データポートはPCで常に存在し、読み出しに副作用はない
+    unsafe {
+        port_write_u8(CMOS_ADDRESS, CMOS_NMI_DISABLE | register);
+        port_read_u8(CMOS_DATA)
+    }
+}
+
+/// 時刻レジスタの生の値（年, 月, 日, 時, 分, 秒, 世紀）
+type RawRtc = [u8; 7];
+
+fn read_raw(century_register: u8) -> RawRtc {
+    while cmos_read(RTC_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+    [
+        cmos_read(RTC_YEAR),
+        cmos_read(RTC_MONTH),
+        cmos_read(RTC_DAY),
+        cmos_read(RTC_HOURS),
+        cmos_read(RTC_MINUTES),
+        cmos_read(RTC_SECONDS),
+        if century_register != 0 {
+            cmos_read(century_register)
+        } else {
+            0
+        },
+    ]
+}
+
+fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// RTCから現在の日時を読み取る
+///
+/// 読み出しの途中で更新が起きると値が混ざるため、2回続けて同じ値が読めるまで繰り返します。
+///
+/// # Errors
+/// * `DateTimeError::RtcUnstable` - 一貫した値を読めなかった場合
+/// * `DateTimeError::InvalidRtcValue` - 値が日時として不正な場合
+pub fn read_rtc() -> Result<DateTime, DateTimeError> {
+    let century_register = CENTURY_REGISTER.load(Ordering::Relaxed);
+    let (raw, status_b) = without_interrupts(|| {
+        let mut last = read_raw(century_register);
+        for _ in 0..READ_RETRIES {
+            let current = read_raw(century_register);
+            if current == last {
+                return Ok((current, cmos_read(RTC_STATUS_B)));
+            }
+            last = current;
+        }
+        Err(DateTimeError::RtcUnstable)
+    })?;
+
+    let [year, month, day, hour, minute, second, century] = raw;
+    let pm = hour & HOUR_PM != 0;
+    let decode = |value: u8| {
+        if status_b & STATUS_B_BINARY != 0 {
+            value
+        } else {
+            bcd_to_binary(value)
+        }
+    };
+    let mut hour = decode(hour & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12時間表記: 12時は0時（AM）または12時（PM）
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    let century = match century {
+        0 => 20,
+        value => decode(value) as u16,
+    };
+
+    let datetime = DateTime {
+        year: century * 100 + decode(year) as u16,
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+    };
+    if !datetime.is_valid() {
+        return Err(DateTimeError::InvalidRtcValue);
+    }
+    Ok(datetime)
+}
+
+/// FADTのCENTURYフィールド（世紀を保持するCMOSレジスタ番号）を設定
+///
+/// ACPIの初期化時、`init` より前に呼び出します。
+pub fn set_century_register(register: u8) {
+    CENTURY_REGISTER.store(register, Ordering::Relaxed);
+}
+
+/// RTCを読み取り、壁時計を初期化
+///
+/// `clock::init` の後に呼び出します。
+pub fn init() {
+    match read_rtc() {
+        Ok(datetime) => {
+            BASE_UNIX_SECS.store(datetime.to_unix(), Ordering::Relaxed);
+            BASE_MONO_NS.store(clock::monotonic_ns(), Ordering::Relaxed);
+            INITIALIZED.store(true, Ordering::Release);
+            crate::info!("Wall clock: {} UTC (from RTC)", datetime);
+        }
+        Err(e) => crate::warn!("Wall clock unavailable: {}", e),
+    }
+}
+
+/// 現在のUNIX時間（秒）
+///
+/// # Returns
+/// RTCを読めていない場合はNone
+pub fn unix_time() -> Option<u64> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return None;
+    }
+    let elapsed_ns = clock::monotonic_ns().saturating_sub(BASE_MONO_NS.load(Ordering::Relaxed));
+    Some(BASE_UNIX_SECS.load(Ordering::Relaxed) + elapsed_ns / 1_000_000_000)
+}
+
+/// 現在の日時（UTC）
+///
+/// 割り込みコンテキストからも呼び出せます。
+///
+/// # Returns
+/// RTCを読めていない場合はNone
+pub fn now() -> Option<DateTime> {
+    unix_time().map(DateTime::from_unix)
+}
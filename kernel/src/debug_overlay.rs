@@ -2,15 +2,15 @@
 //!
 //! 画面右上にFPSやシステム情報を表示するデバッグオーバーレイを提供します。
 
-use crate::graphics::{Region, TaskWriter, compositor};
+use crate::graphics::{Region, TaskWriter, compositor, theme};
 use crate::hpet;
 use core::fmt::Write;
 
 /// オーバーレイの幅（20文字 * 8px）
 const OVERLAY_WIDTH: u32 = 160;
 
-/// オーバーレイの高さ（6行 * 10px）
-const OVERLAY_HEIGHT: u32 = 60;
+/// オーバーレイの高さ（9行 * 10px）
+const OVERLAY_HEIGHT: u32 = 90;
 
 /// 画面端からのマージン
 const MARGIN: u32 = 10;
@@ -33,8 +33,9 @@ pub extern "C" fn debug_overlay_task() -> ! {
         OVERLAY_HEIGHT,
     );
 
-    let buffer = compositor::register_writer(region).expect("Failed to register debug overlay");
-    let mut writer = TaskWriter::new(buffer, 0xFFFFFFFF); // 白色
+    // どのワークスペースでも表示する
+    let buffer = compositor::register_overlay(region).expect("Failed to register debug overlay");
+    let mut writer = TaskWriter::new(buffer, theme::foreground());
 
     // FPS計算用の変数（HPETベース: ミリ秒精度）
     let mut last_time_ms = hpet::elapsed_ms();
@@ -58,11 +59,31 @@ pub extern "C" fn debug_overlay_task() -> ! {
         let uptime_secs = hpet::elapsed_secs();
 
         // 画面をクリアして描画
-        writer.clear(0x00000000); // 黒背景
+        writer.clear_themed();
+        writer.set_color(theme::accent());
         let _ = writeln!(writer, "vitrOS Debug");
         let _ = writeln!(writer, "-----------");
-        let _ = writeln!(writer, "FPS: {}", fps);
+        writer.set_color(theme::foreground());
+        let pacing = compositor::pacing_config();
+        let _ = writeln!(writer, "FPS: {}/{}", fps, pacing.target_fps);
+        let _ = writeln!(writer, "Pacing: {}", pacing.source.as_str());
+        let _ = writeln!(
+            writer,
+            "Skip: {} (max {})",
+            pacing.skipped_frames, pacing.max_frame_skip
+        );
         let _ = writeln!(writer, "Uptime: {}s", uptime_secs);
+        let _ = writeln!(
+            writer,
+            "GFX Mem: {} KB",
+            compositor::memory_usage().bytes / 1024
+        );
+        let _ = writeln!(
+            writer,
+            "Heap: {} KB",
+            crate::allocator::stats().bytes_in_use / 1024
+        );
+        write_pager(&mut writer);
         // ローカルバッファを共有バッファに一括転送
         writer.flush();
 
@@ -74,3 +95,23 @@ pub extern "C" fn debug_overlay_task() -> ! {
         crate::sched::sleep_ms(UPDATE_INTERVAL_MS);
     }
 }
+
+/// ワークスペースのページャーを1行で描画
+///
+/// アクティブなワークスペースを `[n]`、ウィンドウのあるワークスペースを `n*` で表示します。
+fn write_pager(writer: &mut TaskWriter) {
+    let active = compositor::active_workspace();
+    let _ = write!(writer, "WS:");
+    for (workspace, &count) in compositor::workspace_occupancy().iter().enumerate() {
+        if workspace == active {
+            writer.set_color(theme::accent());
+            let _ = write!(writer, "[{}]", workspace + 1);
+            writer.set_color(theme::foreground());
+        } else if count > 0 {
+            let _ = write!(writer, " {}*", workspace + 1);
+        } else {
+            let _ = write!(writer, " {} ", workspace + 1);
+        }
+    }
+    let _ = writeln!(writer);
+}
@@ -0,0 +1,262 @@
+//! カーネルログのビューア（dmesg view）
+//!
+//! シェルの `dmesg view` で、klogのメッセージをテキストコンソールのウィンドウに表示します。
+//! 新しいメッセージは追記され、画面の下端に達するとスクロールします。
+//! 画面の外に出たメッセージは `dmesg scroll` で遡って読めます。
+//!
+//! 表示は専用のカーネルスレッドが行います（`watch` と同じ構成）。スレッドは最初の `start` で
+//! 起動し、`stop` するとウィンドウを閉じて終了します。
+
+use core::fmt::{self, Write};
+
+use crate::graphics::console::TextConsole;
+use crate::graphics::window::Window;
+use crate::graphics::{TaskWriter, compositor, font, theme};
+use crate::klog;
+use crate::log::{Level, Timestamp};
+use crate::sched::{self, kthread};
+use crate::sync::IrqSpinlock;
+
+/// 表示する桁数
+const COLUMNS: usize = 100;
+
+/// 表示する行数
+const ROWS: usize = 30;
+
+/// 画面の外に保持する行数
+const SCROLLBACK: usize = 1000;
+
+/// 新しいメッセージを確認する間隔（ミリ秒）
+const POLL_INTERVAL_MS: u64 = 200;
+
+/// 1回の確認で読む最大件数（確認の間にこれより多く記録されると、古い分は表示されない）
+const POLL_BATCH: usize = 256;
+
+/// ウィンドウの左上（画面左端・上端からのマージン）
+const MARGIN: u32 = 20;
+
+/// dmesg viewのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmesgViewError {
+    /// 表示スレッドを起動できない
+    SpawnFailed,
+}
+
+impl fmt::Display for DmesgViewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DmesgViewError::SpawnFailed => write!(f, "Failed to start the dmesg view thread"),
+        }
+    }
+}
+
+struct ViewState {
+    /// 表示する最低レベル（Noneなら停止）
+    min_level: Option<Level>,
+    /// 内容を変更するたびに増える番号（スレッドが変更を検出する）
+    generation: u64,
+    /// まだ反映していないスクロールの行数（正で遡る）
+    scroll: isize,
+    /// 表示スレッドが動作中か
+    running: bool,
+}
+
+static STATE: IrqSpinlock<ViewState> = IrqSpinlock::new(ViewState {
+    min_level: None,
+    generation: 0,
+    scroll: 0,
+    running: false,
+});
+
+/// 表示を開始（表示中なら表示するレベルを変えて読み直す）
+///
+/// # Errors
+/// * `DmesgViewError::SpawnFailed` - 表示スレッドを起動できない場合
+pub fn start(min_level: Level) -> Result<(), DmesgViewError> {
+    let spawn = {
+        let mut state = STATE.lock();
+        state.min_level = Some(min_level);
+        state.generation += 1;
+        state.scroll = 0;
+        !core::mem::replace(&mut state.running, true)
+    };
+    if spawn && kthread::spawn("DmesgView", view_thread).is_err() {
+        let mut state = STATE.lock();
+        state.min_level = None;
+        state.running = false;
+        return Err(DmesgViewError::SpawnFailed);
+    }
+    Ok(())
+}
+
+/// 表示を停止（次の確認時にウィンドウを閉じる）
+///
+/// # Returns
+/// 表示中だった場合はtrue
+pub fn stop() -> bool {
+    let mut state = STATE.lock();
+    state.generation += 1;
+    state.min_level.take().is_some()
+}
+
+/// スクロールバックを遡る（負の値で新しい方へ戻る）
+///
+/// # Returns
+/// 表示中でなければfalse
+pub fn scroll(lines: isize) -> bool {
+    let mut state = STATE.lock();
+    if state.min_level.is_none() {
+        return false;
+    }
+    state.scroll = state.scroll.saturating_add(lines);
+    true
+}
+
+/// 表示中のウィンドウとコンソール
+struct View {
+    window: Window,
+    writer: TaskWriter,
+    console: TextConsole,
+    min_level: Level,
+    /// 表示した最後のメッセージの連番
+    last_seq: Option<u64>,
+}
+
+impl View {
+    fn create(min_level: Level) -> Option<Self> {
+        // セルの大きさは `font use` で選んだコンソールの書体に従う
+        let typeface = font::console_typeface();
+        let (cell_width, cell_height) = (typeface.cell_width(), typeface.cell_height());
+        let (screen_width, screen_height) = compositor::screen_size();
+        let columns = COLUMNS.min((screen_width.saturating_sub(MARGIN * 2)) as usize / cell_width);
+        let rows = ROWS.min((screen_height.saturating_sub(MARGIN * 3)) as usize / cell_height);
+        let window = Window::create(
+            "dmesg",
+            MARGIN,
+            MARGIN,
+            (columns * cell_width) as u32,
+            (rows * cell_height) as u32,
+            theme::background(),
+        )
+        .ok()?;
+        let mut writer = window.writer(theme::foreground());
+        writer.set_typeface(typeface);
+        let console = TextConsole::new(
+            columns,
+            rows,
+            SCROLLBACK,
+            theme::foreground(),
+            theme::background(),
+        );
+        let mut view = Self {
+            window,
+            writer,
+            console,
+            min_level,
+            last_seq: None,
+        };
+
+        // 保持しているメッセージをすべて表示
+        for entry in klog::snapshot() {
+            view.append(entry.seq, entry.time_ns, entry.level, &entry.text);
+        }
+        Some(view)
+    }
+
+    /// 1件のメッセージを追記
+    fn append(&mut self, seq: u64, time_ns: u64, level: Level, text: &str) {
+        self.last_seq = Some(seq);
+        if level < self.min_level {
+            return;
+        }
+        let _ = writeln!(
+            self.console,
+            "{} {}[{}]\x1b[0m {}",
+            Timestamp(time_ns),
+            level.ansi_color(),
+            level.as_str(),
+            text
+        );
+    }
+
+    /// 前回以降のメッセージを追記
+    fn poll(&mut self) {
+        let last_seq = self.last_seq;
+        let console = &mut self.console;
+        let min_level = self.min_level;
+        let mut newest = last_seq;
+        // 割り当てを避けるため、読み出し中のメッセージを直接コンソールへ書き込む
+        klog::for_each_recent(POLL_BATCH, |entry| {
+            if last_seq.is_some_and(|last| entry.seq <= last) {
+                return;
+            }
+            newest = Some(entry.seq);
+            if entry.level >= min_level {
+                let _ = writeln!(
+                    console,
+                    "{} {}[{}]\x1b[0m {}",
+                    Timestamp(entry.time_ns),
+                    entry.level.ansi_color(),
+                    entry.level.as_str(),
+                    entry.text
+                );
+            }
+        });
+        self.last_seq = newest;
+    }
+
+    fn draw(&mut self, scroll: isize) {
+        if scroll != 0 {
+            self.console.scroll_back(scroll);
+            self.console.mark_all_dirty();
+        }
+        if self.console.take_dirty().is_some() {
+            self.console.render(&mut self.writer);
+        }
+    }
+
+    fn close(self) {
+        let _ = self.window.close();
+    }
+}
+
+/// 表示スレッド
+fn view_thread() {
+    let mut view: Option<View> = None;
+    let mut generation = 0;
+    loop {
+        let (min_level, changed, scroll) = {
+            let mut state = STATE.lock();
+            let Some(min_level) = state.min_level else {
+                state.running = false;
+                break;
+            };
+            let changed = state.generation != generation || view.is_none();
+            generation = state.generation;
+            (min_level, changed, core::mem::take(&mut state.scroll))
+        };
+
+        // レベルが変わったら読み直す
+        if changed {
+            if let Some(old) = view.take() {
+                old.close();
+            }
+            view = View::create(min_level);
+            if view.is_none() {
+                crate::warn!("dmesg view: failed to create the window");
+                stop();
+                continue;
+            }
+        }
+        if let Some(view) = view.as_mut() {
+            view.poll();
+            view.draw(scroll);
+        }
+        sched::sleep_ms(POLL_INTERVAL_MS);
+    }
+
+    if let Some(view) = view {
+        view.close();
+    }
+}
@@ -0,0 +1,375 @@
+//! ユーザープログラムのELFローダー
+//!
+//! VFS（initramfsなど）上のELF64実行ファイルを読み込み、専用のアドレス空間に
+//! PT_LOADセグメントをマップしてユーザータスク（Ring 3）として起動します。
+//!
+//! - セグメントの権限はプログラムヘッダのフラグに従います。書き込み可能かつ実行可能な
+//!   セグメント（W^X違反）は拒否し、実行可能でないページにはNXを設定します。
+//! - ユーザースタックはユーザー空間の上端付近に確保します。最初の64KBだけをマップし、
+//!   それより下へのアクセスはページフォルトで1MBまで伸長します。その直下の1ページはガードとして
+//!   常にマップしません。初期スタックはSystem V ABIに従い、argc = 0、argv/envp/auxvは空です。
+//! - プログラムは `int 0x80` のシステムコール（`syscall` モジュール）でカーネルを呼び出します。
+//!
+//! 読み込んだイメージはタスクが保持するアドレス空間が所有し、タスクの回収時に解放されます。
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use vitros_common::elf::{
+    ELF_DATA_LSB, EM_X86_64, ET_EXEC, Elf64Header, Elf64ProgramHeader, PF_W, PF_X, PT_LOAD,
+};
+
+use crate::fs::{self, FsError};
+use crate::gdt::selector;
+use crate::paging::{self, AddressSpace, PAGE_SIZE, PageTableFlags, PagingError};
+use crate::sched::{self, Task, TaskId, UserProgram, nice};
+
+/// ユーザースタックの上端（この値は含まない）
+const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_0000;
+
+/// 起動時にマップするユーザースタックのページ数（64KB）
+const USER_STACK_PAGES: u64 = 16;
+
+/// ユーザースタックの最大ページ数（1MB、これを超えるとガードページでフォルトする）
+const USER_STACK_MAX_PAGES: u64 = 256;
+
+/// 起動時にマップするユーザースタックの下端
+const USER_STACK_BOTTOM: u64 = USER_STACK_TOP - USER_STACK_PAGES * PAGE_SIZE as u64;
+
+/// ユーザースタックが伸長できる下限
+const USER_STACK_LIMIT: u64 = USER_STACK_TOP - USER_STACK_MAX_PAGES * PAGE_SIZE as u64;
+
+/// 初期スタックに置くワード数（argc, argv終端, envp終端, AT_NULL）
+///
+/// スタックのページはゼロクリア済みのため、値を書き込む必要はありません。
+/// RSPがargcを指し、16バイト境界に揃うよう偶数にしています。
+const INITIAL_STACK_WORDS: u64 = 6;
+
+/// セグメントを置けるユーザー空間の下限（NULLページはマップしない）
+const USER_IMAGE_START: u64 = PAGE_SIZE as u64;
+
+/// プログラム起動時のRFLAGS（割り込み有効）
+const USER_RFLAGS: u64 = 0x202;
+
+/// ELFの読み込みエラー
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// ファイルを読み込めない
+    Fs(FsError),
+    /// ELF64・リトルエンディアン・x86_64の実行ファイルではない
+    InvalidHeader,
+    /// セグメントがファイルやユーザー空間の範囲外、または他のセグメントと重なる
+    InvalidSegment,
+    /// 書き込み可能かつ実行可能なセグメント
+    WritableAndExecutable,
+    /// エントリポイントが実行可能なセグメント内にない
+    InvalidEntry,
+    /// ページテーブルやフレームを確保できない
+    OutOfMemory,
+    /// タスクの作成に失敗
+    TaskCreationFailed,
+}
+
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            LoadError::Fs(e) => write!(f, "{}", e),
+            LoadError::InvalidHeader => write!(f, "Not an x86_64 ELF64 executable"),
+            LoadError::InvalidSegment => write!(f, "Invalid program segment"),
+            LoadError::WritableAndExecutable => {
+                write!(f, "Segment is both writable and executable")
+            }
+            LoadError::InvalidEntry => write!(f, "Entry point is not in an executable segment"),
+            LoadError::OutOfMemory => write!(f, "Out of memory"),
+            LoadError::TaskCreationFailed => write!(f, "Failed to create task"),
+        }
+    }
+}
+
+impl From<FsError> for LoadError {
+    fn from(e: FsError) -> Self {
+        LoadError::Fs(e)
+    }
+}
+
+impl From<PagingError> for LoadError {
+    fn from(e: PagingError) -> Self {
+        match e {
+            // 既にマップ済み = 他のセグメントとページを共有している
+            PagingError::AlreadyMapped | PagingError::InvalidAddress => LoadError::InvalidSegment,
+            _ => LoadError::OutOfMemory,
+        }
+    }
+}
+
+/// ユーザースタックの伸長で割り当てるアドレスか（起動時にマップした範囲より下、上限まで）
+pub fn is_stack_growth(addr: u64) -> bool {
+    (USER_STACK_LIMIT..USER_STACK_BOTTOM).contains(&addr)
+}
+
+/// ユーザースタックの下のガードページ（伸長の上限を超えたアクセスはここでフォルトする）
+pub fn stack_guard_page() -> u64 {
+    USER_STACK_LIMIT - PAGE_SIZE as u64
+}
+
+/// バイト列から構造体を読み出す（範囲外ならNone）
+fn read_struct<T: Copy>(data: &[u8], offset: u64) -> Option<T> {
+    let offset = usize::try_from(offset).ok()?;
+    let end = offset.checked_add(core::mem::size_of::<T>())?;
+    let bytes = data.get(offset..end)?;
+    // SAFETY: 範囲はスライス内に収まっており、read_unalignedはアライメントを要求しない。
+    // Tはすべてのビットパターンが有効な整数のみからなるELFの構造体。
+    Some(unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// ELFヘッダを検証
+fn parse_header(data: &[u8]) -> Result<Elf64Header, LoadError> {
+    let header: Elf64Header = read_struct(data, 0).ok_or(LoadError::InvalidHeader)?;
+    if !header.is_valid()
+        || header.e_ident[5] != ELF_DATA_LSB
+        || header.e_type != ET_EXEC
+        || header.e_machine != EM_X86_64
+        || header.e_phentsize as usize != core::mem::size_of::<Elf64ProgramHeader>()
+    {
+        return Err(LoadError::InvalidHeader);
+    }
+    Ok(header)
+}
+
+/// PT_LOADセグメントを列挙
+fn load_segments(data: &[u8], header: &Elf64Header) -> Result<Vec<Elf64ProgramHeader>, LoadError> {
+    let entry_size = core::mem::size_of::<Elf64ProgramHeader>() as u64;
+    (0..header.e_phnum as u64)
+        .map(|i| {
+            let offset = header
+                .e_phoff
+                .checked_add(i * entry_size)
+                .ok_or(LoadError::InvalidHeader)?;
+            read_struct::<Elf64ProgramHeader>(data, offset).ok_or(LoadError::InvalidHeader)
+        })
+        .filter(|ph| !matches!(ph, Ok(ph) if ph.p_type != PT_LOAD || ph.p_memsz == 0))
+        .collect()
+}
+
+/// セグメントの範囲と権限を検証
+fn validate_segment(data: &[u8], ph: &Elf64ProgramHeader) -> Result<(), LoadError> {
+    if ph.p_flags & PF_W != 0 && ph.p_flags & PF_X != 0 {
+        return Err(LoadError::WritableAndExecutable);
+    }
+    let file_end = ph
+        .p_offset
+        .checked_add(ph.p_filesz)
+        .ok_or(LoadError::InvalidSegment)?;
+    let mem_end = ph
+        .p_vaddr
+        .checked_add(ph.p_memsz)
+        .ok_or(LoadError::InvalidSegment)?;
+    // 伸長したスタックとの間にガードページを1枚残す
+    let image_end = USER_STACK_LIMIT - PAGE_SIZE as u64;
+    if ph.p_filesz > ph.p_memsz
+        || file_end > data.len() as u64
+        || ph.p_vaddr < USER_IMAGE_START
+        || mem_end > image_end
+    {
+        return Err(LoadError::InvalidSegment);
+    }
+    Ok(())
+}
+
+/// セグメントをアドレス空間にマップし、ファイルの内容をコピー
+///
+/// p_fileszを超える部分（.bss）はゼロのまま残します。
+fn map_segment(
+    address_space: &mut AddressSpace,
+    data: &[u8],
+    ph: &Elf64ProgramHeader,
+    no_execute: u64,
+) -> Result<(), LoadError> {
+    let page_size = PAGE_SIZE as u64;
+    let mut flags = 0;
+    if ph.p_flags & PF_W != 0 {
+        flags |= PageTableFlags::Writable as u64;
+    }
+    if ph.p_flags & PF_X == 0 {
+        flags |= no_execute;
+    }
+
+    let file_start = ph.p_vaddr;
+    let file_end = ph.p_vaddr + ph.p_filesz;
+    let mut page = ph.p_vaddr & !(page_size - 1);
+    let end = ph.p_vaddr + ph.p_memsz;
+    while page < end {
+        let frame = address_space.map_new_page(page, flags)?;
+
+        // このページに含まれるファイル内容の範囲
+        let copy_start = page.max(file_start);
+        let copy_end = (page + page_size).min(file_end);
+        if copy_start < copy_end {
+            let src = (ph.p_offset + (copy_start - file_start)) as usize;
+            let len = (copy_end - copy_start) as usize;
+            // SAFETY: frameはmap_new_pageが返した直接マッピング上のフレームで、
+            // 書き込み範囲はページ内に収まる。srcの範囲はvalidate_segmentで検証済み。
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data[src..src + len].as_ptr(),
+                    (frame + (copy_start - page)) as *mut u8,
+                    len,
+                );
+            }
+        }
+        page += page_size;
+    }
+    Ok(())
+}
+
+/// ELFイメージを新しいアドレス空間に読み込む
+///
+/// # Arguments
+/// * `data` - ELFファイルの内容
+///
+/// # Returns
+/// アドレス空間、エントリポイント、初期RSPをまとめたユーザープログラム
+///
+/// # Errors
+/// ヘッダやセグメントが不正な場合、またはメモリが不足した場合
+pub fn load(data: &[u8]) -> Result<UserProgram, LoadError> {
+    let header = parse_header(data)?;
+    let segments = load_segments(data, &header)?;
+    for ph in &segments {
+        validate_segment(data, ph)?;
+    }
+    let entry_is_executable = segments.iter().any(|ph| {
+        ph.p_flags & PF_X != 0
+            && header.e_entry >= ph.p_vaddr
+            && header.e_entry < ph.p_vaddr + ph.p_memsz
+    });
+    if !entry_is_executable {
+        return Err(LoadError::InvalidEntry);
+    }
+
+    // 途中で失敗した場合、マップ済みのフレームはアドレス空間のDropで解放される
+    let no_execute = paging::no_execute_flag();
+    let mut address_space = AddressSpace::new()?;
+    for ph in &segments {
+        map_segment(&mut address_space, data, ph, no_execute)?;
+    }
+
+    let writable_data = PageTableFlags::Writable as u64 | no_execute;
+    for i in 0..USER_STACK_PAGES {
+        address_space.map_new_page(USER_STACK_BOTTOM + i * PAGE_SIZE as u64, writable_data)?;
+    }
+
+    Ok(UserProgram {
+        address_space,
+        entry: header.e_entry,
+        stack_pointer: USER_STACK_TOP - INITIAL_STACK_WORDS * 8,
+    })
+}
+
+/// タスク名（タスクは `&'static str` の名前を持つため、プログラムごとに1度だけ確保する）
+static TASK_NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// パスの最後の要素をタスク名として取得
+fn task_name(path: &str) -> &'static str {
+    let name = path.rsplit('/').find(|c| !c.is_empty()).unwrap_or(path);
+    let mut names = TASK_NAMES.lock();
+    if let Some(interned) = names.iter().find(|n| **n == name) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(String::from(name).into_boxed_str());
+    names.push(interned);
+    interned
+}
+
+/// VFS上のELF実行ファイルをユーザータスクとして起動
+///
+/// # Arguments
+/// * `path` - 実行ファイルのパス（例: `/initrd/bin/hello`）
+///
+/// # Returns
+/// 作成したタスクのID
+///
+/// # Errors
+/// ファイルの読み込み、ELFの検証、メモリ確保、タスクの作成に失敗した場合
+pub fn spawn(path: &str) -> Result<TaskId, LoadError> {
+    let data = fs::read_to_vec(path)?;
+    spawn_image(task_name(path), &data)
+}
+
+/// メモリ上のELFイメージをユーザータスクとして起動
+///
+/// # Errors
+/// `spawn` と同じ（ファイルの読み込みを除く）
+pub fn spawn_image(name: &'static str, data: &[u8]) -> Result<TaskId, LoadError> {
+    let program = load(data)?;
+    let entry = program.entry;
+    let task = Task::new_user(name, nice::DEFAULT, program, user_task_entry)
+        .map_err(|_| LoadError::TaskCreationFailed)?;
+    let id = task.id();
+    crate::info!(
+        "ELF: started {} (task {}) at 0x{:X}",
+        name,
+        id.as_u64(),
+        entry
+    );
+    sched::add_task(task);
+    Ok(id)
+}
+
+/// ユーザータスクのエントリポイント（カーネル側のトランポリン）
+///
+/// スケジューラがタスクのアドレス空間とカーネルスタック（TSS.RSP0）を
+/// 設定した状態で呼ばれ、iretqでRing 3のエントリポイントへ移行します。
+extern "C" fn user_task_entry() -> ! {
+    let Some((entry, stack_pointer)) = sched::current_user_entry() else {
+        crate::error!("ELF: user task has no program");
+        sched::exit();
+    };
+    enter_user_mode(entry, stack_pointer)
+}
+
+/// Ring 3へ移行する
+///
+/// カーネルの値が漏れないよう、汎用レジスタはすべてゼロにしてから移行します。
+fn enter_user_mode(entry: u64, stack_pointer: u64) -> ! {
+    // SAFETY: iretq用のフレーム（SS, RSP, RFLAGS, CS, RIP）を積んでRing 3へ移行する。
+    // セレクタはGDTのユーザーセグメント（RPL=3）で、entryとstack_pointerは
+    // 現在のアドレス空間にユーザー権限でマップされている。このタスクのカーネルスタックは
+    // 以降Ring 3からの割り込みでのみ使用されるため、戻らなくてもよい。
+    unsafe {
+        core::arch::asm!(
+            "push rax", // SS
+            "push rdi", // RSP
+            "push rsi", // RFLAGS
+            "push rdx", // CS
+            "push rcx", // RIP
+            "xor eax, eax",
+            "xor ebx, ebx",
+            "xor ecx, ecx",
+            "xor edx, edx",
+            "xor esi, esi",
+            "xor edi, edi",
+            "xor ebp, ebp",
+            "xor r8d, r8d",
+            "xor r9d, r9d",
+            "xor r10d, r10d",
+            "xor r11d, r11d",
+            "xor r12d, r12d",
+            "xor r13d, r13d",
+            "xor r14d, r14d",
+            "xor r15d, r15d",
+            // カーネルのGSベースを退避し、ユーザーモードのGSベースに切り替える（percpu参照）
+            "swapgs",
+            "iretq",
+            in("rax") selector::USER_DATA as u64,
+            in("rdi") stack_pointer,
+            in("rsi") USER_RFLAGS,
+            in("rdx") selector::USER_CODE as u64,
+            in("rcx") entry,
+            options(noreturn)
+        );
+    }
+}
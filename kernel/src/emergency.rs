@@ -0,0 +1,174 @@
+//! 緊急用メモリ予約（パニック・例外処理用）
+//!
+//! 例外ハンドラやパニック処理は、ヒープが枯渇・破損しているまさにその時に、文字列の
+//! 整形などで小さな割り当てを必要とすることがあります。そのためヒープとは別の小さな
+//! 領域をカーネルイメージ内に確保しておき、CPUが緊急状態（`in_emergency`）の間は
+//! グローバルアロケータがまずこの領域から割り当てます。
+//!
+//! # 設計
+//! - 緊急状態はCPUごと（他のCPUで通常動作中のタスクの割り当ては予約を消費しない）
+//! - 予約はバンプ方式で、未解放の割り当てが0になった時点で先頭に巻き戻す
+//! - オフセットと未解放数を1つのアトミック変数にまとめ、ロックなしで割り当てる
+//!   （パニックした処理がどのロックを保持していても使える）
+//! - ヒープを触らないため、ヒープのメタデータが壊れていても割り当てられる
+
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::percpu;
+use crate::smp::MAX_CPUS;
+
+/// 予約領域のサイズ
+pub const RESERVE_SIZE: usize = 64 * 1024;
+
+/// 予約領域（ページ境界に配置）
+#[repr(C, align(4096))]
+struct Reserve(UnsafeCell<[u8; RESERVE_SIZE]>);
+
+// SAFETY: 領域の各部分は `STATE` のCASで排他的に割り当てられ、同時に同じ部分を
+// 複数の割り当てに渡すことはない
+unsafe impl Sync for Reserve {}
+
+static RESERVE: Reserve = Reserve(UnsafeCell::new([0; RESERVE_SIZE]));
+
+/// 割り当て状態（上位32ビット: 未解放の割り当て数、下位32ビット: 次の空きオフセット）
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// CPUごとの緊急状態のネスト数
+static DEPTH: [AtomicUsize; MAX_CPUS] = [const { AtomicUsize::new(0) }; MAX_CPUS];
+
+/// 使用量の最大値（バイト）
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// 予約から割り当てた回数
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// 予約が足りず割り当てられなかった回数
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+const COUNT_SHIFT: u32 = 32;
+const OFFSET_MASK: u64 = (1 << COUNT_SHIFT) - 1;
+
+/// 緊急用予約の統計情報
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct EmergencyStats {
+    /// 使用中のバイト数（巻き戻すまでは解放済みの分も含む）
+    pub used: usize,
+    /// 使用量の最大値
+    pub peak: usize,
+    /// 予約領域のサイズ
+    pub capacity: usize,
+    /// 未解放の割り当て数
+    pub outstanding: u64,
+    /// 予約から割り当てた回数
+    pub allocations: u64,
+    /// 予約が足りず割り当てられなかった回数
+    pub failures: u64,
+}
+
+/// 緊急状態を抜けるガード（スコープを抜けると抜ける）
+pub struct EmergencyGuard {
+    cpu: usize,
+}
+
+impl Drop for EmergencyGuard {
+    fn drop(&mut self) {
+        DEPTH[self.cpu].fetch_sub(1, Ordering::Release);
+    }
+}
+
+fn base() -> usize {
+    RESERVE.0.get() as usize
+}
+
+/// 現在のCPUを緊急状態にする
+///
+/// 返されたガードを破棄するまで、このCPUでの割り当ては予約から行われます。
+/// 割り込み無効状態（例外ハンドラ内など）で使い、ガードを保持したまま別のCPUへ
+/// 移動しないようにしてください。
+pub fn enter() -> EmergencyGuard {
+    let cpu = percpu::current_index();
+    DEPTH[cpu].fetch_add(1, Ordering::Acquire);
+    EmergencyGuard { cpu }
+}
+
+/// 現在のCPUを以後ずっと緊急状態にする（パニック処理用）
+pub fn enter_permanently() {
+    core::mem::forget(enter());
+}
+
+/// 現在のCPUが緊急状態か
+pub fn in_emergency() -> bool {
+    DEPTH[percpu::current_index()].load(Ordering::Acquire) > 0
+}
+
+/// 指定したアドレスが予約領域内か
+pub fn owns(ptr: *mut u8) -> bool {
+    let addr = ptr as usize;
+    addr >= base() && addr < base() + RESERVE_SIZE
+}
+
+/// 予約から割り当てる（アロケータから呼ばれる）
+///
+/// # Returns
+/// 予約が足りない場合はnull
+pub fn allocate(layout: Layout) -> *mut u8 {
+    let mut state = STATE.load(Ordering::Relaxed);
+    loop {
+        let offset = (state & OFFSET_MASK) as usize;
+        let start = (base() + offset).next_multiple_of(layout.align()) - base();
+        let end = start.saturating_add(layout.size().max(1));
+        if end > RESERVE_SIZE {
+            FAILURES.fetch_add(1, Ordering::Relaxed);
+            return null_mut();
+        }
+
+        let count = state >> COUNT_SHIFT;
+        let new = ((count + 1) << COUNT_SHIFT) | end as u64;
+        match STATE.compare_exchange_weak(state, new, Ordering::AcqRel, Ordering::Relaxed) {
+            Ok(_) => {
+                PEAK.fetch_max(end, Ordering::Relaxed);
+                ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+                return (base() + start) as *mut u8;
+            }
+            Err(current) => state = current,
+        }
+    }
+}
+
+/// 予約の割り当てを解放（アロケータから呼ばれる）
+///
+/// 未解放の割り当てが0になった時点で、予約全体を再び使えるようにします。
+pub fn deallocate(_ptr: *mut u8) {
+    let mut state = STATE.load(Ordering::Relaxed);
+    loop {
+        let count = (state >> COUNT_SHIFT).saturating_sub(1);
+        let new = if count == 0 {
+            0
+        } else {
+            (count << COUNT_SHIFT) | (state & OFFSET_MASK)
+        };
+        match STATE.compare_exchange_weak(state, new, Ordering::AcqRel, Ordering::Relaxed) {
+            Ok(_) => return,
+            Err(current) => state = current,
+        }
+    }
+}
+
+/// 統計情報を取得
+///
+/// ロックを使わないため、パニック時にも呼び出せます。
+pub fn stats() -> EmergencyStats {
+    let state = STATE.load(Ordering::Relaxed);
+    EmergencyStats {
+        used: (state & OFFSET_MASK) as usize,
+        peak: PEAK.load(Ordering::Relaxed),
+        capacity: RESERVE_SIZE,
+        outstanding: state >> COUNT_SHIFT,
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        failures: FAILURES.load(Ordering::Relaxed),
+    }
+}
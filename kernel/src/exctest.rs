@@ -0,0 +1,221 @@
+//! CPU例外のテスト（exctest）
+//!
+//! 各例外を意図的に発生させるユーザープログラムを使い捨てのタスクとして起動し、
+//! カーネルが停止せず、タスクだけが終了し、例外の記録（`fault_report`）が期待どおりの
+//! ベクタとアドレスを持つことを確認します。シェルの `exctest` コマンドから実行します。
+
+use vitros_common::elf::{ELF_MAGIC, PF_R, PF_X};
+
+use crate::fault_report::{self, FaultReport};
+use crate::idt::{
+    VECTOR_BREAKPOINT, VECTOR_DIVIDE_ERROR, VECTOR_INVALID_OPCODE, VECTOR_PAGE_FAULT,
+};
+use crate::ktest::{ELF_ENTRY, USER_EXIT_LIMIT_MS, build_elf, wait_task_exit};
+use crate::{elf_loader, println};
+
+/// 期待するフォルトアドレス
+#[derive(Debug, Clone, Copy)]
+enum ExpectedAddr {
+    /// コード先頭からのオフセット
+    Code(u64),
+    /// 固定のアドレス
+    Absolute(u64),
+    /// ユーザースタックのガードページ内
+    StackGuard,
+}
+
+impl ExpectedAddr {
+    /// 期待するアドレスの範囲（開始, 終了）
+    fn range(self) -> (u64, u64) {
+        match self {
+            ExpectedAddr::Code(offset) => (ELF_ENTRY + offset, ELF_ENTRY + offset + 1),
+            ExpectedAddr::Absolute(addr) => (addr, addr + 1),
+            ExpectedAddr::StackGuard => {
+                let guard = elf_loader::stack_guard_page();
+                (guard, guard + crate::paging::PAGE_SIZE as u64)
+            }
+        }
+    }
+}
+
+/// exctestのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExcTestError {
+    /// 存在しないテスト
+    UnknownTest,
+    /// テスト用タスクの起動に失敗
+    SpawnFailed,
+    /// タスクが時間内に終了しなかった
+    NotKilled,
+    /// タスクは終了したが例外の記録がない
+    NoReport,
+    /// 記録された例外ベクタが異なる
+    WrongVector { expected: u8, observed: u8 },
+    /// 記録されたフォルトアドレスが期待する範囲外
+    WrongAddress { expected: u64, observed: u64 },
+}
+
+impl core::fmt::Display for ExcTestError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ExcTestError::UnknownTest => write!(f, "Unknown test"),
+            ExcTestError::SpawnFailed => write!(f, "Failed to spawn test task"),
+            ExcTestError::NotKilled => {
+                write!(f, "Task was not killed within {} ms", USER_EXIT_LIMIT_MS)
+            }
+            ExcTestError::NoReport => write!(f, "Task exited without a fault report"),
+            ExcTestError::WrongVector { expected, observed } => {
+                write!(f, "Vector {} reported (expected {})", observed, expected)
+            }
+            ExcTestError::WrongAddress { expected, observed } => write!(
+                f,
+                "Address 0x{:X} reported (expected 0x{:X})",
+                observed, expected
+            ),
+        }
+    }
+}
+
+/// テストの定義
+pub struct ExcTest {
+    /// テスト名（タスク名にも使用）
+    pub name: &'static str,
+    /// 1行の説明
+    pub help: &'static str,
+    /// 例外を発生させるユーザーモードのコード
+    code: &'static [u8],
+    /// 期待する例外ベクタ
+    vector: u8,
+    /// 期待するフォルトアドレス
+    addr: ExpectedAddr,
+}
+
+/// テスト一覧
+#[rustfmt::skip]
+pub const TESTS: &[ExcTest] = &[
+    ExcTest {
+        name: "divide",
+        help: "Divide by zero (#DE)",
+        code: &[
+            0x31, 0xC9, // xor ecx, ecx
+            0xF7, 0xF1, // div ecx
+            0xEB, 0xFE, // jmp $
+        ],
+        vector: VECTOR_DIVIDE_ERROR,
+        addr: ExpectedAddr::Code(2),
+    },
+    ExcTest {
+        name: "ud2",
+        help: "Invalid opcode (#UD)",
+        code: &[
+            0x0F, 0x0B, // ud2
+            0xEB, 0xFE, // jmp $
+        ],
+        vector: VECTOR_INVALID_OPCODE,
+        addr: ExpectedAddr::Code(0),
+    },
+    ExcTest {
+        name: "unmapped-read",
+        help: "Read from the unmapped NULL page (#PF)",
+        code: &[
+            0x48, 0x8B, 0x04, 0x25, 0x00, 0x00, 0x00, 0x00, // mov rax, [0]
+            0xEB, 0xFE,                                     // jmp $
+        ],
+        vector: VECTOR_PAGE_FAULT,
+        addr: ExpectedAddr::Absolute(0),
+    },
+    ExcTest {
+        name: "text-write",
+        help: "Write to the read-only text segment (#PF)",
+        code: &[
+            0x48, 0x8D, 0x05, 0xF9, 0xFF, 0xFF, 0xFF, // lea rax, [rip - 7]（コード先頭）
+            0xC6, 0x00, 0x90,                         // mov byte ptr [rax], 0x90
+            0xEB, 0xFE,                               // jmp $
+        ],
+        vector: VECTOR_PAGE_FAULT,
+        addr: ExpectedAddr::Code(0),
+    },
+    ExcTest {
+        name: "stack-overflow",
+        help: "Grow the user stack into its guard page (#PF)",
+        code: &[
+            0x48, 0x81, 0xEC, 0x00, 0x10, 0x00, 0x00, // sub rsp, 0x1000
+            0x48, 0x89, 0x04, 0x24,                   // mov [rsp], rax
+            0xEB, 0xF3,                               // jmp（先頭へ）
+        ],
+        vector: VECTOR_PAGE_FAULT,
+        addr: ExpectedAddr::StackGuard,
+    },
+    ExcTest {
+        name: "int3",
+        help: "Breakpoint in user mode (#BP)",
+        code: &[
+            0xCC,       // int3
+            0xEB, 0xFE, // jmp $
+        ],
+        vector: VECTOR_BREAKPOINT,
+        // トラップのため、次の命令のアドレスが記録される
+        addr: ExpectedAddr::Code(1),
+    },
+];
+
+/// テストを名前で実行
+///
+/// # Errors
+/// * `ExcTestError::UnknownTest` - 存在しないテスト名の場合
+/// * その他 - テストが失敗した場合
+pub fn run(name: &str) -> Result<(), ExcTestError> {
+    let test = TESTS
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or(ExcTestError::UnknownTest)?;
+    run_test(test)
+}
+
+/// 全テストを実行
+///
+/// # Returns
+/// (成功数, 失敗数)
+pub fn run_all() -> (usize, usize) {
+    let failed = TESTS.iter().filter(|t| run_test(t).is_err()).count();
+    (TESTS.len() - failed, failed)
+}
+
+fn run_test(test: &ExcTest) -> Result<(), ExcTestError> {
+    println!("[exctest] {} ...", test.name);
+    let result = trigger(test);
+    match result {
+        Ok(report) => println!(
+            "[exctest] {} PASSED (vector {}, address 0x{:X}, RIP 0x{:X}, error code 0x{:X})",
+            test.name, report.vector, report.addr, report.rip, report.error_code
+        ),
+        Err(e) => println!("[exctest] {} FAILED: {}", test.name, e),
+    }
+    result.map(|_| ())
+}
+
+/// 例外を発生させるタスクを起動し、終了後の記録を検証
+fn trigger(test: &ExcTest) -> Result<FaultReport, ExcTestError> {
+    let image = build_elf(&ELF_MAGIC, PF_R | PF_X, test.code);
+    let task = elf_loader::spawn_image(test.name, &image).map_err(|_| ExcTestError::SpawnFailed)?;
+    if wait_task_exit(task) > USER_EXIT_LIMIT_MS {
+        return Err(ExcTestError::NotKilled);
+    }
+
+    let report = fault_report::find(task).ok_or(ExcTestError::NoReport)?;
+    if report.vector != test.vector {
+        return Err(ExcTestError::WrongVector {
+            expected: test.vector,
+            observed: report.vector,
+        });
+    }
+    let (start, end) = test.addr.range();
+    if !(start..end).contains(&report.addr) {
+        return Err(ExcTestError::WrongAddress {
+            expected: start,
+            observed: report.addr,
+        });
+    }
+    Ok(report)
+}
@@ -0,0 +1,178 @@
+//! 障害注入（フォールトインジェクション）
+//!
+//! OOMパス、例外処理、ウォッチドッグなどを再現性のある形で検証するため、
+//! 制御された障害シナリオを発生させます。シェルの `faultinject` コマンドから使用します。
+//!
+//! # シナリオ
+//! - `alloc <size> <count>` - 指定サイズクラスの次のN回の割り当てを失敗させる
+//! - `pagefault <addr>` - 犠牲タスクを作成し、指定アドレスにアクセスさせてページフォルトを起こす
+//! - `timerdelay <ms>` - 期限切れタイマーの配送を指定時間保留する
+//! - `lock <ms>` - ブロックデバイスレジストリのロックを指定時間保持し、競合を発生させる
+//! - `status` / `clear` - 状態の表示 / 全シナリオの解除
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::allocator;
+use crate::println;
+use crate::sched::kthread;
+use crate::timer;
+
+/// サイズクラスごとの失敗カウンタ数（最後の要素は4KB超の大きな割り当て）
+const NUM_ALLOC_CLASSES: usize = allocator::NUM_SIZE_CLASSES + 1;
+
+/// いずれかのサイズクラスに失敗が予約されているか（割り当てパスの高速判定用）
+static ALLOC_ARMED: AtomicBool = AtomicBool::new(false);
+
+/// サイズクラスごとの残り失敗回数
+static ALLOC_FAILURES: [AtomicU32; NUM_ALLOC_CLASSES] =
+    [const { AtomicU32::new(0) }; NUM_ALLOC_CLASSES];
+
+/// 注入により失敗させた割り当ての累計
+static ALLOC_INJECTED: AtomicU64 = AtomicU64::new(0);
+
+/// このtickに達するまで期限切れタイマーの配送を保留する（0の場合は無効）
+static TIMER_HOLD_UNTIL: AtomicU64 = AtomicU64::new(0);
+
+/// 障害注入のエラー
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultInjectError {
+    /// 犠牲タスク・競合タスクの作成に失敗
+    TaskCreationFailed,
+}
+
+impl core::fmt::Display for FaultInjectError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FaultInjectError::TaskCreationFailed => write!(f, "Failed to create task"),
+        }
+    }
+}
+
+/// 割り当てを失敗させるべきか判定（アロケータから呼ばれる）
+///
+/// 割り当てパスから呼ばれるため、ヒープを使わずアトミック操作のみで判定します。
+///
+/// # Arguments
+/// * `class_idx` - サイズクラスのインデックス（`NUM_SIZE_CLASSES` は大きな割り当て）
+#[inline]
+pub fn should_fail_alloc(class_idx: usize) -> bool {
+    if !ALLOC_ARMED.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    let Some(counter) = ALLOC_FAILURES.get(class_idx) else {
+        return false;
+    };
+    let consumed = counter
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+        .is_ok();
+    if consumed {
+        ALLOC_INJECTED.fetch_add(1, Ordering::Relaxed);
+        if ALLOC_FAILURES
+            .iter()
+            .all(|c| c.load(Ordering::Relaxed) == 0)
+        {
+            ALLOC_ARMED.store(false, Ordering::Relaxed);
+        }
+    }
+    consumed
+}
+
+/// 指定サイズの次のN回の割り当てを失敗させる
+///
+/// # Arguments
+/// * `size` - 割り当てサイズ（バイト）。このサイズが属するサイズクラス全体が対象
+/// * `count` - 失敗させる回数
+pub fn fail_allocations(size: usize, count: u32) {
+    let class_idx = allocator::size_class_index(size);
+    ALLOC_FAILURES[class_idx].fetch_add(count, Ordering::AcqRel);
+    if count > 0 {
+        ALLOC_ARMED.store(true, Ordering::Release);
+    }
+}
+
+/// 期限切れタイマーの配送を保留中か判定（タイマー割り込みから呼ばれる）
+///
+/// # Arguments
+/// * `current_tick` - 現在のtick数
+#[inline]
+pub fn timer_delivery_held(current_tick: u64) -> bool {
+    current_tick < TIMER_HOLD_UNTIL.load(Ordering::Relaxed)
+}
+
+/// 期限切れタイマーの配送を指定時間保留する
+///
+/// 保留中に期限を迎えたタイマーは、保留解除後のtickでまとめて配送されます。
+pub fn delay_timers(ms: u64) {
+    let until = timer::current_tick() + timer::ms_to_ticks(ms).max(1);
+    TIMER_HOLD_UNTIL.store(until, Ordering::Relaxed);
+}
+
+/// 犠牲タスクを作成し、指定アドレスへのアクセスでページフォルトを発生させる
+///
+/// # Errors
+/// * `FaultInjectError::TaskCreationFailed` - タスクの作成に失敗した場合
+pub fn trigger_page_fault(addr: u64) -> Result<(), FaultInjectError> {
+    kthread::spawn("FaultVictim", move || {
+        println!("[FaultInject] Victim touching 0x{:016X}", addr);
+        // SAFETY: 意図的に不正なアドレスを読み出してページフォルトを発生させる。
+        // 読み出した値は使用しない。
+        unsafe {
+            core::ptr::read_volatile(addr as *const u8);
+        }
+        println!("[FaultInject] Victim survived access to 0x{:016X}", addr);
+    })
+    .map(drop)
+    .map_err(|_| FaultInjectError::TaskCreationFailed)
+}
+
+/// ブロックデバイスレジストリのロックを保持するタスクを作成し、ロック競合を発生させる
+///
+/// # Errors
+/// * `FaultInjectError::TaskCreationFailed` - タスクの作成に失敗した場合
+pub fn contend_lock(ms: u64) -> Result<(), FaultInjectError> {
+    kthread::spawn("LockHog", move || {
+        println!("[FaultInject] Holding block registry lock for {} ms", ms);
+        crate::block::hold_registry_lock(ms);
+        println!("[FaultInject] Released block registry lock");
+    })
+    .map(drop)
+    .map_err(|_| FaultInjectError::TaskCreationFailed)
+}
+
+/// 全シナリオを解除
+pub fn clear() {
+    ALLOC_ARMED.store(false, Ordering::Relaxed);
+    for counter in &ALLOC_FAILURES {
+        counter.store(0, Ordering::Relaxed);
+    }
+    TIMER_HOLD_UNTIL.store(0, Ordering::Relaxed);
+}
+
+/// 現在の注入状態を表示
+pub fn print_status() {
+    println!("Allocation failures pending:");
+    for (i, counter) in ALLOC_FAILURES.iter().enumerate() {
+        let remaining = counter.load(Ordering::Relaxed);
+        if remaining == 0 {
+            continue;
+        }
+        match allocator::SIZE_CLASSES.get(i) {
+            Some(size) => println!("  {:>5}B: {}", size, remaining),
+            None => println!("  large : {}", remaining),
+        }
+    }
+    println!(
+        "Allocation failures injected: {}",
+        ALLOC_INJECTED.load(Ordering::Relaxed)
+    );
+
+    let hold_until = TIMER_HOLD_UNTIL.load(Ordering::Relaxed);
+    let now = timer::current_tick();
+    if hold_until > now {
+        println!("Timer delivery held for {} more ticks", hold_until - now);
+    } else {
+        println!("Timer delivery: normal");
+    }
+}
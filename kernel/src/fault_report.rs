@@ -0,0 +1,62 @@
+//! 例外によるタスク終了の記録
+//!
+//! ユーザーモードで回復できない例外が発生したタスクは、例外ハンドラがそのタスクだけを終了させ、
+//! 例外の内容をここに記録します。`exctest` は記録を参照して、期待どおりの例外で
+//! 終了したかを確認します。
+//!
+//! 例外ハンドラから記録するため、固定長のリングバッファでヒープを使いません。
+
+use crate::sched::TaskId;
+use crate::sync::IrqSpinlock;
+
+/// 保持する記録の数
+const CAPACITY: usize = 16;
+
+/// 例外1件の記録
+#[derive(Debug, Clone, Copy)]
+pub struct FaultReport {
+    /// 終了させたタスク
+    pub task_id: TaskId,
+    /// 例外ベクタ
+    pub vector: u8,
+    /// エラーコード（エラーコードのない例外は0）
+    pub error_code: u64,
+    /// 例外発生時のRIP（#BPなどのトラップでは次の命令）
+    pub rip: u64,
+    /// フォルトアドレス（#PFはCR2、それ以外はRIP）
+    pub addr: u64,
+}
+
+/// 記録のリングバッファ
+struct Reports {
+    entries: [Option<FaultReport>; CAPACITY],
+    /// 次に書き込む位置
+    next: usize,
+}
+
+static REPORTS: IrqSpinlock<Reports> = IrqSpinlock::new(Reports {
+    entries: [None; CAPACITY],
+    next: 0,
+});
+
+/// 例外を記録（例外ハンドラから呼ばれる）
+///
+/// バッファが一周すると古い記録を上書きします。
+pub fn record(report: FaultReport) {
+    let mut reports = REPORTS.lock();
+    let index = reports.next;
+    reports.entries[index] = Some(report);
+    reports.next = (index + 1) % CAPACITY;
+}
+
+/// 指定したタスクの最新の記録を取得
+///
+/// # Returns
+/// 記録がない（例外で終了していない、または上書き済み）場合はNone
+pub fn find(task_id: TaskId) -> Option<FaultReport> {
+    let reports = REPORTS.lock();
+    (0..CAPACITY)
+        .map(|i| (reports.next + CAPACITY - 1 - i) % CAPACITY)
+        .filter_map(|index| reports.entries[index])
+        .find(|report| report.task_id == task_id)
+}
@@ -0,0 +1,109 @@
+//! フレームバッファのカーネルコンソール（fbcon）
+//!
+//! Compositorが起動するまでの間、カーネルログを画面へ直接表示します。シリアルを
+//! 接続していない実機でも、起動の途中経過や起動中に止まった場所が分かるようにします。
+//!
+//! `init` の時点でklogに残っているメッセージから表示を始め、以降のメッセージはログの
+//! シンクとして受け取ります。Compositorが画面を所有した後は描画できないため、
+//! Compositorの初期化の直前に `detach` で表示をやめます。
+
+use core::fmt::Write;
+
+use crate::graphics::console::FramebufferConsole;
+use crate::graphics::theme;
+use crate::log::{self, Level, LogSink, Record, Timestamp};
+use crate::sync::IrqSpinlock;
+use crate::{emergency, klog};
+
+/// 画面の外に保持する行数
+const SCROLLBACK: usize = 0;
+
+/// 表示する最低レベル
+const MIN_LEVEL: Level = Level::Info;
+
+/// 表示中のコンソール（`detach` の後はNone）
+static CONSOLE: IrqSpinlock<Option<FramebufferConsole>> = IrqSpinlock::new(None);
+
+/// 1件のメッセージをシリアルと同じ形式で表示
+fn write_entry(
+    console: &mut FramebufferConsole,
+    time_ns: u64,
+    level: Level,
+    text: core::fmt::Arguments,
+) {
+    let _ = writeln!(
+        console,
+        "{} {}[{}]\x1b[0m {}",
+        Timestamp(time_ns),
+        level.ansi_color(),
+        level.as_str(),
+        text
+    );
+}
+
+struct FbconSink;
+
+impl LogSink for FbconSink {
+    fn name(&self) -> &'static str {
+        "fbcon"
+    }
+
+    fn write(&self, record: &Record) {
+        // パニック中は描画中のCPUを待たない（同じCPUで描画中に発生した場合のデッドロックを避ける）
+        let guard = if emergency::in_emergency() {
+            CONSOLE.try_lock()
+        } else {
+            Some(CONSOLE.lock())
+        };
+        if let Some(mut guard) = guard
+            && let Some(console) = guard.as_mut()
+        {
+            write_entry(console, record.time_ns, record.level, record.args);
+        }
+    }
+}
+
+static FBCON_SINK: FbconSink = FbconSink;
+
+/// カーネルコンソールを開始（ヒープの初期化後に呼ぶ）
+///
+/// # Safety
+/// `fb_base` は1行 `stride` ピクセル × `height` 行の有効なフレームバッファで、
+/// `detach` を呼ぶまで他から描画されないこと
+pub unsafe fn init(fb_base: u64, width: u32, height: u32, stride: u32) {
+    // SAFETY: 呼び出し元がフレームバッファの有効性と排他を保証する
+    let mut console = unsafe {
+        FramebufferConsole::new(
+            fb_base,
+            width,
+            height,
+            stride,
+            SCROLLBACK,
+            theme::foreground(),
+            theme::background(),
+        )
+    };
+
+    // 起動してからのメッセージを表示
+    for entry in klog::snapshot().filter(|entry| entry.level >= MIN_LEVEL) {
+        write_entry(
+            &mut console,
+            entry.time_ns,
+            entry.level,
+            format_args!("{}", entry.text),
+        );
+    }
+    *CONSOLE.lock() = Some(console);
+
+    if let Err(e) = log::register_sink(&FBCON_SINK, MIN_LEVEL) {
+        crate::warn!("[fbcon] Failed to register sink: {}", e);
+    }
+}
+
+/// 表示をやめる（Compositorが画面を所有する前に呼ぶ）
+///
+/// コンソールのセルもここで解放します。
+pub fn detach() {
+    let console = CONSOLE.lock().take();
+    drop(console);
+}
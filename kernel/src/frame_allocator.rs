@@ -0,0 +1,378 @@
+//! 物理フレームアロケータ
+//!
+//! UEFIメモリマップ全体から利用可能な4KBフレームをビットマップで管理します。
+//! ページングやヒープアロケータが物理メモリを必要とする場合はここから取得します。
+
+use spin::Mutex;
+use vitros_common::boot_info::BootInfo;
+use vitros_common::uefi;
+
+use crate::info;
+use crate::io::without_interrupts;
+use crate::paging::{KERNEL_VIRTUAL_BASE, MAX_SUPPORTED_MEMORY_GB, PAGE_SIZE};
+
+/// 管理対象の最大フレーム数
+const MAX_FRAMES: usize = (MAX_SUPPORTED_MEMORY_GB << 30) / PAGE_SIZE;
+
+/// ビットマップのワード数（1ワード = 64フレーム）
+const BITMAP_WORDS: usize = MAX_FRAMES / 64;
+
+/// フレームアロケータのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// 4KB境界に揃っていないアドレス
+    Unaligned,
+    /// フレームアロケータが管理していない（利用可能メモリ由来でない）アドレス
+    OutOfRange,
+    /// 既に解放済みのフレーム（二重解放）
+    DoubleFree,
+    /// 既に使用中のフレーム（予約の重複）
+    InUse,
+}
+
+impl core::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            FrameError::Unaligned => write!(f, "Frame address is not 4KB aligned"),
+            FrameError::OutOfRange => write!(f, "Frame address is out of range"),
+            FrameError::DoubleFree => write!(f, "Frame is already free"),
+            FrameError::InUse => write!(f, "Frame is already in use"),
+        }
+    }
+}
+
+/// フレーム使用状況の統計情報
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    /// 管理下にある（利用可能メモリ由来の）フレーム数
+    pub total_frames: usize,
+    /// 空きフレーム数
+    pub free_frames: usize,
+}
+
+/// ヒープに使う物理メモリ領域
+#[derive(Debug, Clone, Copy)]
+pub struct HeapRegion {
+    /// 先頭の物理アドレス（4KBアライン）
+    pub start: u64,
+    /// ヒープのサイズ（バイト）
+    pub size: usize,
+    /// 領域を含むEFI_CONVENTIONAL_MEMORYリージョンのサイズ（バイト）
+    pub region_size: usize,
+}
+
+/// ビットマップ方式のフレームアロケータ
+///
+/// ビットが1のフレームは使用中（または利用不可）、0のフレームは空きを表します。
+struct BitmapFrameAllocator {
+    bitmap: [u64; BITMAP_WORDS],
+    /// 管理下にあるフレーム（ビットが1）。これ以外のフレームは割り当ても解放もしない
+    managed: [u64; BITMAP_WORDS],
+    /// 次回の探索開始ワード（直前に割り当てた付近から探す）
+    next_word: usize,
+    total_frames: usize,
+    free_frames: usize,
+}
+
+impl BitmapFrameAllocator {
+    const fn new() -> Self {
+        Self {
+            // 初期状態では全フレームを使用中として扱う
+            bitmap: [u64::MAX; BITMAP_WORDS],
+            managed: [0; BITMAP_WORDS],
+            next_word: 0,
+            total_frames: 0,
+            free_frames: 0,
+        }
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 64] & (1 << (frame % 64)) != 0
+    }
+
+    fn is_managed(&self, frame: usize) -> bool {
+        self.managed[frame / 64] & (1 << (frame % 64)) != 0
+    }
+
+    fn set_managed(&mut self, frame: usize) {
+        self.managed[frame / 64] |= 1 << (frame % 64);
+    }
+
+    fn set_used(&mut self, frame: usize) {
+        self.bitmap[frame / 64] |= 1 << (frame % 64);
+    }
+
+    fn set_free(&mut self, frame: usize) {
+        self.bitmap[frame / 64] &= !(1 << (frame % 64));
+    }
+
+    /// 物理アドレス範囲 [start, end) に完全に含まれるフレームを空きにする
+    fn add_free_range(&mut self, start: u64, end: u64) {
+        let first = start.div_ceil(PAGE_SIZE as u64) as usize;
+        let last = ((end / PAGE_SIZE as u64) as usize).min(MAX_FRAMES);
+
+        for frame in first..last {
+            if !self.is_managed(frame) {
+                self.set_managed(frame);
+                self.set_free(frame);
+                self.total_frames += 1;
+                self.free_frames += 1;
+            }
+        }
+    }
+
+    /// 物理アドレス範囲 [start, end) にかかるフレームを使用中にする
+    ///
+    /// 範囲内に空きでないフレームが1つでもあれば何も変更せずにエラーを返す
+    fn reserve_range(&mut self, start: u64, end: u64) -> Result<(), FrameError> {
+        let first = (start / PAGE_SIZE as u64) as usize;
+        let last = end.div_ceil(PAGE_SIZE as u64) as usize;
+        if last > MAX_FRAMES || (first..last).any(|frame| !self.is_managed(frame)) {
+            return Err(FrameError::OutOfRange);
+        }
+        if (first..last).any(|frame| self.is_used(frame)) {
+            return Err(FrameError::InUse);
+        }
+
+        for frame in first..last {
+            self.set_used(frame);
+        }
+        self.free_frames -= last - first;
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Option<u64> {
+        if self.free_frames == 0 {
+            return None;
+        }
+
+        // next_wordから末尾、先頭からnext_wordの順に空きビットを探す
+        for i in 0..BITMAP_WORDS {
+            let word_idx = (self.next_word + i) % BITMAP_WORDS;
+            let word = self.bitmap[word_idx];
+            if word != u64::MAX {
+                let bit = (!word).trailing_zeros() as usize;
+                let frame = word_idx * 64 + bit;
+                self.set_used(frame);
+                self.free_frames -= 1;
+                self.next_word = word_idx;
+                return Some((frame * PAGE_SIZE) as u64);
+            }
+        }
+
+        None
+    }
+
+    /// 物理的に連続したcount個の空きフレームを割り当てる（先頭から線形探索）
+    fn allocate_contiguous(&mut self, count: usize) -> Option<u64> {
+        if count == 0 || self.free_frames < count {
+            return None;
+        }
+
+        let mut run_start = 0;
+        let mut run_len = 0;
+        let mut frame = 0;
+        while frame < MAX_FRAMES {
+            // 全フレームが使用中のワードは一括でスキップ
+            if frame % 64 == 0 && self.bitmap[frame / 64] == u64::MAX {
+                run_len = 0;
+                frame += 64;
+                continue;
+            }
+
+            if self.is_used(frame) {
+                run_len = 0;
+            } else {
+                if run_len == 0 {
+                    run_start = frame;
+                }
+                run_len += 1;
+
+                if run_len == count {
+                    for f in run_start..run_start + count {
+                        self.set_used(f);
+                    }
+                    self.free_frames -= count;
+                    return Some((run_start * PAGE_SIZE) as u64);
+                }
+            }
+            frame += 1;
+        }
+
+        None
+    }
+
+    fn deallocate(&mut self, phys_addr: u64) -> Result<(), FrameError> {
+        if !phys_addr.is_multiple_of(PAGE_SIZE as u64) {
+            return Err(FrameError::Unaligned);
+        }
+        let frame = (phys_addr / PAGE_SIZE as u64) as usize;
+        if frame >= MAX_FRAMES || !self.is_managed(frame) {
+            return Err(FrameError::OutOfRange);
+        }
+        if !self.is_used(frame) {
+            return Err(FrameError::DoubleFree);
+        }
+
+        self.set_free(frame);
+        self.free_frames += 1;
+        Ok(())
+    }
+}
+
+/// グローバルフレームアロケータ
+static FRAME_ALLOCATOR: Mutex<BitmapFrameAllocator> = Mutex::new(BitmapFrameAllocator::new());
+
+unsafe extern "C" {
+    /// カーネルイメージの終端（リンカスクリプトで定義、仮想アドレス）
+    static __kernel_end: u8;
+}
+
+/// UEFIメモリマップからフレームアロケータを初期化
+///
+/// EFI_CONVENTIONAL_MEMORYの領域をすべて空きフレームとして登録し、
+/// 物理アドレス0からカーネルイメージ末尾までは除きます。カーネル・initrd・BootInfoは
+/// ブートローダーがEfiLoaderDataとして確保しているため、空きフレームには含まれません。
+///
+/// 最大のEFI_CONVENTIONAL_MEMORYリージョンはヒープ用に予約します。ページテーブルなどが
+/// 先にフレームを取得してヒープと重ならないよう、最初の割り当てより前に予約します。
+///
+/// # Arguments
+/// * `boot_info` - ブートローダから渡されたメモリ情報
+/// * `heap_limit` - ヒープサイズの上限（バイト）
+///
+/// # Returns
+/// ヒープ用に予約した領域。EFI_CONVENTIONAL_MEMORYがなければNone
+pub fn init(boot_info: &BootInfo, heap_limit: usize) -> Option<HeapRegion> {
+    // __kernel_endはリンカが定義するシンボルで、値ではなくアドレスのみを参照する
+    let kernel_end_virt = &raw const __kernel_end as u64;
+    let kernel_end_phys = kernel_end_virt - KERNEL_VIRTUAL_BASE;
+
+    let (stats, heap) = without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+
+        // 低位メモリとカーネルイメージはUEFI上で空きと報告されることがあるため除く
+        let usable_start = kernel_end_phys.next_multiple_of(PAGE_SIZE as u64);
+        let mut heap: Option<HeapRegion> = None;
+        let count = boot_info.memory_map_count.min(boot_info.memory_map.len());
+        for region in &boot_info.memory_map[..count] {
+            if region.region_type != uefi::EFI_CONVENTIONAL_MEMORY {
+                continue;
+            }
+            let start = region.start.max(usable_start);
+            let end = region.start + region.size;
+            if start >= end {
+                continue;
+            }
+            allocator.add_free_range(start, end);
+
+            let region_size = (end - start) as usize;
+            if heap.is_none_or(|heap| region_size > heap.region_size) {
+                heap = Some(HeapRegion {
+                    start,
+                    size: region_size.min(heap_limit),
+                    region_size,
+                });
+            }
+        }
+
+        // ヒープ領域はフレームアロケータから払い出さないよう予約
+        if let Some(heap) = heap {
+            allocator
+                .reserve_range(heap.start, heap.start + heap.size as u64)
+                .expect("Heap region overlaps used frames");
+        }
+
+        let stats = FrameStats {
+            total_frames: allocator.total_frames,
+            free_frames: allocator.free_frames,
+        };
+        (stats, heap)
+    });
+
+    info!(
+        "Frame allocator initialized: {} free frames ({} MB), kernel end phys=0x{:X}",
+        stats.free_frames,
+        stats.free_frames * PAGE_SIZE / 1024 / 1024,
+        kernel_end_phys
+    );
+    heap
+}
+
+/// 物理フレームを1つ割り当てる
+///
+/// # Returns
+/// 割り当てたフレームの物理アドレス（4KBアライン）。空きがなければNone
+#[allow(dead_code)]
+pub fn alloc_frame() -> Option<u64> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate())
+}
+
+/// 物理フレームを解放する
+///
+/// # Arguments
+/// * `phys_addr` - alloc_frame()で取得したフレームの物理アドレス
+///
+/// # Errors
+/// * `FrameError::Unaligned` - アドレスが4KB境界に揃っていない場合
+/// * `FrameError::OutOfRange` - フレームアロケータが管理していないアドレスの場合
+/// * `FrameError::DoubleFree` - 既に空きのフレームを解放しようとした場合
+#[allow(dead_code)]
+pub fn free_frame(phys_addr: u64) -> Result<(), FrameError> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().deallocate(phys_addr))
+}
+
+/// 物理的に連続したフレームを割り当てる
+///
+/// # Arguments
+/// * `count` - フレーム数
+///
+/// # Returns
+/// 先頭フレームの物理アドレス（4KBアライン）。連続した空きがなければNone
+pub fn alloc_contiguous(count: usize) -> Option<u64> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate_contiguous(count))
+}
+
+/// alloc_contiguous()で割り当てた連続フレームを解放する
+///
+/// # Arguments
+/// * `phys_addr` - 先頭フレームの物理アドレス
+/// * `count` - フレーム数
+///
+/// # Errors
+/// `free_frame` と同じ。途中でエラーが発生した場合、それ以降のフレームは解放されない
+pub fn free_contiguous(phys_addr: u64, count: usize) -> Result<(), FrameError> {
+    without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        (0..count as u64).try_for_each(|i| allocator.deallocate(phys_addr + i * PAGE_SIZE as u64))
+    })
+}
+
+/// フレームの使用状況を取得（ロック取得を待たない版）
+///
+/// 割り込みハンドラなど、ロック保持中のコードを割り込んでいる可能性がある場所から使用します。
+///
+/// # Returns
+/// ロックが取得できなければNone
+pub fn try_stats() -> Option<FrameStats> {
+    without_interrupts(|| {
+        FRAME_ALLOCATOR.try_lock().map(|allocator| FrameStats {
+            total_frames: allocator.total_frames,
+            free_frames: allocator.free_frames,
+        })
+    })
+}
+
+/// フレームの使用状況を取得
+#[allow(dead_code)]
+pub fn stats() -> FrameStats {
+    without_interrupts(|| {
+        let allocator = FRAME_ALLOCATOR.lock();
+        FrameStats {
+            total_frames: allocator.total_frames,
+            free_frames: allocator.free_frames,
+        }
+    })
+}
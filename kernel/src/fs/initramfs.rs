@@ -0,0 +1,164 @@
+//! initramfs - ブートローダーが読み込んだcpioアーカイブの読み取り専用ファイルシステム
+//!
+//! ブートローダーがESPの `initrd.img` を物理メモリに置き、その範囲を
+//! `BootInfo` で渡します。アーカイブ（newc形式のcpio）は起動後も解放されないため、
+//! ファイルの内容はコピーせずアーカイブのスライスを直接参照します。
+//! ヒープに確保するのはディレクトリツリーのみです。
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use vitros_common::cpio::{CpioError, CpioReader, EntryKind};
+
+use super::{DirEntry, FileSystem, FileType, FsError, Metadata};
+
+/// ファイルシステムツリーのノード
+enum Node {
+    /// 通常ファイル（アーカイブ内の内容）
+    File(&'static [u8]),
+    /// ディレクトリ（名前順のエントリ）
+    Dir(BTreeMap<String, Node>),
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::File(data) => Metadata {
+                file_type: FileType::File,
+                size: data.len() as u64,
+            },
+            Node::Dir(entries) => Metadata {
+                file_type: FileType::Directory,
+                size: entries.len() as u64,
+            },
+        }
+    }
+}
+
+/// パスをたどってノードを取得
+fn lookup<'a>(root: &'a Node, path: &[&str]) -> Result<&'a Node, FsError> {
+    path.iter().try_fold(root, |node, name| match node {
+        Node::Dir(entries) => entries.get(*name).ok_or(FsError::NotFound),
+        Node::File(_) => Err(FsError::NotADirectory),
+    })
+}
+
+/// ディレクトリを作成しながらパスをたどる（アーカイブの構築用）
+///
+/// cpioでは親ディレクトリのエントリが省略されることがあるため、途中のディレクトリは暗黙に作成します。
+fn ensure_dir<'a>(root: &'a mut Node, path: &[&str]) -> Option<&'a mut BTreeMap<String, Node>> {
+    path.iter()
+        .try_fold(root, |node, name| match node {
+            Node::Dir(entries) => Some(
+                entries
+                    .entry(String::from(*name))
+                    .or_insert_with(|| Node::Dir(BTreeMap::new())),
+            ),
+            Node::File(_) => None,
+        })
+        .and_then(|node| match node {
+            Node::Dir(entries) => Some(entries),
+            Node::File(_) => None,
+        })
+}
+
+/// cpioアーカイブを読み取り専用でマウントするファイルシステム
+pub struct InitRamFs {
+    root: Node,
+}
+
+impl InitRamFs {
+    /// アーカイブを走査してディレクトリツリーを構築
+    ///
+    /// ファイル・ディレクトリ以外のエントリ（シンボリックリンクなど）と、
+    /// 既存のディレクトリと名前が衝突するエントリは無視します。
+    ///
+    /// # Errors
+    /// アーカイブが不正な場合
+    pub fn new(archive: &'static [u8]) -> Result<Self, CpioError> {
+        let mut root = Node::Dir(BTreeMap::new());
+        for entry in CpioReader::new(archive) {
+            let entry = entry?;
+            let components: Vec<&str> = entry
+                .name
+                .split('/')
+                .filter(|c| !c.is_empty() && *c != ".")
+                .collect();
+            let Some((name, parent)) = components.split_last() else {
+                continue;
+            };
+            let Some(dir) = ensure_dir(&mut root, parent) else {
+                continue;
+            };
+            match entry.kind {
+                EntryKind::File => {
+                    if !matches!(dir.get(*name), Some(Node::Dir(_))) {
+                        dir.insert(String::from(*name), Node::File(entry.data));
+                    }
+                }
+                EntryKind::Directory => {
+                    dir.entry(String::from(*name))
+                        .or_insert_with(|| Node::Dir(BTreeMap::new()));
+                }
+                EntryKind::Other => {}
+            }
+        }
+        Ok(Self { root })
+    }
+}
+
+impl FileSystem for InitRamFs {
+    fn name(&self) -> &str {
+        "initramfs"
+    }
+
+    fn metadata(&self, path: &[&str]) -> Result<Metadata, FsError> {
+        lookup(&self.root, path).map(Node::metadata)
+    }
+
+    fn create_file(&self, _path: &[&str]) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn create_dir(&self, _path: &[&str]) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn remove(&self, _path: &[&str]) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn read(&self, path: &[&str], offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        match lookup(&self.root, path)? {
+            Node::File(data) => {
+                let start = (offset as usize).min(data.len());
+                let len = buf.len().min(data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                Ok(len)
+            }
+            Node::Dir(_) => Err(FsError::IsADirectory),
+        }
+    }
+
+    fn write(&self, _path: &[&str], _offset: u64, _data: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn truncate(&self, _path: &[&str], _size: u64) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<DirEntry>, FsError> {
+        match lookup(&self.root, path)? {
+            Node::Dir(entries) => Ok(entries
+                .iter()
+                .map(|(name, node)| DirEntry {
+                    name: name.clone(),
+                    file_type: node.metadata().file_type,
+                })
+                .collect()),
+            Node::File(_) => Err(FsError::NotADirectory),
+        }
+    }
+}
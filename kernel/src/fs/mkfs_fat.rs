@@ -0,0 +1,283 @@
+//! FAT32フォーマッタ（mkfs.fatの簡易版）
+//!
+//! ブロックデバイス全体を1つのFAT32ボリュームとしてフォーマットします。
+//! パーティションテーブルは作らず、ルートディレクトリにはボリュームラベルのみを置きます。
+//! RAMディスク上にテスト用のイメージを作るためのもので、ブートコードは書き込みません。
+//!
+//! クラスタサイズはMicrosoftの仕様書（fatgen103）の既定値の表に従います。小さなデバイスでは
+//! クラスタ数が仕様上のFAT32の下限（65525）を下回りますが、FAT32であることはBPBの
+//! `BPB_FATSz16 == 0` で判定されるため、Linuxなどでもそのままマウントできます。
+
+use alloc::vec;
+
+use crate::block::{self, BlockDevice, BlockError, SECTOR_SIZE};
+
+/// 予約領域のセクタ数（ブートセクタ、FSInfo、バックアップを含む）
+const RESERVED_SECTORS: u32 = 32;
+
+/// FATの数
+const FAT_COUNT: u32 = 2;
+
+/// FSInfoセクタの位置
+const FSINFO_SECTOR: u32 = 1;
+
+/// バックアップブートセクタの位置（続くセクタはFSInfoのバックアップ）
+const BACKUP_BOOT_SECTOR: u32 = 6;
+
+/// ルートディレクトリのクラスタ番号
+const ROOT_CLUSTER: u32 = 2;
+
+/// データ領域に必要な最小クラスタ数
+const MIN_CLUSTERS: u32 = 16;
+
+/// FAT32で使えるクラスタ数の上限
+const MAX_CLUSTERS: u32 = 0x0FFF_FFF5 - 2;
+
+/// FATのクラスタチェーンの終端
+const FAT_EOC: u32 = 0x0FFF_FFFF;
+
+/// メディア記述子（固定ディスク）
+const MEDIA_FIXED: u8 = 0xF8;
+
+/// ディレクトリエントリの属性: ボリュームラベル
+const ATTR_VOLUME_ID: u8 = 0x08;
+
+/// ゼロ埋めで一度に書き込むセクタ数
+const ZERO_BATCH_SECTORS: u32 = 64;
+
+/// ラベルを指定しなかった場合のボリュームラベル
+pub const DEFAULT_LABEL: &str = "NO NAME";
+
+/// フォーマットのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MkfsError {
+    /// ブロックサイズが512バイトでない
+    UnsupportedBlockSize,
+    /// デバイスが小さすぎる
+    TooSmall,
+    /// デバイスが大きすぎる（セクタ数が32ビットに収まらない）
+    TooLarge,
+    /// ボリュームラベルが11文字を超える、または使用できない文字を含む
+    InvalidLabel,
+    /// ブロックデバイスの読み書きに失敗
+    Block(BlockError),
+}
+
+impl core::fmt::Display for MkfsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            MkfsError::UnsupportedBlockSize => {
+                write!(f, "Block size must be {} bytes", SECTOR_SIZE)
+            }
+            MkfsError::TooSmall => write!(f, "Device is too small for FAT32"),
+            MkfsError::TooLarge => write!(f, "Device is too large for FAT32"),
+            MkfsError::InvalidLabel => write!(f, "Invalid volume label"),
+            MkfsError::Block(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<BlockError> for MkfsError {
+    fn from(e: BlockError) -> Self {
+        MkfsError::Block(e)
+    }
+}
+
+/// フォーマットしたボリュームの配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fat32Layout {
+    /// 総セクタ数
+    pub total_sectors: u32,
+    /// クラスタあたりのセクタ数
+    pub sectors_per_cluster: u32,
+    /// 1つのFATのセクタ数
+    pub fat_sectors: u32,
+    /// データ領域の先頭セクタ
+    pub data_start: u32,
+    /// データ領域のクラスタ数
+    pub clusters: u32,
+}
+
+impl Fat32Layout {
+    /// 総セクタ数から配置を計算（fatgen103の計算式）
+    ///
+    /// # Errors
+    /// * `MkfsError::TooSmall` - データ領域のクラスタ数が `MIN_CLUSTERS` 未満の場合
+    fn compute(total_sectors: u32) -> Result<Self, MkfsError> {
+        let sectors_per_cluster = match total_sectors {
+            0..=532_480 => 1,
+            532_481..=16_777_216 => 8,
+            16_777_217..=33_554_432 => 16,
+            33_554_433..=67_108_864 => 32,
+            _ => 64,
+        };
+        let available = total_sectors
+            .checked_sub(RESERVED_SECTORS)
+            .ok_or(MkfsError::TooSmall)?;
+        let divisor = (256 * sectors_per_cluster + FAT_COUNT) / 2;
+        let fat_sectors = available.div_ceil(divisor);
+        let data_start = RESERVED_SECTORS + FAT_COUNT * fat_sectors;
+        let clusters = total_sectors.saturating_sub(data_start) / sectors_per_cluster;
+        if clusters < MIN_CLUSTERS {
+            return Err(MkfsError::TooSmall);
+        }
+        Ok(Self {
+            total_sectors,
+            sectors_per_cluster,
+            fat_sectors,
+            data_start,
+            clusters: clusters.min(MAX_CLUSTERS),
+        })
+    }
+
+    /// クラスタの先頭セクタ
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+}
+
+/// ボリュームラベルを11バイトの空白埋めに変換（英小文字は大文字にする）
+fn encode_label(label: &str) -> Result<[u8; 11], MkfsError> {
+    const INVALID: &[u8] = b"\"*+,./:;<=>?[\\]|";
+    if label.len() > 11 {
+        return Err(MkfsError::InvalidLabel);
+    }
+    let mut encoded = [b' '; 11];
+    for (dst, c) in encoded.iter_mut().zip(label.bytes()) {
+        if !(0x20..0x7F).contains(&c) || INVALID.contains(&c) {
+            return Err(MkfsError::InvalidLabel);
+        }
+        *dst = c.to_ascii_uppercase();
+    }
+    Ok(encoded)
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// ブートセクタ（BPB）を作成
+fn boot_sector(layout: &Fat32Layout, label: &[u8; 11], volume_id: u32) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    sector[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]); // jmp short + nop
+    sector[3..11].copy_from_slice(b"JE4OS   ");
+    put_u16(&mut sector, 11, SECTOR_SIZE as u16); // BPB_BytsPerSec
+    sector[13] = layout.sectors_per_cluster as u8; // BPB_SecPerClus
+    put_u16(&mut sector, 14, RESERVED_SECTORS as u16); // BPB_RsvdSecCnt
+    sector[16] = FAT_COUNT as u8; // BPB_NumFATs
+    sector[21] = MEDIA_FIXED; // BPB_Media
+    put_u16(&mut sector, 24, 63); // BPB_SecPerTrk
+    put_u16(&mut sector, 26, 255); // BPB_NumHeads
+    put_u32(&mut sector, 32, layout.total_sectors); // BPB_TotSec32
+    put_u32(&mut sector, 36, layout.fat_sectors); // BPB_FATSz32
+    put_u32(&mut sector, 44, ROOT_CLUSTER); // BPB_RootClus
+    put_u16(&mut sector, 48, FSINFO_SECTOR as u16); // BPB_FSInfo
+    put_u16(&mut sector, 50, BACKUP_BOOT_SECTOR as u16); // BPB_BkBootSec
+    sector[64] = 0x80; // BS_DrvNum
+    sector[66] = 0x29; // BS_BootSig
+    put_u32(&mut sector, 67, volume_id); // BS_VolID
+    sector[71..82].copy_from_slice(label); // BS_VolLab
+    sector[82..90].copy_from_slice(b"FAT32   "); // BS_FilSysType
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+    sector
+}
+
+/// FSInfoセクタを作成
+fn fsinfo_sector(layout: &Fat32Layout) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    put_u32(&mut sector, 0, 0x4161_5252); // FSI_LeadSig
+    put_u32(&mut sector, 484, 0x6141_7272); // FSI_StrucSig
+    put_u32(&mut sector, 488, layout.clusters - 1); // FSI_Free_Count（ルート以外）
+    put_u32(&mut sector, 492, ROOT_CLUSTER + 1); // FSI_Nxt_Free
+    put_u32(&mut sector, 508, 0xAA55_0000); // FSI_TrailSig
+    sector
+}
+
+/// 連続したセクタをゼロで埋める
+fn zero_sectors(dev: &mut dyn BlockDevice, start: u32, count: u32) -> Result<(), BlockError> {
+    let zeros = vec![0u8; ZERO_BATCH_SECTORS as usize * SECTOR_SIZE];
+    let mut done = 0;
+    while done < count {
+        let batch = (count - done).min(ZERO_BATCH_SECTORS);
+        dev.write_blocks(
+            (start + done) as u64,
+            &zeros[..batch as usize * SECTOR_SIZE],
+        )?;
+        done += batch;
+    }
+    Ok(())
+}
+
+/// ブロックデバイスをFAT32でフォーマット
+///
+/// # Arguments
+/// * `dev` - フォーマットするデバイス（既存の内容は失われる）
+/// * `label` - ボリュームラベル（11文字以内）
+///
+/// # Returns
+/// フォーマットしたボリュームの配置
+///
+/// # Errors
+/// * `MkfsError::UnsupportedBlockSize` - ブロックサイズが512バイトでない場合
+/// * `MkfsError::TooSmall` / `MkfsError::TooLarge` - デバイスの容量がFAT32に合わない場合
+/// * `MkfsError::InvalidLabel` - ボリュームラベルが不正な場合
+/// * `MkfsError::Block` - デバイスへの書き込みに失敗した場合
+pub fn format(dev: &mut dyn BlockDevice, label: &str) -> Result<Fat32Layout, MkfsError> {
+    if dev.block_size() != SECTOR_SIZE {
+        return Err(MkfsError::UnsupportedBlockSize);
+    }
+    let total_sectors = u32::try_from(dev.block_count()).map_err(|_| MkfsError::TooLarge)?;
+    let label = encode_label(label)?;
+    let layout = Fat32Layout::compute(total_sectors)?;
+    // ボリュームIDは作成時刻から作る（一意であればよい）
+    let volume_id = crate::hpet::elapsed_ns() as u32 ^ (crate::timer::current_tick() as u32);
+
+    // 予約領域・FAT・ルートディレクトリのクラスタをゼロで埋める
+    let root_sector = layout.cluster_sector(ROOT_CLUSTER);
+    zero_sectors(dev, 0, root_sector + layout.sectors_per_cluster)?;
+
+    let boot = boot_sector(&layout, &label, volume_id);
+    let fsinfo = fsinfo_sector(&layout);
+    for base in [0, BACKUP_BOOT_SECTOR] {
+        dev.write_blocks(base as u64, &boot)?;
+        dev.write_blocks((base + FSINFO_SECTOR) as u64, &fsinfo)?;
+    }
+
+    // FATの先頭: 予約エントリ2つとルートディレクトリのチェーン終端
+    let mut fat = [0u8; SECTOR_SIZE];
+    put_u32(&mut fat, 0, 0x0FFF_FF00 | MEDIA_FIXED as u32);
+    put_u32(&mut fat, 4, FAT_EOC);
+    put_u32(&mut fat, 8, FAT_EOC);
+    for index in 0..FAT_COUNT {
+        let fat_start = RESERVED_SECTORS + index * layout.fat_sectors;
+        dev.write_blocks(fat_start as u64, &fat)?;
+    }
+
+    // ルートディレクトリ: ボリュームラベルのエントリのみ
+    let mut root = [0u8; SECTOR_SIZE];
+    root[0..11].copy_from_slice(&label);
+    root[11] = ATTR_VOLUME_ID;
+    dev.write_blocks(root_sector as u64, &root)?;
+
+    dev.flush()?;
+    Ok(layout)
+}
+
+/// 登録済みのブロックデバイスをFAT32でフォーマット
+///
+/// # Arguments
+/// * `index` - デバイス番号
+/// * `label` - ボリュームラベル（11文字以内）
+///
+/// # Errors
+/// * `MkfsError::Block(BlockError::NoDevice)` - デバイスが存在しない場合
+/// * その他 - `format` と同じ
+pub fn format_device(index: usize, label: &str) -> Result<Fat32Layout, MkfsError> {
+    block::with_device(index, |dev| format(dev, label))?
+}
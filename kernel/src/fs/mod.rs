@@ -0,0 +1,429 @@
+//! 仮想ファイルシステム（VFS）層
+//!
+//! ファイルシステム共通の `FileSystem` トレイトと、マウントテーブルを提供します。
+//! パスは常に `/` から始まる絶対パスで、最長一致するマウントポイントの
+//! ファイルシステムにマウントポイントからの相対パスで処理を委譲します。
+//!
+//! # モジュール構成
+//! - `ramfs`: カーネルヒープ上のメモリファイルシステム
+//! - `initramfs`: ブートローダーが読み込んだcpioアーカイブ（読み取り専用）
+//! - `mkfs_fat`: ブロックデバイスのFAT32フォーマッタ
+
+pub mod initramfs;
+pub mod mkfs_fat;
+pub mod ramfs;
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use vitros_common::boot_info::BootInfo;
+
+use crate::info;
+use crate::paging;
+use crate::sync::BlockingMutex;
+
+/// ファイルシステム操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// パスが存在しない
+    NotFound,
+    /// 同名のファイルまたはディレクトリが既に存在する
+    AlreadyExists,
+    /// パスの途中がディレクトリではない
+    NotADirectory,
+    /// ディレクトリに対してファイル操作を行った
+    IsADirectory,
+    /// 空でないディレクトリを削除しようとした
+    DirectoryNotEmpty,
+    /// パスが絶対パスでない、または空の要素を含む
+    InvalidPath,
+    /// パスを含むファイルシステムがマウントされていない
+    NotMounted,
+    /// マウントポイントが既に使用されている、または削除できない
+    Busy,
+    /// メモリ不足
+    OutOfMemory,
+    /// 読み取り専用のファイルシステムに書き込もうとした
+    ReadOnly,
+}
+
+impl core::fmt::Display for FsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            FsError::NotFound => write!(f, "No such file or directory"),
+            FsError::AlreadyExists => write!(f, "File exists"),
+            FsError::NotADirectory => write!(f, "Not a directory"),
+            FsError::IsADirectory => write!(f, "Is a directory"),
+            FsError::DirectoryNotEmpty => write!(f, "Directory not empty"),
+            FsError::InvalidPath => write!(f, "Invalid path"),
+            FsError::NotMounted => write!(f, "No file system mounted"),
+            FsError::Busy => write!(f, "Mount point busy"),
+            FsError::OutOfMemory => write!(f, "Out of memory"),
+            FsError::ReadOnly => write!(f, "Read-only file system"),
+        }
+    }
+}
+
+/// ファイルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// 通常ファイル
+    File,
+    /// ディレクトリ
+    Directory,
+}
+
+/// ファイルの属性
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    /// 種類
+    pub file_type: FileType,
+    /// サイズ（バイト、ディレクトリはエントリ数）
+    pub size: u64,
+}
+
+/// ディレクトリエントリ
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// エントリ名
+    pub name: String,
+    /// 種類
+    pub file_type: FileType,
+}
+
+/// ファイルシステムの共通インターフェース
+///
+/// パスはファイルシステムのルートからの要素列で渡されます（空ならルート自身）。
+/// 各要素は空でなく、`.` や `..` を含まないことをVFSが保証します。
+pub trait FileSystem: Send + Sync {
+    /// ファイルシステムの種類名（"ramfs" など）
+    fn name(&self) -> &str;
+
+    /// 属性を取得
+    fn metadata(&self, path: &[&str]) -> Result<Metadata, FsError>;
+
+    /// 空のファイルを作成
+    fn create_file(&self, path: &[&str]) -> Result<(), FsError>;
+
+    /// ディレクトリを作成
+    fn create_dir(&self, path: &[&str]) -> Result<(), FsError>;
+
+    /// ファイルまたは空のディレクトリを削除
+    fn remove(&self, path: &[&str]) -> Result<(), FsError>;
+
+    /// ファイルを読み込む
+    ///
+    /// # Returns
+    /// 読み込んだバイト数（ファイル末尾以降なら0）
+    fn read(&self, path: &[&str], offset: u64, buf: &mut [u8]) -> Result<usize, FsError>;
+
+    /// ファイルに書き込む（末尾を超える場合はファイルを伸長する）
+    ///
+    /// # Returns
+    /// 書き込んだバイト数
+    fn write(&self, path: &[&str], offset: u64, data: &[u8]) -> Result<usize, FsError>;
+
+    /// ファイルのサイズを変更
+    fn truncate(&self, path: &[&str], size: u64) -> Result<(), FsError>;
+
+    /// ディレクトリの内容を取得（名前順）
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<DirEntry>, FsError>;
+}
+
+/// マウントテーブルのエントリ
+struct Mount {
+    /// マウントポイントの要素列（ルートなら空）
+    point: Vec<String>,
+    /// マウントされたファイルシステム
+    fs: Arc<dyn FileSystem>,
+}
+
+/// マウント情報（一覧表示用）
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    /// マウントポイント
+    pub path: String,
+    /// ファイルシステムの種類名
+    pub fs_name: String,
+}
+
+/// マウントテーブル
+///
+/// ファイル操作は時間がかかり得るため、BlockingMutexで保護する
+static MOUNTS: BlockingMutex<Vec<Mount>> = BlockingMutex::new(Vec::new());
+
+/// パスを要素列に分解
+///
+/// 連続した `/` と `.` は無視し、`..` は1つ上の要素を取り除きます。
+///
+/// # Errors
+/// * `FsError::InvalidPath` - 絶対パスでない場合
+fn split_path(path: &str) -> Result<Vec<&str>, FsError> {
+    let rest = path.strip_prefix('/').ok_or(FsError::InvalidPath)?;
+    let mut components = Vec::new();
+    for component in rest.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+    Ok(components)
+}
+
+/// パスを含むファイルシステムと、その中での相対パスを解決
+///
+/// # Errors
+/// * `FsError::InvalidPath` - 絶対パスでない場合
+/// * `FsError::NotMounted` - パスを含むファイルシステムがない場合
+fn resolve(path: &str) -> Result<(Arc<dyn FileSystem>, Vec<&str>), FsError> {
+    let components = split_path(path)?;
+    let mounts = MOUNTS.lock();
+    let mount = mounts
+        .iter()
+        .filter(|m| {
+            m.point.len() <= components.len()
+                && m.point.iter().zip(&components).all(|(a, b)| a == b)
+        })
+        .max_by_key(|m| m.point.len())
+        .ok_or(FsError::NotMounted)?;
+    let relative = components[mount.point.len()..].to_vec();
+    Ok((Arc::clone(&mount.fs), relative))
+}
+
+/// ファイルシステムをマウント
+///
+/// ルート以外のマウントポイントは、既存のファイルシステム上のディレクトリである必要があります。
+///
+/// # Errors
+/// * `FsError::InvalidPath` - 絶対パスでない場合
+/// * `FsError::Busy` - 同じマウントポイントに既にマウントされている場合
+/// * `FsError::NotADirectory` - マウントポイントがディレクトリでない場合
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> Result<(), FsError> {
+    let point: Vec<String> = split_path(path)?.into_iter().map(String::from).collect();
+    if !point.is_empty() && metadata(path)?.file_type != FileType::Directory {
+        return Err(FsError::NotADirectory);
+    }
+
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|m| m.point == point) {
+        return Err(FsError::Busy);
+    }
+    info!("VFS: mounted {} at {}", fs.name(), path);
+    mounts.push(Mount { point, fs });
+    Ok(())
+}
+
+/// マウント一覧を取得
+pub fn mounts() -> Vec<MountInfo> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|m| MountInfo {
+            path: if m.point.is_empty() {
+                String::from("/")
+            } else {
+                m.point.iter().fold(String::new(), |mut acc, c| {
+                    acc.push('/');
+                    acc.push_str(c);
+                    acc
+                })
+            },
+            fs_name: String::from(m.fs.name()),
+        })
+        .collect()
+}
+
+/// 属性を取得
+///
+/// # Errors
+/// * `FsError::NotFound` - パスが存在しない場合
+/// * その他 - パスの解決に失敗した場合
+pub fn metadata(path: &str) -> Result<Metadata, FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.metadata(&rel)
+}
+
+/// 空のファイルを作成
+///
+/// # Errors
+/// * `FsError::AlreadyExists` - 同名のエントリが既にある場合
+/// * `FsError::NotFound` - 親ディレクトリが存在しない場合
+pub fn create_file(path: &str) -> Result<(), FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.create_file(&rel)
+}
+
+/// ディレクトリを作成
+///
+/// # Errors
+/// * `FsError::AlreadyExists` - 同名のエントリが既にある場合
+/// * `FsError::NotFound` - 親ディレクトリが存在しない場合
+pub fn create_dir(path: &str) -> Result<(), FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.create_dir(&rel)
+}
+
+/// ファイルまたは空のディレクトリを削除
+///
+/// # Errors
+/// * `FsError::DirectoryNotEmpty` - ディレクトリが空でない場合
+/// * `FsError::Busy` - マウントポイント（ファイルシステムのルート）を指定した場合
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let (fs, rel) = resolve(path)?;
+    if rel.is_empty() {
+        return Err(FsError::Busy);
+    }
+    fs.remove(&rel)
+}
+
+/// ファイルを読み込む
+///
+/// # Returns
+/// 読み込んだバイト数
+///
+/// # Errors
+/// * `FsError::IsADirectory` - ディレクトリを指定した場合
+#[allow(dead_code)]
+pub fn read(path: &str, offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.read(&rel, offset, buf)
+}
+
+/// ファイル全体を読み込む
+///
+/// # Errors
+/// * `FsError::IsADirectory` - ディレクトリを指定した場合
+pub fn read_to_vec(path: &str) -> Result<Vec<u8>, FsError> {
+    let (fs, rel) = resolve(path)?;
+    let size = fs.metadata(&rel)?.size as usize;
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(size)
+        .map_err(|_| FsError::OutOfMemory)?;
+    buf.resize(size, 0);
+    let read = fs.read(&rel, 0, &mut buf)?;
+    buf.truncate(read);
+    Ok(buf)
+}
+
+/// ファイルに書き込む
+///
+/// # Returns
+/// 書き込んだバイト数
+///
+/// # Errors
+/// * `FsError::IsADirectory` - ディレクトリを指定した場合
+#[allow(dead_code)]
+pub fn write(path: &str, offset: u64, data: &[u8]) -> Result<usize, FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.write(&rel, offset, data)
+}
+
+/// ファイルの内容を置き換える（存在しなければ作成）
+///
+/// # Errors
+/// * `FsError::IsADirectory` - ディレクトリを指定した場合
+/// * `FsError::NotFound` - 親ディレクトリが存在しない場合
+pub fn write_all(path: &str, data: &[u8]) -> Result<(), FsError> {
+    let (fs, rel) = resolve(path)?;
+    match fs.create_file(&rel) {
+        Ok(()) | Err(FsError::AlreadyExists) => {}
+        Err(e) => return Err(e),
+    }
+    fs.truncate(&rel, 0)?;
+    fs.write(&rel, 0, data).map(|_| ())
+}
+
+/// ファイルのサイズを変更
+///
+/// # Errors
+/// * `FsError::IsADirectory` - ディレクトリを指定した場合
+#[allow(dead_code)]
+pub fn truncate(path: &str, size: u64) -> Result<(), FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.truncate(&rel, size)
+}
+
+/// ディレクトリの内容を取得
+///
+/// # Errors
+/// * `FsError::NotADirectory` - ファイルを指定した場合
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    let (fs, rel) = resolve(path)?;
+    fs.read_dir(&rel)
+}
+
+/// initramfsをマウントするディレクトリ
+const INITRD_MOUNT_POINT: &str = "/initrd";
+
+/// VFSを初期化し、ルートにramfsをマウントする
+///
+/// ブートローダーがinitrdを読み込んでいれば、`/initrd` に読み取り専用でマウントします。
+/// ヒープ初期化後に呼び出します。
+pub fn init(boot_info: &BootInfo) {
+    if let Err(e) = mount("/", Arc::new(ramfs::RamFs::new())) {
+        crate::warn!("VFS: failed to mount root ramfs: {}", e);
+        return;
+    }
+    if let Err(e) = create_dir("/tmp") {
+        crate::warn!("VFS: failed to create /tmp: {}", e);
+    }
+    if boot_info.initrd_size == 0 {
+        return;
+    }
+    match initrd_image(boot_info) {
+        Some(archive) => mount_initrd(boot_info.initrd_address, archive),
+        None => crate::warn!(
+            "VFS: initrd at 0x{:X} (+{} bytes) is outside mapped memory",
+            boot_info.initrd_address,
+            boot_info.initrd_size
+        ),
+    }
+}
+
+/// ブートローダーが読み込んだinitrdのcpioアーカイブを取得
+///
+/// # Returns
+/// initrdがない、または高位アドレスにマップされていない場合はNone
+pub fn initrd_image(boot_info: &BootInfo) -> Option<&'static [u8]> {
+    let (phys_addr, size) = (boot_info.initrd_address, boot_info.initrd_size);
+    let last = phys_addr.checked_add(size.checked_sub(1)?)?;
+    let virt_addr = paging::phys_to_virt(phys_addr).ok()?;
+    // initrdは1つのメモリマップ領域（EfiLoaderData）なので、両端がマップされていれば全体が参照できる
+    paging::translate(virt_addr)?;
+    paging::translate(paging::phys_to_virt(last).ok()?)?;
+    // SAFETY: initrdはブートローダーがEfiLoaderDataとして確保した領域で、
+    // フレームアロケータやヒープが払い出すことはなく、カーネル実行中は解放されない。
+    // 範囲は高位アドレスにマップ済みであることを上で確認している。
+    // 以降は読み取りのみで、書き込む箇所は存在しない。
+    Some(unsafe { core::slice::from_raw_parts(virt_addr as *const u8, size as usize) })
+}
+
+/// initrdのcpioアーカイブを `/initrd` にマウント
+fn mount_initrd(phys_addr: u64, archive: &'static [u8]) {
+    info!(
+        "initrd: phys=0x{:X}-0x{:X} ({} bytes)",
+        phys_addr,
+        phys_addr + archive.len() as u64,
+        archive.len()
+    );
+
+    let initramfs = match initramfs::InitRamFs::new(archive) {
+        Ok(fs) => fs,
+        Err(e) => {
+            crate::warn!("VFS: failed to parse initrd: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = create_dir(INITRD_MOUNT_POINT) {
+        crate::warn!("VFS: failed to create {}: {}", INITRD_MOUNT_POINT, e);
+        return;
+    }
+    if let Err(e) = mount(INITRD_MOUNT_POINT, Arc::new(initramfs)) {
+        crate::warn!("VFS: failed to mount initrd: {}", e);
+    }
+}
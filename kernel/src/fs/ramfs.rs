@@ -0,0 +1,204 @@
+//! ramfs - カーネルヒープ上のメモリファイルシステム
+//!
+//! ファイルの内容とディレクトリ構造をすべてヒープに保持します。
+//! 永続化はされず、再起動すると内容は失われます。ストレージドライバが
+//! なくても書き込み可能なファイルシステムとして、テストやシェルから使用します。
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{DirEntry, FileSystem, FileType, FsError, Metadata};
+use crate::sync::BlockingMutex;
+
+/// ファイルシステムツリーのノード
+enum Node {
+    /// 通常ファイル（内容）
+    File(Vec<u8>),
+    /// ディレクトリ（名前順のエントリ）
+    Dir(BTreeMap<String, Node>),
+}
+
+impl Node {
+    fn file_type(&self) -> FileType {
+        match self {
+            Node::File(_) => FileType::File,
+            Node::Dir(_) => FileType::Directory,
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let size = match self {
+            Node::File(data) => data.len() as u64,
+            Node::Dir(entries) => entries.len() as u64,
+        };
+        Metadata {
+            file_type: self.file_type(),
+            size,
+        }
+    }
+}
+
+/// パスをたどってノードを取得
+fn lookup<'a>(root: &'a Node, path: &[&str]) -> Result<&'a Node, FsError> {
+    path.iter().try_fold(root, |node, name| match node {
+        Node::Dir(entries) => entries.get(*name).ok_or(FsError::NotFound),
+        Node::File(_) => Err(FsError::NotADirectory),
+    })
+}
+
+/// パスをたどってノードを取得（可変）
+fn lookup_mut<'a>(root: &'a mut Node, path: &[&str]) -> Result<&'a mut Node, FsError> {
+    path.iter().try_fold(root, |node, name| match node {
+        Node::Dir(entries) => entries.get_mut(*name).ok_or(FsError::NotFound),
+        Node::File(_) => Err(FsError::NotADirectory),
+    })
+}
+
+/// 親ディレクトリのエントリと末尾の名前を取得
+///
+/// # Errors
+/// * `FsError::AlreadyExists` - パスがルート自身の場合
+fn parent_mut<'a, 'p>(
+    root: &'a mut Node,
+    path: &[&'p str],
+) -> Result<(&'a mut BTreeMap<String, Node>, &'p str), FsError> {
+    let (name, parent) = path.split_last().ok_or(FsError::AlreadyExists)?;
+    match lookup_mut(root, parent)? {
+        Node::Dir(entries) => Ok((entries, name)),
+        Node::File(_) => Err(FsError::NotADirectory),
+    }
+}
+
+/// ファイルの内容を取得（可変）
+fn file_mut<'a>(root: &'a mut Node, path: &[&str]) -> Result<&'a mut Vec<u8>, FsError> {
+    match lookup_mut(root, path)? {
+        Node::File(data) => Ok(data),
+        Node::Dir(_) => Err(FsError::IsADirectory),
+    }
+}
+
+/// ファイルの長さを変更（伸ばした部分は0で埋める）
+fn resize_file(data: &mut Vec<u8>, size: usize) -> Result<(), FsError> {
+    if size > data.len() {
+        data.try_reserve(size - data.len())
+            .map_err(|_| FsError::OutOfMemory)?;
+    }
+    data.resize(size, 0);
+    Ok(())
+}
+
+/// メモリファイルシステム
+pub struct RamFs {
+    /// ルートディレクトリ
+    ///
+    /// 大きなファイルのコピーは時間がかかるため、BlockingMutexで保護する
+    root: BlockingMutex<Node>,
+}
+
+impl RamFs {
+    /// 空のファイルシステムを作成
+    pub fn new() -> Self {
+        Self {
+            root: BlockingMutex::new(Node::Dir(BTreeMap::new())),
+        }
+    }
+
+    fn create(&self, path: &[&str], node: Node) -> Result<(), FsError> {
+        let mut root = self.root.lock();
+        let (entries, name) = parent_mut(&mut root, path)?;
+        if entries.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+        entries.insert(String::from(name), node);
+        Ok(())
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &str {
+        "ramfs"
+    }
+
+    fn metadata(&self, path: &[&str]) -> Result<Metadata, FsError> {
+        let root = self.root.lock();
+        lookup(&root, path).map(Node::metadata)
+    }
+
+    fn create_file(&self, path: &[&str]) -> Result<(), FsError> {
+        self.create(path, Node::File(Vec::new()))
+    }
+
+    fn create_dir(&self, path: &[&str]) -> Result<(), FsError> {
+        self.create(path, Node::Dir(BTreeMap::new()))
+    }
+
+    fn remove(&self, path: &[&str]) -> Result<(), FsError> {
+        let removed = {
+            let mut root = self.root.lock();
+            let (entries, name) = parent_mut(&mut root, path).map_err(|e| match e {
+                // ルート自身は削除できない
+                FsError::AlreadyExists => FsError::Busy,
+                e => e,
+            })?;
+            match entries.get(name) {
+                None => return Err(FsError::NotFound),
+                Some(Node::Dir(children)) if !children.is_empty() => {
+                    return Err(FsError::DirectoryNotEmpty);
+                }
+                Some(_) => entries.remove(name),
+            }
+        };
+        // ファイルの内容の解放はロックの外で行う
+        drop(removed);
+        Ok(())
+    }
+
+    fn read(&self, path: &[&str], offset: u64, buf: &mut [u8]) -> Result<usize, FsError> {
+        let root = self.root.lock();
+        let data = match lookup(&root, path)? {
+            Node::File(data) => data,
+            Node::Dir(_) => return Err(FsError::IsADirectory),
+        };
+        let start = (offset as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write(&self, path: &[&str], offset: u64, data: &[u8]) -> Result<usize, FsError> {
+        let mut root = self.root.lock();
+        let file = file_mut(&mut root, path)?;
+        let start = offset as usize;
+        let end = start.checked_add(data.len()).ok_or(FsError::OutOfMemory)?;
+        if end > file.len() {
+            resize_file(file, end)?;
+        }
+        file[start..end].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn truncate(&self, path: &[&str], size: u64) -> Result<(), FsError> {
+        let mut root = self.root.lock();
+        let file = file_mut(&mut root, path)?;
+        resize_file(file, size as usize)?;
+        if file.len() < file.capacity() / 2 {
+            file.shrink_to_fit();
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &[&str]) -> Result<Vec<DirEntry>, FsError> {
+        let root = self.root.lock();
+        match lookup(&root, path)? {
+            Node::Dir(entries) => Ok(entries
+                .iter()
+                .map(|(name, node)| DirEntry {
+                    name: name.clone(),
+                    file_type: node.file_type(),
+                })
+                .collect()),
+            Node::File(_) => Err(FsError::NotADirectory),
+        }
+    }
+}
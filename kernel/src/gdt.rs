@@ -3,6 +3,8 @@
 //! x86_64アーキテクチャでは、セグメンテーションはほぼ使用されませんが、
 //! 特権レベル（Ring 0/3）の管理とTSS（Interrupt Stack Table用）のためにGDTは必須です。
 
+use alloc::boxed::Box;
+
 use crate::info;
 use crate::paging::KERNEL_VIRTUAL_BASE;
 use core::arch::asm;
@@ -240,13 +242,24 @@ static mut GDT: Gdt = Gdt::new();
 /// グローバルTSSインスタンス
 static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
-/// Double Fault用のISTスタック（16KB）
-/// Linux kernelと同様、Double Faultハンドラ用の専用スタックを提供
+/// ISTスタック（16KB）
+/// Linux kernelと同様、Double Fault・NMI・Machine Checkのハンドラ用の専用スタックを提供
 #[allow(dead_code)]
 #[repr(align(16))]
-struct DoubleFaultStack([u8; 16384]);
+struct IstStack([u8; 16384]);
+
+/// ISTスタックの最上位アドレス
+fn ist_stack_top(stack: *const IstStack) -> u64 {
+    stack as u64 + core::mem::size_of::<IstStack>() as u64
+}
+
+static mut DOUBLE_FAULT_STACK: IstStack = IstStack([0; 16384]);
+
+/// NMI用のISTスタック
+static mut NMI_STACK: IstStack = IstStack([0; 16384]);
 
-static mut DOUBLE_FAULT_STACK: DoubleFaultStack = DoubleFaultStack([0; 16384]);
+/// Machine Check用のISTスタック
+static mut MACHINE_CHECK_STACK: IstStack = IstStack([0; 16384]);
 
 /// セグメントセレクタ
 pub mod selector {
@@ -256,10 +269,8 @@ pub mod selector {
     #[allow(dead_code)]
     pub const KERNEL_DATA: u16 = 0x10;
     /// ユーザーコードセグメントセレクタ（RPL=3を含む）
-    #[allow(dead_code)]
     pub const USER_CODE: u16 = 0x18 | 3;
     /// ユーザーデータセグメントセレクタ（RPL=3を含む）
-    #[allow(dead_code)]
     pub const USER_DATA: u16 = 0x20 | 3;
     /// TSSセグメントセレクタ
     pub const TSS: u16 = 0x28;
@@ -268,6 +279,14 @@ pub mod selector {
 /// Double Fault用のISTインデックス
 pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
 
+/// NMI用のISTインデックス
+///
+/// NMIはカーネルスタックを切り替えた直後など任意の位置で発生するため、専用スタックを使う
+pub const NMI_IST_INDEX: u8 = 2;
+
+/// Machine Check用のISTインデックス
+pub const MACHINE_CHECK_IST_INDEX: u8 = 3;
+
 /// GDTを初期化してロード
 pub fn init() -> Result<(), GdtError> {
     // SAFETY: この関数は以下の操作を行う：
@@ -280,34 +299,54 @@ pub fn init() -> Result<(), GdtError> {
     // すべての操作はカーネル初期化時のRing 0で実行され、
     // 必要な構造体は静的に確保されたメモリに存在する。
     unsafe {
-        // TSSを初期化（Double Fault用のISTスタックを設定）
-        let double_fault_stack_top = (&raw const DOUBLE_FAULT_STACK as u64)
-            + core::mem::size_of::<DoubleFaultStack>() as u64;
+        // TSSを初期化（Double Fault・NMI・Machine Check用のISTスタックを設定）
+        let double_fault_stack_top = ist_stack_top(&raw const DOUBLE_FAULT_STACK);
+        let nmi_stack_top = ist_stack_top(&raw const NMI_STACK);
+        let machine_check_stack_top = ist_stack_top(&raw const MACHINE_CHECK_STACK);
 
         TSS.ist1 = double_fault_stack_top;
+        TSS.ist2 = nmi_stack_top;
+        TSS.ist3 = machine_check_stack_top;
 
         info!("TSS initialized:");
         info!(
             "  IST1 (Double Fault stack): 0x{:016X}",
             double_fault_stack_top
         );
+        info!("  IST2 (NMI stack): 0x{:016X}", nmi_stack_top);
+        info!(
+            "  IST3 (Machine Check stack): 0x{:016X}",
+            machine_check_stack_top
+        );
 
-        // GDTにTSSディスクリプタを設定
         let tss_addr = &raw const TSS as u64;
-        let gdt_ptr = core::ptr::addr_of_mut!(GDT);
-        core::ptr::write(
-            core::ptr::addr_of_mut!((*gdt_ptr).tss),
-            TssDescriptor::new(tss_addr),
-        );
+        load(core::ptr::addr_of_mut!(GDT), tss_addr);
 
         info!("TSS descriptor set in GDT at 0x{:016X}", tss_addr);
+        info!("TSS loaded into TR register");
 
-        // GDTのアドレスを取得（カーネルが高位アドレスでリンクされているため既に高位）
-        let gdt_addr = &raw const GDT as u64;
+        // BSPのCPUごとのデータ（GSベース）を設定
+        crate::percpu::init_bsp(tss_addr);
+    }
+    Ok(())
+}
+
+/// TSSディスクリプタを設定したGDTをロードし、セグメントレジスタとTRをリロード
+///
+/// # Safety
+/// `gdt`と`tss_addr`はロード後も解放されない（'staticな）領域を指していること。
+/// Ring 0で割り込み無効状態から呼び出すこと。
+unsafe fn load(gdt: *mut Gdt, tss_addr: u64) {
+    // SAFETY: 呼び出し元が上記の条件を保証する。セレクタはすべてこのGDTの有効なエントリ
+    unsafe {
+        core::ptr::write(
+            core::ptr::addr_of_mut!((*gdt).tss),
+            TssDescriptor::new(tss_addr),
+        );
 
         let gdtr = Gdtr {
             limit: (core::mem::size_of::<Gdt>() - 1) as u16,
-            base: gdt_addr,
+            base: gdt as u64,
         };
 
         // LGDT命令でGDTをロード
@@ -330,24 +369,67 @@ pub fn init() -> Result<(), GdtError> {
         );
 
         // データセグメントレジスタをリロード
+        // FS/GSはリロードしない（GSのベースはCPUごとのデータを指しており、リロードで失われる）
         asm!(
             "mov ds, {0:x}",
             "mov es, {0:x}",
-            "mov fs, {0:x}",
-            "mov gs, {0:x}",
             "mov ss, {0:x}",
             in(reg) selector::KERNEL_DATA,
             options(nostack, preserves_flags)
         );
 
-        // LTR命令でTSSをロード
+        // LTR命令でTSSをロード（TSSディスクリプタはBusyになるため、CPUごとに別のGDTが必要）
         asm!(
             "ltr {0:x}",
             in(reg) selector::TSS,
             options(nostack, preserves_flags)
         );
+    }
+}
 
-        info!("TSS loaded into TR register");
+/// アプリケーションプロセッサ用のGDT・TSS・ISTスタック
+struct ApTables {
+    gdt: Gdt,
+    tss: TaskStateSegment,
+    double_fault_stack: IstStack,
+    nmi_stack: IstStack,
+    machine_check_stack: IstStack,
+}
+
+/// アプリケーションプロセッサのGDTを初期化してロード
+///
+/// CPUごとにGDT・TSS・ISTスタックをヒープに確保します（解放しない）。
+/// APの起動直後、そのAP上で一度だけ呼び出します。
+pub fn init_ap() {
+    // ISTスタックが大きいため、スタックを経由せずにヒープ上で直接初期化する
+    // SAFETY: ApTablesの全フィールドはゼロ初期化で有効な値（直後に上書きする）
+    let tables: &'static mut ApTables =
+        Box::leak(unsafe { Box::<ApTables>::new_zeroed().assume_init() });
+    tables.gdt = Gdt::new();
+    tables.tss = TaskStateSegment::new();
+    tables.tss.ist1 = ist_stack_top(&raw const tables.double_fault_stack);
+    tables.tss.ist2 = ist_stack_top(&raw const tables.nmi_stack);
+    tables.tss.ist3 = ist_stack_top(&raw const tables.machine_check_stack);
+
+    let tss_addr = &raw const tables.tss as u64;
+    // SAFETY: tablesはリークしたヒープ領域で'static。APの初期化中は割り込み無効
+    unsafe { load(&raw mut tables.gdt, tss_addr) };
+    crate::percpu::set_tss(tss_addr);
+}
+
+/// Ring 3からの割り込み・例外で使用するカーネルスタックを設定
+///
+/// ユーザーモードで割り込みが発生すると、CPUは自身のTSS.RSP0のスタックに切り替えます。
+/// スケジューラがタスクを切り替えるたびに、現在のCPUのTSSへそのタスクのカーネルスタックの最上位を設定します。
+pub fn set_kernel_stack(stack_top: u64) {
+    let tss = crate::percpu::this_cpu().tss() as *mut TaskStateSegment;
+    if tss.is_null() {
+        return;
+    }
+    // SAFETY: tssは現在のCPUのTSS（'static）を指す。RSP0はCPUが特権レベル変更時に読むのみで、
+    // 書き込みはそのCPUのスケジューラから割り込み無効中に行われるため競合しない。
+    // packed構造体のためアライメントを仮定しない。
+    unsafe {
+        core::ptr::addr_of_mut!((*tss).rsp0).write_unaligned(stack_top);
     }
-    Ok(())
 }
@@ -0,0 +1,430 @@
+//! ウィンドウのバッキングストア
+//!
+//! ウィンドウのコンテンツ領域の画素を保持するバッファです。描画コマンドは
+//! Writerのflush時に一度だけバッキングストアへ描画され、Compositorは再合成のたびに
+//! コマンドを再生するのではなく、ここから画素をコピーします。
+//! 画素はフレームバッファのネイティブ形式で保持するため、転送は単純なコピーになります。
+
+use super::buffer::{DrawCommand, DrawList};
+use super::color::Color;
+use super::page_buffer::{BufferAllocError, PageBuffer};
+use super::pixel_format;
+use super::region::Region;
+use super::shadow_buffer::ShadowBuffer;
+use super::simd;
+
+/// ウィンドウ1枚分の画素バッファ
+pub struct BackingStore {
+    /// ピクセルデータ（ネイティブ形式、フレームアロケータから確保）
+    buffer: PageBuffer,
+    /// 幅（ピクセル）
+    width: u32,
+    /// 高さ（ピクセル）
+    height: u32,
+}
+
+impl BackingStore {
+    /// 背景色で塗りつぶしたバッキングストアを作成
+    ///
+    /// # Errors
+    /// * `BufferAllocError::InvalidSize` - `width * height`が0またはオーバーフローする場合
+    /// * `BufferAllocError::OutOfFrames` - 連続した空きフレームが不足している場合
+    pub fn new(width: u32, height: u32, background: Color) -> Result<Self, BufferAllocError> {
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or(BufferAllocError::InvalidSize)?;
+        let mut store = Self {
+            buffer: PageBuffer::new(size)?,
+            width,
+            height,
+        };
+        store.fill(background);
+        Ok(store)
+    }
+
+    /// 同じ内容を持つ複製を作成
+    ///
+    /// # Errors
+    /// * `BufferAllocError::OutOfFrames` - 連続した空きフレームが不足している場合
+    pub fn duplicate(&self) -> Result<Self, BufferAllocError> {
+        let size = self.width as usize * self.height as usize;
+        let mut buffer = PageBuffer::new(size)?;
+        buffer
+            .as_mut_slice()
+            .copy_from_slice(self.buffer.as_slice());
+        Ok(Self {
+            buffer,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    /// サイズを変更した新しいバッキングストアを作成
+    ///
+    /// 左上を基準に重なる部分の内容を引き継ぎ、広がった部分は背景色で塗りつぶします。
+    ///
+    /// # Errors
+    /// * `BufferAllocError` - 新しいバッファを確保できない場合
+    pub fn resized(
+        &self,
+        width: u32,
+        height: u32,
+        background: Color,
+    ) -> Result<Self, BufferAllocError> {
+        let mut store = Self::new(width, height, background)?;
+        let overlap = Region::new(0, 0, width.min(self.width), height.min(self.height));
+        store.copy_from(self, &overlap);
+        Ok(store)
+    }
+
+    /// 幅を取得
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// 高さを取得
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// バッファ全体を表す領域（ローカル座標）
+    pub fn bounds(&self) -> Region {
+        Region::new(0, 0, self.width, self.height)
+    }
+
+    /// 1ピクセルを取得（ネイティブ形式、範囲外はNone）
+    #[allow(dead_code)]
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.buffer.as_slice()[y as usize * self.width as usize + x as usize])
+    }
+
+    /// ピクセルデータ（ネイティブ形式、行優先）
+    pub(super) fn pixels_mut(&mut self) -> &mut [u32] {
+        self.buffer.as_mut_slice()
+    }
+
+    /// バッファ全体を単色で塗りつぶす
+    pub fn fill(&mut self, color: Color) {
+        self.buffer
+            .as_mut_slice()
+            .fill(pixel_format::to_native(color));
+    }
+
+    /// 同じサイズの別のバッキングストアから指定領域をコピー
+    ///
+    /// # Arguments
+    /// * `other` - コピー元
+    /// * `area` - コピーする領域（ローカル座標、両方のバッファ内にクリップされる）
+    pub fn copy_from(&mut self, other: &BackingStore, area: &Region) {
+        let Some(area) = area
+            .intersect(&self.bounds())
+            .and_then(|a| a.intersect(&other.bounds()))
+        else {
+            return;
+        };
+        let dst_stride = self.width as usize;
+        let src_stride = other.width as usize;
+        let src = other.buffer.as_slice();
+        let dst = self.buffer.as_mut_slice();
+        for y in area.y as usize..area.bottom() as usize {
+            let from = y * src_stride + area.x as usize;
+            let to = y * dst_stride + area.x as usize;
+            simd::copy_row(
+                &mut dst[to..to + area.width as usize],
+                &src[from..from + area.width as usize],
+            );
+        }
+    }
+
+    /// 別のバッファの矩形を任意の位置へコピー
+    ///
+    /// # Arguments
+    /// * `src` - コピー元
+    /// * `src_rect` - コピー元の領域（コピー元の座標、範囲外はクリップされる）
+    /// * `dst_x`, `dst_y` - コピー先の左上（このバッファの座標）
+    ///
+    /// # Returns
+    /// 変更された領域。重なりがなければNone
+    pub fn copy_rect_from(
+        &mut self,
+        src: &BackingStore,
+        src_rect: &Region,
+        dst_x: u32,
+        dst_y: u32,
+    ) -> Option<Region> {
+        let src_area = src_rect.intersect(&src.bounds())?;
+        // クリップでコピー元の左上がずれた分だけコピー先もずらす
+        let dst_x = dst_x.checked_add(src_area.x - src_rect.x)?;
+        let dst_y = dst_y.checked_add(src_area.y - src_rect.y)?;
+        let dst_area =
+            Region::new(dst_x, dst_y, src_area.width, src_area.height).intersect(&self.bounds())?;
+
+        let src_stride = src.width as usize;
+        let dst_stride = self.width as usize;
+        let src_pixels = src.buffer.as_slice();
+        let dst_pixels = self.buffer.as_mut_slice();
+        let offset_x = (src_area.x + (dst_area.x - dst_x)) as usize;
+        let offset_y = src_area.y + (dst_area.y - dst_y);
+        for row in 0..dst_area.height {
+            let from = (offset_y + row) as usize * src_stride + offset_x;
+            let to = (dst_area.y + row) as usize * dst_stride + dst_area.x as usize;
+            simd::copy_row(
+                &mut dst_pixels[to..to + dst_area.width as usize],
+                &src_pixels[from..from + dst_area.width as usize],
+            );
+        }
+        Some(dst_area)
+    }
+
+    /// このバッファ内の矩形を別の位置へコピー（重なっていてもよい）
+    ///
+    /// # Arguments
+    /// * `src_rect` - コピー元の領域（範囲外はクリップされる）
+    /// * `dst_x`, `dst_y` - コピー先の左上
+    ///
+    /// # Returns
+    /// 変更された領域。バッファ内に収まる部分がなければNone
+    pub fn copy_within(&mut self, src_rect: &Region, dst_x: u32, dst_y: u32) -> Option<Region> {
+        let bounds = self.bounds();
+        let src_area = src_rect.intersect(&bounds)?;
+        let dst_x = dst_x.checked_add(src_area.x - src_rect.x)?;
+        let dst_y = dst_y.checked_add(src_area.y - src_rect.y)?;
+        let dst_area =
+            Region::new(dst_x, dst_y, src_area.width, src_area.height).intersect(&bounds)?;
+
+        let stride = self.width as usize;
+        let offset_x = (src_area.x + (dst_area.x - dst_x)) as usize;
+        let offset_y = src_area.y + (dst_area.y - dst_y);
+        let pixels = self.buffer.as_mut_slice();
+        let copy_row = |pixels: &mut [u32], row: u32| {
+            let from = (offset_y + row) as usize * stride + offset_x;
+            let to = (dst_area.y + row) as usize * stride + dst_area.x as usize;
+            pixels.copy_within(from..from + dst_area.width as usize, to);
+        };
+        // 下へずらす場合は、まだ読んでいない行を上書きしないよう下の行からコピーする
+        if dst_area.y > offset_y {
+            (0..dst_area.height)
+                .rev()
+                .for_each(|row| copy_row(pixels, row));
+        } else {
+            (0..dst_area.height).for_each(|row| copy_row(pixels, row));
+        }
+        Some(dst_area)
+    }
+
+    /// 描画コマンドを描画
+    ///
+    /// # Returns
+    /// 変更された領域（ローカル座標）。何も描画されなければNone
+    pub fn render(&mut self, list: &DrawList) -> Option<Region> {
+        let base = self.buffer.as_ptr() as u64;
+        let stride = self.width;
+        let bounds = self.bounds();
+        let mut damage: Option<Region> = None;
+
+        for cmd in list.commands() {
+            let changed = match cmd {
+                DrawCommand::Clear { color } => {
+                    self.fill(*color);
+                    Some(bounds)
+                }
+                DrawCommand::DrawChar {
+                    x,
+                    y,
+                    ch,
+                    color,
+                    typeface,
+                } => {
+                    // SAFETY: クリップ領域はバッファ全体であり、書き込みはバッファ内に限られる
+                    unsafe {
+                        super::draw_char_clipped(
+                            base, stride, *x, *y, *ch, *color, typeface, &bounds,
+                        )
+                    };
+                    Region::new(
+                        *x,
+                        *y,
+                        typeface.cell_width() as u32,
+                        typeface.cell_height() as u32,
+                    )
+                    .intersect(&bounds)
+                }
+                DrawCommand::DrawString {
+                    x,
+                    y,
+                    text,
+                    color,
+                    typeface,
+                } => {
+                    let text = list.text(*text);
+                    // SAFETY: 同上
+                    unsafe {
+                        super::draw_string_clipped(
+                            base, stride, *x, *y, text, *color, typeface, &bounds,
+                        )
+                    };
+                    let text_width =
+                        (text.chars().count() as u32).saturating_mul(typeface.cell_width() as u32);
+                    Region::new(*x, *y, text_width, typeface.cell_height() as u32)
+                        .intersect(&bounds)
+                }
+                DrawCommand::FillRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                    color,
+                } => {
+                    let visible = Region::new(*x, *y, *width, *height).intersect(&bounds);
+                    if let Some(visible) = visible {
+                        // SAFETY: visibleはバッファ内にクリップ済み
+                        unsafe {
+                            super::draw_rect(
+                                base,
+                                stride,
+                                visible.x as usize,
+                                visible.y as usize,
+                                visible.width as usize,
+                                visible.height as usize,
+                                *color,
+                            )
+                        };
+                    }
+                    visible
+                }
+                DrawCommand::DrawBitmap {
+                    x,
+                    y,
+                    width,
+                    height,
+                    pixels,
+                    mode,
+                } => {
+                    // SAFETY: 同上
+                    unsafe {
+                        super::draw_bitmap_clipped(
+                            base,
+                            stride,
+                            *x,
+                            *y,
+                            *width,
+                            *height,
+                            list.pixels(*pixels),
+                            *mode,
+                            &bounds,
+                        )
+                    };
+                    Region::new(*x, *y, *width, *height).intersect(&bounds)
+                }
+                DrawCommand::DrawLine {
+                    x0,
+                    y0,
+                    x1,
+                    y1,
+                    color,
+                } => {
+                    // SAFETY: 同上
+                    unsafe {
+                        super::draw_line_clipped(base, stride, *x0, *y0, *x1, *y1, *color, &bounds)
+                    };
+                    signed_region(
+                        (*x0).min(*x1) as i64,
+                        (*y0).min(*y1) as i64,
+                        (*x0).max(*x1) as i64,
+                        (*y0).max(*y1) as i64,
+                    )
+                    .and_then(|r| r.intersect(&bounds))
+                }
+                DrawCommand::DrawCircle {
+                    cx,
+                    cy,
+                    radius,
+                    color,
+                    filled,
+                } => {
+                    // SAFETY: 同上
+                    unsafe {
+                        super::draw_circle_clipped(
+                            base, stride, *cx, *cy, *radius, *color, *filled, &bounds,
+                        )
+                    };
+                    let (cx, cy, r) = (*cx as i64, *cy as i64, *radius as i64);
+                    signed_region(cx - r, cy - r, cx + r, cy + r).and_then(|r| r.intersect(&bounds))
+                }
+                DrawCommand::CopyRect { src_rect, dst } => self.copy_within(src_rect, dst.0, dst.1),
+                DrawCommand::BlitSurface { id, src_rect, dst } => {
+                    super::compositor::surface_store(*id).and_then(|surface| {
+                        self.copy_rect_from(&surface.lock(), src_rect, dst.0, dst.1)
+                    })
+                }
+            };
+            if let Some(changed) = changed {
+                damage = Some(match damage {
+                    Some(existing) => existing.union(&changed),
+                    None => changed,
+                });
+            }
+        }
+
+        damage
+    }
+
+    /// シャドウバッファへ転送
+    ///
+    /// # Arguments
+    /// * `shadow` - 転送先
+    /// * `origin_x`, `origin_y` - このバッファの左上を置く画面座標
+    /// * `clip` - 転送する領域（画面座標、シャドウバッファ内にクリップ済み）
+    /// * `opacity` - 不透明度（255なら行ごとのコピー、それ未満なら転送先と合成）
+    pub fn blit_to(
+        &self,
+        shadow: &mut ShadowBuffer,
+        origin_x: u32,
+        origin_y: u32,
+        clip: &Region,
+        opacity: u8,
+    ) {
+        let placed = Region::new(origin_x, origin_y, self.width, self.height);
+        let Some(area) = placed.intersect(clip) else {
+            return;
+        };
+        if opacity == 0 {
+            return;
+        }
+        let src_stride = self.width as usize;
+        let dst_stride = shadow.width() as usize;
+        let src = self.buffer.as_slice();
+        let dst = shadow.pixels_mut();
+        for y in area.y..area.bottom() {
+            let from = (y - origin_y) as usize * src_stride + (area.x - origin_x) as usize;
+            let to = y as usize * dst_stride + area.x as usize;
+            let src_row = &src[from..from + area.width as usize];
+            let dst_row = &mut dst[to..to + area.width as usize];
+            if opacity == 0xFF {
+                simd::copy_row(dst_row, src_row);
+            } else {
+                for (d, &s) in dst_row.iter_mut().zip(src_row) {
+                    *d = pixel_format::blend_native(*d, s, opacity);
+                }
+            }
+        }
+    }
+}
+
+/// 符号付きの外接矩形（両端を含む）をRegionに変換（負の部分は切り捨てる）
+fn signed_region(left: i64, top: i64, right: i64, bottom: i64) -> Option<Region> {
+    if right < 0 || bottom < 0 {
+        return None;
+    }
+    let (left, top) = (left.max(0), top.max(0));
+    let clamp = |v: i64| v.min(u32::MAX as i64) as u32;
+    Some(Region::new(
+        clamp(left),
+        clamp(top),
+        clamp(right - left + 1),
+        clamp(bottom - top + 1),
+    ))
+}
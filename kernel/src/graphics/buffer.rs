@@ -1,6 +1,11 @@
 //! 描画バッファと描画コマンド
 
+use super::backing_store::BackingStore;
+use super::color::Color;
+use super::font::Typeface;
+use super::page_buffer::BufferAllocError;
 use super::region::Region;
+use super::surface::SurfaceId;
 use crate::sync::BlockingMutex;
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -14,95 +19,330 @@ use alloc::vec::Vec;
 #[derive(Clone)]
 pub enum DrawCommand {
     /// 文字を描画 (x, y は Region 内のローカル座標)
-    DrawChar { x: u32, y: u32, ch: u8, color: u32 },
+    DrawChar {
+        x: u32,
+        y: u32,
+        ch: char,
+        color: Color,
+        typeface: Typeface,
+    },
     /// 文字列を描画
+    ///
+    /// 文字列そのものはコマンドと一緒に渡す `DrawList` の文字列表に置き、
+    /// コマンドは表内の位置だけを持ちます（コマンドごとの文字列の割り当てをなくすため）。
     DrawString {
         x: u32,
         y: u32,
-        text: String,
-        color: u32,
+        text: TextSpan,
+        color: Color,
+        typeface: Typeface,
     },
-    /// 矩形を塗りつぶし
+    /// 矩形を塗りつぶし（色のアルファが255未満なら既存の内容と合成）
     FillRect {
         x: u32,
         y: u32,
         width: u32,
         height: u32,
-        color: u32,
+        color: Color,
+    },
+    /// ビットマップ（ARGB、行優先）を描画
+    ///
+    /// ピクセルは `DrawList` のピクセル表に置き、コマンドは表内の位置だけを持ちます。
+    DrawBitmap {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: PixelSpan,
+        mode: BlitMode,
+    },
+    /// 線分を描画（端点はローカル座標、領域の外へはみ出した部分はクリップ）
+    DrawLine {
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: Color,
+    },
+    /// 円を描画（`filled` なら塗りつぶし、そうでなければ円周のみ）
+    DrawCircle {
+        cx: i32,
+        cy: i32,
+        radius: u32,
+        color: Color,
+        filled: bool,
     },
+    /// 領域内の矩形を同じバッファ内の別の位置へコピー（スクロール用）
+    ///
+    /// コピー元とコピー先が重なっていても正しくコピーします。コピー元の跡は
+    /// そのまま残るので、必要なら続けて `FillRect` で消してください。
+    CopyRect { src_rect: Region, dst: (u32, u32) },
     /// 領域全体をクリア
-    Clear { color: u32 },
+    Clear { color: Color },
+    /// オフスクリーンサーフェスの一部を転送
+    ///
+    /// `src_rect` はサーフェス内の座標、`dst` は転送先の左上（ローカル座標）です。
+    /// サーフェスが破棄済みの場合は何も描画しません。
+    BlitSurface {
+        id: SurfaceId,
+        src_rect: Region,
+        dst: (u32, u32),
+    },
 }
 
-/// 描画コマンドを格納するバッファ
-pub struct WriterBuffer {
-    /// 描画コマンドのキュー
+/// ビットマップの透明の扱い
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlitMode {
+    /// アルファを無視してそのまま書き込む（最も速い）
+    Opaque,
+    /// ピクセルごとのアルファで書き込み先と合成する
+    Alpha,
+    /// この色のピクセルを透明として書き込まない（スプライト用）
+    ColorKey(Color),
+}
+
+/// `DrawList` のピクセル表内のビットマップの位置（ピクセル単位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelSpan {
+    start: u32,
+    len: u32,
+}
+
+/// `DrawList` の文字列表内の文字列の位置（バイト単位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextSpan {
+    start: u32,
+    len: u32,
+}
+
+/// 描画コマンドの列と、DrawString・DrawBitmapが参照する文字列表・ピクセル表
+///
+/// 文字列は1つのStringに、ピクセルは1つのVecに連結して保持します。`clear()` は容量を
+/// 維持するため、毎フレーム使い回せば定常状態では割り当てが発生しません。
+#[derive(Clone, Default)]
+pub struct DrawList {
+    /// 描画コマンド
     commands: Vec<DrawCommand>,
-    /// バッファが変更されたかのフラグ
-    dirty: bool,
-    /// このバッファの描画領域
-    region: Region,
+    /// DrawStringが参照する文字列表
+    text: String,
+    /// DrawBitmapが参照するピクセル表
+    pixels: Vec<Color>,
 }
 
-impl WriterBuffer {
-    /// 新しいWriterBufferを作成
+impl DrawList {
+    /// 空のDrawListを作成
+    #[allow(dead_code)]
+    pub const fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            text: String::new(),
+            pixels: Vec::new(),
+        }
+    }
+
+    /// 容量を指定して空のDrawListを作成
     ///
     /// # Arguments
-    /// * `region` - このバッファの描画領域
-    pub fn new(region: Region) -> Self {
+    /// * `commands` - コマンド数の初期容量
+    /// * `text` - 文字列表の初期容量（バイト）
+    pub fn with_capacity(commands: usize, text: usize) -> Self {
         Self {
-            commands: Vec::with_capacity(64), // 初期容量64コマンド
-            dirty: false,
-            region,
+            commands: Vec::with_capacity(commands),
+            text: String::with_capacity(text),
+            pixels: Vec::new(),
         }
     }
 
     /// コマンドを追加
     ///
-    /// # Arguments
-    /// * `cmd` - 追加する描画コマンド
-    #[allow(dead_code)]
-    pub fn push_command(&mut self, cmd: DrawCommand) {
-        self.commands.push(cmd);
-        self.dirty = true;
+    /// DrawStringは `push_string()`、DrawBitmapは `push_bitmap()` で追加してください
+    /// （他のDrawListの位置は無効）。
+    pub fn push(&mut self, command: DrawCommand) {
+        self.commands.push(command);
     }
 
-    /// 複数のコマンドを一括で追加（アロケーションフリー）
+    /// 文字列を文字列表にコピーし、DrawStringコマンドを追加
     ///
     /// # Arguments
-    /// * `commands` - 追加する描画コマンドのイテレータ
-    pub fn extend_commands<I: IntoIterator<Item = DrawCommand>>(&mut self, commands: I) {
-        let old_len = self.commands.len();
-        self.commands.extend(commands);
-        if self.commands.len() > old_len {
-            self.dirty = true;
-        }
+    /// * `x`, `y` - 左上（ローカル座標）
+    /// * `text` - 描画する文字列
+    /// * `color` - 文字色
+    /// * `typeface` - 書体
+    pub fn push_string(&mut self, x: u32, y: u32, text: &str, color: Color, typeface: Typeface) {
+        let span = TextSpan {
+            start: self.text.len() as u32,
+            len: text.len() as u32,
+        };
+        self.text.push_str(text);
+        self.commands.push(DrawCommand::DrawString {
+            x,
+            y,
+            text: span,
+            color,
+            typeface,
+        });
     }
 
-    /// コマンドのスライス参照を取得（アロケーションなし）
+    /// ビットマップをピクセル表にコピーし、DrawBitmapコマンドを追加
     ///
-    /// # Returns
-    /// 蓄積された描画コマンドへのスライス参照
+    /// # Arguments
+    /// * `x`, `y` - 左上（ローカル座標）
+    /// * `width`, `height` - ビットマップのサイズ
+    /// * `pixels` - ARGBのピクセル（行優先、`width` × `height` 個）
+    /// * `mode` - 透明の扱い
+    pub fn push_bitmap(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+        mode: BlitMode,
+    ) {
+        let span = PixelSpan {
+            start: self.pixels.len() as u32,
+            len: pixels.len() as u32,
+        };
+        self.pixels.extend_from_slice(pixels);
+        self.commands.push(DrawCommand::DrawBitmap {
+            x,
+            y,
+            width,
+            height,
+            pixels: span,
+            mode,
+        });
+    }
+
+    /// 描画コマンド
     #[inline]
     pub fn commands(&self) -> &[DrawCommand] {
         &self.commands
     }
 
-    /// コマンドをクリアしてダーティフラグをリセット
+    /// 文字列表から文字列を取得
+    ///
+    /// 範囲外の位置（他のDrawListのコマンドなど）には空文字列を返します。
+    pub fn text(&self, span: TextSpan) -> &str {
+        let start = span.start as usize;
+        self.text
+            .get(start..start + span.len as usize)
+            .unwrap_or("")
+    }
+
+    /// ピクセル表からビットマップのピクセルを取得
     ///
-    /// Vecの容量は維持されるため、再アロケーションは発生しません。
+    /// 範囲外の位置（他のDrawListのコマンドなど）には空のスライスを返します。
+    pub fn pixels(&self, span: PixelSpan) -> &[Color] {
+        let start = span.start as usize;
+        self.pixels
+            .get(start..start + span.len as usize)
+            .unwrap_or(&[])
+    }
+
+    /// コマンドがないか
     #[inline]
-    pub fn clear_commands(&mut self) {
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// コマンドと文字列表・ピクセル表を空にする（容量は維持）
+    pub fn clear(&mut self) {
         self.commands.clear();
-        self.dirty = false;
+        self.text.clear();
+        self.pixels.clear();
+    }
+}
+
+/// Writerとcompositorが共有する表示中のバッファ
+///
+/// Writerはローカルのバックバッファに描画した後、`present()` で表示中の
+/// フロントバッファと入れ替えます（ダブルバッファリング）。ロックを保持するのは
+/// 入れ替えと変更領域のコピーの間だけなので、Compositorの転送を妨げません。
+/// フロントバッファは描画後も保持されるため、Writerが描き直さなくても
+/// ウィンドウの移動や重なりの変化で内容が失われることはありません。
+pub struct WriterBuffer {
+    /// 表示中の画素（Compositorはここから転送する）
+    front: BackingStore,
+    /// 前回の合成以降に変更された領域（ローカル座標）
+    damage: Option<Region>,
+    /// Writer以外（リサイズ・背景変更）がフロントバッファを変更した回数
+    ///
+    /// Writerはこの値が変わっていたらバックバッファをフロントから作り直します。
+    generation: u64,
+    /// このバッファの描画領域
+    region: Region,
+}
+
+impl WriterBuffer {
+    /// 背景色で塗りつぶしたWriterBufferを作成
+    ///
+    /// # Arguments
+    /// * `region` - このバッファの描画領域
+    /// * `background` - 初期の背景色
+    ///
+    /// # Errors
+    /// * `BufferAllocError` - フロントバッファを確保できない場合
+    pub fn new(region: Region, background: Color) -> Result<Self, BufferAllocError> {
+        let front = BackingStore::new(region.width, region.height, background)?;
+        Ok(Self {
+            damage: Some(front.bounds()),
+            front,
+            generation: 0,
+            region,
+        })
+    }
+
+    /// 描画済みのバックバッファを表示中のフロントバッファと入れ替え
+    ///
+    /// 入れ替え後、変更領域を新しいフロントから古いフロント（次のバックバッファ）へ
+    /// コピーして、両者の内容を一致させます。
+    ///
+    /// # Arguments
+    /// * `back` - 描画済みのバックバッファ（フロントと同じサイズ）
+    /// * `changed` - バックバッファで変更された領域（ローカル座標）
+    pub fn present(&mut self, back: &mut BackingStore, changed: Region) {
+        core::mem::swap(&mut self.front, back);
+        back.copy_from(&self.front, &changed);
+        self.add_damage(changed);
+    }
+
+    /// 描画コマンドをフロントバッファへ直接描画
+    ///
+    /// バックバッファを確保できなかったWriterが使用します。
+    pub fn render_direct(&mut self, list: &DrawList) {
+        if let Some(changed) = self.front.render(list) {
+            self.add_damage(changed);
+        }
+    }
+
+    fn add_damage(&mut self, changed: Region) {
+        self.damage = Some(match self.damage {
+            Some(existing) => existing.union(&changed),
+            None => changed,
+        });
+    }
+
+    /// 表示中のフロントバッファ
+    #[inline]
+    pub fn front(&self) -> &BackingStore {
+        &self.front
     }
 
-    /// ダーティかどうか
+    /// 前回の合成以降に変更された領域を取得してリセット
     ///
     /// # Returns
-    /// バッファに未描画のコマンドがあればtrue
-    pub fn is_dirty(&self) -> bool {
-        self.dirty
+    /// 変更された領域（ローカル座標）。変更がなければNone
+    #[inline]
+    pub fn take_damage(&mut self) -> Option<Region> {
+        self.damage.take()
+    }
+
+    /// フロントバッファの世代
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
 
     /// 領域を取得
@@ -112,6 +352,41 @@ impl WriterBuffer {
     pub fn region(&self) -> Region {
         self.region
     }
+
+    /// 領域を変更（ウィンドウの移動・リサイズ時）
+    ///
+    /// サイズが変わった場合はフロントバッファを作り直し、重なる部分の内容を引き継ぎます。
+    ///
+    /// # Arguments
+    /// * `region` - 新しい描画領域
+    /// * `background` - 広がった部分を塗りつぶす背景色
+    ///
+    /// # Errors
+    /// * `BufferAllocError` - 新しいフロントバッファを確保できない場合（領域は変更されない）
+    pub fn set_region(
+        &mut self,
+        region: Region,
+        background: Color,
+    ) -> Result<(), BufferAllocError> {
+        if region.width != self.front.width() || region.height != self.front.height() {
+            self.front = self
+                .front
+                .resized(region.width, region.height, background)?;
+            self.generation += 1;
+            self.damage = Some(self.front.bounds());
+        }
+        self.region = region;
+        Ok(())
+    }
+
+    /// フロントバッファを単色で塗りつぶす
+    ///
+    /// Writerは次のflushでバックバッファをフロントから作り直します。
+    pub fn clear(&mut self, color: Color) {
+        self.front.fill(color);
+        self.generation += 1;
+        self.damage = Some(self.front.bounds());
+    }
 }
 
 /// 共有可能なバッファハンドル
@@ -0,0 +1,315 @@
+//! キャンバス（タスク側で描画するダブルバッファ）
+//!
+//! タスクが所有するバックバッファに線・文字・矩形・転送などをタスク自身のCPU時間で描画し、
+//! 完成したフレームだけを `present` でオフスクリーンサーフェス（フロントバッファ）へ
+//! コピーします。ウィンドウへは `DrawCommand::BlitSurface` 1つで転送するため、
+//! グラフのような画素数の多い描画でもCompositorスレッドの負荷と描画コマンドの種類は増えません。
+//!
+//! バックバッファへの描画はロックを取らないため、描画の途中で他のタスクへ切り替わっても
+//! 表示中のフレームは崩れません。`present` ではサーフェスのロックを取り、前回から変更された
+//! 範囲だけをコピーします。
+
+use super::backing_store::BackingStore;
+use super::color::Color;
+use super::compositor;
+use super::font::{CELL_HEIGHT, CELL_WIDTH, Typeface};
+use super::pixel_format;
+use super::region::Region;
+use super::surface::{Surface, SurfaceError, SurfaceId};
+use super::theme;
+use super::writer::TaskWriter;
+
+/// キャンバス
+///
+/// ドロップするとバックバッファは解放されますが、サーフェスは作成したタスクが
+/// 終了するまで残ります。すぐに解放するには `destroy()` を呼び出してください。
+pub struct Canvas {
+    /// フロントバッファ（Compositorのレジストリが所有）
+    surface: Surface,
+    /// バックバッファ（このキャンバスが所有）
+    back: BackingStore,
+    /// 前回の `present` 以降に描画した範囲
+    damage: Option<Region>,
+}
+
+#[allow(dead_code)]
+impl Canvas {
+    /// 現在のテーマの背景色で塗りつぶしたキャンバスを作成
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotInitialized` - Compositorが未初期化の場合
+    /// * `SurfaceError::InvalidSize` - 幅または高さが0の場合
+    /// * `SurfaceError::OutOfMemory` - 画素バッファを確保できない場合
+    pub fn create(width: u32, height: u32) -> Result<Self, SurfaceError> {
+        let surface = Surface::create(width, height)?;
+        let back = match BackingStore::new(width, height, theme::background()) {
+            Ok(back) => back,
+            Err(e) => {
+                let _ = surface.destroy();
+                return Err(e.into());
+            }
+        };
+        Ok(Self {
+            surface,
+            back,
+            damage: None,
+        })
+    }
+
+    /// サーフェスID（`TaskWriter::blit_surface` に指定する）
+    pub fn id(&self) -> SurfaceId {
+        self.surface.id()
+    }
+
+    /// 幅を取得
+    pub fn width(&self) -> u32 {
+        self.back.width()
+    }
+
+    /// 高さを取得
+    pub fn height(&self) -> u32 {
+        self.back.height()
+    }
+
+    /// キャンバス全体を表す領域
+    pub fn bounds(&self) -> Region {
+        self.back.bounds()
+    }
+
+    fn mark(&mut self, changed: Region) {
+        self.damage = Some(match self.damage {
+            Some(existing) => existing.union(&changed),
+            None => changed,
+        });
+    }
+
+    /// 全体を単色で塗りつぶす
+    pub fn clear(&mut self, color: Color) {
+        self.back.fill(color);
+        self.mark(self.bounds());
+    }
+
+    /// 現在のテーマの背景色で塗りつぶす
+    pub fn clear_themed(&mut self) {
+        self.clear(theme::background());
+    }
+
+    /// 1ピクセル描画（範囲外は無視）
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if self.put(x, y, pixel_format::to_native(color)) {
+            self.mark(Region::new(x as u32, y as u32, 1, 1));
+        }
+    }
+
+    /// ネイティブ形式の画素を書き込む
+    ///
+    /// # Returns
+    /// 範囲内で書き込んだ場合はtrue
+    fn put(&mut self, x: i32, y: i32, native: u32) -> bool {
+        if x < 0 || y < 0 || x as u32 >= self.width() || y as u32 >= self.height() {
+            return false;
+        }
+        let stride = self.width() as usize;
+        self.back.pixels_mut()[y as usize * stride + x as usize] = native;
+        true
+    }
+
+    /// 矩形を塗りつぶす（範囲外はクリップ）
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        let Some(visible) = Region::new(x, y, width, height).intersect(&self.bounds()) else {
+            return;
+        };
+        let stride = self.width();
+        let base = self.back.pixels_mut().as_mut_ptr() as u64;
+        // SAFETY: visibleはバックバッファ内にクリップ済み
+        unsafe {
+            super::draw_rect(
+                base,
+                stride,
+                visible.x as usize,
+                visible.y as usize,
+                visible.width as usize,
+                visible.height as usize,
+                color,
+            )
+        };
+        self.mark(visible);
+    }
+
+    /// 矩形の枠線を描画（範囲外はクリップ）
+    pub fn stroke_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let right = x.saturating_add(width - 1);
+        let bottom = y.saturating_add(height - 1);
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, bottom, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(right, y, 1, height, color);
+    }
+
+    /// 線分を描画（Bresenham、範囲外の部分はクリップ）
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let native = pixel_format::to_native(color);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.put(x, y, native);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+
+        // 線分の外接矩形をキャンバス内にクリップして変更範囲とする
+        let left = x0.min(x1).max(0) as u32;
+        let top = y0.min(y1).max(0) as u32;
+        let right = x0.max(x1).max(-1) + 1;
+        let bottom = y0.max(y1).max(-1) + 1;
+        if let Some(changed) = Region::new(
+            left,
+            top,
+            (right as u32).saturating_sub(left),
+            (bottom as u32).saturating_sub(top),
+        )
+        .intersect(&self.bounds())
+        {
+            self.mark(changed);
+        }
+    }
+
+    /// 文字列を描画（範囲外はクリップ）
+    ///
+    /// # Returns
+    /// 描画した文字列の幅（ピクセル、クリップ前）
+    pub fn draw_text(&mut self, x: u32, y: u32, text: &str, color: Color) -> u32 {
+        let bounds = self.bounds();
+        let stride = self.width();
+        let base = self.back.pixels_mut().as_mut_ptr() as u64;
+        // SAFETY: クリップ領域はバックバッファ全体であり、書き込みはバッファ内に限られる
+        unsafe {
+            super::draw_string_clipped(base, stride, x, y, text, color, &Typeface::Builtin, &bounds)
+        };
+        let text_width = (text.chars().count() as u32).saturating_mul(CELL_WIDTH as u32);
+        if let Some(changed) = Region::new(x, y, text_width, CELL_HEIGHT as u32).intersect(&bounds)
+        {
+            self.mark(changed);
+        }
+        text_width
+    }
+
+    /// 別のキャンバスのバックバッファの矩形を転送
+    ///
+    /// # Arguments
+    /// * `src` - 転送元
+    /// * `src_rect` - 転送元の領域（転送元の座標、範囲外はクリップ）
+    /// * `dst_x`, `dst_y` - 転送先の左上
+    pub fn blit(&mut self, src: &Canvas, src_rect: Region, dst_x: u32, dst_y: u32) {
+        if let Some(changed) = self.back.copy_rect_from(&src.back, &src_rect, dst_x, dst_y) {
+            self.mark(changed);
+        }
+    }
+
+    /// 時系列の値を折れ線グラフとして描画
+    ///
+    /// 値は `area` の下端を0、上端を `max` として縦方向に拡大縮小し、
+    /// 横方向には領域の幅いっぱいに等間隔で並べます。`max` を超える値は上端に張り付きます。
+    ///
+    /// # Arguments
+    /// * `area` - グラフを描画する領域
+    /// * `samples` - 値（古い順）
+    /// * `max` - 上端に対応する値（0なら値の最大値）
+    /// * `color` - 線の色
+    pub fn plot(&mut self, area: Region, samples: &[u64], max: u64, color: Color) {
+        if area.width == 0 || area.height == 0 || samples.is_empty() {
+            return;
+        }
+        let max = match max {
+            0 => samples.iter().copied().max().unwrap_or(0).max(1),
+            max => max,
+        };
+        let span_x = area.width as i64 - 1;
+        let span_y = area.height as i64 - 1;
+        let point = |index: usize, value: u64| {
+            let x = match samples.len() {
+                1 => 0,
+                len => index as i64 * span_x / (len as i64 - 1),
+            };
+            let y = span_y - (value.min(max) as u128 * span_y as u128 / max as u128) as i64;
+            ((area.x as i64 + x) as i32, (area.y as i64 + y) as i32)
+        };
+
+        let mut previous = point(0, samples[0]);
+        if samples.len() == 1 {
+            self.set_pixel(previous.0, previous.1, color);
+            return;
+        }
+        for (index, &value) in samples.iter().enumerate().skip(1) {
+            let current = point(index, value);
+            self.draw_line(previous.0, previous.1, current.0, current.1, color);
+            previous = current;
+        }
+    }
+
+    /// バックバッファをフロントバッファ（サーフェス）へ反映
+    ///
+    /// 前回の呼び出し以降に描画した範囲だけをコピーします。ウィンドウに表示するには、
+    /// 続けて `TaskWriter::blit_surface` で転送してflushするか、`present_to` を使用してください。
+    ///
+    /// # Returns
+    /// コピーした領域。何も描画していなければNone
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotFound` - サーフェスが破棄済みの場合
+    pub fn present(&mut self) -> Result<Option<Region>, SurfaceError> {
+        let Some(damage) = self.damage else {
+            return Ok(None);
+        };
+        let store = compositor::surface_store(self.id()).ok_or(SurfaceError::NotFound)?;
+        store.lock().copy_from(&self.back, &damage);
+        self.damage = None;
+        Ok(Some(damage))
+    }
+
+    /// バックバッファを反映し、キャンバス全体をWriterに転送するコマンドを追加
+    ///
+    /// 表示に反映するには、続けて `writer.flush()` を呼び出してください。
+    ///
+    /// # Arguments
+    /// * `writer` - 転送先のWriter
+    /// * `dst_x`, `dst_y` - 転送先の左上（Writerのローカル座標）
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotFound` - サーフェスが破棄済みの場合
+    pub fn present_to(
+        &mut self,
+        writer: &mut TaskWriter,
+        dst_x: u32,
+        dst_y: u32,
+    ) -> Result<(), SurfaceError> {
+        self.present()?;
+        writer.blit_surface(self.id(), self.bounds(), dst_x, dst_y);
+        Ok(())
+    }
+
+    /// キャンバスを破棄（サーフェスも解放）
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotFound` - サーフェスが破棄済みの場合
+    pub fn destroy(self) -> Result<(), SurfaceError> {
+        self.surface.destroy()
+    }
+}
@@ -0,0 +1,186 @@
+//! 色の表現
+//!
+//! カーネル内の色はすべて `Color`（0xAARRGGBB）で扱います。
+//! フレームバッファへ書き込む直前に `pixel_format::to_native` でネイティブ形式へ変換し、
+//! シリアルコンソールへはANSIのTrueColorエスケープシーケンスとして出力します。
+
+use core::fmt;
+
+/// ANSIエスケープ: 文字色・装飾を既定に戻す
+pub const ANSI_RESET: &str = "\x1b[0m";
+
+/// 32bit ARGB色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Color(u32);
+
+#[allow(dead_code)]
+impl Color {
+    pub const BLACK: Color = Color::rgb(0x00, 0x00, 0x00);
+    pub const WHITE: Color = Color::rgb(0xFF, 0xFF, 0xFF);
+    pub const RED: Color = Color::rgb(0xFF, 0x00, 0x00);
+    pub const GREEN: Color = Color::rgb(0x00, 0xFF, 0x00);
+    pub const BLUE: Color = Color::rgb(0x00, 0x00, 0xFF);
+    pub const YELLOW: Color = Color::rgb(0xFF, 0xFF, 0x00);
+    pub const CYAN: Color = Color::rgb(0x00, 0xFF, 0xFF);
+    pub const MAGENTA: Color = Color::rgb(0xFF, 0x00, 0xFF);
+    pub const GRAY: Color = Color::rgb(0xAA, 0xAA, 0xAA);
+    pub const DARK_GRAY: Color = Color::rgb(0x55, 0x55, 0x55);
+    pub const TRANSPARENT: Color = Color::argb(0x00, 0x00, 0x00, 0x00);
+
+    /// 不透明な色を作成
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::argb(0xFF, r, g, b)
+    }
+
+    /// アルファ値付きの色を作成
+    pub const fn argb(a: u8, r: u8, g: u8, b: u8) -> Self {
+        Self((a as u32) << 24 | (r as u32) << 16 | (g as u32) << 8 | b as u32)
+    }
+
+    /// 0x00RRGGBB形式の値から不透明な色を作成
+    pub const fn from_rgb_u32(value: u32) -> Self {
+        Self(0xFF00_0000 | (value & 0x00FF_FFFF))
+    }
+
+    /// 0xAARRGGBB形式の値から色を作成
+    pub const fn from_argb_u32(value: u32) -> Self {
+        Self(value)
+    }
+
+    pub const fn alpha(self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+
+    pub const fn red(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    pub const fn green(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub const fn blue(self) -> u8 {
+        self.0 as u8
+    }
+
+    /// アルファを除いた0x00RRGGBB形式（フレームバッファの内部表現）
+    pub const fn to_rgb_u32(self) -> u32 {
+        self.0 & 0x00FF_FFFF
+    }
+
+    /// 0xAARRGGBB形式
+    pub const fn to_argb_u32(self) -> u32 {
+        self.0
+    }
+
+    /// アルファ値を差し替えた色
+    pub const fn with_alpha(self, alpha: u8) -> Self {
+        Self((self.0 & 0x00FF_FFFF) | (alpha as u32) << 24)
+    }
+
+    /// 2色を線形補間
+    ///
+    /// # Arguments
+    /// * `other` - 補間先の色
+    /// * `t` - 0で `self`、255で `other`
+    pub fn lerp(self, other: Color, t: u8) -> Color {
+        let mix = |a: u8, b: u8| -> u8 {
+            let t = t as u32;
+            ((a as u32 * (255 - t) + b as u32 * t + 127) / 255) as u8
+        };
+        Color::argb(
+            mix(self.alpha(), other.alpha()),
+            mix(self.red(), other.red()),
+            mix(self.green(), other.green()),
+            mix(self.blue(), other.blue()),
+        )
+    }
+
+    /// この色を自身のアルファ値で `background` の上に合成（source-over）
+    ///
+    /// # Returns
+    /// 合成結果（背景が不透明なら結果も不透明）
+    pub fn blend_over(self, background: Color) -> Color {
+        let src_a = self.alpha() as u32;
+        let dst_a = background.alpha() as u32 * (255 - src_a) / 255;
+        let out_a = src_a + dst_a;
+        if out_a == 0 {
+            return Color::TRANSPARENT;
+        }
+        let mix = |s: u8, d: u8| -> u8 {
+            ((s as u32 * src_a + d as u32 * dst_a + out_a / 2) / out_a) as u8
+        };
+        Color::argb(
+            out_a as u8,
+            mix(self.red(), background.red()),
+            mix(self.green(), background.green()),
+            mix(self.blue(), background.blue()),
+        )
+    }
+
+    /// 文字列から色を解析
+    ///
+    /// `#RRGGBB`、`#AARRGGBB`（`#`は省略可）、または色名（`black`、`white` など）を受け付けます。
+    pub fn parse(s: &str) -> Option<Color> {
+        if let Some(color) = NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, color)| color)
+        {
+            return Some(color);
+        }
+
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        match hex.len() {
+            6 => Some(Color::from_rgb_u32(value)),
+            8 => Some(Color::from_argb_u32(value)),
+            _ => None,
+        }
+    }
+
+    /// シリアル端末の文字色をこの色にするANSIエスケープシーケンス
+    pub fn ansi_fg(self) -> AnsiForeground {
+        AnsiForeground(self)
+    }
+}
+
+/// `Color::parse` が受け付ける色名
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("black", Color::BLACK),
+    ("white", Color::WHITE),
+    ("red", Color::RED),
+    ("green", Color::GREEN),
+    ("blue", Color::BLUE),
+    ("yellow", Color::YELLOW),
+    ("cyan", Color::CYAN),
+    ("magenta", Color::MAGENTA),
+    ("gray", Color::GRAY),
+    ("darkgray", Color::DARK_GRAY),
+];
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.alpha() == 0xFF {
+            write!(f, "#{:06X}", self.to_rgb_u32())
+        } else {
+            write!(f, "#{:08X}", self.0)
+        }
+    }
+}
+
+/// ANSI TrueColorの文字色指定（`Color::ansi_fg` で作成）
+pub struct AnsiForeground(Color);
+
+impl fmt::Display for AnsiForeground {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "\x1b[38;2;{};{};{}m",
+            self.0.red(),
+            self.0.green(),
+            self.0.blue()
+        )
+    }
+}
@@ -1,10 +1,11 @@
 //! Compositor - 各Writerのバッファを合成してフレームバッファに描画
 
+use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
-use spin::Mutex as SpinMutex;
 
 /// フレームカウント（Compositorが描画したフレーム数）
 static FRAME_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -15,9 +16,364 @@ static SCREEN_WIDTH: AtomicU32 = AtomicU32::new(0);
 /// 画面高さ
 static SCREEN_HEIGHT: AtomicU32 = AtomicU32::new(0);
 
-use super::buffer::{DrawCommand, SharedBuffer};
+/// ハードウェアフレームバッファのベースアドレス（パニック時にロックなしで参照する）
+static FB_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// ハードウェアフレームバッファの1行のピクセル数（パニック時にロックなしで参照する）
+static FB_STRIDE: AtomicU32 = AtomicU32::new(0);
+
+/// ハードウェアフレームバッファへの転送を止めたか（パニック画面の表示中）
+static FROZEN: AtomicBool = AtomicBool::new(false);
+
+/// ワークスペース（仮想デスクトップ）の数
+pub const WORKSPACE_COUNT: usize = 4;
+
+/// アクティブなワークスペース
+static ACTIVE_WORKSPACE: AtomicUsize = AtomicUsize::new(0);
+
+/// 画面全体の再合成が必要か（ワークスペースの切り替え時など）
+static FULL_REDRAW: AtomicBool = AtomicBool::new(false);
+
+use super::buffer::{SharedBuffer, WriterBuffer};
+use super::color::Color;
+use super::cursor::CursorOverlay;
+use super::font::Typeface;
+use super::page_buffer::{self, PageBufferUsage};
 use super::region::Region;
 use super::shadow_buffer::ShadowBuffer;
+use super::surface::{SharedSurface, SurfaceError, SurfaceId, SurfaceInfo};
+use super::theme;
+use super::window::{WindowError, WindowId, WindowInfo, WindowState};
+use crate::sync::IrqSpinlock;
+use crate::sync::wait_queue::WaitQueue;
+
+// =============================================================================
+// フレームペーシング設定（実行時に変更可能）
+// =============================================================================
+
+/// 選択可能な目標フレームレート
+pub const SUPPORTED_FPS: [u32; 3] = [30, 60, 120];
+
+/// フレームスキップ数の上限
+pub const MAX_FRAME_SKIP_LIMIT: u32 = 10;
+
+/// 目標フレームレート
+static TARGET_FPS: AtomicU32 = AtomicU32::new(60);
+
+/// ペーシング方式（PacingSourceの値）
+static PACING_SOURCE: AtomicU8 = AtomicU8::new(PacingSource::Vsync as u8);
+
+/// Deadline方式で遅延時に一度に読み飛ばせる最大フレーム数
+static MAX_FRAME_SKIP: AtomicU32 = AtomicU32::new(2);
+
+/// 読み飛ばしたフレームの累計
+static SKIPPED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+/// 未処理の描画更新（damage）があるか（起動直後の最初のフレームは必ず描画する）
+static DAMAGE_PENDING: AtomicBool = AtomicBool::new(true);
+
+/// CompositorタスクがDamage待ちでブロックしているか
+static WAITING_FOR_DAMAGE: AtomicBool = AtomicBool::new(false);
+
+/// CompositorタスクのID（Damage通知で起床させるため）
+static COMPOSITOR_TASK_ID: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Vsync方式の周期タイマーが発火した回数
+static VBLANK_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Vsync方式の周期タイマーが登録済みか
+static VBLANK_ARMED: AtomicBool = AtomicBool::new(false);
+
+/// 周期タイマーの発火を待つCompositorタスク
+static VBLANK: WaitQueue = WaitQueue::new();
+
+/// 描画したフレームの所要時間の統計
+static FRAME_TIMES: IrqSpinlock<FrameTimes> = IrqSpinlock::new(FrameTimes::new());
+
+/// フレームペーシングの方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacingSource {
+    /// 毎フレーム描画後に1フレーム分スリープ（処理時間分だけ遅れる）
+    Sleep = 0,
+    /// 固定間隔のデッドラインに合わせて描画し、遅延時はフレームを読み飛ばす
+    Deadline = 1,
+    /// 描画更新があった時のみ描画（目標FPSを上限とする）
+    DamageDriven = 2,
+    /// 周期タイマーで固定間隔に起床し、描画更新があったフレームだけ描画する
+    Vsync = 3,
+}
+
+impl PacingSource {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => PacingSource::Deadline,
+            2 => PacingSource::DamageDriven,
+            3 => PacingSource::Vsync,
+            _ => PacingSource::Sleep,
+        }
+    }
+
+    /// 表示用の名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PacingSource::Sleep => "sleep",
+            PacingSource::Deadline => "deadline",
+            PacingSource::DamageDriven => "damage",
+            PacingSource::Vsync => "vsync",
+        }
+    }
+
+    /// 名前から変換
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sleep" => Some(PacingSource::Sleep),
+            "deadline" => Some(PacingSource::Deadline),
+            "damage" => Some(PacingSource::DamageDriven),
+            "vsync" => Some(PacingSource::Vsync),
+            _ => None,
+        }
+    }
+}
+
+/// 現在のフレームペーシング設定
+#[derive(Debug, Clone, Copy)]
+pub struct PacingConfig {
+    /// 目標フレームレート
+    pub target_fps: u32,
+    /// ペーシング方式
+    pub source: PacingSource,
+    /// 最大フレームスキップ数
+    pub max_frame_skip: u32,
+    /// 読み飛ばしたフレームの累計
+    pub skipped_frames: u64,
+}
+
+/// ペーシング設定のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacingError {
+    /// サポートしていないフレームレート
+    UnsupportedFps(u32),
+    /// フレームスキップ数が上限を超えている
+    FrameSkipTooLarge(u32),
+}
+
+impl core::fmt::Display for PacingError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PacingError::UnsupportedFps(fps) => {
+                write!(f, "Unsupported FPS {} (expected 30, 60 or 120)", fps)
+            }
+            PacingError::FrameSkipTooLarge(skip) => write!(
+                f,
+                "Max frame skip {} exceeds limit {}",
+                skip, MAX_FRAME_SKIP_LIMIT
+            ),
+        }
+    }
+}
+
+/// 目標フレームレートを設定
+///
+/// # Errors
+/// * `PacingError::UnsupportedFps` - 30/60/120以外が指定された場合
+#[allow(dead_code)]
+pub fn set_target_fps(fps: u32) -> Result<(), PacingError> {
+    if !SUPPORTED_FPS.contains(&fps) {
+        return Err(PacingError::UnsupportedFps(fps));
+    }
+    TARGET_FPS.store(fps, Ordering::Relaxed);
+    Ok(())
+}
+
+/// ペーシング方式を設定
+#[allow(dead_code)]
+pub fn set_pacing_source(source: PacingSource) {
+    PACING_SOURCE.store(source as u8, Ordering::Relaxed);
+    // Damage待ち・周期タイマー待ちでブロック中の場合は、新しい方式で再評価させる
+    notify_damage();
+    VBLANK.wake_all();
+}
+
+/// Deadline方式での最大フレームスキップ数を設定
+///
+/// # Errors
+/// * `PacingError::FrameSkipTooLarge` - MAX_FRAME_SKIP_LIMITを超える場合
+#[allow(dead_code)]
+pub fn set_max_frame_skip(skip: u32) -> Result<(), PacingError> {
+    if skip > MAX_FRAME_SKIP_LIMIT {
+        return Err(PacingError::FrameSkipTooLarge(skip));
+    }
+    MAX_FRAME_SKIP.store(skip, Ordering::Relaxed);
+    Ok(())
+}
+
+/// 現在のフレームペーシング設定を取得
+pub fn pacing_config() -> PacingConfig {
+    PacingConfig {
+        target_fps: TARGET_FPS.load(Ordering::Relaxed),
+        source: PacingSource::from_u8(PACING_SOURCE.load(Ordering::Relaxed)),
+        max_frame_skip: MAX_FRAME_SKIP.load(Ordering::Relaxed),
+        skipped_frames: SKIPPED_FRAMES.load(Ordering::Relaxed),
+    }
+}
+
+/// 描画したフレームの所要時間の統計（マイクロ秒）
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTimeStats {
+    /// 計測したフレーム数
+    pub frames: u64,
+    /// 最短
+    pub min_us: u64,
+    /// 平均
+    pub avg_us: u64,
+    /// 最長
+    pub max_us: u64,
+}
+
+/// フレームの所要時間の累計（ナノ秒）
+struct FrameTimes {
+    frames: u64,
+    total_ns: u64,
+    min_ns: u64,
+    max_ns: u64,
+}
+
+impl FrameTimes {
+    const fn new() -> Self {
+        Self {
+            frames: 0,
+            total_ns: 0,
+            min_ns: u64::MAX,
+            max_ns: 0,
+        }
+    }
+
+    fn record(&mut self, ns: u64) {
+        self.frames += 1;
+        self.total_ns = self.total_ns.saturating_add(ns);
+        self.min_ns = self.min_ns.min(ns);
+        self.max_ns = self.max_ns.max(ns);
+    }
+}
+
+/// 描画したフレームの所要時間（合成の開始からハードウェアFBへの転送まで）の統計を取得
+///
+/// 描画するものがなく読み飛ばしたフレームは含みません。
+pub fn frame_time_stats() -> FrameTimeStats {
+    let times = FRAME_TIMES.lock();
+    if times.frames == 0 {
+        return FrameTimeStats {
+            frames: 0,
+            min_us: 0,
+            avg_us: 0,
+            max_us: 0,
+        };
+    }
+    FrameTimeStats {
+        frames: times.frames,
+        min_us: times.min_ns / 1000,
+        avg_us: times.total_ns / times.frames / 1000,
+        max_us: times.max_ns / 1000,
+    }
+}
+
+/// フレームの所要時間の統計をリセット
+pub fn reset_frame_time_stats() {
+    *FRAME_TIMES.lock() = FrameTimes::new();
+}
+
+/// Vsync方式の周期タイマーを登録（登録済みなら何もしない）
+fn arm_vblank() {
+    if !VBLANK_ARMED.swap(true, Ordering::AcqRel) {
+        schedule_vblank(crate::clock::monotonic_ns() + frame_interval_ns());
+    }
+}
+
+/// 目標フレームレートでの1フレームの長さ（ナノ秒）
+fn frame_interval_ns() -> u64 {
+    1_000_000_000 / TARGET_FPS.load(Ordering::Relaxed).max(1) as u64
+}
+
+/// 次の周期タイマーを登録
+///
+/// タイマーはtick境界でしか発火しないため、個々の発火は最大1tick遅れます。期限は
+/// 前の期限に1フレーム分を足して決めるので、遅れが積み重なって周期がずれることはありません。
+fn schedule_vblank(deadline_ns: u64) {
+    let remaining = deadline_ns.saturating_sub(crate::clock::monotonic_ns());
+    let tick_ns = crate::clock::tick_ns().max(1);
+    crate::timer::register_timer(
+        remaining.div_ceil(tick_ns).max(1),
+        crate::timer::TimerClass::Wakeup,
+        alloc::boxed::Box::new(move || on_vblank(deadline_ns)),
+    );
+}
+
+/// 周期タイマーの発火（softirqから呼ばれる）
+fn on_vblank(deadline_ns: u64) {
+    if PacingSource::from_u8(PACING_SOURCE.load(Ordering::Relaxed)) != PacingSource::Vsync {
+        // 他の方式に切り替わった: 周期を止め、待機中のCompositorに方式を再評価させる
+        VBLANK_ARMED.store(false, Ordering::Release);
+        VBLANK.wake_all();
+        return;
+    }
+    VBLANK_SEQ.fetch_add(1, Ordering::AcqRel);
+    VBLANK.wake_all();
+
+    let now = crate::clock::monotonic_ns();
+    let mut next = deadline_ns + frame_interval_ns();
+    if next <= now {
+        // 1フレーム以上遅れた（softirqが長く止まっていた）: 現在時刻に再同期
+        next = now + frame_interval_ns();
+    }
+    schedule_vblank(next);
+}
+
+/// 前回から周期タイマーが発火するまで待機
+///
+/// # Returns
+/// 前回の待機から発火した回数（2以上なら描画が間に合わず周期を逃している）。
+/// 他の方式に切り替わった場合は0
+fn wait_for_vblank(last_seq: &mut u64) -> u64 {
+    arm_vblank();
+    loop {
+        let seq = VBLANK_SEQ.load(Ordering::Acquire);
+        if seq != *last_seq {
+            return seq - core::mem::replace(last_seq, seq);
+        }
+        if PacingSource::from_u8(PACING_SOURCE.load(Ordering::Relaxed)) != PacingSource::Vsync {
+            return 0;
+        }
+        // 確認してから待機キューに入るまでに発火した場合は、次の発火で起床する（1フレーム遅れる）
+        VBLANK.wait();
+    }
+}
+
+/// 描画更新（damage）をCompositorに通知
+///
+/// Writerが共有バッファにコマンドを転送した時に呼び出されます。
+/// Damage駆動モードでブロック中のCompositorを起床させます。
+pub fn notify_damage() {
+    DAMAGE_PENDING.store(true, Ordering::Release);
+    if WAITING_FOR_DAMAGE.swap(false, Ordering::AcqRel) {
+        let id = COMPOSITOR_TASK_ID.load(Ordering::Relaxed);
+        if id != u64::MAX {
+            crate::sched::unblock_task(crate::sched::TaskId::from_u64(id));
+        }
+    }
+}
+
+/// Damageが通知されるまでCompositorタスクをブロック
+fn wait_for_damage() {
+    WAITING_FOR_DAMAGE.store(true, Ordering::Release);
+    if DAMAGE_PENDING.load(Ordering::Acquire) && WAITING_FOR_DAMAGE.swap(false, Ordering::AcqRel) {
+        // 待機登録前にdamageが届いていた
+        return;
+    }
+    // 登録後に通知された場合も、WAKEUP_PENDINGによりブロックせずに戻る
+    crate::sched::block_current_task();
+}
 
 /// Compositorの設定
 #[derive(Clone)]
@@ -28,21 +384,42 @@ pub struct CompositorConfig {
     pub fb_width: u32,
     /// フレームバッファの高さ
     pub fb_height: u32,
+    /// フレームバッファの1行のピクセル数（幅より大きいことがある）
+    pub fb_stride: u32,
     /// リフレッシュ間隔（tick数）
     #[allow(dead_code)]
     pub refresh_interval_ticks: u64,
 }
 
+/// 1フレームで個別に合成する再描画領域の上限（超えた分はまとめて1つの領域にする）
+const MAX_DAMAGE_RECTS: usize = 16;
+
 /// Compositor（シングルトン）
 ///
-/// 全てのWriterバッファを管理します。
+/// 全てのウィンドウ（Writerバッファ）を管理します。
 /// シャドウバッファはcompositor_task()内でローカルに所有し、
 /// トリプルバッファリングを実現します。
 pub struct Compositor {
     /// 設定
     config: CompositorConfig,
-    /// 登録されたバッファのリスト（Copy-on-Write方式でスナップショット取得可能）
-    buffers: Arc<Vec<SharedBuffer>>,
+    /// ウィンドウのリスト（背面→前面の順、Copy-on-Write方式でスナップショット取得可能）
+    windows: Arc<Vec<WindowState>>,
+    /// 次に割り当てるウィンドウID
+    next_window_id: u64,
+    /// ウィンドウの移動・重なり順の変更などで再合成が必要になった領域
+    damage: Vec<Region>,
+    /// オフスクリーンサーフェスのレジストリ
+    surfaces: BTreeMap<SurfaceId, SurfaceEntry>,
+    /// 次に割り当てるサーフェスID
+    next_surface_id: u64,
+}
+
+/// レジストリに登録されたサーフェス
+struct SurfaceEntry {
+    /// 作成したタスク（終了時にサーフェスを解放する）
+    owner: crate::sched::TaskId,
+    /// 画素バッファ
+    store: SharedSurface,
 }
 
 impl Compositor {
@@ -53,40 +430,90 @@ impl Compositor {
     pub fn new(config: CompositorConfig) -> Self {
         Self {
             config,
-            buffers: Arc::new(Vec::new()),
+            windows: Arc::new(Vec::new()),
+            next_window_id: 1,
+            damage: Vec::new(),
+            surfaces: BTreeMap::new(),
+            next_surface_id: 1,
         }
     }
 
-    /// 新しいWriterを登録し、そのバッファへの参照を返す
+    /// ウィンドウを最前面に追加
     ///
-    /// Copy-on-Write方式: 新しいVecを作成してバッファを追加し、Arcを置き換えます。
+    /// Copy-on-Write方式: 新しいVecを作成してウィンドウを追加し、Arcを置き換えます。
     /// これにより、既存のスナップショットは影響を受けません。
     ///
     /// # Arguments
-    /// * `region` - Writer用の描画領域
-    ///
-    /// # Returns
-    /// 共有バッファへの参照
-    pub fn register_writer(&mut self, region: Region) -> SharedBuffer {
-        let buffer = Arc::new(crate::sync::BlockingMutex::new(
-            super::buffer::WriterBuffer::new(region),
-        ));
+    /// * `title` - タイトル（Noneならタイトルバーなし）
+    /// * `content` - コンテンツ領域（画面座標）
+    /// * `background` - 背景色
+    /// * `buffer` - 確保済みの共有バッファ
+    /// * `workspace` - 所属するワークスペース（Noneならすべてに表示）
+    fn add_window(
+        &mut self,
+        title: Option<String>,
+        content: Region,
+        background: Color,
+        buffer: SharedBuffer,
+        workspace: Option<usize>,
+    ) -> WindowId {
+        let id = WindowId::new(self.next_window_id);
+        self.next_window_id += 1;
+
+        let state = WindowState {
+            id,
+            title,
+            content,
+            background,
+            buffer,
+            workspace,
+            opacity: 0xFF,
+        };
+        self.damage.push(state.frame());
 
         // Copy-on-Write: 新しいVecを作成して追加
-        let mut new_buffers = Vec::clone(&self.buffers);
-        new_buffers.push(Arc::clone(&buffer));
-        self.buffers = Arc::new(new_buffers);
+        let mut new_windows = Vec::clone(&self.windows);
+        new_windows.push(state);
+        self.windows = Arc::new(new_windows);
+
+        id
+    }
+
+    /// ウィンドウリストを変更（Copy-on-Write）
+    ///
+    /// 変更前後のウィンドウ領域を再合成の対象に加えます。
+    ///
+    /// # Arguments
+    /// * `id` - 対象のウィンドウ
+    /// * `f` - ウィンドウリストと対象の位置を受け取り、リストを変更する関数
+    fn modify_window<F>(&mut self, id: WindowId, f: F) -> Result<(), WindowError>
+    where
+        F: FnOnce(&mut Vec<WindowState>, usize) -> Result<(), WindowError>,
+    {
+        let index = self
+            .windows
+            .iter()
+            .position(|w| w.id == id)
+            .ok_or(WindowError::NotFound)?;
+        let old_frame = self.windows[index].frame();
 
-        buffer
+        let mut new_windows = Vec::clone(&self.windows);
+        f(&mut new_windows, index)?;
+        if let Some(state) = new_windows.iter().find(|w| w.id == id) {
+            self.damage.push(state.frame());
+        }
+        self.damage.push(old_frame);
+        self.windows = Arc::new(new_windows);
+        Ok(())
     }
 
-    /// バッファリストのスナップショットを取得
+    /// ウィンドウリストと未処理の再合成領域のスナップショットを取得
     ///
-    /// Arcのクローンを返すため、非常に高速です。
-    /// スナップショット取得後にregister_writer()が呼ばれても、
+    /// ウィンドウリストはArcのクローンを返すため、非常に高速です。
+    /// スナップショット取得後にウィンドウが追加・変更されても、
     /// スナップショットは影響を受けません（Copy-on-Write）。
-    pub fn get_buffers_snapshot(&self) -> Arc<Vec<SharedBuffer>> {
-        Arc::clone(&self.buffers)
+    fn take_snapshot(&mut self) -> (Arc<Vec<WindowState>>, Vec<Region>) {
+        (Arc::clone(&self.windows), core::mem::take(&mut self.damage))
     }
 
     /// フレームバッファ設定を取得
@@ -95,101 +522,190 @@ impl Compositor {
     }
 }
 
-/// コマンドをシャドウバッファに描画（Compositorから独立した関数）
+/// ウィンドウの配置が有効か検証
 ///
-/// compositor_task()内でローカルに所有するシャドウバッファに描画します。
-/// これにより、割り込み有効状態で描画処理を実行できます。
+/// コンテンツ領域のサイズが0でなく、タイトルバーを含めて左上が画面内にある必要があります。
+fn validate_geometry(has_title: bool, content: &Region) -> Result<(), WindowError> {
+    let (screen_width, screen_height) = screen_size();
+    let title_fits = !has_title || content.y >= super::window::TITLE_BAR_HEIGHT;
+    if content.width == 0
+        || content.height == 0
+        || !title_fits
+        || content.x >= screen_width
+        || content.y >= screen_height
+    {
+        return Err(WindowError::InvalidGeometry);
+    }
+    Ok(())
+}
+
+/// 重なっている再合成領域をまとめる
+///
+/// 個別に合成すると重複部分を何度も描き直すため、重なる領域は1つに統合します。
+/// 領域数がMAX_DAMAGE_RECTSを超える場合は全体を1つのバウンディングボックスにします。
+fn coalesce_damage(damage: &mut Vec<Region>) {
+    let mut i = 0;
+    while i < damage.len() {
+        let mut merged = false;
+        let mut j = i + 1;
+        while j < damage.len() {
+            if damage[i].intersect(&damage[j]).is_some() {
+                let other = damage.swap_remove(j);
+                damage[i] = damage[i].union(&other);
+                merged = true;
+            } else {
+                j += 1;
+            }
+        }
+        // 統合で領域が広がった場合は、前の領域とも重なり得るため最初からやり直す
+        if merged {
+            i = 0;
+        } else {
+            i += 1;
+        }
+    }
+
+    if damage.len() > MAX_DAMAGE_RECTS {
+        let bounds = damage.iter().skip(1).fold(damage[0], |acc, r| acc.union(r));
+        damage.clear();
+        damage.push(bounds);
+    }
+}
+
+/// 指定領域を背面から前面の順に合成
+///
+/// デスクトップ背景で塗りつぶした後、領域に重なるアクティブなワークスペースの各ウィンドウのタイトルバーと
+/// バッキングストアの画素を領域内にクリップして転送します。前面のウィンドウが後から描かれるため、
+/// 隠れた部分は自然に上書きされます。
 ///
 /// # Arguments
 /// * `shadow_buffer` - 描画先のシャドウバッファ
-/// * `region` - 描画領域
-/// * `commands` - 描画コマンドのスライス
-fn render_commands_to(shadow_buffer: &mut ShadowBuffer, region: &Region, commands: &[DrawCommand]) {
+/// * `windows` - ウィンドウリスト（背面→前面の順）
+/// * `workspace` - アクティブなワークスペース
+/// * `area` - 合成する領域（画面内にクリップ済み）
+///
+/// # Returns
+/// 描画中のバッファのロックを取得できず合成が不完全ならfalse
+fn compose_area(
+    shadow_buffer: &mut ShadowBuffer,
+    windows: &[WindowState],
+    workspace: usize,
+    area: &Region,
+) -> bool {
     let shadow_base = shadow_buffer.base_addr();
     let shadow_width = shadow_buffer.width();
+    let mut complete = true;
 
-    for cmd in commands {
-        match cmd {
-            DrawCommand::Clear { color } => {
-                // 領域全体をクリア
-                unsafe {
-                    super::draw_rect(
-                        shadow_base,
-                        shadow_width,
-                        region.x as usize,
-                        region.y as usize,
-                        region.width as usize,
-                        region.height as usize,
-                        *color,
-                    );
-                }
-                shadow_buffer.mark_dirty(region);
-            }
-            DrawCommand::DrawChar { x, y, ch, color } => {
-                // ローカル座標をグローバル座標に変換
-                let global_x = region.x + x;
-                let global_y = region.y + y;
-                unsafe {
-                    super::draw_char(
-                        shadow_base,
-                        shadow_width,
-                        global_x as usize,
-                        global_y as usize,
-                        *ch,
-                        *color,
-                    );
-                }
-                // 8x8文字のdirty rect
-                shadow_buffer.mark_dirty(&Region::new(global_x, global_y, 8, 8));
-            }
-            DrawCommand::DrawString { x, y, text, color } => {
-                let global_x = region.x + x;
-                let global_y = region.y + y;
-                unsafe {
-                    super::draw_string(
-                        shadow_base,
-                        shadow_width,
-                        global_x as usize,
-                        global_y as usize,
-                        text,
-                        *color,
-                    );
-                }
-                // 文字列全体のdirty rect（幅 = 文字数 * 8）
-                let text_width = (text.len() as u32) * 8;
-                shadow_buffer.mark_dirty(&Region::new(global_x, global_y, text_width, 8));
+    // 背景は前のフレームの内容を残さないよう、テーマの色のアルファに関係なく不透明で塗る
+    // SAFETY: areaは画面内にクリップ済みで、シャドウバッファは画面サイズ分確保されている
+    unsafe {
+        fill_region(
+            shadow_base,
+            shadow_width,
+            area,
+            theme::background().with_alpha(0xFF),
+        )
+    };
+
+    for window in windows {
+        if !window.is_visible_on(workspace)
+            || window.opacity == 0
+            || window.frame().intersect(area).is_none()
+        {
+            continue;
+        }
+
+        if let Some(bar) = window.title_bar().and_then(|bar| bar.intersect(area)) {
+            let (title_x, title_y) = window.title_origin();
+            let title = window.title.as_deref().unwrap_or("");
+            // SAFETY: barは画面内のareaにクリップ済み
+            unsafe {
+                fill_region(
+                    shadow_base,
+                    shadow_width,
+                    &bar,
+                    theme::accent().with_alpha(window.opacity),
+                );
+                super::draw_string_clipped(
+                    shadow_base,
+                    shadow_width,
+                    title_x,
+                    title_y,
+                    title,
+                    theme::background().with_alpha(window.opacity),
+                    &Typeface::Builtin,
+                    &bar,
+                );
             }
-            DrawCommand::FillRect {
-                x,
-                y,
-                width,
-                height,
-                color,
-            } => {
-                let global_x = region.x + x;
-                let global_y = region.y + y;
-                unsafe {
-                    super::draw_rect(
-                        shadow_base,
-                        shadow_width,
-                        global_x as usize,
-                        global_y as usize,
-                        *width as usize,
-                        *height as usize,
-                        *color,
-                    );
+        }
+
+        let Some(clip) = window.content.intersect(area) else {
+            continue;
+        };
+        match window.buffer.try_lock() {
+            Some(buf) => {
+                let front = buf.front();
+                let stored = Region::new(
+                    window.content.x,
+                    window.content.y,
+                    front.width(),
+                    front.height(),
+                );
+                // リサイズ直後などでバッファがコンテンツ領域より小さい場合は背景色で補う
+                if stored.intersect(&clip).map(|r| (r.width, r.height))
+                    != Some((clip.width, clip.height))
+                {
+                    // SAFETY: clipは画面内のareaにクリップ済み
+                    unsafe {
+                        fill_region(
+                            shadow_base,
+                            shadow_width,
+                            &clip,
+                            window.background.with_alpha(window.opacity),
+                        )
+                    };
                 }
-                shadow_buffer.mark_dirty(&Region::new(global_x, global_y, *width, *height));
+                front.blit_to(
+                    shadow_buffer,
+                    window.content.x,
+                    window.content.y,
+                    &clip,
+                    window.opacity,
+                );
             }
+            // Writerがフロントバッファを入れ替え中: 次のフレームで描き直す
+            None => complete = false,
         }
     }
+
+    shadow_buffer.mark_dirty(area);
+    complete
+}
+
+/// 領域を単色で塗りつぶす
+///
+/// # Safety
+/// regionが画面内に収まっている必要がある
+unsafe fn fill_region(shadow_base: u64, shadow_width: u32, region: &Region, color: Color) {
+    // SAFETY: 呼び出し元が領域の有効性を保証する
+    unsafe {
+        super::draw_rect(
+            shadow_base,
+            shadow_width,
+            region.x as usize,
+            region.y as usize,
+            region.width as usize,
+            region.height as usize,
+            color,
+        );
+    }
 }
 
 // グローバルCompositorインスタンス
 lazy_static! {
     /// グローバルCompositorインスタンス
     /// 初期化前はNone
-    static ref COMPOSITOR: SpinMutex<Option<Compositor>> = SpinMutex::new(None);
+    static ref COMPOSITOR: IrqSpinlock<Option<Compositor>> = IrqSpinlock::new(None);
 }
 
 /// Compositorを初期化
@@ -199,6 +715,8 @@ lazy_static! {
 pub fn init_compositor(config: CompositorConfig) {
     // 画面サイズをグローバル変数に保存
     SCREEN_WIDTH.store(config.fb_width, Ordering::Relaxed);
+    FB_BASE.store(config.fb_base, Ordering::Relaxed);
+    FB_STRIDE.store(config.fb_stride, Ordering::Relaxed);
     SCREEN_HEIGHT.store(config.fb_height, Ordering::Relaxed);
 
     let mut comp = COMPOSITOR.lock();
@@ -223,44 +741,431 @@ pub fn screen_size() -> (u32, u32) {
     )
 }
 
+/// 次のフレームで画面全体を合成し直し、ハードウェアフレームバッファへ転送させる
+///
+/// フレームバッファをCompositor以外が書き換えた後（ベンチマークなど）に呼び出します。
+pub fn redraw_all() {
+    FULL_REDRAW.store(true, Ordering::Release);
+    notify_damage();
+}
+
+/// ハードウェアフレームバッファの配置
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    /// ベースアドレス
+    pub base: u64,
+    /// 幅（ピクセル）
+    pub width: u32,
+    /// 高さ（ピクセル）
+    pub height: u32,
+    /// 1行のピクセル数（幅より大きいことがある）
+    pub stride: u32,
+}
+
+impl Framebuffer {
+    /// 行末の余白を含めたバイト数
+    pub fn size_bytes(&self) -> usize {
+        self.stride as usize * self.height as usize * 4
+    }
+}
+
+/// ハードウェアフレームバッファの情報を取得
+///
+/// # Returns
+/// Compositorが未初期化の場合はNone
+pub fn framebuffer() -> Option<Framebuffer> {
+    COMPOSITOR.lock().as_ref().map(|c| Framebuffer {
+        base: c.config.fb_base,
+        width: c.config.fb_width,
+        height: c.config.fb_height,
+        stride: c.config.fb_stride,
+    })
+}
+
+/// ハードウェアフレームバッファへの転送を止め、フレームバッファの情報を取得（パニック画面用）
+///
+/// 以後Compositorは合成を続けても画面には転送しないため、呼び出し元が直接描画した内容が
+/// 上書きされません。ロックを取らないため、パニック中でも呼び出せます。
+///
+/// # Returns
+/// Compositorが未初期化の場合はNone
+pub fn freeze() -> Option<Framebuffer> {
+    FROZEN.store(true, Ordering::Release);
+    let base = FB_BASE.load(Ordering::Relaxed);
+    let (width, height) = screen_size();
+    let stride = FB_STRIDE.load(Ordering::Relaxed);
+    (base != 0).then_some(Framebuffer {
+        base,
+        width,
+        height,
+        stride,
+    })
+}
+
+/// コンポジタのメモリ使用量を取得
+///
+/// シャドウバッファなど、フレームアロケータから確保したピクセルバッファの合計です。
+pub fn memory_usage() -> PageBufferUsage {
+    page_buffer::usage()
+}
+
+/// Compositorのロックを取得し、処理を実行
+///
+/// ロック保持中は割り込みが無効になるため、プリエンプトされません。
+///
+/// # Errors
+/// * `WindowError::NotInitialized` - Compositorが未初期化の場合
+fn with_compositor<R>(f: impl FnOnce(&mut Compositor) -> R) -> Result<R, WindowError> {
+    COMPOSITOR
+        .lock()
+        .as_mut()
+        .map(f)
+        .ok_or(WindowError::NotInitialized)
+}
+
 /// 新しいWriterを登録（タスク作成時に呼ばれる）
 ///
+/// タイトルバーを持たず、現在のテーマの背景色で塗りつぶしたウィンドウとして最前面に追加されます。
+///
 /// # Arguments
 /// * `region` - Writer用の描画領域
 ///
 /// # Returns
-/// 共有バッファへの参照。Compositorが未初期化ならNone
-///
-/// # Note
-/// 割り込みを無効化してロックを取得することで、
-/// ロック保持中にプリエンプトされることを防ぎます。
+/// 共有バッファへの参照。Compositorが未初期化、またはバッファを確保できなければNone
 pub fn register_writer(region: Region) -> Option<SharedBuffer> {
-    // 割り込みを無効化してロック取得（スピンロック競合回避）
-    let flags = unsafe {
-        let flags: u64;
-        core::arch::asm!(
-            "pushfq",
-            "pop {}",
-            "cli",
-            out(reg) flags,
-            options(nomem, nostack)
-        );
-        flags
+    add_window(None, region, theme::background())
+        .ok()
+        .map(|(_, buffer)| buffer)
+}
+
+/// すべてのワークスペースに表示するWriterを登録
+///
+/// デバッグオーバーレイなど、ワークスペースを切り替えても表示し続ける領域に使用します。
+///
+/// # Returns
+/// 共有バッファへの参照。Compositorが未初期化、またはバッファを確保できなければNone
+pub fn register_overlay(region: Region) -> Option<SharedBuffer> {
+    let (id, buffer) = add_window(None, region, theme::background()).ok()?;
+    move_window_to_workspace(id, None).ok()?;
+    Some(buffer)
+}
+
+/// ウィンドウをアクティブなワークスペースの最前面に追加
+///
+/// バッキングストアはCompositorのロックの外で確保します。
+///
+/// # Errors
+/// * `WindowError::NotInitialized` - Compositorが未初期化の場合
+/// * `WindowError::InvalidGeometry` - 領域が画面外、またはタイトルバーが画面上端からはみ出す場合
+/// * `WindowError::OutOfMemory` - バッキングストアを確保できない場合
+pub(super) fn add_window(
+    title: Option<String>,
+    content: Region,
+    background: Color,
+) -> Result<(WindowId, SharedBuffer), WindowError> {
+    validate_geometry(title.is_some(), &content)?;
+    let buffer = WriterBuffer::new(content, background).map_err(|_| WindowError::OutOfMemory)?;
+    let buffer = Arc::new(crate::sync::BlockingMutex::new(buffer));
+    let workspace = Some(active_workspace());
+    let id = with_compositor(|c| {
+        c.add_window(title, content, background, Arc::clone(&buffer), workspace)
+    })?;
+    notify_damage();
+    Ok((id, buffer))
+}
+
+/// ウィンドウの属性（タイトル・背景色など）を変更
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+/// * `WindowError::InvalidGeometry` - 変更後の配置が無効な場合
+pub(super) fn update_window<F>(id: WindowId, f: F) -> Result<(), WindowError>
+where
+    F: FnOnce(&mut WindowState),
+{
+    with_compositor(|c| {
+        c.modify_window(id, |windows, index| {
+            let mut state = windows[index].clone();
+            f(&mut state);
+            validate_geometry(state.title.is_some(), &state.content)?;
+            windows[index] = state;
+            Ok(())
+        })
+    })??;
+    notify_damage();
+    Ok(())
+}
+
+/// ウィンドウを移動
+///
+/// # Arguments
+/// * `id` - 対象のウィンドウ
+/// * `x`, `y` - タイトルバーを含むウィンドウの新しい左上の座標
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+/// * `WindowError::InvalidGeometry` - 左上が画面外になる場合
+pub fn move_window(id: WindowId, x: u32, y: u32) -> Result<(), WindowError> {
+    update_window(id, |state| state.content = state.content_at(x, y))?;
+    // Writerのバッファにも反映（画素はローカル座標のため描き直しは不要）
+    sync_buffer_region(id)
+}
+
+/// ウィンドウのコンテンツ領域のサイズを変更
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+/// * `WindowError::InvalidGeometry` - サイズが0の場合
+/// * `WindowError::OutOfMemory` - 新しいバッキングストアを確保できない場合
+pub fn resize_window(id: WindowId, width: u32, height: u32) -> Result<(), WindowError> {
+    update_window(id, |state| {
+        state.content.width = width;
+        state.content.height = height;
+    })?;
+    // TaskWriterは次のflush時に新しいサイズで折り返しを行う
+    sync_buffer_region(id)
+}
+
+/// ウィンドウの共有バッファと現在の状態を取得
+fn window_buffer(id: WindowId) -> Option<(SharedBuffer, Region, Color)> {
+    with_compositor(|c| {
+        c.windows
+            .iter()
+            .find(|w| w.id == id)
+            .map(|w| (Arc::clone(&w.buffer), w.content, w.background))
+    })
+    .ok()
+    .flatten()
+}
+
+/// ウィンドウのバッファの領域をCompositorが保持する領域に合わせる
+///
+/// バッファのロックはブロックし得るため、Compositorのロックの外で行います。
+fn sync_buffer_region(id: WindowId) -> Result<(), WindowError> {
+    let Some((buffer, content, background)) = window_buffer(id) else {
+        return Err(WindowError::NotFound);
     };
+    buffer
+        .lock()
+        .set_region(content, background)
+        .map_err(|_| WindowError::OutOfMemory)
+}
+
+/// ウィンドウの背景色を変更し、コンテンツを背景色で消去
+///
+/// Writerは次のflushで描き直した内容を反映します。
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+pub fn set_window_background(id: WindowId, background: Color) -> Result<(), WindowError> {
+    update_window(id, |state| state.background = background)?;
+    let (buffer, _, _) = window_buffer(id).ok_or(WindowError::NotFound)?;
+    buffer.lock().clear(background);
+    notify_damage();
+    Ok(())
+}
+
+/// ウィンドウの不透明度を変更
+///
+/// 半透明のウィンドウは背面のウィンドウと合成して表示します（255で不透明、0で非表示）。
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+pub fn set_window_opacity(id: WindowId, opacity: u8) -> Result<(), WindowError> {
+    update_window(id, |state| state.opacity = opacity)
+}
+
+/// ウィンドウを最前面に移動
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+pub fn raise_window(id: WindowId) -> Result<(), WindowError> {
+    with_compositor(|c| {
+        c.modify_window(id, |windows, index| {
+            let state = windows.remove(index);
+            windows.push(state);
+            Ok(())
+        })
+    })??;
+    notify_damage();
+    Ok(())
+}
+
+/// ウィンドウを閉じる
+///
+/// ウィンドウが覆っていた領域は次のフレームで下のウィンドウから再合成されます。
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+pub fn close_window(id: WindowId) -> Result<(), WindowError> {
+    with_compositor(|c| {
+        c.modify_window(id, |windows, index| {
+            windows.remove(index);
+            Ok(())
+        })
+    })??;
+    notify_damage();
+    Ok(())
+}
+
+/// ウィンドウを別のワークスペースに移動
+///
+/// # Arguments
+/// * `id` - 対象のウィンドウ
+/// * `workspace` - 移動先（Noneならすべてのワークスペースに表示）
+///
+/// # Errors
+/// * `WindowError::NotFound` - 指定したIDのウィンドウが存在しない場合
+/// * `WindowError::InvalidWorkspace` - 移動先のワークスペースが存在しない場合
+pub fn move_window_to_workspace(id: WindowId, workspace: Option<usize>) -> Result<(), WindowError> {
+    if workspace.is_some_and(|w| w >= WORKSPACE_COUNT) {
+        return Err(WindowError::InvalidWorkspace);
+    }
+    update_window(id, |state| state.workspace = workspace)
+}
+
+/// アクティブなワークスペース
+pub fn active_workspace() -> usize {
+    ACTIVE_WORKSPACE.load(Ordering::Relaxed)
+}
 
-    let result = {
-        let mut comp = COMPOSITOR.lock();
-        comp.as_mut().map(|c| c.register_writer(region))
+/// アクティブなワークスペースを切り替え
+///
+/// ロックもメモリ確保も行わないため、キーボード割り込みハンドラから呼び出せます。
+/// 画面は次のフレームで新しいワークスペースのウィンドウから再合成されます。
+///
+/// # Errors
+/// * `WindowError::InvalidWorkspace` - ワークスペースが存在しない場合
+pub fn switch_workspace(workspace: usize) -> Result<(), WindowError> {
+    if workspace >= WORKSPACE_COUNT {
+        return Err(WindowError::InvalidWorkspace);
+    }
+    if ACTIVE_WORKSPACE.swap(workspace, Ordering::Relaxed) != workspace {
+        redraw_all();
+    }
+    Ok(())
+}
+
+/// 隣のワークスペースに切り替え（端では反対側に回り込む）
+///
+/// # Arguments
+/// * `forward` - trueなら次、falseなら前のワークスペース
+pub fn switch_workspace_adjacent(forward: bool) {
+    let current = active_workspace();
+    let next = if forward {
+        (current + 1) % WORKSPACE_COUNT
+    } else {
+        (current + WORKSPACE_COUNT - 1) % WORKSPACE_COUNT
     };
+    let _ = switch_workspace(next);
+}
 
-    // 割り込みを元の状態に復元
-    unsafe {
-        if flags & 0x200 != 0 {
-            core::arch::asm!("sti", options(nomem, nostack));
-        }
+/// ワークスペースごとのウィンドウ数（すべてに表示するウィンドウは含まない）
+pub fn workspace_occupancy() -> [usize; WORKSPACE_COUNT] {
+    let mut counts = [0; WORKSPACE_COUNT];
+    let Ok(snapshot) = with_compositor(|c| Arc::clone(&c.windows)) else {
+        return counts;
+    };
+    for workspace in snapshot.iter().filter_map(|w| w.workspace) {
+        counts[workspace] += 1;
     }
+    counts
+}
+
+/// サーフェスを現在のタスクの所有としてレジストリに登録
+///
+/// # Errors
+/// * `SurfaceError::NotInitialized` - Compositorが未初期化の場合
+pub(super) fn register_surface(store: SharedSurface) -> Result<SurfaceId, SurfaceError> {
+    let owner = crate::sched::current_task_id();
+    with_compositor(|c| {
+        let id = SurfaceId::new(c.next_surface_id);
+        c.next_surface_id += 1;
+        c.surfaces.insert(id, SurfaceEntry { owner, store });
+        id
+    })
+    .map_err(|_| SurfaceError::NotInitialized)
+}
+
+/// サーフェスの画素バッファを取得
+///
+/// 破棄済み、またはCompositorが未初期化ならNone
+pub(super) fn surface_store(id: SurfaceId) -> Option<SharedSurface> {
+    with_compositor(|c| c.surfaces.get(&id).map(|entry| Arc::clone(&entry.store)))
+        .ok()
+        .flatten()
+}
+
+/// サーフェスをレジストリから削除
+///
+/// 画素バッファは転送中の参照がなくなった時点で解放されます。
+///
+/// # Errors
+/// * `SurfaceError::NotFound` - 指定したIDのサーフェスが存在しない場合
+pub(super) fn destroy_surface(id: SurfaceId) -> Result<(), SurfaceError> {
+    let removed =
+        with_compositor(|c| c.surfaces.remove(&id)).map_err(|_| SurfaceError::NotFound)?;
+    // 画素バッファの解放（フレームの返却）は割り込み有効状態で行う
+    removed.map(drop).ok_or(SurfaceError::NotFound)
+}
+
+/// 終了したタスクが所有するサーフェスをすべて解放
+///
+/// Reaperがタスクを回収した後に呼び出します。
+///
+/// # Returns
+/// 解放したサーフェスの数
+pub fn release_task_surfaces(task_id: crate::sched::TaskId) -> usize {
+    let Ok(removed) = with_compositor(|c| {
+        let ids: Vec<SurfaceId> = c
+            .surfaces
+            .iter()
+            .filter(|(_, entry)| entry.owner == task_id)
+            .map(|(&id, _)| id)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| c.surfaces.remove(&id))
+            .collect::<Vec<_>>()
+    }) else {
+        return 0;
+    };
+    removed.len()
+}
+
+/// サーフェスの一覧を取得（ID順）
+pub fn surfaces() -> Vec<SurfaceInfo> {
+    let Ok(entries) = with_compositor(|c| {
+        c.surfaces
+            .iter()
+            .map(|(&id, entry)| (id, entry.owner, Arc::clone(&entry.store)))
+            .collect::<Vec<_>>()
+    }) else {
+        return Vec::new();
+    };
+    entries
+        .into_iter()
+        .map(|(id, owner, store)| {
+            let store = store.lock();
+            SurfaceInfo {
+                id,
+                owner,
+                width: store.width(),
+                height: store.height(),
+            }
+        })
+        .collect()
+}
 
-    result
+/// ウィンドウの一覧を取得（背面→前面の順）
+pub fn windows() -> Vec<WindowInfo> {
+    let Ok(snapshot) = with_compositor(|c| Arc::clone(&c.windows)) else {
+        return Vec::new();
+    };
+    snapshot
+        .iter()
+        .enumerate()
+        .map(|(z, state)| WindowInfo::from_state(state, z))
+        .collect()
 }
 
 /// Compositorタスクのエントリポイント
@@ -275,98 +1180,177 @@ pub extern "C" fn compositor_task() -> ! {
     crate::info!("[Compositor] Started (double buffering)");
 
     // 初期化: 設定を取得（短いクリティカルセクション）
-    let config = {
-        let flags = unsafe {
-            let flags: u64;
-            core::arch::asm!(
-                "pushfq",
-                "pop {}",
-                "cli",
-                out(reg) flags,
-                options(nomem, nostack)
-            );
-            flags
-        };
+    let config = COMPOSITOR
+        .lock()
+        .as_ref()
+        .map(|c| c.get_config().clone())
+        .expect("Compositor not initialized");
 
-        let cfg = {
-            let comp = COMPOSITOR.lock();
-            comp.as_ref().map(|c| c.get_config().clone())
-        };
-
-        unsafe {
-            if flags & 0x200 != 0 {
-                core::arch::asm!("sti", options(nomem, nostack));
-            }
+    // シャドウバッファをタスクローカルで所有（ダブルバッファリング）
+    let mut shadow_buffer = match ShadowBuffer::new(config.fb_width, config.fb_height) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            crate::error!("[Compositor] Failed to allocate shadow buffer: {}", e);
+            crate::sched::exit();
         }
-
-        cfg.expect("Compositor not initialized")
     };
 
-    // シャドウバッファをタスクローカルで所有（ダブルバッファリング）
-    let mut shadow_buffer = ShadowBuffer::new(config.fb_width, config.fb_height);
-
     crate::info!(
         "[Compositor] Shadow buffer initialized: {}x{}",
         config.fb_width,
         config.fb_height
     );
 
+    COMPOSITOR_TASK_ID.store(crate::sched::current_task_id().as_u64(), Ordering::Relaxed);
+
+    // マウスカーソル（シャドウバッファ上で描画・復元）
+    let mut cursor = CursorOverlay::new();
+
+    // ロックを取得できず次のフレームに持ち越した再合成領域
+    let mut deferred_damage: Vec<Region> = Vec::new();
+
+    // Deadline方式の次の描画期限（ミリ秒）
+    let mut next_deadline_ms = crate::hpet::elapsed_ms();
+
+    // Vsync方式で最後に処理した周期タイマーの発火回数
+    let mut last_vblank = VBLANK_SEQ.load(Ordering::Acquire);
+
     loop {
-        // Phase 1: バッファリストのスナップショット取得（割り込み無効、数μs）
-        let buffers_snapshot = {
-            let flags = unsafe {
-                let flags: u64;
-                core::arch::asm!(
-                    "pushfq",
-                    "pop {}",
-                    "cli",
-                    out(reg) flags,
-                    options(nomem, nostack)
-                );
-                flags
-            };
+        let pacing = pacing_config();
+        let frame_interval_ms = (1000 / pacing.target_fps as u64).max(1);
 
-            let snapshot = {
-                let comp = COMPOSITOR.lock();
-                comp.as_ref().map(|c| c.get_buffers_snapshot())
-            };
+        // Damage駆動: 更新があるまで待機
+        if pacing.source == PacingSource::DamageDriven
+            && !DAMAGE_PENDING.swap(false, Ordering::AcqRel)
+        {
+            wait_for_damage();
+            continue;
+        }
 
-            unsafe {
-                if flags & 0x200 != 0 {
-                    core::arch::asm!("sti", options(nomem, nostack));
-                }
+        // Vsync: 周期タイマーの発火を待ち、描画更新がなければこのフレームを読み飛ばす
+        if pacing.source == PacingSource::Vsync {
+            let fired = wait_for_vblank(&mut last_vblank);
+            if fired > 1 {
+                SKIPPED_FRAMES.fetch_add(fired - 1, Ordering::Relaxed);
+            }
+            if fired == 0 || !DAMAGE_PENDING.swap(false, Ordering::AcqRel) {
+                continue;
             }
+        }
+        let frame_start_ns = crate::clock::monotonic_ns();
 
-            match snapshot {
-                Some(s) => s,
-                None => {
-                    crate::sched::sleep_ms(16);
-                    continue;
-                }
+        // Phase 1: ウィンドウリストと再合成領域のスナップショット取得（割り込み無効、数μs）
+        let (windows_snapshot, mut damage) = match with_compositor(|c| c.take_snapshot()) {
+            Ok(snapshot) => snapshot,
+            Err(_) => {
+                // 読み取り済みの更新を次のフレームに持ち越す
+                DAMAGE_PENDING.store(true, Ordering::Release);
+                crate::sched::sleep_ms(16);
+                continue;
             }
         };
+        // 前のフレームで合成しきれなかった領域
+        damage.append(&mut deferred_damage);
 
-        // Phase 2+3: 各バッファから直接レンダリング（アロケーションフリー）
-        // ロックを取得したままレンダリングし、終わったらクリア
-        for buffer in buffers_snapshot.iter() {
-            if let Some(mut buf) = buffer.try_lock() {
-                if buf.is_dirty() {
-                    let region = buf.region();
-                    // スライス参照で直接レンダリング（Vecの移動なし）
-                    render_commands_to(&mut shadow_buffer, &region, buf.commands());
-                    // 容量を維持したままクリア（再アロケーションなし）
-                    buf.clear_commands();
+        // ワークスペースが切り替わった場合などは画面全体を合成し直す
+        let workspace = active_workspace();
+        if FULL_REDRAW.swap(false, Ordering::AcqRel) {
+            damage.push(Region::new(0, 0, config.fb_width, config.fb_height));
+        }
+
+        // Phase 2: 内容が更新されたウィンドウの変更領域を再合成対象に追加
+        // 非表示のワークスペースのウィンドウは変更を読み捨てる（切り替え時に全体を合成する）
+        for window in windows_snapshot.iter() {
+            match window.buffer.try_lock() {
+                Some(mut buf) => {
+                    if let Some(changed) = buf.take_damage()
+                        && window.is_visible_on(workspace)
+                    {
+                        // ローカル座標から画面座標へ変換
+                        damage.push(Region::new(
+                            window.content.x + changed.x,
+                            window.content.y + changed.y,
+                            changed.width,
+                            changed.height,
+                        ));
+                    }
                 }
+                // flush中: 次のフレームで確認する
+                None => DAMAGE_PENDING.store(true, Ordering::Release),
             }
         }
 
+        // カーソルを消してから合成を行う（カーソルの下の内容を最新に保つ）
+        cursor.restore(&mut shadow_buffer);
+
+        // Phase 3: 再合成領域ごとに背面から前面へ合成（割り込み有効）
+        let screen = Region::new(0, 0, config.fb_width, config.fb_height);
+        damage.retain_mut(|area| match area.intersect(&screen) {
+            Some(clipped) => {
+                *area = clipped;
+                true
+            }
+            None => false,
+        });
+        coalesce_damage(&mut damage);
+        // 合成するものがないフレームはトレースを埋めないよう記録しない
+        let traced = !damage.is_empty();
+        if traced {
+            crate::trace::marker("frame start");
+        }
+        for area in &damage {
+            if !compose_area(&mut shadow_buffer, &windows_snapshot, workspace, area) {
+                deferred_damage.push(*area);
+            }
+        }
+        if !deferred_damage.is_empty() {
+            DAMAGE_PENDING.store(true, Ordering::Release);
+        }
+
+        // カーソルを最前面に描画
+        if crate::mouse::is_present() {
+            let (x, y) = crate::mouse::cursor_position();
+            cursor.draw(&mut shadow_buffer, x, y);
+        }
+
         // Phase 4: シャドウバッファをハードウェアFBに転送（割り込み有効）
         // dirty_rectがある場合のみ転送され、転送後にdirty_rectはクリアされる
-        let _blitted = unsafe { shadow_buffer.blit_to(config.fb_base) };
+        // パニック画面の表示中は転送しない
+        if !FROZEN.load(Ordering::Acquire) {
+            let _blitted = unsafe { shadow_buffer.blit_to(config.fb_base, config.fb_stride) };
+        }
 
         FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
+        if traced {
+            crate::trace::marker("frame end");
+            FRAME_TIMES
+                .lock()
+                .record(crate::clock::monotonic_ns().saturating_sub(frame_start_ns));
+        }
 
-        // 次のリフレッシュまで待機（約60fps = 16ms間隔）
-        crate::sched::sleep_ms(16);
+        // 次のフレームまで待機
+        match pacing.source {
+            // 次の周期タイマーの発火はループの先頭で待つ
+            PacingSource::Vsync => {}
+            PacingSource::Sleep | PacingSource::DamageDriven => {
+                crate::sched::sleep_ms(frame_interval_ms);
+            }
+            PacingSource::Deadline => {
+                next_deadline_ms += frame_interval_ms;
+                let now = crate::hpet::elapsed_ms();
+                if now >= next_deadline_ms {
+                    // 期限を過ぎている: 最大max_frame_skipフレームまで読み飛ばして追いつく
+                    let behind = (now - next_deadline_ms) / frame_interval_ms + 1;
+                    let skip = behind.min(pacing.max_frame_skip as u64);
+                    SKIPPED_FRAMES.fetch_add(skip, Ordering::Relaxed);
+                    next_deadline_ms += skip * frame_interval_ms;
+                    if next_deadline_ms <= now {
+                        // スキップ上限でも追いつけない場合は現在時刻に再同期
+                        next_deadline_ms = now + frame_interval_ms;
+                    }
+                }
+                crate::sched::sleep_ms(next_deadline_ms.saturating_sub(now));
+            }
+        }
     }
 }
@@ -0,0 +1,570 @@
+//! テキストコンソール
+//!
+//! 文字セルの格子にテキストを流し込み、最下行に達したら上へスクロールする端末です。
+//! カーネルコンソール（`fbcon`）と `dmesg view` のウィンドウで使います。
+//!
+//! # 設計
+//! - 行は循環バッファに保持し、スクロールは先頭の行の位置をずらすだけで行う。
+//!   画面より多く保持した行はスクロールバックとして `scroll_back` で遡って表示できる
+//! - `\n` `\r` `\t`（8桁ごと）`\x08` と、ANSIエスケープのSGR（8色・明るい8色・TrueColorの
+//!   前景色/背景色、太字は明るい色、リセット）、`ESC[2J`（画面消去）、`ESC[K`（行末まで消去）を解釈する
+//! - 右端に達したら次の行へ折り返す
+//! - 描画は差分で行う。`FramebufferConsole` はスクロールをフレームバッファ内のmemmoveで行い、
+//!   変更された行だけを描き直す
+//!
+//! セルはコンソールの作成時に確保するため、書き込みではヒープを使いません
+//! （ログのシンクから呼び出せます）。
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use super::color::Color;
+use super::font::{CELL_HEIGHT, CELL_WIDTH};
+use super::writer::TaskWriter;
+use super::{draw_char, draw_rect};
+
+/// タブの間隔（桁）
+const TAB_WIDTH: usize = 8;
+
+/// SGRで受け付けるパラメータの最大数（TrueColorの `38;2;r;g;b` が収まる数）
+const MAX_PARAMS: usize = 8;
+
+/// ANSIの8色（30〜37 / 40〜47）
+const ANSI_COLORS: [Color; 8] = [
+    Color::rgb(0x00, 0x00, 0x00),
+    Color::rgb(0xCD, 0x31, 0x31),
+    Color::rgb(0x0D, 0xBC, 0x79),
+    Color::rgb(0xE5, 0xE5, 0x10),
+    Color::rgb(0x24, 0x72, 0xC8),
+    Color::rgb(0xBC, 0x3F, 0xBC),
+    Color::rgb(0x11, 0xA8, 0xCD),
+    Color::rgb(0xE5, 0xE5, 0xE5),
+];
+
+/// ANSIの明るい8色（90〜97 / 100〜107、太字の8色）
+const ANSI_BRIGHT_COLORS: [Color; 8] = [
+    Color::rgb(0x66, 0x66, 0x66),
+    Color::rgb(0xF1, 0x4C, 0x4C),
+    Color::rgb(0x23, 0xD1, 0x8B),
+    Color::rgb(0xF5, 0xF5, 0x43),
+    Color::rgb(0x3B, 0x8E, 0xEA),
+    Color::rgb(0xD6, 0x70, 0xD6),
+    Color::rgb(0x29, 0xB8, 0xDB),
+    Color::rgb(0xFF, 0xFF, 0xFF),
+];
+
+/// 文字セル
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+/// エスケープシーケンスの解析状態
+#[derive(Clone, Copy)]
+enum Escape {
+    /// 通常の文字
+    None,
+    /// ESCを受け取った
+    Esc,
+    /// CSI（`ESC [`）のパラメータを受け取り中
+    Csi {
+        params: [u16; MAX_PARAMS],
+        count: usize,
+    },
+}
+
+/// テキストコンソール
+pub struct TextConsole {
+    columns: usize,
+    rows: usize,
+    /// 保持する行数（画面の行数 + スクロールバック）
+    capacity: usize,
+    /// 行の循環バッファ（capacity × columns）
+    cells: Vec<Cell>,
+    /// 最も古い行の循環バッファ上の位置
+    first: usize,
+    /// 保持している行数（1以上、最後の行にカーソルがある）
+    lines: usize,
+    /// 最も古い行の通し番号（捨てた行の数）
+    first_line: u64,
+    /// カーソルの桁
+    column: usize,
+    fg: Color,
+    bg: Color,
+    /// 太字（明るい色）
+    bold: bool,
+    default_fg: Color,
+    default_bg: Color,
+    escape: Escape,
+    /// スクロールバックで遡っている行数
+    view_offset: usize,
+    /// 前回の描画以降に変更された最初の行の通し番号
+    dirty_from: Option<u64>,
+}
+
+impl TextConsole {
+    /// コンソールを作成
+    ///
+    /// # Arguments
+    /// * `columns`, `rows` - 画面の桁数と行数（それぞれ1以上に切り上げる）
+    /// * `scrollback` - 画面の外に保持する行数
+    /// * `fg`, `bg` - 既定の文字色と背景色（SGR 0/39/49で戻る色）
+    pub fn new(columns: usize, rows: usize, scrollback: usize, fg: Color, bg: Color) -> Self {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let capacity = rows + scrollback;
+        let blank = Cell { ch: ' ', fg, bg };
+        Self {
+            columns,
+            rows,
+            capacity,
+            cells: vec![blank; capacity * columns],
+            first: 0,
+            lines: 1,
+            first_line: 0,
+            column: 0,
+            fg,
+            bg,
+            bold: false,
+            default_fg: fg,
+            default_bg: bg,
+            escape: Escape::None,
+            view_offset: 0,
+            dirty_from: Some(0),
+        }
+    }
+
+    /// 画面の桁数
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// 画面の行数
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// 既定の背景色
+    pub fn background(&self) -> Color {
+        self.default_bg
+    }
+
+    /// 最後の行（カーソルのある行）の通し番号
+    fn last_line(&self) -> u64 {
+        self.first_line + self.lines as u64 - 1
+    }
+
+    /// 通し番号の行のセル（保持していなければNone）
+    pub fn line(&self, line: u64) -> Option<&[Cell]> {
+        let index = line.checked_sub(self.first_line)? as usize;
+        if index >= self.lines {
+            return None;
+        }
+        let start = (self.first + index) % self.capacity * self.columns;
+        Some(&self.cells[start..start + self.columns])
+    }
+
+    /// 画面の最上行の通し番号
+    pub fn view_top(&self) -> u64 {
+        let bottom = self.lines.saturating_sub(self.rows);
+        self.first_line + bottom.saturating_sub(self.view_offset) as u64
+    }
+
+    /// スクロールバックを遡る（負の値で戻る）
+    ///
+    /// # Returns
+    /// 遡っている行数
+    pub fn scroll_back(&mut self, delta: isize) -> usize {
+        let max = self.lines.saturating_sub(self.rows);
+        self.view_offset = self.view_offset.saturating_add_signed(delta).min(max);
+        self.view_offset
+    }
+
+    /// 変更された最初の行の通し番号を取り出す（描画側が呼ぶ）
+    pub fn take_dirty(&mut self) -> Option<u64> {
+        self.dirty_from.take()
+    }
+
+    /// 全体を描き直させる
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_from = Some(self.first_line);
+    }
+
+    fn mark_dirty(&mut self, line: u64) {
+        self.dirty_from = Some(self.dirty_from.map_or(line, |from| from.min(line)));
+    }
+
+    fn blank(&self) -> Cell {
+        Cell {
+            ch: ' ',
+            fg: self.fg,
+            bg: self.bg,
+        }
+    }
+
+    /// カーソルのある行のセル
+    fn current_line_mut(&mut self) -> &mut [Cell] {
+        let start = (self.first + self.lines - 1) % self.capacity * self.columns;
+        &mut self.cells[start..start + self.columns]
+    }
+
+    /// 改行（循環バッファが満杯なら最も古い行を捨てる）
+    fn newline(&mut self) {
+        if self.lines == self.capacity {
+            self.first = (self.first + 1) % self.capacity;
+            self.first_line += 1;
+        } else {
+            self.lines += 1;
+        }
+        // スクロールバックを見ている間は表示位置を保つ
+        if self.view_offset > 0 {
+            self.view_offset = (self.view_offset + 1).min(self.lines.saturating_sub(self.rows));
+        }
+        self.column = 0;
+        let blank = self.blank();
+        self.current_line_mut().fill(blank);
+        self.mark_dirty(self.last_line());
+    }
+
+    /// 文字を1つ置く（右端なら折り返す）
+    fn put(&mut self, ch: char) {
+        if self.column == self.columns {
+            self.newline();
+        }
+        let cell = Cell {
+            ch,
+            fg: self.fg,
+            bg: self.bg,
+        };
+        let column = self.column;
+        self.current_line_mut()[column] = cell;
+        self.column += 1;
+        self.mark_dirty(self.last_line());
+    }
+
+    /// カーソルから行末までを消去
+    fn erase_to_end(&mut self) {
+        let (column, blank) = (self.column.min(self.columns), self.blank());
+        self.current_line_mut()[column..].fill(blank);
+        self.mark_dirty(self.last_line());
+    }
+
+    /// 画面とスクロールバックを消去
+    pub fn clear(&mut self) {
+        self.first_line = self.last_line() + 1;
+        self.first = (self.first + self.lines) % self.capacity;
+        self.lines = 1;
+        self.column = 0;
+        self.view_offset = 0;
+        let blank = self.blank();
+        self.current_line_mut().fill(blank);
+        self.mark_dirty(self.first_line);
+    }
+
+    fn write_char(&mut self, ch: char) {
+        match self.escape {
+            Escape::None => match ch {
+                '\x1b' => self.escape = Escape::Esc,
+                '\n' => self.newline(),
+                '\r' => self.column = 0,
+                '\t' => {
+                    let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                    while self.column < next.min(self.columns) {
+                        self.put(' ');
+                    }
+                }
+                '\x08' => self.column = self.column.saturating_sub(1),
+                // その他の制御文字は無視
+                c if c.is_control() => {}
+                c => self.put(c),
+            },
+            Escape::Esc => {
+                self.escape = match ch {
+                    '[' => Escape::Csi {
+                        params: [0; MAX_PARAMS],
+                        count: 0,
+                    },
+                    // 未対応のシーケンスは読み捨てる
+                    _ => Escape::None,
+                };
+            }
+            Escape::Csi {
+                mut params,
+                mut count,
+            } => match ch {
+                '0'..='9' => {
+                    let digit = ch as u16 - b'0' as u16;
+                    count = count.max(1);
+                    params[count - 1] = params[count - 1].saturating_mul(10).saturating_add(digit);
+                    self.escape = Escape::Csi { params, count };
+                }
+                ';' => {
+                    // 空のパラメータは0
+                    count = (count.max(1) + 1).min(MAX_PARAMS);
+                    self.escape = Escape::Csi { params, count };
+                }
+                // 終端文字（0x40〜0x7E）
+                '@'..='~' => {
+                    self.escape = Escape::None;
+                    self.csi(ch, &params[..count]);
+                }
+                // 中間文字などは無視して読み進める
+                _ => {}
+            },
+        }
+    }
+
+    /// CSIシーケンスを実行
+    fn csi(&mut self, command: char, params: &[u16]) {
+        match command {
+            'm' => self.sgr(params),
+            'J' if params.first() == Some(&2) => self.clear(),
+            'K' if params.first().is_none_or(|&p| p == 0) => self.erase_to_end(),
+            _ => {}
+        }
+    }
+
+    /// SGR（文字色・背景色・装飾）
+    fn sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.reset_attributes();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                p @ 30..=37 => {
+                    let index = (p - 30) as usize;
+                    self.fg = if self.bold {
+                        ANSI_BRIGHT_COLORS[index]
+                    } else {
+                        ANSI_COLORS[index]
+                    };
+                }
+                39 => self.fg = self.default_fg,
+                p @ 40..=47 => self.bg = ANSI_COLORS[(p - 40) as usize],
+                49 => self.bg = self.default_bg,
+                p @ 90..=97 => self.fg = ANSI_BRIGHT_COLORS[(p - 90) as usize],
+                p @ 100..=107 => self.bg = ANSI_BRIGHT_COLORS[(p - 100) as usize],
+                // TrueColor（38;2;r;g;b / 48;2;r;g;b）
+                p @ (38 | 48) if params.get(i + 1) == Some(&2) && i + 4 < params.len() => {
+                    let channel = |v: u16| v.min(0xFF) as u8;
+                    let color = Color::rgb(
+                        channel(params[i + 2]),
+                        channel(params[i + 3]),
+                        channel(params[i + 4]),
+                    );
+                    if p == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                    i += 4;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn reset_attributes(&mut self) {
+        self.fg = self.default_fg;
+        self.bg = self.default_bg;
+        self.bold = false;
+    }
+
+    /// 画面に表示している行をTaskWriterへ描画
+    ///
+    /// 同じ色の文字をまとめて書き込みます。背景色が既定と異なるセルは塗りつぶします。
+    /// セルの大きさはTaskWriterの書体に従います。
+    pub fn render(&mut self, writer: &mut TaskWriter) {
+        self.dirty_from = None;
+        let cell_width = writer.cell_width() as usize;
+        let cell_height = writer.cell_height() as usize;
+        writer.clear(self.default_bg);
+        let top = self.view_top();
+        let mut run = [0u8; 4 * 256];
+        for row in 0..self.rows {
+            let Some(line) = self.line(top + row as u64) else {
+                break;
+            };
+            let y = (row * cell_height) as u32;
+            let mut start = 0;
+            while start < line.len() {
+                let fg = line[start].fg;
+                let bg = line[start].bg;
+                let mut len = 0;
+                let mut end = start;
+                while end < line.len()
+                    && line[end].fg == fg
+                    && line[end].bg == bg
+                    && len + line[end].ch.len_utf8() <= run.len()
+                {
+                    len += line[end].ch.encode_utf8(&mut run[len..]).len();
+                    end += 1;
+                }
+                let x = (start * cell_width) as u32;
+                if bg != self.default_bg {
+                    writer.fill_rect(
+                        x,
+                        y,
+                        ((end - start) * cell_width) as u32,
+                        cell_height as u32,
+                        bg,
+                    );
+                }
+                // runは文字単位でエンコードしているため、常に有効なUTF-8
+                let text = core::str::from_utf8(&run[..len]).unwrap_or("");
+                writer.set_position(x, y);
+                writer.set_color(fg);
+                let _ = writer.write_str(text.trim_end_matches(' '));
+                start = end;
+            }
+        }
+        writer.flush();
+    }
+}
+
+impl fmt::Write for TextConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            self.write_char(ch);
+        }
+        Ok(())
+    }
+}
+
+/// フレームバッファへ直接描画するテキストコンソール
+///
+/// Compositorを経由しないため、Compositorの起動前（カーネルコンソール）にだけ使います。
+pub struct FramebufferConsole {
+    console: TextConsole,
+    fb_base: u64,
+    /// フレームバッファの1行のピクセル数（行の間隔、幅より大きいことがある）
+    stride: u32,
+    /// 前回描画したときの画面の最上行の通し番号
+    drawn_top: Option<u64>,
+}
+
+impl FramebufferConsole {
+    /// フレームバッファ全体を使うコンソールを作成
+    ///
+    /// # Safety
+    /// `fb_base` は1行 `stride` ピクセル × `height` 行の有効なフレームバッファで、
+    /// このコンソールの使用中は他から描画されないこと
+    pub unsafe fn new(
+        fb_base: u64,
+        width: u32,
+        height: u32,
+        stride: u32,
+        scrollback: usize,
+        fg: Color,
+        bg: Color,
+    ) -> Self {
+        let columns = width as usize / CELL_WIDTH;
+        let rows = height as usize / CELL_HEIGHT;
+        Self {
+            console: TextConsole::new(columns, rows, scrollback, fg, bg),
+            fb_base,
+            stride,
+            drawn_top: None,
+        }
+    }
+
+    /// テキストコンソール
+    #[allow(dead_code)]
+    pub fn console(&mut self) -> &mut TextConsole {
+        &mut self.console
+    }
+
+    /// 変更をフレームバッファへ反映
+    ///
+    /// 画面の最上行が進んでいれば、残る行をmemmoveで上へ移してから変更された行だけを描き直します。
+    pub fn sync(&mut self) {
+        let Some(dirty) = self.console.take_dirty() else {
+            return;
+        };
+        let rows = self.console.rows() as u64;
+        let top = self.console.view_top();
+        let redraw_from = match self.drawn_top {
+            Some(drawn) if top >= drawn && top - drawn < rows => {
+                let scroll = (top - drawn) as usize;
+                if scroll > 0 {
+                    self.scroll_pixels(scroll);
+                }
+                // スクロールで空いた下端の行と、変更された行から下を描き直す
+                dirty.min(drawn + rows).max(top)
+            }
+            _ => top,
+        };
+        self.drawn_top = Some(top);
+        for line in redraw_from..top + rows {
+            self.draw_row((line - top) as usize, line);
+        }
+    }
+
+    /// 画面を `lines` 行分上へ移す
+    fn scroll_pixels(&mut self, lines: usize) {
+        let stride = self.stride as usize;
+        let rows = self.console.rows();
+        let shift = lines * CELL_HEIGHT * stride;
+        let keep = (rows - lines) * CELL_HEIGHT * stride;
+        let fb = self.fb_base as *mut u32;
+        // SAFETY: 移動元と移動先はともにrows行分の画面内にある。重なるためcopy（memmove）を使う
+        unsafe { core::ptr::copy(fb.add(shift), fb, keep) };
+    }
+
+    /// 画面の `row` 行目に通し番号 `line` の行を描画（保持していなければ背景で塗る）
+    fn draw_row(&mut self, row: usize, line: u64) {
+        let y = row * CELL_HEIGHT;
+        let width = self.console.columns() * CELL_WIDTH;
+        let background = self.console.background();
+        // SAFETY: 行は画面内（rows × CELL_HEIGHT ≤ height）に収まり、newの呼び出し元が
+        // フレームバッファの有効性を保証する
+        unsafe {
+            draw_rect(
+                self.fb_base,
+                self.stride,
+                0,
+                y,
+                width,
+                CELL_HEIGHT,
+                background,
+            );
+            let Some(cells) = self.console.line(line) else {
+                return;
+            };
+            for (column, cell) in cells.iter().enumerate() {
+                let x = column * CELL_WIDTH;
+                if cell.bg != background {
+                    draw_rect(
+                        self.fb_base,
+                        self.stride,
+                        x,
+                        y,
+                        CELL_WIDTH,
+                        CELL_HEIGHT,
+                        cell.bg,
+                    );
+                }
+                if cell.ch != ' ' {
+                    draw_char(self.fb_base, self.stride, x, y, cell.ch, cell.fg);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Write for FramebufferConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.console.write_str(s)?;
+        self.sync();
+        Ok(())
+    }
+}
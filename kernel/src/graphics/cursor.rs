@@ -0,0 +1,151 @@
+//! マウスカーソルの描画
+//!
+//! Compositorタスクがシャドウバッファ上でカーソルを描画・復元します。
+//! 各フレームで「前回の背景を復元 → Writerの描画 → 新しい位置の背景を保存して描画」の
+//! 順に処理し、ハードウェアフレームバッファへは完成したフレームのみ転送するため、
+//! カーソルがちらつきません。
+//!
+//! スプライトはカラーキー（`CURSOR_KEY` の色が透明）のビットマップとして描画します。
+
+use super::buffer::BlitMode;
+use super::color::Color;
+use super::region::Region;
+use super::shadow_buffer::ShadowBuffer;
+
+/// カーソルの幅（ピクセル）
+const CURSOR_WIDTH: usize = 12;
+
+/// カーソルの高さ（ピクセル）
+const CURSOR_HEIGHT: usize = 19;
+
+/// 輪郭の色
+const OUTLINE_COLOR: Color = Color::BLACK;
+
+/// 塗りつぶしの色
+const FILL_COLOR: Color = Color::WHITE;
+
+/// 矢印カーソルのスプライト（'#' = 輪郭, '.' = 塗り, ' ' = 透明）
+const CURSOR_SPRITE: [&[u8; CURSOR_WIDTH]; CURSOR_HEIGHT] = [
+    b"#           ",
+    b"##          ",
+    b"#.#         ",
+    b"#..#        ",
+    b"#...#       ",
+    b"#....#      ",
+    b"#.....#     ",
+    b"#......#    ",
+    b"#.......#   ",
+    b"#........#  ",
+    b"#.........# ",
+    b"#..........#",
+    b"#......#####",
+    b"#...#..#    ",
+    b"#..# #..#   ",
+    b"#.#  #..#   ",
+    b"##    #..#  ",
+    b"      #..#  ",
+    b"       ##   ",
+];
+
+/// スプライトの透明部分の色（輪郭・塗りには使わない色）
+const CURSOR_KEY: Color = Color::MAGENTA;
+
+/// スプライトのビットマップ（行優先）
+const CURSOR_PIXELS: [Color; CURSOR_WIDTH * CURSOR_HEIGHT] = sprite_pixels();
+
+/// `CURSOR_SPRITE` をカラーキーのビットマップに変換
+const fn sprite_pixels() -> [Color; CURSOR_WIDTH * CURSOR_HEIGHT] {
+    let mut pixels = [CURSOR_KEY; CURSOR_WIDTH * CURSOR_HEIGHT];
+    let mut row = 0;
+    while row < CURSOR_HEIGHT {
+        let mut col = 0;
+        while col < CURSOR_WIDTH {
+            pixels[row * CURSOR_WIDTH + col] = match CURSOR_SPRITE[row][col] {
+                b'#' => OUTLINE_COLOR,
+                b'.' => FILL_COLOR,
+                _ => CURSOR_KEY,
+            };
+            col += 1;
+        }
+        row += 1;
+    }
+    pixels
+}
+
+/// シャドウバッファ上のカーソル
+pub struct CursorOverlay {
+    /// カーソルの下にあった背景（ネイティブ形式）
+    saved: [u32; CURSOR_WIDTH * CURSOR_HEIGHT],
+    /// 背景を保存した領域（画面端でクリップ済み）。未描画ならNone
+    saved_region: Option<Region>,
+}
+
+impl CursorOverlay {
+    /// 未描画状態のカーソルを作成
+    pub const fn new() -> Self {
+        Self {
+            saved: [0; CURSOR_WIDTH * CURSOR_HEIGHT],
+            saved_region: None,
+        }
+    }
+
+    /// 描画済みのカーソルを消して背景を復元
+    ///
+    /// Writerの描画より前に呼び出します。
+    pub fn restore(&mut self, shadow: &mut ShadowBuffer) {
+        let Some(region) = self.saved_region.take() else {
+            return;
+        };
+        let stride = shadow.width() as usize;
+        let pixels = shadow.pixels_mut();
+        for row in 0..region.height as usize {
+            let dst = (region.y as usize + row) * stride + region.x as usize;
+            let src = row * CURSOR_WIDTH;
+            pixels[dst..dst + region.width as usize]
+                .copy_from_slice(&self.saved[src..src + region.width as usize]);
+        }
+        shadow.mark_dirty(&region);
+    }
+
+    /// 指定位置の背景を保存してカーソルを描画
+    ///
+    /// Writerの描画の後、blitの前に呼び出します。
+    ///
+    /// # Arguments
+    /// * `shadow` - 描画先のシャドウバッファ
+    /// * `x`, `y` - カーソルの先端の座標
+    pub fn draw(&mut self, shadow: &mut ShadowBuffer, x: u32, y: u32) {
+        let width = (CURSOR_WIDTH as u32).min(shadow.width().saturating_sub(x));
+        let height = (CURSOR_HEIGHT as u32).min(shadow.height().saturating_sub(y));
+        if width == 0 || height == 0 {
+            return;
+        }
+        let region = Region::new(x, y, width, height);
+
+        let stride = shadow.width() as usize;
+        let pixels = shadow.pixels_mut();
+        for row in 0..height as usize {
+            let line = (y as usize + row) * stride + x as usize;
+            let saved = row * CURSOR_WIDTH;
+            self.saved[saved..saved + width as usize]
+                .copy_from_slice(&pixels[line..line + width as usize]);
+        }
+        // SAFETY: regionはシャドウバッファ内にクリップ済み
+        unsafe {
+            super::draw_bitmap_clipped(
+                shadow.base_addr(),
+                shadow.width(),
+                x,
+                y,
+                CURSOR_WIDTH as u32,
+                CURSOR_HEIGHT as u32,
+                &CURSOR_PIXELS,
+                BlitMode::ColorKey(CURSOR_KEY),
+                &region,
+            )
+        };
+
+        self.saved_region = Some(region);
+        shadow.mark_dirty(&region);
+    }
+}
@@ -1,4 +1,337 @@
-// 8x8 ビットマップフォント（ASCII 32-126）
+//! フォントとグリフのフォールバックチェーン
+//!
+//! 文字は幅 `CELL_WIDTH`、高さ `CELL_HEIGHT` の文字セル単位で描画します。
+//! 文字に対応するグリフは `FONT_CHAIN` のフォントを先頭から順に探し、
+//! どのフォントにもなければ置換グリフ（枠付きの四角）を使用します。
+//!
+//! ASCIIのビットマップは8x8で、セルの下2行は行間として空白になります。
+//! Latin-1補助（U+00A0〜U+00FF）の文字はASCIIのグリフにアクセントを重ねて合成します。
+//! 罫線素片とブロック要素はセル全体を使って合成するため、行をまたいでつながります。
+//!
+//! 組み込みのフォントのほかに、PSF1/PSF2のビットマップフォントを読み込んで
+//! `Typeface` として使えます。読み込んだフォントのセルはグリフと同じ大きさで、
+//! フォントにない文字は組み込みのフォールバックチェーンのグリフをセルの左上に描画します。
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use vitros_common::psf::{PsfError, PsfFont};
+
+use super::{latin1, line_art};
+use crate::sync::IrqSpinlock;
+
+/// 文字セルの幅（ピクセル）
+pub const CELL_WIDTH: usize = 8;
+
+/// 文字セルの高さ（ピクセル、8x8グリフ + 行間2ピクセル）
+pub const CELL_HEIGHT: usize = 10;
+
+/// 1文字分のビットマップ（各行のbit 0が左端）
+pub type Glyph = [u8; CELL_HEIGHT];
+
+/// グリフの提供元
+pub struct Font {
+    /// フォント名（表示用）
+    #[allow(dead_code)]
+    pub name: &'static str,
+    /// 文字に対応するグリフを返す。収録していない文字はNone
+    pub lookup: fn(char) -> Option<Glyph>,
+}
+
+/// 組み込みの8x8 ASCIIフォント
+pub const ASCII_FONT: Font = Font {
+    name: "ascii-8x8",
+    lookup: ascii_glyph,
+};
+
+/// Latin-1補助の記号とアクセント付きの文字のフォント
+pub const LATIN1_FONT: Font = Font {
+    name: "latin1-8x8",
+    lookup: latin1::glyph,
+};
+
+/// 罫線素片・ブロック要素を合成するフォント
+pub const LINE_ART_FONT: Font = Font {
+    name: "line-art",
+    lookup: line_art::glyph,
+};
+
+/// グリフを探す順序
+pub const FONT_CHAIN: &[Font] = &[ASCII_FONT, LATIN1_FONT, LINE_ART_FONT];
+
+/// どのフォントにも収録されていない文字のグリフ
+const REPLACEMENT_GLYPH: Glyph = [0x00, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x00, 0x00];
+
+/// 8x8 ASCIIフォントからグリフを取得
+fn ascii_glyph(ch: char) -> Option<Glyph> {
+    let index = (ch as u32).checked_sub(32)? as usize;
+    let bitmap = FONT_8X8.get(index)?;
+    let mut glyph = [0u8; CELL_HEIGHT];
+    glyph[..8].copy_from_slice(bitmap);
+    Some(glyph)
+}
+
+/// 文字を描画するグリフを取得
+///
+/// # Returns
+/// 制御文字ならNone（何も描画しない）。未収録の文字は置換グリフ
+pub fn glyph_for(ch: char) -> Option<Glyph> {
+    if ch.is_control() {
+        return None;
+    }
+    Some(
+        FONT_CHAIN
+            .iter()
+            .find_map(|font| (font.lookup)(ch))
+            .unwrap_or(REPLACEMENT_GLYPH),
+    )
+}
+
+/// 読み込めるフォントの最大数（読み込んだフォントは解放しない）
+const MAX_LOADED_FONTS: usize = 8;
+
+/// フォントの読み込みのエラー
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontError {
+    /// PSFとして解析できない
+    Psf(PsfError),
+    /// 同じ名前のフォントを読み込み済み
+    AlreadyLoaded,
+    /// 読み込めるフォントの数の上限に達した
+    TooManyFonts,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Psf(e) => write!(f, "{}", e),
+            FontError::AlreadyLoaded => write!(f, "A font with this name is already loaded"),
+            FontError::TooManyFonts => {
+                write!(f, "Too many fonts loaded (max {})", MAX_LOADED_FONTS)
+            }
+        }
+    }
+}
+
+/// 読み込んだビットマップフォント（PSF1/PSF2）
+///
+/// グリフは描画しやすいように1行を `u32`（bit 0が左端）に展開して保持します。
+pub struct BitmapFont {
+    name: String,
+    width: usize,
+    height: usize,
+    /// グリフの行（グリフ番号 × `height` + 行）
+    rows: Vec<u32>,
+    /// 文字からグリフ番号への対応（Unicodeテーブルがなければ空で、ASCIIの文字コードをそのまま使う）
+    map: BTreeMap<char, u32>,
+}
+
+impl BitmapFont {
+    /// PSF1/PSF2のデータからフォントを作成（データはコピーするので一時的なバッファでよい）
+    ///
+    /// # Errors
+    /// * `FontError::Psf` - PSFとして解析できない場合
+    pub fn from_psf(name: &str, data: &[u8]) -> Result<Self, FontError> {
+        let psf = PsfFont::parse(data).map_err(FontError::Psf)?;
+        let rows = (0..psf.glyph_count())
+            .flat_map(|glyph| (0..psf.height()).map(move |row| (glyph, row)))
+            .map(|(glyph, row)| psf.row(glyph, row).unwrap_or(0))
+            .collect();
+        let mut map = BTreeMap::new();
+        for (ch, glyph) in psf.unicode_entries() {
+            // 同じ文字が複数のグリフにあれば最初のものを使う
+            map.entry(ch).or_insert(glyph);
+        }
+        Ok(Self {
+            name: String::from(name),
+            width: psf.width() as usize,
+            height: psf.height() as usize,
+            rows,
+            map,
+        })
+    }
+
+    /// フォント名
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// グリフの幅（ピクセル）
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// グリフの高さ（ピクセル）
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// グリフの個数
+    pub fn glyph_count(&self) -> usize {
+        self.rows.len() / self.height
+    }
+
+    /// 文字のグリフの行を取得
+    ///
+    /// # Returns
+    /// フォントに収録されていない文字ならNone
+    fn glyph(&self, ch: char) -> Option<&[u32]> {
+        let index = if self.map.is_empty() {
+            // テーブルのないフォントの128以上の配置（CP437など）は分からないため、ASCIIに限る
+            if !ch.is_ascii() {
+                return None;
+            }
+            ch as u32
+        } else {
+            *self.map.get(&ch)?
+        } as usize;
+        self.rows
+            .get(index * self.height..(index + 1) * self.height)
+    }
+}
+
+/// 文字の描画に使う書体
+///
+/// 組み込みのフォント（セルは `CELL_WIDTH` × `CELL_HEIGHT`）か、読み込んだビットマップフォントです。
+/// ウィンドウの大きさなどは組み込みのセルを単位に決めているため、既定は組み込みのフォントです。
+#[derive(Clone, Copy, Default)]
+pub enum Typeface {
+    /// 組み込みのフォントとフォールバックチェーン
+    #[default]
+    Builtin,
+    /// 読み込んだビットマップフォント
+    Bitmap(&'static BitmapFont),
+}
+
+impl Typeface {
+    /// 書体の名前
+    pub fn name(&self) -> &str {
+        match self {
+            Typeface::Builtin => ASCII_FONT.name,
+            Typeface::Bitmap(font) => font.name(),
+        }
+    }
+
+    /// 文字セルの幅（ピクセル）
+    pub fn cell_width(&self) -> usize {
+        match self {
+            Typeface::Builtin => CELL_WIDTH,
+            Typeface::Bitmap(font) => font.width(),
+        }
+    }
+
+    /// 文字セルの高さ（行の高さ、ピクセル）
+    pub fn cell_height(&self) -> usize {
+        match self {
+            Typeface::Builtin => CELL_HEIGHT,
+            Typeface::Bitmap(font) => font.height(),
+        }
+    }
+
+    /// 文字を描画するグリフを取得
+    ///
+    /// # Returns
+    /// 制御文字ならNone（何も描画しない）
+    pub fn glyph(&self, ch: char) -> Option<GlyphRows> {
+        if let Typeface::Bitmap(font) = self
+            && !ch.is_control()
+            && let Some(rows) = font.glyph(ch)
+        {
+            return Some(GlyphRows::Bitmap(rows));
+        }
+        glyph_for(ch).map(GlyphRows::Builtin)
+    }
+}
+
+/// 描画するグリフの行（各行のbit 0が左端）
+pub enum GlyphRows {
+    /// 組み込みのグリフ（幅 `CELL_WIDTH`、高さ `CELL_HEIGHT`）
+    Builtin(Glyph),
+    /// 読み込んだフォントのグリフ
+    Bitmap(&'static [u32]),
+}
+
+impl GlyphRows {
+    /// 行数
+    pub fn height(&self) -> usize {
+        match self {
+            GlyphRows::Builtin(glyph) => glyph.len(),
+            GlyphRows::Bitmap(rows) => rows.len(),
+        }
+    }
+
+    /// 幅（ピクセル）
+    pub fn width(&self, typeface: &Typeface) -> usize {
+        match self {
+            GlyphRows::Builtin(_) => CELL_WIDTH,
+            GlyphRows::Bitmap(_) => typeface.cell_width(),
+        }
+    }
+
+    /// 1行分のビットマップ
+    pub fn row(&self, row: usize) -> u32 {
+        match self {
+            GlyphRows::Builtin(glyph) => glyph[row] as u32,
+            GlyphRows::Bitmap(rows) => rows[row],
+        }
+    }
+}
+
+/// 読み込んだフォント
+static LOADED: IrqSpinlock<Vec<&'static BitmapFont>> = IrqSpinlock::new(Vec::new());
+
+/// コンソール（`dmesg view` など）で使う書体
+static CONSOLE_TYPEFACE: IrqSpinlock<Typeface> = IrqSpinlock::new(Typeface::Builtin);
+
+/// フォントを登録（読み込んだフォントは解放しない）
+///
+/// # Errors
+/// * `FontError::AlreadyLoaded` - 同じ名前のフォントを読み込み済みの場合
+/// * `FontError::TooManyFonts` - 読み込めるフォントの数の上限に達した場合
+pub fn register(font: BitmapFont) -> Result<&'static BitmapFont, FontError> {
+    let mut loaded = LOADED.lock();
+    if loaded.iter().any(|f| f.name() == font.name()) {
+        return Err(FontError::AlreadyLoaded);
+    }
+    if loaded.len() >= MAX_LOADED_FONTS {
+        return Err(FontError::TooManyFonts);
+    }
+    let font: &'static BitmapFont = alloc::boxed::Box::leak(alloc::boxed::Box::new(font));
+    loaded.push(font);
+    Ok(font)
+}
+
+/// 名前で書体を探す（組み込みのフォントは `ascii-8x8`）
+pub fn find(name: &str) -> Option<Typeface> {
+    if name == ASCII_FONT.name {
+        return Some(Typeface::Builtin);
+    }
+    LOADED
+        .lock()
+        .iter()
+        .find(|font| font.name() == name)
+        .map(|font| Typeface::Bitmap(font))
+}
+
+/// 読み込んだフォントの一覧
+pub fn loaded() -> Vec<&'static BitmapFont> {
+    LOADED.lock().clone()
+}
+
+/// コンソールで使う書体
+pub fn console_typeface() -> Typeface {
+    *CONSOLE_TYPEFACE.lock()
+}
+
+/// コンソールで使う書体を設定（次に開くコンソールから使われる）
+pub fn set_console_typeface(typeface: Typeface) {
+    *CONSOLE_TYPEFACE.lock() = typeface;
+}
+
+/// 8x8 ビットマップフォント（ASCII 32-126）
 pub const FONT_8X8: [[u8; 8]; 95] = [
     // 32: Space
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
@@ -0,0 +1,228 @@
+//! Latin-1補助（U+00A0〜U+00FF）のグリフ
+//!
+//! アクセント付きの文字は、ASCIIのグリフにダイアクリティカルマーク（2行）を重ねて合成します。
+//! 大文字はマークを置くために2行下げ、セルの行間まで使います。セディーユは文字の下に付けます。
+//! 記号と、合成できない文字（Æ、ß など）は8x8のビットマップを持ちます。
+
+use super::font::{CELL_HEIGHT, FONT_8X8, Glyph};
+
+/// ダイアクリティカルマーク
+#[derive(Clone, Copy)]
+enum Mark {
+    Grave,
+    Acute,
+    Circumflex,
+    Tilde,
+    Diaeresis,
+    Ring,
+    Cedilla,
+}
+
+impl Mark {
+    /// マークの2行分のビットマップ（各行のbit 0が左端）
+    const fn rows(self) -> [u8; 2] {
+        match self {
+            Mark::Grave => [0x06, 0x0C],
+            Mark::Acute => [0x18, 0x0C],
+            Mark::Circumflex => [0x0C, 0x33],
+            Mark::Tilde => [0x6E, 0x3B],
+            Mark::Diaeresis => [0x33, 0x00],
+            Mark::Ring => [0x0C, 0x12],
+            Mark::Cedilla => [0x18, 0x0C],
+        }
+    }
+}
+
+/// U+00C0〜U+00FF の文字の作り方
+enum Form {
+    /// ASCIIの文字にマークを重ねる
+    Composed(char, Mark),
+    /// ビットマップを持つ
+    Bitmap([u8; 8]),
+}
+
+/// U+00A0〜U+00BF（記号）のビットマップ
+const SYMBOLS: [[u8; 8]; 32] = [
+    // U+00A0 NO-BREAK SPACE
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // U+00A1 ¡
+    [0x18, 0x00, 0x18, 0x18, 0x3C, 0x3C, 0x18, 0x00],
+    // U+00A2 ¢
+    [0x18, 0x3C, 0x66, 0x06, 0x66, 0x3C, 0x18, 0x00],
+    // U+00A3 £
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x66, 0x3F, 0x00],
+    // U+00A4 ¤
+    [0x00, 0x63, 0x3E, 0x36, 0x36, 0x3E, 0x63, 0x00],
+    // U+00A5 ¥
+    [0x33, 0x33, 0x1E, 0x3F, 0x0C, 0x3F, 0x0C, 0x00],
+    // U+00A6 ¦
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00],
+    // U+00A7 §
+    [0x1E, 0x03, 0x1E, 0x33, 0x1E, 0x30, 0x1E, 0x00],
+    // U+00A8 ¨
+    [0x33, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // U+00A9 ©
+    [0x3E, 0x41, 0x5D, 0x45, 0x5D, 0x41, 0x3E, 0x00],
+    // U+00AA ª
+    [0x1E, 0x30, 0x3E, 0x33, 0x3E, 0x00, 0x3F, 0x00],
+    // U+00AB «
+    [0x00, 0x6C, 0x36, 0x1B, 0x36, 0x6C, 0x00, 0x00],
+    // U+00AC ¬
+    [0x00, 0x00, 0x3F, 0x30, 0x30, 0x00, 0x00, 0x00],
+    // U+00AD SOFT HYPHEN
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00],
+    // U+00AE ®
+    [0x3E, 0x41, 0x4D, 0x55, 0x4D, 0x55, 0x3E, 0x00],
+    // U+00AF ¯
+    [0x3F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // U+00B0 °
+    [0x0E, 0x1B, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // U+00B1 ±
+    [0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x3F, 0x00],
+    // U+00B2 ²
+    [0x0E, 0x18, 0x0C, 0x06, 0x0F, 0x00, 0x00, 0x00],
+    // U+00B3 ³
+    [0x0F, 0x0C, 0x0E, 0x18, 0x0F, 0x00, 0x00, 0x00],
+    // U+00B4 ´
+    [0x18, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // U+00B5 µ
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1F, 0x03, 0x03],
+    // U+00B6 ¶
+    [0x7E, 0x6F, 0x6F, 0x6E, 0x68, 0x68, 0x68, 0x00],
+    // U+00B7 ·
+    [0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00, 0x00, 0x00],
+    // U+00B8 ¸
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x0C],
+    // U+00B9 ¹
+    [0x06, 0x07, 0x06, 0x06, 0x0F, 0x00, 0x00, 0x00],
+    // U+00BA º
+    [0x0E, 0x1B, 0x1B, 0x0E, 0x00, 0x1F, 0x00, 0x00],
+    // U+00BB »
+    [0x00, 0x1B, 0x36, 0x6C, 0x36, 0x1B, 0x00, 0x00],
+    // U+00BC ¼
+    [0x21, 0x11, 0x09, 0x14, 0x1A, 0x3D, 0x10, 0x00],
+    // U+00BD ½
+    [0x21, 0x11, 0x09, 0x34, 0x22, 0x11, 0x70, 0x00],
+    // U+00BE ¾
+    [0x23, 0x12, 0x0B, 0x14, 0x1A, 0x3D, 0x10, 0x00],
+    // U+00BF ¿
+    [0x0C, 0x00, 0x0C, 0x06, 0x03, 0x33, 0x1E, 0x00],
+];
+
+/// U+00C0〜U+00FF（文字）
+const LETTERS: [Form; 64] = [
+    Form::Composed('A', Mark::Grave),                               // À
+    Form::Composed('A', Mark::Acute),                               // Á
+    Form::Composed('A', Mark::Circumflex),                          // Â
+    Form::Composed('A', Mark::Tilde),                               // Ã
+    Form::Composed('A', Mark::Diaeresis),                           // Ä
+    Form::Composed('A', Mark::Ring),                                // Å
+    Form::Bitmap([0x7C, 0x36, 0x33, 0x7F, 0x33, 0x33, 0x73, 0x00]), // Æ
+    Form::Composed('C', Mark::Cedilla),                             // Ç
+    Form::Composed('E', Mark::Grave),                               // È
+    Form::Composed('E', Mark::Acute),                               // É
+    Form::Composed('E', Mark::Circumflex),                          // Ê
+    Form::Composed('E', Mark::Diaeresis),                           // Ë
+    Form::Composed('I', Mark::Grave),                               // Ì
+    Form::Composed('I', Mark::Acute),                               // Í
+    Form::Composed('I', Mark::Circumflex),                          // Î
+    Form::Composed('I', Mark::Diaeresis),                           // Ï
+    Form::Bitmap([0x1F, 0x36, 0x66, 0x6F, 0x66, 0x36, 0x1F, 0x00]), // Ð
+    Form::Composed('N', Mark::Tilde),                               // Ñ
+    Form::Composed('O', Mark::Grave),                               // Ò
+    Form::Composed('O', Mark::Acute),                               // Ó
+    Form::Composed('O', Mark::Circumflex),                          // Ô
+    Form::Composed('O', Mark::Tilde),                               // Õ
+    Form::Composed('O', Mark::Diaeresis),                           // Ö
+    Form::Bitmap([0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00, 0x00]), // ×
+    Form::Bitmap([0x5C, 0x36, 0x73, 0x6B, 0x67, 0x36, 0x1D, 0x00]), // Ø
+    Form::Composed('U', Mark::Grave),                               // Ù
+    Form::Composed('U', Mark::Acute),                               // Ú
+    Form::Composed('U', Mark::Circumflex),                          // Û
+    Form::Composed('U', Mark::Diaeresis),                           // Ü
+    Form::Composed('Y', Mark::Acute),                               // Ý
+    Form::Bitmap([0x0F, 0x06, 0x3E, 0x66, 0x3E, 0x06, 0x0F, 0x00]), // Þ
+    Form::Bitmap([0x1E, 0x33, 0x33, 0x1B, 0x33, 0x33, 0x1B, 0x03]), // ß
+    Form::Composed('a', Mark::Grave),                               // à
+    Form::Composed('a', Mark::Acute),                               // á
+    Form::Composed('a', Mark::Circumflex),                          // â
+    Form::Composed('a', Mark::Tilde),                               // ã
+    Form::Composed('a', Mark::Diaeresis),                           // ä
+    Form::Composed('a', Mark::Ring),                                // å
+    Form::Bitmap([0x00, 0x00, 0x36, 0x58, 0x7E, 0x1B, 0x6E, 0x00]), // æ
+    Form::Composed('c', Mark::Cedilla),                             // ç
+    Form::Composed('e', Mark::Grave),                               // è
+    Form::Composed('e', Mark::Acute),                               // é
+    Form::Composed('e', Mark::Circumflex),                          // ê
+    Form::Composed('e', Mark::Diaeresis),                           // ë
+    Form::Composed('i', Mark::Grave),                               // ì
+    Form::Composed('i', Mark::Acute),                               // í
+    Form::Composed('i', Mark::Circumflex),                          // î
+    Form::Composed('i', Mark::Diaeresis),                           // ï
+    Form::Bitmap([0x16, 0x0C, 0x1A, 0x3E, 0x33, 0x33, 0x1E, 0x00]), // ð
+    Form::Composed('n', Mark::Tilde),                               // ñ
+    Form::Composed('o', Mark::Grave),                               // ò
+    Form::Composed('o', Mark::Acute),                               // ó
+    Form::Composed('o', Mark::Circumflex),                          // ô
+    Form::Composed('o', Mark::Tilde),                               // õ
+    Form::Composed('o', Mark::Diaeresis),                           // ö
+    Form::Bitmap([0x0C, 0x0C, 0x00, 0x3F, 0x00, 0x0C, 0x0C, 0x00]), // ÷
+    Form::Bitmap([0x00, 0x00, 0x5E, 0x33, 0x3B, 0x37, 0x3D, 0x00]), // ø
+    Form::Composed('u', Mark::Grave),                               // ù
+    Form::Composed('u', Mark::Acute),                               // ú
+    Form::Composed('u', Mark::Circumflex),                          // û
+    Form::Composed('u', Mark::Diaeresis),                           // ü
+    Form::Composed('y', Mark::Acute),                               // ý
+    Form::Bitmap([0x07, 0x06, 0x3E, 0x66, 0x66, 0x3E, 0x06, 0x0F]), // þ
+    Form::Composed('y', Mark::Diaeresis),                           // ÿ
+];
+
+/// Latin-1補助のグリフを取得
+///
+/// # Returns
+/// 対象範囲外の文字ならNone
+pub fn glyph(ch: char) -> Option<Glyph> {
+    let code = ch as u32;
+    match code {
+        0xA0..=0xBF => Some(widen(&SYMBOLS[(code - 0xA0) as usize])),
+        0xC0..=0xFF => Some(match LETTERS[(code - 0xC0) as usize] {
+            Form::Composed(base, mark) => compose(base, mark),
+            Form::Bitmap(bitmap) => widen(&bitmap),
+        }),
+        _ => None,
+    }
+}
+
+/// 8x8のビットマップをセルの上端に置く
+fn widen(bitmap: &[u8; 8]) -> Glyph {
+    let mut glyph = [0u8; CELL_HEIGHT];
+    glyph[..8].copy_from_slice(bitmap);
+    glyph
+}
+
+/// ASCIIのグリフにマークを重ねる
+fn compose(base: char, mark: Mark) -> Glyph {
+    let bitmap = &FONT_8X8[base as usize - 32];
+    let rows = mark.rows();
+    match mark {
+        // 文字の下（ASCIIのグリフの空いている最下行と行間の1行目）
+        Mark::Cedilla => {
+            let mut glyph = widen(bitmap);
+            glyph[7..9].copy_from_slice(&rows);
+            glyph
+        }
+        // 大文字は上端まで使っているため、2行下げてマークの場所を空ける
+        _ if base.is_ascii_uppercase() => {
+            let mut glyph = [0u8; CELL_HEIGHT];
+            glyph[..2].copy_from_slice(&rows);
+            glyph[2..10].copy_from_slice(bitmap);
+            glyph
+        }
+        // 小文字の上2行は空き（i の点はマークで置き換える）
+        _ => {
+            let mut glyph = widen(bitmap);
+            glyph[..2].copy_from_slice(&rows);
+            glyph
+        }
+    }
+}
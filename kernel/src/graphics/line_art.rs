@@ -0,0 +1,415 @@
+//! 罫線素片（U+2500〜U+257F）とブロック要素（U+2580〜U+259F）のグリフ合成
+//!
+//! ビットマップを同梱する代わりに、文字セル全体（8x`CELL_HEIGHT`）に対して
+//! 線分や矩形として描画します。セルの端まで線を伸ばすため、行間を空けて表示しても
+//! 縦方向の罫線が途切れません。
+
+use super::font::{CELL_HEIGHT, CELL_WIDTH, Glyph};
+
+/// 線の種類（2ビット）
+const NONE: u8 = 0;
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+/// 中心から上・右・下・左へ伸びる線の種類をまとめる
+const fn arms(up: u8, right: u8, down: u8, left: u8) -> u8 {
+    (up << 6) | (right << 4) | (down << 2) | left
+}
+
+const N: u8 = NONE;
+const L: u8 = LIGHT;
+const H: u8 = HEAVY;
+const D: u8 = DOUBLE;
+
+/// U+2500〜U+2570 の各文字の線構成（上, 右, 下, 左）
+///
+/// 破線（U+2504〜U+250B, U+254C〜U+254F）は実線として登録し、`dash_gaps` で間引きます。
+/// 円弧（U+256D〜U+2570）は同じ向きの角として描画します。
+const BOX_ARMS: [u8; 0x71] = [
+    arms(N, L, N, L), // ─
+    arms(N, H, N, H), // ━
+    arms(L, N, L, N), // │
+    arms(H, N, H, N), // ┃
+    arms(N, L, N, L), // ┄
+    arms(N, H, N, H), // ┅
+    arms(L, N, L, N), // ┆
+    arms(H, N, H, N), // ┇
+    arms(N, L, N, L), // ┈
+    arms(N, H, N, H), // ┉
+    arms(L, N, L, N), // ┊
+    arms(H, N, H, N), // ┋
+    arms(N, L, L, N), // ┌
+    arms(N, H, L, N), // ┍
+    arms(N, L, H, N), // ┎
+    arms(N, H, H, N), // ┏
+    arms(N, N, L, L), // ┐
+    arms(N, N, L, H), // ┑
+    arms(N, N, H, L), // ┒
+    arms(N, N, H, H), // ┓
+    arms(L, L, N, N), // └
+    arms(L, H, N, N), // ┕
+    arms(H, L, N, N), // ┖
+    arms(H, H, N, N), // ┗
+    arms(L, N, N, L), // ┘
+    arms(L, N, N, H), // ┙
+    arms(H, N, N, L), // ┚
+    arms(H, N, N, H), // ┛
+    arms(L, L, L, N), // ├
+    arms(L, H, L, N), // ┝
+    arms(H, L, L, N), // ┞
+    arms(L, L, H, N), // ┟
+    arms(H, L, H, N), // ┠
+    arms(H, H, L, N), // ┡
+    arms(L, H, H, N), // ┢
+    arms(H, H, H, N), // ┣
+    arms(L, N, L, L), // ┤
+    arms(L, N, L, H), // ┥
+    arms(H, N, L, L), // ┦
+    arms(L, N, H, L), // ┧
+    arms(H, N, H, L), // ┨
+    arms(H, N, L, H), // ┩
+    arms(L, N, H, H), // ┪
+    arms(H, N, H, H), // ┫
+    arms(N, L, L, L), // ┬
+    arms(N, L, L, H), // ┭
+    arms(N, H, L, L), // ┮
+    arms(N, H, L, H), // ┯
+    arms(N, L, H, L), // ┰
+    arms(N, L, H, H), // ┱
+    arms(N, H, H, L), // ┲
+    arms(N, H, H, H), // ┳
+    arms(L, L, N, L), // ┴
+    arms(L, L, N, H), // ┵
+    arms(L, H, N, L), // ┶
+    arms(L, H, N, H), // ┷
+    arms(H, L, N, L), // ┸
+    arms(H, L, N, H), // ┹
+    arms(H, H, N, L), // ┺
+    arms(H, H, N, H), // ┻
+    arms(L, L, L, L), // ┼
+    arms(L, L, L, H), // ┽
+    arms(L, H, L, L), // ┾
+    arms(L, H, L, H), // ┿
+    arms(H, L, L, L), // ╀
+    arms(L, L, H, L), // ╁
+    arms(H, L, H, L), // ╂
+    arms(H, L, L, H), // ╃
+    arms(H, H, L, L), // ╄
+    arms(L, L, H, H), // ╅
+    arms(L, H, H, L), // ╆
+    arms(H, H, L, H), // ╇
+    arms(L, H, H, H), // ╈
+    arms(H, L, H, H), // ╉
+    arms(H, H, H, L), // ╊
+    arms(H, H, H, H), // ╋
+    arms(N, L, N, L), // ╌
+    arms(N, H, N, H), // ╍
+    arms(L, N, L, N), // ╎
+    arms(H, N, H, N), // ╏
+    arms(N, D, N, D), // ═
+    arms(D, N, D, N), // ║
+    arms(N, D, L, N), // ╒
+    arms(N, L, D, N), // ╓
+    arms(N, D, D, N), // ╔
+    arms(N, N, L, D), // ╕
+    arms(N, N, D, L), // ╖
+    arms(N, N, D, D), // ╗
+    arms(L, D, N, N), // ╘
+    arms(D, L, N, N), // ╙
+    arms(D, D, N, N), // ╚
+    arms(L, N, N, D), // ╛
+    arms(D, N, N, L), // ╜
+    arms(D, N, N, D), // ╝
+    arms(L, D, L, N), // ╞
+    arms(D, L, D, N), // ╟
+    arms(D, D, D, N), // ╠
+    arms(L, N, L, D), // ╡
+    arms(D, N, D, L), // ╢
+    arms(D, N, D, D), // ╣
+    arms(N, D, L, D), // ╤
+    arms(N, L, D, L), // ╥
+    arms(N, D, D, D), // ╦
+    arms(L, D, N, D), // ╧
+    arms(D, L, N, L), // ╨
+    arms(D, D, N, D), // ╩
+    arms(L, D, L, D), // ╪
+    arms(D, L, D, L), // ╫
+    arms(D, D, D, D), // ╬
+    arms(N, L, L, N), // ╭
+    arms(N, N, L, L), // ╮
+    arms(L, N, N, L), // ╯
+    arms(L, L, N, N), // ╰
+];
+
+/// U+2574〜U+257F（半分の線）の線構成（上, 右, 下, 左）
+const HALF_ARMS: [u8; 12] = [
+    arms(N, N, N, L), // ╴
+    arms(L, N, N, N), // ╵
+    arms(N, L, N, N), // ╶
+    arms(N, N, L, N), // ╷
+    arms(N, N, N, H), // ╸
+    arms(H, N, N, N), // ╹
+    arms(N, H, N, N), // ╺
+    arms(N, N, H, N), // ╻
+    arms(N, H, N, L), // ╼
+    arms(L, N, H, N), // ╽
+    arms(N, L, N, H), // ╾
+    arms(H, N, L, N), // ╿
+];
+
+/// 細線の行（横線）と列（縦線）
+const CENTER_ROW: usize = CELL_HEIGHT / 2 - 1;
+const CENTER_COL: usize = CELL_WIDTH / 2 - 1;
+
+/// 二重線の外側の行・列（中心から±オフセット）
+const DOUBLE_OFFSET: usize = 1;
+
+/// 罫線素片またはブロック要素のグリフを合成
+///
+/// # Returns
+/// 対象範囲外の文字ならNone
+pub fn glyph(ch: char) -> Option<Glyph> {
+    let code = ch as u32;
+    match code {
+        0x2500..=0x2570 => {
+            let mut glyph = draw_arms(BOX_ARMS[(code - 0x2500) as usize]);
+            dash_gaps(code, &mut glyph);
+            Some(glyph)
+        }
+        0x2571..=0x2573 => Some(diagonal(code)),
+        0x2574..=0x257F => Some(draw_arms(HALF_ARMS[(code - 0x2574) as usize])),
+        0x2580..=0x259F => Some(block_element(code)),
+        _ => None,
+    }
+}
+
+/// 横方向の線分を描画（列 `from`〜`to` を含む）
+fn hline(glyph: &mut Glyph, row: usize, from: usize, to: usize) {
+    for col in from..=to {
+        glyph[row] |= 1 << col;
+    }
+}
+
+/// 縦方向の線分を描画（行 `from`〜`to` を含む）
+fn vline(glyph: &mut Glyph, col: usize, from: usize, to: usize) {
+    for row in glyph.iter_mut().take(to + 1).skip(from) {
+        *row |= 1 << col;
+    }
+}
+
+/// 4方向の線を描画
+///
+/// 二重線は、同じ側に直交する二重線があればその内側の線で止め、
+/// なければ反対側の線まで伸ばすことで角や分岐を正しくつなげます。
+fn draw_arms(spec: u8) -> Glyph {
+    let mut glyph = [0u8; CELL_HEIGHT];
+    let up = (spec >> 6) & 0x3;
+    let right = (spec >> 4) & 0x3;
+    let down = (spec >> 2) & 0x3;
+    let left = spec & 0x3;
+
+    let (top, bottom) = (CENTER_ROW - DOUBLE_OFFSET, CENTER_ROW + 1 + DOUBLE_OFFSET);
+    let (near, far) = (CENTER_COL - DOUBLE_OFFSET, CENTER_COL + 1 + DOUBLE_OFFSET);
+    let last_row = CELL_HEIGHT - 1;
+    let last_col = CELL_WIDTH - 1;
+
+    // 横方向（左・右）
+    for (weight, is_left) in [(left, true), (right, false)] {
+        match weight {
+            LIGHT => {
+                let (from, to) = if is_left {
+                    (0, CENTER_COL)
+                } else {
+                    (CENTER_COL, last_col)
+                };
+                hline(&mut glyph, CENTER_ROW, from, to);
+            }
+            HEAVY => {
+                let (from, to) = if is_left {
+                    (0, CENTER_COL + 1)
+                } else {
+                    (CENTER_COL, last_col)
+                };
+                hline(&mut glyph, CENTER_ROW, from, to);
+                hline(&mut glyph, CENTER_ROW + 1, from, to);
+            }
+            DOUBLE => {
+                for (row, blocker) in [(top, up), (bottom, down)] {
+                    // 直交する二重線があれば手前の線で止め、なければ奥の線まで伸ばす
+                    let end = if blocker == DOUBLE {
+                        if is_left { near } else { far }
+                    } else if is_left {
+                        far
+                    } else {
+                        near
+                    };
+                    let (from, to) = if is_left { (0, end) } else { (end, last_col) };
+                    hline(&mut glyph, row, from, to);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 縦方向（上・下）
+    for (weight, is_up) in [(up, true), (down, false)] {
+        let span = |end: usize| {
+            if is_up { (0, end) } else { (end, last_row) }
+        };
+        match weight {
+            LIGHT => {
+                let (from, to) = span(CENTER_ROW);
+                vline(&mut glyph, CENTER_COL, from, to);
+            }
+            HEAVY => {
+                let (from, to) = if is_up {
+                    (0, CENTER_ROW + 1)
+                } else {
+                    (CENTER_ROW, last_row)
+                };
+                vline(&mut glyph, CENTER_COL, from, to);
+                vline(&mut glyph, CENTER_COL + 1, from, to);
+            }
+            DOUBLE => {
+                for (col, blocker) in [(near, left), (far, right)] {
+                    let end = if blocker == DOUBLE {
+                        if is_up { top } else { bottom }
+                    } else if is_up {
+                        bottom
+                    } else {
+                        top
+                    };
+                    let (from, to) = span(end);
+                    vline(&mut glyph, col, from, to);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    glyph
+}
+
+/// 破線の隙間を空ける
+fn dash_gaps(code: u32, glyph: &mut Glyph) {
+    // (横方向の隙間の列, 縦方向の隙間の行)
+    let (cols, rows): (&[usize], &[usize]) = match code {
+        // 二重破線
+        0x254C..=0x254F => (&[3, 7], &[4, 9]),
+        // 三重破線
+        0x2504..=0x2507 => (&[2, 5], &[3, 6, 9]),
+        // 四重破線
+        0x2508..=0x250B => (&[1, 3, 5, 7], &[1, 3, 5, 7, 9]),
+        _ => return,
+    };
+    let horizontal = matches!(code, 0x2504 | 0x2505 | 0x2508 | 0x2509 | 0x254C | 0x254D);
+    if horizontal {
+        for row in glyph.iter_mut() {
+            for &col in cols {
+                *row &= !(1 << col);
+            }
+        }
+    } else {
+        for &row in rows {
+            glyph[row] = 0;
+        }
+    }
+}
+
+/// 斜線（╱ ╲ ╳）を描画
+fn diagonal(code: u32) -> Glyph {
+    let mut glyph = [0u8; CELL_HEIGHT];
+    for (row, bits) in glyph.iter_mut().enumerate() {
+        // 行をセル幅に対応付ける
+        let col = row * CELL_WIDTH / CELL_HEIGHT;
+        if code != 0x2572 {
+            *bits |= 1 << (CELL_WIDTH - 1 - col); // ╱
+        }
+        if code != 0x2571 {
+            *bits |= 1 << col; // ╲
+        }
+    }
+    glyph
+}
+
+/// 上から `eighths` / 8 の高さを行数に変換
+fn rows_for(eighths: usize) -> usize {
+    (eighths * CELL_HEIGHT + 4) / 8
+}
+
+/// ブロック要素を描画
+fn block_element(code: u32) -> Glyph {
+    let mut glyph = [0u8; CELL_HEIGHT];
+    let full_row = u8::MAX;
+    let half_rows = CELL_HEIGHT / 2;
+    let left_half: u8 = 0x0F;
+    let right_half: u8 = 0xF0;
+
+    // 下から `eighths` / 8 を塗る
+    let lower = |glyph: &mut Glyph, eighths: usize| {
+        let rows = rows_for(eighths);
+        for row in glyph.iter_mut().skip(CELL_HEIGHT - rows) {
+            *row = full_row;
+        }
+    };
+    // 左から `eighths` / 8 を塗る（1列 = 1/8）
+    let left = |glyph: &mut Glyph, eighths: usize| {
+        let bits = ((1u16 << eighths) - 1) as u8;
+        for row in glyph.iter_mut() {
+            *row = bits;
+        }
+    };
+    // 象限（左上, 右上, 左下, 右下）
+    let quadrants = |glyph: &mut Glyph, ul: bool, ur: bool, ll: bool, lr: bool| {
+        for (row, bits) in glyph.iter_mut().enumerate() {
+            let (l, r) = if row < half_rows { (ul, ur) } else { (ll, lr) };
+            *bits = (if l { left_half } else { 0 }) | (if r { right_half } else { 0 });
+        }
+    };
+    // 網掛け（1行おきにずらしたパターン）
+    let shade = |glyph: &mut Glyph, even: u8, odd: u8| {
+        for (row, bits) in glyph.iter_mut().enumerate() {
+            *bits = if row.is_multiple_of(2) { even } else { odd };
+        }
+    };
+
+    match code {
+        0x2580 => {
+            for row in glyph.iter_mut().take(half_rows) {
+                *row = full_row;
+            }
+        }
+        0x2581..=0x2588 => lower(&mut glyph, (code - 0x2580) as usize),
+        0x2589..=0x258F => left(&mut glyph, (0x2590 - code) as usize),
+        0x2590 => {
+            for row in glyph.iter_mut() {
+                *row = right_half;
+            }
+        }
+        0x2591 => shade(&mut glyph, 0x11, 0x44),
+        0x2592 => shade(&mut glyph, 0x55, 0xAA),
+        0x2593 => shade(&mut glyph, 0xEE, 0xBB),
+        0x2594 => {
+            for row in glyph.iter_mut().take(rows_for(1)) {
+                *row = full_row;
+            }
+        }
+        0x2595 => {
+            for row in glyph.iter_mut() {
+                *row = 0x80;
+            }
+        }
+        0x2596 => quadrants(&mut glyph, false, false, true, false),
+        0x2597 => quadrants(&mut glyph, false, false, false, true),
+        0x2598 => quadrants(&mut glyph, true, false, false, false),
+        0x2599 => quadrants(&mut glyph, true, false, true, true),
+        0x259A => quadrants(&mut glyph, true, false, false, true),
+        0x259B => quadrants(&mut glyph, true, true, true, false),
+        0x259C => quadrants(&mut glyph, true, true, false, true),
+        0x259D => quadrants(&mut glyph, false, true, false, false),
+        0x259E => quadrants(&mut glyph, false, true, true, false),
+        _ => quadrants(&mut glyph, false, true, true, true), // 0x259F
+    }
+    glyph
+}
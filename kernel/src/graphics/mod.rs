@@ -1,100 +1,116 @@
-mod font;
+pub mod font;
+mod latin1;
+mod line_art;
 
+pub mod backing_store;
 pub mod buffer;
+pub mod canvas;
+pub mod color;
 pub mod compositor;
+pub mod console;
+pub mod cursor;
+pub mod page_buffer;
+pub mod pixel_format;
 pub mod region;
 pub mod shadow_buffer;
+pub mod simd;
+pub mod surface;
+pub mod theme;
+pub mod window;
 pub mod writer;
 
-pub use font::FONT_8X8;
+pub use buffer::BlitMode;
+pub use color::Color;
+pub use font::{CELL_HEIGHT, CELL_WIDTH, Typeface};
 pub use region::Region;
 pub use writer::TaskWriter;
 
-/// 高速なメモリ塗りつぶし（rep stosd使用）
+/// 高速なメモリ塗りつぶし
 ///
-/// x86-64の`rep stosd`命令を使用して、32ビット値を連続してメモリに書き込みます。
-/// ループオーバーヘッドなしで高速に動作します。
+/// 短い塗りつぶしは`rep stosd`命令、長い塗りつぶしはCPUが対応していればSSE2/AVXの
+/// ストアで32ビット値を連続してメモリに書き込みます（`simd` モジュール）。
 ///
 /// # Safety
 /// - ptrは有効なメモリアドレスで、count個のu32を書き込める領域を指す必要がある
 #[inline(always)]
 unsafe fn fast_fill_u32(ptr: *mut u32, value: u32, count: usize) {
-    if count == 0 {
-        return;
-    }
     // SAFETY: 呼び出し元がptrの有効性とcount個の書き込み可能領域を保証する
+    unsafe { simd::fill_pixels(ptr, value, count) };
+}
+
+/// 1ピクセルを書き込む（不透明ならそのまま、半透明なら書き込み先と合成）
+///
+/// # Safety
+/// - ptrは読み書き可能なピクセルを指す必要がある
+#[inline(always)]
+unsafe fn put_pixel(ptr: *mut u32, native: u32, alpha: u8) {
+    // SAFETY: 呼び出し元がptrの有効性を保証する
     unsafe {
-        core::arch::asm!(
-            "rep stosd",
-            inout("rdi") ptr => _,
-            inout("ecx") count => _,
-            in("eax") value,
-            options(nostack, preserves_flags)
-        );
+        *ptr = if alpha == 0xFF {
+            native
+        } else {
+            pixel_format::blend_native(*ptr, native, alpha)
+        };
     }
 }
 
 // フレームバッファに文字を描画
 //
+// 文字は幅 CELL_WIDTH、高さ CELL_HEIGHT のセルに描画します。グリフはフォールバック
+// チェーンから選ばれ、罫線素片などはセルの下端まで描画されます。
+//
 // # Safety
 // fb_base は有効なフレームバッファアドレスである必要があり、
-// 描画範囲が画面内に収まっていることを呼び出し側が保証する必要があります。
-pub unsafe fn draw_char(fb_base: u64, width: u32, x: usize, y: usize, ch: u8, color: u32) {
+// 描画範囲（文字セル全体）が画面内に収まっていることを呼び出し側が保証する必要があります。
+pub unsafe fn draw_char(fb_base: u64, width: u32, x: usize, y: usize, ch: char, color: Color) {
+    // SAFETY: 呼び出し元が保証する
+    unsafe { draw_char_in(fb_base, width, x, y, ch, color, &Typeface::Builtin) };
+}
+
+// 指定した書体でフレームバッファに文字を描画
+//
+// セルの大きさは書体の cell_width × cell_height です。
+//
+// # Safety
+// draw_char と同じ（文字セルは書体のセルの大きさ）
+pub unsafe fn draw_char_in(
+    fb_base: u64,
+    width: u32,
+    x: usize,
+    y: usize,
+    ch: char,
+    color: Color,
+    typeface: &Typeface,
+) {
     let fb_ptr = fb_base as *mut u32;
     let stride = width as usize;
 
-    if ch < 32 || ch > 126 {
-        return; // サポート外の文字
-    }
-
-    // 事前に境界チェック: 文字全体（8x8）が画面内に収まるか確認
-    // 文字の右端 (x + 7) と下端 (y + 7) が画面内であればOK
-    let x_end = match x.checked_add(8) {
-        Some(end) => end,
-        None => return, // オーバーフロー
-    };
-    let y_end = match y.checked_add(8) {
-        Some(end) => end,
-        None => return, // オーバーフロー
+    let Some(glyph) = typeface.glyph(ch) else {
+        return; // 制御文字
     };
+    let alpha = color.alpha();
+    if alpha == 0 {
+        return; // 完全に透明
+    }
+    let color = pixel_format::to_native(color);
 
     // 文字が完全に画面外の場合は早期リターン
-    if x >= stride || y_end == 0 {
+    if x >= stride || y.checked_add(glyph.height()).is_none() {
         return;
     }
 
-    let font_index = (ch - 32) as usize;
-    let glyph = FONT_8X8[font_index];
-
-    // 文字が完全に画面内に収まる場合は高速パス
-    // SAFETY: 呼び出し元が描画範囲の有効性を保証する
-    if x_end <= stride {
-        // 高速パス: 境界チェック不要
-        for row in 0..8 {
-            let glyph_row = glyph[row];
-            if glyph_row == 0 {
-                continue; // この行には描画するピクセルがない
-            }
-            let row_offset = (y + row) * stride + x;
-            for col in 0..8 {
-                if (glyph_row >> col) & 1 == 1 {
-                    unsafe { *fb_ptr.add(row_offset + col) = color };
-                }
-            }
+    // 右端がクリップされる場合は見える列だけ描画
+    let visible_cols = stride.saturating_sub(x).min(glyph.width(typeface));
+    for row in 0..glyph.height() {
+        let glyph_row = glyph.row(row);
+        if glyph_row == 0 {
+            continue; // この行には描画するピクセルがない
         }
-    } else {
-        // 低速パス: 右端がクリップされる場合
-        let visible_cols = stride.saturating_sub(x).min(8);
-        for row in 0..8 {
-            let glyph_row = glyph[row];
-            if glyph_row == 0 {
-                continue;
-            }
-            let row_offset = (y + row) * stride + x;
-            for col in 0..visible_cols {
-                if (glyph_row >> col) & 1 == 1 {
-                    unsafe { *fb_ptr.add(row_offset + col) = color };
-                }
+        let row_offset = (y + row) * stride + x;
+        for col in 0..visible_cols {
+            if (glyph_row >> col) & 1 == 1 {
+                // SAFETY: 呼び出し元が描画範囲の有効性を保証する
+                unsafe { put_pixel(fb_ptr.add(row_offset + col), color, alpha) };
             }
         }
     }
@@ -105,14 +121,15 @@ pub unsafe fn draw_char(fb_base: u64, width: u32, x: usize, y: usize, ch: u8, co
 // # Safety
 // fb_base は有効なフレームバッファアドレスである必要があり、
 // 描画範囲が画面内に収まっていることを呼び出し側が保証する必要があります。
-pub unsafe fn draw_string(fb_base: u64, width: u32, x: usize, y: usize, s: &str, color: u32) {
+#[allow(dead_code)]
+pub unsafe fn draw_string(fb_base: u64, width: u32, x: usize, y: usize, s: &str, color: Color) {
     let mut cur_x = x;
-    for ch in s.bytes() {
+    for ch in s.chars() {
         unsafe {
             draw_char(fb_base, width, cur_x, y, ch, color);
         }
         // オーバーフローチェック
-        if let Some(next_x) = cur_x.checked_add(8) {
+        if let Some(next_x) = cur_x.checked_add(CELL_WIDTH) {
             cur_x = next_x;
         } else {
             break; // オーバーフロー時は描画を停止
@@ -120,8 +137,316 @@ pub unsafe fn draw_string(fb_base: u64, width: u32, x: usize, y: usize, s: &str,
     }
 }
 
+// クリップ矩形の内側だけに文字を描画
+//
+// ウィンドウの重なりを考慮した再合成で、文字の一部だけを描き直すために使用します。
+//
+// # Safety
+// fb_base は有効なフレームバッファアドレスである必要があり、
+// clip が画面内に収まっていることを呼び出し側が保証する必要があります。
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn draw_char_clipped(
+    fb_base: u64,
+    width: u32,
+    x: u32,
+    y: u32,
+    ch: char,
+    color: Color,
+    typeface: &Typeface,
+    clip: &Region,
+) {
+    let cell = Region::new(
+        x,
+        y,
+        typeface.cell_width() as u32,
+        typeface.cell_height() as u32,
+    );
+    let Some(visible) = cell.intersect(clip) else {
+        return;
+    };
+    if visible.width == cell.width && visible.height == cell.height {
+        // 文字全体がクリップ内: 通常の描画
+        // SAFETY: 呼び出し元がclipの有効性を保証し、文字はclip内に収まっている
+        unsafe { draw_char_in(fb_base, width, x as usize, y as usize, ch, color, typeface) };
+        return;
+    }
+
+    let fb_ptr = fb_base as *mut u32;
+    let stride = width as usize;
+    let alpha = color.alpha();
+    let color = pixel_format::to_native(color);
+    let Some(glyph) = typeface.glyph(ch) else {
+        return; // 制御文字
+    };
+    // フォールバックのグリフはセルより小さいことがある
+    let bottom = visible
+        .bottom()
+        .min(y.saturating_add(glyph.height() as u32));
+    for py in visible.y..bottom {
+        let glyph_row = glyph.row((py - y) as usize);
+        for px in visible.x..visible.right() {
+            if (px - x) < u32::BITS && (glyph_row >> (px - x)) & 1 == 1 {
+                // SAFETY: (px, py) はclip内であり、呼び出し元が画面内であることを保証する
+                unsafe { put_pixel(fb_ptr.add(py as usize * stride + px as usize), color, alpha) };
+            }
+        }
+    }
+}
+
+// クリップ矩形の内側だけに文字列を描画
+//
+// # Safety
+// draw_char_clipped と同じ
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn draw_string_clipped(
+    fb_base: u64,
+    width: u32,
+    x: u32,
+    y: u32,
+    s: &str,
+    color: Color,
+    typeface: &Typeface,
+    clip: &Region,
+) {
+    let mut cur_x = x;
+    for ch in s.chars() {
+        if cur_x >= clip.right() {
+            break; // 以降の文字はすべてクリップ外
+        }
+        unsafe {
+            draw_char_clipped(fb_base, width, cur_x, y, ch, color, typeface, clip);
+        }
+        cur_x = cur_x.saturating_add(typeface.cell_width() as u32);
+    }
+}
+
+// クリップ矩形の内側だけにビットマップ（ARGB、行優先）を描画
+//
+// `mode` に従って、不透明なまま書き込む・ピクセルごとのアルファで合成する・
+// カラーキーと同じ色のピクセルを透明として飛ばす、のいずれかで描画します。
+// ピクセル数が `bitmap_width` × `bitmap_height` に足りない場合は、ある分だけ描画します。
+//
+// # Safety
+// draw_char_clipped と同じ
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn draw_bitmap_clipped(
+    fb_base: u64,
+    width: u32,
+    x: u32,
+    y: u32,
+    bitmap_width: u32,
+    bitmap_height: u32,
+    pixels: &[Color],
+    mode: BlitMode,
+    clip: &Region,
+) {
+    let placed = Region::new(x, y, bitmap_width, bitmap_height);
+    let Some(visible) = placed.intersect(clip) else {
+        return;
+    };
+    let fb_ptr = fb_base as *mut u32;
+    let stride = width as usize;
+    for py in visible.y..visible.bottom() {
+        let src_row = (py - y) as usize * bitmap_width as usize;
+        let dst_row = py as usize * stride;
+        for px in visible.x..visible.right() {
+            let Some(&pixel) = pixels.get(src_row + (px - x) as usize) else {
+                return;
+            };
+            let alpha = match mode {
+                BlitMode::Opaque => 0xFF,
+                BlitMode::Alpha => pixel.alpha(),
+                BlitMode::ColorKey(key) if pixel == key => 0,
+                BlitMode::ColorKey(_) => 0xFF,
+            };
+            if alpha != 0 {
+                // SAFETY: (px, py) はclip内であり、呼び出し元が画面内であることを保証する
+                unsafe {
+                    put_pixel(
+                        fb_ptr.add(dst_row + px as usize),
+                        pixel_format::to_native(pixel),
+                        alpha,
+                    )
+                };
+            }
+        }
+    }
+}
+
+// クリップ矩形の内側だけに線分を描画（Bresenham）
+//
+// 端点は符号付きで、クリップ矩形の外（負の座標を含む）にはみ出してもよい。
+// 半透明の色は既存の画素と合成します。
+//
+// # Safety
+// draw_char_clipped と同じ
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn draw_line_clipped(
+    fb_base: u64,
+    width: u32,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    color: Color,
+    clip: &Region,
+) {
+    let fb_ptr = fb_base as *mut u32;
+    let native = pixel_format::to_native(color);
+    let alpha = color.alpha();
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    loop {
+        // SAFETY: plot_clippedはclip外の画素を書き込まない
+        unsafe { plot_clipped(fb_ptr, width, x, y, native, alpha, clip) };
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += step_x;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += step_y;
+        }
+    }
+}
+
+// クリップ矩形の内側だけに円を描画
+//
+// 行ごとに円周の横幅を求めて描画するため、塗りつぶしでも同じ画素を二度書き込まず、
+// 半透明の色でも濃さが均一になります。中心はクリップ矩形の外にあってもよい。
+//
+// # Safety
+// draw_char_clipped と同じ
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn draw_circle_clipped(
+    fb_base: u64,
+    width: u32,
+    cx: i32,
+    cy: i32,
+    radius: u32,
+    color: Color,
+    filled: bool,
+    clip: &Region,
+) {
+    let fb_ptr = fb_base as *mut u32;
+    let native = pixel_format::to_native(color);
+    let alpha = color.alpha();
+    let r = radius.min(i32::MAX as u32 / 2) as i64;
+    // 行ごとの半幅（dyが増えるにつれて単調に減る）
+    let mut half = r;
+    let mut prev_half = r;
+    for dy in 0..=r {
+        while half > 0 && half * half + dy * dy > r * r {
+            half -= 1;
+        }
+        let rows: &[i64] = if dy == 0 { &[0] } else { &[dy, -dy] };
+        for &row in rows {
+            let y = cy as i64 + row;
+            let (left, right) = (cx as i64 - half, cx as i64 + half);
+            if filled {
+                // SAFETY: span_clippedはclip外の画素を書き込まない
+                unsafe { span_clipped(fb_ptr, width, left, right, y, native, alpha, clip) };
+            } else {
+                // 前の行との間が空かないよう、半幅が縮んだ分だけ内側へ伸ばす
+                let inner = (prev_half - 1).max(half);
+                // 半幅が0の行では左右の線が中心で重なるので、右側は中心を除く
+                let right_start = if half == 0 { cx as i64 + 1 } else { right };
+                // SAFETY: 同上
+                unsafe {
+                    span_clipped(
+                        fb_ptr,
+                        width,
+                        cx as i64 - inner,
+                        left,
+                        y,
+                        native,
+                        alpha,
+                        clip,
+                    );
+                    span_clipped(
+                        fb_ptr,
+                        width,
+                        right_start,
+                        cx as i64 + inner,
+                        y,
+                        native,
+                        alpha,
+                        clip,
+                    );
+                }
+            }
+        }
+        prev_half = half;
+    }
+}
+
+// クリップ矩形内の1ピクセルを書き込む（クリップ外は無視）
+//
+// # Safety
+// fb_ptr は幅 `width` のフレームバッファを指し、clipがその内側にあること
+#[inline]
+unsafe fn plot_clipped(
+    fb_ptr: *mut u32,
+    width: u32,
+    x: i32,
+    y: i32,
+    native: u32,
+    alpha: u8,
+    clip: &Region,
+) {
+    if alpha == 0 || x < 0 || y < 0 || !clip.contains(x as u32, y as u32) {
+        return;
+    }
+    // SAFETY: (x, y) はclip内
+    unsafe {
+        put_pixel(
+            fb_ptr.add(y as usize * width as usize + x as usize),
+            native,
+            alpha,
+        )
+    };
+}
+
+// クリップ矩形内の水平線（left..=right）を書き込む（クリップ外は無視）
+//
+// # Safety
+// plot_clipped と同じ
+#[allow(clippy::too_many_arguments)]
+#[inline]
+unsafe fn span_clipped(
+    fb_ptr: *mut u32,
+    width: u32,
+    left: i64,
+    right: i64,
+    y: i64,
+    native: u32,
+    alpha: u8,
+    clip: &Region,
+) {
+    if alpha == 0 || y < clip.y as i64 || y >= clip.bottom() as i64 {
+        return;
+    }
+    let left = left.max(clip.x as i64);
+    let right = right.min(clip.right() as i64 - 1);
+    let row = y as usize * width as usize;
+    for x in left..=right {
+        // SAFETY: (x, y) はclip内
+        unsafe { put_pixel(fb_ptr.add(row + x as usize), native, alpha) };
+    }
+}
+
 // 矩形を描画（塗りつぶし）
 //
+// 半透明の色は既存の画素と合成します（不透明な色は従来どおり高速に塗りつぶします）。
+//
 // # Safety
 // fb_base は有効なフレームバッファアドレスである必要があり、
 // 描画範囲が画面内に収まっていることを呼び出し側が保証する必要があります。
@@ -132,7 +457,7 @@ pub unsafe fn draw_rect(
     y: usize,
     w: usize,
     h: usize,
-    color: u32,
+    color: Color,
 ) {
     // 空の矩形は何もしない
     if w == 0 || h == 0 {
@@ -148,6 +473,21 @@ pub unsafe fn draw_rect(
         return; // 完全に画面外
     }
     let clipped_w = x_end - x;
+    let alpha = color.alpha();
+    let color = pixel_format::to_native(color);
+    if alpha == 0 {
+        return; // 完全に透明
+    }
+    if alpha != 0xFF {
+        for dy in 0..h {
+            let row_start = y.saturating_add(dy) * stride + x;
+            for dx in 0..clipped_w {
+                // SAFETY: 呼び出し側が描画範囲の有効性を保証
+                unsafe { put_pixel(fb.add(row_start + dx), color, alpha) };
+            }
+        }
+        return;
+    }
 
     // 行単位で塗りつぶし（rep stosd使用で高速化）
     for dy in 0..h {
@@ -176,13 +516,15 @@ pub unsafe fn draw_rect_outline(
     y: usize,
     w: usize,
     h: usize,
-    color: u32,
+    color: Color,
 ) {
     let fb = fb_base as *mut u32;
 
     if w == 0 || h == 0 {
         return; // サイズが0の場合は何もしない
     }
+    let alpha = color.alpha();
+    let color = pixel_format::to_native(color);
 
     // 上下の辺
     for dx in 0..w {
@@ -195,7 +537,7 @@ pub unsafe fn draw_rect_outline(
                 .and_then(|y_off| y_off.checked_add(pixel_x));
             if let Some(off) = top_offset {
                 unsafe {
-                    *fb.add(off) = color;
+                    put_pixel(fb.add(off), color, alpha);
                 }
             }
 
@@ -206,7 +548,7 @@ pub unsafe fn draw_rect_outline(
                     .and_then(|y_off| y_off.checked_add(pixel_x));
                 if let Some(off) = bottom_offset {
                     unsafe {
-                        *fb.add(off) = color;
+                        put_pixel(fb.add(off), color, alpha);
                     }
                 }
             }
@@ -223,7 +565,7 @@ pub unsafe fn draw_rect_outline(
                     .and_then(|y_off| y_off.checked_add(x));
                 if let Some(off) = left_offset {
                     unsafe {
-                        *fb.add(off) = color;
+                        put_pixel(fb.add(off), color, alpha);
                     }
                 }
             }
@@ -237,7 +579,7 @@ pub unsafe fn draw_rect_outline(
                     .and_then(|y_off| y_off.checked_add(right_x));
                 if let Some(off) = right_offset {
                     unsafe {
-                        *fb.add(off) = color;
+                        put_pixel(fb.add(off), color, alpha);
                     }
                 }
             }
@@ -247,39 +589,46 @@ pub unsafe fn draw_rect_outline(
 
 // フレームバッファライター（writeln!マクロ対応）
 pub struct FramebufferWriter {
-    // 可視化機能が有効な場合はパブリック、それ以外はプライベート
-    #[cfg(feature = "visualize-allocator")]
-    pub fb_base: u64,
-    #[cfg(not(feature = "visualize-allocator"))]
     fb_base: u64,
-
-    #[cfg(feature = "visualize-allocator")]
-    pub width: u32,
-    #[cfg(not(feature = "visualize-allocator"))]
     width: u32,
-
-    #[cfg(feature = "visualize-allocator")]
-    pub height: u32,
-    #[cfg(not(feature = "visualize-allocator"))]
     height: u32,
-
+    // 1行のピクセル数（幅より大きいことがある）
+    stride: u32,
     x: usize,
     y: usize,
-    color: u32,
+    color: Color,
+    // 最後に画面をクリアした色（スクロールで空いた行を塗る）
+    background: Color,
+    // 文字の書体（文字の送りと行の高さを決める）
+    typeface: Typeface,
 }
 
 impl FramebufferWriter {
-    pub fn new(fb_base: u64, width: u32, height: u32, color: u32) -> Self {
+    pub fn new(fb_base: u64, width: u32, height: u32, stride: u32, color: Color) -> Self {
         Self {
             fb_base,
             width,
             height,
+            stride,
             x: 0,
             y: 0,
             color,
+            background: Color::BLACK,
+            typeface: Typeface::Builtin,
         }
     }
 
+    // 書体を設定（以降の文字と改行に使われる）
+    #[allow(dead_code)]
+    pub fn set_typeface(&mut self, typeface: Typeface) {
+        self.typeface = typeface;
+    }
+
+    // 1行の高さ（ピクセル）
+    pub fn line_height(&self) -> usize {
+        self.typeface.cell_height()
+    }
+
     // カーソル位置を設定
     #[allow(dead_code)]
     pub fn set_position(&mut self, x: usize, y: usize) {
@@ -289,19 +638,19 @@ impl FramebufferWriter {
 
     // 文字色を設定
     #[allow(dead_code)]
-    pub fn set_color(&mut self, color: u32) {
+    pub fn set_color(&mut self, color: Color) {
         self.color = color;
     }
 
     // 現在位置から指定幅をクリア（背景色で塗りつぶし）
     #[allow(dead_code)]
-    pub fn clear_area(&mut self, width_chars: usize, bg_color: u32) {
-        let width_pixels = width_chars * 8;
-        let height_pixels = 10; // 1行分の高さ
+    pub fn clear_area(&mut self, width_chars: usize, bg_color: Color) {
+        let width_pixels = width_chars * self.typeface.cell_width();
+        let height_pixels = self.line_height();
         unsafe {
             draw_rect(
                 self.fb_base,
-                self.width,
+                self.stride,
                 self.x,
                 self.y,
                 width_pixels,
@@ -311,25 +660,54 @@ impl FramebufferWriter {
         }
     }
 
-    // 改行処理
+    // 改行処理（下端に達したら1行分スクロール）
     fn newline(&mut self) {
+        let line_height = self.line_height();
         self.x = 0;
-        self.y += 10; // 1行分（8ピクセル + マージン2ピクセル）
+        self.y += line_height;
+        if self.y + line_height > self.height as usize && self.y >= line_height {
+            self.scroll_up();
+            self.y -= line_height;
+        }
+    }
+
+    // 画面を1行分上へ移し、空いた最下行を背景色で塗りつぶす
+    fn scroll_up(&mut self) {
+        let stride = self.stride as usize;
+        let line_height = self.line_height();
+        let shift = line_height * stride;
+        let keep = self.y * stride;
+        let fb = self.fb_base as *mut u32;
+        // SAFETY: 移動元はy + line_height行目まで（画面の高さを超えない範囲）にあり、
+        // 重なる領域の移動のためcopy（memmove）を使う
+        unsafe {
+            core::ptr::copy(fb.add(shift), fb, keep);
+            draw_rect(
+                self.fb_base,
+                self.stride,
+                0,
+                self.y - line_height,
+                self.width as usize,
+                line_height,
+                self.background,
+            );
+        }
     }
 
     /// 画面全体をクリア（指定色で塗りつぶし）
     ///
     /// # Arguments
-    /// * `color` - 塗りつぶし色（0xRRGGBB形式）
-    pub fn clear_screen(&mut self, color: u32) {
+    /// * `color` - 塗りつぶし色
+    pub fn clear_screen(&mut self, color: Color) {
         let fb = self.fb_base as *mut u32;
-        let total_pixels = (self.width as usize) * (self.height as usize);
-        // rep stosdを使用して高速に塗りつぶし
+        // 行末の余白も含めて一度に塗りつぶす
+        let total_pixels = (self.stride as usize) * (self.height as usize);
         // SAFETY: fb_baseは有効なフレームバッファアドレスであり、
-        // total_pixelsはwidth * heightで計算された有効な範囲
+        // total_pixelsはstride * heightで計算された有効な範囲
         unsafe {
-            fast_fill_u32(fb, color, total_pixels);
+            fast_fill_u32(fb, pixel_format::to_native(color), total_pixels);
         }
+        self.background = color;
         // カーソルを左上に戻す
         self.x = 0;
         self.y = 0;
@@ -338,19 +716,28 @@ impl FramebufferWriter {
 
 impl core::fmt::Write for FramebufferWriter {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        for ch in s.bytes() {
-            if ch == b'\n' {
+        for ch in s.chars() {
+            if ch == '\n' {
                 self.newline();
             } else {
                 // 画面の右端に達したら自動改行
-                if self.x + 8 > self.width as usize {
+                let cell_width = self.typeface.cell_width();
+                if self.x + cell_width > self.width as usize {
                     self.newline();
                 }
 
                 unsafe {
-                    draw_char(self.fb_base, self.width, self.x, self.y, ch, self.color);
+                    draw_char_in(
+                        self.fb_base,
+                        self.stride,
+                        self.x,
+                        self.y,
+                        ch,
+                        self.color,
+                        &self.typeface,
+                    );
                 }
-                self.x += 8;
+                self.x += cell_width;
             }
         }
         Ok(())
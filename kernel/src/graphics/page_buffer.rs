@@ -0,0 +1,147 @@
+//! フレームアロケータから確保するピクセルバッファ
+//!
+//! シャドウバッファやウィンドウのバックバッファは数MBに達するため、
+//! スラブ/バンプアロケータ（4KB超は解放不可）ではなく、物理的に連続したフレームを
+//! 直接マッピング経由で使用します。確保量はコンポジタのメモリ使用量として集計されます。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::frame_allocator;
+use crate::paging::{PAGE_SIZE, phys_to_virt};
+
+/// 確保中のバッファ数
+static BUFFER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 確保中のバイト数（ページ単位に切り上げた値）
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// ピクセルバッファ確保のエラー
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAllocError {
+    /// サイズが0、またはサイズ計算がオーバーフローした
+    InvalidSize,
+    /// 連続した空きフレームが不足している
+    OutOfFrames,
+}
+
+impl core::fmt::Display for BufferAllocError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            BufferAllocError::InvalidSize => write!(f, "Invalid buffer size"),
+            BufferAllocError::OutOfFrames => write!(f, "Not enough contiguous frames"),
+        }
+    }
+}
+
+/// ページバッファ全体のメモリ使用量
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct PageBufferUsage {
+    /// 確保中のバッファ数
+    pub buffers: usize,
+    /// 確保中のバイト数
+    pub bytes: usize,
+}
+
+/// 物理的に連続したフレーム上のu32ピクセルバッファ
+///
+/// 破棄時にフレームをフレームアロケータへ返却します。
+pub struct PageBuffer {
+    /// 先頭フレームの物理アドレス
+    phys_base: u64,
+    /// 直接マッピング経由の仮想アドレス
+    virt_base: u64,
+    /// フレーム数
+    frame_count: usize,
+    /// ピクセル数
+    len: usize,
+}
+
+// SAFETY: PageBufferは確保したフレームを排他的に所有し、
+// アクセスは&self/&mut selfを通じてのみ行われる。
+unsafe impl Send for PageBuffer {}
+
+impl PageBuffer {
+    /// 指定ピクセル数のバッファを確保し、0で初期化
+    ///
+    /// # Errors
+    /// * `BufferAllocError::InvalidSize` - ピクセル数が0、またはバイト数がオーバーフローした場合
+    /// * `BufferAllocError::OutOfFrames` - 連続した空きフレームが不足している場合
+    pub fn new(len: usize) -> Result<Self, BufferAllocError> {
+        let bytes = len
+            .checked_mul(core::mem::size_of::<u32>())
+            .filter(|&b| b > 0)
+            .ok_or(BufferAllocError::InvalidSize)?;
+        let frame_count = bytes.div_ceil(PAGE_SIZE);
+
+        let phys_base =
+            frame_allocator::alloc_contiguous(frame_count).ok_or(BufferAllocError::OutOfFrames)?;
+        let virt_base = match phys_to_virt(phys_base) {
+            Ok(virt) => virt,
+            Err(_) => {
+                let _ = frame_allocator::free_contiguous(phys_base, frame_count);
+                return Err(BufferAllocError::OutOfFrames);
+            }
+        };
+
+        // SAFETY: 確保したフレームは直接マッピングされており、
+        // frame_count * PAGE_SIZEバイトを排他的に所有している。
+        unsafe {
+            core::ptr::write_bytes(virt_base as *mut u8, 0, frame_count * PAGE_SIZE);
+        }
+
+        BUFFER_COUNT.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(frame_count * PAGE_SIZE, Ordering::Relaxed);
+
+        Ok(Self {
+            phys_base,
+            virt_base,
+            frame_count,
+            len,
+        })
+    }
+
+    /// ピクセルデータへのスライス
+    #[inline]
+    pub fn as_slice(&self) -> &[u32] {
+        // SAFETY: virt_baseはlen個のu32を保持する確保済み領域を指している
+        unsafe { core::slice::from_raw_parts(self.virt_base as *const u32, self.len) }
+    }
+
+    /// ピクセルデータへの可変スライス
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u32] {
+        // SAFETY: virt_baseはlen個のu32を保持する確保済み領域を指しており、
+        // &mut selfにより排他的にアクセスしている
+        unsafe { core::slice::from_raw_parts_mut(self.virt_base as *mut u32, self.len) }
+    }
+
+    /// 先頭ピクセルへのポインタ
+    #[inline]
+    pub fn as_ptr(&self) -> *const u32 {
+        self.virt_base as *const u32
+    }
+}
+
+impl Drop for PageBuffer {
+    fn drop(&mut self) {
+        if let Err(e) = frame_allocator::free_contiguous(self.phys_base, self.frame_count) {
+            crate::warn!(
+                "PageBuffer: failed to free frames at 0x{:X}: {}",
+                self.phys_base,
+                e
+            );
+        }
+        BUFFER_COUNT.fetch_sub(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_sub(self.frame_count * PAGE_SIZE, Ordering::Relaxed);
+    }
+}
+
+/// ページバッファ全体のメモリ使用量を取得
+pub fn usage() -> PageBufferUsage {
+    PageBufferUsage {
+        buffers: BUFFER_COUNT.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,196 @@
+//! ピクセルフォーマット変換
+//!
+//! カーネル内部の色表現は 0x00RRGGBB 形式（メモリ上は B, G, R, X の順）で統一し、
+//! フレームバッファへ書き込む直前にGOPが報告したフォーマットへ変換します。
+//! 内部表現と一致するフォーマット（UEFIのBlueGreenRed）では変換は行われません。
+
+use core::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+use vitros_common::boot_info::FramebufferInfo;
+use vitros_common::uefi;
+
+use super::color::Color;
+
+/// 対応しているピクセルフォーマット
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 0x00RRGGBB（UEFI PixelBlueGreenRedReserved8BitPerColor）。内部表現と同一
+    Bgr,
+    /// 0x00BBGGRR（UEFI PixelRedGreenBlueReserved8BitPerColor）
+    Rgb,
+    /// ビットマスク指定（各色が8ビット幅でバイト境界に揃っている場合のみ対応）
+    Bitmask {
+        red_shift: u8,
+        green_shift: u8,
+        blue_shift: u8,
+    },
+}
+
+impl core::fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PixelFormat::Bgr => write!(f, "BGR (native)"),
+            PixelFormat::Rgb => write!(f, "RGB"),
+            PixelFormat::Bitmask {
+                red_shift,
+                green_shift,
+                blue_shift,
+            } => write!(
+                f,
+                "Bitmask (R<<{}, G<<{}, B<<{})",
+                red_shift, green_shift, blue_shift
+            ),
+        }
+    }
+}
+
+/// ピクセルフォーマットのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormatError {
+    /// フレームバッファを持たない（PixelBltOnly）
+    BltOnly,
+    /// 8ビット幅・バイト境界以外のビットマスク
+    UnsupportedBitmask { red: u32, green: u32, blue: u32 },
+    /// 未知のフォーマット値
+    Unknown(u32),
+}
+
+impl core::fmt::Display for PixelFormatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PixelFormatError::BltOnly => {
+                write!(f, "GOP mode is BltOnly (no linear framebuffer)")
+            }
+            PixelFormatError::UnsupportedBitmask { red, green, blue } => write!(
+                f,
+                "Unsupported pixel bitmask R=0x{:08X} G=0x{:08X} B=0x{:08X}",
+                red, green, blue
+            ),
+            PixelFormatError::Unknown(value) => write!(f, "Unknown pixel format {}", value),
+        }
+    }
+}
+
+/// 現在のフォーマット種別（0: BGR, 1: RGB, 2: Bitmask）
+const KIND_BGR: u8 = 0;
+const KIND_RGB: u8 = 1;
+const KIND_BITMASK: u8 = 2;
+
+static FORMAT_KIND: AtomicU8 = AtomicU8::new(KIND_BGR);
+
+/// Bitmask時の各色シフト量（赤: bit0-7, 緑: bit8-15, 青: bit16-23）
+static BITMASK_SHIFTS: AtomicU32 = AtomicU32::new(0);
+
+/// 8ビット幅でバイト境界に揃ったマスクからシフト量を求める
+fn mask_shift(mask: u32) -> Option<u8> {
+    let shift = mask.trailing_zeros();
+    if shift < 32 && shift.is_multiple_of(8) && mask >> shift == 0xFF {
+        Some(shift as u8)
+    } else {
+        None
+    }
+}
+
+/// GOPが報告したフォーマットを解析する
+fn parse(fb: &FramebufferInfo) -> Result<PixelFormat, PixelFormatError> {
+    match fb.pixel_format {
+        uefi::EFI_PIXEL_BLUE_GREEN_RED_RESERVED_8BIT_PER_COLOR => Ok(PixelFormat::Bgr),
+        uefi::EFI_PIXEL_RED_GREEN_BLUE_RESERVED_8BIT_PER_COLOR => Ok(PixelFormat::Rgb),
+        uefi::EFI_PIXEL_BIT_MASK => {
+            let [red, green, blue, _] = fb.pixel_bitmask;
+            match (mask_shift(red), mask_shift(green), mask_shift(blue)) {
+                (Some(16), Some(8), Some(0)) => Ok(PixelFormat::Bgr),
+                (Some(0), Some(8), Some(16)) => Ok(PixelFormat::Rgb),
+                (Some(red_shift), Some(green_shift), Some(blue_shift)) => {
+                    Ok(PixelFormat::Bitmask {
+                        red_shift,
+                        green_shift,
+                        blue_shift,
+                    })
+                }
+                _ => Err(PixelFormatError::UnsupportedBitmask { red, green, blue }),
+            }
+        }
+        uefi::EFI_PIXEL_BLT_ONLY => Err(PixelFormatError::BltOnly),
+        other => Err(PixelFormatError::Unknown(other)),
+    }
+}
+
+/// フレームバッファのピクセルフォーマットを設定
+///
+/// 描画処理を行う前に一度だけ呼び出します。
+///
+/// # Arguments
+/// * `fb` - ブートローダから渡されたフレームバッファ情報
+///
+/// # Returns
+/// 採用したピクセルフォーマット
+///
+/// # Errors
+/// 描画に対応していないフォーマットの場合は `PixelFormatError` を返します。
+/// この場合、フレームバッファへの描画を行ってはいけません。
+pub fn init(fb: &FramebufferInfo) -> Result<PixelFormat, PixelFormatError> {
+    let format = parse(fb)?;
+    match format {
+        PixelFormat::Bgr => FORMAT_KIND.store(KIND_BGR, Ordering::Relaxed),
+        PixelFormat::Rgb => FORMAT_KIND.store(KIND_RGB, Ordering::Relaxed),
+        PixelFormat::Bitmask {
+            red_shift,
+            green_shift,
+            blue_shift,
+        } => {
+            let shifts = red_shift as u32 | (green_shift as u32) << 8 | (blue_shift as u32) << 16;
+            BITMASK_SHIFTS.store(shifts, Ordering::Relaxed);
+            FORMAT_KIND.store(KIND_BITMASK, Ordering::Relaxed);
+        }
+    }
+    Ok(format)
+}
+
+/// 色をフレームバッファのネイティブ形式に変換（アルファは捨てる）
+///
+/// 描画関数の入口で1回だけ呼び出し、ピクセルごとのループでは変換済みの値を使います。
+/// 内部表現と同じBGRフォーマットでは0x00RRGGBBをそのまま返します。
+#[inline(always)]
+pub fn to_native(color: Color) -> u32 {
+    let color = color.to_rgb_u32();
+    match FORMAT_KIND.load(Ordering::Relaxed) {
+        KIND_BGR => color,
+        kind => convert_slow(color, kind),
+    }
+}
+
+#[cold]
+fn convert_slow(color: u32, kind: u8) -> u32 {
+    let r = (color >> 16) & 0xFF;
+    let g = (color >> 8) & 0xFF;
+    let b = color & 0xFF;
+    if kind == KIND_RGB {
+        return (b << 16) | (g << 8) | r;
+    }
+    let shifts = BITMASK_SHIFTS.load(Ordering::Relaxed);
+    (r << (shifts & 0xFF)) | (g << ((shifts >> 8) & 0xFF)) | (b << ((shifts >> 16) & 0xFF))
+}
+
+/// ネイティブ形式の色 `src` をアルファ値 `alpha` で `dst` の上に合成（source-over）
+///
+/// 対応しているフォーマットはすべて各色が8ビット幅でバイト境界に揃っているため、
+/// 色の並びに関係なくバイトごとに補間できます（変換は不要）。
+#[inline]
+pub fn blend_native(dst: u32, src: u32, alpha: u8) -> u32 {
+    match alpha {
+        0xFF => src,
+        0 => dst,
+        _ => {
+            let a = alpha as u32;
+            let inv = 255 - a;
+            // 1バイトおきの2色を16ビットずつの組にまとめて補間し、組ごとに255で割る（四捨五入）
+            let mix = |src: u32, dst: u32| {
+                let t = (src & 0x00FF_00FF) * a + (dst & 0x00FF_00FF) * inv + 0x0080_0080;
+                ((t + ((t >> 8) & 0x00FF_00FF)) >> 8) & 0x00FF_00FF
+            };
+            mix(src, dst) | mix(src >> 8, dst >> 8) << 8
+        }
+    }
+}
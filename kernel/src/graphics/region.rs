@@ -52,4 +52,28 @@ impl Region {
     pub fn bottom(&self) -> u32 {
         self.y + self.height
     }
+
+    /// 他の領域との共通部分を取得
+    ///
+    /// # Returns
+    /// 重なりがなければNone
+    pub fn intersect(&self, other: &Region) -> Option<Region> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Region::new(x, y, right - x, bottom - y))
+    }
+
+    /// 両方の領域を含む最小の領域（バウンディングボックス）を取得
+    pub fn union(&self, other: &Region) -> Region {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Region::new(x, y, right - x, bottom - y)
+    }
 }
@@ -3,15 +3,13 @@
 //! ハードウェアフレームバッファへの直接描画を避け、
 //! フレーム完成後に一括転送することでちらつきを防止します。
 
-use alloc::vec;
-use alloc::vec::Vec;
-
+use super::page_buffer::{BufferAllocError, PageBuffer};
 use super::region::Region;
 
 /// シャドウフレームバッファ
 pub struct ShadowBuffer {
-    /// ピクセルデータ（ARGB 32bit）
-    buffer: Vec<u32>,
+    /// ピクセルデータ（ARGB 32bit、フレームアロケータから確保）
+    buffer: PageBuffer,
     /// バッファの幅（ピクセル）
     width: u32,
     /// バッファの高さ（ピクセル）
@@ -27,19 +25,20 @@ impl ShadowBuffer {
     /// * `width` - バッファの幅（ピクセル）
     /// * `height` - バッファの高さ（ピクセル）
     ///
-    /// # Panics
-    /// `width * height`がオーバーフローする場合にパニックします。
-    pub fn new(width: u32, height: u32) -> Self {
+    /// # Errors
+    /// * `BufferAllocError::InvalidSize` - `width * height`が0またはオーバーフローする場合
+    /// * `BufferAllocError::OutOfFrames` - 連続した空きフレームが不足している場合
+    pub fn new(width: u32, height: u32) -> Result<Self, BufferAllocError> {
         let size = (width as usize)
             .checked_mul(height as usize)
-            .expect("ShadowBuffer size overflow");
-        let buffer = vec![0u32; size]; // 黒で初期化
-        Self {
+            .ok_or(BufferAllocError::InvalidSize)?;
+        let buffer = PageBuffer::new(size)?; // 黒で初期化
+        Ok(Self {
             buffer,
             width,
             height,
             dirty_rect: None,
-        }
+        })
     }
 
     /// バッファをu64アドレスとして取得（既存描画関数との互換性）
@@ -55,17 +54,24 @@ impl ShadowBuffer {
     }
 
     /// 高さを取得
-    #[allow(dead_code)]
     #[inline]
     pub fn height(&self) -> u32 {
         self.height
     }
 
+    /// ピクセルデータへの可変参照を取得（ネイティブ形式、行優先）
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        self.buffer.as_mut_slice()
+    }
+
     /// バッファ全体をクリア
     #[allow(dead_code)]
     #[inline]
-    pub fn clear(&mut self, color: u32) {
-        self.buffer.fill(color);
+    pub fn clear(&mut self, color: super::color::Color) {
+        self.buffer
+            .as_mut_slice()
+            .fill(super::pixel_format::to_native(color));
         self.mark_all_dirty();
     }
 
@@ -132,28 +138,32 @@ impl ShadowBuffer {
     /// # Safety
     /// - `hw_fb_base`は有効なフレームバッファアドレスであること
     /// - `hw_fb_base`は4バイト境界にアライメントされていること
-    /// - 転送先には`self.buffer.len() * 4`バイト以上の書き込み可能な領域があること
+    /// - 転送先には`hw_stride * height * 4`バイト以上の書き込み可能な領域があること
+    /// - `hw_stride`は`width`以上であること
     /// - 呼び出し元は転送先メモリへの排他的アクセス権を持つこと
-    pub unsafe fn blit_to(&mut self, hw_fb_base: u64) -> bool {
+    pub unsafe fn blit_to(&mut self, hw_fb_base: u64, hw_stride: u32) -> bool {
         let dirty = match self.take_dirty_rect() {
             Some(r) => r,
             None => return false, // 変更なし、転送不要
         };
 
+        // 描画時にネイティブ形式へ変換済みのため、ここではそのままコピーする
         let dst_base = hw_fb_base as *mut u32;
         let src_base = self.buffer.as_ptr();
         let stride = self.width as usize;
+        let hw_stride = hw_stride as usize;
 
-        // dirty rect内の各行をコピー
+        // dirty rect内の各行をコピー（転送先は行末に余白があることがあるため、行の間隔が異なる）
         for y in dirty.y..(dirty.y + dirty.height) {
-            let row_offset = (y as usize) * stride + (dirty.x as usize);
-            // SAFETY: row_offset < width * height が保証されている
+            let src_offset = (y as usize) * stride + (dirty.x as usize);
+            let dst_offset = (y as usize) * hw_stride + (dirty.x as usize);
+            // SAFETY: src_offset < width * height、dst_offset < hw_stride * height が保証されている
             // （dirty rectは画面境界でクリップ済み）
             unsafe {
-                let src = src_base.add(row_offset);
-                let dst = dst_base.add(row_offset);
+                let src = src_base.add(src_offset);
+                let dst = dst_base.add(dst_offset);
                 let count = dirty.width as usize;
-                core::ptr::copy_nonoverlapping(src, dst, count);
+                super::simd::copy_pixels(dst, src, count);
             }
         }
 
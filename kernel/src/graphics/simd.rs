@@ -0,0 +1,310 @@
+//! SIMDによるピクセルの転送・塗りつぶし
+//!
+//! 行単位のピクセル転送（シャドウバッファ→フレームバッファ、バッキングストア間のコピー）と
+//! 大きな塗りつぶしを、SSE2またはAVXの128/256ビットのロード・ストアで行います。
+//! 使用する命令セットは初回の使用時にCPUの機能（`cpu::features`）とCR4、タスク切り替えで
+//! 保存する拡張状態（`cpu::xsave_components`）から判定し、使えない場合は
+//! スカラー（`copy_nonoverlapping`・`rep stosd`）にフォールバックします。
+//!
+//! # カーネル内でのSIMDレジスタの扱い
+//! - カーネルは浮動小数点・SIMDを使わない設定でビルドされるため、割り込みハンドラが
+//!   XMM/YMMレジスタを壊すことはありません
+//! - タスク切り替えはXMMレジスタを（XSAVEでAVXの状態を保存する場合はYMMレジスタも）
+//!   保存するため、どちらの経路もタスクの途中でプリエンプトされても安全です
+//! - AVXはタスク切り替えがYMMレジスタを保存する場合だけ使います（`cpu::init_extended_state`）
+
+use core::arch::asm;
+use core::arch::x86_64::{
+    __m128i, __m256i, _mm_loadu_si128, _mm_set1_epi32, _mm_storeu_si128, _mm256_loadu_si256,
+    _mm256_set1_epi32, _mm256_storeu_si256,
+};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// これより短い転送・塗りつぶしはスカラーで行う（ピクセル数）
+const SIMD_MIN_PIXELS: usize = 16;
+
+/// CR4.OSFXSR（SSE命令とfxsaveによるXMMレジスタの保存を有効にする）
+const CR4_OSFXSR: u64 = 1 << 9;
+
+/// まだ判定していないことを表す値
+const UNDETECTED: u8 = u8::MAX;
+
+/// CPUが対応する最も速い命令セット
+static SUPPORTED: AtomicU8 = AtomicU8::new(UNDETECTED);
+
+/// 使用中の命令セット（ベンチマークのため、対応する範囲で切り替えられる）
+static ACTIVE: AtomicU8 = AtomicU8::new(UNDETECTED);
+
+/// ピクセル転送に使う命令セット
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    /// SIMDを使わない
+    Scalar = 0,
+    /// 128ビット（SSE2）
+    Sse2 = 1,
+    /// 256ビット（AVX）
+    Avx = 2,
+}
+
+impl SimdLevel {
+    /// 遅い順のすべての命令セット
+    pub const ALL: [SimdLevel; 3] = [SimdLevel::Scalar, SimdLevel::Sse2, SimdLevel::Avx];
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => SimdLevel::Sse2,
+            2 => SimdLevel::Avx,
+            _ => SimdLevel::Scalar,
+        }
+    }
+
+    /// 表示用の名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SimdLevel::Scalar => "scalar",
+            SimdLevel::Sse2 => "sse2",
+            SimdLevel::Avx => "avx",
+        }
+    }
+
+    /// 名前から変換
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.as_str() == name)
+    }
+}
+
+/// SIMD設定のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimdError {
+    /// CPUまたはOSの設定が対応していない命令セット
+    Unsupported(SimdLevel),
+}
+
+impl core::fmt::Display for SimdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SimdError::Unsupported(level) => {
+                write!(f, "{} is not supported on this CPU", level.as_str())
+            }
+        }
+    }
+}
+
+/// CPUが対応する最も速い命令セットを判定
+fn detect() -> SimdLevel {
+    let cr4: u64;
+    // SAFETY: CR4の読み出しは副作用がない（カーネルはリング0で動作する）
+    unsafe { asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags)) };
+    let features = crate::cpu::features();
+    if cr4 & CR4_OSFXSR == 0 || !features.sse2 {
+        return SimdLevel::Scalar;
+    }
+    // YMMレジスタをタスク切り替えで保存する場合だけAVXを使う
+    // （XCR0のAVXを設定するのはその場合だけなので、それ以外ではAVX命令は#UDになる）
+    if features.avx && crate::cpu::xsave_components() & crate::cpu::XCR0_AVX != 0 {
+        SimdLevel::Avx
+    } else {
+        SimdLevel::Sse2
+    }
+}
+
+/// CPUが対応する最も速い命令セット
+pub fn supported() -> SimdLevel {
+    match SUPPORTED.load(Ordering::Relaxed) {
+        UNDETECTED => {
+            let level = detect();
+            SUPPORTED.store(level as u8, Ordering::Relaxed);
+            level
+        }
+        level => SimdLevel::from_u8(level),
+    }
+}
+
+/// 使用中の命令セット
+pub fn active() -> SimdLevel {
+    match ACTIVE.load(Ordering::Relaxed) {
+        UNDETECTED => {
+            let level = supported();
+            ACTIVE.store(level as u8, Ordering::Relaxed);
+            level
+        }
+        level => SimdLevel::from_u8(level),
+    }
+}
+
+/// 使用する命令セットを切り替える（ベンチマーク用）
+///
+/// # Errors
+/// * `SimdError::Unsupported` - CPUが対応していない命令セットの場合
+pub fn set_active(level: SimdLevel) -> Result<(), SimdError> {
+    if level > supported() {
+        return Err(SimdError::Unsupported(level));
+    }
+    ACTIVE.store(level as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+/// ピクセルを転送
+///
+/// # Safety
+/// - `src` から `count` 個のu32が読み出し可能で、`dst` から `count` 個のu32が書き込み可能であること
+/// - 両者が重なっていないこと
+#[inline]
+pub unsafe fn copy_pixels(dst: *mut u32, src: *const u32, count: usize) {
+    let level = if count < SIMD_MIN_PIXELS {
+        SimdLevel::Scalar
+    } else {
+        active()
+    };
+    // SAFETY: 呼び出し元が範囲を保証し、命令セットはCPUが対応するものに限られる
+    unsafe {
+        match level {
+            SimdLevel::Scalar => core::ptr::copy_nonoverlapping(src, dst, count),
+            SimdLevel::Sse2 => copy_sse2(dst, src, count),
+            SimdLevel::Avx => copy_avx(dst, src, count),
+        }
+    }
+}
+
+/// ピクセルを同じ値で塗りつぶす
+///
+/// # Safety
+/// `dst` から `count` 個のu32が書き込み可能であること
+#[inline]
+pub unsafe fn fill_pixels(dst: *mut u32, value: u32, count: usize) {
+    let level = if count < SIMD_MIN_PIXELS {
+        SimdLevel::Scalar
+    } else {
+        active()
+    };
+    // SAFETY: 呼び出し元が範囲を保証し、命令セットはCPUが対応するものに限られる
+    unsafe {
+        match level {
+            SimdLevel::Scalar => fill_scalar(dst, value, count),
+            SimdLevel::Sse2 => fill_sse2(dst, value, count),
+            SimdLevel::Avx => fill_avx(dst, value, count),
+        }
+    }
+}
+
+/// 同じ長さの行を転送（長さが違う場合は短い方に合わせる）
+pub fn copy_row(dst: &mut [u32], src: &[u32]) {
+    let count = dst.len().min(src.len());
+    // SAFETY: 両方のスライスにcount個以上の要素があり、&mutと&は重ならない
+    unsafe { copy_pixels(dst.as_mut_ptr(), src.as_ptr(), count) };
+}
+
+/// `rep stosd` による塗りつぶし
+///
+/// # Safety
+/// fill_pixels と同じ
+#[inline(always)]
+unsafe fn fill_scalar(dst: *mut u32, value: u32, count: usize) {
+    if count == 0 {
+        return;
+    }
+    // SAFETY: 呼び出し元がdstの有効性とcount個の書き込み可能領域を保証する
+    unsafe {
+        asm!(
+            "rep stosd",
+            inout("rdi") dst => _,
+            inout("ecx") count => _,
+            in("eax") value,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// SSE2による転送（1周で16ピクセル）
+///
+/// # Safety
+/// copy_pixels と同じ。加えてCPUとCR4がSSE2に対応していること
+#[target_feature(enable = "sse2")]
+unsafe fn copy_sse2(dst: *mut u32, src: *const u32, count: usize) {
+    let mut i = 0;
+    // SAFETY: i + 16 <= count の範囲だけをロード・ストアする
+    unsafe {
+        while i + 16 <= count {
+            let s = src.add(i) as *const __m128i;
+            let d = dst.add(i) as *mut __m128i;
+            let (a, b) = (_mm_loadu_si128(s), _mm_loadu_si128(s.add(1)));
+            let (c, e) = (_mm_loadu_si128(s.add(2)), _mm_loadu_si128(s.add(3)));
+            _mm_storeu_si128(d, a);
+            _mm_storeu_si128(d.add(1), b);
+            _mm_storeu_si128(d.add(2), c);
+            _mm_storeu_si128(d.add(3), e);
+            i += 16;
+        }
+        core::ptr::copy_nonoverlapping(src.add(i), dst.add(i), count - i);
+    }
+}
+
+/// SSE2による塗りつぶし（1周で16ピクセル）
+///
+/// # Safety
+/// fill_pixels と同じ。加えてCPUとCR4がSSE2に対応していること
+#[target_feature(enable = "sse2")]
+unsafe fn fill_sse2(dst: *mut u32, value: u32, count: usize) {
+    let v = _mm_set1_epi32(value as i32);
+    let mut i = 0;
+    // SAFETY: i + 16 <= count の範囲だけをストアする
+    unsafe {
+        while i + 16 <= count {
+            let d = dst.add(i) as *mut __m128i;
+            _mm_storeu_si128(d, v);
+            _mm_storeu_si128(d.add(1), v);
+            _mm_storeu_si128(d.add(2), v);
+            _mm_storeu_si128(d.add(3), v);
+            i += 16;
+        }
+        fill_scalar(dst.add(i), value, count - i);
+    }
+}
+
+/// AVXによる転送（1周で32ピクセル）
+///
+/// # Safety
+/// copy_pixels と同じ。加えてCPUとXCR0がAVXに対応していること
+#[target_feature(enable = "avx")]
+unsafe fn copy_avx(dst: *mut u32, src: *const u32, count: usize) {
+    let mut i = 0;
+    // SAFETY: i + 32 <= count の範囲だけをロード・ストアする
+    unsafe {
+        while i + 32 <= count {
+            let s = src.add(i) as *const __m256i;
+            let d = dst.add(i) as *mut __m256i;
+            let (a, b) = (_mm256_loadu_si256(s), _mm256_loadu_si256(s.add(1)));
+            let (c, e) = (_mm256_loadu_si256(s.add(2)), _mm256_loadu_si256(s.add(3)));
+            _mm256_storeu_si256(d, a);
+            _mm256_storeu_si256(d.add(1), b);
+            _mm256_storeu_si256(d.add(2), c);
+            _mm256_storeu_si256(d.add(3), e);
+            i += 32;
+        }
+        core::ptr::copy_nonoverlapping(src.add(i), dst.add(i), count - i);
+    }
+}
+
+/// AVXによる塗りつぶし（1周で32ピクセル）
+///
+/// # Safety
+/// fill_pixels と同じ。加えてCPUとXCR0がAVXに対応していること
+#[target_feature(enable = "avx")]
+unsafe fn fill_avx(dst: *mut u32, value: u32, count: usize) {
+    let v = _mm256_set1_epi32(value as i32);
+    let mut i = 0;
+    // SAFETY: i + 32 <= count の範囲だけをストアする
+    unsafe {
+        while i + 32 <= count {
+            let d = dst.add(i) as *mut __m256i;
+            _mm256_storeu_si256(d, v);
+            _mm256_storeu_si256(d.add(1), v);
+            _mm256_storeu_si256(d.add(2), v);
+            _mm256_storeu_si256(d.add(3), v);
+            i += 32;
+        }
+        fill_scalar(dst.add(i), value, count - i);
+    }
+}
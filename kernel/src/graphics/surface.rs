@@ -0,0 +1,166 @@
+//! オフスクリーンサーフェス
+//!
+//! グラフや可視化のように描画コストの高い内容を一度だけ描画しておき、
+//! ウィンドウから `DrawCommand::BlitSurface` で繰り返し転送するための画素バッファです。
+//! サーフェスはCompositorのレジストリに登録され、作成したタスクが終了すると
+//! Reaperによって解放されます。
+//!
+//! ハンドルはIDのみを保持し、画素バッファはレジストリが所有します。そのため、
+//! ハンドルを残したままタスクが終了してもメモリはリークしません。
+
+use alloc::sync::Arc;
+
+use super::backing_store::BackingStore;
+use super::buffer::{DrawCommand, DrawList};
+use super::compositor;
+use super::page_buffer::BufferAllocError;
+use super::region::Region;
+use super::theme;
+use crate::sync::BlockingMutex;
+
+/// サーフェス操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceError {
+    /// Compositorが未初期化
+    NotInitialized,
+    /// 指定したIDのサーフェスが存在しない（破棄済みを含む）
+    NotFound,
+    /// 幅または高さが0
+    InvalidSize,
+    /// 画素バッファを確保できない
+    OutOfMemory,
+    /// サーフェスへの描画にBlitSurfaceが含まれている（サーフェス間の転送は未対応）
+    NestedBlit,
+}
+
+impl core::fmt::Display for SurfaceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SurfaceError::NotInitialized => write!(f, "Compositor not initialized"),
+            SurfaceError::NotFound => write!(f, "No such surface"),
+            SurfaceError::InvalidSize => write!(f, "Invalid surface size"),
+            SurfaceError::OutOfMemory => write!(f, "Not enough memory for surface"),
+            SurfaceError::NestedBlit => write!(f, "Cannot blit a surface into a surface"),
+        }
+    }
+}
+
+impl From<BufferAllocError> for SurfaceError {
+    fn from(e: BufferAllocError) -> Self {
+        match e {
+            BufferAllocError::InvalidSize => SurfaceError::InvalidSize,
+            BufferAllocError::OutOfFrames => SurfaceError::OutOfMemory,
+        }
+    }
+}
+
+/// サーフェスID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SurfaceId(u64);
+
+impl SurfaceId {
+    pub(super) const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+/// レジストリが所有するサーフェスの画素バッファ
+pub(super) type SharedSurface = Arc<BlockingMutex<BackingStore>>;
+
+/// サーフェスの情報（一覧表示用）
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceInfo {
+    pub id: SurfaceId,
+    /// 作成したタスク
+    pub owner: crate::sched::TaskId,
+    /// 幅（ピクセル）
+    pub width: u32,
+    /// 高さ（ピクセル）
+    pub height: u32,
+}
+
+/// サーフェスのハンドル
+///
+/// ドロップしてもサーフェスは破棄されません。破棄するには `destroy()` を呼び出すか、
+/// 作成したタスクを終了させます。
+pub struct Surface {
+    id: SurfaceId,
+    width: u32,
+    height: u32,
+}
+
+#[allow(dead_code)]
+impl Surface {
+    /// 現在のテーマの背景色で塗りつぶしたサーフェスを作成し、現在のタスクを所有者として登録
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotInitialized` - Compositorが未初期化の場合
+    /// * `SurfaceError::InvalidSize` - 幅または高さが0の場合
+    /// * `SurfaceError::OutOfMemory` - 画素バッファを確保できない場合
+    pub fn create(width: u32, height: u32) -> Result<Self, SurfaceError> {
+        if width == 0 || height == 0 {
+            return Err(SurfaceError::InvalidSize);
+        }
+        // 画素バッファはCompositorのロックの外で確保する
+        let store = BackingStore::new(width, height, theme::background())?;
+        let id = compositor::register_surface(Arc::new(BlockingMutex::new(store)))?;
+        Ok(Self { id, width, height })
+    }
+
+    /// サーフェスID（`DrawCommand::BlitSurface` に指定する）
+    pub fn id(&self) -> SurfaceId {
+        self.id
+    }
+
+    /// 幅を取得
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// 高さを取得
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// サーフェス全体を表す領域
+    pub fn bounds(&self) -> Region {
+        Region::new(0, 0, self.width, self.height)
+    }
+
+    /// 描画コマンドをサーフェスへ描画
+    ///
+    /// # Returns
+    /// 変更された領域。何も描画されなければNone
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotFound` - サーフェスが破棄済みの場合
+    /// * `SurfaceError::NestedBlit` - コマンドにBlitSurfaceが含まれている場合
+    pub fn draw(&self, list: &DrawList) -> Result<Option<Region>, SurfaceError> {
+        // サーフェス同士の転送はロック順序によってデッドロックし得るため受け付けない
+        if list
+            .commands()
+            .iter()
+            .any(|cmd| matches!(cmd, DrawCommand::BlitSurface { .. }))
+        {
+            return Err(SurfaceError::NestedBlit);
+        }
+        let store = compositor::surface_store(self.id).ok_or(SurfaceError::NotFound)?;
+        let changed = store.lock().render(list);
+        Ok(changed)
+    }
+
+    /// サーフェスを破棄
+    ///
+    /// 以降、このIDを指定したBlitSurfaceは何も描画しません。
+    ///
+    /// # Errors
+    /// * `SurfaceError::NotFound` - サーフェスが破棄済みの場合
+    pub fn destroy(self) -> Result<(), SurfaceError> {
+        compositor::destroy_surface(self.id)
+    }
+}
@@ -0,0 +1,144 @@
+//! UIテーマ
+//!
+//! 画面やコンソールで使う色を役割（背景・前景・強調・エラー）ごとに1か所で管理します。
+//! 各描画箇所は色を直接持たず、描画のたびにここから取得するため、
+//! 設定（`config set theme.*`）を変えると実行中のUI全体に反映されます。
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::color::Color;
+
+/// テーマ内の色の役割
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeRole {
+    /// 背景色
+    Background,
+    /// 通常の文字色
+    Foreground,
+    /// 見出し・プロンプトなどの強調色
+    Accent,
+    /// エラー・パニック表示の色
+    Error,
+}
+
+impl ThemeRole {
+    /// すべての役割
+    pub const ALL: [ThemeRole; 4] = [
+        ThemeRole::Background,
+        ThemeRole::Foreground,
+        ThemeRole::Accent,
+        ThemeRole::Error,
+    ];
+}
+
+/// テーマ（役割ごとの色の組）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub accent: Color,
+    pub error: Color,
+}
+
+impl Theme {
+    /// 役割に対応する色を取得
+    pub fn get(&self, role: ThemeRole) -> Color {
+        match role {
+            ThemeRole::Background => self.background,
+            ThemeRole::Foreground => self.foreground,
+            ThemeRole::Accent => self.accent,
+            ThemeRole::Error => self.error,
+        }
+    }
+}
+
+/// 既定のテーマ（黒背景に白文字）
+pub const DARK: Theme = Theme {
+    background: Color::BLACK,
+    foreground: Color::WHITE,
+    accent: Color::YELLOW,
+    error: Color::RED,
+};
+
+/// 明るい背景のテーマ
+pub const LIGHT: Theme = Theme {
+    background: Color::rgb(0xF0, 0xF0, 0xF0),
+    foreground: Color::rgb(0x20, 0x20, 0x20),
+    accent: Color::rgb(0x00, 0x5F, 0xAF),
+    error: Color::rgb(0xC0, 0x00, 0x00),
+};
+
+/// 名前付きテーマの一覧
+pub const PRESETS: &[(&str, Theme)] = &[("dark", DARK), ("light", LIGHT)];
+
+/// 現在のテーマ（ThemeRole::ALLの順、0xAARRGGBB）
+///
+/// パニックハンドラや割り込みからも参照するためロックを使わない
+static CURRENT: [AtomicU32; 4] = [
+    AtomicU32::new(DARK.background.to_argb_u32()),
+    AtomicU32::new(DARK.foreground.to_argb_u32()),
+    AtomicU32::new(DARK.accent.to_argb_u32()),
+    AtomicU32::new(DARK.error.to_argb_u32()),
+];
+
+/// 現在のテーマで役割に対応する色を取得
+pub fn color(role: ThemeRole) -> Color {
+    Color::from_argb_u32(CURRENT[role as usize].load(Ordering::Relaxed))
+}
+
+/// 役割の色を変更
+pub fn set_color(role: ThemeRole, color: Color) {
+    CURRENT[role as usize].store(color.to_argb_u32(), Ordering::Relaxed);
+}
+
+/// テーマ全体を置き換え
+pub fn apply(theme: &Theme) {
+    for role in ThemeRole::ALL {
+        set_color(role, theme.get(role));
+    }
+}
+
+/// 名前付きテーマを適用
+///
+/// # Returns
+/// 該当するテーマがなければfalse
+pub fn apply_preset(name: &str) -> bool {
+    match PRESETS.iter().find(|(preset, _)| *preset == name) {
+        Some((_, theme)) => {
+            apply(theme);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 現在のテーマを取得
+#[allow(dead_code)]
+pub fn current() -> Theme {
+    Theme {
+        background: background(),
+        foreground: foreground(),
+        accent: accent(),
+        error: error(),
+    }
+}
+
+/// 背景色
+pub fn background() -> Color {
+    color(ThemeRole::Background)
+}
+
+/// 文字色
+pub fn foreground() -> Color {
+    color(ThemeRole::Foreground)
+}
+
+/// 強調色
+pub fn accent() -> Color {
+    color(ThemeRole::Accent)
+}
+
+/// エラー色
+pub fn error() -> Color {
+    color(ThemeRole::Error)
+}
@@ -0,0 +1,281 @@
+//! ウィンドウ
+//!
+//! Compositorが重ね合わせて表示する描画領域です。各ウィンドウはタイトルバー・背景色・
+//! Zオーダーを持ち、実行中に移動・リサイズ・最前面への移動ができます。
+//! 中身の描画は従来どおり `TaskWriter` で行い、座標はコンテンツ領域のローカル座標です。
+//!
+//! 各ウィンドウの内容はバッキングストアに保持されるため、他のウィンドウに隠れた後や
+//! 移動後もWriterが描き直す必要はありません。
+//! `compositor::register_writer` で作成される領域も、タイトルバーを持たない
+//! ウィンドウとして同じZオーダーに並びます。
+//!
+//! 各ウィンドウは作成時のワークスペース（仮想デスクトップ）に属し、そのワークスペースが
+//! アクティブな間だけ表示されます。オーバーレイはすべてのワークスペースに表示されます。
+
+use alloc::string::{String, ToString};
+
+use super::buffer::SharedBuffer;
+use super::color::Color;
+use super::compositor;
+use super::region::Region;
+use super::writer::TaskWriter;
+
+/// タイトルバーの高さ（ピクセル）
+pub const TITLE_BAR_HEIGHT: u32 = 12;
+
+/// タイトル文字列のタイトルバー内での余白（ピクセル）
+const TITLE_PADDING: u32 = 2;
+
+/// ウィンドウ操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowError {
+    /// Compositorが未初期化
+    NotInitialized,
+    /// 指定したIDのウィンドウが存在しない
+    NotFound,
+    /// 幅または高さが0、または画面外に配置されている
+    InvalidGeometry,
+    /// バッキングストアを確保できない
+    OutOfMemory,
+    /// 存在しないワークスペース
+    InvalidWorkspace,
+}
+
+impl core::fmt::Display for WindowError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            WindowError::NotInitialized => write!(f, "Compositor not initialized"),
+            WindowError::NotFound => write!(f, "No such window"),
+            WindowError::InvalidGeometry => write!(f, "Invalid window geometry"),
+            WindowError::OutOfMemory => write!(f, "Not enough memory for window contents"),
+            WindowError::InvalidWorkspace => write!(
+                f,
+                "Invalid workspace (expected 0-{})",
+                compositor::WORKSPACE_COUNT - 1
+            ),
+        }
+    }
+}
+
+/// ウィンドウID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WindowId(u64);
+
+impl WindowId {
+    pub(super) const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// u64から変換（シェルなどで数値指定されたIDを扱うため）
+    pub const fn from_u64(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+/// Compositorが保持するウィンドウの状態
+#[derive(Clone)]
+pub(super) struct WindowState {
+    pub id: WindowId,
+    /// タイトル（Noneならタイトルバーなし）
+    pub title: Option<String>,
+    /// コンテンツ領域（画面座標）
+    pub content: Region,
+    /// 背景色（リサイズで広がった部分や消去に使用）
+    pub background: Color,
+    /// 描画コマンドのバッファ
+    pub buffer: SharedBuffer,
+    /// 所属するワークスペース（Noneならすべてのワークスペースに表示）
+    pub workspace: Option<usize>,
+    /// 不透明度（255で不透明、0で非表示）
+    pub opacity: u8,
+}
+
+impl WindowState {
+    /// 指定したワークスペースがアクティブな時に表示されるか
+    pub fn is_visible_on(&self, workspace: usize) -> bool {
+        self.workspace.is_none_or(|w| w == workspace)
+    }
+
+    /// タイトルバーを含むウィンドウ全体の領域
+    pub fn frame(&self) -> Region {
+        match self.title_bar() {
+            Some(bar) => bar.union(&self.content),
+            None => self.content,
+        }
+    }
+
+    /// タイトルバーの領域
+    pub fn title_bar(&self) -> Option<Region> {
+        self.title.as_ref().map(|_| {
+            Region::new(
+                self.content.x,
+                self.content.y - TITLE_BAR_HEIGHT,
+                self.content.width,
+                TITLE_BAR_HEIGHT,
+            )
+        })
+    }
+
+    /// タイトル文字列の描画位置
+    pub fn title_origin(&self) -> (u32, u32) {
+        (
+            self.content.x + TITLE_PADDING,
+            self.content.y - TITLE_BAR_HEIGHT + TITLE_PADDING,
+        )
+    }
+
+    /// フレームの左上を指定してコンテンツ領域の位置を計算
+    pub fn content_at(&self, x: u32, y: u32) -> Region {
+        let offset = if self.title.is_some() {
+            TITLE_BAR_HEIGHT
+        } else {
+            0
+        };
+        Region::new(x, y + offset, self.content.width, self.content.height)
+    }
+}
+
+/// ウィンドウの情報（一覧表示用）
+#[derive(Debug, Clone)]
+pub struct WindowInfo {
+    pub id: WindowId,
+    /// タイトル（タイトルバーなしなら空文字列）
+    pub title: String,
+    /// タイトルバーを含む領域
+    pub frame: Region,
+    /// 背景色
+    #[allow(dead_code)]
+    pub background: Color,
+    /// Zオーダー（0が最背面）
+    pub z_order: usize,
+    /// 所属するワークスペース（Noneならすべてのワークスペースに表示）
+    pub workspace: Option<usize>,
+    /// 不透明度（255で不透明）
+    pub opacity: u8,
+}
+
+impl WindowInfo {
+    pub(super) fn from_state(state: &WindowState, z_order: usize) -> Self {
+        Self {
+            id: state.id,
+            title: state.title.clone().unwrap_or_default(),
+            frame: state.frame(),
+            background: state.background,
+            z_order,
+            workspace: state.workspace,
+            opacity: state.opacity,
+        }
+    }
+}
+
+/// ウィンドウのハンドル
+///
+/// ドロップしてもウィンドウは閉じません。閉じるには `close()` を呼び出します。
+pub struct Window {
+    id: WindowId,
+    buffer: SharedBuffer,
+}
+
+impl Window {
+    /// タイトルバー付きのウィンドウを作成して最前面に配置
+    ///
+    /// # Arguments
+    /// * `title` - タイトルバーに表示する文字列
+    /// * `x`, `y` - タイトルバーを含むウィンドウの左上の座標
+    /// * `width`, `height` - コンテンツ領域のサイズ
+    /// * `background` - コンテンツ領域の背景色
+    ///
+    /// # Errors
+    /// * `WindowError::NotInitialized` - Compositorが未初期化の場合
+    /// * `WindowError::InvalidGeometry` - サイズが0の場合
+    /// * `WindowError::OutOfMemory` - バッキングストアを確保できない場合
+    pub fn create(
+        title: &str,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        background: Color,
+    ) -> Result<Self, WindowError> {
+        if width == 0 || height == 0 {
+            return Err(WindowError::InvalidGeometry);
+        }
+        let content = Region::new(x, y + TITLE_BAR_HEIGHT, width, height);
+        let (id, buffer) = compositor::add_window(Some(title.to_string()), content, background)?;
+        Ok(Self { id, buffer })
+    }
+
+    /// ウィンドウID
+    #[allow(dead_code)]
+    pub fn id(&self) -> WindowId {
+        self.id
+    }
+
+    /// コンテンツ領域に描画するWriterを作成
+    ///
+    /// # Arguments
+    /// * `color` - 初期文字色
+    pub fn writer(&self, color: Color) -> TaskWriter {
+        TaskWriter::new(self.buffer.clone(), color)
+    }
+
+    /// ウィンドウを移動
+    ///
+    /// # Arguments
+    /// * `x`, `y` - タイトルバーを含むウィンドウの新しい左上の座標
+    #[allow(dead_code)]
+    pub fn move_to(&self, x: u32, y: u32) -> Result<(), WindowError> {
+        compositor::move_window(self.id, x, y)
+    }
+
+    /// コンテンツ領域のサイズを変更
+    #[allow(dead_code)]
+    pub fn resize(&self, width: u32, height: u32) -> Result<(), WindowError> {
+        compositor::resize_window(self.id, width, height)
+    }
+
+    /// 最前面に移動
+    #[allow(dead_code)]
+    pub fn raise(&self) -> Result<(), WindowError> {
+        compositor::raise_window(self.id)
+    }
+
+    /// 別のワークスペースに移動
+    ///
+    /// # Arguments
+    /// * `workspace` - 移動先（Noneならすべてのワークスペースに表示）
+    #[allow(dead_code)]
+    pub fn set_workspace(&self, workspace: Option<usize>) -> Result<(), WindowError> {
+        compositor::move_window_to_workspace(self.id, workspace)
+    }
+
+    /// タイトルを変更
+    #[allow(dead_code)]
+    pub fn set_title(&self, title: &str) -> Result<(), WindowError> {
+        let title = title.to_string();
+        compositor::update_window(self.id, move |state| state.title = Some(title))
+    }
+
+    /// 背景色を変更し、コンテンツを消去
+    #[allow(dead_code)]
+    pub fn set_background(&self, background: Color) -> Result<(), WindowError> {
+        compositor::set_window_background(self.id, background)
+    }
+
+    /// 不透明度を変更（255で不透明）
+    #[allow(dead_code)]
+    pub fn set_opacity(&self, opacity: u8) -> Result<(), WindowError> {
+        compositor::set_window_opacity(self.id, opacity)
+    }
+
+    /// ウィンドウを閉じる
+    #[allow(dead_code)]
+    pub fn close(self) -> Result<(), WindowError> {
+        compositor::close_window(self.id)
+    }
+}
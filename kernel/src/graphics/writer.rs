@@ -1,9 +1,12 @@
 //! Per-task Writer
 
-use super::buffer::{DrawCommand, SharedBuffer};
+use super::backing_store::BackingStore;
+use super::buffer::{BlitMode, DrawCommand, DrawList, SharedBuffer};
+use super::color::Color;
+use super::font::Typeface;
 use super::region::Region;
+use super::surface::SurfaceId;
 use alloc::string::String;
-use alloc::vec::Vec;
 
 /// タスクごとのWriter
 ///
@@ -11,23 +14,34 @@ use alloc::vec::Vec;
 /// 描画コマンドをローカルバッファに蓄積し、
 /// flush()で共有バッファに一括転送します。
 ///
-/// これにより、1フレームの描画で1回のロック取得のみで済み、
-/// ロック競合を大幅に削減します。
+/// flush()ではローカルのバックバッファにコマンドを描画してから、
+/// 短時間だけロックを取得して共有バッファのフロントバッファと入れ替えます。
+/// これにより、描画処理中にCompositorの転送を妨げません。
 ///
 /// 最適化: 連続する文字をDrawStringにバッチ化することで、
 /// コマンド数を大幅に削減し、パフォーマンスを向上させます。
+/// DrawStringの文字列はDrawListの文字列表に置き、フレームをまたいで容量を使い回すため、
+/// 定常状態の描画ではヒープの割り当てが発生しません。
 pub struct TaskWriter {
     /// 共有バッファへの参照
     buffer: SharedBuffer,
-    /// ローカルコマンドバッファ（ロックなしで追加可能）
-    local_commands: Vec<DrawCommand>,
+    /// ローカルコマンドバッファと文字列表（ロックなしで追加可能）
+    local_commands: DrawList,
     /// 描画領域（領域チェック用にキャッシュ）
     region: Region,
+    /// バックバッファ（確保できなければNoneで、フロントへ直接描画する）
+    back: Option<BackingStore>,
+    /// バックバッファの元になったフロントバッファの世代
+    generation: u64,
     /// カーソル位置（ローカル座標）
     cursor_x: u32,
     cursor_y: u32,
     /// 現在の文字色
-    color: u32,
+    color: Color,
+    /// 最後にクリアした背景色（縦方向に溢れたときの再クリアに使用）
+    background: Color,
+    /// 文字の書体（セルの大きさと行の高さを決める）
+    typeface: Typeface,
     /// 現在蓄積中の文字列（バッチ化用）
     pending_text: String,
     /// 蓄積中の文字列の開始X座標
@@ -42,16 +56,24 @@ impl TaskWriter {
     /// # Arguments
     /// * `buffer` - 共有バッファへの参照
     /// * `color` - 初期文字色
-    pub fn new(buffer: SharedBuffer, color: u32) -> Self {
-        // 共有バッファからregionを取得してキャッシュ
-        let region = buffer.lock().region();
+    pub fn new(buffer: SharedBuffer, color: Color) -> Self {
+        // 共有バッファからregionを取得してキャッシュし、フロントの内容でバックバッファを作成
+        let (region, back, generation) = {
+            let buf = buffer.lock();
+            (buf.region(), buf.front().duplicate().ok(), buf.generation())
+        };
         Self {
             buffer,
-            local_commands: Vec::with_capacity(32), // バッチ化により必要なコマンド数が減少
+            // バッチ化により必要なコマンド数が減少
+            local_commands: DrawList::with_capacity(32, 1024),
             region,
+            back,
+            generation,
             cursor_x: 0,
             cursor_y: 0,
             color,
+            background: super::theme::background(),
+            typeface: Typeface::Builtin,
             pending_text: String::with_capacity(128), // 文字列バッファを事前確保
             pending_x: 0,
             pending_y: 0,
@@ -65,6 +87,8 @@ impl TaskWriter {
     /// * `y` - Y座標（ローカル座標）
     #[allow(dead_code)]
     pub fn set_position(&mut self, x: u32, y: u32) {
+        // 蓄積中のテキストは移動前の位置で確定させる
+        self.commit_pending_text();
         self.cursor_x = x;
         self.cursor_y = y;
     }
@@ -72,12 +96,38 @@ impl TaskWriter {
     /// 文字色を設定
     ///
     /// # Arguments
-    /// * `color` - 新しい文字色（0xRRGGBB形式）
-    #[allow(dead_code)]
-    pub fn set_color(&mut self, color: u32) {
+    /// * `color` - 新しい文字色
+    pub fn set_color(&mut self, color: Color) {
+        // 蓄積中のテキストは変更前の色で確定させる
+        if color != self.color {
+            self.commit_pending_text();
+        }
         self.color = color;
     }
 
+    /// 書体を設定
+    ///
+    /// 以降の文字はこの書体のセルの大きさで並び、改行は書体の行の高さだけ進みます。
+    ///
+    /// # Arguments
+    /// * `typeface` - 新しい書体
+    #[allow(dead_code)]
+    pub fn set_typeface(&mut self, typeface: Typeface) {
+        // 蓄積中のテキストは変更前の書体で確定させる
+        self.commit_pending_text();
+        self.typeface = typeface;
+    }
+
+    /// 現在の書体の文字セルの幅（ピクセル）
+    pub fn cell_width(&self) -> u32 {
+        self.typeface.cell_width() as u32
+    }
+
+    /// 現在の書体の文字セルの高さ（行の高さ、ピクセル）
+    pub fn cell_height(&self) -> u32 {
+        self.typeface.cell_height() as u32
+    }
+
     /// 領域をクリア
     ///
     /// ローカルバッファにClearコマンドを追加します。
@@ -85,18 +135,135 @@ impl TaskWriter {
     ///
     /// # Arguments
     /// * `bg_color` - 背景色
-    pub fn clear(&mut self, bg_color: u32) {
+    pub fn clear(&mut self, bg_color: Color) {
         // 蓄積中のテキストをコミットしてからクリア
         self.commit_pending_text();
         self.local_commands
             .push(DrawCommand::Clear { color: bg_color });
+        self.background = bg_color;
         self.cursor_x = 0;
         self.cursor_y = 0;
     }
 
-    /// ローカルバッファのコマンドを共有バッファに一括転送
+    /// 現在のテーマの背景色でクリアし、文字色をテーマの前景色に戻す
+    ///
+    /// 毎フレーム描画し直すタスクはこれを使うことで、テーマの変更が次のフレームから反映されます。
+    pub fn clear_themed(&mut self) {
+        self.clear(super::theme::background());
+        self.set_color(super::theme::foreground());
+    }
+
+    /// 矩形を塗りつぶす
+    ///
+    /// # Arguments
+    /// * `x`, `y` - 左上（ローカル座標）
+    /// * `width`, `height` - サイズ
+    /// * `color` - 塗りつぶす色
+    #[allow(dead_code)]
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        self.commit_pending_text();
+        self.local_commands.push(DrawCommand::FillRect {
+            x,
+            y,
+            width,
+            height,
+            color,
+        });
+    }
+
+    /// ビットマップ（ARGB、行優先）を描画
+    ///
+    /// # Arguments
+    /// * `x`, `y` - 左上（ローカル座標）
+    /// * `width`, `height` - ビットマップのサイズ
+    /// * `pixels` - ピクセル（`width` × `height` 個）
+    /// * `mode` - 透明の扱い（不透明・アルファ合成・カラーキー）
+    #[allow(dead_code)]
+    pub fn draw_bitmap(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+        mode: BlitMode,
+    ) {
+        self.commit_pending_text();
+        self.local_commands
+            .push_bitmap(x, y, width, height, pixels, mode);
+    }
+
+    /// 線分を描画
     ///
-    /// この呼び出しでのみ共有バッファのロックを取得します。
+    /// # Arguments
+    /// * `x0`, `y0` - 始点（ローカル座標、領域外でもよい）
+    /// * `x1`, `y1` - 終点（同上）
+    /// * `color` - 線の色
+    #[allow(dead_code)]
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        self.commit_pending_text();
+        self.local_commands.push(DrawCommand::DrawLine {
+            x0,
+            y0,
+            x1,
+            y1,
+            color,
+        });
+    }
+
+    /// 円を描画
+    ///
+    /// # Arguments
+    /// * `cx`, `cy` - 中心（ローカル座標、領域外でもよい）
+    /// * `radius` - 半径
+    /// * `color` - 色
+    /// * `filled` - trueなら塗りつぶし、falseなら円周のみ
+    #[allow(dead_code)]
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, color: Color, filled: bool) {
+        self.commit_pending_text();
+        self.local_commands.push(DrawCommand::DrawCircle {
+            cx,
+            cy,
+            radius,
+            color,
+            filled,
+        });
+    }
+
+    /// 領域内の矩形を別の位置へコピー（スクロール用）
+    ///
+    /// # Arguments
+    /// * `src_rect` - コピー元の領域（ローカル座標）
+    /// * `dst_x`, `dst_y` - コピー先の左上（ローカル座標）
+    #[allow(dead_code)]
+    pub fn copy_rect(&mut self, src_rect: Region, dst_x: u32, dst_y: u32) {
+        self.commit_pending_text();
+        self.local_commands.push(DrawCommand::CopyRect {
+            src_rect,
+            dst: (dst_x, dst_y),
+        });
+    }
+
+    /// オフスクリーンサーフェスの一部を転送
+    ///
+    /// # Arguments
+    /// * `id` - 転送元のサーフェス
+    /// * `src_rect` - サーフェス内の転送元領域
+    /// * `dst_x`, `dst_y` - 転送先の左上（ローカル座標）
+    #[allow(dead_code)]
+    pub fn blit_surface(&mut self, id: SurfaceId, src_rect: Region, dst_x: u32, dst_y: u32) {
+        self.commit_pending_text();
+        self.local_commands.push(DrawCommand::BlitSurface {
+            id,
+            src_rect,
+            dst: (dst_x, dst_y),
+        });
+    }
+
+    /// ローカルバッファのコマンドを描画して表示に反映
+    ///
+    /// コマンドはロックを保持せずにバックバッファへ描画し、
+    /// 共有バッファのロックはフロントバッファとの入れ替え時のみ取得します。
     /// 1フレームの描画の最後に呼び出してください。
     pub fn flush(&mut self) {
         // 蓄積中のテキストをコミット
@@ -106,10 +273,40 @@ impl TaskWriter {
             return;
         }
 
-        // 一括転送: drain()を使用してVecの容量を維持（アロケーションフリー）
-        self.buffer
-            .lock()
-            .extend_commands(self.local_commands.drain(..));
+        // リサイズなどでフロントバッファが変更されていればバックバッファを作り直す
+        {
+            let buf = self.buffer.lock();
+            // ウィンドウがリサイズされていれば次の描画から新しいサイズで折り返す
+            self.region = buf.region();
+            if buf.generation() != self.generation {
+                self.back = buf.front().duplicate().ok();
+                self.generation = buf.generation();
+            }
+        }
+
+        // ロックを保持せずにバックバッファへ描画
+        let changed = self
+            .back
+            .as_mut()
+            .and_then(|back| back.render(&self.local_commands));
+
+        {
+            let mut buf = self.buffer.lock();
+            match self.back.as_mut() {
+                Some(back) if buf.generation() == self.generation => {
+                    if let Some(changed) = changed {
+                        buf.present(back, changed);
+                    }
+                }
+                // バックバッファがない、または描画中にリサイズされた: フロントへ直接描画
+                _ => buf.render_direct(&self.local_commands),
+            }
+        }
+        // コマンドと文字列表の容量は維持（アロケーションフリー）
+        self.local_commands.clear();
+
+        // Damage駆動モードのCompositorに更新を通知
+        super::compositor::notify_damage();
     }
 
     /// 蓄積中のテキストをDrawStringコマンドにコミット
@@ -121,45 +318,46 @@ impl TaskWriter {
             return;
         }
 
-        // 蓄積中のテキストをDrawStringとして追加
-        // clone()でテキストをコピーし、clear()で元バッファを再利用
-        // これによりpending_textの容量は維持される（リアロケーション防止）
-        // 注: DrawCommandがStringを所有するため、新規Stringの作成は避けられない
-        let text = self.pending_text.clone();
+        // 蓄積中のテキストを文字列表にコピーしてDrawStringとして追加
+        // pending_textと文字列表はどちらも容量を維持する（リアロケーション防止）
+        self.local_commands.push_string(
+            self.pending_x,
+            self.pending_y,
+            &self.pending_text,
+            self.color,
+            self.typeface,
+        );
         self.pending_text.clear();
-        self.local_commands.push(DrawCommand::DrawString {
-            x: self.pending_x,
-            y: self.pending_y,
-            text,
-            color: self.color,
-        });
     }
 }
 
 impl core::fmt::Write for TaskWriter {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let cell_width = self.cell_width();
+        let cell_height = self.cell_height();
         // 最適化: 連続する文字をDrawStringにバッチ化
-        for ch in s.bytes() {
-            if ch == b'\n' {
+        for ch in s.chars() {
+            if ch == '\n' {
                 // 改行時: 蓄積中のテキストをコミット
                 self.commit_pending_text();
                 self.cursor_x = 0;
-                self.cursor_y += 10;
+                self.cursor_y += cell_height;
             } else {
                 // 領域内に収まるかチェック
-                if self.cursor_x + 8 > self.region.width {
+                if self.cursor_x + cell_width > self.region.width {
                     // 行の折り返し: 蓄積中のテキストをコミット
                     self.commit_pending_text();
                     self.cursor_x = 0;
-                    self.cursor_y += 10;
+                    self.cursor_y += cell_height;
                 }
 
                 // 縦方向のオーバーフロー処理
-                if self.cursor_y + 8 > self.region.height {
+                if self.cursor_y + cell_height > self.region.height {
                     // 蓄積中のテキストをコミットしてからクリア
                     self.commit_pending_text();
-                    self.local_commands
-                        .push(DrawCommand::Clear { color: 0x00000000 });
+                    self.local_commands.push(DrawCommand::Clear {
+                        color: self.background,
+                    });
                     self.cursor_y = 0;
                 }
 
@@ -169,9 +367,9 @@ impl core::fmt::Write for TaskWriter {
                     self.pending_y = self.cursor_y;
                 }
 
-                // 文字を蓄積（1バイトのASCII文字として）
-                self.pending_text.push(ch as char);
-                self.cursor_x += 8;
+                // 文字を蓄積（描画時にフォールバックチェーンでグリフを選ぶ）
+                self.pending_text.push(ch);
+                self.cursor_x += cell_width;
             }
         }
         Ok(())
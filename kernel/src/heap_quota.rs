@@ -0,0 +1,272 @@
+//! タスクごとのヒープ使用量の計上とクォータ
+//!
+//! グローバルアロケータが割り当てのたびに現在のタスクへ使用量を計上し、
+//! タスクごとの現在値・ピーク値を記録します。クォータを設定したタスクは、
+//! 上限を超える割り当てだけが失敗します（他のタスクの割り当てには影響しません）。
+//!
+//! # 計上の仕組み
+//! - 各タスクには計上スロット（1〜`MAX_TRACKED_TASKS`）を割り当て、スラブブロックごとに
+//!   スロット番号をタグとして記録します。解放時はタグのスロットから差し引くため、
+//!   別のタスクが解放しても割り当てたタスクの使用量が減ります。
+//! - スロット0はスケジューラ起動前の割り当てと、スロットを使い切った後のタスクの割り当てを
+//!   まとめて計上します（クォータは適用されません）。
+//! - 4KB超の大きな割り当ては解放できないため、割り当てたタスクの使用量に残り続けます。
+//! - 終了したタスクのスロットは、そのタスクが割り当てたブロックがすべて解放されるまで
+//!   再利用しません。
+//!
+//! アロケータから呼ばれるため、このモジュールはヒープを使用しません。
+
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+use crate::sched::{self, TaskId};
+
+/// 個別に計上できるタスク数（スロット1〜MAX_TRACKED_TASKS）
+pub const MAX_TRACKED_TASKS: usize = 63;
+
+/// タスクに割り当てられていない割り当ての計上先スロット
+pub const UNTRACKED_SLOT: u8 = 0;
+
+/// スロット数（スロット0を含む）
+const NUM_SLOTS: usize = MAX_TRACKED_TASKS + 1;
+
+/// ヒープクォータ操作のエラー
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapQuotaError {
+    /// 計上スロットをすべて使用中
+    TooManyTasks,
+}
+
+impl core::fmt::Display for HeapQuotaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            HeapQuotaError::TooManyTasks => write!(f, "No free accounting slot"),
+        }
+    }
+}
+
+/// タスク1つ分のヒープ使用量
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct TaskHeapUsage {
+    /// タスクID（スロット0の場合はNone）
+    pub task_id: Option<TaskId>,
+    /// 現在の使用量（バイト）
+    pub current: usize,
+    /// ピーク使用量（バイト）
+    pub peak: usize,
+    /// クォータ（バイト、Noneなら無制限）
+    pub quota: Option<usize>,
+    /// クォータ超過で失敗させた割り当ての回数
+    pub denied: u64,
+    /// 計上した割り当ての累計回数（割り当て頻度の計測用）
+    pub allocs: u64,
+    /// タスクが終了済みか
+    pub exited: bool,
+}
+
+/// 計上スロット
+#[derive(Clone, Copy)]
+struct Account {
+    /// 使用中のスロットか
+    active: bool,
+    task_id: u64,
+    current: usize,
+    peak: usize,
+    quota: Option<usize>,
+    denied: u64,
+    allocs: u64,
+    exited: bool,
+}
+
+impl Account {
+    const EMPTY: Account = Account {
+        active: false,
+        task_id: 0,
+        current: 0,
+        peak: 0,
+        quota: None,
+        denied: 0,
+        allocs: 0,
+        exited: false,
+    };
+
+    fn usage(&self, slot: usize) -> TaskHeapUsage {
+        TaskHeapUsage {
+            task_id: (slot != UNTRACKED_SLOT as usize).then(|| TaskId::from_u64(self.task_id)),
+            current: self.current,
+            peak: self.peak,
+            quota: self.quota,
+            denied: self.denied,
+            allocs: self.allocs,
+            exited: self.exited,
+        }
+    }
+}
+
+/// 計上スロットの表
+struct AccountTable {
+    accounts: [Account; NUM_SLOTS],
+}
+
+impl AccountTable {
+    const fn new() -> Self {
+        let mut accounts = [Account::EMPTY; NUM_SLOTS];
+        accounts[UNTRACKED_SLOT as usize].active = true;
+        Self { accounts }
+    }
+
+    /// タスクのスロットを検索
+    fn find(&self, task_id: u64) -> Option<usize> {
+        (1..NUM_SLOTS).find(|&slot| {
+            let account = &self.accounts[slot];
+            account.active && !account.exited && account.task_id == task_id
+        })
+    }
+
+    /// タスクのスロットを検索し、なければ空きスロットを割り当てる
+    fn find_or_insert(&mut self, task_id: u64) -> Option<usize> {
+        if let Some(slot) = self.find(task_id) {
+            return Some(slot);
+        }
+        let slot = (1..NUM_SLOTS).find(|&slot| !self.accounts[slot].active)?;
+        self.accounts[slot] = Account {
+            active: true,
+            task_id,
+            ..Account::EMPTY
+        };
+        Some(slot)
+    }
+
+    /// 終了済みで使用量が0になったスロットを解放
+    fn release_if_idle(&mut self, slot: usize) {
+        let account = &self.accounts[slot];
+        if slot != UNTRACKED_SLOT as usize && account.exited && account.current == 0 {
+            self.accounts[slot] = Account::EMPTY;
+        }
+    }
+}
+
+/// グローバルな計上表
+static ACCOUNTS: Mutex<AccountTable> = Mutex::new(AccountTable::new());
+
+/// 現在のタスクに割り当てを計上（アロケータから呼ばれる）
+///
+/// # Arguments
+/// * `size` - 割り当てサイズ（バイト）
+///
+/// # Returns
+/// 計上先のスロット番号。クォータを超える場合はNone（割り当てを失敗させる）
+pub fn charge(size: usize) -> Option<u8> {
+    let task_id = sched::current_task_id_lockless();
+
+    without_interrupts(|| {
+        let mut table = ACCOUNTS.lock();
+        let slot = task_id
+            .and_then(|id| table.find_or_insert(id.as_u64()))
+            .unwrap_or(UNTRACKED_SLOT as usize);
+
+        let account = &mut table.accounts[slot];
+        let new_current = account.current.saturating_add(size);
+        if let Some(quota) = account.quota
+            && new_current > quota
+        {
+            account.denied += 1;
+            return None;
+        }
+
+        account.current = new_current;
+        account.peak = account.peak.max(new_current);
+        account.allocs += 1;
+        Some(slot as u8)
+    })
+}
+
+/// charge()で計上した使用量を差し引く（アロケータから呼ばれる）
+///
+/// # Arguments
+/// * `slot` - charge()が返したスロット番号
+/// * `size` - 割り当てサイズ（バイト）
+pub fn uncharge(slot: u8, size: usize) {
+    let slot = slot as usize;
+    if slot >= NUM_SLOTS {
+        return;
+    }
+
+    without_interrupts(|| {
+        let mut table = ACCOUNTS.lock();
+        let account = &mut table.accounts[slot];
+        account.current = account.current.saturating_sub(size);
+        table.release_if_idle(slot);
+    });
+}
+
+/// タスクの終了を通知
+///
+/// 以降、同じスロットには新しい割り当てを計上しません。
+/// 使用量が0になった時点でスロットを解放します。
+///
+/// # Arguments
+/// * `task_id` - 終了したタスクのID
+pub fn task_exited(task_id: TaskId) {
+    without_interrupts(|| {
+        let mut table = ACCOUNTS.lock();
+        if let Some(slot) = table.find(task_id.as_u64()) {
+            table.accounts[slot].exited = true;
+            table.release_if_idle(slot);
+        }
+    });
+}
+
+/// タスクのヒープクォータを設定
+///
+/// 現在の使用量がクォータを超えている場合でも、以降の割り当てが失敗するだけで、
+/// 割り当て済みのメモリには影響しません。
+///
+/// # Arguments
+/// * `task_id` - 対象タスクのID
+/// * `quota` - クォータ（バイト）。Noneで無制限に戻す
+///
+/// # Errors
+/// * `HeapQuotaError::TooManyTasks` - 計上スロットをすべて使用中の場合
+pub fn set_quota(task_id: TaskId, quota: Option<usize>) -> Result<(), HeapQuotaError> {
+    without_interrupts(|| {
+        let mut table = ACCOUNTS.lock();
+        let slot = table
+            .find_or_insert(task_id.as_u64())
+            .ok_or(HeapQuotaError::TooManyTasks)?;
+        table.accounts[slot].quota = quota;
+        Ok(())
+    })
+}
+
+/// タスクのヒープ使用量を取得（ロック取得を待たない版）
+///
+/// 割り込みハンドラなど、計上表のロック保持中のコードを割り込んでいる可能性がある場所から使用します。
+///
+/// # Returns
+/// 計上スロットがない場合、またはロックが取得できない場合はNone
+pub fn try_task_usage(task_id: TaskId) -> Option<TaskHeapUsage> {
+    without_interrupts(|| {
+        let table = ACCOUNTS.try_lock()?;
+        let slot = table.find(task_id.as_u64())?;
+        Some(table.accounts[slot].usage(slot))
+    })
+}
+
+/// 全スロットのヒープ使用量をコールバックに渡す
+///
+/// 計上表のスナップショットを取ってからロック外でコールバックを呼ぶため、
+/// コールバック内でヒープを使用しても構いません。スロット0（未計上分）が最初に渡されます。
+///
+/// # Arguments
+/// * `f` - 使用中のスロットごとに呼ばれるコールバック
+pub fn for_each_usage(mut f: impl FnMut(&TaskHeapUsage)) {
+    let snapshot = without_interrupts(|| ACCOUNTS.lock().accounts);
+    for (slot, account) in snapshot.iter().enumerate() {
+        if account.active {
+            f(&account.usage(slot));
+        }
+    }
+}
@@ -6,7 +6,8 @@
 use core::ptr::{read_volatile, write_volatile};
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
-use crate::paging::KERNEL_VIRTUAL_BASE;
+use crate::iotrace::{self, Device, Direction};
+use crate::paging::{self, CacheMode};
 
 /// HPETが利用可能かどうか
 static HPET_AVAILABLE: AtomicBool = AtomicBool::new(false);
@@ -40,7 +41,9 @@ unsafe fn read_hpet_reg(offset: u64) -> u64 {
         return 0;
     }
     let addr = (base + offset) as *const u64;
-    unsafe { read_volatile(addr) }
+    let value = unsafe { read_volatile(addr) };
+    iotrace::mmio(Device::Hpet, addr as u64, 64, value, Direction::Read);
+    value
 }
 
 /// HPETレジスタへの書き込み（64bit）
@@ -50,16 +53,25 @@ unsafe fn write_hpet_reg(offset: u64, value: u64) {
         return;
     }
     let addr = (base + offset) as *mut u64;
-    unsafe { write_volatile(addr, value) }
+    unsafe { write_volatile(addr, value) };
+    iotrace::mmio(Device::Hpet, addr as u64, 64, value, Direction::Write);
 }
 
 /// ACPIからHPETを初期化
 ///
+/// レジスタ領域をキャッシュ無効でマップします。マップできない場合、HPETは使いません。
+///
 /// # Arguments
 /// * `base_phys_addr` - HPETレジスタの物理ベースアドレス
 pub fn init(base_phys_addr: u64) {
-    // 物理アドレスを仮想アドレスに変換
-    let base_virt = KERNEL_VIRTUAL_BASE + base_phys_addr;
+    // レジスタ領域（1KB）は直接マッピングに含まれないため、ここでマップする
+    let base_virt = match paging::map_mmio(base_phys_addr, 0x400, CacheMode::Uncacheable) {
+        Ok(virt) => virt,
+        Err(e) => {
+            crate::warn!("HPET: failed to map registers: {}", e);
+            return;
+        }
+    };
     HPET_BASE.store(base_virt, Ordering::SeqCst);
 
     // SAFETY: HPETのベースアドレスはACPIテーブルから取得した有効なアドレス
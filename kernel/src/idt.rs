@@ -4,92 +4,232 @@
 
 use crate::{info, println};
 use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
 use spin::Mutex;
 
 use crate::apic;
 use crate::gdt;
+use crate::irq;
 use crate::paging::KERNEL_VIRTUAL_BASE;
+use crate::percpu;
 use crate::timer;
 
 // =============================================================================
 // 例外ハンドラ生成マクロ
 // =============================================================================
 
+/// すべての汎用レジスタを保存して `InterruptFrame` を組み立てる命令列
+///
+/// RAXからR15の順に積むため、スタック上ではR15が最も低いアドレスに来ます。
+macro_rules! push_all_registers {
+    () => {
+        concat!(
+            "push rax\n",
+            "push rbx\n",
+            "push rcx\n",
+            "push rdx\n",
+            "push rsi\n",
+            "push rdi\n",
+            "push rbp\n",
+            "push r8\n",
+            "push r9\n",
+            "push r10\n",
+            "push r11\n",
+            "push r12\n",
+            "push r13\n",
+            "push r14\n",
+            "push r15\n",
+        )
+    };
+}
+
+/// `push_all_registers!` で保存したレジスタを復元する命令列
+macro_rules! pop_all_registers {
+    () => {
+        concat!(
+            "pop r15\n",
+            "pop r14\n",
+            "pop r13\n",
+            "pop r12\n",
+            "pop r11\n",
+            "pop r10\n",
+            "pop r9\n",
+            "pop r8\n",
+            "pop rbp\n",
+            "pop rdi\n",
+            "pop rsi\n",
+            "pop rdx\n",
+            "pop rcx\n",
+            "pop rbx\n",
+            "pop rax\n",
+        )
+    };
+}
+
 /// エラーコードなしの例外ハンドラを生成するマクロ
 ///
+/// エラーコードのスロットに0を積んでエラーコード付きの例外と同じ配置にし、
+/// 組み立てた `InterruptFrame` へのポインタをRDI（第1引数）に渡して
 /// レジスタの保存/復元とiretqを含むnaked関数を生成します。
+///
+/// Ring 3から入った場合はGSベースをカーネルの値に切り替えます（`percpu::swapgs_if_user!`）。
+/// どこでも発生する例外（NMI・#MC）は `paranoid` を指定し、GSベースの値で判断します。
 macro_rules! exception_handler {
     ($name:ident, $inner:ident) => {
         #[unsafe(naked)]
         extern "C" fn $name() {
             core::arch::naked_asm!(
-                // caller-savedレジスタを保存
-                "push rax",
-                "push rcx",
-                "push rdx",
-                "push rsi",
-                "push rdi",
-                "push r8",
-                "push r9",
-                "push r10",
-                "push r11",
-                // 実際のハンドラを呼び出し
+                // エラーコードの代わり
+                "push 0",
+                percpu::swapgs_if_user!("[rsp + 16]"),
+                push_all_registers!(),
+                // 実際のハンドラを呼び出し（RDIに割り込みフレーム）
+                // CPUが積んだ5ワード・エラーコード・15レジスタの計21ワードのため、
+                // 8バイトずらしてcall時に16バイト境界に揃える
+                "mov rdi, rsp",
+                "sub rsp, 8",
                 "call {handler_inner}",
-                // レジスタを復元
-                "pop r11",
-                "pop r10",
-                "pop r9",
-                "pop r8",
-                "pop rdi",
-                "pop rsi",
-                "pop rdx",
-                "pop rcx",
-                "pop rax",
+                "add rsp, 8",
+                pop_all_registers!(),
+                // エラーコードのスロットを取り除く
+                "add rsp, 8",
+                percpu::swapgs_if_user!("[rsp + 8]"),
                 // 割り込みから復帰
                 "iretq",
                 handler_inner = sym $inner,
             )
         }
     };
+    ($name:ident, $inner:ident, paranoid) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            core::arch::naked_asm!(
+                // エラーコードの代わり
+                "push 0",
+                push_all_registers!(),
+                percpu::swapgs_paranoid_entry!(),
+                "mov rdi, rsp",
+                "sub rsp, 8",
+                "call {handler_inner}",
+                "add rsp, 8",
+                percpu::swapgs_paranoid_exit!(),
+                pop_all_registers!(),
+                "add rsp, 8",
+                "iretq",
+                handler_inner = sym $inner,
+            )
+        }
+    };
 }
 
 /// エラーコード付きの例外ハンドラを生成するマクロ
 ///
-/// エラーコードをRDI（第1引数）に移動し、レジスタの保存/復元とiretqを含むnaked関数を生成します。
+/// CPUが積んだエラーコードの下にすべての汎用レジスタを保存して `InterruptFrame` を組み立て、
+/// そのポインタをRDI（第1引数）に渡してレジスタの保存/復元とiretqを含むnaked関数を生成します。
+/// ハンドラから復帰すると全レジスタを復元するため、中断したコードを再開できます
+/// （ページフォルトからのスワップイン等）。
+///
+/// GSベースの切り替えは `exception_handler!` と同じです（#DFは `paranoid`）。
 macro_rules! exception_handler_with_error_code {
     ($name:ident, $inner:ident) => {
         #[unsafe(naked)]
         extern "C" fn $name() {
             core::arch::naked_asm!(
-                // エラーコードをRDIレジスタに移動（System V ABIの第1引数）
-                "pop rdi",
-                // caller-savedレジスタを保存（rdiはエラーコードが入っているので保存しない）
-                "push rax",
-                "push rcx",
-                "push rdx",
-                "push rsi",
-                "push r8",
-                "push r9",
-                "push r10",
-                "push r11",
-                // 実際のハンドラを呼び出し（RDIにエラーコード）
+                percpu::swapgs_if_user!("[rsp + 16]"),
+                push_all_registers!(),
+                // 実際のハンドラを呼び出し（RDIに割り込みフレーム）
+                // CPUが積んだ6ワードと15レジスタの計21ワードのため、
+                // 8バイトずらしてcall時に16バイト境界に揃える
+                "mov rdi, rsp",
+                "sub rsp, 8",
                 "call {handler_inner}",
-                // レジスタを復元
-                "pop r11",
-                "pop r10",
-                "pop r9",
-                "pop r8",
-                "pop rsi",
-                "pop rdx",
-                "pop rcx",
-                "pop rax",
+                "add rsp, 8",
+                pop_all_registers!(),
+                // エラーコードのスロットを取り除く
+                "add rsp, 8",
+                percpu::swapgs_if_user!("[rsp + 8]"),
                 // 割り込みから復帰
                 "iretq",
                 handler_inner = sym $inner,
             )
         }
     };
+    ($name:ident, $inner:ident, paranoid) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            core::arch::naked_asm!(
+                push_all_registers!(),
+                percpu::swapgs_paranoid_entry!(),
+                "mov rdi, rsp",
+                "sub rsp, 8",
+                "call {handler_inner}",
+                "add rsp, 8",
+                percpu::swapgs_paranoid_exit!(),
+                pop_all_registers!(),
+                "add rsp, 8",
+                "iretq",
+                handler_inner = sym $inner,
+            )
+        }
+    };
+}
+
+/// 例外ベクタ: Divide Error (#DE)
+pub const VECTOR_DIVIDE_ERROR: u8 = 0;
+/// 例外ベクタ: Non-Maskable Interrupt (NMI)
+pub const VECTOR_NMI: u8 = 2;
+/// 例外ベクタ: Breakpoint (#BP)
+pub const VECTOR_BREAKPOINT: u8 = 3;
+/// 例外ベクタ: Invalid Opcode (#UD)
+pub const VECTOR_INVALID_OPCODE: u8 = 6;
+/// 例外ベクタ: Device Not Available (#NM)
+pub const VECTOR_DEVICE_NOT_AVAILABLE: u8 = 7;
+/// 例外ベクタ: General Protection Fault (#GP)
+pub const VECTOR_GENERAL_PROTECTION: u8 = 13;
+/// 例外ベクタ: Page Fault (#PF)
+pub const VECTOR_PAGE_FAULT: u8 = 14;
+/// 例外ベクタ: Machine Check (#MC)
+pub const VECTOR_MACHINE_CHECK: u8 = 18;
+
+/// System Control Port B（NMIの要因を示す）
+const SYSTEM_CONTROL_PORT_B: u16 = 0x61;
+/// System Control Port B: PCIのSERR#やメモリのパリティエラーによるNMI
+const NMI_REASON_SERR: u8 = 1 << 7;
+/// System Control Port B: ISAのIOCHK#によるNMI
+const NMI_REASON_IOCHK: u8 = 1 << 6;
+
+/// NMIの受信回数
+static NMI_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// 例外発生時の割り込みフレーム
+///
+/// 例外スタブが保存した汎用レジスタ、エラーコード（ない例外では0）、
+/// CPUが積んだRIP/CS/RFLAGS/RSP/SSを、スタック上の配置の順に並べたものです。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
 }
 
 /// IDT操作のエラー型
@@ -111,6 +251,82 @@ impl core::fmt::Display for IdtError {
     }
 }
 
+impl InterruptFrame {
+    /// ユーザーモード（Ring 3）で発生した例外か
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+
+    /// 例外発生時の実行位置とすべての汎用レジスタを出力
+    pub fn dump(&self) {
+        println!(
+            "RIP: 0x{:016X}  CS: 0x{:X}  RFLAGS: 0x{:016X}",
+            self.rip, self.cs, self.rflags
+        );
+        println!("RSP: 0x{:016X}  SS: 0x{:X}", self.rsp, self.ss);
+        println!(
+            "RAX {:016X}  RBX {:016X}  RCX {:016X}  RDX {:016X}",
+            self.rax, self.rbx, self.rcx, self.rdx
+        );
+        println!(
+            "RSI {:016X}  RDI {:016X}  RBP {:016X}",
+            self.rsi, self.rdi, self.rbp
+        );
+        println!(
+            "R8  {:016X}  R9  {:016X}  R10 {:016X}  R11 {:016X}",
+            self.r8, self.r9, self.r10, self.r11
+        );
+        println!(
+            "R12 {:016X}  R13 {:016X}  R14 {:016X}  R15 {:016X}",
+            self.r12, self.r13, self.r14, self.r15
+        );
+    }
+}
+
+/// ユーザーモードで発生した例外なら、そのタスクだけを終了させる
+///
+/// 例外の内容を `fault_report` に記録してからタスクを終了させ、戻りません。
+/// カーネルモードの例外の場合は何もせずに戻ります。
+///
+/// # Arguments
+/// * `vector` - 例外ベクタ
+/// * `name` - 例外の表示名
+/// * `error_code` - エラーコード（ない場合は0）
+/// * `addr` - フォルトアドレス（#PFはCR2、それ以外はRIP）
+/// * `frame` - 割り込みフレーム
+fn kill_user_task_on_fault(
+    vector: u8,
+    name: &str,
+    error_code: u64,
+    addr: u64,
+    frame: &InterruptFrame,
+) {
+    if !frame.is_user() {
+        return;
+    }
+    // 記録と警告の出力はヒープが枯渇していても行えるようにする
+    // （sched::exitは戻らないため、ガードはその前に破棄する）
+    let emergency = crate::emergency::enter();
+    let task_id = crate::sched::current_task_id();
+    crate::fault_report::record(crate::fault_report::FaultReport {
+        task_id,
+        vector,
+        error_code,
+        rip: frame.rip,
+        addr,
+    });
+    crate::warn!(
+        "Task {} killed: {} at 0x{:X} (error code 0x{:X}, RIP 0x{:X})",
+        task_id.as_u64(),
+        name,
+        addr,
+        error_code,
+        frame.rip
+    );
+    drop(emergency);
+    crate::sched::exit();
+}
+
 /// 現在高位アドレス空間で実行されているかチェック
 #[allow(dead_code)]
 fn is_higher_half() -> bool {
@@ -227,6 +443,8 @@ extern "C" fn default_handler() {
 #[unsafe(naked)]
 extern "C" fn timer_interrupt_handler() {
     core::arch::naked_asm!(
+        // Ring 3から入った場合はカーネルのGSベースに切り替える
+        percpu::swapgs_if_user!("[rsp + 8]"),
         // レジスタを保存
         "push rax",
         "push rcx",
@@ -258,6 +476,8 @@ extern "C" fn timer_interrupt_handler() {
         "pop rcx",
         "pop rax",
 
+        // Ring 3へ戻る場合はユーザーモードのGSベースに戻す
+        percpu::swapgs_if_user!("[rsp + 8]"),
         // 割り込みから復帰（スタック上のRFLAGSが復元される）
         "iretq",
 
@@ -270,26 +490,24 @@ extern "C" fn timer_interrupt_handler() {
 ///
 /// need_reschedフラグがセットされている場合、スケジューラを呼び出します。
 /// RFLAGSの保存・復元はswitch_context()内部で自動的に処理されます。
-extern "C" fn check_resched_on_interrupt_exit_wrapper() {
+pub(crate) extern "C" fn check_resched_on_interrupt_exit_wrapper() {
     crate::sched::check_resched_on_interrupt_exit();
 }
 
 /// タイマー割り込みハンドラの実装
 extern "C" fn timer_handler_inner() {
-    // tick数をインクリメント
-    let _tick = timer::increment_tick();
-
-    // 期限切れタイマーをチェック（ペンディングキューに移動するだけ）
-    timer::check_timers();
+    // システム時刻とタイマーはBSPのみが進める（各CPUのLocal APICタイマーが同じ周期で動くため）
+    if crate::percpu::is_bsp() {
+        // tick数をインクリメント
+        let _tick = timer::increment_tick();
 
-    // 現在のタスクのvruntimeを更新（CFS風スケジューリング）
-    // タイマー周波数は250Hzなので、1tick = 4ms = 4,000,000ns
-    const TIMER_PERIOD_NS: u64 = 4_000_000;
-    crate::sched::update_current_task_vruntime(TIMER_PERIOD_NS);
+        // 期限切れタイマーをチェック（ペンディングキューに移動するだけ）
+        timer::check_timers();
+    }
 
-    // スケジューリングが必要であることを示すフラグをセット
+    // 現在のタスクがプリエンプション粒度を使い切っていればフラグをセット
     // 実際のスケジューリングは割り込み復帰時に行われる（Linux風）
-    crate::sched::set_need_resched();
+    crate::sched::scheduler_tick();
 
     // EOI (End of Interrupt) を送信
     apic::send_eoi();
@@ -299,16 +517,19 @@ extern "C" fn timer_handler_inner() {
 // 例外ハンドラ実装
 // =============================================================================
 
-/// Divide Error (#DE, ベクタ0) ハンドラ
-/// ゼロ除算または除算結果がオーバーフローした場合に発生
+// Divide Error (#DE, ベクタ0) ハンドラ
+// ゼロ除算または除算結果がオーバーフローした場合に発生
 exception_handler!(divide_error_handler, divide_error_handler_inner);
 
-extern "C" fn divide_error_handler_inner() {
+extern "C" fn divide_error_handler_inner(frame: &InterruptFrame) {
+    kill_user_task_on_fault(VECTOR_DIVIDE_ERROR, "divide error", 0, frame.rip, frame);
+
     println!("\n\n");
     println!("========================================");
     println!("EXCEPTION: Divide Error (#DE)");
     println!("========================================");
     println!("Division by zero or division overflow occurred.");
+    frame.dump();
     println!("");
 
     // 停止
@@ -317,16 +538,17 @@ extern "C" fn divide_error_handler_inner() {
     }
 }
 
-/// Debug Exception (#DB, ベクタ1) ハンドラ
-/// デバッグレジスタによるブレークポイントやシングルステップで発生
+// Debug Exception (#DB, ベクタ1) ハンドラ
+// デバッグレジスタによるブレークポイントやシングルステップで発生
 exception_handler!(debug_exception_handler, debug_exception_handler_inner);
 
-extern "C" fn debug_exception_handler_inner() {
+extern "C" fn debug_exception_handler_inner(frame: &InterruptFrame) {
     println!("\n\n");
     println!("========================================");
     println!("EXCEPTION: Debug Exception (#DB)");
     println!("========================================");
     println!("Debug exception occurred.");
+    frame.dump();
     println!("");
 
     loop {
@@ -334,32 +556,118 @@ extern "C" fn debug_exception_handler_inner() {
     }
 }
 
-/// Breakpoint (#BP, ベクタ3) ハンドラ
-/// INT3命令（0xCC）によって発生
+// Non-Maskable Interrupt (NMI, ベクタ2) ハンドラ
+// ハードウェアエラー（SERR#/IOCHK#）やウォッチドッグ、他のCPUからのNMI IPI
+// （TLBシュートダウンを含む）で発生
+//
+// 任意の位置で発生するため専用のISTスタックで動き、割り込まれたコードが保持している
+// 可能性のあるロックは使いません（表示はロックを使わない `println!` のみ）。
+exception_handler!(nmi_handler, nmi_handler_inner, paranoid);
+
+extern "C" fn nmi_handler_inner(frame: &InterruptFrame) {
+    // 他のCPUからのTLBシュートダウン（ハードウェアエラーと重なった場合に備えて要因も調べる）
+    let shootdown = crate::smp::handle_tlb_shootdown();
+    // SAFETY: System Control Port Bの読み出しは副作用がない
+    let reason = unsafe { crate::io::port_read_u8(SYSTEM_CONTROL_PORT_B) };
+    let cause = if reason & NMI_REASON_SERR != 0 {
+        Some("system error (SERR#, memory parity)")
+    } else if reason & NMI_REASON_IOCHK != 0 {
+        Some("I/O channel check (IOCHK#)")
+    } else {
+        None
+    };
+
+    if shootdown && cause.is_none() {
+        return;
+    }
+    NMI_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    let Some(cause) = cause else {
+        // 要因を特定できないNMIは記録だけして再開する
+        println!(
+            "NMI received for unknown reason 0x{:02X} on CPU {} (RIP 0x{:X})",
+            reason,
+            crate::percpu::current_index(),
+            frame.rip
+        );
+        return;
+    };
+
+    println!("\n\n");
+    println!("========================================");
+    println!("FATAL: Non-Maskable Interrupt (NMI)");
+    println!("========================================");
+    println!("Hardware error reported: {}", cause);
+    println!("System Control Port B: 0x{:02X}", reason);
+    frame.dump();
+    println!("");
+    panic!("NMI: {} (RIP 0x{:X})", cause, frame.rip);
+}
+
+/// NMIの受信回数
+pub fn nmi_count() -> u64 {
+    NMI_COUNT.load(Ordering::Relaxed)
+}
+
+// Breakpoint (#BP, ベクタ3) ハンドラ
+// INT3命令（0xCC）によって発生
 exception_handler!(breakpoint_handler, breakpoint_handler_inner);
 
-extern "C" fn breakpoint_handler_inner() {
+extern "C" fn breakpoint_handler_inner(frame: &InterruptFrame) {
+    // ユーザータスクにはデバッガがないため終了させる
+    kill_user_task_on_fault(VECTOR_BREAKPOINT, "breakpoint", 0, frame.rip, frame);
+
     println!("\n\n");
     println!("========================================");
     println!("EXCEPTION: Breakpoint (#BP)");
     println!("========================================");
     println!("Breakpoint exception occurred.");
+    frame.dump();
     println!("");
 
     // ブレークポイントは通常、続行可能
     println!("Control will transfer to debugger if attached.");
 }
 
-/// Invalid Opcode (#UD, ベクタ6) ハンドラ
-/// 無効な命令やサポートされていない命令を実行しようとした場合に発生
+// Invalid Opcode (#UD, ベクタ6) ハンドラ
+// 無効な命令やサポートされていない命令を実行しようとした場合に発生
 exception_handler!(invalid_opcode_handler, invalid_opcode_handler_inner);
 
-extern "C" fn invalid_opcode_handler_inner() {
+extern "C" fn invalid_opcode_handler_inner(frame: &InterruptFrame) {
+    kill_user_task_on_fault(VECTOR_INVALID_OPCODE, "invalid opcode", 0, frame.rip, frame);
+
     println!("\n\n");
     println!("========================================");
     println!("EXCEPTION: Invalid Opcode (#UD)");
     println!("========================================");
     println!("Attempted to execute an invalid or unsupported instruction.");
+    frame.dump();
+    println!("");
+
+    loop {
+        unsafe { asm!("hlt") };
+    }
+}
+
+// Device Not Available (#NM, ベクタ7) ハンドラ
+// CR0.TSが立った状態でFPU/SIMD命令を実行した場合に発生
+// タスクの拡張状態を遅延して復元し、中断した命令から再開する（`sched::handle_device_not_available`）
+exception_handler!(
+    device_not_available_handler,
+    device_not_available_handler_inner
+);
+
+extern "C" fn device_not_available_handler_inner(frame: &InterruptFrame) {
+    if crate::sched::handle_device_not_available() {
+        return;
+    }
+
+    println!("\n\n");
+    println!("========================================");
+    println!("EXCEPTION: Device Not Available (#NM)");
+    println!("========================================");
+    println!("FPU/SIMD instruction used with no task to restore extended state for.");
+    frame.dump();
     println!("");
 
     loop {
@@ -367,15 +675,25 @@ extern "C" fn invalid_opcode_handler_inner() {
     }
 }
 
+// Machine Check (#MC, ベクタ18) ハンドラ
+// CPUがハードウェアエラー（メモリ・キャッシュ・バスなど）を検出した場合に発生
+// 解読は `mce::handle` が行い、専用のISTスタックで動く
+exception_handler!(machine_check_handler, machine_check_handler_inner, paranoid);
+
+extern "C" fn machine_check_handler_inner(frame: &InterruptFrame) {
+    crate::mce::handle(frame);
+}
+
 // =============================================================================
 // エラーコード付き例外ハンドラ実装
 // =============================================================================
 
-/// Double Fault (#DF, ベクタ8) ハンドラ
-/// 例外ハンドラ内で別の例外が発生した場合に発生（重大なエラー）
-exception_handler_with_error_code!(double_fault_handler, double_fault_handler_inner);
+// Double Fault (#DF, ベクタ8) ハンドラ
+// 例外ハンドラ内で別の例外が発生した場合に発生（重大なエラー）
+exception_handler_with_error_code!(double_fault_handler, double_fault_handler_inner, paranoid);
 
-extern "C" fn double_fault_handler_inner(error_code: u64) {
+extern "C" fn double_fault_handler_inner(frame: &InterruptFrame) {
+    let error_code = frame.error_code;
     // CR2レジスタから最後のPage Fault違反アドレスを取得
     // Double FaultはPage Fault → Page Faultで発生するため、CR2には最初のPage Faultアドレスが残っている
     let fault_addr: u64;
@@ -383,6 +701,9 @@ extern "C" fn double_fault_handler_inner(error_code: u64) {
         asm!("mov {}, cr2", out(reg) fault_addr, options(nomem, nostack));
     }
 
+    // 復帰しないため、以後の割り当ては緊急用予約から行う
+    crate::emergency::enter_permanently();
+
     // Guard Pageアクセスの検知（スタックオーバーフロー）
     let guard_page_addr = {
         let stack_addr = core::ptr::addr_of!(crate::paging::KERNEL_STACK) as u64;
@@ -406,6 +727,20 @@ extern "C" fn double_fault_handler_inner(error_code: u64) {
         println!("The kernel stack has been exhausted.");
         println!("Possible causes: infinite recursion or large local variables.");
         println!("");
+    } else if let Some((task_id, guard)) = crate::sched::guard_owner(fault_addr) {
+        // タスクスタックのガードページ（スタック上に例外フレームを積めずダブルフォルトになる）
+        println!("\n\n");
+        println!("========================================");
+        println!("FATAL: TASK STACK OVERFLOW DETECTED");
+        println!("========================================");
+        println!("Stack overflow in task {}!", task_id.as_u64());
+        println!("");
+        println!("Guard Page address: 0x{:016X}", guard);
+        println!("Fault address (CR2): 0x{:016X}", fault_addr);
+        println!("Error code: 0x{:X}", error_code);
+        println!("");
+        println!("Possible causes: infinite recursion or large local variables.");
+        println!("");
     } else {
         // 通常のDouble Fault
         println!("\n\n");
@@ -419,6 +754,8 @@ extern "C" fn double_fault_handler_inner(error_code: u64) {
         println!("System is in a critical error state.");
         println!("");
     }
+    frame.dump();
+    println!("");
 
     // 永久停止
     loop {
@@ -426,14 +763,23 @@ extern "C" fn double_fault_handler_inner(error_code: u64) {
     }
 }
 
-/// General Protection Fault (#GP, ベクタ13) ハンドラ
-/// セグメント違反、特権レベル違反、無効なメモリアクセスなどで発生
+// General Protection Fault (#GP, ベクタ13) ハンドラ
+// セグメント違反、特権レベル違反、無効なメモリアクセスなどで発生
 exception_handler_with_error_code!(
     general_protection_fault_handler,
     general_protection_fault_handler_inner
 );
 
-extern "C" fn general_protection_fault_handler_inner(error_code: u64) {
+extern "C" fn general_protection_fault_handler_inner(frame: &InterruptFrame) {
+    let error_code = frame.error_code;
+    kill_user_task_on_fault(
+        VECTOR_GENERAL_PROTECTION,
+        "general protection fault",
+        error_code,
+        frame.rip,
+        frame,
+    );
+
     println!("\n\n");
     println!("========================================");
     println!("EXCEPTION: General Protection Fault (#GP)");
@@ -463,23 +809,56 @@ extern "C" fn general_protection_fault_handler_inner(error_code: u64) {
         println!("  - Index: 0x{:X}", index);
     }
     println!("");
+    frame.dump();
+    println!("");
 
     loop {
         unsafe { asm!("hlt") };
     }
 }
 
-/// Page Fault (#PF, ベクタ14) ハンドラ
-/// 無効なページアクセス、権限違反、ページ未マップなどで発生
+// Page Fault (#PF, ベクタ14) ハンドラ
+// 無効なページアクセス、権限違反、ページ未マップなどで発生
 exception_handler_with_error_code!(page_fault_handler, page_fault_handler_inner);
 
-extern "C" fn page_fault_handler_inner(error_code: u64) {
+extern "C" fn page_fault_handler_inner(frame: &mut InterruptFrame) {
+    let error_code = frame.error_code;
     // CR2レジスタから違反アドレスを取得
     let fault_addr: u64;
     unsafe {
         asm!("mov {}, cr2", out(reg) fault_addr, options(nomem, nostack));
     }
 
+    // 解決できればマッピングを修正済みのため、復帰してフォルトした命令を再実行する
+    let fault = crate::page_fault::PageFault {
+        addr: fault_addr,
+        error_code,
+        frame: *frame,
+    };
+    let Err(reason) = crate::page_fault::resolve(&fault) else {
+        return;
+    };
+
+    // プローブ読み込みのフォルトは、フィックスアップ先へ復帰して呼び出し元に失敗を返す
+    if !fault.is_user()
+        && let Some(fixup) = crate::page_fault::fixup_address(frame.rip)
+    {
+        frame.rip = fixup;
+        return;
+    }
+
+    // ユーザーモードでの不正アクセスはそのタスクだけを終了させる
+    if fault.is_user() {
+        crate::trace::record(crate::trace::TraceKind::UserFault, fault_addr, error_code);
+        kill_user_task_on_fault(
+            VECTOR_PAGE_FAULT,
+            "page fault",
+            error_code,
+            fault_addr,
+            frame,
+        );
+    }
+
     println!("\n\n");
     println!("========================================");
     println!("EXCEPTION: Page Fault (#PF)");
@@ -487,6 +866,15 @@ extern "C" fn page_fault_handler_inner(error_code: u64) {
     println!("Invalid memory access occurred.");
     println!("Fault address: 0x{:016X}", fault_addr);
     println!("Error code: 0x{:X}", error_code);
+    println!("Reason: {}", reason);
+    if let Some((task_id, guard)) = crate::sched::guard_owner(fault_addr) {
+        println!(
+            "Stack overflow in task {} (guard page 0x{:016X})",
+            task_id.as_u64(),
+            guard
+        );
+    }
+    frame.dump();
 
     // エラーコードの詳細を解析
     println!("");
@@ -529,9 +917,10 @@ extern "C" fn page_fault_handler_inner(error_code: u64) {
     );
     println!("");
 
-    loop {
-        unsafe { asm!("hlt") };
-    }
+    panic!(
+        "Unrecoverable page fault at 0x{:X} (RIP 0x{:X})",
+        fault_addr, fault.frame.rip
+    );
 }
 
 /// IDTエントリを設定
@@ -546,6 +935,17 @@ fn set_idt_entry(vector: u8, handler: usize) {
     );
 }
 
+/// ユーザーモードから呼び出せる（DPL = 3）IDTエントリを設定
+fn set_idt_entry_user(vector: u8, handler: usize) {
+    let mut idt = IDT.lock();
+
+    idt.entries[vector as usize] = IdtEntry::new(
+        handler,
+        gdt::selector::KERNEL_CODE,
+        3, // DPL = 3 (ユーザーレベルから `int` 命令で呼び出し可能)
+    );
+}
+
 /// IST付きIDTエントリを設定
 fn set_idt_entry_with_ist(vector: u8, handler: usize, ist_index: u8) {
     let mut idt = IDT.lock();
@@ -562,25 +962,75 @@ fn set_idt_entry_with_ist(vector: u8, handler: usize, ist_index: u8) {
 /// IDTを初期化してロード
 pub fn init() -> Result<(), IdtError> {
     // 例外ハンドラを登録
-    set_idt_entry(0, divide_error_handler as usize); // #DE: Divide Error
-    set_idt_entry(1, debug_exception_handler as usize); // #DB: Debug Exception
-    set_idt_entry(3, breakpoint_handler as usize); // #BP: Breakpoint
-    set_idt_entry(6, invalid_opcode_handler as usize); // #UD: Invalid Opcode
+    set_idt_entry(
+        VECTOR_DIVIDE_ERROR,
+        divide_error_handler as *const () as usize,
+    ); // #DE: Divide Error
+    set_idt_entry(1, debug_exception_handler as *const () as usize); // #DB: Debug Exception
+    // NMIとMachine Checkは任意の位置で発生するため専用スタックを使用
+    set_idt_entry_with_ist(
+        VECTOR_NMI,
+        nmi_handler as *const () as usize,
+        gdt::NMI_IST_INDEX,
+    ); // NMI
+    // #BP: Breakpoint（ユーザーモードのINT3も受け付ける）
+    set_idt_entry_user(VECTOR_BREAKPOINT, breakpoint_handler as *const () as usize);
+    set_idt_entry(
+        VECTOR_INVALID_OPCODE,
+        invalid_opcode_handler as *const () as usize,
+    ); // #UD: Invalid Opcode
+    // #NM: Device Not Available（拡張状態の遅延復元）
+    set_idt_entry(
+        VECTOR_DEVICE_NOT_AVAILABLE,
+        device_not_available_handler as *const () as usize,
+    );
     // Double FaultハンドラにはIST1を設定（専用スタック使用）
     set_idt_entry_with_ist(
         8,
-        double_fault_handler as usize,
+        double_fault_handler as *const () as usize,
         gdt::DOUBLE_FAULT_IST_INDEX,
     ); // #DF: Double Fault
-    set_idt_entry(13, general_protection_fault_handler as usize); // #GP: General Protection Fault
-    set_idt_entry(14, page_fault_handler as usize); // #PF: Page Fault
+    set_idt_entry(
+        VECTOR_GENERAL_PROTECTION,
+        general_protection_fault_handler as *const () as usize,
+    ); // #GP: General Protection Fault
+    set_idt_entry(VECTOR_PAGE_FAULT, page_fault_handler as *const () as usize); // #PF: Page Fault
+    set_idt_entry_with_ist(
+        VECTOR_MACHINE_CHECK,
+        machine_check_handler as *const () as usize,
+        gdt::MACHINE_CHECK_IST_INDEX,
+    ); // #MC: Machine Check
 
     // タイマー割り込みハンドラを登録
     set_idt_entry(
         apic::TIMER_INTERRUPT_VECTOR,
-        timer_interrupt_handler as usize,
+        timer_interrupt_handler as *const () as usize,
+    );
+
+    // 外部割り込みとIPIは共通の入口を経由する（ハンドラはirq::request_irqで登録）
+    for vector in irq::FIRST_VECTOR..=irq::LAST_VECTOR {
+        if let Some(entry) = irq::entry_point(vector) {
+            set_idt_entry(vector, entry);
+        }
+    }
+
+    // システムコール（int 0x80）
+    set_idt_entry_user(
+        crate::syscall::INTERRUPT_VECTOR,
+        crate::syscall::syscall_entry as *const () as usize,
     );
 
+    load();
+
+    info!("IDT initialized with exception handlers");
+    Ok(())
+}
+
+/// 現在のCPUに共有IDTをロード
+///
+/// すべてのCPUが同じIDTを使用します。アプリケーションプロセッサは起動時にこれを呼び出します。
+pub fn load() {
+    // SAFETY: IDTは静的に確保され、init()で全ハンドラを登録済み。LIDTは有効なIDTRを読むのみ
     unsafe {
         // IDTのアドレスを取得（カーネルが高位アドレスでリンクされているため既に高位）
         let idt = IDT.lock();
@@ -598,7 +1048,4 @@ pub fn init() -> Result<(), IdtError> {
             options(readonly, nostack, preserves_flags)
         );
     }
-
-    info!("IDT initialized with exception handlers");
-    Ok(())
 }
@@ -40,9 +40,23 @@ where
     result
 }
 
+/// 割り込みが有効か（RFLAGS.IF）
+///
+/// 割り込み・例外ハンドラの中（割り込みゲートによりIFがクリアされている）ではfalseになります。
+#[inline]
+pub fn interrupts_enabled() -> bool {
+    let rflags: u64;
+    // SAFETY: PUSHFQ/POP命令でRFLAGSを読み取るのみで、状態を変更しない
+    unsafe {
+        asm!("pushfq; pop {}", out(reg) rflags, options(nomem, preserves_flags));
+    }
+    rflags & 0x200 != 0
+}
+
 // I/Oポートに1バイト書き込み
 #[inline]
 pub unsafe fn port_write_u8(port: u16, value: u8) {
+    crate::iotrace::port(port, 8, value as u32, crate::iotrace::Direction::Write);
     unsafe {
         asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack));
     }
@@ -55,6 +69,7 @@ pub unsafe fn port_read_u8(port: u16) -> u8 {
     unsafe {
         asm!("in al, dx", in("dx") port, out("al") value, options(nomem, nostack));
     }
+    crate::iotrace::port(port, 8, value as u32, crate::iotrace::Direction::Read);
     value
 }
 
@@ -62,6 +77,7 @@ pub unsafe fn port_read_u8(port: u16) -> u8 {
 #[allow(dead_code)]
 #[inline]
 pub unsafe fn port_write_u16(port: u16, value: u16) {
+    crate::iotrace::port(port, 16, value as u32, crate::iotrace::Direction::Write);
     unsafe {
         asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack));
     }
@@ -75,6 +91,7 @@ pub unsafe fn port_read_u16(port: u16) -> u16 {
     unsafe {
         asm!("in ax, dx", in("dx") port, out("ax") value, options(nomem, nostack));
     }
+    crate::iotrace::port(port, 16, value as u32, crate::iotrace::Direction::Read);
     value
 }
 
@@ -82,6 +99,7 @@ pub unsafe fn port_read_u16(port: u16) -> u16 {
 #[allow(dead_code)]
 #[inline]
 pub unsafe fn port_write_u32(port: u16, value: u32) {
+    crate::iotrace::port(port, 32, value, crate::iotrace::Direction::Write);
     unsafe {
         asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack));
     }
@@ -95,5 +113,6 @@ pub unsafe fn port_read_u32(port: u16) -> u32 {
     unsafe {
         asm!("in eax, dx", in("dx") port, out("eax") value, options(nomem, nostack));
     }
+    crate::iotrace::port(port, 32, value, crate::iotrace::Direction::Read);
     value
 }
@@ -0,0 +1,478 @@
+//! I/O APIC 実装
+//!
+//! 外部割り込み（キーボード、マウス、シリアルなど）をLocal APICへ配送します。
+//! ACPIのMADT解析時に登録されたI/O APICとInterrupt Source Overrideを元に、
+//! リダイレクションテーブルを設定します。
+//!
+//! Intel 82093AA I/O APIC データシートに基づく実装
+
+use core::ptr::{read_volatile, write_volatile};
+
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+use crate::iotrace::{self, Device, Direction};
+use crate::paging::{self, CacheMode};
+use crate::{apic, info};
+
+/// サポートするI/O APICの最大数
+const MAX_IO_APICS: usize = 4;
+
+/// ISA IRQの数（Interrupt Source Overrideの対象）
+const ISA_IRQ_COUNT: usize = 16;
+
+/// 外部割り込みに使用できる最小ベクタ（0〜31はCPU例外）
+const MIN_EXTERNAL_VECTOR: u8 = 32;
+
+/// I/O APICレジスタ
+mod registers {
+    /// I/O Register Select（MMIOオフセット）
+    pub const IOREGSEL: u64 = 0x00;
+    /// I/O Window（MMIOオフセット）
+    pub const IOWIN: u64 = 0x10;
+    /// I/O APIC ID Register
+    pub const ID: u32 = 0x00;
+    /// I/O APIC Version Register
+    pub const VERSION: u32 = 0x01;
+    /// リダイレクションテーブルの先頭（エントリnは 0x10 + 2n, 0x11 + 2n）
+    pub const REDIRECTION_TABLE: u32 = 0x10;
+}
+
+/// I/O APIC操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoApicError {
+    /// I/O APICが登録されていない
+    NotFound,
+    /// GSIを担当するI/O APICが存在しない
+    GsiOutOfRange,
+    /// 外部割り込みに使用できないベクタ
+    InvalidVector,
+    /// 登録数が上限を超えた
+    TooManyIoApics,
+    /// MMIO領域のマッピングに失敗
+    MapFailed,
+}
+
+impl core::fmt::Display for IoApicError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IoApicError::NotFound => write!(f, "No I/O APIC registered"),
+            IoApicError::GsiOutOfRange => write!(f, "No I/O APIC handles this GSI"),
+            IoApicError::InvalidVector => write!(f, "Invalid interrupt vector"),
+            IoApicError::TooManyIoApics => write!(f, "Too many I/O APICs"),
+            IoApicError::MapFailed => write!(f, "Failed to map I/O APIC MMIO region"),
+        }
+    }
+}
+
+/// トリガーモード
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// エッジトリガー（ISA IRQの既定）
+    Edge,
+    /// レベルトリガー（PCI INTxの既定）
+    Level,
+}
+
+/// 割り込み信号の極性
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// High active（ISA IRQの既定）
+    ActiveHigh,
+    /// Low active（PCI INTxの既定）
+    ActiveLow,
+}
+
+/// リダイレクションテーブルエントリ
+///
+/// 配送モードはFixed、宛先モードはPhysical（Local APIC IDで指定）に固定しています。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectionEntry {
+    /// 配送する割り込みベクタ
+    pub vector: u8,
+    /// 宛先のLocal APIC ID
+    pub destination: u8,
+    /// トリガーモード
+    pub trigger_mode: TriggerMode,
+    /// 極性
+    pub polarity: Polarity,
+    /// マスクするか
+    pub masked: bool,
+}
+
+impl RedirectionEntry {
+    /// 64bitのレジスタ値にエンコード
+    fn to_raw(self) -> u64 {
+        let mut raw = self.vector as u64;
+        // bits 8-10: Delivery Mode = Fixed (000)
+        // bit 11: Destination Mode = Physical (0)
+        if self.polarity == Polarity::ActiveLow {
+            raw |= 1 << 13;
+        }
+        if self.trigger_mode == TriggerMode::Level {
+            raw |= 1 << 15;
+        }
+        if self.masked {
+            raw |= 1 << 16;
+        }
+        raw |= (self.destination as u64) << 56;
+        raw
+    }
+}
+
+/// MADTから取得したI/O APIC情報
+#[derive(Debug, Clone, Copy)]
+struct IoApic {
+    /// I/O APIC ID
+    id: u8,
+    /// MMIOの物理アドレス
+    phys_base: u64,
+    /// MMIOの仮想アドレス（init()でマップ後に設定）
+    virt_base: u64,
+    /// 担当するGSIの先頭
+    gsi_base: u32,
+    /// リダイレクションテーブルのエントリ数（init()で取得）
+    entry_count: u32,
+}
+
+impl IoApic {
+    /// このI/O APICが指定したGSIを担当するか
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entry_count
+    }
+
+    /// I/O APICレジスタからの読み込み
+    ///
+    /// # Safety
+    /// virt_baseがマップ済みのI/O APIC MMIO領域を指していること。
+    /// IOREGSELとIOWINの組を他から割り込まれないよう、IO_APICSのロックを保持していること。
+    unsafe fn read(&self, reg: u32) -> u32 {
+        let (select, window) = self.register_addrs();
+        // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
+        let value = unsafe {
+            write_volatile(select as *mut u32, reg);
+            read_volatile(window as *const u32)
+        };
+        iotrace::mmio(Device::IoApic, select, 32, reg as u64, Direction::Write);
+        iotrace::mmio(Device::IoApic, window, 32, value as u64, Direction::Read);
+        value
+    }
+
+    /// I/O APICレジスタへの書き込み
+    ///
+    /// # Safety
+    /// `read` と同じ
+    unsafe fn write(&self, reg: u32, value: u32) {
+        let (select, window) = self.register_addrs();
+        // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
+        unsafe {
+            write_volatile(select as *mut u32, reg);
+            write_volatile(window as *mut u32, value);
+        }
+        iotrace::mmio(Device::IoApic, select, 32, reg as u64, Direction::Write);
+        iotrace::mmio(Device::IoApic, window, 32, value as u64, Direction::Write);
+    }
+
+    /// IOREGSELとIOWINの仮想アドレス
+    fn register_addrs(&self) -> (u64, u64) {
+        (
+            self.virt_base + registers::IOREGSEL,
+            self.virt_base + registers::IOWIN,
+        )
+    }
+
+    /// リダイレクションテーブルエントリを書き込み
+    ///
+    /// # Safety
+    /// `read` と同じ。indexがentry_count未満であること
+    unsafe fn write_redirection(&self, index: u32, raw: u64) {
+        let reg = registers::REDIRECTION_TABLE + index * 2;
+        // SAFETY: 呼び出し元が上記の安全性要件を満たすことを保証する。
+        // 書き込み途中で誤配送しないよう、マスクビットを含む下位を先にマスク状態にしてから
+        // 上位（宛先）、最後に下位を書き込む。
+        unsafe {
+            self.write(reg, (1 << 16) as u32);
+            self.write(reg + 1, (raw >> 32) as u32);
+            self.write(reg, raw as u32);
+        }
+    }
+}
+
+/// Interrupt Source Override（ISA IRQ → GSIの対応）
+#[derive(Debug, Clone, Copy)]
+struct SourceOverride {
+    /// 割り当て先のGSI
+    gsi: u32,
+    /// MPS INTI flags（bits 0-1: 極性, bits 2-3: トリガーモード）
+    flags: u16,
+}
+
+/// I/O APICの状態
+struct IoApicState {
+    io_apics: [Option<IoApic>; MAX_IO_APICS],
+    overrides: [Option<SourceOverride>; ISA_IRQ_COUNT],
+}
+
+/// 登録済みI/O APIC（ヒープ初期化前のACPI解析から登録されるため固定長配列で保持）
+static IO_APICS: Mutex<IoApicState> = Mutex::new(IoApicState {
+    io_apics: [None; MAX_IO_APICS],
+    overrides: [None; ISA_IRQ_COUNT],
+});
+
+/// I/O APICを登録（MADT解析から呼ばれる）
+///
+/// MMIO領域のマップはinit()で行います。
+///
+/// # Errors
+/// * `IoApicError::TooManyIoApics` - 登録数が上限を超えた場合
+pub fn register(id: u8, phys_base: u64, gsi_base: u32) -> Result<(), IoApicError> {
+    without_interrupts(|| {
+        let mut state = IO_APICS.lock();
+        let slot = state
+            .io_apics
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(IoApicError::TooManyIoApics)?;
+        *slot = Some(IoApic {
+            id,
+            phys_base,
+            virt_base: 0,
+            gsi_base,
+            entry_count: 0,
+        });
+        Ok(())
+    })
+}
+
+/// Interrupt Source Overrideを登録（MADT解析から呼ばれる）
+///
+/// # Arguments
+/// * `source_irq` - ISA IRQ番号
+/// * `gsi` - 割り当て先のGSI
+/// * `flags` - MPS INTI flags
+pub fn register_override(source_irq: u8, gsi: u32, flags: u16) {
+    if (source_irq as usize) < ISA_IRQ_COUNT {
+        without_interrupts(|| {
+            IO_APICS.lock().overrides[source_irq as usize] = Some(SourceOverride { gsi, flags });
+        });
+    }
+}
+
+/// I/O APICのレジスタ領域（IOREGSEL〜IOWIN）の大きさ
+const MMIO_SIZE: u64 = 0x20;
+
+/// 登録済みのI/O APICを初期化
+///
+/// MMIO領域をマップし、全てのリダイレクションエントリをマスクします。
+/// Local APIC初期化後に呼び出します。
+///
+/// # Errors
+/// * `IoApicError::NotFound` - I/O APICが登録されていない場合
+/// * `IoApicError::MapFailed` - MMIO領域のマッピングに失敗した場合
+pub fn init() -> Result<(), IoApicError> {
+    without_interrupts(|| {
+        let mut state = IO_APICS.lock();
+        let mut found = false;
+
+        for io_apic in state.io_apics.iter_mut().flatten() {
+            io_apic.virt_base =
+                paging::map_mmio(io_apic.phys_base, MMIO_SIZE, CacheMode::Uncacheable)
+                    .map_err(|_| IoApicError::MapFailed)?;
+
+            // SAFETY: virt_baseはマップ済みのI/O APIC MMIO領域を指しており、
+            // IO_APICSのロックを保持している。
+            unsafe {
+                let version = io_apic.read(registers::VERSION);
+                // bits 16-23: Maximum Redirection Entry（エントリ数 - 1）
+                io_apic.entry_count = ((version >> 16) & 0xFF) + 1;
+
+                for index in 0..io_apic.entry_count {
+                    io_apic.write_redirection(index, 1 << 16);
+                }
+
+                let hw_id = (io_apic.read(registers::ID) >> 24) & 0x0F;
+                info!(
+                    "I/O APIC {} (HW ID {}): GSI {}-{}, all entries masked",
+                    io_apic.id,
+                    hw_id,
+                    io_apic.gsi_base,
+                    io_apic.gsi_base + io_apic.entry_count - 1
+                );
+            }
+            found = true;
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(IoApicError::NotFound)
+        }
+    })
+}
+
+/// GSIのリダイレクションエントリを書き込み
+///
+/// # Errors
+/// * `IoApicError::InvalidVector` - ベクタが32未満の場合
+/// * `IoApicError::GsiOutOfRange` - GSIを担当するI/O APICがない場合
+pub fn set_redirection(gsi: u32, entry: RedirectionEntry) -> Result<(), IoApicError> {
+    if entry.vector < MIN_EXTERNAL_VECTOR {
+        return Err(IoApicError::InvalidVector);
+    }
+
+    without_interrupts(|| {
+        let state = IO_APICS.lock();
+        let io_apic = state
+            .io_apics
+            .iter()
+            .flatten()
+            .find(|io_apic| io_apic.handles(gsi))
+            .ok_or(IoApicError::GsiOutOfRange)?;
+
+        // SAFETY: handles()が真ならinit()済みでvirt_baseはマップ済み、
+        // indexはentry_count未満。IO_APICSのロックを保持している。
+        unsafe {
+            io_apic.write_redirection(gsi - io_apic.gsi_base, entry.to_raw());
+        }
+        Ok(())
+    })
+}
+
+/// GSIのマスク状態を変更
+///
+/// # Errors
+/// * `IoApicError::GsiOutOfRange` - GSIを担当するI/O APICがない場合
+#[allow(dead_code)]
+pub fn set_masked(gsi: u32, masked: bool) -> Result<(), IoApicError> {
+    without_interrupts(|| {
+        let state = IO_APICS.lock();
+        let io_apic = state
+            .io_apics
+            .iter()
+            .flatten()
+            .find(|io_apic| io_apic.handles(gsi))
+            .ok_or(IoApicError::GsiOutOfRange)?;
+
+        let reg = registers::REDIRECTION_TABLE + (gsi - io_apic.gsi_base) * 2;
+        // SAFETY: set_redirectionと同じ
+        unsafe {
+            let low = io_apic.read(reg);
+            let low = if masked {
+                low | (1 << 16)
+            } else {
+                low & !(1 << 16)
+            };
+            io_apic.write(reg, low);
+        }
+        Ok(())
+    })
+}
+
+/// GSIを指定したベクタへルーティング
+///
+/// エッジトリガー・High activeで、現在のCPUのLocal APICへ配送します。
+///
+/// # Errors
+/// `set_redirection` と同じ
+#[allow(dead_code)]
+pub fn route_irq(gsi: u32, vector: u8) -> Result<(), IoApicError> {
+    set_redirection(
+        gsi,
+        RedirectionEntry {
+            vector,
+            destination: apic::local_apic_id(),
+            trigger_mode: TriggerMode::Edge,
+            polarity: Polarity::ActiveHigh,
+            masked: false,
+        },
+    )
+}
+
+/// ISA IRQを指定したベクタへルーティング
+///
+/// Interrupt Source Overrideがあれば、そのGSI・極性・トリガーモードに従います。
+///
+/// # Arguments
+/// * `irq` - ISA IRQ番号（0〜15）
+/// * `vector` - 配送する割り込みベクタ
+///
+/// # Returns
+/// ルーティング先のGSI
+///
+/// # Errors
+/// `set_redirection` と同じ
+#[allow(dead_code)]
+pub fn route_isa_irq(irq: u8, vector: u8) -> Result<u32, IoApicError> {
+    // ISA IRQの既定はエッジトリガー・High active
+    route_isa_irq_with_defaults(irq, vector, Polarity::ActiveHigh, TriggerMode::Edge)
+}
+
+/// ISA IRQを、バス既定とは異なる極性・トリガーモードを既定としてルーティング
+///
+/// ACPIのSCIのように、Overrideがない場合の既定がレベルトリガー・Low activeである
+/// 割り込みに使用します。Overrideで指定されたフィールドはそちらが優先されます。
+///
+/// # Arguments
+/// * `irq` - ISA IRQ番号（0〜15）
+/// * `vector` - 配送する割り込みベクタ
+/// * `default_polarity` - Overrideが極性を指定しない場合の極性
+/// * `default_trigger` - Overrideがトリガーモードを指定しない場合のトリガーモード
+///
+/// # Returns
+/// ルーティング先のGSI
+///
+/// # Errors
+/// `set_redirection` と同じ
+pub fn route_isa_irq_with_defaults(
+    irq: u8,
+    vector: u8,
+    default_polarity: Polarity,
+    default_trigger: TriggerMode,
+) -> Result<u32, IoApicError> {
+    let source_override = without_interrupts(|| {
+        IO_APICS
+            .lock()
+            .overrides
+            .get(irq as usize)
+            .copied()
+            .flatten()
+    });
+
+    // Overrideがなければ既定の極性・トリガーモードを使用し、GSIはIRQ番号と同一
+    let (gsi, polarity, trigger_mode) = match source_override {
+        Some(o) => {
+            // INTI flags: 極性 01=High, 11=Low / トリガー 01=Edge, 11=Level（00はバス既定）
+            let polarity = match o.flags & 0x3 {
+                0x1 => Polarity::ActiveHigh,
+                0x3 => Polarity::ActiveLow,
+                _ => default_polarity,
+            };
+            let trigger_mode = match (o.flags >> 2) & 0x3 {
+                0x1 => TriggerMode::Edge,
+                0x3 => TriggerMode::Level,
+                _ => default_trigger,
+            };
+            (o.gsi, polarity, trigger_mode)
+        }
+        None => (irq as u32, default_polarity, default_trigger),
+    };
+
+    if gsi != irq as u32 {
+        info!("ISA IRQ {} is overridden to GSI {}", irq, gsi);
+    }
+
+    set_redirection(
+        gsi,
+        RedirectionEntry {
+            vector,
+            destination: apic::local_apic_id(),
+            trigger_mode,
+            polarity,
+            masked: false,
+        },
+    )?;
+    Ok(gsi)
+}
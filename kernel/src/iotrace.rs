@@ -0,0 +1,280 @@
+//! I/Oポート・MMIOアクセスのトレース
+//!
+//! ドライバの立ち上げ時に、レジスタへのアクセス列をデータシートや正常に動く環境のトレースと
+//! 比較できるよう、選択したデバイスへのアクセス（アドレス・幅・値・方向）をシリアルに出力します。
+//! シェルの `iotrace <device> on` で有効にします。
+//!
+//! I/Oポートは `io` モジュールのアクセス関数で、MMIOは各ドライバのレジスタアクセス関数で記録します。
+//! どのデバイスも有効でなければアトミック変数を1回読むだけで戻るため、通常時のコストはほぼありません。
+//!
+//! ログの出力自体がシリアルポートへのアクセスになるため、出力中のアクセスは記録しません。
+//! また、デバイスごとに1秒あたりの出力件数を制限し、超えた分は件数だけを報告します。
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use crate::{println, timer};
+
+/// デバイスごとの1秒あたりの最大出力件数
+const RATE_LIMIT_PER_SEC: u32 = 100;
+
+/// 実行時に登録できるI/Oポート範囲の数
+const MAX_DYNAMIC_RANGES: usize = 8;
+
+/// トレース対象のデバイス
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    /// COM1シリアルポート
+    Serial,
+    /// PS/2コントローラ（キーボード・マウス）
+    Ps2,
+    /// PCIコンフィギュレーション空間（I/OポートとMMCONFIG）
+    Pci,
+    /// IDE（ATA）プライマリチャネル
+    Ata,
+    /// virtio-blk（レガシーI/Oレジスタ）
+    VirtioBlk,
+    /// Local APIC
+    Apic,
+    /// I/O APIC
+    IoApic,
+    /// HPET
+    Hpet,
+}
+
+/// すべてのデバイス（表示順）
+pub const DEVICES: [Device; 8] = [
+    Device::Serial,
+    Device::Ps2,
+    Device::Pci,
+    Device::Ata,
+    Device::VirtioBlk,
+    Device::Apic,
+    Device::IoApic,
+    Device::Hpet,
+];
+
+impl Device {
+    /// シェルで指定する名前
+    pub fn name(self) -> &'static str {
+        match self {
+            Device::Serial => "serial",
+            Device::Ps2 => "ps2",
+            Device::Pci => "pci",
+            Device::Ata => "ata",
+            Device::VirtioBlk => "virtio-blk",
+            Device::Apic => "apic",
+            Device::IoApic => "ioapic",
+            Device::Hpet => "hpet",
+        }
+    }
+
+    /// 名前からデバイスを取得
+    pub fn from_name(name: &str) -> Option<Self> {
+        DEVICES.iter().copied().find(|d| d.name() == name)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn bit(self) -> u32 {
+        1 << self.index()
+    }
+
+    /// 固定のI/Oポートを持つデバイス
+    fn from_fixed_port(port: u16) -> Option<Self> {
+        match port {
+            0x3F8..=0x3FF => Some(Device::Serial),
+            0x60 | 0x64 => Some(Device::Ps2),
+            0xCF8..=0xCFF => Some(Device::Pci),
+            0x1F0..=0x1F7 | 0x3F6 => Some(Device::Ata),
+            _ => None,
+        }
+    }
+}
+
+/// アクセスの方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+/// アクセス先の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Space {
+    Port,
+    Mmio,
+}
+
+/// トレースの設定エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoTraceError {
+    /// 動的なI/Oポート範囲の登録数が上限に達している
+    TooManyRanges,
+}
+
+impl core::fmt::Display for IoTraceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IoTraceError::TooManyRanges => write!(f, "Too many I/O port ranges"),
+        }
+    }
+}
+
+/// トレースが有効なデバイスのビットマスク
+static ENABLED: AtomicU32 = AtomicU32::new(0);
+
+/// トレースの出力中か（出力によるシリアルアクセスを記録しない）
+static EMITTING: AtomicBool = AtomicBool::new(false);
+
+/// 実行時に登録したI/Oポート範囲（デバイス番号 << 32 | 先頭 << 16 | 長さ、長さ0は未使用）
+static DYNAMIC_RANGES: [AtomicU64; MAX_DYNAMIC_RANGES] =
+    [const { AtomicU64::new(0) }; MAX_DYNAMIC_RANGES];
+
+/// デバイスごとの出力制限の状態
+struct RateState {
+    /// 現在の1秒間の開始tick
+    window_start: AtomicU64,
+    /// 現在の1秒間に出力した件数
+    emitted: AtomicU32,
+    /// 出力を省略した件数
+    suppressed: AtomicU64,
+}
+
+static RATE: [RateState; DEVICES.len()] = [const {
+    RateState {
+        window_start: AtomicU64::new(0),
+        emitted: AtomicU32::new(0),
+        suppressed: AtomicU64::new(0),
+    }
+}; DEVICES.len()];
+
+/// デバイスのトレースを有効・無効にする
+pub fn set_enabled(device: Device, enabled: bool) {
+    if enabled {
+        let rate = &RATE[device.index()];
+        rate.window_start
+            .store(timer::current_tick(), Ordering::Relaxed);
+        rate.emitted.store(0, Ordering::Relaxed);
+        rate.suppressed.store(0, Ordering::Relaxed);
+        ENABLED.fetch_or(device.bit(), Ordering::Relaxed);
+    } else {
+        ENABLED.fetch_and(!device.bit(), Ordering::Relaxed);
+    }
+}
+
+/// デバイスのトレースが有効か
+pub fn is_enabled(device: Device) -> bool {
+    ENABLED.load(Ordering::Relaxed) & device.bit() != 0
+}
+
+/// BARなど実行時に決まるI/Oポート範囲をデバイスに対応付ける
+///
+/// # Errors
+/// * `IoTraceError::TooManyRanges` - 登録数が上限に達している場合
+pub fn register_ports(device: Device, base: u16, len: u16) -> Result<(), IoTraceError> {
+    let packed = (device.index() as u64) << 32 | (base as u64) << 16 | len as u64;
+    DYNAMIC_RANGES
+        .iter()
+        .find(|slot| {
+            slot.compare_exchange(0, packed, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        })
+        .map(|_| ())
+        .ok_or(IoTraceError::TooManyRanges)
+}
+
+/// I/Oポートに対応するデバイス
+fn device_for_port(port: u16) -> Option<Device> {
+    Device::from_fixed_port(port).or_else(|| {
+        DYNAMIC_RANGES.iter().find_map(|slot| {
+            let packed = slot.load(Ordering::Relaxed);
+            let base = (packed >> 16) as u16;
+            let len = packed as u16;
+            (len != 0 && port >= base && port - base < len)
+                .then(|| DEVICES[(packed >> 32) as usize])
+        })
+    })
+}
+
+/// I/Oポートへのアクセスを記録
+#[inline]
+pub fn port(port: u16, width: u8, value: u32, direction: Direction) {
+    if ENABLED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Some(device) = device_for_port(port) {
+        emit(
+            device,
+            Space::Port,
+            port as u64,
+            width,
+            value as u64,
+            direction,
+        );
+    }
+}
+
+/// MMIOレジスタへのアクセスを記録
+#[inline]
+pub fn mmio(device: Device, addr: u64, width: u8, value: u64, direction: Direction) {
+    if ENABLED.load(Ordering::Relaxed) & device.bit() == 0 {
+        return;
+    }
+    emit(device, Space::Mmio, addr, width, value, direction);
+}
+
+/// 出力制限を確認してアクセスを1行出力
+#[cold]
+fn emit(device: Device, space: Space, addr: u64, width: u8, value: u64, direction: Direction) {
+    if !is_enabled(device) || EMITTING.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    let rate = &RATE[device.index()];
+    let now = timer::current_tick();
+    let window = timer::frequency_hz().max(1);
+    if now.wrapping_sub(rate.window_start.load(Ordering::Relaxed)) >= window {
+        rate.window_start.store(now, Ordering::Relaxed);
+        rate.emitted.store(0, Ordering::Relaxed);
+        let suppressed = rate.suppressed.swap(0, Ordering::Relaxed);
+        if suppressed > 0 {
+            println!(
+                "[iotrace] {}: {} accesses suppressed",
+                device.name(),
+                suppressed
+            );
+        }
+    }
+
+    if rate.emitted.fetch_add(1, Ordering::Relaxed) < RATE_LIMIT_PER_SEC {
+        let dir = match direction {
+            Direction::Read => 'R',
+            Direction::Write => 'W',
+        };
+        let digits = width as usize / 4;
+        match space {
+            Space::Port => println!(
+                "[iotrace] {:<10} {}{:<2} port 0x{:04X} = 0x{:0digits$X}",
+                device.name(),
+                dir,
+                width,
+                addr,
+                value
+            ),
+            Space::Mmio => println!(
+                "[iotrace] {:<10} {}{:<2} mmio 0x{:016X} = 0x{:0digits$X}",
+                device.name(),
+                dir,
+                width,
+                addr,
+                value
+            ),
+        }
+    } else {
+        rate.suppressed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    EMITTING.store(false, Ordering::Release);
+}
@@ -0,0 +1,309 @@
+//! 汎用の割り込み（IRQ）管理
+//!
+//! 外部割り込みとIPIのベクタごとにハンドラを登録する仕組みです。ドライバは
+//! `request_irq` でハンドラを登録するだけで割り込みを受け取れます。
+//!
+//! 管理するベクタ（`FIRST_VECTOR`〜`LAST_VECTOR`、システムコールを除く）のIDTエントリは
+//! すべて `irq_entry_table` の入口を指します。各入口はベクタ番号を積んで共通の入口へ
+//! ジャンプし、共通の入口がレジスタを保存して `dispatch` を呼び出します。
+//!
+//! 1つのベクタに `MAX_SHARED` 個までのハンドラを登録でき（レベルトリガーの共有など）、
+//! 割り込みのたびに登録順にすべて呼び出します。ベクタごとに割り込み回数・最後の時刻・
+//! どのハンドラも処理しなかった回数を記録します。
+
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::apic;
+use crate::io::without_interrupts;
+use crate::percpu;
+
+/// 管理する最初のベクタ（タイマー割り込みの次）
+pub const FIRST_VECTOR: u8 = apic::TIMER_INTERRUPT_VECTOR + 1;
+
+/// 管理する最後のベクタ（0xFFはLocal APICのスプリアス割り込み）
+pub const LAST_VECTOR: u8 = 0xFE;
+
+/// 管理するベクタの数
+const VECTOR_COUNT: usize = (LAST_VECTOR - FIRST_VECTOR) as usize + 1;
+
+/// 入口1つあたりのバイト数（`irq_entry_table` の `.balign` と合わせる）
+const ENTRY_STRIDE: usize = 16;
+
+/// 1つのベクタに登録できるハンドラの数
+pub const MAX_SHARED: usize = 4;
+
+/// ハンドラの戻り値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// このハンドラのデバイスの割り込みだった
+    Handled,
+    /// このハンドラのデバイスの割り込みではなかった（共有ベクタ）
+    Unhandled,
+}
+
+/// 割り込みハンドラ
+///
+/// 割り込み無効の状態で呼び出されます。EOIは `dispatch` が送信するため不要です。
+pub type IrqHandler = fn() -> IrqReturn;
+
+/// IRQ操作のエラー型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// 管理対象外のベクタ（例外、タイマー、システムコールなど）
+    InvalidVector,
+    /// ベクタに登録できるハンドラの数を超えた
+    Busy,
+    /// 同じ名前のハンドラが登録済み
+    AlreadyRegistered,
+    /// その名前のハンドラは登録されていない
+    NotRegistered,
+}
+
+impl core::fmt::Display for IrqError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IrqError::InvalidVector => write!(f, "Vector is not managed by the IRQ layer"),
+            IrqError::Busy => write!(f, "Too many handlers on this vector"),
+            IrqError::AlreadyRegistered => write!(f, "Handler already registered"),
+            IrqError::NotRegistered => write!(f, "Handler not registered"),
+        }
+    }
+}
+
+/// 登録されたハンドラ
+#[derive(Clone, Copy)]
+struct Action {
+    handler: IrqHandler,
+    name: &'static str,
+}
+
+/// 1つのベクタの状態
+struct Line {
+    actions: Mutex<[Option<Action>; MAX_SHARED]>,
+    /// 割り込み回数
+    count: AtomicU64,
+    /// どのハンドラも処理しなかった回数
+    unhandled: AtomicU64,
+    /// 最後の割り込みの時刻（起動からのナノ秒）
+    last_ns: AtomicU64,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            actions: Mutex::new([None; MAX_SHARED]),
+            count: AtomicU64::new(0),
+            unhandled: AtomicU64::new(0),
+            last_ns: AtomicU64::new(0),
+        }
+    }
+}
+
+static LINES: [Line; VECTOR_COUNT] = [const { Line::new() }; VECTOR_COUNT];
+
+/// ベクタの状態（管理対象外ならNone）
+fn line(vector: u8) -> Option<&'static Line> {
+    if vector < FIRST_VECTOR || vector == crate::syscall::INTERRUPT_VECTOR {
+        return None;
+    }
+    LINES.get((vector - FIRST_VECTOR) as usize)
+}
+
+// ベクタごとの入口
+//
+// 各入口は `ENTRY_STRIDE` バイト境界に置き、ベクタ番号を積んで共通の入口へジャンプする。
+// `pushq $imm` と `jmp rel32` は合わせて10バイト以下のため、入口は等間隔に並ぶ。
+global_asm!(
+    ".pushsection .text.irq_entry, \"ax\"",
+    ".global irq_entry_table",
+    ".balign 16",
+    "irq_entry_table:",
+    ".set irq_vector, {first}",
+    ".rept {count}",
+    "    .balign 16",
+    "    pushq $irq_vector",
+    "    jmp {common}",
+    "    .set irq_vector, irq_vector + 1",
+    ".endr",
+    ".popsection",
+    first = const FIRST_VECTOR,
+    count = const VECTOR_COUNT,
+    common = sym irq_common_entry,
+    options(att_syntax)
+);
+
+unsafe extern "C" {
+    // global_asm!で定義した入口の先頭
+    static irq_entry_table: u8;
+}
+
+/// 共通の入口
+///
+/// caller-savedレジスタを保存し、入口が積んだベクタ番号をRDIに渡して `dispatch` を呼び出します。
+/// 他の外部割り込みハンドラと同様に、割り込み復帰時のスケジューリングチェックも行います。
+#[unsafe(naked)]
+extern "C" fn irq_common_entry() {
+    core::arch::naked_asm!(
+        // Ring 3から入った場合はカーネルのGSベースに切り替える（CSはベクタ番号・RIPの上）
+        percpu::swapgs_if_user!("[rsp + 16]"),
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        // ベクタ番号は保存した9レジスタの上にある
+        "mov rdi, [rsp + 72]",
+        // CPUが積んだ5ワード・ベクタ番号・9レジスタの計15ワードのため、
+        // 8バイトずらしてcall時に16バイト境界に揃える
+        "sub rsp, 8",
+        "call {dispatch}",
+        "call {check_resched}",
+        "add rsp, 8",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        // ベクタ番号を取り除く
+        "add rsp, 8",
+        percpu::swapgs_if_user!("[rsp + 8]"),
+        "iretq",
+        dispatch = sym dispatch,
+        check_resched = sym crate::idt::check_resched_on_interrupt_exit_wrapper,
+    )
+}
+
+/// 登録されたハンドラを呼び出してEOIを送信
+extern "C" fn dispatch(vector: u64) {
+    if let Some(line) = u8::try_from(vector).ok().and_then(line) {
+        line.count.fetch_add(1, Ordering::Relaxed);
+        line.last_ns
+            .store(crate::clock::monotonic_ns(), Ordering::Relaxed);
+        // 他のCPUで同じベクタを処理できるよう、ロックはコピーする間だけ保持する
+        let actions = *line.actions.lock();
+        let mut handled = false;
+        for action in actions.iter().flatten() {
+            handled |= (action.handler)() == IrqReturn::Handled;
+        }
+        if !handled {
+            line.unhandled.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    apic::send_eoi();
+}
+
+/// ベクタの入口のアドレス
+///
+/// IDTの初期化時に、管理するベクタのエントリを設定するために使います。
+///
+/// # Returns
+/// 入口のアドレス。管理対象外のベクタの場合はNone
+pub fn entry_point(vector: u8) -> Option<usize> {
+    line(vector)?;
+    let table = &raw const irq_entry_table as usize;
+    Some(table + (vector - FIRST_VECTOR) as usize * ENTRY_STRIDE)
+}
+
+/// ベクタにハンドラを登録
+///
+/// 登録済みのハンドラがあるベクタには共有として追加します。
+/// ハンドラの中から呼び出さないでください。
+///
+/// # Arguments
+/// * `vector` - 割り込みベクタ
+/// * `handler` - ハンドラ
+/// * `name` - ハンドラ名（統計の表示と `free_irq` に使う）
+///
+/// # Errors
+/// * `IrqError::InvalidVector` - 管理対象外のベクタの場合
+/// * `IrqError::AlreadyRegistered` - 同じ名前のハンドラが登録済みの場合
+/// * `IrqError::Busy` - ベクタに `MAX_SHARED` 個のハンドラが登録済みの場合
+pub fn request_irq(vector: u8, handler: IrqHandler, name: &'static str) -> Result<(), IrqError> {
+    let line = line(vector).ok_or(IrqError::InvalidVector)?;
+    // 割り込みハンドラと同じCPUでロックを奪い合わないよう割り込みを無効化する
+    without_interrupts(|| {
+        let mut actions = line.actions.lock();
+        if actions.iter().flatten().any(|a| a.name == name) {
+            return Err(IrqError::AlreadyRegistered);
+        }
+        let slot = actions
+            .iter_mut()
+            .find(|a| a.is_none())
+            .ok_or(IrqError::Busy)?;
+        *slot = Some(Action { handler, name });
+        Ok(())
+    })
+}
+
+/// ベクタからハンドラを登録解除
+///
+/// 戻った時点で他のCPUが実行中の割り込みでは、まだハンドラが呼ばれることがあります。
+///
+/// # Errors
+/// * `IrqError::InvalidVector` - 管理対象外のベクタの場合
+/// * `IrqError::NotRegistered` - その名前のハンドラが登録されていない場合
+pub fn free_irq(vector: u8, name: &str) -> Result<(), IrqError> {
+    let line = line(vector).ok_or(IrqError::InvalidVector)?;
+    without_interrupts(|| {
+        let mut actions = line.actions.lock();
+        let slot = actions
+            .iter_mut()
+            .find(|a| a.is_some_and(|a| a.name == name))
+            .ok_or(IrqError::NotRegistered)?;
+        *slot = None;
+        Ok(())
+    })
+}
+
+/// ベクタの統計
+#[derive(Debug, Clone)]
+pub struct IrqStats {
+    pub vector: u8,
+    /// 割り込み回数
+    pub count: u64,
+    /// どのハンドラも処理しなかった回数
+    pub unhandled: u64,
+    /// 最後の割り込みの時刻（起動からのナノ秒、未発生なら0）
+    pub last_ns: u64,
+    /// 登録されているハンドラ名
+    pub handlers: Vec<&'static str>,
+}
+
+/// ハンドラが登録されているか、割り込みが発生したベクタの統計を取得
+pub fn stats() -> Vec<IrqStats> {
+    (FIRST_VECTOR..=LAST_VECTOR)
+        .filter_map(|vector| {
+            let line = line(vector)?;
+            let handlers: Vec<&'static str> = without_interrupts(|| {
+                line.actions
+                    .lock()
+                    .iter()
+                    .flatten()
+                    .map(|a| a.name)
+                    .collect()
+            });
+            let count = line.count.load(Ordering::Relaxed);
+            if handlers.is_empty() && count == 0 {
+                return None;
+            }
+            Some(IrqStats {
+                vector,
+                count,
+                unhandled: line.unhandled.load(Ordering::Relaxed),
+                last_ns: line.last_ns.load(Ordering::Relaxed),
+                handlers,
+            })
+        })
+        .collect()
+}
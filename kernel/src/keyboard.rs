@@ -0,0 +1,250 @@
+//! PS/2キーボードドライバ
+//!
+//! I/O APIC経由でIRQ1を受け取り、スキャンコードセット1をASCIIに変換して
+//! 入力バッファに格納します。SysRqの組み合わせはバッファ格納より前、
+//! 割り込みハンドラの先頭で処理するため、シェルやコンポジタが停止していても使用できます。
+//! Ctrl+Alt+←/→ はワークスペースの切り替えとして同じく割り込みハンドラで処理します。
+
+use spin::Mutex;
+
+use crate::io::{port_read_u8, without_interrupts};
+use crate::irq::{self, IrqReturn};
+use crate::{info, ioapic, mouse, sysrq, warn, workqueue};
+
+/// キーボード割り込みのベクタ番号
+pub const INTERRUPT_VECTOR: u8 = 33;
+
+/// キーボードのISA IRQ番号
+const KEYBOARD_IRQ: u8 = 1;
+
+/// PS/2データポート
+const DATA_PORT: u16 = 0x60;
+
+/// PS/2ステータスポート
+const STATUS_PORT: u16 = 0x64;
+
+/// ステータスレジスタ: 出力バッファにデータあり
+const STATUS_OUTPUT_FULL: u8 = 0x01;
+
+/// 入力バッファのサイズ
+const INPUT_BUFFER_SIZE: usize = 128;
+
+/// スキャンコード（セット1）
+mod scancode {
+    /// 拡張キーのプレフィックス
+    pub const EXTENDED: u8 = 0xE0;
+    /// キーリリース時に立つビット
+    pub const RELEASE: u8 = 0x80;
+    pub const LEFT_CTRL: u8 = 0x1D;
+    pub const LEFT_SHIFT: u8 = 0x2A;
+    pub const RIGHT_SHIFT: u8 = 0x36;
+    pub const LEFT_ALT: u8 = 0x38;
+    /// Alt+PrtScで送られるSysRqキー
+    pub const SYSRQ: u8 = 0x54;
+    /// PrtSc（0xE0プレフィックス付き）
+    pub const PRINT_SCREEN: u8 = 0x37;
+    /// ←キー（0xE0プレフィックス付き）
+    pub const LEFT_ARROW: u8 = 0x4B;
+    /// →キー（0xE0プレフィックス付き）
+    pub const RIGHT_ARROW: u8 = 0x4D;
+}
+
+/// 入力バッファに格納せず、割り込みハンドラで実行するキー操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyAction {
+    /// SysRqコマンド（Ctrl+Alt+PrtScの後に押されたキー、小文字）
+    SysRq(u8),
+    /// 隣のワークスペースへの切り替え（trueなら次）
+    SwitchWorkspace(bool),
+}
+
+/// スキャンコード → ASCII（シフトなし）
+const SCANCODE_MAP: [u8; 58] = [
+    0, 0x1B, b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'0', b'-', b'=', 0x08, b'\t',
+    b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', b'o', b'p', b'[', b']', b'\n', 0, b'a', b's',
+    b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', b'\'', b'`', 0, b'\\', b'z', b'x', b'c', b'v',
+    b'b', b'n', b'm', b',', b'.', b'/', 0, b'*', 0, b' ',
+];
+
+/// スキャンコード → ASCII（シフトあり）
+const SCANCODE_MAP_SHIFT: [u8; 58] = [
+    0, 0x1B, b'!', b'@', b'#', b'$', b'%', b'^', b'&', b'*', b'(', b')', b'_', b'+', 0x08, b'\t',
+    b'Q', b'W', b'E', b'R', b'T', b'Y', b'U', b'I', b'O', b'P', b'{', b'}', b'\n', 0, b'A', b'S',
+    b'D', b'F', b'G', b'H', b'J', b'K', b'L', b':', b'"', b'~', 0, b'|', b'Z', b'X', b'C', b'V',
+    b'B', b'N', b'M', b'<', b'>', b'?', 0, b'*', 0, b' ',
+];
+
+/// キーボードの状態（割り込みハンドラからのみ更新）
+struct KeyboardState {
+    /// 直前に0xE0プレフィックスを受信したか
+    extended: bool,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    /// SysRqの組み合わせが押され、次のキーをSysRqコマンドとして扱うか
+    sysrq_armed: bool,
+    /// 入力バッファ（リングバッファ）
+    buffer: [u8; INPUT_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+static KEYBOARD: Mutex<KeyboardState> = Mutex::new(KeyboardState {
+    extended: false,
+    ctrl: false,
+    alt: false,
+    shift: false,
+    sysrq_armed: false,
+    buffer: [0; INPUT_BUFFER_SIZE],
+    head: 0,
+    len: 0,
+});
+
+impl KeyboardState {
+    /// 入力バッファに1文字追加（満杯の場合は破棄）
+    fn push(&mut self, byte: u8) {
+        if self.len < INPUT_BUFFER_SIZE {
+            let tail = (self.head + self.len) % INPUT_BUFFER_SIZE;
+            self.buffer[tail] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// 入力バッファから1文字取り出し
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % INPUT_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// スキャンコードを処理
+    ///
+    /// # Returns
+    /// 割り込みハンドラで実行すべきキー操作
+    fn process(&mut self, code: u8) -> Option<KeyAction> {
+        if code == scancode::EXTENDED {
+            self.extended = true;
+            return None;
+        }
+        let extended = core::mem::take(&mut self.extended);
+        let released = code & scancode::RELEASE != 0;
+        let key = code & !scancode::RELEASE;
+
+        // 修飾キー（右Ctrl/右Altは0xE0付きで同じコード）
+        match key {
+            scancode::LEFT_CTRL => {
+                self.ctrl = !released;
+                return None;
+            }
+            scancode::LEFT_ALT => {
+                self.alt = !released;
+                return None;
+            }
+            scancode::LEFT_SHIFT | scancode::RIGHT_SHIFT if !extended => {
+                self.shift = !released;
+                return None;
+            }
+            _ => {}
+        }
+        if released {
+            return None;
+        }
+
+        // Ctrl+Alt+PrtSc（Alt押下中はSysRqコードとして送られることがある）
+        let is_sysrq_key = key == scancode::SYSRQ || (extended && key == scancode::PRINT_SCREEN);
+        if is_sysrq_key && self.ctrl && self.alt {
+            self.sysrq_armed = true;
+            return None;
+        }
+        if extended {
+            return match key {
+                scancode::LEFT_ARROW if self.ctrl && self.alt => {
+                    Some(KeyAction::SwitchWorkspace(false))
+                }
+                scancode::RIGHT_ARROW if self.ctrl && self.alt => {
+                    Some(KeyAction::SwitchWorkspace(true))
+                }
+                _ => None,
+            };
+        }
+
+        let map = if self.shift {
+            &SCANCODE_MAP_SHIFT
+        } else {
+            &SCANCODE_MAP
+        };
+        let ascii = map.get(key as usize).copied().unwrap_or(0);
+        if ascii == 0 {
+            return None;
+        }
+
+        if self.sysrq_armed {
+            self.sysrq_armed = false;
+            return Some(KeyAction::SysRq(ascii.to_ascii_lowercase()));
+        }
+        self.push(ascii);
+        None
+    }
+}
+
+/// キーボード割り込みハンドラ（`irq` から呼ばれる）
+fn handle_interrupt() -> IrqReturn {
+    // SAFETY: 0x64/0x60はPS/2コントローラの標準ポート
+    let code = unsafe {
+        let status = port_read_u8(STATUS_PORT);
+        // マウスのデータはIRQ12側で読み取る
+        if status & STATUS_OUTPUT_FULL == 0 || mouse::is_aux_data(status) {
+            return IrqReturn::Unhandled;
+        }
+        port_read_u8(DATA_PORT)
+    };
+
+    // 割り込みハンドラ内（割り込み無効）なのでそのままロックを取得できる
+    let action = KEYBOARD.lock().process(code);
+
+    // ロック解放後に実行（SysRqコマンドがパニックや再起動をしてもロックが残らないように）
+    match action {
+        Some(KeyAction::SysRq(key)) => sysrq::handle(key),
+        Some(KeyAction::SwitchWorkspace(forward)) => {
+            // 画面全体の再合成を伴うため、割り込みハンドラの外で行う
+            let switch = move || crate::graphics::compositor::switch_workspace_adjacent(forward);
+            if workqueue::queue_work(switch).is_err() {
+                switch();
+            }
+        }
+        None => {}
+    }
+    IrqReturn::Handled
+}
+
+/// 入力バッファから1文字取り出す（ノンブロッキング）
+#[allow(dead_code)]
+pub fn read_char() -> Option<u8> {
+    without_interrupts(|| KEYBOARD.lock().pop())
+}
+
+/// キーボードを初期化してIRQ1をルーティング
+///
+/// I/O APIC初期化後に呼び出します。
+pub fn init() {
+    // 初期化前に溜まっているデータを読み捨てる
+    // SAFETY: 0x64/0x60はPS/2コントローラの標準ポート
+    unsafe {
+        while port_read_u8(STATUS_PORT) & STATUS_OUTPUT_FULL != 0 {
+            port_read_u8(DATA_PORT);
+        }
+    }
+
+    if let Err(e) = irq::request_irq(INTERRUPT_VECTOR, handle_interrupt, "keyboard") {
+        warn!("PS/2 keyboard IRQ handler not registered: {}", e);
+        return;
+    }
+    match ioapic::route_isa_irq(KEYBOARD_IRQ, INTERRUPT_VECTOR) {
+        Ok(gsi) => info!("PS/2 keyboard initialized (GSI {})", gsi),
+        Err(e) => warn!("PS/2 keyboard IRQ not routed: {}", e),
+    }
+}
@@ -0,0 +1,344 @@
+//! カーネルログのリングバッファ（klog）
+//!
+//! `log` の各メッセージをバイト列のリングバッファに保持し、シリアルが接続されていなくても
+//! 後から `dmesg` やパニック時のミニダンプで読めるようにします。
+//!
+//! # 設計
+//! - ヒープの初期化前は静的な小さい領域（`EARLY_CAPACITY`）に記録し、`init` でヒープ上の
+//!   指定サイズの領域へ内容ごと移す（起動直後のメッセージも失われない）
+//! - 可変長のレコードを詰めて格納し、空きが足りなければ古いレコードから捨てる
+//! - `IrqSpinlock` で保護し、割り込みハンドラからも記録できる
+//! - 読み出しは1レコードずつロックを取り直すため、長い `dmesg` の表示中も記録を妨げない
+//!
+//! # レコードの形式
+//! `[全体長 u16][連番 u64][時刻 u64][レベル u8][モジュール名長 u8][本文長 u16][モジュール名][本文]`
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::log::Level;
+use crate::sync::IrqSpinlock;
+
+/// ヒープの初期化前に使う領域のサイズ（バイト）
+const EARLY_CAPACITY: usize = 8 * 1024;
+
+/// `init` で指定できるサイズの上限（バイト）
+const MAX_CAPACITY: usize = 1024 * 1024;
+
+/// 1レコードに保持する本文の最大長（超えた分は記録側で切り捨てる）
+pub const MAX_TEXT: usize = 256;
+
+/// モジュール名の最大長
+const MAX_MODULE: usize = u8::MAX as usize;
+
+/// レコードのヘッダ長
+const HEADER_LEN: usize = 2 + 8 + 8 + 1 + 1 + 2;
+
+/// 保持しているメッセージ
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// 連番（欠番は捨てられたメッセージ）
+    pub seq: u64,
+    /// 単調時刻（ナノ秒）
+    pub time_ns: u64,
+    pub level: Level,
+    pub module: String,
+    pub text: String,
+}
+
+/// ヒープを使わずに読み出したメッセージ（`for_each_recent` 用）
+#[allow(dead_code)]
+pub struct EntryRef<'a> {
+    pub seq: u64,
+    pub time_ns: u64,
+    pub level: Level,
+    pub module: &'a str,
+    pub text: &'a str,
+}
+
+/// リングバッファの統計情報
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct KlogStats {
+    /// バッファのサイズ（バイト）
+    pub capacity: usize,
+    /// 使用中のバイト数
+    pub used: usize,
+    /// 保持しているメッセージ数
+    pub records: usize,
+    /// 空きを作るために捨てたメッセージ数
+    pub evicted: u64,
+}
+
+/// ヘッダの内容
+struct Header {
+    total_len: usize,
+    seq: u64,
+    time_ns: u64,
+    level: Level,
+    module_len: usize,
+    text_len: usize,
+}
+
+struct Ring {
+    /// ヒープの初期化前に使う領域
+    early: [u8; EARLY_CAPACITY],
+    /// `init` で確保した領域
+    heap: Option<Vec<u8>>,
+    /// 最も古いレコードの先頭（通算のバイト位置）
+    head: u64,
+    /// 次に書き込む位置（通算のバイト位置）
+    tail: u64,
+    /// 保持しているレコード数
+    records: usize,
+    /// 捨てたレコード数
+    evicted: u64,
+}
+
+static RING: IrqSpinlock<Ring> = IrqSpinlock::new(Ring {
+    early: [0; EARLY_CAPACITY],
+    heap: None,
+    head: 0,
+    tail: 0,
+    records: 0,
+    evicted: 0,
+});
+
+impl Ring {
+    fn buf(&self) -> &[u8] {
+        self.heap.as_deref().unwrap_or(&self.early)
+    }
+
+    fn buf_mut(&mut self) -> &mut [u8] {
+        match &mut self.heap {
+            Some(heap) => heap,
+            None => &mut self.early,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.buf().len()
+    }
+
+    /// 通算位置 `pos` からバイト列を書き込む（末尾で折り返す）
+    fn write_at(&mut self, pos: u64, bytes: &[u8]) {
+        let buf = self.buf_mut();
+        let cap = buf.len();
+        let start = (pos % cap as u64) as usize;
+        let first = bytes.len().min(cap - start);
+        buf[start..start + first].copy_from_slice(&bytes[..first]);
+        buf[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+    }
+
+    /// 通算位置 `pos` からバイト列を読み出す（末尾で折り返す）
+    fn read_at(&self, pos: u64, out: &mut [u8]) {
+        let buf = self.buf();
+        let cap = buf.len();
+        let start = (pos % cap as u64) as usize;
+        let first = out.len().min(cap - start);
+        out[..first].copy_from_slice(&buf[start..start + first]);
+        let rest = out.len() - first;
+        out[first..].copy_from_slice(&buf[..rest]);
+    }
+
+    fn read_header(&self, pos: u64) -> Header {
+        let mut raw = [0u8; HEADER_LEN];
+        self.read_at(pos, &mut raw);
+        Header {
+            total_len: u16::from_le_bytes([raw[0], raw[1]]) as usize,
+            seq: u64::from_le_bytes(raw[2..10].try_into().unwrap_or_default()),
+            time_ns: u64::from_le_bytes(raw[10..18].try_into().unwrap_or_default()),
+            level: Level::from_u8(raw[18]),
+            module_len: raw[19] as usize,
+            text_len: u16::from_le_bytes([raw[20], raw[21]]) as usize,
+        }
+    }
+
+    /// 最も古いレコードを捨てる
+    fn evict(&mut self) {
+        let header = self.read_header(self.head);
+        self.head += header.total_len as u64;
+        self.records -= 1;
+        self.evicted += 1;
+    }
+
+    fn push(&mut self, seq: u64, time_ns: u64, level: Level, module: &str, text: &str) {
+        let module = &module.as_bytes()[..module.len().min(MAX_MODULE)];
+        let text = &text.as_bytes()[..text.len().min(MAX_TEXT)];
+        let total_len = HEADER_LEN + module.len() + text.len();
+        while self.capacity() - ((self.tail - self.head) as usize) < total_len {
+            self.evict();
+        }
+
+        let mut header = [0u8; HEADER_LEN];
+        header[0..2].copy_from_slice(&(total_len as u16).to_le_bytes());
+        header[2..10].copy_from_slice(&seq.to_le_bytes());
+        header[10..18].copy_from_slice(&time_ns.to_le_bytes());
+        header[18] = level as u8;
+        header[19] = module.len() as u8;
+        header[20..22].copy_from_slice(&(text.len() as u16).to_le_bytes());
+        let pos = self.tail;
+        self.write_at(pos, &header);
+        self.write_at(pos + HEADER_LEN as u64, module);
+        self.write_at(pos + (HEADER_LEN + module.len()) as u64, text);
+        self.tail += total_len as u64;
+        self.records += 1;
+    }
+
+    /// 通算位置 `pos` のレコードを読み出す
+    ///
+    /// # Returns
+    /// (ヘッダ, モジュール名, 本文)。本文は文字境界で切り捨てて記録しているためUTF-8として有効
+    fn read<'a>(
+        &self,
+        pos: u64,
+        module_buf: &'a mut [u8; MAX_MODULE],
+        text_buf: &'a mut [u8; MAX_TEXT],
+    ) -> (Header, &'a str, &'a str) {
+        let header = self.read_header(pos);
+        let module = &mut module_buf[..header.module_len];
+        self.read_at(pos + HEADER_LEN as u64, module);
+        let text = &mut text_buf[..header.text_len];
+        self.read_at(pos + (HEADER_LEN + header.module_len) as u64, text);
+        (
+            header,
+            core::str::from_utf8(module).unwrap_or("?"),
+            core::str::from_utf8(text).unwrap_or("?"),
+        )
+    }
+}
+
+/// メッセージを記録（`log` から呼ばれる）
+///
+/// `text` は `MAX_TEXT` バイト以下で、文字境界で切り捨て済みであること。
+pub(crate) fn push(seq: u64, time_ns: u64, level: Level, module: &str, text: &str) {
+    RING.lock().push(seq, time_ns, level, module, text);
+}
+
+/// ヒープ上にリングバッファを確保し、それまでの記録を移す
+///
+/// ヒープの初期化後に一度だけ呼び出します。
+///
+/// # Arguments
+/// * `capacity` - バッファのサイズ（バイト）。起動直後の領域より小さい値や上限を超える値は丸める
+pub fn init(capacity: usize) {
+    let capacity = capacity.clamp(EARLY_CAPACITY, MAX_CAPACITY);
+    // ログの記録中に割り当てないよう、ロックの外で確保する
+    let mut heap = vec![0u8; capacity];
+
+    let mut ring = RING.lock();
+    if ring.heap.is_some() {
+        drop(ring);
+        crate::warn!("klog: already initialized");
+        return;
+    }
+    // 通算位置とバッファ内の位置の対応はサイズで変わるため、新しいサイズで置き直す
+    for pos in ring.head..ring.tail {
+        let mut byte = [0u8];
+        ring.read_at(pos, &mut byte);
+        heap[(pos % capacity as u64) as usize] = byte[0];
+    }
+    ring.heap = Some(heap);
+    drop(ring);
+    crate::info!("klog: {} KB ring buffer", capacity / 1024);
+}
+
+/// 保持しているメッセージを古い順に読み出すイテレータ
+///
+/// 作成した時点までのメッセージを返します。読み出しの途中で捨てられたメッセージは飛ばします。
+pub struct Snapshot {
+    /// 次に読むレコードの通算位置
+    next: u64,
+    /// 作成時点の書き込み位置
+    end: u64,
+}
+
+impl Iterator for Snapshot {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let mut module_buf = [0u8; MAX_MODULE];
+        let mut text_buf = [0u8; MAX_TEXT];
+        // ロックの保持中は割り当てない（割り当て中のログ出力がデッドロックするため）
+        let (seq, time_ns, level, module, text) = {
+            let ring = RING.lock();
+            self.next = self.next.max(ring.head);
+            if self.next >= self.end {
+                return None;
+            }
+            let (header, module, text) = ring.read(self.next, &mut module_buf, &mut text_buf);
+            self.next += header.total_len as u64;
+            (header.seq, header.time_ns, header.level, module, text)
+        };
+        Some(Entry {
+            seq,
+            time_ns,
+            level,
+            module: String::from(module),
+            text: String::from(text),
+        })
+    }
+}
+
+/// 保持しているメッセージの読み出しを開始
+pub fn snapshot() -> Snapshot {
+    let ring = RING.lock();
+    Snapshot {
+        next: ring.head,
+        end: ring.tail,
+    }
+}
+
+/// 直近のメッセージを古い順に列挙（パニック時用）
+///
+/// ヒープを使わず、ロックを待ちません。
+///
+/// # Arguments
+/// * `count` - 列挙する最大件数
+/// * `f` - 各メッセージを受け取るクロージャ
+///
+/// # Returns
+/// ロックを取得できなかった場合はfalse
+pub fn for_each_recent(count: usize, mut f: impl FnMut(&EntryRef)) -> bool {
+    let Some(ring) = RING.try_lock() else {
+        return false;
+    };
+    let mut module_buf = [0u8; MAX_MODULE];
+    let mut text_buf = [0u8; MAX_TEXT];
+    let mut pos = ring.head;
+    for _ in 0..ring.records.saturating_sub(count) {
+        pos += ring.read_header(pos).total_len as u64;
+    }
+    while pos < ring.tail {
+        let (header, module, text) = ring.read(pos, &mut module_buf, &mut text_buf);
+        pos += header.total_len as u64;
+        f(&EntryRef {
+            seq: header.seq,
+            time_ns: header.time_ns,
+            level: header.level,
+            module,
+            text,
+        });
+    }
+    true
+}
+
+/// 保持しているメッセージをすべて捨てる
+pub fn clear() {
+    let mut ring = RING.lock();
+    ring.head = ring.tail;
+    ring.records = 0;
+}
+
+/// 統計情報を取得
+pub fn stats() -> KlogStats {
+    let ring = RING.lock();
+    KlogStats {
+        capacity: ring.capacity(),
+        used: (ring.tail - ring.head) as usize,
+        records: ring.records,
+        evicted: ring.evicted,
+    }
+}
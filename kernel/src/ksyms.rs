@@ -0,0 +1,90 @@
+//! カーネルシンボル表
+//!
+//! バックトレースのアドレスを関数名に変換するための表です。カーネル自身は自分のシンボルを
+//! 知らないため、リンカスクリプトで `.ksyms` セクションに予約領域を置き、ビルド後に
+//! `scripts/embed_ksyms.sh` が `nm` の出力をそこへ書き込みます。
+//!
+//! # 形式
+//! `<16桁の16進アドレス> <関数名>\n` の行をアドレス順に並べたテキストで、残りは0で埋めます。
+//! 領域に収まらなかった行は捨てられます（最後の改行のない行は無視します）。
+//! 埋め込まれていない場合（先頭が0）は、バックトレースにアドレスのみを表示します。
+//!
+//! パニック時に使うため、ヒープもロックも使いません。
+
+use core::cell::UnsafeCell;
+
+/// 予約領域のサイズ（`scripts/embed_ksyms.sh` は `.ksyms` セクションの大きさに合わせる）
+pub const KSYMS_SIZE: usize = 256 * 1024;
+
+/// アドレスの桁数
+const ADDR_DIGITS: usize = 16;
+
+/// 予約領域
+#[repr(C, align(4096))]
+struct Table(UnsafeCell<[u8; KSYMS_SIZE]>);
+
+// SAFETY: 内容はビルド後に書き込まれ、実行中は読み出すだけ
+unsafe impl Sync for Table {}
+
+// 内容が0のままだとコンパイラに仮定されないよう、UnsafeCellで包む
+#[used]
+#[unsafe(link_section = ".ksyms")]
+static TABLE: Table = Table(UnsafeCell::new([0; KSYMS_SIZE]));
+
+/// シンボルの検索結果
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    /// 関数名（デマングル済み）
+    pub name: &'static str,
+    /// 関数の先頭からのオフセット
+    pub offset: u64,
+}
+
+fn table() -> &'static [u8] {
+    // SAFETY: 実行中に書き換えられることはない
+    unsafe { &*TABLE.0.get() }
+}
+
+/// シンボル表が埋め込まれているか
+pub fn is_embedded() -> bool {
+    table()[0] != 0
+}
+
+/// 表の各行を (アドレス, 関数名) として列挙
+fn entries() -> impl Iterator<Item = (u64, &'static str)> {
+    let table = table();
+    // 0埋めの手前（最後の改行の次）までが有効
+    let end = table.iter().position(|&b| b == 0).unwrap_or(table.len());
+    let valid = match table[..end].iter().rposition(|&b| b == b'\n') {
+        Some(last) => &table[..last],
+        None => &[],
+    };
+    valid.split(|&b| b == b'\n').filter_map(|line| {
+        if line.len() < ADDR_DIGITS + 2 || line[ADDR_DIGITS] != b' ' {
+            return None;
+        }
+        let addr = core::str::from_utf8(&line[..ADDR_DIGITS]).ok()?;
+        let addr = u64::from_str_radix(addr, 16).ok()?;
+        let name = core::str::from_utf8(&line[ADDR_DIGITS + 1..]).unwrap_or("?");
+        Some((addr, name))
+    })
+}
+
+/// アドレスを含む関数を検索
+///
+/// # Returns
+/// アドレス以下で最も近いシンボル。表が埋め込まれていない、またはどのシンボルよりも
+/// 前のアドレスの場合はNone
+pub fn lookup(addr: u64) -> Option<Symbol> {
+    let mut found = None;
+    for (start, name) in entries() {
+        if start > addr {
+            break;
+        }
+        found = Some(Symbol {
+            name,
+            offset: addr - start,
+        });
+    }
+    found
+}
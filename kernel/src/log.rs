@@ -0,0 +1,399 @@
+//! カーネルログ
+//!
+//! `trace!`〜`error!` マクロで出力したメッセージに、レベル・モジュール名・単調時刻を付けて
+//! 登録された出力先（シンク）に配送し、直近のメッセージを `klog` のリングバッファに保持します。
+//! リングバッファはシェルの `dmesg` で表示できます。
+//!
+//! # フィルタ
+//! - 既定のレベル（`set_default_level`）未満のメッセージは捨てる
+//! - モジュールごとのレベル（`set_module_level`）があれば、最も長く一致するものを使う
+//!   （`block` は `block` と `block::ata` などに一致する）
+//! - シンクごとにも最低レベルを持ち、フィルタを通ったメッセージのうちそれ以上のものだけを出力する
+//!
+//! # 割り込みコンテキスト
+//! ヒープを使わず、状態は `IrqSpinlock` で保護しているため割り込みハンドラからも出力できます
+//! （モジュールごとのレベルの設定のみヒープを使う）。
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+
+use crate::clock;
+use crate::klog;
+use crate::serial::SerialPort;
+use crate::sync::IrqSpinlock;
+
+/// 登録できるシンクの上限
+pub const MAX_SINKS: usize = 4;
+
+/// `module_path!()` から取り除くクレート名
+const CRATE_PREFIX: &str = "vitros_kernel::";
+
+/// ログレベル（重要度の低い順）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Trace = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+}
+
+impl Level {
+    /// 全レベル（重要度の低い順）
+    pub const ALL: [Level; 5] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+    ];
+
+    /// 表示名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+
+    /// 名前から変換（小文字）
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// 数値から変換（範囲外はError）
+    pub fn from_u8(value: u8) -> Self {
+        Self::ALL[(value as usize).min(Self::ALL.len() - 1)]
+    }
+
+    /// シリアル出力での色（ANSIエスケープ、色なしなら空）
+    pub fn ansi_color(&self) -> &'static str {
+        match self {
+            Level::Trace | Level::Debug => "\x1b[90m",
+            Level::Info => "",
+            Level::Warn => "\x1b[33m",
+            Level::Error => "\x1b[31m",
+        }
+    }
+}
+
+/// ログのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogError {
+    /// シンクの登録数が上限に達している
+    TooManySinks,
+    /// 同じ名前のシンクが登録済み
+    DuplicateSink,
+    /// 指定した名前のシンクがない
+    UnknownSink,
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogError::TooManySinks => write!(f, "Too many log sinks (max {})", MAX_SINKS),
+            LogError::DuplicateSink => write!(f, "Log sink is already registered"),
+            LogError::UnknownSink => write!(f, "Unknown log sink"),
+        }
+    }
+}
+
+/// シンクに渡すメッセージ
+pub struct Record<'a> {
+    /// 連番（リングバッファ内の位置を表す）
+    pub seq: u64,
+    /// 単調時刻（ナノ秒）
+    pub time_ns: u64,
+    pub level: Level,
+    /// モジュール名（クレート名を除いたもの）
+    pub module: &'static str,
+    /// 本文（切り捨てなし）
+    pub args: fmt::Arguments<'a>,
+}
+
+/// ログの出力先
+///
+/// `write` はメッセージを出力した処理の中（割り込みハンドラ内を含む）で呼ばれるため、
+/// ブロックやヒープ割り当てをせずに戻ってください。重い出力はタスク側で行います。
+pub trait LogSink: Sync {
+    /// シンク名（シェルでの指定に使う）
+    fn name(&self) -> &'static str;
+    /// メッセージを出力
+    fn write(&self, record: &Record);
+}
+
+/// シリアルポートへの出力
+struct SerialSink;
+
+impl LogSink for SerialSink {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn write(&self, record: &Record) {
+        let mut serial = SerialPort::new(crate::serial::COM1);
+        let _ = writeln!(
+            serial,
+            "{} {}[{}]\x1b[0m {}",
+            Timestamp(record.time_ns),
+            record.level.ansi_color(),
+            record.level.as_str(),
+            record.args
+        );
+    }
+}
+
+static SERIAL_SINK: SerialSink = SerialSink;
+
+/// 単調時刻の表示（`[   秒.マイクロ秒]`）
+pub struct Timestamp(pub u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:06}]",
+            self.0 / 1_000_000_000,
+            self.0 % 1_000_000_000 / 1_000
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SinkSlot {
+    sink: &'static dyn LogSink,
+    /// このシンクに出力する最低レベル
+    min_level: Level,
+}
+
+static SINKS: IrqSpinlock<[Option<SinkSlot>; MAX_SINKS]> = IrqSpinlock::new([
+    Some(SinkSlot {
+        sink: &SERIAL_SINK,
+        min_level: Level::Trace,
+    }),
+    None,
+    None,
+    None,
+]);
+
+/// 既定のレベル
+static DEFAULT_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// モジュールごとのレベルが1つでも設定されているか（フィルタの高速判定用）
+static HAS_MODULE_LEVELS: AtomicBool = AtomicBool::new(false);
+
+/// モジュールごとのレベル（モジュール名の接頭辞, レベル）
+static MODULE_LEVELS: IrqSpinlock<Vec<(String, Level)>> = IrqSpinlock::new(Vec::new());
+
+/// 次に振る連番
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// 固定長バッファへの書き込み（溢れた分は文字境界で切り捨てる）
+struct TruncatingWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.buf.len() - self.len;
+        let mut take = s.len().min(room);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.buf[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+/// `module_path!()` からクレート名を取り除く
+fn short_module(module_path: &'static str) -> &'static str {
+    match module_path.strip_prefix(CRATE_PREFIX) {
+        Some(module) => module,
+        // クレートルート（main.rs）
+        None => "main",
+    }
+}
+
+/// `prefix` がモジュール名 `module` 自身かその親モジュールか
+fn module_matches(prefix: &str, module: &str) -> bool {
+    module == prefix
+        || (module.starts_with(prefix) && module.as_bytes().get(prefix.len()) == Some(&b':'))
+}
+
+/// モジュールに適用されるレベル
+pub fn level_for(module: &str) -> Level {
+    let default = Level::from_u8(DEFAULT_LEVEL.load(Ordering::Relaxed));
+    if !HAS_MODULE_LEVELS.load(Ordering::Acquire) {
+        return default;
+    }
+    MODULE_LEVELS
+        .lock()
+        .iter()
+        .filter(|(prefix, _)| module_matches(prefix, module))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or(default, |(_, level)| *level)
+}
+
+/// メッセージを出力（マクロから呼ばれる）
+#[doc(hidden)]
+pub fn log(level: Level, module_path: &'static str, args: fmt::Arguments) {
+    let module = short_module(module_path);
+    if level < level_for(module) {
+        return;
+    }
+
+    let record = Record {
+        seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+        time_ns: clock::monotonic_ns(),
+        level,
+        module,
+        args,
+    };
+
+    // 本文は固定長に切り捨ててリングバッファに記録する（シンクには切り捨てずに渡す）
+    let mut text = [0u8; klog::MAX_TEXT];
+    let mut writer = TruncatingWriter {
+        buf: &mut text,
+        len: 0,
+    };
+    let _ = writer.write_fmt(args);
+    let len = writer.len;
+    // TruncatingWriterは文字境界で切り捨てる
+    let text = core::str::from_utf8(&text[..len]).unwrap_or("");
+    klog::push(record.seq, record.time_ns, level, module, text);
+
+    // シンクの出力は遅いことがあるため、ロックを外してから行う
+    let sinks = *SINKS.lock();
+    for slot in sinks.iter().flatten() {
+        if level >= slot.min_level {
+            slot.sink.write(&record);
+        }
+    }
+}
+
+/// シンクを登録
+///
+/// # Arguments
+/// * `sink` - 出力先
+/// * `min_level` - このシンクに出力する最低レベル
+///
+/// # Errors
+/// * `LogError::DuplicateSink` - 同じ名前のシンクが登録済みの場合
+/// * `LogError::TooManySinks` - 登録数が上限に達している場合
+pub fn register_sink(sink: &'static dyn LogSink, min_level: Level) -> Result<(), LogError> {
+    let mut sinks = SINKS.lock();
+    if sinks.iter().flatten().any(|s| s.sink.name() == sink.name()) {
+        return Err(LogError::DuplicateSink);
+    }
+    let free = sinks
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(LogError::TooManySinks)?;
+    *free = Some(SinkSlot { sink, min_level });
+    Ok(())
+}
+
+/// シンクの最低レベルを変更
+///
+/// # Errors
+/// * `LogError::UnknownSink` - 指定した名前のシンクがない場合
+pub fn set_sink_level(name: &str, min_level: Level) -> Result<(), LogError> {
+    let mut sinks = SINKS.lock();
+    let slot = sinks
+        .iter_mut()
+        .flatten()
+        .find(|slot| slot.sink.name() == name)
+        .ok_or(LogError::UnknownSink)?;
+    slot.min_level = min_level;
+    Ok(())
+}
+
+/// 登録済みのシンク（名前, 最低レベル）
+pub fn sinks() -> Vec<(&'static str, Level)> {
+    SINKS
+        .lock()
+        .iter()
+        .flatten()
+        .map(|slot| (slot.sink.name(), slot.min_level))
+        .collect()
+}
+
+/// 既定のレベルを設定
+pub fn set_default_level(level: Level) {
+    DEFAULT_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// 既定のレベル
+pub fn default_level() -> Level {
+    Level::from_u8(DEFAULT_LEVEL.load(Ordering::Relaxed))
+}
+
+/// モジュールごとのレベルを設定（Noneで解除）
+///
+/// # Arguments
+/// * `module` - モジュール名（クレート名を除く。`block` は `block::ata` などにも適用される）
+/// * `level` - 設定するレベル
+pub fn set_module_level(module: &str, level: Option<Level>) {
+    let mut levels = MODULE_LEVELS.lock();
+    levels.retain(|(prefix, _)| prefix != module);
+    if let Some(level) = level {
+        levels.push((String::from(module), level));
+    }
+    HAS_MODULE_LEVELS.store(!levels.is_empty(), Ordering::Release);
+}
+
+/// モジュールごとのレベルの一覧
+pub fn module_levels() -> Vec<(String, Level)> {
+    MODULE_LEVELS.lock().clone()
+}
+
+/// トレースレベルのログ
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Trace, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// デバッグレベルのログ
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Debug, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// 情報レベルのログ
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Info, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// 警告レベルのログ（黄色表示）
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Warn, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// エラーレベルのログ（赤色表示）
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Level::Error, module_path!(), format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,160 @@
+//! ログコンソール
+//!
+//! カーネルログ（`log`）のシンクとして、直近のメッセージを画面下部のオーバーレイに表示します。
+//! シンクは行を固定長のリングに書き込むだけで、描画はオーバーレイタスクが行います
+//! （シンクは割り込みハンドラ内からも呼ばれるため）。
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::graphics::{Region, TaskWriter, compositor, theme};
+use crate::log::{self, Level, LogSink, Record, Timestamp};
+use crate::sync::IrqSpinlock;
+
+/// 表示する行数
+const LINES: usize = 6;
+
+/// 1行の最大文字数（超えた分は切り捨てる）
+const COLUMNS: usize = 96;
+
+/// 1行の高さ（px）
+const LINE_HEIGHT: u32 = 10;
+
+/// 1文字の幅（px）
+const CHAR_WIDTH: u32 = 8;
+
+/// 画面端からのマージン
+const MARGIN: u32 = 10;
+
+/// 更新を確認する間隔（ミリ秒）
+const UPDATE_INTERVAL_MS: u64 = 200;
+
+/// 既定で表示する最低レベル（`log sink console <level>` で変更できる）
+const DEFAULT_LEVEL: Level = Level::Warn;
+
+#[derive(Clone, Copy)]
+struct Line {
+    level: Level,
+    len: usize,
+    text: [u8; COLUMNS],
+}
+
+struct Lines {
+    lines: [Line; LINES],
+    /// 次に書き込む位置
+    next: usize,
+    /// 書き込んだ行数（LINESで頭打ち）
+    count: usize,
+}
+
+static LINES_BUFFER: IrqSpinlock<Lines> = IrqSpinlock::new(Lines {
+    lines: [Line {
+        level: Level::Info,
+        len: 0,
+        text: [0; COLUMNS],
+    }; LINES],
+    next: 0,
+    count: 0,
+});
+
+/// 前回の描画以降に行が追加されたか
+static DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// 1行分のバッファへの書き込み（改行は空白にし、溢れた分は切り捨てる）
+struct LineWriter<'a> {
+    line: &'a mut Line,
+}
+
+impl Write for LineWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            // フォントはASCIIのみ
+            let byte = match c {
+                ' '..='~' => c as u8,
+                _ => b' ',
+            };
+            if self.line.len == COLUMNS {
+                break;
+            }
+            self.line.text[self.line.len] = byte;
+            self.line.len += 1;
+        }
+        Ok(())
+    }
+}
+
+struct ConsoleSink;
+
+impl LogSink for ConsoleSink {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn write(&self, record: &Record) {
+        let mut line = Line {
+            level: record.level,
+            len: 0,
+            text: [0; COLUMNS],
+        };
+        let _ = write!(
+            LineWriter { line: &mut line },
+            "{} {}: {}",
+            Timestamp(record.time_ns),
+            record.module,
+            record.args
+        );
+
+        let mut lines = LINES_BUFFER.lock();
+        let next = lines.next;
+        lines.lines[next] = line;
+        lines.next = (next + 1) % LINES;
+        lines.count = (lines.count + 1).min(LINES);
+        DIRTY.store(true, Ordering::Release);
+    }
+}
+
+static CONSOLE_SINK: ConsoleSink = ConsoleSink;
+
+/// ログコンソールタスクのエントリポイント
+pub extern "C" fn log_console_task() -> ! {
+    if let Err(e) = log::register_sink(&CONSOLE_SINK, DEFAULT_LEVEL) {
+        crate::warn!("[LogConsole] Failed to register sink: {}", e);
+    }
+    crate::info!("[LogConsole] Started");
+
+    // 画面下部に配置
+    let (screen_width, screen_height) = compositor::screen_size();
+    let width = (COLUMNS as u32 * CHAR_WIDTH).min(screen_width - MARGIN * 2);
+    let height = LINES as u32 * LINE_HEIGHT;
+    let region = Region::new(MARGIN, screen_height - height - MARGIN, width, height);
+
+    // どのワークスペースでも表示する
+    let buffer = compositor::register_overlay(region).expect("Failed to register log console");
+    let mut writer = TaskWriter::new(buffer, theme::foreground());
+
+    loop {
+        if DIRTY.swap(false, Ordering::Acquire) {
+            // 描画中に追加された行は次回に回すため、ロックの保持は複製の間だけにする
+            let (lines, next, count) = {
+                let buffer = LINES_BUFFER.lock();
+                (buffer.lines, buffer.next, buffer.count)
+            };
+
+            writer.clear_themed();
+            for i in 0..count {
+                let line = &lines[(next + LINES - count + i) % LINES];
+                writer.set_color(match line.level {
+                    Level::Error => theme::error(),
+                    Level::Warn => theme::accent(),
+                    _ => theme::foreground(),
+                });
+                // LineWriterはASCIIのみを書き込む
+                let text = core::str::from_utf8(&line.text[..line.len]).unwrap_or("");
+                let _ = writeln!(writer, "{}", text);
+            }
+            writer.flush();
+        }
+
+        crate::sched::sleep_ms(UPDATE_INTERVAL_MS);
+    }
+}
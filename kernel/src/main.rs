@@ -10,19 +10,65 @@ mod acpi;
 mod addr;
 mod allocator;
 mod apic;
+mod backtrace;
+mod block;
+mod boot_health;
+mod boot_splash;
+mod clock;
+mod cmdline;
+mod config;
+mod cpu;
+mod datetime;
 mod debug_overlay;
+mod dmesg_view;
+mod elf_loader;
+mod emergency;
+mod exctest;
+mod fault_inject;
+mod fault_report;
+mod fbcon;
+mod frame_allocator;
+mod fs;
 mod gdt;
 mod graphics;
+mod heap_quota;
 mod hpet;
 mod idt;
 mod io;
+mod ioapic;
+mod iotrace;
+mod irq;
+mod keyboard;
+mod klog;
+mod ksyms;
+mod ktest;
+mod log;
+mod log_console;
+mod mce;
+mod membench;
+mod metrics;
+mod minidump;
+mod mouse;
+mod page_fault;
 mod paging;
+mod panic_screen;
 mod pci;
+mod percpu;
 mod pit;
+mod power;
 mod sched;
 mod serial;
+mod shell;
+mod smp;
 mod sync;
+mod syscall;
+mod sysrq;
 mod timer;
+mod trace;
+mod watch;
+mod worker_pool;
+mod workqueue;
+mod zram;
 
 // 後方互換性のためのエイリアス
 use sched as task;
@@ -35,17 +81,31 @@ use alloc::boxed::Box;
 use core::arch::asm;
 use core::fmt::Write;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 use vitros_common::boot_info::BootInfo;
-use vitros_common::uefi;
+use vitros_common::boot_progress::Stage;
 
 // カーネル仮想アドレスベース（ブートローダと同じ値）
 const KERNEL_VMA: u64 = 0xFFFF800000000000;
 
+/// パニック処理を開始したか（パニック画面の描画中のパニックで再描画しないため）
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 // パニックハンドラ
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("\n!!! KERNEL PANIC !!!");
-    println!("{}", info);
+    // 以降の処理でレジスタが書き換わる前に取得する
+    let regs = minidump::Registers::capture();
+    // ヒープが枯渇・破損していても診断情報を出力できるよう、以後の割り当ては緊急用予約から行う
+    emergency::enter_permanently();
+    let first = !PANICKING.swap(true, Ordering::AcqRel);
+    minidump::write(info, &regs);
+    let error_color = graphics::theme::error().ansi_fg();
+    println!("\n{}!!! KERNEL PANIC !!!", error_color);
+    println!("{}{}", info, graphics::color::ANSI_RESET);
+    if first {
+        panic_screen::show(info, &regs);
+    }
     loop {
         hlt()
     }
@@ -81,6 +141,8 @@ pub extern "C" fn boot_complete() {
 extern "C" fn idle_task() -> ! {
     info!("[Idle] Idle task started");
     loop {
+        // 停止する前に、他のCPUで待っているタスクを引き取る
+        sched::idle_balance();
         // SAFETY: hlt命令はCPUを低消費電力状態にする特権命令。
         // 次の割り込みで復帰するため、メモリ安全性に影響しない。
         unsafe {
@@ -96,11 +158,11 @@ extern "C" fn task1() -> ! {
     // 新Writer方式：固有の描画領域を取得
     let region = graphics::Region::new(400, 500, 350, 20);
     let buffer = graphics::compositor::register_writer(region).expect("Failed to register writer");
-    let mut writer = graphics::TaskWriter::new(buffer, 0xFFFFFFFF);
+    let mut writer = graphics::TaskWriter::new(buffer, graphics::theme::foreground());
 
     let mut counter = 0u64;
     loop {
-        writer.clear(0x00000000);
+        writer.clear_themed();
         // tick数を表示してタイマー割り込みが発生しているか確認
         let tick = timer::current_tick();
         let _ = write!(writer, "[Task1] Count:{} Tick:{}", counter, tick);
@@ -119,11 +181,11 @@ extern "C" fn task2() -> ! {
     // 新Writer方式：固有の描画領域を取得
     let region = graphics::Region::new(400, 520, 300, 20);
     let buffer = graphics::compositor::register_writer(region).expect("Failed to register writer");
-    let mut writer = graphics::TaskWriter::new(buffer, 0xFFFFFFFF);
+    let mut writer = graphics::TaskWriter::new(buffer, graphics::theme::foreground());
 
     let mut counter = 0u64;
     loop {
-        writer.clear(0x00000000);
+        writer.clear_themed();
         let _ = write!(writer, "[Task2 Med ] Count: {}", counter);
         // ローカルバッファを共有バッファに一括転送（1回のロックのみ）
         writer.flush();
@@ -140,11 +202,11 @@ extern "C" fn task3() -> ! {
     // 新Writer方式：固有の描画領域を取得
     let region = graphics::Region::new(400, 540, 300, 20);
     let buffer = graphics::compositor::register_writer(region).expect("Failed to register writer");
-    let mut writer = graphics::TaskWriter::new(buffer, 0xFFFFFFFF);
+    let mut writer = graphics::TaskWriter::new(buffer, graphics::theme::foreground());
 
     let mut counter = 0u64;
     loop {
-        writer.clear(0x00000000);
+        writer.clear_themed();
         let _ = write!(writer, "[Task3 Low ] Count: {}", counter);
         // ローカルバッファを共有バッファに一括転送（1回のロックのみ）
         writer.flush();
@@ -196,21 +258,53 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
     // ブートローダーが設定したページテーブルによりアクセス可能な領域を参照する。
     // BootInfoはブートローダーによって正しく初期化されており、
     // カーネル実行中は不変であることが保証されている。
-    let boot_info = unsafe { &*(boot_info_virt_addr as *const BootInfo) };
+    let boot_info: &'static BootInfo = unsafe { &*(boot_info_virt_addr as *const BootInfo) };
+
+    // カーネルコマンドラインを取り込み、ログレベルを反映
+    cmdline::init(boot_info);
+    if let Some(name) = cmdline::get("loglevel") {
+        match log::Level::from_name(name) {
+            Some(level) => log::set_default_level(level),
+            None => warn!("cmdline: unknown loglevel '{}', ignoring", name),
+        }
+    }
+
+    // CPUの機能を検出（以降のサブシステムはこの結果で処理を切り替える）
+    cpu::init().expect("Unsupported CPU");
+    // タスク切り替えで保存する拡張状態（XSAVE/AVX）を設定（タスクを作成する前に行う）
+    cpu::init_extended_state();
 
     // GDTを初期化
     info!("Initializing GDT...");
     gdt::init().expect("Failed to initialize GDT");
     info!("GDT initialized");
+    boot_splash::advance(Stage::Gdt);
 
     // ブートローダーが既にページングを設定し、高位アドレスで起動している
     info!("Running in higher-half (set up by bootloader)");
 
+    // ヒープサイズの上限
+    #[cfg(feature = "visualize-allocator")]
+    const HEAP_LIMIT: usize = 4 * 1024 * 1024; // ブロックを数えやすいよう4MBに制限
+
+    #[cfg(not(feature = "visualize-allocator"))]
+    const HEAP_LIMIT: usize = usize::MAX; // 本番環境では全て使用
+
+    // 物理フレームアロケータを初期化（UEFIメモリマップ全体を使用）
+    // ページテーブル用のフレームを確保するため、ページングより先に初期化する。
+    // ヒープ領域もここで予約し、以降のalloc_frameが払い出さないようにする
+    let heap_region = frame_allocator::init(boot_info, HEAP_LIMIT);
+
     // カーネル用のページテーブルを作成（UEFIメモリマップに基づいて動的にマッピング）
     info!("Creating kernel page tables...");
     paging::init(boot_info).expect("Failed to initialize paging system");
     info!("Kernel page tables created and loaded");
 
+    // PAT[1]をWrite-Combiningに設定（フレームバッファのマッピングで使用）
+    if !paging::init_pat() {
+        warn!("PAT not supported; framebuffer stays uncached");
+    }
+
     // GDTを高位アドレスで再ロード（念のため）
     info!("Reloading GDT...");
     gdt::init().expect("Failed to reload GDT");
@@ -220,20 +314,46 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
     info!("Initializing IDT...");
     idt::init().expect("Failed to initialize IDT");
     info!("IDT initialized");
+    boot_splash::advance(Stage::Idt);
+
+    // Machine Checkを有効化（#MCのハンドラを登録した後）
+    if mce::init().is_none() {
+        warn!("Machine check not supported; hardware errors will reset the CPU");
+    }
 
     // タスクシステムを初期化
     task::init();
 
     // ACPI を初期化
     acpi::init(&boot_info);
+    boot_splash::advance(Stage::Acpi);
+
+    // 高分解能クロックを初期化（HPETの初期化後、TSCを較正）
+    clock::init();
+
+    // RTCから壁時計を初期化（FADTの世紀レジスタを使うためACPIの後）
+    datetime::init();
 
     // PCIバスをスキャン
     pci::scan_pci_bus();
 
     // Local APICを初期化
     info!("Initializing Local APIC...");
-    apic::init();
+    apic::init().expect("Failed to initialize Local APIC");
     info!("Local APIC initialized");
+    boot_splash::advance(Stage::Apic);
+
+    // I/O APICを初期化（全エントリをマスク、各ドライバがroute_irqで有効化する）
+    if let Err(e) = ioapic::init() {
+        warn!("I/O APIC not available: {}", e);
+    }
+
+    // PS/2キーボード（SysRqを含む）とマウスを初期化
+    keyboard::init();
+    mouse::init();
+
+    // シリアル受信割り込みを有効化（QEMUのシリアルコンソールからの入力用）
+    serial::init_rx();
 
     // APIC Timerをキャリブレーション（割り込み無効状態で実行）
     info!("Calibrating APIC Timer...");
@@ -245,57 +365,81 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
     // ローカルフレームバッファを初期化
     // 物理アドレスを高位仮想アドレスに変換
     //
-    // TODO: フレームバッファにWrite-Combining (WC)を設定してパフォーマンス向上
-    // 現状: 0x80000000〜がMTRR=UC → 描画が遅い
-    // 改善案:
-    //   - MTRR0-6: 0x81000000〜0xFFFFFFFFを7分割でUC設定
-    //   - MTRR7: 768GB地点のUC維持
-    //   - PAT[1]=WCに書き換え、フレームバッファのPTEでPWT=1,PCD=0設定
-    //   - 結果: フレームバッファ=WC、他MMIO=UC
-    // See: https://github.com/jugeeeemu-tech/VitrOS/issues/7
-    let fb_virt_base = paging::phys_to_virt(boot_info.framebuffer.base)
-        .expect("Failed to convert framebuffer address");
-    let mut fb_writer = FramebufferWriter::new(
-        fb_virt_base,
-        boot_info.framebuffer.width,
-        boot_info.framebuffer.height,
-        0xFFFFFFFF,
-    );
-
-    // カーネル起動時に画面を黒でクリア
-    fb_writer.clear_screen(0x00000000);
+    // 対応していないピクセルフォーマットの場合は色化けを避けるため、
+    // フレームバッファへの描画とCompositorを無効化する
+    let fb_virt_base = match graphics::pixel_format::init(&boot_info.framebuffer) {
+        Ok(format) => {
+            info!("Framebuffer pixel format: {}", format);
+            let fb = &boot_info.framebuffer;
+            if fb.stride != fb.width {
+                info!("Framebuffer stride: {} pixels per row", fb.stride);
+            }
+            // フレームバッファは直接マッピングに含まれないため、ここでWrite-Combiningでマップする
+            // ファームウェアのMTRRではUC（0x80000000〜）のことが多く、そのままでは転送が遅い。
+            // PATでWCを選んだページは、MTRRがUCでもWBでも実効的にWCになる
+            // （PATが使えなければUCでマップし、下でMTRRの設定を報告する）
+            let mapped = paging::map_mmio(fb.base, fb.size, paging::CacheMode::WriteCombining)
+                .or_else(|_| paging::map_mmio(fb.base, fb.size, paging::CacheMode::Uncacheable));
+            Some(mapped.expect("Failed to map framebuffer"))
+        }
+        Err(e) => {
+            error!("Graphics disabled: {}", e);
+            None
+        }
+    };
+
+    // 既に直接マッピングされていた部分はmap_mmioでは属性が変わらないため、
+    // 先頭と末尾のページがWCでなければページ単位で設定し直す
+    if let Some(base) = fb_virt_base {
+        let size = boot_info.framebuffer.size;
+        let mtrr = paging::mtrr_memory_type(boot_info.framebuffer.base);
+        let is_wc =
+            |addr: u64| paging::cache_mode(addr) == Ok(Some(paging::CacheMode::WriteCombining));
+        let result = if is_wc(base) && is_wc(base + size.saturating_sub(1)) {
+            Ok(())
+        } else {
+            paging::set_cache_mode(base, size as usize, paging::CacheMode::WriteCombining)
+        };
+        match result {
+            Ok(()) => info!(
+                "Framebuffer mapped write-combining (MTRR: {})",
+                mtrr.as_str()
+            ),
+            Err(e) => warn!(
+                "Framebuffer left as MTRR {} (write-combining not set: {})",
+                mtrr.as_str(),
+                e
+            ),
+        }
+    }
+    // 起動画面を引き継ぐ（ログを表示する設定なら、画面をテーマの背景色でクリア）
+    // SAFETY: フレームバッファはマップ済みで、Compositorの初期化前にfinishするまで
+    // 他に描画するものはない
+    let splash_shown =
+        fb_virt_base.is_some_and(|base| unsafe { boot_splash::init(boot_info, base) });
+    if let Some(base) = fb_virt_base.filter(|_| !splash_shown) {
+        let mut fb_writer = FramebufferWriter::new(
+            base,
+            boot_info.framebuffer.width,
+            boot_info.framebuffer.height,
+            boot_info.framebuffer.stride,
+            graphics::theme::foreground(),
+        );
+        fb_writer.clear_screen(graphics::theme::background());
+    }
 
     info!("Memory map count: {}", boot_info.memory_map_count);
     info!("Memory map array len: {}", boot_info.memory_map.len());
 
-    // 利用可能なメモリを探してアロケータを初期化
-    let mut largest_start_phys: u64 = 0;
-    let mut largest_size = 0;
-
-    // 配列の範囲内に制限
-    let safe_count = boot_info.memory_map_count.min(boot_info.memory_map.len());
-    info!("Using safe count: {}", safe_count);
-
-    for i in 0..safe_count {
-        let region = &boot_info.memory_map[i];
-        // region_type == 7 は EFI_CONVENTIONAL_MEMORY
-        if region.region_type == uefi::EFI_CONVENTIONAL_MEMORY && region.size > largest_size as u64
-        {
-            largest_start_phys = region.start;
-            largest_size = region.size as usize;
-        }
-    }
-
-    if largest_size > 0 {
+    // frame_allocator::initで予約した領域でヒープアロケータを初期化
+    if let Some(frame_allocator::HeapRegion {
+        start: largest_start_phys,
+        size: heap_size,
+        region_size: largest_size,
+    }) = heap_region
+    {
         info!("Found usable memory");
 
-        // ヒープサイズを決定
-        #[cfg(feature = "visualize-allocator")]
-        let heap_size = largest_size.min(256 * 1024); // 可視化のため256KBに制限
-
-        #[cfg(not(feature = "visualize-allocator"))]
-        let heap_size = largest_size; // 本番環境では全て使用
-
         // 物理アドレスを高位仮想アドレスに変換
         let largest_start_virt =
             paging::phys_to_virt(largest_start_phys).expect("Failed to convert heap address");
@@ -306,92 +450,203 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
 
         // SAFETY: largest_start_virtはphys_to_virtで変換された有効な仮想アドレス。
         // heap_sizeはEFI_CONVENTIONAL_MEMORYリージョンのサイズ以下に制限されている。
-        // この領域はUEFIメモリマップで使用可能と報告されており、
-        // カーネルの他の部分では使用されていない。
+        // この領域はUEFIメモリマップで使用可能と報告されており、フレームアロケータの
+        // 初期化時に予約済みのため、カーネルの他の部分では使用されていない。
         // init_heapは一度だけ呼び出され、以降はグローバルアロケータとして機能する。
         unsafe {
             allocator::init_heap(largest_start_virt as usize, heap_size);
         }
 
-        // 可視化テストを実行
-        #[cfg(feature = "visualize-allocator")]
-        {
-            info!("Starting allocator visualization");
-            allocator_visualization::run_visualization_tests(&mut fb_writer);
-        }
-
         info!("Heap initialized successfully");
+        boot_splash::advance(Stage::Heap);
+
+        // カーネルログのリングバッファをヒープへ移す（ヒープが必要）
+        const KLOG_CAPACITY: usize = 64 * 1024;
+        klog::init(KLOG_CAPACITY);
+
+        // Compositorの起動までカーネルログを画面に表示（ヒープが必要）
+        if let Some(fb_virt_base) = fb_virt_base.filter(|_| !splash_shown) {
+            // SAFETY: フレームバッファはマップ済みで、Compositorの初期化前にdetachするまで
+            // 他に描画するものはない
+            unsafe {
+                fbcon::init(
+                    fb_virt_base,
+                    boot_info.framebuffer.width,
+                    boot_info.framebuffer.height,
+                    boot_info.framebuffer.stride,
+                );
+            }
+        }
 
         // タイマーシステムを初期化（ヒープが必要）
+        // 周波数はコマンドラインの timer_hz で変更できる
         const TIMER_FREQUENCY_HZ: u64 = 250;
-        timer::init(TIMER_FREQUENCY_HZ);
+        const TIMER_FREQUENCY_RANGE: core::ops::RangeInclusive<u64> = 10..=1000;
+        let timer_hz = match cmdline::number("timer_hz") {
+            Some(hz) if TIMER_FREQUENCY_RANGE.contains(&hz) => hz,
+            Some(hz) => {
+                warn!(
+                    "cmdline: timer_hz={} out of range {:?}, using {}",
+                    hz, TIMER_FREQUENCY_RANGE, TIMER_FREQUENCY_HZ
+                );
+                TIMER_FREQUENCY_HZ
+            }
+            None => TIMER_FREQUENCY_HZ,
+        };
+        timer::init(timer_hz);
+
+        // APIC Timerを初期化（既定は250Hz = 4msタイムスライス）
+        info!("Initializing APIC Timer ({} Hz)...", timer_hz);
+        apic::init_timer(timer_hz as u32).expect("Failed to initialize APIC Timer");
+
+        // アプリケーションプロセッサを起動（APのGDT/TSSにヒープが必要）
+        smp::init();
+
+        // 起動処理が下位半分に残したマッピング（APトランポリンの恒等マップ）を外す
+        // 以降、物理アドレスをそのまま参照するとページフォルトになる
+        // 応答しなかったAPがあとからトランポリンを実行する可能性がある場合は残す
+        if smp::ap_boot_pending() {
+            warn!("Low-half mappings kept: a timed-out AP may still enter the trampoline");
+        } else {
+            let removed = paging::unmap_kernel_low_half();
+            info!("Low-half mappings removed ({} PML4 entries)", removed);
+        }
+
+        // ブロックデバイスを検出（ヒープが必要）
+        block::init();
 
-        // APIC Timerを初期化（250Hz = 4msタイムスライス）
-        info!("Initializing APIC Timer...");
-        apic::init_timer(TIMER_FREQUENCY_HZ as u32).expect("Failed to initialize APIC Timer");
+        // VFSを初期化し、ルートにramfs、/initrdにinitramfsをマウント（ヒープが必要）
+        fs::init(boot_info);
 
         // =================================================================
         // Compositorを初期化
         // =================================================================
-        info!("Initializing Compositor...");
-        graphics::compositor::init_compositor(graphics::compositor::CompositorConfig {
-            fb_base: fb_virt_base,
-            fb_width: boot_info.framebuffer.width,
-            fb_height: boot_info.framebuffer.height,
-            refresh_interval_ticks: 10,
-        });
-        info!("Compositor initialized");
+        if let Some(fb_virt_base) = fb_virt_base {
+            info!("Initializing Compositor...");
+            fbcon::detach();
+            boot_splash::finish();
+            graphics::compositor::init_compositor(graphics::compositor::CompositorConfig {
+                fb_base: fb_virt_base,
+                fb_width: boot_info.framebuffer.width,
+                fb_height: boot_info.framebuffer.height,
+                fb_stride: boot_info.framebuffer.stride,
+                refresh_interval_ticks: 10,
+            });
+            info!("Compositor initialized");
+        } else {
+            warn!("Compositor not started: unsupported framebuffer pixel format");
+        }
+
+        // 設定されたサイズ（またはinitramfsのイメージ）でRAMディスクを作成
+        block::ramdisk::init();
 
         // =================================================================
         // プリエンプティブマルチタスキングのタスクを作成（割り込み無効状態で）
         // =================================================================
         info!("Creating tasks for preemptive multitasking...");
 
-        // Compositorタスク（Realtimeクラス、最高優先度）
-        let compositor = Box::new(
-            task::Task::new_realtime(
-                "Compositor",
-                task::rt_priority::MAX,
-                graphics::compositor::compositor_task,
-            )
-            .expect("Failed to create Compositor task"),
-        );
-        task::add_task(*compositor);
-
         // アイドルタスク（Idleクラス）
         let idle =
             Box::new(task::Task::new_idle("Idle", idle_task).expect("Failed to create idle task"));
         task::add_task(*idle);
 
-        // ワーカータスク1（Normalクラス、nice -5 = やや高い優先度）
-        let t1 = Box::new(
-            task::Task::new("Task1", task::nice::DEFAULT - 5, task1)
-                .expect("Failed to create Task1"),
-        );
-        task::add_task(*t1);
+        // 描画を行うタスクはCompositorが有効な場合のみ作成
+        if fb_virt_base.is_some() {
+            // Compositorタスク（Realtimeクラス、最高優先度）
+            let compositor = Box::new(
+                task::Task::new_realtime(
+                    "Compositor",
+                    task::rt_priority::MAX,
+                    graphics::compositor::compositor_task,
+                )
+                .expect("Failed to create Compositor task"),
+            );
+            task::add_task(*compositor);
+
+            // ワーカータスク1（Normalクラス、nice -5 = やや高い優先度）
+            let t1 = Box::new(
+                task::Task::new("Task1", task::nice::DEFAULT - 5, task1)
+                    .expect("Failed to create Task1"),
+            );
+            task::add_task(*t1);
+
+            // ワーカータスク2（Normalクラス、nice 0 = 標準優先度）
+            let t2 = Box::new(
+                task::Task::new("Task2", task::nice::DEFAULT, task2)
+                    .expect("Failed to create Task2"),
+            );
+            task::add_task(*t2);
+
+            // ワーカータスク3（Normalクラス、nice +19 = 最低優先度）
+            let t3 = Box::new(
+                task::Task::new("Task3", task::nice::MAX, task3).expect("Failed to create Task3"),
+            );
+            task::add_task(*t3);
+
+            // デバッグオーバーレイタスク（Normalクラス、標準優先度）
+            let debug = Box::new(
+                task::Task::new(
+                    "DebugOverlay",
+                    task::nice::DEFAULT,
+                    debug_overlay::debug_overlay_task,
+                )
+                .expect("Failed to create DebugOverlay task"),
+            );
+            task::add_task(*debug);
+
+            // ログコンソールタスク（Normalクラス、標準優先度）
+            let log_console = Box::new(
+                task::Task::new(
+                    "LogConsole",
+                    task::nice::DEFAULT,
+                    log_console::log_console_task,
+                )
+                .expect("Failed to create LogConsole task"),
+            );
+            task::add_task(*log_console);
+
+            // アロケータ可視化タスク（Normalクラス、標準優先度）
+            #[cfg(feature = "visualize-allocator")]
+            {
+                let viz = Box::new(
+                    task::Task::new(
+                        "AllocViz",
+                        task::nice::DEFAULT,
+                        allocator_visualization::allocator_visualization_task,
+                    )
+                    .expect("Failed to create AllocViz task"),
+                );
+                task::add_task(*viz);
+            }
+        }
 
-        // ワーカータスク2（Normalクラス、nice 0 = 標準優先度）
-        let t2 = Box::new(
-            task::Task::new("Task2", task::nice::DEFAULT, task2).expect("Failed to create Task2"),
-        );
-        task::add_task(*t2);
+        // 終了したタスクを回収するReaperタスク
+        task::start_reaper().expect("Failed to start Reaper task");
 
-        // ワーカータスク3（Normalクラス、nice +19 = 最低優先度）
-        let t3 = Box::new(
-            task::Task::new("Task3", task::nice::MAX, task3).expect("Failed to create Task3"),
+        // シリアルコンソールのシェルタスク
+        let shell = Box::new(
+            task::Task::new("Shell", task::nice::DEFAULT, shell::shell_task)
+                .expect("Failed to create Shell task"),
         );
-        task::add_task(*t3);
-
-        // デバッグオーバーレイタスク（Normalクラス、標準優先度）
-        let debug = Box::new(
-            task::Task::new(
-                "DebugOverlay",
-                task::nice::DEFAULT,
-                debug_overlay::debug_overlay_task,
-            )
-            .expect("Failed to create DebugOverlay task"),
+        task::add_task(*shell);
+
+        // 空きフレーム不足時にコールドなページを圧縮する回収デーモン
+        let zramd = Box::new(
+            task::Task::new("Zramd", task::nice::MAX, zram::zramd_task)
+                .expect("Failed to create Zramd task"),
         );
-        task::add_task(*debug);
+        task::add_task(*zramd);
+
+        // バックグラウンドジョブ用のワーカープール
+        worker_pool::init(2).expect("Failed to initialize worker pool");
+
+        // 割り込みハンドラから処理を移すためのワークキュー
+        workqueue::init(2).expect("Failed to initialize work queue");
+
+        // ACPIモードへ切り替え、電源ボタンのSCIを有効化（電源管理タスクを起動）
+        if let Err(e) = power::init() {
+            warn!("ACPI power management not enabled: {}", e);
+        }
 
         info!("All tasks created. Setting up kernel main task...");
 
@@ -423,36 +678,44 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
 
         info!("Returned from scheduler! KernelMain task rescheduled, entering idle loop...");
 
-        // TaskWriterで情報を表示（Compositor経由）
-        let region = graphics::Region::new(10, 350, 700, 80);
-        let buffer =
-            graphics::compositor::register_writer(region).expect("Failed to register writer");
-        let mut writer = graphics::TaskWriter::new(buffer, 0xFFFFFFFF);
-
-        let _ = writeln!(
-            writer,
-            "Framebuffer: 0x{:X}, {}x{}",
-            boot_info.framebuffer.base, boot_info.framebuffer.width, boot_info.framebuffer.height
-        );
-        let _ = writeln!(writer, "Memory regions: {}", boot_info.memory_map_count);
-        let _ = writeln!(
-            writer,
-            "Largest usable memory: phys=0x{:X} virt=0x{:X} - 0x{:X} ({} MB)",
-            largest_start_phys,
-            largest_start_virt,
-            largest_start_virt + largest_size as u64,
-            largest_size / 1024 / 1024
-        );
-        let _ = writeln!(writer, "Heap initialized: {} KB", heap_size / 1024);
-
-        #[cfg(not(feature = "visualize-allocator"))]
-        {
-            let _ = writeln!(writer, "");
-            let _ = writeln!(writer, "Kernel running...");
-            let _ = writeln!(writer, "System ready.");
+        // システム情報ウィンドウに表示（Compositor経由）
+        if let Ok(window) = graphics::window::Window::create(
+            "System",
+            10,
+            338,
+            700,
+            80,
+            graphics::theme::background(),
+        ) {
+            let mut writer = window.writer(graphics::theme::foreground());
+
+            let _ = writeln!(
+                writer,
+                "Framebuffer: 0x{:X}, {}x{}",
+                boot_info.framebuffer.base,
+                boot_info.framebuffer.width,
+                boot_info.framebuffer.height
+            );
+            let _ = writeln!(writer, "Memory regions: {}", boot_info.memory_map_count);
+            let _ = writeln!(
+                writer,
+                "Largest usable memory: phys=0x{:X} virt=0x{:X} - 0x{:X} ({} MB)",
+                largest_start_phys,
+                largest_start_virt,
+                largest_start_virt + largest_size as u64,
+                largest_size / 1024 / 1024
+            );
+            let _ = writeln!(writer, "Heap initialized: {} KB", heap_size / 1024);
+
+            #[cfg(not(feature = "visualize-allocator"))]
+            {
+                let _ = writeln!(writer, "");
+                let _ = writeln!(writer, "Kernel running...");
+                let _ = writeln!(writer, "System ready.");
+            }
+            // ローカルバッファを共有バッファに一括転送
+            writer.flush();
         }
-        // ローカルバッファを共有バッファに一括転送
-        writer.flush();
 
         // ヒープが初期化されたので、タイマーを登録できる
         info!("Registering test timers...");
@@ -460,6 +723,7 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
         // 1秒後に実行されるタイマー
         timer::register_timer(
             timer::seconds_to_ticks(1),
+            timer::TimerClass::Background,
             Box::new(|| {
                 info!("Timer 1: 1 second elapsed!");
             }),
@@ -468,6 +732,7 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
         // 2秒後に実行されるタイマー
         timer::register_timer(
             timer::seconds_to_ticks(2),
+            timer::TimerClass::Background,
             Box::new(|| {
                 info!("Timer 2: 2 seconds elapsed!");
             }),
@@ -476,6 +741,7 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
         // 3秒後に実行されるタイマー
         timer::register_timer(
             timer::seconds_to_ticks(3),
+            timer::TimerClass::Background,
             Box::new(|| {
                 info!("Timer 3: 3 seconds elapsed!");
             }),
@@ -486,9 +752,14 @@ extern "C" fn kernel_main_inner(boot_info_phys_addr: u64) -> ! {
         error!("No usable memory found!");
     }
 
+    boot_health::report();
+
     info!("Entering main loop");
     boot_complete();
 
+    // コマンドラインで指定されたktestのシナリオを実行
+    ktest::run_at_boot();
+
     // KernelMainタスクは自身をブロックして停止
     loop {
         task::block_current_task();
@@ -0,0 +1,364 @@
+//! Machine Check Architecture（MCA）
+//!
+//! CPUが検出したハードウェアエラー（メモリ・キャッシュ・バスなど）は、エラーを検出した
+//! バンクの `IA32_MCi_STATUS` に記録され、訂正できないものは#MC（ベクタ18）で通知されます。
+//! CR4.MCEが無効のまま#MCが発生するとCPUはシャットダウンするため、各CPUの起動時に
+//! `init` で有効にします。
+//!
+//! #MCのハンドラ（`handle`）は全バンクの状態を読み出して内容を解読・表示し、
+//! 実行を継続できないエラーならパニックします。ハンドラは専用のISTスタックで動き、
+//! 割り込まれたコードがロックを保持している可能性があるため、表示はロックを使わない
+//! `println!` で行います。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::idt::InterruptFrame;
+use crate::{info, println, warn};
+
+/// IA32_MCG_CAP: バンク数と機能
+const IA32_MCG_CAP: u32 = 0x179;
+/// IA32_MCG_STATUS: #MC発生時のプロセッサの状態
+const IA32_MCG_STATUS: u32 = 0x17A;
+/// IA32_MCG_CTL: 全体の有効化（MCG_CAP.MCG_CTL_Pが立っている場合のみ存在）
+const IA32_MCG_CTL: u32 = 0x17B;
+/// IA32_MC0_CTL: バンク0の制御（バンクiのMSRは `IA32_MC0_CTL + 4 * i` から4つ並ぶ）
+const IA32_MC0_CTL: u32 = 0x400;
+
+/// CR4.MCE
+const CR4_MCE: u64 = 1 << 6;
+
+/// MCG_CAP: バンク数
+const MCG_CAP_COUNT_MASK: u64 = 0xFF;
+/// MCG_CAP: IA32_MCG_CTLが存在する
+const MCG_CAP_CTL_P: u64 = 1 << 8;
+
+/// MCG_STATUS: 割り込まれた位置（RIP）から再開できる
+const MCG_STATUS_RIPV: u64 = 1 << 0;
+/// MCG_STATUS: RIPがエラーを起こした命令を指す
+const MCG_STATUS_EIPV: u64 = 1 << 1;
+/// MCG_STATUS: #MCを処理中
+const MCG_STATUS_MCIP: u64 = 1 << 2;
+
+/// MCi_STATUS: 有効なエラーが記録されている
+const MCI_STATUS_VAL: u64 = 1 << 63;
+/// MCi_STATUS: 記録を読む前に次のエラーが発生した
+const MCI_STATUS_OVER: u64 = 1 << 62;
+/// MCi_STATUS: 訂正できなかった
+const MCI_STATUS_UC: u64 = 1 << 61;
+/// MCi_STATUS: MCi_CTLで通知が有効なエラー
+const MCI_STATUS_EN: u64 = 1 << 60;
+/// MCi_STATUS: MCi_MISCが有効
+const MCI_STATUS_MISCV: u64 = 1 << 59;
+/// MCi_STATUS: MCi_ADDRが有効
+const MCI_STATUS_ADDRV: u64 = 1 << 58;
+/// MCi_STATUS: プロセッサの状態が破損した可能性がある
+const MCI_STATUS_PCC: u64 = 1 << 57;
+
+/// 扱うバンク数の上限（MCG_CAPのCountは8ビット）
+const MAX_BANKS: usize = 32;
+
+/// #MCハンドラの呼び出し回数
+static MACHINE_CHECKS: AtomicU64 = AtomicU64::new(0);
+
+/// MSRの読み込み
+///
+/// # Safety
+/// - msrが有効なMSRアドレスであること
+/// - Ring 0で実行されること
+unsafe fn read_msr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    // SAFETY: 呼び出し元が有効なMSRアドレスを指定することを保証する
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") msr,
+            out("eax") low,
+            out("edx") high,
+            options(nostack, preserves_flags)
+        );
+    }
+    ((high as u64) << 32) | (low as u64)
+}
+
+/// MSRへの書き込み
+///
+/// # Safety
+/// - msrが有効なMSRアドレスで、valueがそのMSRに書き込める値であること
+/// - Ring 0で実行されること
+unsafe fn write_msr(msr: u32, value: u64) {
+    // SAFETY: 呼び出し元が有効なMSRアドレスと値を指定することを保証する
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// バンクに記録されたエラー
+#[derive(Debug, Clone, Copy)]
+pub struct BankError {
+    /// バンク番号
+    pub bank: usize,
+    /// IA32_MCi_STATUS
+    pub status: u64,
+    /// IA32_MCi_ADDR（ADDRVが立っていなければNone）
+    pub addr: Option<u64>,
+    /// IA32_MCi_MISC（MISCVが立っていなければNone）
+    pub misc: Option<u64>,
+}
+
+impl BankError {
+    /// バンクの記録を読み出す（有効なエラーがなければNone）
+    ///
+    /// # Safety
+    /// `bank` がMCG_CAPのバンク数未満であること
+    unsafe fn read(bank: usize) -> Option<Self> {
+        let base = IA32_MC0_CTL + 4 * bank as u32;
+        // SAFETY: 呼び出し元がバンクの存在を保証する。ADDR/MISCは対応するビットが立っている場合のみ読む
+        unsafe {
+            let status = read_msr(base + 1);
+            if status & MCI_STATUS_VAL == 0 {
+                return None;
+            }
+            let addr = (status & MCI_STATUS_ADDRV != 0).then(|| read_msr(base + 2));
+            let misc = (status & MCI_STATUS_MISCV != 0).then(|| read_msr(base + 3));
+            Some(Self {
+                bank,
+                status,
+                addr,
+                misc,
+            })
+        }
+    }
+
+    /// MCAエラーコード（下位16ビット）
+    pub fn error_code(&self) -> u16 {
+        self.status as u16
+    }
+
+    /// モデル固有のエラーコード
+    pub fn model_code(&self) -> u16 {
+        (self.status >> 16) as u16
+    }
+
+    /// 訂正できなかったか
+    pub fn is_uncorrected(&self) -> bool {
+        self.status & MCI_STATUS_UC != 0
+    }
+
+    /// プロセッサの状態が破損した可能性があるか
+    pub fn is_context_corrupt(&self) -> bool {
+        self.status & MCI_STATUS_PCC != 0
+    }
+
+    /// エラーの種類
+    ///
+    /// Intel SDM Vol.3Bの「Interpreting the MCA Error Codes」の分類に従います
+    /// （ビット12の通知フィルタは無視）。
+    pub fn kind(&self) -> &'static str {
+        let code = self.error_code() & !(1 << 12);
+        match code {
+            0x0000 => "no error",
+            0x0001 => "unclassified",
+            0x0002 => "microcode ROM parity error",
+            0x0003 => "external error",
+            0x0004 => "FRC error",
+            0x0005 => "internal parity error",
+            0x0006 => "SMM handler code access violation",
+            0x0400 => "internal timer error",
+            0x0401..=0x07FF => "internal unclassified",
+            _ if code & 0xEFF0 == 0x0010 => "TLB error",
+            _ if code & 0xEF80 == 0x0080 => "memory controller error",
+            _ if code & 0xEF00 == 0x0100 => "cache hierarchy error",
+            _ if code & 0xE800 == 0x0800 => "bus/interconnect error",
+            _ => "unknown",
+        }
+    }
+
+    /// 内容を出力
+    fn dump(&self) {
+        println!(
+            "  Bank {}: status 0x{:016X} ({}, code 0x{:04X}, model 0x{:04X})",
+            self.bank,
+            self.status,
+            self.kind(),
+            self.error_code(),
+            self.model_code()
+        );
+        println!(
+            "    {}{}{}{}",
+            if self.is_uncorrected() {
+                "uncorrected"
+            } else {
+                "corrected"
+            },
+            if self.is_context_corrupt() {
+                ", context corrupt"
+            } else {
+                ""
+            },
+            if self.status & MCI_STATUS_OVER != 0 {
+                ", overflow"
+            } else {
+                ""
+            },
+            if self.status & MCI_STATUS_EN != 0 {
+                ", signaled"
+            } else {
+                ""
+            }
+        );
+        if let Some(addr) = self.addr {
+            println!("    Address: 0x{:016X}", addr);
+        }
+        if let Some(misc) = self.misc {
+            println!("    Misc: 0x{:016X}", misc);
+        }
+    }
+}
+
+/// Machine Checkに対応しているか（MCEとMCAの両方）
+fn is_supported() -> bool {
+    let features = crate::cpu::features();
+    features.mce && features.mca
+}
+
+/// バンク数
+fn bank_count() -> usize {
+    // SAFETY: is_supportedでMCAの対応を確認済みの場合のみ呼び出す
+    let cap = unsafe { read_msr(IA32_MCG_CAP) };
+    ((cap & MCG_CAP_COUNT_MASK) as usize).min(MAX_BANKS)
+}
+
+/// 現在のCPUでMachine Checkを有効化
+///
+/// 全バンクのエラー通知を有効にし、起動前から残っている記録を警告として出力してから消去した後、
+/// CR4.MCEを立てます。各CPUの起動時に一度呼び出します。
+///
+/// # Returns
+/// 有効化したバンク数。CPUが対応していない場合はNone
+pub fn init() -> Option<usize> {
+    if !is_supported() {
+        return None;
+    }
+    let banks = bank_count();
+    // SAFETY: MCAの対応を確認済み。バンクのMSRはMCG_CAPのバンク数の範囲のみ操作し、
+    // MCi_CTLには全ビット1、MCi_STATUSには0を書き込む（いずれもSDMで許可された値）
+    unsafe {
+        if read_msr(IA32_MCG_CAP) & MCG_CAP_CTL_P != 0 {
+            write_msr(IA32_MCG_CTL, u64::MAX);
+        }
+        for bank in 0..banks {
+            if let Some(error) = BankError::read(bank) {
+                warn!(
+                    "MCE: bank {} has a record from before boot: status 0x{:016X} ({})",
+                    bank,
+                    error.status,
+                    error.kind()
+                );
+            }
+            let base = IA32_MC0_CTL + 4 * bank as u32;
+            write_msr(base, u64::MAX);
+            write_msr(base + 1, 0);
+        }
+
+        let cr4: u64;
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        asm!("mov cr4, {}", in(reg) cr4 | CR4_MCE, options(nostack, preserves_flags));
+    }
+    info!(
+        "Machine check enabled on CPU {} ({} banks)",
+        crate::percpu::current_index(),
+        banks
+    );
+    Some(banks)
+}
+
+/// #MCハンドラの呼び出し回数
+pub fn count() -> u64 {
+    MACHINE_CHECKS.load(Ordering::Relaxed)
+}
+
+/// Machine Check (#MC) を処理
+///
+/// 全バンクの記録を出力して消去します。訂正済みのエラーのみで、割り込まれた位置から
+/// 再開できる場合は戻ります。それ以外はパニックします。
+pub fn handle(frame: &InterruptFrame) {
+    MACHINE_CHECKS.fetch_add(1, Ordering::Relaxed);
+
+    // MCIPが立っていなければCPUが通知したものではない（int命令など）
+    let mcg_status = if is_supported() {
+        // SAFETY: MCAの対応を確認済み
+        unsafe { read_msr(IA32_MCG_STATUS) }
+    } else {
+        0
+    };
+    if mcg_status & MCG_STATUS_MCIP == 0 {
+        println!(
+            "Spurious machine check on CPU {} (RIP 0x{:X}), ignoring",
+            crate::percpu::current_index(),
+            frame.rip
+        );
+        return;
+    }
+
+    println!("\n\n");
+    println!("========================================");
+    println!("EXCEPTION: Machine Check (#MC)");
+    println!("========================================");
+    println!(
+        "CPU {}: MCG_STATUS 0x{:X} (RIPV={}, EIPV={})",
+        crate::percpu::current_index(),
+        mcg_status,
+        u8::from(mcg_status & MCG_STATUS_RIPV != 0),
+        u8::from(mcg_status & MCG_STATUS_EIPV != 0)
+    );
+
+    let mut first_fatal: Option<BankError> = None;
+    let mut errors = 0;
+    for bank in 0..bank_count() {
+        // SAFETY: バンク数の範囲内。記録を出力した後に0を書き込んで消去する
+        let Some(error) = (unsafe { BankError::read(bank) }) else {
+            continue;
+        };
+        errors += 1;
+        error.dump();
+        if first_fatal.is_none() && (error.is_uncorrected() || error.is_context_corrupt()) {
+            first_fatal = Some(error);
+        }
+        // SAFETY: 同上
+        unsafe { write_msr(IA32_MC0_CTL + 4 * bank as u32 + 1, 0) };
+    }
+    if errors == 0 {
+        println!("  No bank has a valid record.");
+    }
+    frame.dump();
+    println!("");
+
+    // MCIPを消去する（立ったまま次の#MCが発生するとCPUはシャットダウンする）
+    // SAFETY: MCG_STATUSへの0の書き込みはSDMで許可されている
+    unsafe { write_msr(IA32_MCG_STATUS, 0) };
+
+    if let Some(error) = first_fatal {
+        panic!(
+            "Machine check: {} in bank {} (status 0x{:016X}, RIP 0x{:X})",
+            error.kind(),
+            error.bank,
+            error.status,
+            frame.rip
+        );
+    }
+    if mcg_status & MCG_STATUS_RIPV == 0 {
+        panic!(
+            "Machine check: execution cannot be restarted (RIP 0x{:X})",
+            frame.rip
+        );
+    }
+    println!("Corrected machine check, continuing.");
+}
@@ -0,0 +1,372 @@
+//! メモリ帯域・レイテンシのマイクロベンチマーク（membench）
+//!
+//! ヒープ・シャドウバッファ・ハードウェアフレームバッファのそれぞれで、連続/ランダムの
+//! 読み書きを計測します。書き込みは通常のストアとノンテンポラルストア（MOVNTI）の両方を測ります。
+//! フレームバッファはファームウェアの設定によりライトコンバイニングやキャッシュ無効で
+//! マップされていることがあり、通常のメモリとは桁違いの結果になります。
+//! 結果はCompositorの転送方法やアロケータの最適化を選ぶ判断材料にします。
+//!
+//! # 計測方法
+//! - 連続: 8バイト単位で先頭から順に読み書きし、帯域（MB/s）を求めます
+//! - ランダム読み込み: キャッシュライン単位のポインタチェイスで、1回のロードのレイテンシを求めます
+//! - ランダム書き込み: キャッシュライン単位のランダムな位置へのストア1回あたりの時間を求めます
+//!
+//! ランダムな順序は、ライン数（2の累乗）を法とする最大周期の線形合同法で作るため、
+//! 順序表のためのメモリを使いません。
+//!
+//! `run_blit` は、Compositorが使うピクセルの行転送と塗りつぶしを、CPUが対応する
+//! 命令セット（スカラー・SSE2・AVX）ごとに計測し、SIMDによる高速化を確かめます。
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::hint::black_box;
+use spin::Mutex;
+
+use crate::graphics::compositor;
+use crate::graphics::shadow_buffer::ShadowBuffer;
+use crate::graphics::simd::{self, SimdLevel};
+use crate::hpet;
+
+/// 1つの対象で計測する最大バイト数
+const MAX_BENCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// ヒープで計測するバイト数
+///
+/// 4KB超のヒープ割り当ては解放できないため、初回に確保した領域を使い回します。
+const HEAP_BENCH_BYTES: usize = 512 * 1024;
+
+/// 1項目あたりの最短計測時間（ナノ秒）。短い計測はこの時間に達するまで繰り返す
+const MIN_MEASURE_NS: u64 = 20_000_000;
+
+/// キャッシュラインのサイズ（バイト）
+const CACHE_LINE: usize = 64;
+
+/// ヒープの計測領域（初回の計測時に確保）
+static HEAP_ARENA: Mutex<Option<Box<[u64]>>> = Mutex::new(None);
+
+/// 計測対象
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// カーネルヒープ
+    Heap,
+    /// シャドウバッファと同じ、フレームアロケータから確保したピクセルバッファ
+    Shadow,
+    /// ハードウェアフレームバッファ
+    Framebuffer,
+}
+
+impl Target {
+    /// すべての計測対象
+    pub const ALL: [Target; 3] = [Target::Heap, Target::Shadow, Target::Framebuffer];
+
+    /// 表示名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Target::Heap => "heap",
+            Target::Shadow => "shadow",
+            Target::Framebuffer => "fb",
+        }
+    }
+
+    /// 表示名から計測対象を取得
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == name)
+    }
+}
+
+/// membenchのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembenchError {
+    /// 時間計測に必要なHPETが利用できない
+    ClockUnavailable,
+    /// 計測対象が利用できない（Compositorが未初期化など）
+    TargetUnavailable,
+    /// 計測領域を確保できない
+    OutOfMemory,
+}
+
+impl core::fmt::Display for MembenchError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            MembenchError::ClockUnavailable => write!(f, "HPET is not available"),
+            MembenchError::TargetUnavailable => write!(f, "Target is not available"),
+            MembenchError::OutOfMemory => write!(f, "Failed to allocate the benchmark buffer"),
+        }
+    }
+}
+
+/// 1つの対象の計測結果
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    /// 計測したバイト数
+    pub bytes: usize,
+    /// 連続読み込みの帯域（MB/s）
+    pub seq_read_mbps: u64,
+    /// 連続書き込みの帯域（MB/s）
+    pub seq_write_mbps: u64,
+    /// ノンテンポラルストアによる連続書き込みの帯域（MB/s）
+    pub seq_write_nt_mbps: u64,
+    /// ランダム読み込みのレイテンシ（ナノ秒/ロード）
+    pub rand_read_ns: u64,
+    /// ランダム書き込みの時間（ナノ秒/ストア）
+    pub rand_write_ns: u64,
+    /// ノンテンポラルストアによるランダム書き込みの時間（ナノ秒/ストア）
+    pub rand_write_nt_ns: u64,
+}
+
+/// ピクセル転送の命令セットごとの計測結果
+#[derive(Debug, Clone, Copy)]
+pub struct BlitBenchResult {
+    /// 命令セット
+    pub level: SimdLevel,
+    /// 画面サイズのバッファ間の行転送の帯域（MB/s）
+    pub copy_mbps: u64,
+    /// 画面サイズのバッファの塗りつぶしの帯域（MB/s）
+    pub fill_mbps: u64,
+}
+
+/// 対象を計測
+///
+/// フレームバッファの計測は画面の内容を書き換えるため、終了後に画面全体を再描画させます。
+///
+/// # Errors
+/// * `MembenchError::ClockUnavailable` - HPETが利用できない場合
+/// * `MembenchError::TargetUnavailable` - Compositorが未初期化の場合（シャドウバッファ・フレームバッファ）
+/// * `MembenchError::OutOfMemory` - 計測領域を確保できない場合
+pub fn run(target: Target) -> Result<BenchResult, MembenchError> {
+    if !hpet::is_available() {
+        return Err(MembenchError::ClockUnavailable);
+    }
+    match target {
+        Target::Heap => {
+            let mut arena = HEAP_ARENA.lock();
+            if arena.is_none() {
+                let mut words = Vec::new();
+                words
+                    .try_reserve_exact(HEAP_BENCH_BYTES / 8)
+                    .map_err(|_| MembenchError::OutOfMemory)?;
+                words.resize(HEAP_BENCH_BYTES / 8, 0u64);
+                *arena = Some(words.into_boxed_slice());
+            }
+            let words = arena.as_mut().ok_or(MembenchError::OutOfMemory)?;
+            // SAFETY: 領域はロックで排他的に借用しており、範囲内のみアクセスする
+            Ok(unsafe { bench_region(words.as_mut_ptr(), words.len() * 8) })
+        }
+        Target::Shadow => {
+            let (width, height) = compositor::screen_size();
+            let mut shadow =
+                ShadowBuffer::new(width, height).map_err(|_| MembenchError::OutOfMemory)?;
+            let pixels = shadow.pixels_mut();
+            let bytes = (pixels.len() * 4).min(MAX_BENCH_BYTES);
+            // SAFETY: ページ単位で確保したバッファ（8バイト境界）で、範囲内のみアクセスする
+            Ok(unsafe { bench_region(pixels.as_mut_ptr() as *mut u64, bytes) })
+        }
+        Target::Framebuffer => {
+            let fb = compositor::framebuffer().ok_or(MembenchError::TargetUnavailable)?;
+            let bytes = fb.size_bytes().min(MAX_BENCH_BYTES);
+            // SAFETY: フレームバッファはマップ済みで、1行のピクセル数×高さ×4バイトの範囲内のみアクセスする。
+            // Compositorの転送と競合しても画素が乱れるだけで、直後に全体を再描画させる
+            let result = unsafe { bench_region(fb.base as *mut u64, bytes) };
+            compositor::redraw_all();
+            Ok(result)
+        }
+    }
+}
+
+/// ピクセルの行転送と塗りつぶしを、CPUが対応する命令セットごとに計測
+///
+/// シャドウバッファと同じ大きさのバッファを2つ確保し、Compositorの転送と同じく1行ずつコピーします。
+/// 計測中は使用する命令セットを切り替えるため、Compositorの転送も同じ命令セットで行われます
+/// （終了後に元に戻します）。
+///
+/// # Errors
+/// * `MembenchError::ClockUnavailable` - HPETが利用できない場合
+/// * `MembenchError::OutOfMemory` - 計測用のバッファを確保できない場合
+pub fn run_blit() -> Result<Vec<BlitBenchResult>, MembenchError> {
+    if !hpet::is_available() {
+        return Err(MembenchError::ClockUnavailable);
+    }
+    let (width, height) = compositor::screen_size();
+    let mut src = ShadowBuffer::new(width, height).map_err(|_| MembenchError::OutOfMemory)?;
+    let mut dst = ShadowBuffer::new(width, height).map_err(|_| MembenchError::OutOfMemory)?;
+    let (width, height) = (width as usize, height as usize);
+    let bytes = width * height * 4;
+    let src = src.pixels_mut();
+    let dst = dst.pixels_mut();
+
+    let original = simd::active();
+    let mut results = Vec::new();
+    for level in SimdLevel::ALL {
+        if simd::set_active(level).is_err() {
+            continue;
+        }
+        let copy_ns = measure(|| {
+            for (d, s) in dst.chunks_exact_mut(width).zip(src.chunks_exact(width)) {
+                simd::copy_row(d, s);
+            }
+            black_box(&mut *dst);
+        });
+        let fill_ns = measure(|| {
+            // SAFETY: dstはwidth×height個のピクセルを持つ
+            unsafe { simd::fill_pixels(dst.as_mut_ptr(), 0x0012_3456, width * height) };
+            black_box(&mut *dst);
+        });
+        results.push(BlitBenchResult {
+            level,
+            copy_mbps: mbps(bytes, copy_ns),
+            fill_mbps: mbps(bytes, fill_ns),
+        });
+    }
+    let _ = simd::set_active(original);
+    Ok(results)
+}
+
+/// 領域の計測（全項目）
+///
+/// # Safety
+/// `base` から `bytes` バイトが読み書き可能で、8バイト境界に揃っていること
+unsafe fn bench_region(base: *mut u64, bytes: usize) -> BenchResult {
+    let words = bytes / 8;
+    // ランダムアクセスはライン数を2の累乗に切り下げる（線形合同法の周期のため）
+    let lines = 1usize << (bytes / CACHE_LINE).max(1).ilog2();
+
+    // SAFETY: 各関数のアクセスは呼び出し元が保証する範囲内
+    unsafe {
+        BenchResult {
+            bytes,
+            seq_read_mbps: mbps(bytes, measure(|| seq_read(base, words))),
+            seq_write_mbps: mbps(bytes, measure(|| seq_write(base, words, false))),
+            seq_write_nt_mbps: mbps(bytes, measure(|| seq_write(base, words, true))),
+            rand_read_ns: per_op(lines, measure(|| pointer_chase(base, lines))),
+            rand_write_ns: per_op(lines, measure(|| rand_write(base, lines, false))),
+            rand_write_nt_ns: per_op(lines, measure(|| rand_write(base, lines, true))),
+        }
+    }
+}
+
+/// 1パスあたりの時間（ナノ秒）を計測
+///
+/// 最初に1回実行してキャッシュやTLBの状態を揃えた後、`MIN_MEASURE_NS` に達するまで繰り返します。
+fn measure(mut pass: impl FnMut()) -> u64 {
+    pass();
+    let start = hpet::elapsed_ns();
+    let mut passes = 0;
+    loop {
+        pass();
+        passes += 1;
+        let elapsed = hpet::elapsed_ns() - start;
+        if elapsed >= MIN_MEASURE_NS {
+            return (elapsed / passes).max(1);
+        }
+    }
+}
+
+/// 帯域（MB/s）
+fn mbps(bytes: usize, ns_per_pass: u64) -> u64 {
+    bytes as u64 * 1000 / ns_per_pass
+}
+
+/// 1操作あたりの時間（ナノ秒）
+fn per_op(ops: usize, ns_per_pass: u64) -> u64 {
+    ns_per_pass / ops as u64
+}
+
+/// 線形合同法で次のライン番号を求める（`lines` は2の累乗）
+///
+/// 乗数が4で割って1余り、増分が奇数のため、全ラインを1回ずつ巡回します。
+#[inline]
+fn next_line(line: usize, lines: usize) -> usize {
+    (line.wrapping_mul(1_103_515_245).wrapping_add(12_345)) & (lines - 1)
+}
+
+/// ノンテンポラルストア
+///
+/// # Safety
+/// `ptr` が書き込み可能で、8バイト境界に揃っていること
+#[inline]
+unsafe fn store_nt(ptr: *mut u64, value: u64) {
+    // SAFETY: 呼び出し元がptrの有効性を保証する
+    unsafe {
+        asm!("movnti [{}], {}", in(reg) ptr, in(reg) value, options(nostack, preserves_flags));
+    }
+}
+
+/// ノンテンポラルストアの完了を待つ
+fn store_fence() {
+    // SAFETY: SFENCEは先行するストアの順序を保証するのみ
+    unsafe {
+        asm!("sfence", options(nostack, preserves_flags));
+    }
+}
+
+/// 連続読み込み
+unsafe fn seq_read(base: *mut u64, words: usize) {
+    let mut sum = 0u64;
+    for i in 0..words {
+        // SAFETY: i < words で、呼び出し元が範囲を保証する
+        sum = sum.wrapping_add(unsafe { core::ptr::read_volatile(base.add(i)) });
+    }
+    black_box(sum);
+}
+
+/// 連続書き込み
+unsafe fn seq_write(base: *mut u64, words: usize, non_temporal: bool) {
+    for i in 0..words {
+        // SAFETY: i < words で、呼び出し元が範囲を保証する
+        unsafe {
+            if non_temporal {
+                store_nt(base.add(i), i as u64);
+            } else {
+                core::ptr::write_volatile(base.add(i), i as u64);
+            }
+        }
+    }
+    if non_temporal {
+        store_fence();
+    }
+}
+
+/// ポインタチェイス（各ラインの先頭に次のラインのアドレスを書いてから辿る）
+unsafe fn pointer_chase(base: *mut u64, lines: usize) {
+    let words_per_line = CACHE_LINE / 8;
+    let mut line = 0;
+    for _ in 0..lines {
+        let next = next_line(line, lines);
+        // SAFETY: line, next < lines で、呼び出し元が範囲を保証する
+        unsafe {
+            let next_addr = base.add(next * words_per_line) as u64;
+            core::ptr::write_volatile(base.add(line * words_per_line), next_addr);
+        }
+        line = next;
+    }
+
+    let mut ptr = base as *const u64;
+    for _ in 0..lines {
+        // SAFETY: チェインには領域内のアドレスだけを書き込んだ
+        ptr = unsafe { core::ptr::read_volatile(ptr) } as *const u64;
+    }
+    black_box(ptr);
+}
+
+/// ランダムな位置への書き込み
+unsafe fn rand_write(base: *mut u64, lines: usize, non_temporal: bool) {
+    let words_per_line = CACHE_LINE / 8;
+    let mut line = 0;
+    for i in 0..lines {
+        line = next_line(line, lines);
+        // SAFETY: line < lines で、呼び出し元が範囲を保証する
+        unsafe {
+            let ptr = base.add(line * words_per_line);
+            if non_temporal {
+                store_nt(ptr, i as u64);
+            } else {
+                core::ptr::write_volatile(ptr, i as u64);
+            }
+        }
+    }
+    if non_temporal {
+        store_fence();
+    }
+}
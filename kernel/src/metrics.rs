@@ -0,0 +1,223 @@
+//! メトリクスレジストリ
+//!
+//! 性能調査で繰り返し見たい数値（tick数、フレーム数、空きメモリ、実行可能キューの長さ、
+//! タスクごとのCPU時間など）を名前で一覧化し、同じ方法で取得できるようにします。
+//! 値の実体は各サブシステムが保持し、ここには取得関数のみを登録します。
+//! シェルの `watch` コマンドから使用します。
+//!
+//! # 式
+//! `watch` に渡す式は `name[:arg][/s]` の形式です。
+//! - `arg` は引数を取るメトリクス（`task.cpu` のタスクIDなど）に渡す数値
+//! - `/s` を付けると、前回の取得からの1秒あたりの増加量を返す（`frames/s` でFPSなど）
+
+use alloc::string::String;
+use core::fmt;
+
+use crate::graphics::compositor;
+use crate::paging::PAGE_SIZE;
+use crate::sched::{self, TaskId};
+use crate::{allocator, clock, frame_allocator, timer};
+
+/// メトリクス操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricError {
+    /// 存在しないメトリクス名
+    UnknownMetric,
+    /// 引数を取るメトリクスに引数がない
+    MissingArgument,
+    /// 引数を取らないメトリクスに引数がある
+    UnexpectedArgument,
+    /// 引数が数値でない
+    InvalidArgument,
+}
+
+impl fmt::Display for MetricError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetricError::UnknownMetric => write!(f, "Unknown metric"),
+            MetricError::MissingArgument => write!(f, "Metric requires an argument (name:arg)"),
+            MetricError::UnexpectedArgument => write!(f, "Metric takes no argument"),
+            MetricError::InvalidArgument => write!(f, "Invalid metric argument"),
+        }
+    }
+}
+
+/// メトリクスの定義
+pub struct Metric {
+    /// 名前（`subsystem.name`）
+    pub name: &'static str,
+    /// 1行の説明
+    pub help: &'static str,
+    /// 単位（表示用）
+    pub unit: &'static str,
+    /// 引数の説明（引数を取らなければNone）
+    pub arg: Option<&'static str>,
+    /// 値を取得（取得できなければNone）
+    sample: fn(u64) -> Option<u64>,
+}
+
+/// メトリクスの一覧
+pub const METRICS: &[Metric] = &[
+    Metric {
+        name: "tick",
+        help: "Timer ticks since boot",
+        unit: "ticks",
+        arg: None,
+        sample: |_| Some(timer::current_tick()),
+    },
+    Metric {
+        name: "uptime",
+        help: "Monotonic time since boot",
+        unit: "ms",
+        arg: None,
+        sample: |_| Some(clock::monotonic_ns() / 1_000_000),
+    },
+    Metric {
+        name: "frames",
+        help: "Frames composited since boot",
+        unit: "frames",
+        arg: None,
+        sample: |_| Some(compositor::frame_count()),
+    },
+    Metric {
+        name: "mem.free",
+        help: "Free physical memory",
+        unit: "KB",
+        arg: None,
+        sample: |_| Some((frame_allocator::stats().free_frames * (PAGE_SIZE / 1024)) as u64),
+    },
+    Metric {
+        name: "heap.used",
+        help: "Heap bytes currently allocated",
+        unit: "KB",
+        arg: None,
+        sample: |_| Some((allocator::stats().bytes_in_use / 1024) as u64),
+    },
+    Metric {
+        name: "heap.large",
+        help: "Heap used by large allocations",
+        unit: "KB",
+        arg: None,
+        sample: |_| Some((allocator::large_usage().0 / 1024) as u64),
+    },
+    Metric {
+        name: "runqueue",
+        help: "Runnable tasks waiting on all CPUs",
+        unit: "tasks",
+        arg: None,
+        sample: |_| Some(sched::runnable_count() as u64),
+    },
+    Metric {
+        name: "timers.pending",
+        help: "Expired timers waiting for their callback",
+        unit: "timers",
+        arg: None,
+        sample: |_| Some(timer::stats().pending as u64),
+    },
+    Metric {
+        name: "task.cpu",
+        help: "CPU time used by a task",
+        unit: "ms",
+        arg: Some("task_id"),
+        sample: |id| sched::task_runtime_ns(TaskId::from_u64(id)).map(|ns| ns / 1_000_000),
+    },
+];
+
+/// 名前でメトリクスを検索
+pub fn find(name: &str) -> Option<&'static Metric> {
+    METRICS.iter().find(|metric| metric.name == name)
+}
+
+/// 解析済みの式
+#[derive(Clone)]
+pub struct Expr {
+    /// 入力された式（表示用）
+    pub text: String,
+    metric: &'static Metric,
+    arg: u64,
+    /// 1秒あたりの増加量を返すか
+    rate: bool,
+}
+
+impl Expr {
+    /// 式を解析
+    ///
+    /// # Errors
+    /// * `MetricError::UnknownMetric` - 存在しないメトリクス名の場合
+    /// * `MetricError::MissingArgument` - 引数を取るメトリクスに引数がない場合
+    /// * `MetricError::UnexpectedArgument` - 引数を取らないメトリクスに引数がある場合
+    /// * `MetricError::InvalidArgument` - 引数が数値でない場合
+    pub fn parse(text: &str) -> Result<Self, MetricError> {
+        let (body, rate) = match text.strip_suffix("/s") {
+            Some(body) => (body, true),
+            None => (text, false),
+        };
+        let (name, arg) = match body.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (body, None),
+        };
+        let metric = find(name).ok_or(MetricError::UnknownMetric)?;
+        let arg = match (metric.arg, arg) {
+            (Some(_), Some(arg)) => arg.parse().map_err(|_| MetricError::InvalidArgument)?,
+            (Some(_), None) => return Err(MetricError::MissingArgument),
+            (None, Some(_)) => return Err(MetricError::UnexpectedArgument),
+            (None, None) => 0,
+        };
+        Ok(Self {
+            text: String::from(text),
+            metric,
+            arg,
+            rate,
+        })
+    }
+
+    /// 表示用の単位
+    pub fn unit(&self) -> &'static str {
+        self.metric.unit
+    }
+
+    /// 1秒あたりの増加量を返す式か
+    pub fn is_rate(&self) -> bool {
+        self.rate
+    }
+
+    /// メトリクスの現在値（`/s` は考慮しない）
+    pub fn sample(&self) -> Option<u64> {
+        (self.metric.sample)(self.arg)
+    }
+}
+
+/// 式を繰り返し評価する（`/s` の式では前回の値を覚えておく）
+pub struct Sampler {
+    expr: Expr,
+    /// 前回の (値, 単調時刻ns)
+    last: Option<(u64, u64)>,
+}
+
+impl Sampler {
+    pub fn new(expr: Expr) -> Self {
+        Self { expr, last: None }
+    }
+
+    /// 評価する式
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// 式を評価
+    ///
+    /// # Returns
+    /// 値。取得できない場合と、`/s` の式の初回はNone
+    pub fn sample(&mut self) -> Option<u64> {
+        let value = self.expr.sample()?;
+        if !self.expr.rate {
+            return Some(value);
+        }
+        let now = clock::monotonic_ns();
+        let previous = self.last.replace((value, now));
+        let (last_value, last_ns) = previous?;
+        let elapsed_ns = now.saturating_sub(last_ns).max(1);
+        Some((value.saturating_sub(last_value) as u128 * 1_000_000_000 / elapsed_ns as u128) as u64)
+    }
+}
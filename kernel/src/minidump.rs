@@ -0,0 +1,351 @@
+//! パニック時のミニダンプ
+//!
+//! パニックの原因調査に必要な最小限の情報（パニックメッセージ、レジスタ、現在のタスク、
+//! バックトレース、スタックの先頭、直近のトレースイベントとログ、メモリの概要）を、最大16KBのテキストレコードとして
+//! シリアルに出力します。カーネルはUEFIランタイムサービスを使用しないため、
+//! 永続的な出力先はシリアルのみです。
+//!
+//! メモリが深刻に破壊されていても出力できるよう、次の制約を守ります。
+//! - ヒープを使わない
+//! - ロックを待たない（取得できなければその項目を `busy` として省略する）
+//! - ページテーブルを辿らない（スタックは現在のRSPと同じページの範囲だけを読む）
+//!
+//! レコードは `==== MINIDUMP BEGIN` と `==== MINIDUMP END` で囲まれ、終端行に本文のバイト数と
+//! チェックサム（全バイトの32bit加算）を含むため、途切れたレコードを検出できます。
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::backtrace::{self, Frame};
+use crate::io::without_interrupts;
+use crate::log::Timestamp;
+use crate::paging::PAGE_SIZE;
+use crate::serial::SerialPort;
+use crate::{allocator, datetime, emergency, frame_allocator, klog, sched, timer, trace};
+
+/// レコード全体の最大サイズ
+const MAX_SIZE: usize = 16 * 1024;
+
+/// 終端行のために確保しておくサイズ
+const END_RESERVE: usize = 128;
+
+/// 出力するスタックの最大ワード数
+const STACK_WORDS: usize = 64;
+
+/// 出力するトレースイベントの最大数
+const TRACE_EVENTS: usize = 100;
+
+/// 出力するログメッセージの最大数
+const LOG_MESSAGES: usize = 32;
+
+/// レコードの形式のバージョン
+const VERSION: u32 = 3;
+
+/// 出力済みか（パニック中のパニックでは再出力しない）
+static WRITTEN: AtomicBool = AtomicBool::new(false);
+
+/// パニックした時点のレジスタ
+///
+/// パニックハンドラの先頭で取得するため、汎用レジスタの多くはパニック処理の途中の値です。
+/// rsp・rbp（バックトレースの起点）とCR2・CR3は原因の特定に使えます。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+}
+
+impl Registers {
+    /// 呼び出し元の時点のレジスタを取得
+    ///
+    /// 格納先のアドレスを保持するレジスタには、そのアドレスが記録されます。
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut regs = Self::default();
+        // SAFETY: regsへの書き込みは構造体の範囲内（repr(C)のu64 × 20）。
+        // raxは保存後に作業用に使うためclobberとして宣言する。
+        // pushfq/popはスタックを使うが、同じ命令列内で元に戻す。
+        unsafe {
+            core::arch::asm!(
+                "mov [{p} + 0x00], rax",
+                "mov [{p} + 0x08], rbx",
+                "mov [{p} + 0x10], rcx",
+                "mov [{p} + 0x18], rdx",
+                "mov [{p} + 0x20], rsi",
+                "mov [{p} + 0x28], rdi",
+                "mov [{p} + 0x30], rbp",
+                "mov [{p} + 0x38], rsp",
+                "mov [{p} + 0x40], r8",
+                "mov [{p} + 0x48], r9",
+                "mov [{p} + 0x50], r10",
+                "mov [{p} + 0x58], r11",
+                "mov [{p} + 0x60], r12",
+                "mov [{p} + 0x68], r13",
+                "mov [{p} + 0x70], r14",
+                "mov [{p} + 0x78], r15",
+                "lea rax, [rip]",
+                "mov [{p} + 0x80], rax",
+                "pushfq",
+                "pop rax",
+                "mov [{p} + 0x88], rax",
+                "mov rax, cr2",
+                "mov [{p} + 0x90], rax",
+                "mov rax, cr3",
+                "mov [{p} + 0x98], rax",
+                p = in(reg) &mut regs as *mut Self,
+                out("rax") _,
+            );
+        }
+        regs
+    }
+}
+
+const _: () = assert!(core::mem::offset_of!(Registers, cr3) == 0x98);
+
+/// 上限付きでシリアルに書き込み、バイト数とチェックサムを数えるWriter
+struct RecordWriter {
+    serial: SerialPort,
+    written: usize,
+    checksum: u32,
+    truncated: bool,
+}
+
+impl RecordWriter {
+    fn new() -> Self {
+        Self {
+            serial: SerialPort::new(crate::serial::COM1),
+            written: 0,
+            checksum: 0,
+            truncated: false,
+        }
+    }
+}
+
+impl Write for RecordWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.written >= MAX_SIZE - END_RESERVE {
+                self.truncated = true;
+                // 以降の書き込みを打ち切る
+                return Err(fmt::Error);
+            }
+            self.serial.write_byte(byte);
+            self.written += 1;
+            self.checksum = self.checksum.wrapping_add(byte as u32);
+        }
+        Ok(())
+    }
+}
+
+/// ミニダンプを出力
+///
+/// パニックハンドラから呼び出します。2回目以降の呼び出し（パニック中のパニック）では何もしません。
+///
+/// # Arguments
+/// * `regs` - パニックハンドラの先頭で取得したレジスタ
+pub fn write(info: &PanicInfo, regs: &Registers) {
+    if WRITTEN.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    // 出力中に他のタスクのログが混ざらないよう、割り込みを無効にして一度に書き出す
+    without_interrupts(|| {
+        let mut w = RecordWriter::new();
+        // 上限に達した場合は途中で打ち切り、終端行だけを書く
+        let _ = write_body(&mut w, info, regs);
+
+        let mut serial = SerialPort::new(crate::serial::COM1);
+        let _ = writeln!(
+            serial,
+            "\n==== MINIDUMP END len={} sum={:08X}{} ====",
+            w.written,
+            w.checksum,
+            if w.truncated { " truncated" } else { "" }
+        );
+        serial.flush();
+    });
+}
+
+fn write_body(w: &mut RecordWriter, info: &PanicInfo, regs: &Registers) -> fmt::Result {
+    writeln!(w, "\n==== MINIDUMP BEGIN v{} ====", VERSION)?;
+    writeln!(w, "panic: {}", info.message())?;
+    match info.location() {
+        Some(loc) => writeln!(
+            w,
+            "location: {}:{}:{}",
+            loc.file(),
+            loc.line(),
+            loc.column()
+        )?,
+        None => writeln!(w, "location: unknown")?,
+    }
+    writeln!(w, "tick: {}", timer::current_tick())?;
+    match datetime::now() {
+        Some(now) => writeln!(w, "time: {} UTC", now)?,
+        None => writeln!(w, "time: unknown")?,
+    }
+    match sched::current_task_id_lockless() {
+        Some(id) => writeln!(w, "task: {}", id.as_u64())?,
+        None => writeln!(w, "task: none")?,
+    }
+
+    write_registers(w, regs)?;
+    write_backtrace(w, regs.rbp)?;
+    write_stack(w, regs.rsp)?;
+    write_trace(w)?;
+    write_log(w)?;
+    write_memory(w)
+}
+
+/// レジスタを出力
+fn write_registers(w: &mut RecordWriter, regs: &Registers) -> fmt::Result {
+    writeln!(
+        w,
+        "rax={:016X} rbx={:016X} rcx={:016X} rdx={:016X}",
+        regs.rax, regs.rbx, regs.rcx, regs.rdx
+    )?;
+    writeln!(
+        w,
+        "rsi={:016X} rdi={:016X} rbp={:016X} rsp={:016X}",
+        regs.rsi, regs.rdi, regs.rbp, regs.rsp
+    )?;
+    writeln!(
+        w,
+        "r8={:016X} r9={:016X} r10={:016X} r11={:016X}",
+        regs.r8, regs.r9, regs.r10, regs.r11
+    )?;
+    writeln!(
+        w,
+        "r12={:016X} r13={:016X} r14={:016X} r15={:016X}",
+        regs.r12, regs.r13, regs.r14, regs.r15
+    )?;
+    writeln!(
+        w,
+        "rip={:016X} rflags={:016X} cr2={:016X} cr3={:016X}",
+        regs.rip, regs.rflags, regs.cr2, regs.cr3
+    )
+}
+
+/// フレームポインタを辿ったバックトレースを出力
+fn write_backtrace(w: &mut RecordWriter, rbp: u64) -> fmt::Result {
+    writeln!(w, "backtrace:")?;
+    let mut result = Ok(());
+    let mut index = 0;
+    backtrace::walk(rbp, |addr| {
+        if result.is_ok() {
+            result = writeln!(w, "  #{:<2} {}", index, Frame(addr));
+        }
+        index += 1;
+    });
+    result
+}
+
+/// RSPから同じページの終わりまで（最大 `STACK_WORDS` ワード）を出力
+///
+/// 現在使用中のページはマップされていることが確実なため、ページテーブルを辿らずに読めます。
+fn write_stack(w: &mut RecordWriter, rsp: u64) -> fmt::Result {
+    let start = rsp & !7;
+    let page_end = (rsp & !(PAGE_SIZE as u64 - 1)) + PAGE_SIZE as u64;
+    let words = (((page_end - start) / 8) as usize).min(STACK_WORDS);
+    writeln!(w, "stack: {} words at {:016X}", words, start)?;
+    for i in 0..words {
+        let addr = start + i as u64 * 8;
+        // SAFETY: addrは現在のスタックと同じページ内の8バイト境界のアドレスで、
+        // このページは実行中のコードが使用しているためマップされている。
+        let value = unsafe { core::ptr::read_volatile(addr as *const u64) };
+        if i % 4 == 0 {
+            write!(w, "  +{:03X}:", i * 8)?;
+        }
+        write!(w, " {:016X}", value)?;
+        if i % 4 == 3 || i + 1 == words {
+            writeln!(w)?;
+        }
+    }
+    Ok(())
+}
+
+/// 直近のトレースイベントを古い順に出力
+fn write_trace(w: &mut RecordWriter) -> fmt::Result {
+    writeln!(w, "trace: {} total, last {}", trace::total(), TRACE_EVENTS)?;
+    let mut result = Ok(());
+    trace::for_each_recent(TRACE_EVENTS, |event| {
+        if result.is_ok() {
+            result = writeln!(
+                w,
+                "  {:>10} {:<7} {:X} {:X} {}",
+                event.tick,
+                event.kind.name(),
+                event.args[0],
+                event.args[1],
+                event.label.as_str()
+            );
+        }
+    });
+    result
+}
+
+/// 直近のログメッセージを古い順に出力
+fn write_log(w: &mut RecordWriter) -> fmt::Result {
+    writeln!(w, "log: last {}", LOG_MESSAGES)?;
+    let mut result = Ok(());
+    let available = klog::for_each_recent(LOG_MESSAGES, |entry| {
+        if result.is_ok() {
+            result = writeln!(
+                w,
+                "  {} {:<5} {}: {}",
+                Timestamp(entry.time_ns),
+                entry.level.as_str(),
+                entry.module,
+                entry.text
+            );
+        }
+    });
+    if !available {
+        writeln!(w, "  busy")?;
+    }
+    result
+}
+
+/// ヒープとフレームの使用状況を出力
+fn write_memory(w: &mut RecordWriter) -> fmt::Result {
+    let (large_used, large_total) = allocator::large_usage();
+    writeln!(
+        w,
+        "heap: large {} / {} KB",
+        large_used / 1024,
+        large_total / 1024
+    )?;
+    let reserve = emergency::stats();
+    writeln!(
+        w,
+        "emergency: {} / {} bytes (peak {}, {} allocs, {} failed)",
+        reserve.used, reserve.capacity, reserve.peak, reserve.allocations, reserve.failures
+    )?;
+    match frame_allocator::try_stats() {
+        Some(stats) => writeln!(
+            w,
+            "frames: {} free / {} total",
+            stats.free_frames, stats.total_frames
+        ),
+        None => writeln!(w, "frames: busy"),
+    }
+}
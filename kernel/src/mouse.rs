@@ -0,0 +1,321 @@
+//! PS/2マウスドライバ
+//!
+//! 8042コントローラの補助ポートに接続されたマウスを有効化し、I/O APIC経由で
+//! IRQ12を受け取ります。3バイトの移動パケットをデコードしてカーソル位置
+//! （画面サイズでクランプ）とボタン状態を更新し、Compositorに再描画を通知します。
+//! カーソルの描画はCompositorタスクが行います。
+
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::graphics::compositor;
+use crate::io::{port_read_u8, port_write_u8, without_interrupts};
+use crate::irq::{self, IrqReturn};
+use crate::{info, ioapic, warn};
+
+/// マウス割り込みのベクタ番号
+pub const INTERRUPT_VECTOR: u8 = 44;
+
+/// マウスのISA IRQ番号
+const MOUSE_IRQ: u8 = 12;
+
+/// PS/2データポート
+const DATA_PORT: u16 = 0x60;
+
+/// PS/2ステータス/コマンドポート
+const STATUS_PORT: u16 = 0x64;
+
+/// ステータスレジスタのビット
+mod status {
+    /// 出力バッファにデータあり
+    pub const OUTPUT_FULL: u8 = 0x01;
+    /// 入力バッファにデータあり（コントローラが未処理）
+    pub const INPUT_FULL: u8 = 0x02;
+    /// 出力バッファのデータは補助ポート（マウス）から
+    pub const AUX_DATA: u8 = 0x20;
+}
+
+/// 8042コントローラコマンド
+mod controller {
+    pub const READ_CONFIG: u8 = 0x20;
+    pub const WRITE_CONFIG: u8 = 0x60;
+    pub const ENABLE_AUX: u8 = 0xA8;
+    /// 次のデータバイトを補助ポートへ送る
+    pub const WRITE_AUX: u8 = 0xD4;
+    /// 設定バイト: 補助ポートの割り込み有効
+    pub const CONFIG_AUX_IRQ: u8 = 0x02;
+    /// 設定バイト: 補助ポートのクロック無効
+    pub const CONFIG_AUX_CLOCK_DISABLE: u8 = 0x20;
+}
+
+/// マウスコマンド
+mod command {
+    pub const SET_DEFAULTS: u8 = 0xF6;
+    pub const ENABLE_REPORTING: u8 = 0xF4;
+    pub const ACK: u8 = 0xFA;
+}
+
+/// パケット1バイト目のビット
+mod packet {
+    pub const LEFT: u8 = 0x01;
+    pub const RIGHT: u8 = 0x02;
+    pub const MIDDLE: u8 = 0x04;
+    /// 常に1（パケット同期の確認に使う）
+    pub const ALWAYS_ONE: u8 = 0x08;
+    pub const X_SIGN: u8 = 0x10;
+    pub const Y_SIGN: u8 = 0x20;
+    pub const X_OVERFLOW: u8 = 0x40;
+    pub const Y_OVERFLOW: u8 = 0x80;
+}
+
+/// コントローラ応答待ちのポーリング回数上限
+const POLL_LIMIT: u32 = 100_000;
+
+/// まだ位置が決まっていないことを示すカーソル位置
+const POSITION_UNSET: u64 = u64::MAX;
+
+/// PS/2マウス操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseError {
+    /// コントローラが応答しない
+    Timeout,
+    /// マウスがACK以外を返した
+    NoAck(u8),
+}
+
+impl core::fmt::Display for MouseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            MouseError::Timeout => write!(f, "PS/2 controller timeout"),
+            MouseError::NoAck(byte) => write!(f, "Mouse did not acknowledge (0x{:02X})", byte),
+        }
+    }
+}
+
+/// マウスボタンの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// マウスが検出・初期化されたか
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+/// カーソル位置（上位32bit: x, 下位32bit: y）。1つのアトミックで読み書きして不整合を防ぐ
+static CURSOR_POSITION: AtomicU64 = AtomicU64::new(POSITION_UNSET);
+
+/// ボタン状態（パケット1バイト目の下位3ビット）
+static BUTTONS: AtomicU8 = AtomicU8::new(0);
+
+/// パケット組み立て中の状態（割り込みハンドラからのみ更新）
+struct PacketState {
+    bytes: [u8; 3],
+    index: usize,
+}
+
+static PACKET: Mutex<PacketState> = Mutex::new(PacketState {
+    bytes: [0; 3],
+    index: 0,
+});
+
+/// コントローラの入力バッファが空くまで待機
+fn wait_write() -> Result<(), MouseError> {
+    for _ in 0..POLL_LIMIT {
+        // SAFETY: 0x64はPS/2コントローラの標準ステータスポート
+        if unsafe { port_read_u8(STATUS_PORT) } & status::INPUT_FULL == 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(MouseError::Timeout)
+}
+
+/// コントローラの出力バッファにデータが来るまで待機して読み取る
+fn read_data() -> Result<u8, MouseError> {
+    for _ in 0..POLL_LIMIT {
+        // SAFETY: 0x64/0x60はPS/2コントローラの標準ポート
+        unsafe {
+            if port_read_u8(STATUS_PORT) & status::OUTPUT_FULL != 0 {
+                return Ok(port_read_u8(DATA_PORT));
+            }
+        }
+        core::hint::spin_loop();
+    }
+    Err(MouseError::Timeout)
+}
+
+/// コントローラにコマンドを送る
+fn write_controller(cmd: u8) -> Result<(), MouseError> {
+    wait_write()?;
+    // SAFETY: 0x64はPS/2コントローラの標準コマンドポート
+    unsafe { port_write_u8(STATUS_PORT, cmd) };
+    Ok(())
+}
+
+/// コントローラのデータポートに書き込む
+fn write_data(byte: u8) -> Result<(), MouseError> {
+    wait_write()?;
+    // SAFETY: 0x60はPS/2コントローラの標準データポート
+    unsafe { port_write_u8(DATA_PORT, byte) };
+    Ok(())
+}
+
+/// マウスにコマンドを送り、ACKを確認
+fn send_mouse_command(cmd: u8) -> Result<(), MouseError> {
+    write_controller(controller::WRITE_AUX)?;
+    write_data(cmd)?;
+    match read_data()? {
+        command::ACK => Ok(()),
+        other => Err(MouseError::NoAck(other)),
+    }
+}
+
+/// 補助ポートとマウスを有効化
+fn enable_device() -> Result<(), MouseError> {
+    write_controller(controller::ENABLE_AUX)?;
+
+    // 補助ポートの割り込みを有効化し、クロックを動かす
+    write_controller(controller::READ_CONFIG)?;
+    let config = read_data()?;
+    let config = (config | controller::CONFIG_AUX_IRQ) & !controller::CONFIG_AUX_CLOCK_DISABLE;
+    write_controller(controller::WRITE_CONFIG)?;
+    write_data(config)?;
+
+    send_mouse_command(command::SET_DEFAULTS)?;
+    send_mouse_command(command::ENABLE_REPORTING)
+}
+
+/// 移動量（9bit符号付き）をデコード
+fn decode_delta(value: u8, negative: bool) -> i32 {
+    if negative {
+        value as i32 - 256
+    } else {
+        value as i32
+    }
+}
+
+/// 画面中央（画面サイズ未確定なら原点）
+fn screen_center() -> (u32, u32) {
+    let (width, height) = compositor::screen_size();
+    (width / 2, height / 2)
+}
+
+/// 現在のカーソル位置を取得
+///
+/// # Returns
+/// (x, y) のピクセル座標。マウスが動いていなければ画面中央
+pub fn cursor_position() -> (u32, u32) {
+    match CURSOR_POSITION.load(Ordering::Acquire) {
+        POSITION_UNSET => screen_center(),
+        packed => ((packed >> 32) as u32, packed as u32),
+    }
+}
+
+/// 現在のボタン状態を取得
+#[allow(dead_code)]
+pub fn buttons() -> MouseButtons {
+    let bits = BUTTONS.load(Ordering::Relaxed);
+    MouseButtons {
+        left: bits & packet::LEFT != 0,
+        right: bits & packet::RIGHT != 0,
+        middle: bits & packet::MIDDLE != 0,
+    }
+}
+
+/// マウスが使用可能かどうか
+pub fn is_present() -> bool {
+    PRESENT.load(Ordering::Acquire)
+}
+
+/// 完成したパケットを適用
+fn apply_packet(bytes: [u8; 3]) {
+    let flags = bytes[0];
+    let new_buttons = flags & (packet::LEFT | packet::RIGHT | packet::MIDDLE);
+    let old_buttons = BUTTONS.swap(new_buttons, Ordering::Relaxed);
+
+    let mut moved = false;
+    if flags & (packet::X_OVERFLOW | packet::Y_OVERFLOW) == 0 {
+        let dx = decode_delta(bytes[1], flags & packet::X_SIGN != 0);
+        let dy = decode_delta(bytes[2], flags & packet::Y_SIGN != 0);
+        if dx != 0 || dy != 0 {
+            let (width, height) = compositor::screen_size();
+            let (x, y) = cursor_position();
+            // PS/2のYは上向きが正なので画面座標では反転する
+            let new_x = (x as i32 + dx).clamp(0, width.saturating_sub(1) as i32) as u32;
+            let new_y = (y as i32 - dy).clamp(0, height.saturating_sub(1) as i32) as u32;
+            CURSOR_POSITION.store(((new_x as u64) << 32) | new_y as u64, Ordering::Release);
+            moved = (new_x, new_y) != (x, y);
+        }
+    }
+
+    if moved || old_buttons != new_buttons {
+        compositor::notify_damage();
+    }
+}
+
+/// マウス割り込みハンドラ（`irq` から呼ばれる）
+fn handle_interrupt() -> IrqReturn {
+    // SAFETY: 0x64/0x60はPS/2コントローラの標準ポート
+    let byte = unsafe {
+        if port_read_u8(STATUS_PORT) & status::OUTPUT_FULL == 0 {
+            return IrqReturn::Unhandled;
+        }
+        port_read_u8(DATA_PORT)
+    };
+
+    // 割り込みハンドラ内（割り込み無効）なのでそのままロックを取得できる
+    let completed = {
+        let mut state = PACKET.lock();
+        // 1バイト目はbit3が常に1。ずれていたら同期し直す
+        if state.index == 0 && byte & packet::ALWAYS_ONE == 0 {
+            return IrqReturn::Handled;
+        }
+        let index = state.index;
+        state.bytes[index] = byte;
+        state.index += 1;
+        if state.index == 3 {
+            state.index = 0;
+            Some(state.bytes)
+        } else {
+            None
+        }
+    };
+
+    if let Some(bytes) = completed {
+        apply_packet(bytes);
+    }
+    IrqReturn::Handled
+}
+
+/// 出力バッファがキーボードではなくマウスのデータを保持しているか
+///
+/// キーボード割り込みハンドラがマウスのバイトを読み捨てないために使用します。
+pub fn is_aux_data(status_byte: u8) -> bool {
+    status_byte & status::AUX_DATA != 0
+}
+
+/// マウスを初期化してIRQ12をルーティング
+///
+/// I/O APICとキーボードの初期化後に呼び出します。
+pub fn init() {
+    // 初期化中のACKをキーボード割り込みに横取りされないよう割り込みを無効化
+    if let Err(e) = without_interrupts(enable_device) {
+        warn!("PS/2 mouse not available: {}", e);
+        return;
+    }
+
+    if let Err(e) = irq::request_irq(INTERRUPT_VECTOR, handle_interrupt, "mouse") {
+        warn!("PS/2 mouse IRQ handler not registered: {}", e);
+        return;
+    }
+    match ioapic::route_isa_irq(MOUSE_IRQ, INTERRUPT_VECTOR) {
+        Ok(gsi) => {
+            PRESENT.store(true, Ordering::Release);
+            info!("PS/2 mouse initialized (GSI {})", gsi);
+        }
+        Err(e) => warn!("PS/2 mouse IRQ not routed: {}", e),
+    }
+}
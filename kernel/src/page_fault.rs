@@ -0,0 +1,313 @@
+//! ページフォルトの解決
+//!
+//! ページフォルトハンドラから呼ばれ、フォルトの原因を順に調べて解決できるものはマッピングを
+//! 修正します。解決できた場合、ハンドラはiretqで復帰してフォルトした命令を再実行します。
+//!
+//! 解決できるフォルト:
+//! - コピーオンライトのページへの書き込み（フレームを複製して書き込み可能にする）
+//! - 圧縮スワップに追い出されたページ（`zram`）
+//! - 予約済みのデマンドゼロ領域（最初のアクセスでゼロクリアしたフレームを割り当てる）
+//! - ユーザースタックの伸長（ユーザーモードからのアクセスのみ）
+//!
+//! どれにも該当しないアクセスは不正なアクセスとして扱い、ハンドラがユーザータスクの終了または
+//! カーネルパニックを行います。ただし `probe_read_u64` の読み込み命令でのフォルトは、
+//! ハンドラがフィックスアップ先へ復帰させ、呼び出し元に失敗として返します。
+
+use core::arch::global_asm;
+use spin::Mutex;
+
+use crate::idt::InterruptFrame;
+use crate::io::without_interrupts;
+use crate::paging::{self, PAGE_SIZE, PageTableFlags, PagingError};
+use crate::{elf_loader, frame_allocator, zram};
+
+/// ページフォルトのエラーコードのビット
+pub mod error_code {
+    /// ページは存在する（保護違反）
+    pub const PRESENT: u64 = 1 << 0;
+    /// 書き込みアクセス
+    pub const WRITE: u64 = 1 << 1;
+    /// ユーザーモードからのアクセス
+    pub const USER: u64 = 1 << 2;
+    /// 予約ビット違反
+    pub const RESERVED: u64 = 1 << 3;
+    /// 命令フェッチ
+    #[allow(dead_code)]
+    pub const INSTRUCTION: u64 = 1 << 4;
+}
+
+/// 同時に予約できるデマンドゼロ領域の数
+const MAX_DEMAND_ZERO_REGIONS: usize = 8;
+
+/// ページフォルトの情報
+#[derive(Debug, Clone, Copy)]
+pub struct PageFault {
+    /// フォルトしたアドレス（CR2）
+    pub addr: u64,
+    /// CPUが積んだエラーコード
+    pub error_code: u64,
+    /// フォルトした時点の割り込みフレーム
+    pub frame: InterruptFrame,
+}
+
+impl PageFault {
+    /// ページは存在していたか（falseならページ未マップ）
+    pub fn is_present(&self) -> bool {
+        self.error_code & error_code::PRESENT != 0
+    }
+
+    /// 書き込みアクセスか
+    pub fn is_write(&self) -> bool {
+        self.error_code & error_code::WRITE != 0
+    }
+
+    /// ユーザーモードからのアクセスか
+    pub fn is_user(&self) -> bool {
+        self.error_code & error_code::USER != 0
+    }
+
+    /// フォルトしたページの先頭アドレス
+    fn page(&self) -> u64 {
+        self.addr & !(PAGE_SIZE as u64 - 1)
+    }
+}
+
+/// ページフォルト処理のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultError {
+    /// どの解決方法にも該当しない不正なアクセス
+    InvalidAccess,
+    /// ページテーブルの操作中にフォルトした
+    PageTablesLocked,
+    /// フレームを確保できない
+    OutOfMemory,
+    /// マッピングの変更に失敗
+    Paging(PagingError),
+    /// デマンドゼロ領域の予約数が上限に達している
+    TooManyRegions,
+    /// 領域が4KB境界に揃っていない、既存の領域と重なる、または見つからない
+    InvalidRegion,
+}
+
+impl core::fmt::Display for FaultError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            FaultError::InvalidAccess => write!(f, "Invalid memory access"),
+            FaultError::PageTablesLocked => write!(f, "Fault while page tables are locked"),
+            FaultError::OutOfMemory => write!(f, "Out of memory"),
+            FaultError::Paging(e) => write!(f, "{}", e),
+            FaultError::TooManyRegions => write!(f, "Too many demand-zero regions"),
+            FaultError::InvalidRegion => write!(f, "Invalid demand-zero region"),
+        }
+    }
+}
+
+impl From<PagingError> for FaultError {
+    fn from(e: PagingError) -> Self {
+        match e {
+            PagingError::FrameAllocationFailed => FaultError::OutOfMemory,
+            e => FaultError::Paging(e),
+        }
+    }
+}
+
+/// デマンドゼロ領域（アクセスされたページから順にフレームを割り当てる）
+#[derive(Debug, Clone, Copy)]
+struct DemandZeroRegion {
+    start: u64,
+    end: u64,
+    /// PTEのフラグ（Presentは自動付与）
+    flags: u64,
+}
+
+impl DemandZeroRegion {
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr < self.end
+    }
+}
+
+static DEMAND_ZERO_REGIONS: Mutex<[Option<DemandZeroRegion>; MAX_DEMAND_ZERO_REGIONS]> =
+    Mutex::new([None; MAX_DEMAND_ZERO_REGIONS]);
+
+// フォルトしても回復できる8バイト読み込み（RDI=アドレス、RSI=格納先、RAX=0なら成功）
+//
+// `page_fault_probe_load` の命令が解決できないページフォルトを起こすと、ハンドラは
+// RIPを `page_fault_probe_fixup` に書き換えて復帰し、RAX=1を返させる。
+global_asm!(
+    ".pushsection .text.page_fault_probe, \"ax\"",
+    ".global page_fault_probe_read",
+    ".global page_fault_probe_load",
+    ".global page_fault_probe_fixup",
+    "page_fault_probe_read:",
+    "page_fault_probe_load:",
+    "    mov rax, [rdi]",
+    "    mov [rsi], rax",
+    "    xor eax, eax",
+    "    ret",
+    "page_fault_probe_fixup:",
+    "    mov eax, 1",
+    "    ret",
+    ".popsection",
+);
+
+unsafe extern "C" {
+    fn page_fault_probe_read(addr: u64, out: *mut u64) -> u64;
+    static page_fault_probe_load: u8;
+    static page_fault_probe_fixup: u8;
+}
+
+/// アドレスから8バイトの読み込みを試す
+///
+/// 解決できないページフォルトが起きてもパニックせず、Noneを返します。
+/// デマンドゼロ領域などの解決できるフォルトは通常どおり解決してから読み込みます。
+/// 非カノニカルアドレス（#GP）は対象外です。
+pub fn probe_read_u64(addr: u64) -> Option<u64> {
+    let mut value = 0;
+    // SAFETY: 読み込みでのフォルトはハンドラがフィックスアップ先へ復帰させる。
+    // 格納先はこの関数のローカル変数
+    let failed = unsafe { page_fault_probe_read(addr, &mut value) };
+    (failed == 0).then_some(value)
+}
+
+/// フォルトした命令のフィックスアップ先
+///
+/// カーネルモードの解決できないフォルトでハンドラから呼ばれます。
+///
+/// # Returns
+/// `probe_read_u64` の読み込み命令なら、失敗を返す復帰先のアドレス
+pub fn fixup_address(rip: u64) -> Option<u64> {
+    let load = &raw const page_fault_probe_load as u64;
+    (rip == load).then_some(&raw const page_fault_probe_fixup as u64)
+}
+
+/// ページフォルトを解決する
+///
+/// # Returns
+/// 解決できればOk。ハンドラは復帰してフォルトした命令を再実行できる
+///
+/// # Errors
+/// * `FaultError::InvalidAccess` - 解決方法がない不正なアクセスの場合
+/// * その他 - 解決を試みたが失敗した場合
+pub fn resolve(fault: &PageFault) -> Result<(), FaultError> {
+    // ページテーブルのロック保持中のフォルトは解決できない（解決にロックが必要）
+    if paging::page_tables_locked() {
+        return Err(FaultError::PageTablesLocked);
+    }
+    if fault.error_code & error_code::RESERVED != 0 {
+        return Err(FaultError::InvalidAccess);
+    }
+
+    if fault.is_present() {
+        if fault.is_write() && paging::break_copy_on_write(fault.page())? {
+            return Ok(());
+        }
+        return Err(FaultError::InvalidAccess);
+    }
+
+    if zram::handle_page_fault(fault.addr) {
+        return Ok(());
+    }
+    if let Some(flags) = demand_zero_flags(fault.addr) {
+        return map_zeroed_page(fault.page(), flags);
+    }
+    if fault.is_user() && elf_loader::is_stack_growth(fault.addr) {
+        let flags = PageTableFlags::UserAccessible as u64
+            | PageTableFlags::Writable as u64
+            | paging::no_execute_flag();
+        return map_zeroed_page(fault.page(), flags);
+    }
+    Err(FaultError::InvalidAccess)
+}
+
+/// アドレスを含むデマンドゼロ領域のフラグ
+fn demand_zero_flags(addr: u64) -> Option<u64> {
+    // 例外ハンドラ内（割り込み無効）。予約・解放の途中ならその領域は扱わない
+    let regions = DEMAND_ZERO_REGIONS.try_lock()?;
+    regions
+        .iter()
+        .flatten()
+        .find(|r| r.contains(addr))
+        .map(|r| r.flags)
+}
+
+/// ゼロクリアしたフレームを現在のアドレス空間にマップ
+fn map_zeroed_page(page: u64, flags: u64) -> Result<(), FaultError> {
+    let frame = frame_allocator::alloc_frame().ok_or(FaultError::OutOfMemory)?;
+    let result = paging::phys_to_virt(frame).and_then(|frame_virt| {
+        // SAFETY: 確保直後のフレームは直接マッピング経由で書き込め、他から参照されていない
+        unsafe { core::ptr::write_bytes(frame_virt as *mut u8, 0, PAGE_SIZE) };
+        paging::map_page(page, frame, flags)
+    });
+    result.map_err(|e| {
+        let _ = frame_allocator::free_frame(frame);
+        FaultError::from(e)
+    })
+}
+
+/// デマンドゼロ領域を予約
+///
+/// 領域内のページは最初にアクセスされたときに、ゼロクリアしたフレームが割り当てられます。
+/// 予約した範囲は他のマッピングと重ならないようにしてください。
+///
+/// # Arguments
+/// * `start` - 先頭アドレス（4KBアライン）
+/// * `len` - バイト数（4KBの倍数）
+/// * `flags` - 割り当てるページのPTEフラグ（Presentは自動付与）
+///
+/// # Errors
+/// * `FaultError::InvalidRegion` - アラインされていない、または既存の領域と重なる場合
+/// * `FaultError::TooManyRegions` - 予約数が上限に達している場合
+pub fn reserve_demand_zero(start: u64, len: u64, flags: u64) -> Result<(), FaultError> {
+    let page_size = PAGE_SIZE as u64;
+    let end = start.checked_add(len).ok_or(FaultError::InvalidRegion)?;
+    if len == 0 || !start.is_multiple_of(page_size) || !len.is_multiple_of(page_size) {
+        return Err(FaultError::InvalidRegion);
+    }
+    without_interrupts(|| {
+        let mut regions = DEMAND_ZERO_REGIONS.lock();
+        if regions
+            .iter()
+            .flatten()
+            .any(|r| start < r.end && r.start < end)
+        {
+            return Err(FaultError::InvalidRegion);
+        }
+        let slot = regions
+            .iter_mut()
+            .find(|r| r.is_none())
+            .ok_or(FaultError::TooManyRegions)?;
+        *slot = Some(DemandZeroRegion { start, end, flags });
+        Ok(())
+    })
+}
+
+/// デマンドゼロ領域の予約を解除し、割り当て済みのフレームを解放
+///
+/// # Arguments
+/// * `start` - `reserve_demand_zero` に渡した先頭アドレス
+///
+/// # Returns
+/// 解放したフレーム数
+///
+/// # Errors
+/// * `FaultError::InvalidRegion` - 該当する領域がない場合
+pub fn release_demand_zero(start: u64) -> Result<usize, FaultError> {
+    let region = without_interrupts(|| {
+        DEMAND_ZERO_REGIONS
+            .lock()
+            .iter_mut()
+            .find(|r| r.is_some_and(|r| r.start == start))
+            .and_then(|r| r.take())
+    })
+    .ok_or(FaultError::InvalidRegion)?;
+
+    let mut freed = 0;
+    for page in (region.start..region.end).step_by(PAGE_SIZE) {
+        if let Ok(frame) = paging::unmap_page(page) {
+            let _ = frame_allocator::free_frame(frame);
+            freed += 1;
+        }
+    }
+    Ok(freed)
+}
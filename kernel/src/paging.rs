@@ -4,6 +4,11 @@
 
 use core::arch::asm;
 use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use vitros_common::boot_info::{KernelSegment, MAX_MEMORY_REGIONS};
+use vitros_common::elf::{PF_W, PF_X};
+use vitros_common::uefi;
 
 /// ハイヤーハーフカーネルのベースアドレス（上位カノニカルアドレス空間）
 /// x86_64のカノニカルアドレス空間の上位半分の開始位置
@@ -25,6 +30,16 @@ pub enum PagingError {
     AcpiAddressInvalid,
     /// チェックサム検証失敗
     ChecksumFailed,
+    /// 仮想アドレスが既にマップされている
+    AlreadyMapped,
+    /// 仮想アドレスがマップされていない
+    NotMapped,
+    /// ページテーブル用の物理フレームを確保できない
+    FrameAllocationFailed,
+    /// 経路上に2MB/1GBのヒュージページが存在する
+    HugePageConflict,
+    /// PATが利用できない（Write-Combiningを選べない）
+    PatUnavailable,
 }
 
 impl core::fmt::Display for PagingError {
@@ -36,6 +51,13 @@ impl core::fmt::Display for PagingError {
             PagingError::PageTableInitFailed => write!(f, "Page table initialization failed"),
             PagingError::AcpiAddressInvalid => write!(f, "ACPI address is invalid"),
             PagingError::ChecksumFailed => write!(f, "Checksum verification failed"),
+            PagingError::AlreadyMapped => write!(f, "Virtual address is already mapped"),
+            PagingError::NotMapped => write!(f, "Virtual address is not mapped"),
+            PagingError::FrameAllocationFailed => {
+                write!(f, "Failed to allocate frame for page table")
+            }
+            PagingError::HugePageConflict => write!(f, "Address is covered by a huge page"),
+            PagingError::PatUnavailable => write!(f, "PAT is not available"),
         }
     }
 }
@@ -250,120 +272,129 @@ pub unsafe extern "C" fn switch_to_kernel_stack() {
     )
 }
 
-// グローバルページテーブルを静的に確保
-// 物理メモリの直接マッピング（Direct Mapping）を実装
+// 物理メモリの直接マッピング（Direct Mapping）
+//
+// UEFIメモリマップにあるメモリだけを、できるだけ大きなページ（1GB/2MB）でマップします。
+// 中間テーブルはフレームアロケータから確保するため、`init` の前にフレームアロケータを
+// 初期化しておく必要があります。デバイスのレジスタ（Local APIC、HPET、MMCONFIG、
+// フレームバッファなど）はメモリマップに含まれないため、使う時に `map_mmio` でマップします。
 
-/// 最大サポートメモリ（GB単位）
-/// 静的配列のサイズを決定する - 4GB対応で約16MBのメモリ削減
+/// フレームアロケータが管理する物理メモリの上限（GB単位）
 pub const MAX_SUPPORTED_MEMORY_GB: usize = 4;
 
-/// Page Table数（各PTは2MBをカバー）
-/// 4GB = 2048個のPT（512 * 4 = 2048）
-const PT_COUNT: usize = MAX_SUPPORTED_MEMORY_GB * 512;
+/// 直接マッピングする物理アドレスの上限（PML4の1エントリ分 = 512GB）
+const DIRECT_MAP_LIMIT: u64 = 1 << 39;
+
+/// 2MBページの大きさ
+const HUGE_PAGE_2M: u64 = 1 << 21;
+
+/// 1GBページの大きさ
+const HUGE_PAGE_1G: u64 = 1 << 30;
+
+/// ヒュージページ（2MB/1GB）のエントリでPATエントリを選ぶビット
+const PTE_HUGE_PAT: u64 = 1 << 12;
 
 static mut KERNEL_PML4: PageTable = PageTable::new();
-static mut KERNEL_PDP_HIGH: PageTable = PageTable::new(); // 高位アドレス用（0xFFFF_8000_0000_0000〜）
 
-// Page Directory（4GB分確保、高位アドレスのみ）
-static mut KERNEL_PD_HIGH: [PageTable; MAX_SUPPORTED_MEMORY_GB] =
-    [PageTable::new(); MAX_SUPPORTED_MEMORY_GB];
+unsafe extern "C" {
+    /// カーネルイメージの終端（リンカスクリプトで定義、仮想アドレス）
+    static __kernel_end: u8;
+}
 
-// Page Table（4GB全体を4KBページでマップするため2,048個のPTが必要、高位アドレスのみ）
-// 各PT = 512エントリ × 4KB = 2MB
-// 4GB = 2,048個のPT
-// 低位アドレスはアンマップ（ハイヤーハーフカーネル）
-static mut KERNEL_PT_HIGH: [PageTable; PT_COUNT] = [PageTable::new(); PT_COUNT];
+/// 直接マッピングに使ったページの数（4KB, 2MB, 1GB）
+#[derive(Debug, Clone, Copy, Default)]
+struct DirectMapStats {
+    pages_4k: usize,
+    pages_2m: usize,
+    pages_1g: usize,
+}
 
 /// ページングシステムを初期化してCR3に設定
 /// 物理メモリの直接マッピング（Direct Mapping）を実装
 /// - 低位アドレス（0x0〜）: アンマップ（ハイヤーハーフカーネル）
 /// - 高位アドレス（0xFFFF_8000_0000_0000+）: カーネル用の直接マッピング
 ///
-/// UEFIメモリマップに基づいて、実際に利用可能なメモリ範囲のみをマッピングする。
-/// 最大サポートメモリは MAX_SUPPORTED_MEMORY_GB (4GB) まで。
+/// UEFIメモリマップのうちMMIO以外の領域だけを、1GB/2MBページを優先してマップする。
+/// 物理アドレス0からカーネルイメージ末尾までは、ページ単位で保護できるよう4KBページでマップする。
+///
+/// 直接マッピングは書き込み可・実行禁止（NX）とし、カーネルイメージだけはブートローダーが
+/// 記録したLOADセグメントの属性に従って保護する（.textは読み取り・実行、.rodataは読み取りのみ、
+/// .data/.bssは読み書き）。NXとCR0.WPはここで有効にする。
 ///
 /// # Arguments
 /// * `boot_info` - ブートローダから渡されたメモリ情報
 ///
 /// # Errors
+/// * `PagingError::FrameAllocationFailed` - ページテーブル用のフレームを確保できない場合
 /// * `PagingError::AddressConversionFailed` - アドレス変換に失敗した場合
 /// * `PagingError::GuardPageSetupFailed` - Guard Page設定に失敗した場合
 pub fn init(boot_info: &vitros_common::boot_info::BootInfo) -> Result<(), PagingError> {
-    // サポートする最大アドレスを計算
-    let max_supported = (MAX_SUPPORTED_MEMORY_GB as u64) << 30; // 4GB
-    let actual_max = boot_info.max_physical_address.min(max_supported);
-
-    // 必要なPD数とPT数を計算
-    // 1 PT = 512 * 4KB = 2MB
-    let required_pt_count = ((actual_max + (2 << 20) - 1) / (2 << 20)) as usize;
-    let required_pd_count = (required_pt_count + 511) / 512;
+    use crate::{info, warn};
 
-    use crate::info;
-    info!(
-        "Paging: Mapping {} MB of physical memory",
-        actual_max / (1 << 20)
-    );
-    info!(
-        "Paging: Using {} PDs and {} PTs",
-        required_pd_count, required_pt_count
-    );
+    // 基本フラグ: Present + Writable
+    let flags = PageTableFlags::Present as u64 | PageTableFlags::Writable as u64;
 
+    // ページ単位の保護はNXEとCR0.WPを有効にしてから（NXEなしでNXビットを立てると予約ビット違反）
+    let nx = enable_no_execute();
+    NX_ENABLED.store(nx, Ordering::Relaxed);
+    if !nx {
+        warn!("Paging: NX is not supported; data pages remain executable");
+    }
+    enable_write_protect();
+    let segments = boot_info.kernel_segments();
+    // セグメントの情報がなければカーネルイメージを区別できないため、実行禁止にしない
+    let page_flags = if segments.is_empty() {
+        warn!("Paging: kernel segments unknown; kernel pages are not protected");
+        flags
+    } else {
+        flags | no_execute_flag()
+    };
+
+    let use_1g = crate::cpu::features().page_1g;
+    // __kernel_endはリンカが定義するシンボルで、値ではなくアドレスのみを参照する
+    let kernel_end_phys =
+        virt_to_phys(&raw const __kernel_end as u64)?.next_multiple_of(PAGE_SIZE as u64);
+
+    let (ranges, count) = direct_map_ranges(boot_info);
+    let mut stats = DirectMapStats::default();
+
+    // SAFETY: 起動時はこのCPUだけが実行しており、KERNEL_PML4はまだCR3に読み込まれていない。
+    // 中間テーブルはフレームアロケータから確保し、ブートローダーの直接マッピング経由で書き込む
     unsafe {
-        // 生ポインタを取得（高位アドレス用のみ）
-        let pml4 = addr_of_mut!(KERNEL_PML4);
-        let pdp_high = addr_of_mut!(KERNEL_PDP_HIGH);
-        let pd_high = addr_of_mut!(KERNEL_PD_HIGH);
-        let pt_high = addr_of_mut!(KERNEL_PT_HIGH);
-
-        // すべてのテーブルをクリア
-        (*pml4).clear();
-        (*pdp_high).clear();
-        for i in 0..MAX_SUPPORTED_MEMORY_GB {
-            (*pd_high)[i].clear();
-        }
-        for i in 0..PT_COUNT {
-            (*pt_high)[i].clear();
-        }
-
-        // 基本フラグ: Present + Writable
-        let flags = PageTableFlags::Present as u64 | PageTableFlags::Writable as u64;
-
-        // === PML4の設定 ===
-        // 低位アドレス（0x0〜）はアンマップ（ハイヤーハーフカーネル）
-        // PML4[0]は設定しない（Present=0のまま）
-
-        // PML4[256] -> PDP_HIGH (高位アドレス用: 0xFFFF_8000_0000_0000〜)
-        (*pml4)
-            .entry(256)
-            .set((*pdp_high).physical_address()?, flags);
-
-        // === 必要なPDPエントリのみ設定（高位のみ）===
-        for i in 0..required_pd_count {
-            (*pdp_high)
-                .entry(i)
-                .set((*pd_high)[i].physical_address()?, flags);
-        }
-
-        // === 必要なPTのみリンク（高位のみ）===
-        for pt_idx in 0..required_pt_count {
-            let pd_idx = pt_idx / PAGE_TABLE_ENTRY_COUNT;
-            let entry_idx = pt_idx % PAGE_TABLE_ENTRY_COUNT;
-
-            (*pd_high)[pd_idx]
-                .entry(entry_idx)
-                .set((*pt_high)[pt_idx].physical_address()?, flags);
-        }
-
-        // === 必要なページのみマッピング（高位のみ）===
-        for pt_idx in 0..required_pt_count {
-            for page_idx in 0..PAGE_TABLE_ENTRY_COUNT {
-                let physical_addr =
-                    ((pt_idx * PAGE_TABLE_ENTRY_COUNT + page_idx) * PAGE_SIZE) as u64;
-                if physical_addr < actual_max {
-                    (*pt_high)[pt_idx].entry(page_idx).set(physical_addr, flags);
-                }
+        let pml4 = &mut *addr_of_mut!(KERNEL_PML4);
+        pml4.clear();
+
+        // === 直接マッピング（高位のみ、低位アドレスはアンマップ） ===
+        for &(start, end) in &ranges[..count] {
+            map_direct_range(
+                pml4,
+                start,
+                end,
+                kernel_end_phys,
+                page_flags,
+                use_1g,
+                &mut stats,
+            )?;
+        }
+        info!(
+            "Paging: {} range(s) mapped with {} x 1GB, {} x 2MB, {} x 4KB pages",
+            count, stats.pages_1g, stats.pages_2m, stats.pages_4k
+        );
+
+        // === カーネルイメージの保護 ===
+        for segment in segments {
+            let start = segment.start & !(PAGE_SIZE as u64 - 1);
+            let end = (segment.start + segment.size).next_multiple_of(PAGE_SIZE as u64);
+            for physical_addr in (start..end).step_by(PAGE_SIZE) {
+                kernel_pte(pml4, physical_addr)?
+                    .set(physical_addr, kernel_page_flags(segments, physical_addr));
             }
         }
+        info!(
+            "Paging: {} kernel segment(s) protected (NX: {})",
+            segments.len(),
+            if nx { "on" } else { "off" }
+        );
 
         // === Guard Page の設定 ===
         // スタック領域の直前のページをGuard Page（Present=0）に設定
@@ -371,54 +402,1077 @@ pub fn init(boot_info: &vitros_common::boot_info::BootInfo) -> Result<(), Paging
         let guard_page_virt_addr = stack_virt_addr
             .checked_sub(PAGE_SIZE as u64)
             .ok_or(PagingError::GuardPageSetupFailed)?;
-
-        // 仮想アドレスを物理アドレスに変換
         let guard_page_phys_addr = virt_to_phys(guard_page_virt_addr)?;
-        let physical_offset = guard_page_phys_addr;
 
-        // ページ番号を計算
-        let page_num = (physical_offset >> 12) as usize;
+        // Guard PageのPTエントリをPresent=0に設定（アクセス時にPage Faultが発生）
+        let entry = kernel_pte(pml4, guard_page_phys_addr)
+            .map_err(|_| PagingError::GuardPageSetupFailed)?;
+        entry.set(guard_page_phys_addr, 0);
+        info!(
+            "Guard Page: 0x{:016X} (entry 0x{:016X})",
+            guard_page_virt_addr,
+            entry.get_raw()
+        );
 
-        // PT配列内のインデックスとPT内のエントリ番号を計算
-        let pt_array_idx = page_num / PAGE_TABLE_ENTRY_COUNT;
-        let page_idx_in_pt = page_num % PAGE_TABLE_ENTRY_COUNT;
+        // CR3レジスタにPML4のアドレスを設定
+        write_cr3(pml4.physical_address()?);
+    }
+    Ok(())
+}
 
-        // インデックスの範囲検証
-        if pt_array_idx >= PT_COUNT {
-            return Err(PagingError::GuardPageSetupFailed);
+/// 直接マッピングする物理アドレス範囲を求める
+///
+/// MMIO以外のメモリマップの領域を開始アドレス順に並べ、隣接・重複する領域をまとめます。
+///
+/// # Returns
+/// ([開始, 終了) の配列, 有効な要素数)
+fn direct_map_ranges(
+    boot_info: &vitros_common::boot_info::BootInfo,
+) -> ([(u64, u64); MAX_MEMORY_REGIONS], usize) {
+    let mut ranges = [(0u64, 0u64); MAX_MEMORY_REGIONS];
+    let mut count = 0;
+    let regions = &boot_info.memory_map[..boot_info.memory_map_count.min(MAX_MEMORY_REGIONS)];
+    for region in regions {
+        if matches!(
+            region.region_type,
+            uefi::EFI_MEMORY_MAPPED_IO | uefi::EFI_MEMORY_MAPPED_IO_PORT_SPACE
+        ) {
+            continue;
         }
-        if page_idx_in_pt >= PAGE_TABLE_ENTRY_COUNT {
-            return Err(PagingError::GuardPageSetupFailed);
+        let start = region.start & !(PAGE_SIZE as u64 - 1);
+        let end = region
+            .start
+            .saturating_add(region.size)
+            .next_multiple_of(PAGE_SIZE as u64)
+            .min(DIRECT_MAP_LIMIT);
+        if start >= end {
+            continue;
         }
+        // 挿入ソート（ヒープはまだ使えない）
+        let mut i = count;
+        while i > 0 && ranges[i - 1].0 > start {
+            ranges[i] = ranges[i - 1];
+            i -= 1;
+        }
+        ranges[i] = (start, end);
+        count += 1;
+    }
 
-        // Guard PageのPTエントリをPresent=0に設定（アクセス時にPage Faultが発生）
-        // 高位アドレスのみ設定（低位はアンマップ済み）
-        (*pt_high)[pt_array_idx]
-            .entry(page_idx_in_pt)
-            .set(guard_page_phys_addr, 0);
-
-        // デバッグ: Guard Page設定を確認
-        info!("Guard Page setup:");
-        info!("  Virtual address: 0x{:016X}", guard_page_virt_addr);
-        info!("  Physical offset: 0x{:X}", physical_offset);
-        info!("  Page number: {}", page_num);
-        info!("  PT array index: {}", pt_array_idx);
-        info!("  Entry in PT: {}", page_idx_in_pt);
-        info!(
-            "  Entry value: 0x{:016X}",
-            (*pt_high)[pt_array_idx].entry(page_idx_in_pt).get_raw()
-        );
-        info!(
-            "  Entry is Present: {}",
-            (*pt_high)[pt_array_idx].entry(page_idx_in_pt).get_raw() & 1 != 0
-        );
+    // 隣接・重複する範囲をまとめる
+    let mut merged = 0;
+    for i in 0..count {
+        let (start, end) = ranges[i];
+        if merged > 0 && start <= ranges[merged - 1].1 {
+            ranges[merged - 1].1 = ranges[merged - 1].1.max(end);
+        } else {
+            ranges[merged] = (start, end);
+            merged += 1;
+        }
+    }
+    (ranges, merged)
+}
 
-        // CR3レジスタにPML4のアドレスを設定
-        let pml4_addr = (*pml4).physical_address()?;
-        write_cr3(pml4_addr);
+/// 物理アドレス範囲を直接マッピングに追加（起動時専用）
+///
+/// 境界が揃っていて範囲に収まる部分は1GB/2MBページ、残りは4KBページでマップします。
+/// `small_below` より下の範囲は常に4KBページを使います。
+///
+/// # Safety
+/// 起動時にこのCPUだけが実行中で、`pml4` がまだCR3に読み込まれていないこと
+unsafe fn map_direct_range(
+    pml4: &mut PageTable,
+    start: u64,
+    end: u64,
+    small_below: u64,
+    flags: u64,
+    use_1g: bool,
+    stats: &mut DirectMapStats,
+) -> Result<(), PagingError> {
+    let huge = PageTableFlags::HugePage as u64;
+    let fits = |addr: u64, size: u64| {
+        addr >= small_below && addr.is_multiple_of(size) && addr + size <= end
+    };
+
+    let mut addr = start;
+    while addr < end {
+        let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(KERNEL_VIRTUAL_BASE + addr);
+        // SAFETY: 呼び出し元が排他アクセスを保証する
+        let pdp = unsafe { next_table_or_create(pml4.entry(pml4_idx), false)? };
+        if use_1g && fits(addr, HUGE_PAGE_1G) {
+            pdp.entry(pdp_idx).set(addr, flags | huge);
+            stats.pages_1g += 1;
+            addr += HUGE_PAGE_1G;
+            continue;
+        }
+        // SAFETY: 同上
+        let pd = unsafe { next_table_or_create(pdp.entry(pdp_idx), false)? };
+        if fits(addr, HUGE_PAGE_2M) {
+            pd.entry(pd_idx).set(addr, flags | huge);
+            stats.pages_2m += 1;
+            addr += HUGE_PAGE_2M;
+            continue;
+        }
+        // SAFETY: 同上
+        let pt = unsafe { next_table_or_create(pd.entry(pd_idx), false)? };
+        pt.entry(pt_idx).set(addr, flags);
+        stats.pages_4k += 1;
+        addr += PAGE_SIZE as u64;
+    }
+    Ok(())
+}
+
+/// 直接マッピングのうち4KBページでマップした物理アドレスのPTエントリを取得（起動時専用）
+///
+/// # Safety
+/// `map_direct_range` と同じ
+unsafe fn kernel_pte(
+    pml4: &mut PageTable,
+    physical_addr: u64,
+) -> Result<&'static mut PageTableEntry, PagingError> {
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(KERNEL_VIRTUAL_BASE + physical_addr);
+    // SAFETY: 呼び出し元が排他アクセスを保証する
+    unsafe {
+        let pdp = next_table(pml4.entry(pml4_idx))?;
+        let pd = next_table(pdp.entry(pdp_idx))?;
+        let pt = next_table(pd.entry(pd_idx))?;
+        Ok(pt.entry(pt_idx))
+    }
+}
+
+/// カーネルイメージのページに設定するフラグ
+///
+/// 1ページを複数のセグメントが共有する場合は、いずれかが書き込み可・実行可なら許可する。
+fn kernel_page_flags(segments: &[KernelSegment], physical_addr: u64) -> u64 {
+    let page_end = physical_addr + PAGE_SIZE as u64;
+    let segment_flags = segments
+        .iter()
+        .filter(|s| s.start < page_end && physical_addr < s.start + s.size)
+        .fold(0, |acc, s| acc | s.flags);
+
+    let mut flags = PageTableFlags::Present as u64;
+    if segment_flags & PF_W != 0 {
+        flags |= PageTableFlags::Writable as u64;
+    }
+    if segment_flags & PF_X == 0 {
+        flags |= no_execute_flag();
+    }
+    flags
+}
+
+/// CR0.WPを有効化（カーネルモードでも読み取り専用ページへの書き込みをフォルトにする）
+///
+/// APはトランポリンでBSPのCR0を引き継ぐため、BSPで一度だけ呼び出します。
+fn enable_write_protect() {
+    const CR0_WP: u64 = 1 << 16;
+    // SAFETY: CR0.WPは書き込み保護の有無を切り替えるだけで、既存のマッピングには影響しない
+    unsafe {
+        let cr0: u64;
+        asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        asm!("mov cr0, {}", in(reg) cr0 | CR0_WP, options(nostack, preserves_flags));
+    }
+}
+
+// =============================================================================
+// 動的ページマッピング
+// =============================================================================
+
+/// ページテーブル操作の排他制御用ロック
+static PAGE_TABLE_LOCK: spin::Mutex<()> = spin::Mutex::new(());
+
+/// PAGE_TABLE_LOCKを保持しているCPU番号（保持されていなければusize::MAX）
+static PAGE_TABLE_OWNER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// PAGE_TABLE_LOCKのガード（保持しているCPUを記録する）
+struct PageTableGuard {
+    _guard: spin::MutexGuard<'static, ()>,
+}
+
+impl Drop for PageTableGuard {
+    fn drop(&mut self) {
+        // ロックの解放（_guardのdrop）より先に所有者を消す
+        PAGE_TABLE_OWNER.store(usize::MAX, Ordering::Release);
+    }
+}
+
+/// PAGE_TABLE_LOCKを取得し、現在のCPUを所有者として記録
+fn lock_page_tables() -> PageTableGuard {
+    let guard = PAGE_TABLE_LOCK.lock();
+    PAGE_TABLE_OWNER.store(crate::percpu::current_index(), Ordering::Release);
+    PageTableGuard { _guard: guard }
+}
+
+/// 仮想アドレスから各階層のテーブルインデックスを取得
+///
+/// # Returns
+/// (PML4, PDP, PD, PT) のインデックス
+fn table_indices(virt_addr: u64) -> [usize; 4] {
+    [
+        ((virt_addr >> 39) & 0x1FF) as usize,
+        ((virt_addr >> 30) & 0x1FF) as usize,
+        ((virt_addr >> 21) & 0x1FF) as usize,
+        ((virt_addr >> 12) & 0x1FF) as usize,
+    ]
+}
+
+/// 物理アドレスにあるページテーブルへの参照を取得
+///
+/// # Safety
+/// `table_phys` はページテーブルとして使用中の物理フレームを指し、
+/// 呼び出し元が PAGE_TABLE_LOCK を保持している必要がある
+unsafe fn table_at(table_phys: u64) -> Result<&'static mut PageTable, PagingError> {
+    let table_virt = phys_to_virt(table_phys)?;
+    // SAFETY: 呼び出し元がテーブルの有効性と排他アクセスを保証する
+    Ok(unsafe { &mut *(table_virt as *mut PageTable) })
+}
+
+/// 指定したTLBエントリを無効化
+fn invlpg(virt_addr: u64) {
+    // SAFETY: invlpgはTLBエントリを無効化するだけで、メモリ安全性に影響しない
+    unsafe {
+        asm!("invlpg [{}]", in(reg) virt_addr, options(nostack, preserves_flags));
+    }
+}
+
+/// ページ単位で無効化するページ数の上限（超えたらTLB全体を無効化する）
+const FLUSH_MAX_PAGES: u64 = 32;
+
+/// このCPUのTLBから仮想アドレス範囲 [start, end) のエントリを無効化
+///
+/// 範囲が大きい場合はCR3を再ロードしてTLB全体を無効化します（グローバルページは使わない）。
+pub fn flush_tlb_local(start: u64, end: u64) {
+    let start = start & !(PAGE_SIZE as u64 - 1);
+    if end.saturating_sub(start) > FLUSH_MAX_PAGES * PAGE_SIZE as u64 {
+        reload_cr3();
+    } else {
+        for page in (start..end).step_by(PAGE_SIZE) {
+            invlpg(page);
+        }
+    }
+}
+
+/// 仮想アドレスを辿るPML4の物理アドレス
+///
+/// 上位半分（カーネル空間）はすべてのページテーブルで共有するため、常にカーネルのPML4を
+/// 辿ります。ユーザーのページテーブルが読み込まれている間にカーネル空間をマップしても、
+/// 新しいPML4エントリがそのページテーブルにだけ作られることはありません。
+/// 下位半分は現在のCR3が指すページテーブルを辿ります。
+fn root_table(virt_addr: u64) -> u64 {
+    if virt_addr >= KERNEL_VIRTUAL_BASE {
+        kernel_pml4_phys()
+    } else {
+        read_cr3() & PTE_ADDRESS_MASK
+    }
+}
+
+/// 次の階層のテーブルを取得（存在しなければフレームアロケータから確保）
+///
+/// # Safety
+/// `entry` は現在のページテーブル階層内のエントリであり、
+/// 呼び出し元が PAGE_TABLE_LOCK を保持している必要がある
+unsafe fn next_table_or_create(
+    entry: &mut PageTableEntry,
+    user: bool,
+) -> Result<&'static mut PageTable, PagingError> {
+    if entry.is_present() {
+        if entry.get_raw() & PageTableFlags::HugePage as u64 != 0 {
+            return Err(PagingError::HugePageConflict);
+        }
+        if user {
+            entry.set_flags(PageTableFlags::UserAccessible as u64);
+        }
+        // SAFETY: Presentなエントリはページテーブルを指している
+        return unsafe { table_at(entry.get_address()) };
+    }
+
+    let frame = crate::frame_allocator::alloc_frame().ok_or(PagingError::FrameAllocationFailed)?;
+    // SAFETY: 確保直後のフレームは他から参照されていない
+    let table = unsafe { table_at(frame)? };
+    table.clear();
+
+    let mut flags = PageTableFlags::Present as u64 | PageTableFlags::Writable as u64;
+    if user {
+        flags |= PageTableFlags::UserAccessible as u64;
+    }
+    entry.set(frame, flags);
+    Ok(table)
+}
+
+/// 次の階層のテーブルを取得（存在しなければNotMapped）
+///
+/// # Safety
+/// next_table_or_create と同じ
+unsafe fn next_table(entry: &PageTableEntry) -> Result<&'static mut PageTable, PagingError> {
+    if !entry.is_present() {
+        return Err(PagingError::NotMapped);
+    }
+    if entry.get_raw() & PageTableFlags::HugePage as u64 != 0 {
+        return Err(PagingError::HugePageConflict);
+    }
+    // SAFETY: Presentなエントリはページテーブルを指している
+    unsafe { table_at(entry.get_address()) }
+}
+
+/// 次の階層のテーブルを取得（ヒュージページなら同じ対応の下位テーブルに分割）
+///
+/// 1GBページは2MBページ512個のPDに、2MBページは4KBページ512個のPTに分割します。
+/// 分割後も各ページの物理アドレスと属性は変わらないため、TLBの無効化は
+/// 書き換えたページについて呼び出し元が行えば十分です。
+///
+/// # Arguments
+/// * `entry` - PDPまたはPDのエントリ
+/// * `page_size` - `entry` がヒュージページの場合の大きさ（HUGE_PAGE_1GまたはHUGE_PAGE_2M）
+///
+/// # Safety
+/// next_table_or_create と同じ
+unsafe fn next_table_or_split(
+    entry: &mut PageTableEntry,
+    page_size: u64,
+) -> Result<&'static mut PageTable, PagingError> {
+    let raw = entry.get_raw();
+    if !entry.is_present() || raw & PageTableFlags::HugePage as u64 == 0 {
+        // SAFETY: 呼び出し元の保証をそのまま引き継ぐ
+        return unsafe { next_table(entry) };
+    }
 
+    let frame = crate::frame_allocator::alloc_frame().ok_or(PagingError::FrameAllocationFailed)?;
+    // SAFETY: 確保直後のフレームは他から参照されていない
+    let table = unsafe { table_at(frame)? };
+
+    // bit12（PAT）はアドレス部分に重なるため、アドレスと属性を分けて取り出す
+    let base = raw & PTE_ADDRESS_MASK & !(page_size - 1);
+    let attributes = raw & !PTE_ADDRESS_MASK;
+    let pat = raw & PTE_HUGE_PAT != 0;
+    let (child_size, child_flags) = if page_size == HUGE_PAGE_1G {
+        // 2MBページのエントリもbit7がHugePage、bit12がPAT
+        let pat_bit = if pat { PTE_HUGE_PAT } else { 0 };
+        (HUGE_PAGE_2M, attributes | pat_bit)
+    } else {
+        // 4KBページのエントリはbit7がPAT
+        let pat_bit = if pat { PTE_PAT } else { 0 };
+        (
+            PAGE_SIZE as u64,
+            (attributes & !(PageTableFlags::HugePage as u64)) | pat_bit,
+        )
+    };
+    for i in 0..512 {
+        table
+            .entry(i)
+            .set(base + i as u64 * child_size, child_flags);
+    }
+
+    // 中間エントリは最も緩い権限にし、保護は末端のエントリで行う
+    let mut flags = PageTableFlags::Present as u64 | PageTableFlags::Writable as u64;
+    flags |= raw & PageTableFlags::UserAccessible as u64;
+    entry.set(frame, flags);
+    Ok(table)
+}
+
+/// 仮想アドレスをマップしている末端のエントリを取得（ヒュージページは分割しない）
+///
+/// ヒュージページの場合は、4KBページのエントリと同じ形（HugePageなし、PATはbit7）に
+/// 直した値を返します。
+///
+/// # Errors
+/// * `PagingError::NotMapped` - マップされていない場合
+fn leaf_entry(virt_addr: u64) -> Result<u64, PagingError> {
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+    let huge = PageTableFlags::HugePage as u64;
+    let normalize = |raw: u64| {
+        let pat_bit = if raw & PTE_HUGE_PAT != 0 { PTE_PAT } else { 0 };
+        (raw & !huge & !PTE_HUGE_PAT) | pat_bit
+    };
+
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、root_tableは有効なPML4を指している
+        unsafe {
+            let pml4 = table_at(root_table(virt_addr))?;
+            let pdp = next_table(pml4.entry(pml4_idx))?;
+            let pdp_entry = pdp.entry(pdp_idx);
+            if pdp_entry.is_present() && pdp_entry.get_raw() & huge != 0 {
+                return Ok(normalize(pdp_entry.get_raw()));
+            }
+            let pd = next_table(pdp_entry)?;
+            let pd_entry = pd.entry(pd_idx);
+            if pd_entry.is_present() && pd_entry.get_raw() & huge != 0 {
+                return Ok(normalize(pd_entry.get_raw()));
+            }
+            let pt = next_table(pd_entry)?;
+            let entry = pt.entry(pt_idx);
+            if !entry.is_present() {
+                return Err(PagingError::NotMapped);
+            }
+            Ok(entry.get_raw())
+        }
+    })
+}
+
+/// 4KBページをマップ
+///
+/// 現在のCR3が指すページテーブル階層を辿り、途中のテーブルが存在しなければ
+/// フレームアロケータから確保して作成します。
+///
+/// # Arguments
+/// * `virt_addr` - マップする仮想アドレス（4KBアライン）
+/// * `phys_addr` - マップ先の物理アドレス（4KBアライン）
+/// * `flags` - PTエントリに設定するフラグ（PageTableFlagsの組み合わせ、Presentは自動付与）
+///
+/// # Errors
+/// * `PagingError::InvalidAddress` - アドレスが4KB境界に揃っていない場合
+/// * `PagingError::AlreadyMapped` - 既にマップされている場合
+/// * `PagingError::FrameAllocationFailed` - 中間テーブル用のフレームが確保できない場合
+/// * `PagingError::HugePageConflict` - 経路上にヒュージページがある場合
+#[allow(dead_code)]
+pub fn map_page(virt_addr: u64, phys_addr: u64, flags: u64) -> Result<(), PagingError> {
+    if !virt_addr.is_multiple_of(PAGE_SIZE as u64) || !phys_addr.is_multiple_of(PAGE_SIZE as u64) {
+        return Err(PagingError::InvalidAddress);
+    }
+    let user = flags & PageTableFlags::UserAccessible as u64 != 0;
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、root_tableは有効なPML4を指している
+        unsafe {
+            let pml4 = table_at(root_table(virt_addr))?;
+            let pdp = next_table_or_create(pml4.entry(pml4_idx), user)?;
+            let pd = next_table_or_create(pdp.entry(pdp_idx), user)?;
+            let pt = next_table_or_create(pd.entry(pd_idx), user)?;
+
+            let entry = pt.entry(pt_idx);
+            if entry.is_present() {
+                return Err(PagingError::AlreadyMapped);
+            }
+            entry.set(phys_addr, flags | PageTableFlags::Present as u64);
+        }
+        invlpg(virt_addr);
         Ok(())
+    })
+}
+
+/// 4KBページのマッピングを解除
+///
+/// 中間テーブルは解放しません。ヒュージページでマップされている場合は4KBページに分割します。
+///
+/// # Arguments
+/// * `virt_addr` - マッピングを解除する仮想アドレス（4KBアライン）
+///
+/// # Returns
+/// マップされていた物理アドレス
+///
+/// # Errors
+/// * `PagingError::InvalidAddress` - アドレスが4KB境界に揃っていない場合
+/// * `PagingError::NotMapped` - マップされていない場合
+/// * `PagingError::FrameAllocationFailed` - ヒュージページの分割に使うフレームが確保できない場合
+#[allow(dead_code)]
+pub fn unmap_page(virt_addr: u64) -> Result<u64, PagingError> {
+    if !virt_addr.is_multiple_of(PAGE_SIZE as u64) {
+        return Err(PagingError::InvalidAddress);
     }
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+
+    let phys_addr = crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、root_tableは有効なPML4を指している
+        let phys_addr = unsafe {
+            let pml4 = table_at(root_table(virt_addr))?;
+            let pdp = next_table(pml4.entry(pml4_idx))?;
+            let pd = next_table_or_split(pdp.entry(pdp_idx), HUGE_PAGE_1G)?;
+            let pt = next_table_or_split(pd.entry(pd_idx), HUGE_PAGE_2M)?;
+
+            let entry = pt.entry(pt_idx);
+            if !entry.is_present() {
+                return Err(PagingError::NotMapped);
+            }
+            let phys_addr = entry.get_address();
+            entry.set(0, 0);
+            phys_addr
+        };
+        invlpg(virt_addr);
+        Ok(phys_addr)
+    })?;
+    // フレームを再利用される前に、他のCPUに残っているエントリも無効化する
+    crate::smp::flush_tlb_others(virt_addr, virt_addr + PAGE_SIZE as u64);
+    Ok(phys_addr)
+}
+
+/// 仮想アドレスを物理アドレスに変換（ページテーブルを辿る）
+///
+/// 2MB/1GBのヒュージページにも対応します。
+///
+/// # Arguments
+/// * `virt_addr` - 変換する仮想アドレス
+///
+/// # Returns
+/// 対応する物理アドレス。マップされていない場合はNone
+#[allow(dead_code)]
+pub fn translate(virt_addr: u64) -> Option<u64> {
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+    let huge = PageTableFlags::HugePage as u64;
+
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、root_tableは有効なPML4を指している
+        unsafe {
+            let pml4 = table_at(root_table(virt_addr)).ok()?;
+            let pdp = next_table(pml4.entry(pml4_idx)).ok()?;
+
+            let pdp_entry = pdp.entry(pdp_idx);
+            if pdp_entry.is_present() && pdp_entry.get_raw() & huge != 0 {
+                // 1GBページ（bit12はPATビットなのでマスク）
+                return Some((pdp_entry.get_address() & !0x3FFF_FFFF) + (virt_addr & 0x3FFF_FFFF));
+            }
+            let pd = next_table(pdp_entry).ok()?;
+
+            let pd_entry = pd.entry(pd_idx);
+            if pd_entry.is_present() && pd_entry.get_raw() & huge != 0 {
+                // 2MBページ
+                return Some((pd_entry.get_address() & !0x1F_FFFF) + (virt_addr & 0x1F_FFFF));
+            }
+            let pt = next_table(pd_entry).ok()?;
+
+            let entry = pt.entry(pt_idx);
+            if !entry.is_present() {
+                return None;
+            }
+            Some(entry.get_address() + (virt_addr & 0xFFF))
+        }
+    })
+}
+
+/// PTEの物理アドレス部分のマスク
+const PTE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// スワップアウト済みを示す非PresentなPTEの印（ソフトウェア使用可能ビット9）
+const SWAP_MARKER: u64 = 1 << 9;
+
+/// コピーオンライトで共有中のPresentなPTEの印（ソフトウェア使用可能ビット10）
+const COW_MARKER: u64 = 1 << 10;
+
+/// 4KBページのPTエントリに対して操作を行う
+///
+/// ヒュージページでマップされている場合は4KBページに分割してから操作します。
+///
+/// # Errors
+/// * `PagingError::InvalidAddress` - アドレスが4KB境界に揃っていない場合
+/// * `PagingError::NotMapped` - 中間テーブルが存在しない場合
+/// * `PagingError::FrameAllocationFailed` - ヒュージページの分割に使うフレームが確保できない場合
+fn with_pt_entry<R>(
+    virt_addr: u64,
+    f: impl FnOnce(&mut PageTableEntry) -> R,
+) -> Result<R, PagingError> {
+    if !virt_addr.is_multiple_of(PAGE_SIZE as u64) {
+        return Err(PagingError::InvalidAddress);
+    }
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、root_tableは有効なPML4を指している
+        unsafe {
+            let pml4 = table_at(root_table(virt_addr))?;
+            let pdp = next_table(pml4.entry(pml4_idx))?;
+            let pd = next_table_or_split(pdp.entry(pdp_idx), HUGE_PAGE_1G)?;
+            let pt = next_table_or_split(pd.entry(pd_idx), HUGE_PAGE_2M)?;
+            Ok(f(pt.entry(pt_idx)))
+        }
+    })
+}
+
+/// ページのAccessedビットを読み取ってクリア
+///
+/// ページ回収時のSecond-Chance判定に使用します。
+///
+/// # Returns
+/// 前回クリアしてからアクセスされていればtrue
+///
+/// # Errors
+/// * `PagingError::NotMapped` - マップされていない場合
+/// * その他 `with_pt_entry` と同じ
+pub fn test_and_clear_accessed(virt_addr: u64) -> Result<bool, PagingError> {
+    let accessed = PageTableFlags::Accessed as u64;
+    let was_accessed = with_pt_entry(virt_addr, |entry| {
+        if !entry.is_present() {
+            return Err(PagingError::NotMapped);
+        }
+        let raw = entry.get_raw();
+        entry.set(raw, raw & !PTE_ADDRESS_MASK & !accessed);
+        Ok(raw & accessed != 0)
+    })??;
+    invlpg(virt_addr);
+    Ok(was_accessed)
+}
+
+/// 4KBページをスワップアウト済みとしてマッピング解除
+///
+/// PTEを非Presentにし、`is_swapped_out` で識別できる印を残します。
+///
+/// # Returns
+/// (マップされていた物理アドレス, PTEのフラグ)
+///
+/// # Errors
+/// * `PagingError::NotMapped` - マップされていない場合
+/// * その他 `with_pt_entry` と同じ
+pub fn unmap_to_swap(virt_addr: u64) -> Result<(u64, u64), PagingError> {
+    let result = with_pt_entry(virt_addr, |entry| {
+        if !entry.is_present() {
+            return Err(PagingError::NotMapped);
+        }
+        let raw = entry.get_raw();
+        entry.set(0, SWAP_MARKER);
+        Ok((raw & PTE_ADDRESS_MASK, raw & !PTE_ADDRESS_MASK))
+    })??;
+    invlpg(virt_addr);
+    crate::smp::flush_tlb_others(virt_addr, virt_addr + PAGE_SIZE as u64);
+    Ok(result)
+}
+
+/// ページが `unmap_to_swap` でスワップアウトされた状態かどうか
+pub fn is_swapped_out(virt_addr: u64) -> bool {
+    with_pt_entry(virt_addr, |entry| {
+        !entry.is_present() && entry.get_raw() & SWAP_MARKER != 0
+    })
+    .unwrap_or(false)
+}
+
+/// ユーザーモードから参照できるページか
+///
+/// ユーザー空間（`USER_SPACE_END` 未満）にあり、PML4から末端までのすべての階層で
+/// UserAccessibleが立っていることを確認します。スワップアウト済みのページは参照時に
+/// ページフォルトで読み込まれるため、末端より上の階層がユーザー用であれば参照できるものとします。
+pub fn is_user_page(virt_addr: u64) -> bool {
+    if virt_addr >= USER_SPACE_END {
+        return false;
+    }
+    let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+    let user = PageTableFlags::UserAccessible as u64;
+    let huge = PageTableFlags::HugePage as u64;
+    let is_user = |entry: &PageTableEntry| entry.is_present() && entry.get_raw() & user != 0;
+
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、root_tableは有効なPML4を指している
+        let walk = || unsafe {
+            let pml4 = table_at(root_table(virt_addr)).ok()?;
+            let pml4_entry = pml4.entry(pml4_idx);
+            if !is_user(pml4_entry) {
+                return Some(false);
+            }
+            let pdp_entry = next_table(pml4_entry).ok()?.entry(pdp_idx);
+            if !is_user(pdp_entry) {
+                return Some(false);
+            }
+            if pdp_entry.get_raw() & huge != 0 {
+                return Some(true);
+            }
+            let pd_entry = next_table(pdp_entry).ok()?.entry(pd_idx);
+            if !is_user(pd_entry) {
+                return Some(false);
+            }
+            if pd_entry.get_raw() & huge != 0 {
+                return Some(true);
+            }
+            let entry = next_table(pd_entry).ok()?.entry(pt_idx);
+            Some(is_user(entry) || (!entry.is_present() && entry.get_raw() & SWAP_MARKER != 0))
+        };
+        walk().unwrap_or(false)
+    })
+}
+
+/// 既存のフレームをコピーオンライトで共有してマップ
+///
+/// 読み取り専用でマップし、最初の書き込みのページフォルトでフレームを複製して
+/// 書き込み可能にします。共有中のフレームはこのマッピングの所有ではないため、
+/// 複製前にマッピングを解除してもフレームを解放しないでください。
+///
+/// # Arguments
+/// * `virt_addr` - マップする仮想アドレス（4KBアライン）
+/// * `phys_addr` - 共有するフレームの物理アドレス
+/// * `flags` - 複製後のPTEのフラグ（Writableは複製まで外される）
+///
+/// # Errors
+/// `map_page` と同じ
+#[allow(dead_code)]
+pub fn map_copy_on_write(virt_addr: u64, phys_addr: u64, flags: u64) -> Result<(), PagingError> {
+    map_page(
+        virt_addr,
+        phys_addr,
+        (flags & !(PageTableFlags::Writable as u64)) | COW_MARKER,
+    )
+}
+
+/// コピーオンライトのページを複製して書き込み可能にする
+///
+/// # Returns
+/// 複製した場合はtrue、コピーオンライトのページでなければfalse
+///
+/// # Errors
+/// * `PagingError::FrameAllocationFailed` - 複製先のフレームが確保できない場合
+/// * その他 `with_pt_entry` と同じ
+pub fn break_copy_on_write(virt_addr: u64) -> Result<bool, PagingError> {
+    let shared = with_pt_entry(virt_addr, |entry| {
+        (entry.is_present() && entry.get_raw() & COW_MARKER != 0).then(|| entry.get_address())
+    })?;
+    let Some(shared) = shared else {
+        return Ok(false);
+    };
+
+    let frame = crate::frame_allocator::alloc_frame().ok_or(PagingError::FrameAllocationFailed)?;
+    let copy = phys_to_virt(frame).and_then(|dst| {
+        let src = phys_to_virt(shared)?;
+        // SAFETY: 両方とも直接マッピング上の4KBフレームで、複製先は確保直後のため重ならない
+        unsafe { core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, PAGE_SIZE) };
+        // 複製中に他のマッピングへ変更されていなければ、複製先に付け替える
+        with_pt_entry(virt_addr, |entry| {
+            let raw = entry.get_raw();
+            if !entry.is_present() || raw & COW_MARKER == 0 || entry.get_address() != shared {
+                return false;
+            }
+            let flags = (raw & !PTE_ADDRESS_MASK & !COW_MARKER) | PageTableFlags::Writable as u64;
+            entry.set(frame, flags);
+            true
+        })
+    });
+    match copy {
+        Ok(true) => {
+            invlpg(virt_addr);
+            Ok(true)
+        }
+        // 既に解決済み（書き込みを再実行すれば成功する）
+        Ok(false) => {
+            let _ = crate::frame_allocator::free_frame(frame);
+            Ok(true)
+        }
+        Err(e) => {
+            let _ = crate::frame_allocator::free_frame(frame);
+            Err(e)
+        }
+    }
+}
+
+/// ページテーブルのロックが保持されているか
+///
+/// ページフォルトハンドラが、ページテーブルの操作中に発生したフォルトを
+/// 解決しようとしてデッドロックしないために使用します。
+/// 他のCPUが保持している場合は、解放を待てばよいためfalseを返します。
+pub fn page_tables_locked() -> bool {
+    PAGE_TABLE_OWNER.load(Ordering::Acquire) == crate::percpu::current_index()
+}
+
+// =============================================================================
+// ユーザー空間のページテーブル
+// =============================================================================
+
+/// ユーザー空間の終端（下位カノニカルアドレス空間の上限、この値は含まない）
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// 上位半分（カーネル空間）を表すPML4エントリの開始インデックス
+const KERNEL_PML4_START: usize = PAGE_TABLE_ENTRY_COUNT / 2;
+
+/// カーネルのPML4の物理アドレス
+pub fn kernel_pml4_phys() -> u64 {
+    virt_to_phys(core::ptr::addr_of!(KERNEL_PML4) as u64)
+        .expect("KERNEL_PML4 must be in the higher half")
+}
+
+/// カーネルのPML4から下位半分（ユーザー空間側）のエントリをすべて外す
+///
+/// カーネルは高位アドレスだけで動作するため、起動処理（APのトランポリンの恒等マップなど）が
+/// 下位半分に残したマッピングを取り除き、物理アドレスをそのままポインタとして使う誤りを
+/// ページフォルトとして検出できるようにします。全CPUのTLBを無効化しますが、
+/// 外したテーブルのフレームは解放しません。
+///
+/// 起動に応答しなかったAPがある場合（`smp::ap_boot_pending`）は、そのAPがトランポリンの
+/// 恒等マップを必要とするため呼び出さないでください。
+///
+/// # Returns
+/// 外したPML4エントリの数
+pub fn unmap_kernel_low_half() -> usize {
+    let removed = crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、カーネルのPML4は常に有効
+        let pml4 = unsafe { table_at(kernel_pml4_phys()) };
+        let Ok(pml4) = pml4 else {
+            return 0;
+        };
+        let mut removed = 0;
+        for i in 0..KERNEL_PML4_START {
+            let entry = pml4.entry(i);
+            if entry.is_present() {
+                entry.set(0, 0);
+                removed += 1;
+            }
+        }
+        removed
+    });
+    if removed > 0 {
+        if read_cr3() & PTE_ADDRESS_MASK == kernel_pml4_phys() {
+            reload_cr3();
+        }
+        crate::smp::flush_tlb_others(0, u64::MAX);
+    }
+    removed
+}
+
+/// カーネルのPML4の下位半分に残っているエントリの数
+#[allow(dead_code)]
+pub fn kernel_low_half_entries() -> usize {
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        // SAFETY: PAGE_TABLE_LOCKを保持しており、カーネルのPML4は常に有効
+        let Ok(pml4) = (unsafe { table_at(kernel_pml4_phys()) }) else {
+            return 0;
+        };
+        (0..KERNEL_PML4_START)
+            .filter(|&i| pml4.entry(i).is_present())
+            .count()
+    })
+}
+
+/// 指定したPML4をCR3に読み込む
+///
+/// 既に読み込まれている場合は、TLBを無駄にフラッシュしないよう何もしません。
+/// カーネル空間はすべてのアドレス空間で共有しているため、切り替え後も
+/// 現在のスタックとコードはそのまま使用できます。
+pub fn switch_address_space(pml4_phys: u64) {
+    if read_cr3() & PTE_ADDRESS_MASK != pml4_phys {
+        write_cr3(pml4_phys);
+    }
+}
+
+/// タスクのアドレス空間（PML4を頂点とするページテーブル階層）
+///
+/// 上位半分のPML4エントリはカーネルのページテーブルからコピーし、カーネル空間の
+/// PDP以下のテーブルを全アドレス空間で共有します。下位半分（ユーザー空間）はこのアドレス空間
+/// 専用で、マップしたページのフレームと中間テーブルはDrop時にフレームアロケータへ返却します。
+///
+/// # Note
+/// コピー後にカーネルのPML4へ追加された上位半分のエントリは反映されません。
+/// 現在のカーネル空間はPML4[256]のみを使用しています。
+pub struct AddressSpace {
+    pml4_phys: u64,
+}
+
+impl AddressSpace {
+    /// カーネル空間だけをマップした新しいアドレス空間を作成
+    ///
+    /// # Errors
+    /// * `PagingError::FrameAllocationFailed` - PML4用のフレームが確保できない場合
+    pub fn new() -> Result<Self, PagingError> {
+        let frame =
+            crate::frame_allocator::alloc_frame().ok_or(PagingError::FrameAllocationFailed)?;
+        crate::io::without_interrupts(|| {
+            let _guard = lock_page_tables();
+            // SAFETY: PAGE_TABLE_LOCKを保持しており、確保直後のフレームは他から参照されていない
+            unsafe {
+                let pml4 = table_at(frame)?;
+                let kernel = table_at(kernel_pml4_phys())?;
+                pml4.clear();
+                for i in KERNEL_PML4_START..PAGE_TABLE_ENTRY_COUNT {
+                    *pml4.entry(i) = *kernel.entry(i);
+                }
+            }
+            Ok(Self { pml4_phys: frame })
+        })
+        .inspect_err(|_| {
+            let _ = crate::frame_allocator::free_frame(frame);
+        })
+    }
+
+    /// CR3に設定するPML4の物理アドレス
+    pub fn pml4_phys(&self) -> u64 {
+        self.pml4_phys
+    }
+
+    /// ゼロクリアした新しいフレームをユーザー空間にマップ
+    ///
+    /// # Arguments
+    /// * `virt_addr` - マップする仮想アドレス（4KBアライン、ユーザー空間内）
+    /// * `flags` - PTエントリのフラグ（PresentとUserAccessibleは自動付与）
+    ///
+    /// # Returns
+    /// マップしたフレームのカーネル側の仮想アドレス（内容の書き込み用）
+    ///
+    /// # Errors
+    /// * `PagingError::InvalidAddress` - アドレスが4KB境界に揃っていない、またはユーザー空間外の場合
+    /// * `PagingError::AlreadyMapped` - 既にマップされている場合
+    /// * `PagingError::FrameAllocationFailed` - フレームが確保できない場合
+    pub fn map_new_page(&mut self, virt_addr: u64, flags: u64) -> Result<u64, PagingError> {
+        if !virt_addr.is_multiple_of(PAGE_SIZE as u64) || virt_addr >= USER_SPACE_END {
+            return Err(PagingError::InvalidAddress);
+        }
+        let frame =
+            crate::frame_allocator::alloc_frame().ok_or(PagingError::FrameAllocationFailed)?;
+        let frame_virt = phys_to_virt(frame)?;
+        // SAFETY: 確保直後のフレームは直接マッピング経由で書き込め、他から参照されていない
+        unsafe { core::ptr::write_bytes(frame_virt as *mut u8, 0, PAGE_SIZE) };
+
+        let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+        let flags = flags | PageTableFlags::Present as u64 | PageTableFlags::UserAccessible as u64;
+        crate::io::without_interrupts(|| {
+            let _guard = lock_page_tables();
+            // SAFETY: PAGE_TABLE_LOCKを保持しており、pml4_physはこのページテーブルのPML4
+            unsafe {
+                let pml4 = table_at(self.pml4_phys)?;
+                let pdp = next_table_or_create(pml4.entry(pml4_idx), true)?;
+                let pd = next_table_or_create(pdp.entry(pdp_idx), true)?;
+                let pt = next_table_or_create(pd.entry(pd_idx), true)?;
+
+                let entry = pt.entry(pt_idx);
+                if entry.is_present() {
+                    return Err(PagingError::AlreadyMapped);
+                }
+                entry.set(frame, flags);
+            }
+            Ok(frame_virt)
+        })
+        .inspect_err(|_| {
+            let _ = crate::frame_allocator::free_frame(frame);
+        })
+    }
+}
+
+impl Drop for AddressSpace {
+    /// 下位半分のページと中間テーブル、PML4のフレームを解放
+    ///
+    /// このアドレス空間がCR3に読み込まれていないこと（スケジューラが
+    /// 別のアドレス空間に切り替えた後であること）が前提です。
+    fn drop(&mut self) {
+        /// テーブルを辿って、下の階層とマップ先のフレームを解放
+        ///
+        /// # Safety
+        /// `table` はこのページテーブル専用の中間テーブルで、PAGE_TABLE_LOCKを保持していること
+        unsafe fn free_level(table: &mut PageTable, level: usize) {
+            for entry in table.entries.iter_mut().filter(|e| e.is_present()) {
+                if level > 1 {
+                    // SAFETY: 下位半分にはヒュージページを作らないため、Presentなら中間テーブル
+                    if let Ok(next) = unsafe { table_at(entry.get_address()) } {
+                        unsafe { free_level(next, level - 1) };
+                    }
+                }
+                let _ = crate::frame_allocator::free_frame(entry.get_address());
+                entry.set(0, 0);
+            }
+        }
+
+        debug_assert_ne!(read_cr3() & PTE_ADDRESS_MASK, self.pml4_phys);
+        crate::io::without_interrupts(|| {
+            let _guard = lock_page_tables();
+            // SAFETY: PAGE_TABLE_LOCKを保持しており、下位半分のテーブルはこのページテーブル専用
+            unsafe {
+                if let Ok(pml4) = table_at(self.pml4_phys) {
+                    for i in 0..KERNEL_PML4_START {
+                        let entry = pml4.entry(i);
+                        if !entry.is_present() {
+                            continue;
+                        }
+                        if let Ok(pdp) = table_at(entry.get_address()) {
+                            free_level(pdp, 3);
+                        }
+                        let _ = crate::frame_allocator::free_frame(entry.get_address());
+                        entry.set(0, 0);
+                    }
+                }
+            }
+        });
+        let _ = crate::frame_allocator::free_frame(self.pml4_phys);
+    }
+}
+
+/// 実行禁止ビット（NX）を有効化
+///
+/// CPUがNXをサポートしていればEFER.NXEを設定します。NXEが無効なまま
+/// PTEの実行禁止ビットを立てると予約ビット違反のページフォルトになるため、
+/// `PageTableFlags::NoExecute` を使う前に確認してください。
+///
+/// # Returns
+/// NXが使用可能ならtrue
+pub fn enable_no_execute() -> bool {
+    const EFER_NXE: u64 = 1 << 11;
+
+    if !crate::cpu::features().nx {
+        return false;
+    }
+    // SAFETY: IA32_EFERはx86_64で常に存在し、NXEはCPUIDでサポートを確認済み
+    unsafe {
+        let efer = read_msr(msr::IA32_EFER);
+        if efer & EFER_NXE == 0 {
+            write_msr(msr::IA32_EFER, efer | EFER_NXE);
+        }
+    }
+    true
+}
+
+/// NXが有効か（`init` で設定）
+static NX_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 実行禁止ページに設定するフラグ（NXが使えなければ0）
+pub fn no_execute_flag() -> u64 {
+    if NX_ENABLED.load(Ordering::Relaxed) {
+        PageTableFlags::NoExecute as u64
+    } else {
+        0
+    }
+}
+
+/// 仮想アドレスを含むページのPTEのフラグ（物理アドレス部分を除く）を取得
+///
+/// ヒュージページの場合は4KBページのPTEと同じ形で返します（HugePageは含まない）。
+///
+/// # Errors
+/// * `PagingError::NotMapped` - マップされていない場合
+#[allow(dead_code)]
+pub fn page_flags(virt_addr: u64) -> Result<u64, PagingError> {
+    Ok(leaf_entry(virt_addr)? & !PTE_ADDRESS_MASK)
+}
+
+/// `set_page_flags` で変更できる保護属性のビット
+const PTE_PROTECTION_MASK: u64 = PageTableFlags::Writable as u64
+    | PageTableFlags::UserAccessible as u64
+    | PageTableFlags::NoExecute as u64;
+
+/// 仮想アドレス範囲の保護属性（書き込み・ユーザーアクセス・実行禁止）を変更
+///
+/// 範囲のPTEの保護属性を `flags` に置き換え、このCPUのTLBを無効化します。
+/// キャッシュ方式やソフトウェアの印など、他のビットは変えません。NXが使えない場合、
+/// `PageTableFlags::NoExecute` は無視します。他のCPUは次にTLBエントリを読み直すまで
+/// 古い属性を使うため、保護を強める変更は、そのページを他のCPUが使い始める前に行ってください。
+///
+/// # Arguments
+/// * `virt_addr` - 範囲の先頭（4KB境界に切り下げる）
+/// * `size` - 範囲のバイト数
+/// * `flags` - Writable / UserAccessible / NoExecute の組み合わせ（他のビットは無視）
+///
+/// # Errors
+/// * `PagingError::NotMapped` - 範囲内にマップされていないページがある場合
+/// * `PagingError::FrameAllocationFailed` - ヒュージページの分割に使うフレームが確保できない場合
+#[allow(dead_code)]
+pub fn set_page_flags(virt_addr: u64, size: usize, flags: u64) -> Result<(), PagingError> {
+    let mut protection = flags & PTE_PROTECTION_MASK;
+    if !NX_ENABLED.load(Ordering::Relaxed) {
+        protection &= !(PageTableFlags::NoExecute as u64);
+    }
+    let start = virt_addr & !(PAGE_SIZE as u64 - 1);
+    let end = (virt_addr + size as u64).next_multiple_of(PAGE_SIZE as u64);
+
+    let result = (start..end).step_by(PAGE_SIZE).try_for_each(|page| {
+        with_pt_entry(page, |entry| {
+            if !entry.is_present() {
+                return Err(PagingError::NotMapped);
+            }
+            let raw = entry.get_raw();
+            entry.set(
+                raw,
+                (raw & !PTE_ADDRESS_MASK & !PTE_PROTECTION_MASK) | protection,
+            );
+            Ok(())
+        })??;
+        invlpg(page);
+        Ok(())
+    });
+    // 途中で失敗しても、変更済みのページは他のCPUでも無効化する
+    crate::smp::flush_tlb_others(start, end);
+    result
 }
 
 // =============================================================================
@@ -432,10 +1486,11 @@ mod msr {
     pub const IA32_MTRR_PHYSBASE0: u32 = 0x200;
     pub const IA32_MTRR_PHYSMASK0: u32 = 0x201;
     pub const IA32_PAT: u32 = 0x277;
+    pub const IA32_EFER: u32 = 0xC000_0080;
 }
 
 /// メモリタイプの定義
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum MemoryType {
     Uncacheable = 0,      // UC
@@ -460,7 +1515,7 @@ impl MemoryType {
         }
     }
 
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             MemoryType::Uncacheable => "UC (Uncacheable)",
             MemoryType::WriteCombining => "WC (Write-Combining)",
@@ -490,6 +1545,22 @@ unsafe fn read_msr(msr: u32) -> u64 {
     ((high as u64) << 32) | (low as u64)
 }
 
+/// MSRに書き込む
+///
+/// # Safety
+/// - msrが有効なMSRアドレスで、valueがそのMSRに対して有効な値であること
+unsafe fn write_msr(msr: u32, value: u64) {
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
 /// MTRRの情報を表示
 pub fn dump_mtrr() {
     use crate::info;
@@ -562,3 +1633,287 @@ pub fn dump_mtrr() {
         }
     }
 }
+
+/// 物理アドレスに対するMTRRのメモリタイプを取得
+///
+/// 可変範囲MTRRが複数一致した場合は、UCが最優先、WTとWBの重なりはWTとなる規則に従います。
+/// 1MB未満の固定範囲MTRRは考慮しません。
+pub fn mtrr_memory_type(phys_addr: u64) -> MemoryType {
+    // SAFETY: MTRR関連のMSRはx86_64で常に存在し、読み込みは副作用がない
+    unsafe {
+        let def_type = read_msr(msr::IA32_MTRR_DEF_TYPE);
+        if (def_type >> 11) & 1 == 0 {
+            // MTRRが無効な場合はすべてUC
+            return MemoryType::Uncacheable;
+        }
+        let vcnt = (read_msr(msr::IA32_MTRRCAP) & 0xFF) as u32;
+
+        let mut matched: Option<MemoryType> = None;
+        for i in 0..vcnt {
+            let base = read_msr(msr::IA32_MTRR_PHYSBASE0 + i * 2);
+            let mask = read_msr(msr::IA32_MTRR_PHYSMASK0 + i * 2);
+            if (mask >> 11) & 1 == 0 {
+                continue;
+            }
+            let mask_bits = mask & PTE_ADDRESS_MASK;
+            if phys_addr & mask_bits != base & mask_bits {
+                continue;
+            }
+            let mem_type = MemoryType::from_u8((base & 0xFF) as u8);
+            matched = Some(match (matched, mem_type) {
+                (None, t) => t,
+                (Some(MemoryType::Uncacheable), _) | (_, MemoryType::Uncacheable) => {
+                    MemoryType::Uncacheable
+                }
+                (Some(MemoryType::WriteThrough), MemoryType::WriteBack)
+                | (Some(MemoryType::WriteBack), MemoryType::WriteThrough) => {
+                    MemoryType::WriteThrough
+                }
+                (Some(prev), _) => prev,
+            });
+        }
+        matched.unwrap_or(MemoryType::from_u8((def_type & 0xFF) as u8))
+    }
+}
+
+// =============================================================================
+// PAT (Page Attribute Table) 関連
+// =============================================================================
+
+/// PATの設定値
+///
+/// 電源投入時の既定値からPAT[1]だけをWTからWCに変更します（LinuxのPAT配置と同じ）。
+/// PAT[0]=WB, [1]=WC, [2]=UC-, [3]=UC, [4]=WB, [5]=WT, [6]=UC-, [7]=UC
+const PAT_VALUE: u64 = 0x0007_0406_0007_0106;
+
+/// 4KBページのPTEでPATエントリを選ぶビット（2MB/1GBページではbit12）
+const PTE_PAT: u64 = 1 << 7;
+
+/// PTEのキャッシュ方式を決めるビット（PAT/PCD/PWT）
+const PTE_CACHE_MASK: u64 =
+    PTE_PAT | PageTableFlags::CacheDisable as u64 | PageTableFlags::WriteThrough as u64;
+
+/// PAT[1]をWCに設定済みか
+static PAT_WRITE_COMBINING: AtomicBool = AtomicBool::new(false);
+
+/// ページのキャッシュ方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// ライトバック（通常のメモリ）
+    WriteBack,
+    /// ライトコンバイニング（フレームバッファ向け、`init_pat` が必要）
+    WriteCombining,
+    /// キャッシュ無効（MMIO向け）
+    Uncacheable,
+}
+
+impl CacheMode {
+    /// PTEに設定するページ属性ビット
+    pub fn pte_flags(self) -> u64 {
+        match self {
+            CacheMode::WriteBack => 0,
+            // PAT[1]
+            CacheMode::WriteCombining => PageTableFlags::WriteThrough as u64,
+            // PAT[3]
+            CacheMode::Uncacheable => {
+                PageTableFlags::WriteThrough as u64 | PageTableFlags::CacheDisable as u64
+            }
+        }
+    }
+
+    /// PTEのページ属性ビットから取得（対応しない組み合わせはNone）
+    fn from_pte(raw: u64) -> Option<Self> {
+        [
+            CacheMode::WriteBack,
+            CacheMode::WriteCombining,
+            CacheMode::Uncacheable,
+        ]
+        .into_iter()
+        .find(|mode| mode.pte_flags() == raw & PTE_CACHE_MASK)
+    }
+
+    /// 表示名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheMode::WriteBack => "wb",
+            CacheMode::WriteCombining => "wc",
+            CacheMode::Uncacheable => "uc",
+        }
+    }
+
+    /// 表示名からキャッシュ方式を取得
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wb" => Some(CacheMode::WriteBack),
+            "wc" => Some(CacheMode::WriteCombining),
+            "uc" => Some(CacheMode::Uncacheable),
+            _ => None,
+        }
+    }
+}
+
+/// PATを設定し、PAT[1]をWCにする
+///
+/// すべてのCPUで同じ値を設定する必要があるため、BSPとAPの起動時にそれぞれ呼び出します。
+///
+/// # Returns
+/// PATが使用可能ならtrue
+pub fn init_pat() -> bool {
+    if !crate::cpu::features().pat {
+        return false;
+    }
+    // SAFETY: PATはCPUIDでサポートを確認済み。変更前後にキャッシュを書き戻し、
+    // 旧設定でキャッシュされた内容が残らないようにする
+    unsafe {
+        asm!("wbinvd", options(nostack, preserves_flags));
+        write_msr(msr::IA32_PAT, PAT_VALUE);
+        asm!("wbinvd", options(nostack, preserves_flags));
+    }
+    reload_cr3();
+    PAT_WRITE_COMBINING.store(true, Ordering::Release);
+    true
+}
+
+/// 仮想アドレス範囲のキャッシュ方式を変更
+///
+/// 範囲のPTEのページ属性ビットを書き換え、このCPUのTLBを無効化します。
+/// 他のCPUは次にTLBエントリを読み直すまで古い属性を使うため、起動中に変更する場合は
+/// キャッシュしない方式（WCとUC）の間だけにしてください。
+///
+/// # Arguments
+/// * `virt_addr` - 範囲の先頭（4KB境界に切り下げる）
+/// * `size` - 範囲のバイト数
+/// * `mode` - 新しいキャッシュ方式
+///
+/// # Errors
+/// * `PagingError::PatUnavailable` - WCを指定したがPATが設定されていない場合
+/// * `PagingError::NotMapped` - 範囲内にマップされていないページがある場合
+/// * `PagingError::FrameAllocationFailed` - ヒュージページの分割に使うフレームが確保できない場合
+pub fn set_cache_mode(virt_addr: u64, size: usize, mode: CacheMode) -> Result<(), PagingError> {
+    if mode == CacheMode::WriteCombining && !PAT_WRITE_COMBINING.load(Ordering::Acquire) {
+        return Err(PagingError::PatUnavailable);
+    }
+    let start = virt_addr & !(PAGE_SIZE as u64 - 1);
+    let end = (virt_addr + size as u64).next_multiple_of(PAGE_SIZE as u64);
+
+    let result = (start..end).step_by(PAGE_SIZE).try_for_each(|page| {
+        with_pt_entry(page, |entry| {
+            if !entry.is_present() {
+                return Err(PagingError::NotMapped);
+            }
+            let raw = entry.get_raw();
+            entry.set(
+                raw,
+                (raw & !PTE_ADDRESS_MASK & !PTE_CACHE_MASK) | mode.pte_flags(),
+            );
+            Ok(())
+        })??;
+        invlpg(page);
+        Ok(())
+    });
+    // 途中で失敗しても、変更済みのページは他のCPUでも無効化する
+    crate::smp::flush_tlb_others(start, end);
+    result?;
+    // 以前の属性でキャッシュされた内容を書き戻す
+    // SAFETY: wbinvdはキャッシュを書き戻して無効化するだけで、メモリの内容は変わらない
+    unsafe { asm!("wbinvd", options(nostack, preserves_flags)) };
+    Ok(())
+}
+
+/// 仮想アドレスを含むページのキャッシュ方式を取得
+///
+/// # Returns
+/// キャッシュ方式。このカーネルが使わない組み合わせ（WT、UC-など）の場合はNone
+///
+/// # Errors
+/// * `PagingError::NotMapped` - マップされていない場合
+pub fn cache_mode(virt_addr: u64) -> Result<Option<CacheMode>, PagingError> {
+    Ok(CacheMode::from_pte(leaf_entry(virt_addr)?))
+}
+
+/// デバイスのレジスタなど、直接マッピングにないMMIO領域を高位アドレスにマップ
+///
+/// 直接マッピングと同じ仮想アドレス（`phys_to_virt`）に、書き込み可・実行禁止でマップします。
+/// 2MB境界に揃った部分は2MBページ、残りは4KBページを使います。既にマップされている
+/// ページ（メモリマップに含まれる領域や、以前に `map_mmio` でマップした領域）はそのまま使い、
+/// キャッシュ方式も変えません。変更が必要なら `set_cache_mode` を使ってください。
+///
+/// # Arguments
+/// * `phys_addr` - 領域の物理アドレス
+/// * `size` - 領域のバイト数
+/// * `mode` - キャッシュ方式
+///
+/// # Returns
+/// `phys_addr` に対応する仮想アドレス
+///
+/// # Errors
+/// * `PagingError::InvalidAddress` - 範囲が高位アドレスに収まらない場合
+/// * `PagingError::PatUnavailable` - WCを指定したがPATが設定されていない場合
+/// * `PagingError::FrameAllocationFailed` - 中間テーブル用のフレームが確保できない場合
+pub fn map_mmio(phys_addr: u64, size: u64, mode: CacheMode) -> Result<u64, PagingError> {
+    if mode == CacheMode::WriteCombining && !PAT_WRITE_COMBINING.load(Ordering::Acquire) {
+        return Err(PagingError::PatUnavailable);
+    }
+    let start = phys_addr & !(PAGE_SIZE as u64 - 1);
+    let end = phys_addr
+        .checked_add(size)
+        .filter(|&end| end <= DIRECT_MAP_LIMIT)
+        .ok_or(PagingError::InvalidAddress)?
+        .next_multiple_of(PAGE_SIZE as u64);
+
+    let huge = PageTableFlags::HugePage as u64;
+    let flags = PageTableFlags::Present as u64
+        | PageTableFlags::Writable as u64
+        | no_execute_flag()
+        | mode.pte_flags();
+    // 2MBページのエントリではbit12がPAT（bit7はHugePage）
+    let huge_flags = if flags & PTE_PAT != 0 {
+        (flags & !PTE_PAT) | PTE_HUGE_PAT | huge
+    } else {
+        flags | huge
+    };
+
+    crate::io::without_interrupts(|| {
+        let _guard = lock_page_tables();
+        let mut addr = start;
+        while addr < end {
+            let virt_addr = KERNEL_VIRTUAL_BASE + addr;
+            let [pml4_idx, pdp_idx, pd_idx, pt_idx] = table_indices(virt_addr);
+            // SAFETY: PAGE_TABLE_LOCKを保持しており、カーネルのPML4は常に有効
+            unsafe {
+                let pml4 = table_at(kernel_pml4_phys())?;
+                let pdp = next_table_or_create(pml4.entry(pml4_idx), false)?;
+                let pdp_entry = pdp.entry(pdp_idx);
+                if pdp_entry.is_present() && pdp_entry.get_raw() & huge != 0 {
+                    // 1GBページでマップ済み
+                    addr = (addr | (HUGE_PAGE_1G - 1)) + 1;
+                    continue;
+                }
+                let pd = next_table_or_create(pdp_entry, false)?;
+                let pd_entry = pd.entry(pd_idx);
+                if pd_entry.is_present() && pd_entry.get_raw() & huge != 0 {
+                    // 2MBページでマップ済み
+                    addr = (addr | (HUGE_PAGE_2M - 1)) + 1;
+                    continue;
+                }
+                if !pd_entry.is_present()
+                    && addr.is_multiple_of(HUGE_PAGE_2M)
+                    && addr + HUGE_PAGE_2M <= end
+                {
+                    pd_entry.set(addr, huge_flags);
+                    invlpg(virt_addr);
+                    addr += HUGE_PAGE_2M;
+                    continue;
+                }
+                let pt = next_table_or_create(pd_entry, false)?;
+                let entry = pt.entry(pt_idx);
+                if !entry.is_present() {
+                    entry.set(addr, flags);
+                    invlpg(virt_addr);
+                }
+            }
+            addr += PAGE_SIZE as u64;
+        }
+        phys_to_virt(phys_addr)
+    })
+}
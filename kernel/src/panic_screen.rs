@@ -0,0 +1,188 @@
+//! パニック画面
+//!
+//! シリアルを接続していない実機でもパニックの原因を読めるよう、画面全体を赤で塗り、
+//! メッセージ・発生場所・レジスタ・バックトレースをフレームバッファへ直接描画します。
+//!
+//! Compositorやロックを経由せず、ヒープも使いません。描画前に `compositor::freeze` で
+//! 画面への転送を止めるため、他のCPUで動いているCompositorに上書きされません。
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use crate::backtrace::{self, Frame};
+use crate::graphics::color::Color;
+use crate::graphics::compositor::{self, Framebuffer};
+use crate::graphics::{CELL_HEIGHT, CELL_WIDTH, FramebufferWriter};
+use crate::minidump::Registers;
+use crate::{ksyms, sched};
+
+/// 背景色
+const BACKGROUND: Color = Color::rgb(0xA0, 0x00, 0x00);
+
+/// 見出しの色
+const HEADING: Color = Color::YELLOW;
+
+/// 本文の色
+const TEXT: Color = Color::WHITE;
+
+/// 画面端からの余白（ピクセル）
+const MARGIN: usize = 16;
+
+/// 行単位で描画し、画面外にはみ出す文字を捨てるWriter
+struct Screen {
+    fb: FramebufferWriter,
+    /// 1行の最大文字数
+    columns: usize,
+    /// 描画できる行数
+    rows: usize,
+    /// 次に描画する行
+    row: usize,
+    /// 現在の行に描画した文字数
+    column: usize,
+}
+
+impl Screen {
+    fn new(fb: Framebuffer) -> Self {
+        let (width, height) = (fb.width, fb.height);
+        let mut fb = FramebufferWriter::new(fb.base, width, height, fb.stride, TEXT);
+        fb.clear_screen(BACKGROUND);
+        Self {
+            fb,
+            columns: (width as usize).saturating_sub(MARGIN * 2) / CELL_WIDTH,
+            rows: (height as usize).saturating_sub(MARGIN * 2) / CELL_HEIGHT,
+            row: 0,
+            column: 0,
+        }
+    }
+
+    /// 残りの行数
+    fn remaining(&self) -> usize {
+        self.rows.saturating_sub(self.row)
+    }
+
+    /// 1行描画（長い行は折り返す）
+    fn line(&mut self, color: Color, args: fmt::Arguments) {
+        self.fb.set_color(color);
+        let _ = self.write_fmt(args);
+        self.row += 1;
+        self.column = 0;
+    }
+}
+
+impl Write for Screen {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' || self.column == self.columns {
+                self.row += 1;
+                self.column = 0;
+                if c == '\n' {
+                    continue;
+                }
+            }
+            if self.row >= self.rows {
+                return Err(fmt::Error);
+            }
+            let x = MARGIN + self.column * CELL_WIDTH;
+            let y = MARGIN + self.row * CELL_HEIGHT;
+            self.fb.set_position(x, y);
+            // フォントはASCIIのみ
+            self.fb.write_char(if c.is_ascii() { c } else { '?' })?;
+            self.column += 1;
+        }
+        Ok(())
+    }
+}
+
+/// パニック画面を表示
+///
+/// Compositorが未初期化（フレームバッファが不明）の場合は何もしません。
+///
+/// # Arguments
+/// * `info` - パニックの情報
+/// * `regs` - パニックハンドラの先頭で取得したレジスタ
+pub fn show(info: &PanicInfo, regs: &Registers) {
+    let Some(fb) = compositor::freeze() else {
+        return;
+    };
+    let mut screen = Screen::new(fb);
+
+    screen.line(HEADING, format_args!("*** KERNEL PANIC ***"));
+    screen.line(TEXT, format_args!(""));
+    screen.line(TEXT, format_args!("{}", info.message()));
+    match info.location() {
+        Some(loc) => screen.line(
+            TEXT,
+            format_args!("at {}:{}:{}", loc.file(), loc.line(), loc.column()),
+        ),
+        None => screen.line(TEXT, format_args!("at unknown location")),
+    }
+    match sched::current_task_id_lockless() {
+        Some(id) => screen.line(TEXT, format_args!("task {}", id.as_u64())),
+        None => screen.line(TEXT, format_args!("task none")),
+    }
+    screen.line(TEXT, format_args!(""));
+
+    screen.line(HEADING, format_args!("Registers"));
+    screen.line(
+        TEXT,
+        format_args!(
+            "RAX {:016X}  RBX {:016X}  RCX {:016X}  RDX {:016X}",
+            regs.rax, regs.rbx, regs.rcx, regs.rdx
+        ),
+    );
+    screen.line(
+        TEXT,
+        format_args!(
+            "RSI {:016X}  RDI {:016X}  RBP {:016X}  RSP {:016X}",
+            regs.rsi, regs.rdi, regs.rbp, regs.rsp
+        ),
+    );
+    screen.line(
+        TEXT,
+        format_args!(
+            "R8  {:016X}  R9  {:016X}  R10 {:016X}  R11 {:016X}",
+            regs.r8, regs.r9, regs.r10, regs.r11
+        ),
+    );
+    screen.line(
+        TEXT,
+        format_args!(
+            "R12 {:016X}  R13 {:016X}  R14 {:016X}  R15 {:016X}",
+            regs.r12, regs.r13, regs.r14, regs.r15
+        ),
+    );
+    screen.line(
+        TEXT,
+        format_args!(
+            "RIP {:016X}  RFL {:016X}  CR2 {:016X}  CR3 {:016X}",
+            regs.rip, regs.rflags, regs.cr2, regs.cr3
+        ),
+    );
+    screen.line(TEXT, format_args!(""));
+
+    if ksyms::is_embedded() {
+        screen.line(HEADING, format_args!("Backtrace"));
+    } else {
+        screen.line(
+            HEADING,
+            format_args!("Backtrace (no symbol table embedded)"),
+        );
+    }
+    // 末尾の案内の2行を残す
+    let mut index = 0;
+    backtrace::walk(regs.rbp, |addr| {
+        if screen.remaining() > 2 {
+            screen.line(TEXT, format_args!("#{:<2} {}", index, Frame(addr)));
+        }
+        index += 1;
+    });
+    if index == 0 {
+        screen.line(TEXT, format_args!("(no frames)"));
+    }
+
+    screen.line(TEXT, format_args!(""));
+    screen.line(
+        HEADING,
+        format_args!("System halted. A full minidump was written to the serial port."),
+    );
+}
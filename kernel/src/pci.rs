@@ -3,10 +3,12 @@
 //! PCIデバイスを列挙し、設定空間にアクセスします。
 //! MMCONFIG (MCFG経由) を優先し、利用できない場合はレガシーI/Oポートを使用します。
 
-use crate::info;
-use crate::paging::KERNEL_VIRTUAL_BASE;
-use core::arch::asm;
-use core::ptr::read_volatile;
+use crate::io::{port_read_u32, port_write_u32, without_interrupts};
+use crate::iotrace::{self, Device, Direction};
+use crate::paging::{self, CacheMode, KERNEL_VIRTUAL_BASE};
+use crate::{info, warn};
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
 use core::sync::atomic::{AtomicU64, Ordering};
 
 /// PCI Configuration Address レジスタ (I/Oポート 0xCF8)
@@ -15,6 +17,31 @@ const CONFIG_ADDRESS: u16 = 0xCF8;
 /// PCI Configuration Data レジスタ (I/Oポート 0xCFC)
 const CONFIG_DATA: u16 = 0xCFC;
 
+/// Command レジスタ: I/O空間へのアクセスを有効化
+const COMMAND_IO_SPACE: u16 = 1 << 0;
+/// Command レジスタ: メモリ空間へのアクセスを有効化
+const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+/// Command レジスタ: バスマスタ（DMA）を有効化
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+/// Status レジスタ: ケーパビリティリストが存在する
+const STATUS_CAPABILITIES_LIST: u16 = 1 << 4;
+
+/// BAR0のオフセット
+const BAR0_OFFSET: u8 = 0x10;
+/// BARの最大数（ヘッダタイプ0）
+const MAX_BARS: usize = 6;
+
+/// ケーパビリティリストをたどる最大回数（壊れたリストでの無限ループを防ぐ）
+const MAX_CAPABILITIES: usize = 48;
+
+/// ケーパビリティID: MSI
+pub const CAP_ID_MSI: u8 = 0x05;
+/// ケーパビリティID: PCI Express
+pub const CAP_ID_PCIE: u8 = 0x10;
+/// ケーパビリティID: MSI-X
+pub const CAP_ID_MSIX: u8 = 0x11;
+
 /// MMCONFIG設定
 /// base_address: MCFGテーブルから取得したベースアドレス（0の場合は未設定）
 static MMCONFIG_BASE: AtomicU64 = AtomicU64::new(0);
@@ -37,6 +64,203 @@ pub struct PciDevice {
     pub header_type: u8,
 }
 
+/// デコード済みのBAR（Base Address Register）
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    /// I/O空間
+    Io {
+        /// ベースポート
+        port: u16,
+        /// ポート数
+        size: u32,
+    },
+    /// メモリ空間（MMIO）
+    Memory {
+        /// ベース物理アドレス
+        base: u64,
+        /// 領域サイズ（バイト）
+        size: u64,
+        /// 64ビットBAR（次のBARが上位32ビット）か
+        is_64bit: bool,
+        /// プリフェッチ可能か
+        prefetchable: bool,
+    },
+}
+
+impl fmt::Display for Bar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bar::Io { port, size } => write!(f, "I/O 0x{:04X} (size 0x{:X})", port, size),
+            Bar::Memory {
+                base,
+                size,
+                is_64bit,
+                prefetchable,
+            } => write!(
+                f,
+                "Mem{} 0x{:X} (size 0x{:X}){}",
+                if *is_64bit { 64 } else { 32 },
+                base,
+                size,
+                if *prefetchable { ", prefetchable" } else { "" }
+            ),
+        }
+    }
+}
+
+/// ケーパビリティリストの1エントリ
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capability {
+    /// ケーパビリティID
+    pub id: u8,
+    /// 設定空間内のオフセット
+    pub offset: u8,
+}
+
+/// MSIケーパビリティ
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct MsiCapability {
+    /// 設定空間内のオフセット
+    pub offset: u8,
+    /// 64ビットのメッセージアドレスに対応するか
+    pub is_64bit: bool,
+    /// ベクタごとのマスクに対応するか
+    pub per_vector_masking: bool,
+    /// 要求可能な最大ベクタ数
+    pub max_vectors: u8,
+    /// 有効化されているか
+    pub enabled: bool,
+}
+
+/// MSI-Xケーパビリティ
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct MsixCapability {
+    /// 設定空間内のオフセット
+    pub offset: u8,
+    /// テーブルのエントリ数
+    pub table_size: u16,
+    /// テーブルを含むBAR番号
+    pub table_bar: u8,
+    /// BAR先頭からのテーブルのオフセット
+    pub table_offset: u32,
+    /// PBA（Pending Bit Array）を含むBAR番号
+    pub pba_bar: u8,
+    /// BAR先頭からのPBAのオフセット
+    pub pba_offset: u32,
+    /// 有効化されているか
+    pub enabled: bool,
+}
+
+/// PCI Expressケーパビリティ
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct PcieCapability {
+    /// 設定空間内のオフセット
+    pub offset: u8,
+    /// ケーパビリティのバージョン
+    pub version: u8,
+    /// デバイス/ポートの種別
+    pub port_type: u8,
+}
+
+impl PcieCapability {
+    /// デバイス/ポート種別の名前を取得
+    pub fn port_type_name(&self) -> &'static str {
+        match self.port_type {
+            0x0 => "Endpoint",
+            0x1 => "Legacy Endpoint",
+            0x4 => "Root Port",
+            0x5 => "Upstream Port",
+            0x6 => "Downstream Port",
+            0x7 => "PCIe-to-PCI Bridge",
+            0x8 => "PCI-to-PCIe Bridge",
+            0x9 => "Root Complex Integrated Endpoint",
+            0xA => "Root Complex Event Collector",
+            _ => "Unknown",
+        }
+    }
+}
+
+/// デコード済みのケーパビリティ
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum CapabilityInfo {
+    Msi(MsiCapability),
+    Msix(MsixCapability),
+    Pcie(PcieCapability),
+    /// デコードに対応していないケーパビリティ
+    Other(Capability),
+}
+
+impl fmt::Display for CapabilityInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CapabilityInfo::Msi(msi) => write!(
+                f,
+                "MSI: {} vector(s), {}-bit{}{}",
+                msi.max_vectors,
+                if msi.is_64bit { 64 } else { 32 },
+                if msi.per_vector_masking {
+                    ", maskable"
+                } else {
+                    ""
+                },
+                if msi.enabled { ", enabled" } else { "" }
+            ),
+            CapabilityInfo::Msix(msix) => write!(
+                f,
+                "MSI-X: {} vector(s), table BAR{}+0x{:X}, PBA BAR{}+0x{:X}{}",
+                msix.table_size,
+                msix.table_bar,
+                msix.table_offset,
+                msix.pba_bar,
+                msix.pba_offset,
+                if msix.enabled { ", enabled" } else { "" }
+            ),
+            CapabilityInfo::Pcie(pcie) => write!(
+                f,
+                "PCI Express v{}: {}",
+                pcie.version,
+                pcie.port_type_name()
+            ),
+            CapabilityInfo::Other(cap) => write!(f, "ID 0x{:02X}", cap.id),
+        }
+    }
+}
+
+/// ケーパビリティリストのイテレータ
+pub struct CapabilityIter {
+    device: PciDevice,
+    /// 次に読むエントリのオフセット（0なら終端）
+    next: u8,
+    /// 残りの最大エントリ数
+    remaining: usize,
+}
+
+impl Iterator for CapabilityIter {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Capability> {
+        // 0x40未満は標準ヘッダ領域のため、リストの終端か壊れたポインタとみなす
+        if self.next < 0x40 || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let offset = self.next;
+        let header = self.device.read_config_u16(offset);
+        self.next = (header >> 8) as u8 & 0xFC;
+        Some(Capability {
+            id: header as u8,
+            offset,
+        })
+    }
+}
+
 impl PciDevice {
     /// デバイス情報を読み込んで新しいPciDeviceを作成
     /// MMCONFIG優先、利用できない場合はレガシーI/Oポートを使用
@@ -69,6 +293,294 @@ impl PciDevice {
         })
     }
 
+    /// 設定空間から32ビット値を読み込む
+    ///
+    /// # Arguments
+    /// * `offset` - レジスタオフセット (4バイトアラインメント)
+    pub fn read_config_u32(&self, offset: u8) -> u32 {
+        pci_unified_read_u32(self.bus, self.device, self.function, offset)
+    }
+
+    /// 設定空間から16ビット値を読み込む
+    ///
+    /// # Arguments
+    /// * `offset` - レジスタオフセット (2バイトアラインメント)
+    pub fn read_config_u16(&self, offset: u8) -> u16 {
+        pci_unified_read_u16(self.bus, self.device, self.function, offset)
+    }
+
+    /// 設定空間から8ビット値を読み込む
+    ///
+    /// # Arguments
+    /// * `offset` - レジスタオフセット
+    pub fn read_config_u8(&self, offset: u8) -> u8 {
+        pci_unified_read_u8(self.bus, self.device, self.function, offset)
+    }
+
+    /// 設定空間へ32ビット値を書き込む
+    ///
+    /// # Arguments
+    /// * `offset` - レジスタオフセット (4バイトアラインメント)
+    /// * `value` - 書き込む値
+    pub fn write_config_u32(&self, offset: u8, value: u32) {
+        pci_unified_write_u32(self.bus, self.device, self.function, offset, value);
+    }
+
+    /// I/O空間のBARのベースポートを取得
+    ///
+    /// # Arguments
+    /// * `index` - BAR番号 (0-5)
+    ///
+    /// # Returns
+    /// BARがI/O空間を指していればそのポート番号、メモリ空間または未実装ならNone
+    pub fn io_bar(&self, index: u8) -> Option<u16> {
+        if index >= 6 {
+            return None;
+        }
+        let bar = self.read_config_u32(0x10 + index * 4);
+        // bit 0: 1ならI/O空間
+        if bar & 0x1 == 0 || bar & !0x3 == 0 {
+            return None;
+        }
+        Some((bar & !0x3) as u16)
+    }
+
+    /// I/O・メモリ空間へのアクセスとバスマスタ（DMA）を有効化
+    pub fn enable_bus_master(&self) {
+        // Command レジスタは下位16ビット（上位16ビットのStatusは書き込み1でクリアのため0を書く）
+        let command = self.read_config_u32(0x04) as u16;
+        let command = command | COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER;
+        self.write_config_u32(0x04, command as u32);
+    }
+
+    /// ヘッダタイプに応じたBARの数
+    fn bar_count(&self) -> usize {
+        match self.header_type & 0x7F {
+            0x00 => MAX_BARS,
+            // PCI-to-PCIブリッジ
+            0x01 => 2,
+            _ => 0,
+        }
+    }
+
+    /// 全BARをデコード
+    ///
+    /// サイズは全ビット1を書き込んで読み戻す方法で調べます。調査中は
+    /// I/O・メモリデコードを無効化し、終了後に元のBAR値とCommandレジスタを戻します。
+    ///
+    /// # Returns
+    /// BAR番号をインデックスとした配列。未実装のBARと、64ビットBARの上位半分はNone
+    pub fn bars(&self) -> [Option<Bar>; MAX_BARS] {
+        let mut bars = [None; MAX_BARS];
+        let count = self.bar_count();
+        if count == 0 {
+            return bars;
+        }
+
+        without_interrupts(|| {
+            let command = self.read_config_u32(0x04) as u16;
+            self.write_config_u32(
+                0x04,
+                (command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE)) as u32,
+            );
+
+            let mut index = 0;
+            while index < count {
+                let (bar, used) = self.decode_bar(index, count);
+                bars[index] = bar;
+                index += used;
+            }
+
+            self.write_config_u32(0x04, command as u32);
+        });
+
+        bars
+    }
+
+    /// BARを1つデコード
+    ///
+    /// # Arguments
+    /// * `index` - BAR番号 (0-5)
+    ///
+    /// # Returns
+    /// BARがメモリ空間またはI/O空間を指していればSome、未実装ならNone
+    #[allow(dead_code)]
+    pub fn bar(&self, index: u8) -> Option<Bar> {
+        self.bars().get(index as usize).copied().flatten()
+    }
+
+    /// index番目のBARをデコード（呼び出し側でデコードを無効化しておくこと）
+    ///
+    /// # Returns
+    /// (デコード結果, 消費したBARスロット数)
+    fn decode_bar(&self, index: usize, count: usize) -> (Option<Bar>, usize) {
+        let offset = BAR0_OFFSET + index as u8 * 4;
+        let raw = self.read_config_u32(offset);
+        let mask = self.probe_bar_mask(offset);
+
+        // bit 0: 1ならI/O空間
+        if raw & 0x1 != 0 {
+            // I/Oポートは16ビット（上位ビットは0に固定されている場合がある）
+            let size_mask = (mask & 0xFFFC) as u16;
+            if size_mask == 0 {
+                return (None, 1);
+            }
+            let bar = Bar::Io {
+                port: (raw & 0xFFFC) as u16,
+                size: (!size_mask).wrapping_add(1) as u32,
+            };
+            return (Some(bar), 1);
+        }
+
+        // bit 2-1: 00なら32ビット、10なら64ビット
+        let is_64bit = (raw >> 1) & 0x3 == 0x2;
+        let prefetchable = raw & 0x8 != 0;
+
+        if is_64bit {
+            if index + 1 >= count {
+                return (None, 1);
+            }
+            let raw_high = self.read_config_u32(offset + 4);
+            let mask_high = self.probe_bar_mask(offset + 4);
+            let size_mask = ((mask_high as u64) << 32) | (mask & 0xFFFF_FFF0) as u64;
+            if size_mask == 0 {
+                return (None, 2);
+            }
+            let bar = Bar::Memory {
+                base: ((raw_high as u64) << 32) | (raw & 0xFFFF_FFF0) as u64,
+                size: (!size_mask).wrapping_add(1),
+                is_64bit,
+                prefetchable,
+            };
+            return (Some(bar), 2);
+        }
+
+        let size_mask = mask & 0xFFFF_FFF0;
+        if size_mask == 0 {
+            return (None, 1);
+        }
+        let bar = Bar::Memory {
+            base: (raw & 0xFFFF_FFF0) as u64,
+            size: (!size_mask).wrapping_add(1) as u64,
+            is_64bit,
+            prefetchable,
+        };
+        (Some(bar), 1)
+    }
+
+    /// BARに全ビット1を書き込んで読み戻し、元の値に戻す
+    fn probe_bar_mask(&self, offset: u8) -> u32 {
+        let original = self.read_config_u32(offset);
+        self.write_config_u32(offset, 0xFFFF_FFFF);
+        let mask = self.read_config_u32(offset);
+        self.write_config_u32(offset, original);
+        mask
+    }
+
+    /// ケーパビリティリストを列挙
+    pub fn capabilities(&self) -> CapabilityIter {
+        let status = self.read_config_u16(0x06);
+        let next = if status & STATUS_CAPABILITIES_LIST == 0 {
+            0
+        } else {
+            // CardBusブリッジ（ヘッダタイプ2）はポインタの位置が異なる
+            let pointer_offset = if self.header_type & 0x7F == 0x02 {
+                0x14
+            } else {
+                0x34
+            };
+            self.read_config_u8(pointer_offset) & 0xFC
+        };
+
+        CapabilityIter {
+            device: *self,
+            next,
+            remaining: MAX_CAPABILITIES,
+        }
+    }
+
+    /// 指定IDのケーパビリティを検索
+    ///
+    /// # Returns
+    /// 見つかったケーパビリティの設定空間内オフセット
+    #[allow(dead_code)]
+    pub fn find_capability(&self, id: u8) -> Option<u8> {
+        self.capabilities()
+            .find(|cap| cap.id == id)
+            .map(|cap| cap.offset)
+    }
+
+    /// MSIケーパビリティを取得
+    #[allow(dead_code)]
+    pub fn msi(&self) -> Option<MsiCapability> {
+        self.find_capability(CAP_ID_MSI)
+            .map(|offset| self.decode_msi(offset))
+    }
+
+    /// MSI-Xケーパビリティを取得
+    #[allow(dead_code)]
+    pub fn msix(&self) -> Option<MsixCapability> {
+        self.find_capability(CAP_ID_MSIX)
+            .map(|offset| self.decode_msix(offset))
+    }
+
+    /// PCI Expressケーパビリティを取得
+    #[allow(dead_code)]
+    pub fn pcie(&self) -> Option<PcieCapability> {
+        self.find_capability(CAP_ID_PCIE)
+            .map(|offset| self.decode_pcie(offset))
+    }
+
+    /// ケーパビリティの内容をデコード
+    pub fn capability_info(&self, cap: Capability) -> CapabilityInfo {
+        match cap.id {
+            CAP_ID_MSI => CapabilityInfo::Msi(self.decode_msi(cap.offset)),
+            CAP_ID_MSIX => CapabilityInfo::Msix(self.decode_msix(cap.offset)),
+            CAP_ID_PCIE => CapabilityInfo::Pcie(self.decode_pcie(cap.offset)),
+            _ => CapabilityInfo::Other(cap),
+        }
+    }
+
+    fn decode_msi(&self, offset: u8) -> MsiCapability {
+        // Message Control: bit 0 = Enable, bit 3-1 = Multiple Message Capable (log2),
+        // bit 7 = 64ビットアドレス対応, bit 8 = ベクタごとのマスク対応
+        let control = self.read_config_u16(offset + 2);
+        MsiCapability {
+            offset,
+            is_64bit: control & (1 << 7) != 0,
+            per_vector_masking: control & (1 << 8) != 0,
+            max_vectors: 1 << ((control >> 1) & 0x7).min(5),
+            enabled: control & 0x1 != 0,
+        }
+    }
+
+    fn decode_msix(&self, offset: u8) -> MsixCapability {
+        // Message Control: bit 10-0 = テーブルサイズ - 1, bit 15 = Enable
+        let control = self.read_config_u16(offset + 2);
+        // Table/PBA: bit 2-0 = BAR番号 (BIR), 残りがBAR先頭からのオフセット
+        let table = self.read_config_u32(offset + 4);
+        let pba = self.read_config_u32(offset + 8);
+        MsixCapability {
+            offset,
+            table_size: (control & 0x7FF) + 1,
+            table_bar: (table & 0x7) as u8,
+            table_offset: table & !0x7,
+            pba_bar: (pba & 0x7) as u8,
+            pba_offset: pba & !0x7,
+            enabled: control & (1 << 15) != 0,
+        }
+    }
+
+    fn decode_pcie(&self, offset: u8) -> PcieCapability {
+        // PCI Express Capabilities: bit 3-0 = バージョン, bit 7-4 = デバイス/ポート種別
+        let caps = self.read_config_u16(offset + 2);
+        PcieCapability {
+            offset,
+            version: (caps & 0xF) as u8,
+            port_type: ((caps >> 4) & 0xF) as u8,
+        }
+    }
+
     /// デバイスのクラス名を取得
     pub fn class_name(&self) -> &'static str {
         match self.class_code {
@@ -118,24 +630,35 @@ fn pci_config_read_u32(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
         | ((function as u32) << 8)
         | ((offset as u32) & 0xFC);
 
+    // SAFETY: CONFIG_ADDRESS/CONFIG_DATAはPCIコンフィギュレーション機構#1の固定ポート
     unsafe {
         // CONFIG_ADDRESS レジスタにアドレスを書き込む
-        asm!(
-            "out dx, eax",
-            in("dx") CONFIG_ADDRESS,
-            in("eax") address,
-            options(nomem, nostack, preserves_flags)
-        );
-
+        port_write_u32(CONFIG_ADDRESS, address);
         // CONFIG_DATA レジスタからデータを読み込む
-        let data: u32;
-        asm!(
-            "in eax, dx",
-            in("dx") CONFIG_DATA,
-            out("eax") data,
-            options(nomem, nostack, preserves_flags)
-        );
-        data
+        port_read_u32(CONFIG_DATA)
+    }
+}
+
+/// PCI Configuration Space へ32ビット値を書き込む
+///
+/// # Arguments
+/// * `bus` - PCIバス番号 (0-255)
+/// * `device` - デバイス番号 (0-31)
+/// * `function` - ファンクション番号 (0-7)
+/// * `offset` - レジスタオフセット (4バイトアラインメント)
+/// * `value` - 書き込む値
+fn pci_config_write_u32(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    // アドレスの構築は pci_config_read_u32 と同じ
+    let address: u32 = (1 << 31)
+        | ((bus as u32) << 16)
+        | ((device as u32) << 11)
+        | ((function as u32) << 8)
+        | ((offset as u32) & 0xFC);
+
+    // SAFETY: CONFIG_ADDRESS/CONFIG_DATAはPCIコンフィギュレーション機構#1の固定ポート
+    unsafe {
+        port_write_u32(CONFIG_ADDRESS, address);
+        port_write_u32(CONFIG_DATA, value);
     }
 }
 
@@ -155,6 +678,17 @@ pub fn set_mmconfig(base_address: u64, segment: u16, start_bus: u8, end_bus: u8)
         return;
     }
 
+    // 対象バスの構成空間（1バスあたり1MB）は直接マッピングに含まれないため、ここでマップする
+    let first = base_address + ((start_bus as u64) << 20);
+    let size = ((end_bus as u64).saturating_sub(start_bus as u64) + 1) << 20;
+    if let Err(e) = paging::map_mmio(first, size, CacheMode::Uncacheable) {
+        warn!(
+            "  Failed to map MMCONFIG region, ignoring MMCONFIG entry: {}",
+            e
+        );
+        return;
+    }
+
     MMCONFIG_BASE.store(base_address, Ordering::SeqCst);
     MMCONFIG_START_BUS.store(start_bus as u64, Ordering::SeqCst);
     MMCONFIG_END_BUS.store(end_bus as u64, Ordering::SeqCst);
@@ -196,7 +730,29 @@ unsafe fn mmconfig_read_u32(bus: u8, device: u8, function: u8, offset: u16) -> u
     // 高位仮想アドレスに変換
     let virt_addr = KERNEL_VIRTUAL_BASE + phys_addr;
 
-    unsafe { read_volatile(virt_addr as *const u32) }
+    let value = unsafe { read_volatile(virt_addr as *const u32) };
+    iotrace::mmio(Device::Pci, virt_addr, 32, value as u64, Direction::Read);
+    value
+}
+
+/// MMCONFIG経由でPCI Configuration Spaceへ32ビット値を書き込む
+///
+/// # Safety
+/// この関数はMMCONFIGが有効な場合のみ呼び出すべきです
+unsafe fn mmconfig_write_u32(bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+    let base = MMCONFIG_BASE.load(Ordering::SeqCst);
+
+    // アドレス計算は mmconfig_read_u32 と同じ
+    let phys_addr = base
+        + ((bus as u64) << 20)
+        + ((device as u64) << 15)
+        + ((function as u64) << 12)
+        + (offset as u64);
+
+    let virt_addr = KERNEL_VIRTUAL_BASE + phys_addr;
+
+    unsafe { write_volatile(virt_addr as *mut u32, value) };
+    iotrace::mmio(Device::Pci, virt_addr, 32, value as u64, Direction::Write);
 }
 
 /// 統合されたPCI Configuration Space読み込み（MMCONFIG優先、フォールバック対応）
@@ -210,6 +766,15 @@ fn pci_unified_read_u32(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
     }
 }
 
+/// 統合されたPCI Configuration Space書き込み（MMCONFIG優先、フォールバック対応）
+fn pci_unified_write_u32(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    if is_mmconfig_available(bus) {
+        unsafe { mmconfig_write_u32(bus, device, function, offset as u16, value) }
+    } else {
+        pci_config_write_u32(bus, device, function, offset, value)
+    }
+}
+
 /// 統合されたPCI Configuration Space から16ビット値を読み込む
 fn pci_unified_read_u16(bus: u8, device: u8, function: u8, offset: u8) -> u16 {
     let data = pci_unified_read_u32(bus, device, function, offset & 0xFC);
@@ -224,28 +789,18 @@ fn pci_unified_read_u8(bus: u8, device: u8, function: u8, offset: u8) -> u8 {
     ((data >> shift) & 0xFF) as u8
 }
 
-/// PCIバスをスキャンしてデバイスを列挙
-pub fn scan_pci_bus() {
-    let mmconfig_base = MMCONFIG_BASE.load(Ordering::SeqCst);
-    if mmconfig_base != 0 {
-        info!(
-            "Scanning PCI bus (using MMCONFIG at 0x{:X})...",
-            mmconfig_base
-        );
-    } else {
-        info!("Scanning PCI bus (using legacy I/O ports)...");
-    }
-
-    let mut device_count = 0;
-
+/// 全てのPCIデバイスを列挙してコールバックを呼び出す
+///
+/// # Arguments
+/// * `f` - 見つかったデバイスごとに呼ばれるコールバック
+pub fn for_each_device(mut f: impl FnMut(&PciDevice)) {
     // すべてのバスをスキャン (0-255)
     for bus in 0..=255u8 {
         // 各バスのすべてのデバイスをスキャン (0-31)
         for device in 0..32u8 {
             // ファンクション0をチェック
             if let Some(pci_dev) = PciDevice::read(bus, device, 0) {
-                device_count += 1;
-                print_device(&pci_dev);
+                f(&pci_dev);
 
                 // ヘッダタイプのbit 7が1なら、マルチファンクションデバイス
                 let is_multi_function = (pci_dev.header_type & 0x80) != 0;
@@ -254,14 +809,32 @@ pub fn scan_pci_bus() {
                     // ファンクション1-7もスキャン
                     for function in 1..8u8 {
                         if let Some(func_dev) = PciDevice::read(bus, device, function) {
-                            device_count += 1;
-                            print_device(&func_dev);
+                            f(&func_dev);
                         }
                     }
                 }
             }
         }
     }
+}
+
+/// PCIバスをスキャンしてデバイスを列挙
+pub fn scan_pci_bus() {
+    let mmconfig_base = MMCONFIG_BASE.load(Ordering::SeqCst);
+    if mmconfig_base != 0 {
+        info!(
+            "Scanning PCI bus (using MMCONFIG at 0x{:X})...",
+            mmconfig_base
+        );
+    } else {
+        info!("Scanning PCI bus (using legacy I/O ports)...");
+    }
+
+    let mut device_count = 0;
+    for_each_device(|dev| {
+        device_count += 1;
+        print_device(dev);
+    });
 
     info!("PCI scan complete. Found {} device(s)", device_count);
 }
@@ -279,4 +852,16 @@ fn print_device(dev: &PciDevice) {
         dev.class_code,
         dev.subclass
     );
+    for (index, bar) in dev.bars().iter().enumerate() {
+        if let Some(bar) = bar {
+            info!("      BAR{}: {}", index, bar);
+        }
+    }
+    for cap in dev.capabilities() {
+        info!(
+            "      Cap 0x{:02X}: {}",
+            cap.offset,
+            dev.capability_info(cap)
+        );
+    }
 }
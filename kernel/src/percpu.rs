@@ -0,0 +1,200 @@
+//! CPUごとのデータ
+//!
+//! 各CPUのGSベース（IA32_GS_BASE MSR）に、そのCPUの `PerCpu` のアドレスを設定します。
+//! `gs:[0]` にCPU番号を置くため、CPU番号はロックやMSRの読み出しなしで取得できます。
+//!
+//! GSセレクタを再ロードするとGSベースが上書きされるため、GDTのロード時にGSはリロードしません。
+//!
+//! ユーザーモードのプログラムはGSセレクタを変更できるため、カーネルのGSベースはユーザーモードの
+//! 間 IA32_KERNEL_GS_BASE に退避しておきます。割り込み・例外・システムコールの入口は、
+//! 中断したコードがRing 3ならswapgsでカーネルのGSベースに切り替え、出口で戻します
+//! （`swapgs_if_user!`）。どこでも発生するNMIなどは、GSベースの値から切り替えの要否を判断します
+//! （`swapgs_paranoid_entry!`）。カーネルの実行中は常にIA32_GS_BASEがカーネルの値です。
+//!
+//! FSGSBASEは有効にしないため、ユーザーモードのGSベースはセレクタが指す値（0）のみです。
+//! タスクごとには保存しません。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::smp::MAX_CPUS;
+
+/// IA32_GS_BASE MSR
+const IA32_GS_BASE: u32 = 0xC000_0101;
+
+/// IA32_KERNEL_GS_BASE MSR（swapgsでIA32_GS_BASEと交換される）
+const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+/// 割り込み・例外の入口/出口で、中断したコードがRing 3ならGSベースを切り替える命令列
+///
+/// `$cs` はCPUが積んだCSのスタック上の位置（例: `"[rsp + 8]"`）です。
+/// 入口ではレジスタを積む前に、出口ではiretqの直前に置きます。
+macro_rules! swapgs_if_user {
+    ($cs:literal) => {
+        concat!(
+            "test byte ptr ",
+            $cs,
+            ", 3\n",
+            "jz 90f\n",
+            "swapgs\n",
+            "90:\n",
+        )
+    };
+}
+pub(crate) use swapgs_if_user;
+
+/// どこでも発生する例外（NMI・#MC・#DF）の入口でGSベースを切り替える命令列
+///
+/// ユーザーモードから入った直後やiretqの直前に発生するとCSとGSベースが一致しないため、
+/// IA32_GS_BASEがカーネルのアドレス（上位ビットが1）かどうかで判断します。
+/// 切り替えたかどうかをRBX（callee-saved）に残すため、汎用レジスタを保存した後に置き、
+/// 出口では `swapgs_paranoid_exit!` をレジスタの復元前に置きます。
+macro_rules! swapgs_paranoid_entry {
+    () => {
+        concat!(
+            "mov ecx, 0xC0000101\n", // IA32_GS_BASE
+            "rdmsr\n",
+            "xor ebx, ebx\n",
+            "test edx, edx\n",
+            "js 91f\n",
+            "swapgs\n",
+            "mov ebx, 1\n",
+            "91:\n",
+        )
+    };
+}
+pub(crate) use swapgs_paranoid_entry;
+
+/// `swapgs_paranoid_entry!` で切り替えたGSベースを戻す命令列
+macro_rules! swapgs_paranoid_exit {
+    () => {
+        concat!("test ebx, ebx\n", "jz 92f\n", "swapgs\n", "92:\n")
+    };
+}
+pub(crate) use swapgs_paranoid_exit;
+
+/// BSPのCPU番号
+pub const BSP_INDEX: usize = 0;
+
+/// CPUごとのデータ
+#[repr(C)]
+pub struct PerCpu {
+    /// CPU番号（`gs:[0]` から読むため先頭に置く）
+    index: AtomicUsize,
+    /// このCPUのTSSのアドレス
+    tss: AtomicU64,
+}
+
+impl PerCpu {
+    const fn new(index: usize) -> Self {
+        Self {
+            index: AtomicUsize::new(index),
+            tss: AtomicU64::new(0),
+        }
+    }
+
+    /// CPU番号
+    #[allow(dead_code)]
+    pub fn index(&self) -> usize {
+        self.index.load(Ordering::Relaxed)
+    }
+
+    /// このCPUのTSSのアドレス（未設定なら0）
+    pub fn tss(&self) -> u64 {
+        self.tss.load(Ordering::Relaxed)
+    }
+}
+
+static CPUS: [PerCpu; MAX_CPUS] = {
+    let mut cpus = [const { PerCpu::new(0) }; MAX_CPUS];
+    let mut index = 0;
+    while index < MAX_CPUS {
+        cpus[index] = PerCpu::new(index);
+        index += 1;
+    }
+    cpus
+};
+
+/// BSPのGSベースを設定済みか（設定前はすべてBSPとして扱う）
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// GSベースを設定
+///
+/// ユーザーモード用のGSベース（IA32_KERNEL_GS_BASE）は0にします。
+///
+/// # Safety
+/// `base` は `CPUS` の要素を指すこと
+unsafe fn set_gs_base(base: u64) {
+    // SAFETY: IA32_GS_BASE/IA32_KERNEL_GS_BASEはx86_64で常に存在し、呼び出し元が有効なアドレスを渡す
+    unsafe {
+        asm!(
+            "wrmsr",
+            in("ecx") IA32_GS_BASE,
+            in("eax") base as u32,
+            in("edx") (base >> 32) as u32,
+            options(nostack, preserves_flags)
+        );
+        asm!(
+            "wrmsr",
+            in("ecx") IA32_KERNEL_GS_BASE,
+            in("eax") 0,
+            in("edx") 0,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// BSPのCPUごとのデータを設定
+///
+/// GDTのロード後、スケジューラやヒープを使う前に一度だけ呼び出します。
+///
+/// # Arguments
+/// * `tss` - BSPのTSSのアドレス
+pub fn init_bsp(tss: u64) {
+    let cpu = &CPUS[BSP_INDEX];
+    cpu.tss.store(tss, Ordering::Relaxed);
+    // SAFETY: CPUS[BSP_INDEX]は'staticなPerCpu
+    unsafe { set_gs_base(cpu as *const PerCpu as u64) };
+    READY.store(true, Ordering::Release);
+}
+
+/// アプリケーションプロセッサのCPUごとのデータを設定
+///
+/// APの起動直後、ヒープなどCPU番号を参照する処理より前に呼び出します。
+///
+/// # Arguments
+/// * `index` - CPU番号（`MAX_CPUS` 未満）
+pub fn init_ap(index: usize) {
+    // SAFETY: CPUS[index]は'staticなPerCpu（範囲外ならインデックスでパニック）
+    unsafe { set_gs_base(&CPUS[index] as *const PerCpu as u64) };
+}
+
+/// 現在のCPUのTSSのアドレスを設定
+pub fn set_tss(tss: u64) {
+    this_cpu().tss.store(tss, Ordering::Relaxed);
+}
+
+/// 現在のCPU番号
+///
+/// 割り込み有効状態で呼び出した場合、戻り値を使う前に別のCPUへ移動している可能性があります。
+pub fn current_index() -> usize {
+    if !READY.load(Ordering::Acquire) {
+        return BSP_INDEX;
+    }
+    let index: usize;
+    // SAFETY: GSベースは現在のCPUのPerCpuを指しており、先頭はCPU番号
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) index, options(nostack, readonly, preserves_flags));
+    }
+    index
+}
+
+/// 現在のCPUがBSPか
+pub fn is_bsp() -> bool {
+    current_index() == BSP_INDEX
+}
+
+/// 現在のCPUのデータ
+pub fn this_cpu() -> &'static PerCpu {
+    &CPUS[current_index()]
+}
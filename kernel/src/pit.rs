@@ -120,12 +120,17 @@ pub fn oneshot(count: u16) {
 
 /// PITでマイクロ秒単位の遅延を実現
 ///
+/// 16bitカウンタのため、待機できるのは約54.9msまでです（`clock::delay_us` は分割して呼び出す）。
+///
 /// # Arguments
 /// * `us` - 待機時間（マイクロ秒）
-#[allow(dead_code)]
 pub fn udelay(us: u32) {
     // 1マイクロ秒 = PIT_FREQUENCY / 1_000_000 カウント
-    let count = ((PIT_FREQUENCY as u64 * us as u64) / 1_000_000) as u16;
+    let count = ((PIT_FREQUENCY as u64 * us as u64) / 1_000_000).min(u16::MAX as u64) as u16;
+    // カウント0はPITでは65536を意味するため、待たずに戻る
+    if count == 0 {
+        return;
+    }
 
     unsafe {
         // One-shot mode
@@ -134,15 +139,16 @@ pub fn udelay(us: u32) {
         outb(ports::CHANNEL_0, ((count >> 8) & 0xFF) as u8);
 
         // カウントが0になるまで待つ
+        // Mode 0では0の後も折り返して減り続けるため、0を読み逃しても値が増えたら終了する
+        let mut last_count = count;
         loop {
             outb(ports::COMMAND, 0x00); // latch
-            let low = inb(ports::CHANNEL_0) as u16;
-            let high = inb(ports::CHANNEL_0) as u16;
-            let current = (high << 8) | low;
+            let current = read_current_count();
 
-            if current == 0 {
+            if current == 0 || current > last_count {
                 break;
             }
+            last_count = current;
         }
     }
 }
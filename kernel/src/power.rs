@@ -0,0 +1,508 @@
+//! ACPI電源管理（SCI・電源ボタン・ソフトオフ）
+//!
+//! FADTで報告されたSCI（System Control Interrupt）を受け取り、PM1イベント
+//! レジスタと汎用イベント（GPE）のステータスを処理します。
+//!
+//! # 電源ボタン
+//! 1回目の押下で電源管理タスクを起床させ、ブロックデバイスのフラッシュなどの
+//! 通常のシャットダウン処理の後にS5（ソフトオフ）へ移行します。
+//! シャットダウン処理中にもう一度押すと、後処理を待たずに即座に電源を切ります。
+//!
+//! # 制限
+//! AMLインタプリタを持たないため、GPEの `_Lxx`/`_Exx` メソッドは実行できません。
+//! GPEは `set_gpe_handler` でハンドラを登録したものだけを有効化し、
+//! ハンドラのないGPEが発生した場合は割り込みの嵐を避けるため無効化します。
+//! PMレジスタブロックはI/Oポート空間のもの（FADTのレガシーフィールド）のみ対応します。
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::io::{port_read_u8, port_read_u16, port_write_u8, port_write_u16, without_interrupts};
+use crate::ioapic::{self, Polarity, TriggerMode};
+use crate::irq::{self, IrqReturn};
+use crate::sched::{self, TaskId};
+use crate::{apic, block, clock, info, println, serial, warn};
+
+/// SCIの割り込みベクタ（ISA IRQ 9 + 32）
+pub const SCI_INTERRUPT_VECTOR: u8 = 41;
+
+/// 電源管理タスクのRealtime優先度
+const POWER_TASK_PRIORITY: u8 = 50;
+
+/// ACPIモードへの切り替え完了を待つ最大時間（ミリ秒）
+const ACPI_ENABLE_TIMEOUT_MS: u64 = 300;
+
+/// GPE番号の上限（GPE0/GPE1ブロックの合計で表現できる範囲）
+const MAX_GPES: usize = 256;
+
+/// PM1ステータス/イネーブルレジスタのビット
+mod pm1_event {
+    pub const TMR: u16 = 1 << 0;
+    pub const GBL: u16 = 1 << 5;
+    pub const PWRBTN: u16 = 1 << 8;
+    pub const SLPBTN: u16 = 1 << 9;
+    pub const RTC: u16 = 1 << 10;
+    pub const WAK: u16 = 1 << 15;
+
+    /// 書き込み1でクリアできるステータスビット
+    pub const ALL_STATUS: u16 = TMR | (1 << 4) | GBL | PWRBTN | SLPBTN | RTC | WAK;
+}
+
+/// PM1コントロールレジスタのビット
+mod pm1_control {
+    pub const SCI_EN: u16 = 1 << 0;
+    pub const SLP_TYP_SHIFT: u16 = 10;
+    pub const SLP_TYP_MASK: u16 = 0x7 << SLP_TYP_SHIFT;
+    pub const SLP_EN: u16 = 1 << 13;
+}
+
+/// 電源管理のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// FADTが見つからない、またはPMレジスタがない
+    NotAvailable,
+    /// ファームウェアがACPIモードへ切り替わらなかった
+    AcpiEnableTimeout,
+    /// DSDTに`\_S5`がない
+    SoftOffUnsupported,
+    /// GPE番号がGPEブロックの範囲外
+    InvalidGpe,
+}
+
+impl core::fmt::Display for PowerError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            PowerError::NotAvailable => write!(f, "ACPI power management not available"),
+            PowerError::AcpiEnableTimeout => write!(f, "Firmware did not enter ACPI mode"),
+            PowerError::SoftOffUnsupported => write!(f, "S5 sleep type not found in DSDT"),
+            PowerError::InvalidGpe => write!(f, "GPE number out of range"),
+        }
+    }
+}
+
+/// FADTから取得した電源管理レジスタの情報
+///
+/// ポート番号が0のブロックは存在しません。
+#[derive(Debug, Clone, Copy)]
+pub struct PmInfo {
+    /// SCIの割り込み番号（ISA IRQまたはGSI）
+    pub sci_int: u16,
+    /// SMIコマンドポート
+    pub smi_cmd: u16,
+    /// ACPIモードへ切り替えるためにSMI_CMDへ書き込む値
+    pub acpi_enable: u8,
+    /// PM1aイベントブロック
+    pub pm1a_evt_blk: u16,
+    /// PM1bイベントブロック
+    pub pm1b_evt_blk: u16,
+    /// PM1イベントブロックの長さ（前半がステータス、後半がイネーブル）
+    pub pm1_evt_len: u8,
+    /// PM1aコントロールブロック
+    pub pm1a_cnt_blk: u16,
+    /// PM1bコントロールブロック
+    pub pm1b_cnt_blk: u16,
+    /// GPE0ブロック
+    pub gpe0_blk: u16,
+    /// GPE0ブロックの長さ（前半がステータス、後半がイネーブル）
+    pub gpe0_blk_len: u8,
+    /// GPE1ブロック
+    pub gpe1_blk: u16,
+    /// GPE1ブロックの長さ
+    pub gpe1_blk_len: u8,
+    /// GPE1ブロックの先頭GPE番号
+    pub gpe1_base: u8,
+    /// 電源ボタンが固定機能（PM1のPWRBTN）として実装されているか
+    pub fixed_power_button: bool,
+    /// S5の(SLP_TYPa, SLP_TYPb)
+    pub s5_sleep_type: Option<(u8, u8)>,
+}
+
+impl PmInfo {
+    /// PM1イネーブルレジスタのオフセット
+    fn pm1_enable_offset(&self) -> u16 {
+        self.pm1_evt_len as u16 / 2
+    }
+
+    /// GPEブロックの一覧 (ポート, 長さ, 先頭GPE番号)
+    fn gpe_blocks(&self) -> [(u16, u8, u16); 2] {
+        [
+            (self.gpe0_blk, self.gpe0_blk_len, 0),
+            (self.gpe1_blk, self.gpe1_blk_len, self.gpe1_base as u16),
+        ]
+    }
+
+    /// GPE番号に対応する (ステータスポート, イネーブルポート, ビット)
+    fn gpe_location(&self, gpe: u16) -> Option<(u16, u16, u8)> {
+        self.gpe_blocks().into_iter().find_map(|(port, len, base)| {
+            let count = len as u16 / 2 * 8;
+            if port == 0 || gpe < base || gpe >= base + count {
+                return None;
+            }
+            let index = gpe - base;
+            let status_port = port + index / 8;
+            Some((status_port, status_port + len as u16 / 2, (index % 8) as u8))
+        })
+    }
+}
+
+/// GPEハンドラ（引数はGPE番号）
+pub type GpeHandler = fn(u16);
+
+/// FADTから取得した情報（ACPI初期化時に設定）
+static PM_INFO: Mutex<Option<PmInfo>> = Mutex::new(None);
+
+/// 登録済みのGPEハンドラ
+static GPE_HANDLERS: Mutex<[Option<GpeHandler>; MAX_GPES]> = Mutex::new([None; MAX_GPES]);
+
+/// シャットダウンが要求されたか（電源ボタンの1回目の押下）
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 電源管理タスクのID（0なら未起動）
+static POWER_TASK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// FADTの情報を登録
+///
+/// ACPIテーブルの解析中に呼び出されます。
+pub fn set_pm_info(info: PmInfo) {
+    without_interrupts(|| *PM_INFO.lock() = Some(info));
+}
+
+/// 登録済みの電源管理情報を取得
+fn pm_info() -> Option<PmInfo> {
+    without_interrupts(|| *PM_INFO.lock())
+}
+
+/// PM1ステータスを読み取る（PM1aとPM1bのOR）
+///
+/// # Safety
+/// `info` のポートがFADTで報告された有効なPM1イベントブロックであること
+unsafe fn read_pm1_status(info: &PmInfo) -> u16 {
+    let mut status = unsafe { port_read_u16(info.pm1a_evt_blk) };
+    if info.pm1b_evt_blk != 0 {
+        status |= unsafe { port_read_u16(info.pm1b_evt_blk) };
+    }
+    status
+}
+
+/// PM1レジスタ（ステータスまたはイネーブル）のPM1a/PM1bの両方へ書き込む
+///
+/// # Safety
+/// `info` のポートがFADTで報告された有効なPM1イベントブロックであること
+unsafe fn write_pm1_event(info: &PmInfo, offset: u16, value: u16) {
+    unsafe {
+        port_write_u16(info.pm1a_evt_blk + offset, value);
+        if info.pm1b_evt_blk != 0 {
+            port_write_u16(info.pm1b_evt_blk + offset, value);
+        }
+    }
+}
+
+/// ACPIモードへ切り替え、SCIと電源ボタンイベントを有効化
+///
+/// ヒープとタイマー初期化後、I/O APIC初期化後に呼び出します。
+/// 電源ボタンの押下を待つ電源管理タスクもここで起動します。
+///
+/// # Errors
+/// * `PowerError::NotAvailable` - FADTが見つからない場合
+/// * `PowerError::AcpiEnableTimeout` - ファームウェアがACPIモードへ切り替わらない場合
+pub fn init() -> Result<(), PowerError> {
+    let info = pm_info()
+        .filter(|i| i.pm1a_evt_blk != 0 && i.pm1a_cnt_blk != 0)
+        .ok_or(PowerError::NotAvailable)?;
+
+    enable_acpi_mode(&info)?;
+
+    // SAFETY: ポートはFADTで報告されたPMレジスタブロック
+    unsafe {
+        // 溜まっているイベントを破棄し、必要なものだけを有効化する
+        write_pm1_event(&info, 0, pm1_event::ALL_STATUS);
+        let enable = if info.fixed_power_button {
+            pm1_event::PWRBTN
+        } else {
+            0
+        };
+        write_pm1_event(&info, info.pm1_enable_offset(), enable);
+
+        // ハンドラが登録されるまで全GPEを無効化
+        for (port, len, _) in info.gpe_blocks() {
+            if port == 0 {
+                continue;
+            }
+            let half = len as u16 / 2;
+            for i in 0..half {
+                port_write_u8(port + half + i, 0);
+                port_write_u8(port + i, 0xFF);
+            }
+        }
+    }
+
+    if !info.fixed_power_button {
+        warn!("Power button is a control method device; power button events unavailable");
+    }
+    if info.s5_sleep_type.is_none() {
+        warn!("S5 sleep type unknown; shutdown will halt instead of powering off");
+    }
+
+    // 電源ボタンを待つタスクを起動（押下はSCIハンドラから通知される）
+    let handle = sched::kthread::spawn_realtime("Power", POWER_TASK_PRIORITY, power_task)
+        .map_err(|_| PowerError::NotAvailable)?;
+    POWER_TASK_ID.store(handle.task_id().as_u64(), Ordering::SeqCst);
+
+    irq::request_irq(SCI_INTERRUPT_VECTOR, handle_sci, "acpi-sci")
+        .map_err(|_| PowerError::NotAvailable)?;
+
+    // SCIはOverrideがなければレベルトリガー・Low active（ACPI仕様）
+    let routed = match u8::try_from(info.sci_int) {
+        Ok(irq) if irq < 16 => ioapic::route_isa_irq_with_defaults(
+            irq,
+            SCI_INTERRUPT_VECTOR,
+            Polarity::ActiveLow,
+            TriggerMode::Level,
+        ),
+        _ => ioapic::set_redirection(
+            info.sci_int as u32,
+            ioapic::RedirectionEntry {
+                vector: SCI_INTERRUPT_VECTOR,
+                destination: apic::local_apic_id(),
+                trigger_mode: TriggerMode::Level,
+                polarity: Polarity::ActiveLow,
+                masked: false,
+            },
+        )
+        .map(|()| info.sci_int as u32),
+    };
+    match routed {
+        Ok(gsi) => info!("ACPI SCI enabled (GSI {})", gsi),
+        Err(e) => warn!("ACPI SCI not routed: {}", e),
+    }
+
+    Ok(())
+}
+
+/// ファームウェアにACPIモードへの切り替えを要求し、SCI_ENがセットされるまで待つ
+fn enable_acpi_mode(info: &PmInfo) -> Result<(), PowerError> {
+    // SAFETY: ポートはFADTで報告されたPM1コントロールブロック
+    let sci_enabled = || unsafe { port_read_u16(info.pm1a_cnt_blk) } & pm1_control::SCI_EN != 0;
+
+    if sci_enabled() {
+        return Ok(());
+    }
+    // SMI_CMDが0ならハードウェアは常にACPIモード（切り替え不要）
+    if info.smi_cmd == 0 || info.acpi_enable == 0 {
+        return Ok(());
+    }
+
+    // SAFETY: SMI_CMDへのACPI_ENABLEの書き込みはACPI仕様で定められた手順
+    unsafe { port_write_u8(info.smi_cmd, info.acpi_enable) };
+
+    for _ in 0..ACPI_ENABLE_TIMEOUT_MS {
+        if sci_enabled() {
+            info!("Switched to ACPI mode");
+            return Ok(());
+        }
+        clock::delay_ms(1);
+    }
+    Err(PowerError::AcpiEnableTimeout)
+}
+
+/// GPEハンドラを登録し、そのGPEを有効化
+///
+/// ハンドラは割り込みコンテキストで、ステータスをクリアした後に呼ばれます。
+/// レベルトリガーのGPEでは、ハンドラ内で発生源の要因を解除する必要があります。
+///
+/// # Errors
+/// * `PowerError::NotAvailable` - FADTが見つからない場合
+/// * `PowerError::InvalidGpe` - GPE番号がどのGPEブロックにも含まれない場合
+#[allow(dead_code)]
+pub fn set_gpe_handler(gpe: u16, handler: GpeHandler) -> Result<(), PowerError> {
+    let info = pm_info().ok_or(PowerError::NotAvailable)?;
+    let (_, enable_port, bit) = info.gpe_location(gpe).ok_or(PowerError::InvalidGpe)?;
+
+    without_interrupts(|| {
+        GPE_HANDLERS.lock()[gpe as usize] = Some(handler);
+        // SAFETY: ポートはFADTで報告されたGPEブロック内
+        unsafe {
+            let enable = port_read_u8(enable_port);
+            port_write_u8(enable_port, enable | (1 << bit));
+        }
+    });
+    Ok(())
+}
+
+/// SCI割り込みの処理
+///
+/// PM1イベントとGPEのステータスを確認し、対応する処理を行ってからクリアします。
+/// `irq` から呼び出されます（EOIは呼び出し側で送信）。
+fn handle_sci() -> IrqReturn {
+    let Some(info) = PM_INFO.try_lock().and_then(|info| *info) else {
+        return IrqReturn::Unhandled;
+    };
+
+    // SAFETY: ポートはFADTで報告されたPMレジスタブロック
+    let status = unsafe { read_pm1_status(&info) };
+    let enabled = unsafe { port_read_u16(info.pm1a_evt_blk + info.pm1_enable_offset()) };
+    let pending = status & (enabled | pm1_event::WAK);
+    if pending != 0 {
+        // ステータスは書き込み1でクリア
+        // SAFETY: 同上
+        unsafe { write_pm1_event(&info, 0, pending) };
+    }
+
+    if pending & pm1_event::PWRBTN != 0 {
+        power_button_pressed();
+    }
+    if pending & pm1_event::SLPBTN != 0 {
+        println!("[ACPI] Sleep button pressed (sleep states not supported)");
+    }
+
+    handle_gpes(&info);
+    IrqReturn::Handled
+}
+
+/// 有効なGPEのうちステータスが立っているものを処理
+fn handle_gpes(info: &PmInfo) {
+    for (port, len, base) in info.gpe_blocks() {
+        if port == 0 {
+            continue;
+        }
+        let half = len as u16 / 2;
+        for i in 0..half {
+            // SAFETY: ポートはFADTで報告されたGPEブロック内
+            let (status, enable) =
+                unsafe { (port_read_u8(port + i), port_read_u8(port + half + i)) };
+            let pending = status & enable;
+            if pending == 0 {
+                continue;
+            }
+            // SAFETY: 同上（ステータスは書き込み1でクリア）
+            unsafe { port_write_u8(port + i, pending) };
+
+            for bit in 0..8u16 {
+                if pending & (1 << bit) == 0 {
+                    continue;
+                }
+                let gpe = base + i * 8 + bit;
+                let handler = GPE_HANDLERS
+                    .try_lock()
+                    .and_then(|handlers| handlers.get(gpe as usize).copied().flatten());
+                match handler {
+                    Some(handler) => handler(gpe),
+                    None => {
+                        // AMLメソッドを実行できないため、再発を防ぐために無効化する
+                        println!("[ACPI] Unhandled GPE {:#04X}, disabling", gpe);
+                        // SAFETY: 同上
+                        unsafe {
+                            let enable = port_read_u8(port + half + i);
+                            port_write_u8(port + half + i, enable & !(1 << bit));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 電源ボタンの押下を処理
+///
+/// 1回目は電源管理タスクに通常のシャットダウンを依頼し、2回目は即座に電源を切ります。
+fn power_button_pressed() {
+    if SHUTDOWN_REQUESTED.swap(true, Ordering::SeqCst) {
+        println!("[ACPI] Power button pressed again: forcing power off");
+        force_off();
+    }
+
+    println!("[ACPI] Power button pressed: shutting down (press again to force off)");
+    match POWER_TASK_ID.load(Ordering::SeqCst) {
+        0 => force_off(),
+        id => sched::unblock_task(TaskId::from_u64(id)),
+    }
+}
+
+/// 電源管理タスク
+///
+/// 電源ボタンが押されるまでブロックし、押されたら通常のシャットダウンを行います。
+fn power_task() {
+    // 確認とブロックの間に押下された場合は、WAKEUP_PENDINGによりブロックせずに戻る
+    while !SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+        sched::block_current_task();
+    }
+    shutdown();
+}
+
+/// シャットダウンを要求されたかどうか
+#[allow(dead_code)]
+pub fn is_shutting_down() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// 通常のシャットダウン
+///
+/// ブロックデバイスの書き込みキャッシュとシリアル出力をフラッシュしてから
+/// S5へ移行します。ブロックできるタスクコンテキストから呼び出してください。
+/// 電源を切れない場合はCPUを停止します。
+pub fn shutdown() -> ! {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    info!("System is shutting down...");
+
+    for dev in block::devices() {
+        match block::flush(dev.index) {
+            Ok(()) => info!("  Flushed {}", dev.name),
+            Err(e) => warn!("  Failed to flush {}: {}", dev.name, e),
+        }
+    }
+
+    info!("Powering off");
+    force_off()
+}
+
+/// 後処理を行わずに即座に電源を切る
+///
+/// 割り込みコンテキストからも呼び出せます。
+pub fn force_off() -> ! {
+    serial::flush();
+
+    if let Err(e) = enter_soft_off() {
+        println!("[ACPI] Power off failed: {}", e);
+    }
+    println!("[ACPI] It is now safe to turn off the computer");
+
+    loop {
+        // SAFETY: 割り込みを禁止して停止する。以降の処理は不要
+        unsafe { core::arch::asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
+
+/// PM1コントロールレジスタにS5のスリープタイプを書き込み、ソフトオフへ移行
+///
+/// 成功すれば戻りません。戻った場合は電源断が完了しなかったことを意味します。
+fn enter_soft_off() -> Result<(), PowerError> {
+    let info = PM_INFO
+        .try_lock()
+        .and_then(|info| *info)
+        .ok_or(PowerError::NotAvailable)?;
+    let (slp_typ_a, slp_typ_b) = info.s5_sleep_type.ok_or(PowerError::SoftOffUnsupported)?;
+
+    let sleep_value = |current: u16, slp_typ: u8| {
+        (current & !pm1_control::SLP_TYP_MASK)
+            | ((slp_typ as u16) << pm1_control::SLP_TYP_SHIFT)
+            | pm1_control::SLP_EN
+    };
+
+    without_interrupts(|| {
+        // SAFETY: ポートはFADTで報告されたPM1コントロールブロック。
+        // SLP_ENの書き込みでシステムは電源断へ移行する
+        unsafe {
+            let current = port_read_u16(info.pm1a_cnt_blk);
+            port_write_u16(info.pm1a_cnt_blk, sleep_value(current, slp_typ_a));
+            if info.pm1b_cnt_blk != 0 {
+                let current = port_read_u16(info.pm1b_cnt_blk);
+                port_write_u16(info.pm1b_cnt_blk, sleep_value(current, slp_typ_b));
+            }
+        }
+        // 電源断が完了するまで少し待つ
+        clock::delay_ms(100);
+    });
+
+    Ok(())
+}
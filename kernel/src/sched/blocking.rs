@@ -6,17 +6,17 @@ use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::collections::BTreeSet;
 use lazy_static::lazy_static;
-use spin::Mutex;
 
 use crate::io::without_interrupts;
+use crate::sync::IrqSpinlock;
 
-use super::scheduler::{CURRENT_TASK, current_task_id, schedule};
-use super::task::{Task, TaskId, TaskState};
+use super::scheduler::{current_task, current_task_id, schedule};
+use super::task::{SchedulingClass, Task, TaskId, TaskState};
 
 lazy_static! {
     /// ブロック中のタスク (TaskId -> Task)
     /// ブロッキング同期プリミティブで待機中のタスクを管理
-    pub(super) static ref BLOCKED_TASKS: Mutex<BTreeMap<u64, Box<Task>>> = Mutex::new(BTreeMap::new());
+    pub(super) static ref BLOCKED_TASKS: IrqSpinlock<BTreeMap<u64, Box<Task>>> = IrqSpinlock::new(BTreeMap::new());
 
     /// 起床保留中のタスクID集合
     ///
@@ -24,7 +24,7 @@ lazy_static! {
     /// unblock_task()が呼ばれた時にタスクがまだBLOCKED_TASKSにいない場合、
     /// このセットにIDを追加し、block_current_task()でチェックする。
     /// schedule()でもBLOCKED_TASKSへの追加前にチェックされる。
-    pub(super) static ref WAKEUP_PENDING: Mutex<BTreeSet<u64>> = Mutex::new(BTreeSet::new());
+    pub(super) static ref WAKEUP_PENDING: IrqSpinlock<BTreeSet<u64>> = IrqSpinlock::new(BTreeSet::new());
 }
 
 /// 割り込みコンテキスト内かどうかを判定
@@ -59,17 +59,17 @@ pub fn is_interrupt_context() -> bool {
 /// # アトミック性保証
 /// WAKEUP_PENDINGのチェックとBlocked状態設定を同一のクリティカルセクション内で
 /// 実行し、その間に起床シグナルが失われることを防ぎます。
-/// ロック順序: WAKEUP_PENDING → CURRENT_TASK（デッドロック防止）
+/// ロック順序: WAKEUP_PENDING → 現在のタスク（デッドロック防止）
 ///
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
 pub fn block_current_task() {
     // Lost Wakeup防止: WAKEUP_PENDINGチェックとBlocked設定をアトミックに実行
     let should_block = without_interrupts(|| {
-        // ロック順序: WAKEUP_PENDING → CURRENT_TASK
+        // ロック順序: WAKEUP_PENDING → 現在のタスク
         // この順序を維持することでデッドロックを防ぐ
         let mut wakeup_pending = WAKEUP_PENDING.lock();
-        let mut current = CURRENT_TASK.lock();
+        let mut current = current_task().lock();
 
         if let Some(task) = current.as_mut() {
             let id = task.id().as_u64();
@@ -83,7 +83,7 @@ pub fn block_current_task() {
 
             // WAKEUP_PENDINGにないので、通常通りBlocked状態に設定
             // （まだ両方のロックを保持している）
-            task.set_state(TaskState::Blocked);
+            task.transition(TaskState::Blocked);
             true
         } else {
             false
@@ -98,7 +98,8 @@ pub fn block_current_task() {
 
 /// 指定タスクをアンブロック（Ready状態に戻す）
 ///
-/// BLOCKED_TASKSから取り出して、スケジューリングクラスに応じたキューに追加します。
+/// BLOCKED_TASKSから取り出して、タスクが属するCPUのスケジューリングクラスに応じたキューに追加します。
+/// 他のCPUのキューに追加した場合、必要ならIPIでそのCPUに再スケジュールさせます。
 /// タスクがまだBLOCKED_TASKSに登録されていない場合（Lost Wakeup防止）、
 /// WAKEUP_PENDINGセットに追加し、block_current_task()で検出できるようにします。
 ///
@@ -108,17 +109,23 @@ pub fn block_current_task() {
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
 pub fn unblock_task(task_id: TaskId) {
+    // 割り込みハンドラからの起床では、起こしたCPUへ移さない（wake-affineの判断に使う）
+    let from_task = !is_interrupt_context();
     without_interrupts(|| {
         let mut blocked_tasks = BLOCKED_TASKS.lock();
 
         if let Some(mut task) = blocked_tasks.remove(&task_id.as_u64()) {
             // Ready状態に戻す
-            task.set_state(TaskState::Ready);
-            let sched_class = task.sched_class();
+            task.transition(TaskState::Ready);
+            // Realtimeタスクと対話的なタスクは、現在のタスクの粒度を待たずに即座にプリエンプトさせる
+            let preempt = task.sched_class() == SchedulingClass::Realtime || task.is_interactive();
             drop(blocked_tasks); // ロックを早期に解放
 
+            // 実行するCPUを決める
+            super::scheduler::select_wake_cpu(&mut task, from_task);
+
             // スケジューリングクラスに応じて適切なキューに追加
-            super::scheduler::enqueue_to_appropriate_queue(task, sched_class);
+            super::scheduler::enqueue_to_appropriate_queue(task, preempt);
         } else {
             // タスクがBLOCKED_TASKSにない場合、まだblock_current_task()が
             // 完了していない可能性がある（Lost Wakeup問題）。
@@ -130,50 +137,98 @@ pub fn unblock_task(task_id: TaskId) {
     });
 }
 
+/// 残り時間がこれ未満になったら、ブロックせずにyieldしながら期限を待つ（ナノ秒）
+const SLEEP_SPIN_THRESHOLD_NS: u64 = 1_000_000;
+
 /// 指定したミリ秒数だけ現在のタスクをスリープさせる
 ///
-/// Linux の `schedule_timeout()` に倣った実装です。
-/// タイマーを登録し、タスクをブロック状態にしてスケジューラに譲ります。
-/// タイマー期限切れ時にコールバックでタスクを起床させます。
+/// `sleep_ns` と同じです。
 ///
 /// # Arguments
 /// * `ms` - スリープ時間（ミリ秒）
 ///
+/// # Panics
+/// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+pub fn sleep_ms(ms: u64) {
+    sleep_ns(ms.saturating_mul(1_000_000));
+}
+
+/// 指定したマイクロ秒数だけ現在のタスクをスリープさせる
+///
+/// `sleep_ns` と同じです。
+///
+/// # Arguments
+/// * `us` - スリープ時間（マイクロ秒）
+///
+/// # Panics
+/// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
+#[allow(dead_code)]
+pub fn sleep_us(us: u64) {
+    sleep_ns(us.saturating_mul(1_000));
+}
+
+/// 指定したナノ秒数だけ現在のタスクをスリープさせる
+///
+/// Linux の `schedule_timeout()` に倣い、タイマーを登録してタスクをブロックし、
+/// 期限切れ時にコールバックでタスクを起床させます。
+/// 期限は `clock::monotonic_ns` で判定するため、期限より早く戻ることはありません。
+/// タイマーはtick単位でしか起床できないため、残りが `SLEEP_SPIN_THRESHOLD_NS` 未満になったら
+/// yieldしながら期限を待ち、tickより短いスリープも正確に扱います。
+///
+/// # Arguments
+/// * `ns` - スリープ時間（ナノ秒）
+///
 /// # Note
-/// - タイマー周波数が100Hz（10ms周期）のため、10ms未満の精度は保証されない
-/// - 0msの場合は yield_now() と同等の動作（他タスクに実行機会を与える）
+/// - 0の場合は yield_now() と同等の動作（他タスクに実行機会を与える）
+/// - ブロックした場合の遅れは最大で1tick
 /// - 割り込みコンテキストからは呼び出し不可
 ///
 /// # Panics
 /// 割り込みコンテキストから呼び出された場合（デバッグビルドのみ）
-pub fn sleep_ms(ms: u64) {
+pub fn sleep_ns(ns: u64) {
     // 安全性チェック: 割り込みコンテキストではブロック不可
     debug_assert!(
         !is_interrupt_context(),
-        "sleep_ms() cannot be called from interrupt context"
+        "sleep_ns() cannot be called from interrupt context"
     );
 
-    // 0ms の場合は yield して即座にリターン
-    if ms == 0 {
+    // 0 の場合は yield して即座にリターン
+    if ns == 0 {
         super::scheduler::yield_now();
         return;
     }
 
     // 現在のタスクIDを取得（TaskId は Copy なのでクロージャにキャプチャ可能）
     let task_id = current_task_id();
+    let deadline = crate::clock::monotonic_ns().saturating_add(ns);
+    let tick_ns = crate::clock::tick_ns().max(1);
 
-    // ミリ秒をtick数に変換（最小1tickを保証）
-    let ticks = crate::timer::ms_to_ticks(ms).max(1);
-
-    // タイマーを登録: 期限切れ時に unblock_task を呼び出す
-    crate::timer::register_timer(
-        ticks,
-        Box::new(move || {
-            unblock_task(task_id);
-        }),
-    );
+    loop {
+        let now = crate::clock::monotonic_ns();
+        if now >= deadline {
+            return;
+        }
+        let remaining = deadline - now;
+        if remaining < SLEEP_SPIN_THRESHOLD_NS {
+            super::scheduler::yield_now();
+            continue;
+        }
 
-    // タスクをブロック状態にしてスケジュール
-    // タイマーが起床するまで他のタスクが実行される
-    block_current_task();
+        // tick境界で起床するため、切り捨てたtick数なら期限を大きく過ぎない
+        // （最後の1tick未満はもう一度ブロックするか、yieldで待つ）
+        let ticks = (remaining / tick_ns).max(1);
+
+        // タイマーを登録: 期限切れ時に unblock_task を呼び出す
+        crate::timer::register_timer(
+            ticks,
+            crate::timer::TimerClass::Wakeup,
+            Box::new(move || {
+                unblock_task(task_id);
+            }),
+        );
+
+        // タスクをブロック状態にしてスケジュール
+        // タイマーが起床するまで他のタスクが実行される
+        block_current_task();
+    }
 }
@@ -1,40 +1,286 @@
 //! CPUコンテキストとコンテキストスイッチ
 //!
 //! このモジュールはCPUコンテキストの保存・復元とコンテキストスイッチを担当します。
+//!
+//! 汎用レジスタはタスクのスタックに、FPU/SSE/AVXの状態（拡張状態）はタスクごとに確保した
+//! `ExtendedState` に保存します。拡張状態はCPUがXSAVEに対応していればxsave/xrstorで
+//! （YMM・ZMMレジスタを含む）、対応していなければfxsave/fxrstorで保存します（`cpu::init_extended_state`）。
+//!
+//! 拡張状態の切り替えは遅延させます。切り替え先のタスクの状態がまだこのCPUのレジスタに
+//! 残っていなければCR0.TSを立てておき、タスクが最初にFPU/SIMD命令を使ったときの
+//! Device Not Available例外（#NM）で復元します（`handle_device_not_available`）。
+//! 切り替え元の状態は、そのタスクが今回の実行で拡張状態を使った（CR0.TSが下りている）
+//! 場合だけ保存します。FPU/SIMDを使わないタスクの切り替えでは、保存も復元も行いません。
+//!
+//! レジスタにどのタスクの状態が入っているかはCPUごとに記録し（`FpuOwner`）、
+//! タスクの側にも最後に状態を読み込んだCPUを記録します。他のCPUで状態を読み込み直した
+//! タスクは、元のCPUのレジスタの内容が古くなっているため、戻ってきたときに再び復元します。
+
+use alloc::alloc::{Layout, alloc_zeroed, dealloc};
+use core::arch::asm;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
 
 use super::task::TaskError;
+use crate::cpu;
+use crate::percpu;
+use crate::smp::MAX_CPUS;
+
+/// xsave/fxsaveの保存領域のアラインメント（xsaveは64バイト、fxsaveは16バイトを要求する）
+const EXTENDED_STATE_ALIGN: usize = 64;
+
+/// x87 FPU制御ワードの初期値（すべての例外をマスク、拡張倍精度、最近接丸め）
+const DEFAULT_FCW: u16 = 0x037F;
+
+/// MXCSRの初期値（すべてのSSE例外をマスク、最近接丸め）
+const DEFAULT_MXCSR: u32 = 0x1F80;
+
+/// 保存領域（fxsaveの形式、xsaveの先頭512バイトも同じ）のMXCSRのオフセット
+const MXCSR_OFFSET: usize = 24;
+
+/// CR0.TS（Task Switched）: 立っているとFPU/SIMD命令が#NMを発生させる
+const CR0_TS: u64 = 1 << 3;
+
+/// レジスタに拡張状態が読み込まれているタスクがないことを表すID
+const NO_OWNER: u64 = u64::MAX;
+
+/// 拡張状態をまだどのCPUにも読み込んでいないことを表すCPU番号
+const NO_CPU: usize = usize::MAX;
+
+/// タスクの拡張状態（FPU/SSE/AVX）の保存領域
+///
+/// 大きさはCPUが保存する状態によって変わるため（`cpu::extended_state_size`）、ヒープから確保します。
+pub struct ExtendedState {
+    area: NonNull<u8>,
+    layout: Layout,
+    /// 最後にこの状態をレジスタに復元したCPU（NO_CPUならまだ復元していない）
+    last_cpu: AtomicUsize,
+    /// FPU/SIMD命令を使ったことがあるか（#NMで最初に復元したときに立てる）
+    used: AtomicBool,
+}
+
+// SAFETY: 保存領域はこのExtendedStateだけが所有し、switch_contextは所有するタスクの
+// 切り替え時に、#NMのハンドラは所有するタスクの実行中にのみ読み書きする
+unsafe impl Send for ExtendedState {}
+
+impl ExtendedState {
+    /// 初期状態（例外をすべてマスク）の保存領域を確保
+    ///
+    /// # Errors
+    /// * `TaskError::ContextInitFailed` - 保存領域を確保できない場合
+    pub fn new() -> Result<Self, TaskError> {
+        let layout = Layout::from_size_align(cpu::extended_state_size(), EXTENDED_STATE_ALIGN)
+            .map_err(|_| TaskError::ContextInitFailed)?;
+        // SAFETY: layoutの大きさは0ではない（fxsaveの512バイト以上）
+        let area =
+            NonNull::new(unsafe { alloc_zeroed(layout) }).ok_or(TaskError::ContextInitFailed)?;
+        // xsaveの形式ではヘッダ（XSTATE_BV）が0なので、MXCSR以外の状態は初期値で復元される
+        // SAFETY: 領域は確保直後で、先頭512バイトはfxsaveの形式
+        unsafe {
+            area.as_ptr().cast::<u16>().write(DEFAULT_FCW);
+            area.as_ptr()
+                .add(MXCSR_OFFSET)
+                .cast::<u32>()
+                .write(DEFAULT_MXCSR);
+        }
+        Ok(Self {
+            area,
+            layout,
+            last_cpu: AtomicUsize::new(NO_CPU),
+            used: AtomicBool::new(false),
+        })
+    }
+
+    /// 保存領域の先頭アドレス
+    fn as_ptr(&self) -> *mut u8 {
+        self.area.as_ptr()
+    }
+
+    /// FPU/SIMD命令を使ったことがあるか
+    pub fn is_used(&self) -> bool {
+        self.used.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ExtendedState {
+    fn drop(&mut self) {
+        // SAFETY: areaはnewでlayoutを指定して確保した領域
+        unsafe { dealloc(self.area.as_ptr(), self.layout) };
+    }
+}
+
+/// 初回のコンテキストスイッチで「保存」に使う拡張状態の領域
+///
+/// 保存されるだけで読み出されることはないため、複数のCPUが同時に書き込んでも問題ない。
+#[repr(C, align(64))]
+struct DummyExtendedState([u8; cpu::MAX_EXTENDED_STATE_SIZE]);
+
+/// 初回のコンテキストスイッチ用の拡張状態の領域の実体
+static mut DUMMY_EXTENDED_STATE: DummyExtendedState =
+    DummyExtendedState([0; cpu::MAX_EXTENDED_STATE_SIZE]);
+
+/// CPUごとの拡張状態の所有状況
+struct FpuOwner {
+    /// レジスタに拡張状態が読み込まれているタスクのID（NO_OWNERならなし）
+    owner: AtomicU64,
+    /// 実行中のタスクのID
+    current_id: AtomicU64,
+    /// 実行中のタスクの拡張状態（#NMで復元する。実行中のタスクはBox内にあり移動しない）
+    current: AtomicPtr<ExtendedState>,
+    /// #NMで拡張状態を復元した回数
+    restores: AtomicU64,
+}
+
+impl FpuOwner {
+    const fn new() -> Self {
+        Self {
+            owner: AtomicU64::new(NO_OWNER),
+            current_id: AtomicU64::new(NO_OWNER),
+            current: AtomicPtr::new(ptr::null_mut()),
+            restores: AtomicU64::new(0),
+        }
+    }
+}
+
+/// CPUごとの拡張状態の所有状況（インデックス = CPU番号）
+static FPU_OWNERS: [FpuOwner; MAX_CPUS] = [const { FpuOwner::new() }; MAX_CPUS];
+
+/// 切り替え先のタスクを、このCPUで実行中のタスクとして記録
+///
+/// 割り込み無効状態で、switch_contextの直前に呼び出します。
+///
+/// # Returns
+/// このCPUのレジスタに切り替え先の拡張状態が残っているか。trueならswitch_contextは
+/// CR0.TSを下ろし、falseなら立てて最初のFPU/SIMD命令（#NM）で復元します。
+pub(super) fn prepare_switch(cpu: usize, task_id: u64, state: &ExtendedState) -> bool {
+    let fpu = &FPU_OWNERS[cpu];
+    fpu.current_id.store(task_id, Ordering::Relaxed);
+    fpu.current
+        .store(ptr::from_ref(state).cast_mut(), Ordering::Relaxed);
+    fpu.owner.load(Ordering::Relaxed) == task_id && state.last_cpu.load(Ordering::Relaxed) == cpu
+}
+
+/// 起動時の実行コンテキストを表すタスクを、このCPUの拡張状態の所有者にする
+///
+/// 現在のレジスタの内容をそのままタスクの拡張状態とし、最初の切り替えで保存します。
+/// 割り込み無効状態で呼び出すこと。
+pub(super) fn adopt_current(cpu: usize, task_id: u64, state: &ExtendedState) {
+    // SAFETY: CR0.TSを下ろすだけで、以降のFPU/SIMD命令が#NMを発生させなくなる
+    unsafe { asm!("clts", options(nomem, nostack, preserves_flags)) };
+    prepare_switch(cpu, task_id, state);
+    state.last_cpu.store(cpu, Ordering::Relaxed);
+    FPU_OWNERS[cpu].owner.store(task_id, Ordering::Relaxed);
+}
+
+/// 拡張状態をレジスタに復元
+///
+/// # Safety
+/// `area` は `ExtendedState` の保存領域を指し、CR0.TSが下りていること
+unsafe fn restore_extended_state(area: *const u8) {
+    // SAFETY: 呼び出し元がareaの有効性を保証する。カーネルはSIMDを使わない設定で
+    // ビルドされるため、復元したレジスタをコンパイラが使っていることはない
+    unsafe {
+        if cpu::XSAVE_ENABLED.load(Ordering::Relaxed) {
+            asm!(
+                "xrstor64 [{}]",
+                in(reg) area,
+                in("eax") u32::MAX,
+                in("edx") u32::MAX,
+                options(nostack, readonly, preserves_flags)
+            );
+        } else {
+            asm!("fxrstor64 [{}]", in(reg) area, options(nostack, readonly, preserves_flags));
+        }
+    }
+}
+
+/// Device Not Available（#NM）を処理
+///
+/// CR0.TSが立った状態でタスクがFPU/SIMD命令を使うと発生します。TSを下ろし、
+/// 実行中のタスクの拡張状態を復元してこのCPUの所有者にします。それまでの所有者の状態は、
+/// そのタスクが使った回の切り替えで保存済みのため、ここでは保存しません。
+///
+/// # Returns
+/// 復元できたか（falseなら実行中のタスクが記録されておらず、回復できない）
+pub fn handle_device_not_available() -> bool {
+    let cpu = percpu::current_index();
+    let fpu = &FPU_OWNERS[cpu];
+    // SAFETY: currentは実行中のタスクのExtendedStateを指し、タスクは実行中に解放されない
+    let Some(state) = (unsafe { fpu.current.load(Ordering::Relaxed).as_ref() }) else {
+        return false;
+    };
+    // SAFETY: TSを下ろしてから、実行中のタスクの保存領域を復元する
+    unsafe {
+        asm!("clts", options(nomem, nostack, preserves_flags));
+        restore_extended_state(state.as_ptr());
+    }
+    state.last_cpu.store(cpu, Ordering::Relaxed);
+    state.used.store(true, Ordering::Relaxed);
+    fpu.owner
+        .store(fpu.current_id.load(Ordering::Relaxed), Ordering::Relaxed);
+    fpu.restores.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// 実行中のタスクがFPU/SIMD命令を使ったことがあるか
+pub fn current_uses_fpu() -> bool {
+    crate::io::without_interrupts(|| {
+        let current = FPU_OWNERS[percpu::current_index()]
+            .current
+            .load(Ordering::Relaxed);
+        // SAFETY: currentは実行中のタスクのExtendedStateを指し、タスクは実行中に解放されない
+        unsafe { current.as_ref() }.is_some_and(ExtendedState::is_used)
+    })
+}
+
+/// 全CPUで#NMにより拡張状態を復元した回数の合計
+pub fn fpu_restore_count() -> u64 {
+    FPU_OWNERS
+        .iter()
+        .map(|fpu| fpu.restores.load(Ordering::Relaxed))
+        .sum()
+}
 
 /// CPUコンテキスト（レジスタ状態）
 ///
-/// Linux方式: すべてのレジスタとFPU/SSE状態をスタックに保存
-/// Contextにはスタックポインタのみを保持
+/// Linux方式: 汎用レジスタはすべてスタックに保存し、Contextにはスタックポインタと
+/// 拡張状態の保存領域のアドレスを保持（switch_contextが [rdi], [rdi + 8] で参照する）
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Context {
-    // スタックポインタのみ
-    // レジスタはすべてスタックに保存される
+    /// 保存したスタックポインタ（レジスタはすべてスタックに保存される）
     pub rsp: u64,
+    /// 拡張状態の保存領域（ExtendedStateまたはDUMMY_EXTENDED_STATE）
+    pub extended_state: *mut u8,
 }
 
+// SAFETY: extended_stateは所有するタスクのExtendedStateを指し、
+// 切り替え時にのみswitch_contextが読み書きする
+unsafe impl Send for Context {}
+
 impl Context {
     /// 新しいコンテキストを作成（Linux方式）
     ///
     /// スタックに以下の順序でレジスタを配置（switch_context()のpush順序に合わせる）:
     /// 1. 戻りアドレス（entry_point） - 最上位
     /// 2. rbp, rbx, r12, r13, r14, r15（callee-savedレジスタ）
-    /// 3. rflags
-    /// 4. fxsave領域（512バイト、16バイトアライメント） - 最下位、rspがここを指す
+    /// 3. rflags - 最下位、rspがここを指す
+    ///
+    /// 拡張状態は `extended_state` の初期状態から復元されます。
     ///
     /// # Arguments
     /// * `entry_point` - タスクのエントリポイント
     /// * `stack_top` - スタックの最上位アドレス
+    /// * `extended_state` - タスクの拡張状態の保存領域（タスクと同じ期間だけ有効であること）
     ///
     /// # Errors
     /// * `TaskError::InvalidStackAddress` - スタックアドレスが無効（null、アラインメント不正、範囲不正）
     /// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗
-    pub fn new(entry_point: u64, stack_top: u64) -> Result<Self, TaskError> {
-        const FXSAVE_SIZE: u64 = 512;
-        const FXSAVE_ALIGN: u64 = 16;
+    pub fn new(
+        entry_point: u64,
+        stack_top: u64,
+        extended_state: &ExtendedState,
+    ) -> Result<Self, TaskError> {
         const MIN_REQUIRED_STACK: u64 = 1024; // 最小スタックサイズ
 
         // バリデーション: スタックトップがnullでないか
@@ -106,37 +352,30 @@ impl Context {
             *(rsp as *mut u64) = 0x202; // IF (Interrupt Flag) を有効化
         }
 
-        // 4. fxsave領域を確保（512バイト）
-        // switch_contextと同じアラインメント処理を適用
-        let rsp_before_fxsave = rsp; // アラインメント前のRSPを保存
-        rsp -= FXSAVE_SIZE;
-        rsp = (rsp / FXSAVE_ALIGN) * FXSAVE_ALIGN; // 16バイトアラインに切り下げ
-
-        // バリデーション: 最終的なrspが有効かつ16バイトアラインされているか
-        if rsp == 0 || rsp % FXSAVE_ALIGN != 0 {
-            return Err(TaskError::InvalidStackAddress);
-        }
-
-        // fxsave領域をゼロクリア（初期状態）
-        unsafe {
-            core::ptr::write_bytes(rsp as *mut u8, 0, FXSAVE_SIZE as usize);
-            // アラインメント前のRSPを保存（switch_contextと同じ位置）
-            *((rsp + 504) as *mut u64) = rsp_before_fxsave;
-        }
-
-        Ok(Self { rsp })
+        Ok(Self {
+            rsp,
+            extended_state: extended_state.as_ptr(),
+        })
     }
 
     /// 空のコンテキストを作成
-    #[allow(dead_code)]
+    ///
+    /// 初回のコンテキストスイッチで切り替え元として使うため、拡張状態は
+    /// DUMMY_EXTENDED_STATEに保存されます。
     pub const fn empty() -> Self {
-        Self { rsp: 0 }
+        Self {
+            rsp: 0,
+            extended_state: &raw mut DUMMY_EXTENDED_STATE as *mut u8,
+        }
     }
 }
 
 /// コンテキストスイッチを実行（Linux方式）
 ///
-/// すべてのレジスタとFPU/SSE状態をスタックに保存/復元します。
+/// 汎用レジスタをスタックに保存/復元します。拡張状態（FPU/SSE/AVX）は、切り替え元が
+/// 今回の実行で使った（CR0.TSが下りている）場合だけContextが指す保存領域に保存し、
+/// 切り替え先の状態は復元せずにCR0.TSで最初の使用を待ちます（`handle_device_not_available`）。
+/// 拡張状態は `cpu::XSAVE_ENABLED` に従ってxsave（XCR0のすべての状態）またはfxsaveで保存します。
 ///
 /// # Safety
 /// この関数は低レベルのアセンブリ操作を行うため、正しいコンテキスト構造体へのポインタを渡す必要があります。
@@ -144,12 +383,22 @@ impl Context {
 /// # Arguments
 /// * `old_context` - 現在のコンテキストを保存する先（rspのみ）
 /// * `new_context` - 切り替え先のコンテキスト（rspのみ）
+/// * `old_on_cpu` - 切り替え元タスクの実行中フラグ。切り替え先のスタックに移った後にfalseにする
+/// * `new_fpu_loaded` - 切り替え先の拡張状態がこのCPUのレジスタに残っているか（`prepare_switch`）
+///
+/// 実行中フラグを解除するまで、他のCPUは切り替え元のタスクを実行しません
+/// （コンテキストの保存が終わる前に、同じスタックで再開されることを防ぐ）。
 ///
 /// # Note
 /// 保存されるRFLAGSは割り込み有効フラグ(IF)が強制的にセットされます。
 /// これにより、タスク復帰時に必ず割り込み有効状態になることが保証されます。
 #[unsafe(naked)]
-pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context: *const Context) {
+pub unsafe extern "C" fn switch_context(
+    old_context: *mut Context,
+    new_context: *const Context,
+    old_on_cpu: *const AtomicBool,
+    new_fpu_loaded: bool,
+) {
     core::arch::naked_asm!(
         // ========== 現在のコンテキストを保存 ==========
         // callee-savedレジスタをスタックに保存
@@ -163,24 +412,45 @@ pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context:
         // これにより、タスク復帰時に必ず割り込みが有効になる
         "pushfq",
         "or qword ptr [rsp], 0x200", // IF (bit 9) を強制的に1にする
-        // fxsave用の領域を確保し、16バイトアラインを保証
-        // call命令で8バイトプッシュされているため、アラインメント調整が必要
-        "mov r11, rsp", // アラインメント前のRSPを保存
-        "sub rsp, 512",
-        "and rsp, -16", // 16バイトアラインに切り下げ
-        // FPU/SSE状態を保存
-        "fxsave [rsp]",
-        // アラインメント前のRSPをスタックに保存（復元時に必要）
-        "mov [rsp + 504], r11", // fxsave領域の末尾近くに保存
         // 現在のrspをold_contextに保存
         "mov [rdi], rsp",
+        // 拡張状態を保存（xsaveはedx:eaxでXCR0のすべての状態を指定する）
+        // CR0.TSが立っていれば今回は拡張状態を使っておらず、保存済みの内容のままなので省略
+        "mov r10, rdx", // old_on_cpu（edxはxsaveの引数に使う）
+        "mov rax, cr0",
+        "test eax, {cr0_ts}",
+        "jnz 3f",
+        "mov r11, [rdi + 8]",
+        "cmp byte ptr [rip + {xsave_enabled}], 0",
+        "je 2f",
+        "mov eax, -1",
+        "mov edx, -1",
+        "xsave64 [r11]",
+        "jmp 3f",
+        "2:",
+        "fxsave64 [r11]",
+        "3:",
         // ========== 新しいコンテキストを復元 ==========
         // new_context->rspを読み込み
         "mov rsp, [rsi]",
-        // FPU/SSE状態を復元
-        "fxrstor [rsp]",
-        // アラインメント前のRSPを復元
-        "mov rsp, [rsp + 504]",
+        // 切り替え元のスタックと拡張状態はもう使わないため、他のCPUでの実行を許可
+        // （x86のストアはリリース順序）
+        "mov byte ptr [r10], 0",
+        // 切り替え先の拡張状態がレジスタに残っていればCR0.TSを下ろし、
+        // 残っていなければ立てて最初の使用（#NM）で復元する（CR0の書き込みは変わる場合のみ）
+        "mov rax, cr0",
+        "test cl, cl", // new_fpu_loaded
+        "jz 4f",
+        "test eax, {cr0_ts}",
+        "jz 5f",
+        "clts",
+        "jmp 5f",
+        "4:",
+        "test eax, {cr0_ts}",
+        "jnz 5f",
+        "or rax, {cr0_ts}",
+        "mov cr0, rax",
+        "5:",
         // RFLAGSを復元（IFは保存時に強制セット済みなので、割り込み有効で復帰）
         "popfq",
         // callee-savedレジスタを復元（保存と逆順）
@@ -192,5 +462,7 @@ pub unsafe extern "C" fn switch_context(old_context: *mut Context, new_context:
         "pop rbp",
         // リターン（スタックトップの戻りアドレスに戻る）
         "ret",
+        xsave_enabled = sym cpu::XSAVE_ENABLED,
+        cr0_ts = const CR0_TS,
     )
 }
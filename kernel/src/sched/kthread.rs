@@ -0,0 +1,288 @@
+//! カーネルスレッドAPI
+//!
+//! クロージャを実行するタスクを作成し、終了時の回収とjoinを提供します。
+//!
+//! ```ignore
+//! let handle = kthread::spawn("Checksum", || compute_checksum())?;
+//! let sum = handle.join();
+//! ```
+//!
+//! # 設計
+//! `Task` のエントリポイントは引数を取らない `extern "C" fn() -> !` のため、
+//! クロージャはタスクIDをキーとした `KTHREADS` に保存し、共通のトランポリンが
+//! 起動時に自分のタスクIDで取り出して実行します。
+//! クロージャから戻るか `exit()` を呼ぶとタスクはTerminatedになり、
+//! TCBとスタックはスケジューラによって遅延回収されます。
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+
+use super::blocking::{block_current_task, unblock_task};
+use super::scheduler::{add_task, current_task_id, exit_current_task};
+use super::task::{Nice, RtPriority, Task, TaskError, TaskId, nice};
+
+/// トランポリンが実行するスタートルーチン
+type StartRoutine = Box<dyn FnOnce() + Send + 'static>;
+
+/// カーネルスレッドの管理情報
+struct KthreadEntry {
+    /// 未起動の場合のスタートルーチン
+    start: Option<StartRoutine>,
+    /// スレッドが終了したか
+    exited: bool,
+    /// JoinHandleが破棄され、誰もjoinしないか
+    detached: bool,
+    /// join()で終了を待っているタスク
+    joiners: Vec<TaskId>,
+}
+
+lazy_static! {
+    /// カーネルスレッドの管理テーブル (TaskId -> KthreadEntry)
+    static ref KTHREADS: Mutex<BTreeMap<u64, KthreadEntry>> = Mutex::new(BTreeMap::new());
+}
+
+/// カーネルスレッドのハンドル
+///
+/// `join()` でスレッドの終了を待ち、戻り値を受け取ります。
+/// joinせずに破棄した場合、スレッドはデタッチされ終了時に自動で回収されます。
+#[allow(dead_code)]
+pub struct JoinHandle<T> {
+    task_id: TaskId,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// スレッドのタスクIDを取得
+    #[allow(dead_code)]
+    pub fn task_id(&self) -> TaskId {
+        self.task_id
+    }
+
+    /// スレッドが終了しているか
+    #[allow(dead_code)]
+    pub fn is_finished(&self) -> bool {
+        without_interrupts(|| {
+            KTHREADS
+                .lock()
+                .get(&self.task_id.as_u64())
+                .is_none_or(|entry| entry.exited)
+        })
+    }
+
+    /// スレッドの終了を待って戻り値を取得
+    ///
+    /// # Returns
+    /// クロージャの戻り値。スレッドが `exit()` で終了した場合はNone
+    ///
+    /// # Panics
+    /// 自分自身をjoinした場合（デバッグビルドのみ）
+    #[allow(dead_code)]
+    pub fn join(self) -> Option<T> {
+        let me = current_task_id();
+        debug_assert!(me != self.task_id, "kthread cannot join itself");
+
+        loop {
+            let exited = without_interrupts(|| {
+                let mut threads = KTHREADS.lock();
+                match threads.get_mut(&self.task_id.as_u64()) {
+                    Some(entry) if entry.exited => {
+                        threads.remove(&self.task_id.as_u64());
+                        true
+                    }
+                    Some(entry) => {
+                        if !entry.joiners.contains(&me) {
+                            entry.joiners.push(me);
+                        }
+                        false
+                    }
+                    None => true,
+                }
+            });
+            if exited {
+                break;
+            }
+            // 登録とブロックの間に終了した場合は、WAKEUP_PENDINGによりブロックせずに戻る
+            block_current_task();
+        }
+
+        without_interrupts(|| self.result.lock().take())
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        without_interrupts(|| {
+            let mut threads = KTHREADS.lock();
+            let id = self.task_id.as_u64();
+            match threads.get_mut(&id) {
+                Some(entry) if entry.exited => {
+                    threads.remove(&id);
+                }
+                Some(entry) => entry.detached = true,
+                None => {}
+            }
+        });
+    }
+}
+
+/// 全カーネルスレッド共通のエントリポイント
+extern "C" fn kthread_trampoline() -> ! {
+    let id = current_task_id().as_u64();
+    let start = without_interrupts(|| {
+        KTHREADS
+            .lock()
+            .get_mut(&id)
+            .and_then(|entry| entry.start.take())
+    });
+
+    if let Some(start) = start {
+        start();
+    }
+    exit();
+}
+
+/// 作成済みのタスクにクロージャを登録して起動
+fn start_kthread<F, T>(task: Task, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let task_id = task.id();
+    let result = Arc::new(Mutex::new(None));
+
+    let slot = Arc::clone(&result);
+    let start: StartRoutine = Box::new(move || {
+        let value = f();
+        without_interrupts(|| *slot.lock() = Some(value));
+    });
+
+    // タスクが起動する前にスタートルーチンを登録しておく
+    without_interrupts(|| {
+        KTHREADS.lock().insert(
+            task_id.as_u64(),
+            KthreadEntry {
+                start: Some(start),
+                exited: false,
+                detached: false,
+                joiners: Vec::new(),
+            },
+        );
+    });
+    add_task(task);
+
+    JoinHandle { task_id, result }
+}
+
+/// 指定したnice値でカーネルスレッドを作成して起動
+///
+/// # Arguments
+/// * `name` - タスク名
+/// * `nice` - Nice値（-20〜+19）
+/// * `f` - スレッドで実行するクロージャ
+///
+/// # Returns
+/// スレッドのJoinHandle
+///
+/// # Errors
+/// * `TaskError::StackAllocationFailed` - スタック割り当てに失敗した場合
+/// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
+pub fn spawn_with_nice<F, T>(
+    name: &'static str,
+    nice: Nice,
+    f: F,
+) -> Result<JoinHandle<T>, TaskError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let task = Task::new(name, nice, kthread_trampoline)?;
+    Ok(start_kthread(task, f))
+}
+
+/// Realtimeクラスのカーネルスレッドを作成して起動
+///
+/// # Arguments
+/// * `name` - タスク名
+/// * `rt_priority` - Realtime優先度（1-99、大きいほど高優先度）
+/// * `f` - スレッドで実行するクロージャ
+///
+/// # Errors
+/// * `TaskError::InvalidPriority` - rt_priorityが0の場合
+/// * `TaskError::StackAllocationFailed` - スタック割り当てに失敗した場合
+/// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
+pub fn spawn_realtime<F, T>(
+    name: &'static str,
+    rt_priority: RtPriority,
+    f: F,
+) -> Result<JoinHandle<T>, TaskError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let task = Task::new_realtime(name, rt_priority, kthread_trampoline)?;
+    Ok(start_kthread(task, f))
+}
+
+/// カーネルスレッドを作成して起動（nice値は標準）
+///
+/// # Arguments
+/// * `name` - タスク名
+/// * `f` - スレッドで実行するクロージャ
+///
+/// # Errors
+/// `spawn_with_nice` と同じ
+#[allow(dead_code)]
+pub fn spawn<F, T>(name: &'static str, f: F) -> Result<JoinHandle<T>, TaskError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with_nice(name, nice::DEFAULT, f)
+}
+
+/// スレッドの終了を記録し、join待ちのタスクを起床させる
+///
+/// exit()またはkill()から呼び出されます。カーネルスレッド以外のタスクでは何もしません。
+pub(super) fn notify_exited(task_id: TaskId) {
+    let id = task_id.as_u64();
+    let joiners = without_interrupts(|| {
+        let mut threads = KTHREADS.lock();
+        match threads.get_mut(&id) {
+            // デタッチ済みなら誰も結果を参照しないため、管理情報もここで破棄
+            Some(entry) if entry.detached => {
+                threads.remove(&id);
+                Vec::new()
+            }
+            Some(entry) => {
+                entry.exited = true;
+                core::mem::take(&mut entry.joiners)
+            }
+            None => Vec::new(),
+        }
+    });
+
+    for joiner in joiners {
+        unblock_task(joiner);
+    }
+}
+
+/// 現在のタスクを終了
+///
+/// カーネルスレッドの場合はjoin待ちのタスクを起床させ、現在のタスクを
+/// Terminatedにしてスケジュールします。通常のタスクからも呼び出せます。
+/// この関数から戻ることはありません。
+pub fn exit() -> ! {
+    crate::trace::record(
+        crate::trace::TraceKind::TaskExit,
+        current_task_id().as_u64(),
+        0,
+    );
+    notify_exited(current_task_id());
+    exit_current_task()
+}
@@ -6,31 +6,77 @@
 //! - `task`: タスク構造体、状態、優先度の定義
 //! - `context`: CPUコンテキストとコンテキストスイッチ
 //! - `scheduler`: スケジューラとキュー管理
+//! - `policy`: クラスごとのスケジューリングポリシー（RT/CFS/Idleと実験用ポリシー）
 //! - `blocking`: タスクのブロッキングとスリープ機能
+//! - `kthread`: クロージャを実行するカーネルスレッド（spawn/join/exit）
+//! - `reaper`: 終了済みタスクのTCB・スタックの遅延回収
+//! - `stack`: ガードページ付きのタスクスタック
 
 mod blocking;
 mod context;
+mod policy;
+mod reaper;
 mod scheduler;
+mod stack;
 mod task;
 
+pub mod kthread;
+
 // 公開API: タスク関連
 pub use task::Task;
 pub use task::TaskId;
+pub use task::UserProgram;
 pub use task::nice;
 pub use task::rt_priority;
 
+// 公開API: スタック関連
+pub use stack::guard_owner;
+
+// 公開API: スケジューリングポリシー関連
+pub use policy::PolicyKind;
+
+// 公開API: 拡張状態（FPU/SIMD）の遅延切り替え関連
+pub use context::current_uses_fpu;
+pub use context::fpu_restore_count;
+pub use context::handle_device_not_available;
+
 // 公開API: スケジューラ関連
+#[allow(unused_imports)]
+pub use scheduler::BalanceStats;
 pub use scheduler::add_task;
+pub use scheduler::balance_stats;
 pub use scheduler::check_resched_on_interrupt_exit;
 pub use scheduler::current_task_id;
+pub use scheduler::current_task_id_lockless;
+pub use scheduler::current_user_entry;
+pub use scheduler::dump_tasks;
+pub use scheduler::idle_balance;
 pub use scheduler::init;
+#[allow(unused_imports)]
+pub use scheduler::kill;
+pub use scheduler::normal_policy_name;
+pub use scheduler::runnable_count;
 pub use scheduler::schedule;
+pub use scheduler::scheduler_tick;
 pub use scheduler::set_current_task;
+#[allow(unused_imports)]
 pub use scheduler::set_need_resched;
-pub use scheduler::update_current_task_vruntime;
+pub use scheduler::set_normal_policy;
+#[allow(unused_imports)]
+pub use scheduler::smp_send_reschedule;
+pub use scheduler::task_bursts;
+pub use scheduler::task_runtime_ns;
 
 // 公開API: ブロッキング関連
 pub use blocking::block_current_task;
 pub use blocking::is_interrupt_context;
 pub use blocking::sleep_ms;
+#[allow(unused_imports)]
+pub use blocking::sleep_ns;
+pub use blocking::sleep_us;
 pub use blocking::unblock_task;
+
+// 公開API: 終了関連
+#[allow(unused_imports)]
+pub use kthread::exit;
+pub use reaper::start_reaper;
@@ -0,0 +1,367 @@
+//! スケジューリングポリシー
+//!
+//! スケジューリングクラスごとの実行可能キューと、次タスクの選択・tick時の粒度・
+//! 実行時間の反映を `SchedPolicy` トレイトの実装としてまとめます。
+//! RT/CFS/Idleの各クラスは従来どおりの実装を持ち、Normalクラスのポリシーは
+//! 実験・比較のためにラウンドロビンやロッタリースケジューリングへ切り替えられます。
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::task::{Task, TaskId, burst, rt_priority};
+
+/// スケジューリングポリシーの共通インターフェース
+///
+/// 実装は1つのスケジューリングクラスの実行可能タスクを保持します。
+/// 呼び出し側（scheduler.rs）が割り込み無効化とロックを行います。
+pub trait SchedPolicy: Send {
+    /// ポリシー名
+    fn name(&self) -> &'static str;
+
+    /// 実行可能タスクをキューに追加
+    fn enqueue(&mut self, task: Box<Task>);
+
+    /// 次に実行するタスクをキューから取り出す
+    fn pick_next(&mut self) -> Option<Box<Task>>;
+
+    /// 指定したタスクをキューから取り除く
+    fn remove(&mut self, task_id: TaskId) -> Option<Box<Task>>;
+
+    /// 全タスクをキューから取り出す（ポリシーの切り替え用）
+    ///
+    /// コンテキストのアドレスを保つため、タスクはBoxのまま移動する
+    #[allow(clippy::vec_box)]
+    fn drain(&mut self) -> Vec<Box<Task>>;
+
+    /// キュー内の各タスクを参照
+    fn for_each(&self, f: &mut dyn FnMut(&Task));
+
+    /// キュー内のタスク数（負荷分散の判断に使用）
+    fn len(&self) -> usize {
+        let mut count = 0;
+        self.for_each(&mut |_| count += 1);
+        count
+    }
+
+    /// 実行を終えたタスクに実行時間を反映
+    ///
+    /// # Arguments
+    /// * `ran_ns` - 今回の実行時間（ナノ秒、tick単位の蓄積値）
+    fn put_prev(&self, _task: &mut Task, _ran_ns: u64) {}
+
+    /// プリエンプション粒度を取得（ナノ秒）
+    ///
+    /// タイマーtickで、タスクがこの時間以上実行していればプリエンプトします。
+    fn granularity_ns(&self, task: &Task) -> u64 {
+        task.preempt_granularity_ns()
+    }
+}
+
+// =============================================================================
+// Realtimeクラス
+// =============================================================================
+
+/// Realtimeクラス: 優先度順（同じ優先度ではタスクID順）
+pub struct RtPolicy {
+    /// キー: (MAX - priority, task_id) - 優先度が高い順にソート
+    queue: BTreeMap<(u8, u64), Box<Task>>,
+}
+
+impl RtPolicy {
+    pub const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+        }
+    }
+}
+
+impl SchedPolicy for RtPolicy {
+    fn name(&self) -> &'static str {
+        "rt"
+    }
+
+    fn enqueue(&mut self, task: Box<Task>) {
+        let key = (rt_priority::MAX - task.rt_priority(), task.id().as_u64());
+        self.queue.insert(key, task);
+    }
+
+    fn pick_next(&mut self) -> Option<Box<Task>> {
+        self.queue.pop_first().map(|(_, task)| task)
+    }
+
+    fn remove(&mut self, task_id: TaskId) -> Option<Box<Task>> {
+        let key = *self.queue.keys().find(|k| k.1 == task_id.as_u64())?;
+        self.queue.remove(&key)
+    }
+
+    fn drain(&mut self) -> Vec<Box<Task>> {
+        core::mem::take(&mut self.queue).into_values().collect()
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Task)) {
+        self.queue.values().for_each(|t| f(t));
+    }
+}
+
+// =============================================================================
+// Normalクラス
+// =============================================================================
+
+/// Normalクラスのポリシーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    /// vruntimeが最小のタスクを選択（CFS風、デフォルト）
+    Cfs,
+    /// 到着順に固定のタイムスライスで実行
+    RoundRobin,
+    /// nice値の重みをくじの枚数として抽選
+    Lottery,
+}
+
+impl PolicyKind {
+    /// 表示用の名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyKind::Cfs => "cfs",
+            PolicyKind::RoundRobin => "rr",
+            PolicyKind::Lottery => "lottery",
+        }
+    }
+
+    /// 名前から変換
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cfs" => Some(PolicyKind::Cfs),
+            "rr" => Some(PolicyKind::RoundRobin),
+            "lottery" => Some(PolicyKind::Lottery),
+            _ => None,
+        }
+    }
+
+    /// ポリシーのインスタンスを作成
+    pub fn build(self) -> Box<dyn SchedPolicy> {
+        match self {
+            PolicyKind::Cfs => Box::new(CfsPolicy::new()),
+            PolicyKind::RoundRobin => Box::new(RoundRobinPolicy::new()),
+            PolicyKind::Lottery => Box::new(LotteryPolicy::new()),
+        }
+    }
+}
+
+/// CFS風: vruntimeが最小のタスクを選択
+pub struct CfsPolicy {
+    /// キー: (vruntime, task_id) - vruntimeでソートされ、同じvruntimeの場合はtask_idで区別
+    queue: BTreeMap<(u64, u64), Box<Task>>,
+}
+
+impl CfsPolicy {
+    pub const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+        }
+    }
+}
+
+impl SchedPolicy for CfsPolicy {
+    fn name(&self) -> &'static str {
+        PolicyKind::Cfs.as_str()
+    }
+
+    fn enqueue(&mut self, task: Box<Task>) {
+        let key = (task.vruntime(), task.id().as_u64());
+        self.queue.insert(key, task);
+    }
+
+    fn pick_next(&mut self) -> Option<Box<Task>> {
+        self.queue.pop_first().map(|(_, task)| task)
+    }
+
+    fn remove(&mut self, task_id: TaskId) -> Option<Box<Task>> {
+        let key = *self.queue.keys().find(|k| k.1 == task_id.as_u64())?;
+        self.queue.remove(&key)
+    }
+
+    fn drain(&mut self) -> Vec<Box<Task>> {
+        core::mem::take(&mut self.queue).into_values().collect()
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Task)) {
+        self.queue.values().for_each(|t| f(t));
+    }
+
+    fn put_prev(&self, task: &mut Task, ran_ns: u64) {
+        // ran_nsが0でも最小値(1)を加算して、同じタスクが連続選択されることを防ぐ
+        task.update_vruntime(ran_ns.max(1));
+    }
+}
+
+/// ラウンドロビンのタイムスライス（2tick）
+const RR_TIMESLICE_NS: u64 = 8_000_000;
+
+/// ラウンドロビン: 到着順に固定のタイムスライスで実行（nice値とバースト予測は無視）
+pub struct RoundRobinPolicy {
+    queue: VecDeque<Box<Task>>,
+}
+
+impl RoundRobinPolicy {
+    pub const fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl SchedPolicy for RoundRobinPolicy {
+    fn name(&self) -> &'static str {
+        PolicyKind::RoundRobin.as_str()
+    }
+
+    fn enqueue(&mut self, task: Box<Task>) {
+        self.queue.push_back(task);
+    }
+
+    fn pick_next(&mut self) -> Option<Box<Task>> {
+        self.queue.pop_front()
+    }
+
+    fn remove(&mut self, task_id: TaskId) -> Option<Box<Task>> {
+        let index = self.queue.iter().position(|t| t.id() == task_id)?;
+        self.queue.remove(index)
+    }
+
+    fn drain(&mut self) -> Vec<Box<Task>> {
+        self.queue.drain(..).collect()
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Task)) {
+        self.queue.iter().for_each(|t| f(t));
+    }
+
+    fn granularity_ns(&self, _task: &Task) -> u64 {
+        RR_TIMESLICE_NS
+    }
+}
+
+/// ロッタリースケジューリング: nice値の重みをくじの枚数として毎tick抽選
+pub struct LotteryPolicy {
+    /// コンテキストのアドレスを保つため、タスクはBoxのまま保持する
+    #[allow(clippy::vec_box)]
+    tasks: Vec<Box<Task>>,
+    /// 抽選用の乱数状態（xorshift64、0以外）
+    rng: u64,
+}
+
+impl LotteryPolicy {
+    pub fn new() -> Self {
+        // 起動からの経過時間で種を決める（HPETが利用できなければ固定値）
+        Self {
+            tasks: Vec::new(),
+            rng: crate::hpet::elapsed_ns() | 1,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+}
+
+impl SchedPolicy for LotteryPolicy {
+    fn name(&self) -> &'static str {
+        PolicyKind::Lottery.as_str()
+    }
+
+    fn enqueue(&mut self, task: Box<Task>) {
+        self.tasks.push(task);
+    }
+
+    fn pick_next(&mut self) -> Option<Box<Task>> {
+        // 重み0のタスクも当選できるよう、最低1枚のくじを持たせる
+        let total: u64 = self.tasks.iter().map(|t| t.weight().max(1) as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut ticket = self.next_random() % total;
+        let index = self
+            .tasks
+            .iter()
+            .position(|t| {
+                let tickets = t.weight().max(1) as u64;
+                if ticket < tickets {
+                    true
+                } else {
+                    ticket -= tickets;
+                    false
+                }
+            })
+            .unwrap_or(0);
+        Some(self.tasks.swap_remove(index))
+    }
+
+    fn remove(&mut self, task_id: TaskId) -> Option<Box<Task>> {
+        let index = self.tasks.iter().position(|t| t.id() == task_id)?;
+        Some(self.tasks.swap_remove(index))
+    }
+
+    fn drain(&mut self) -> Vec<Box<Task>> {
+        core::mem::take(&mut self.tasks)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Task)) {
+        self.tasks.iter().for_each(|t| f(t));
+    }
+
+    fn granularity_ns(&self, _task: &Task) -> u64 {
+        burst::MIN_GRANULARITY_NS
+    }
+}
+
+// =============================================================================
+// Idleクラス
+// =============================================================================
+
+/// Idleクラス: FIFO順
+pub struct IdlePolicy {
+    queue: VecDeque<Box<Task>>,
+}
+
+impl IdlePolicy {
+    pub const fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl SchedPolicy for IdlePolicy {
+    fn name(&self) -> &'static str {
+        "idle"
+    }
+
+    fn enqueue(&mut self, task: Box<Task>) {
+        self.queue.push_back(task);
+    }
+
+    fn pick_next(&mut self) -> Option<Box<Task>> {
+        self.queue.pop_front()
+    }
+
+    fn remove(&mut self, task_id: TaskId) -> Option<Box<Task>> {
+        let index = self.queue.iter().position(|t| t.id() == task_id)?;
+        self.queue.remove(index)
+    }
+
+    fn drain(&mut self) -> Vec<Box<Task>> {
+        self.queue.drain(..).collect()
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Task)) {
+        self.queue.iter().for_each(|t| f(t));
+    }
+}
@@ -0,0 +1,76 @@
+//! 終了済みタスクの回収（Reaper）
+//!
+//! 終了したタスクは自身のスタック上でschedule()を呼ぶため、その場でTCBとスタックを
+//! 解放することはできません。schedule()は終了済みタスクを回収待ちリストに移すだけで、
+//! 実際の解放はこのモジュールのReaperタスクが別のスタック上で行います。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::blocking::{block_current_task, unblock_task};
+use super::scheduler::{TERMINATED_TASKS, add_task};
+use super::task::{Task, TaskError, TaskId, nice};
+
+/// ReaperタスクのID（未起動の場合はu64::MAX）
+static REAPER_TASK_ID: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// 指定したタスクがReaperタスクか
+pub(super) fn is_reaper(task_id: TaskId) -> bool {
+    REAPER_TASK_ID.load(Ordering::Relaxed) == task_id.as_u64()
+}
+
+/// Reaperタスクを起床させる
+///
+/// Reaperが未起動の場合、終了済みタスクは起動後にまとめて回収されます。
+pub(super) fn wake_reaper() {
+    let id = REAPER_TASK_ID.load(Ordering::Relaxed);
+    if id != u64::MAX {
+        unblock_task(TaskId::from_u64(id));
+    }
+}
+
+/// Reaperタスクのエントリポイント
+extern "C" fn reaper_task() -> ! {
+    crate::info!("[Reaper] Started");
+
+    loop {
+        let terminated = core::mem::take(&mut *TERMINATED_TASKS.lock());
+
+        if terminated.is_empty() {
+            // 確認後に起床された場合は、WAKEUP_PENDINGによりブロックせずに戻る
+            block_current_task();
+            continue;
+        }
+
+        // ロック外でTCBとスタックを解放
+        for task in terminated {
+            crate::info!(
+                "[Reaper] Reaped task: ID={}, name={}",
+                task.id().as_u64(),
+                task.name()
+            );
+            let task_id = task.id();
+            // 終了したCPUがまだコンテキストを保存中なら、切り替えが終わるまで待つ
+            while task.is_on_cpu() {
+                core::hint::spin_loop();
+            }
+            drop(task);
+            crate::heap_quota::task_exited(task_id);
+            crate::graphics::compositor::release_task_surfaces(task_id);
+            crate::syscall::release_task_console(task_id);
+        }
+    }
+}
+
+/// Reaperタスクを作成して起動
+///
+/// ヒープ初期化後、タスクの終了やkillを使用する前に呼び出します。
+///
+/// # Errors
+/// * `TaskError::StackAllocationFailed` - スタック割り当てに失敗した場合
+/// * `TaskError::ContextInitFailed` - コンテキスト初期化に失敗した場合
+pub fn start_reaper() -> Result<(), TaskError> {
+    let task = Task::new("Reaper", nice::DEFAULT, reaper_task)?;
+    REAPER_TASK_ID.store(task.id().as_u64(), Ordering::Relaxed);
+    add_task(task);
+    Ok(())
+}
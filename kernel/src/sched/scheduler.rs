@@ -1,51 +1,382 @@
 //! スケジューラとタスクキュー管理
 //!
 //! このモジュールはマルチレベルキュースケジューリングとタスク管理を担当します。
+//! 各クラスのキューの中での選択方法は `policy` モジュールの `SchedPolicy` 実装に委譲します。
+//!
+//! # SMP
+//! 実行可能キュー（RT/Normal/Idle）と現在のタスクはCPUごとに持ち、現在のCPU番号は
+//! GSベース（`percpu`）から求めます。割り込みの無効化は自CPUへの割り込みを防ぐだけなので、
+//! CPU間の排他は各キューのロックで行います。
+//!
+//! - タスクは属するCPU（`Task::cpu()`）のキューに入ります
+//! - 自CPUのRealtime/Normalキューが空になると、最も多くのNormalタスクを待たせている
+//!   CPUから1つ引き取ります。加えて一定間隔で偏りを確認し、差が2以上なら1つ引き取ります
+//! - アイドルタスクは停止（hlt）する前に、他のCPUで待っているNormalタスクを引き取ります
+//!   （アイドルバランス）
+//! - タスクのコンテキストからNormalタスクを起こすとき、起こした側のCPUが空いていれば
+//!   そのCPUへ移します（wake-affine）。起こした側と起こされた側が同じデータを扱う
+//!   （コンポジタと描画タスクなど）場合に、キャッシュを温かく保つためです
+//! - 各ヒューリスティクスが働いた回数とCPUごとの移動数は `balance_stats` で取得できます
+//! - 他のCPUのキューに入れたタスクをすぐに実行させる場合は、IPI（`smp_send_reschedule`）で通知します
+//! - 切り替え元のタスクはコンテキストの保存が終わるまで実行中フラグが立っており、
+//!   他のCPUはフラグが下りるまでそのタスクに切り替えません
 
 use alloc::boxed::Box;
-use alloc::collections::BTreeMap;
-use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use lazy_static::lazy_static;
-use spin::Mutex;
 
 use crate::io::without_interrupts;
+use crate::smp::MAX_CPUS;
+use crate::sync::IrqSpinlock;
+use crate::{paging, percpu, smp};
 
 use super::blocking::{BLOCKED_TASKS, WAKEUP_PENDING};
-use super::context::{Context, switch_context};
-use super::task::{SchedulingClass, Task, TaskError, TaskId, TaskState, rt_priority};
+use super::context::{self, Context, switch_context};
+use super::policy::{IdlePolicy, PolicyKind, RtPolicy, SchedPolicy};
+use super::task::{SchedulingClass, Task, TaskError, TaskId, TaskState, burst};
+
+/// 負荷分散の間隔（tick数、250Hzで100ms）
+const BALANCE_INTERVAL_TICKS: u64 = 25;
+
+/// wake-affine: 起こした側のCPUで待っているタスクがこの数以下なら移す
+const WAKE_AFFINE_MAX_QUEUED: usize = 1;
+
+/// CPUごとのスケジューラの状態
+struct CpuSched {
+    /// 現在実行中のタスク
+    current: IrqSpinlock<Option<Box<Task>>>,
+    /// スケジューリングが必要かどうかを示すフラグ
+    /// 割り込みハンドラ（または他のCPU）がこのフラグをセットし、割り込み復帰時にチェックされる
+    need_resched: AtomicBool,
+    /// 現在のタスクに切り替えた時刻（`clock::monotonic_ns`）
+    ///
+    /// 切り替え時にこの時刻からの実行時間をvruntimeとバースト長に反映し、
+    /// タイマー割り込みではロックを取得せずにプリエンプションの判定に使用します。
+    slice_start_ns: AtomicU64,
+    /// 現在のタスクのプリエンプション粒度（ナノ秒）
+    ///
+    /// コンテキストスイッチ時に切り替え先タスクの予測バーストから決まり、
+    /// タイマー割り込みでロックを取得せずに参照されます。
+    granularity_ns: AtomicU64,
+    /// 現在実行中のタスクIDのコピー（タスクが存在しない場合はu64::MAX）
+    ///
+    /// ヒープアロケータなどcurrentのロックを取得できない場所から参照するためのもので、
+    /// コンテキストスイッチの直前に更新されます。
+    current_id: AtomicU64,
+    /// Idleクラスのタスクを実行中か
+    ///
+    /// 他のCPUがこのCPUのキューにタスクを入れたとき、IPIで起こすかの判断に使用します。
+    running_idle: AtomicBool,
+    /// このCPUのタイマーtick数（負荷分散の間隔の計測用）
+    ticks: AtomicU64,
+    /// 他のCPUからこのCPUへ移したタスクの数
+    migrations: AtomicU64,
+}
+
+impl CpuSched {
+    const fn new() -> Self {
+        Self {
+            current: IrqSpinlock::new(None),
+            need_resched: AtomicBool::new(false),
+            slice_start_ns: AtomicU64::new(0),
+            granularity_ns: AtomicU64::new(burst::MIN_GRANULARITY_NS),
+            current_id: AtomicU64::new(u64::MAX),
+            running_idle: AtomicBool::new(false),
+            ticks: AtomicU64::new(0),
+            migrations: AtomicU64::new(0),
+        }
+    }
+}
+
+/// タスクの配置のヒューリスティクスが働いた回数
+struct BalanceCounters {
+    /// 起こしたNormalタスクの数
+    wakeups: AtomicU64,
+    /// 起こしたCPUで実行させた数（元から同じCPU）
+    wake_local: AtomicU64,
+    /// wake-affineで起こしたCPUへ移した数
+    wake_affine: AtomicU64,
+    /// 元のCPUで起こした数（起こしたCPUが混んでいる、元のCPUが空いている、割り込みからの起床）
+    wake_remote: AtomicU64,
+    /// キューが空になったCPUが引き取った数（schedule内）
+    newidle_pulls: AtomicU64,
+    /// アイドルタスクが停止する前に引き取った数
+    idle_pulls: AtomicU64,
+    /// 定期的な負荷分散で引き取った数
+    periodic_pulls: AtomicU64,
+}
 
-/// スケジューリングが必要かどうかを示すフラグ
-/// 割り込みハンドラがこのフラグをセットし、割り込み復帰時にチェックされる
-static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+static BALANCE: BalanceCounters = BalanceCounters {
+    wakeups: AtomicU64::new(0),
+    wake_local: AtomicU64::new(0),
+    wake_affine: AtomicU64::new(0),
+    wake_remote: AtomicU64::new(0),
+    newidle_pulls: AtomicU64::new(0),
+    idle_pulls: AtomicU64::new(0),
+    periodic_pulls: AtomicU64::new(0),
+};
 
-/// 現在のタスクの蓄積実行時間（ナノ秒）
-/// タイマー割り込みで加算され、schedule()でvruntimeに反映される
-/// これにより、ロックを取得せずに実行時間を記録できる
-static ACCUMULATED_RUNTIME: AtomicU64 = AtomicU64::new(0);
+/// タスクの配置の統計（schedstat表示用）
+#[derive(Debug, Clone)]
+pub struct BalanceStats {
+    /// 起こしたNormalタスクの数
+    pub wakeups: u64,
+    /// 起こしたCPUで実行させた数（元から同じCPU）
+    pub wake_local: u64,
+    /// wake-affineで起こしたCPUへ移した数
+    pub wake_affine: u64,
+    /// 元のCPUで起こした数
+    pub wake_remote: u64,
+    /// キューが空になったCPUが引き取った数
+    pub newidle_pulls: u64,
+    /// アイドルタスクが停止する前に引き取った数
+    pub idle_pulls: u64,
+    /// 定期的な負荷分散で引き取った数
+    pub periodic_pulls: u64,
+    /// CPUごとの (CPU番号, 移ってきたタスクの数, 待っているタスクの数)
+    pub cpus: Vec<(usize, u64, usize)>,
+}
+
+/// CPUごとのスケジューラの状態（インデックス = CPU番号）
+static CPU_SCHED: [CpuSched; MAX_CPUS] = [const { CpuSched::new() }; MAX_CPUS];
+
+/// BSPが最初のタスクを設定し、スケジューリングを開始したか
+///
+/// 開始前（カーネルの初期化中）は、他のCPUがBSPのキューからタスクを引き取りません。
+static STARTED: AtomicBool = AtomicBool::new(false);
 
 /// 初回起動時に使用するダミーコンテキスト
 /// 現在のタスクが存在しない場合、このコンテキストに「保存」する（実際には捨てられる）
-static mut DUMMY_CONTEXT: Context = Context { rsp: 0 };
+static mut DUMMY_CONTEXT: Context = Context::empty();
+
+/// ダミーコンテキスト用の実行中フラグ（switch_context()が書き込むのみ）
+static DUMMY_ON_CPU: AtomicBool = AtomicBool::new(false);
+
+/// 1つのCPUの実行可能キュー（マルチレベル）
+struct RunQueues {
+    /// リアルタイムキュー (Realtimeクラスのタスク、優先度順)
+    rt: IrqSpinlock<Box<dyn SchedPolicy>>,
+    /// 通常キュー (Normalクラスのタスク、デフォルトはCFS方式)
+    /// set_normal_policy()で実験用のポリシーに切り替えられる
+    normal: IrqSpinlock<Box<dyn SchedPolicy>>,
+    /// アイドルキュー (Idleクラスのタスク、FIFO順)
+    idle: IrqSpinlock<Box<dyn SchedPolicy>>,
+}
+
+impl RunQueues {
+    fn new() -> Self {
+        Self {
+            rt: IrqSpinlock::new(Box::new(RtPolicy::new())),
+            normal: IrqSpinlock::new(PolicyKind::Cfs.build()),
+            idle: IrqSpinlock::new(Box::new(IdlePolicy::new())),
+        }
+    }
+}
+
+/// 回収待ちタスクのリスト
+///
+/// 終了したタスクはswitch_context()まで動作し続けるため、アドレスが変わらないようBoxのまま保持する
+#[allow(clippy::vec_box)]
+type TerminatedList = Vec<Box<Task>>;
 
-// グローバルタスクキュー（マルチレベル）
 lazy_static! {
-    /// リアルタイムキュー (Realtimeクラスのタスク)
-    /// キー: (255 - priority, task_id) - 優先度が高い順にソート
-    /// 値: タスク
-    static ref RT_QUEUE: Mutex<BTreeMap<(u8, u64), Box<Task>>> = Mutex::new(BTreeMap::new());
+    /// CPUごとの実行可能キュー（インデックス = CPU番号）
+    static ref RUN_QUEUES: Vec<RunQueues> = (0..MAX_CPUS).map(|_| RunQueues::new()).collect();
 
-    /// 通常キュー (Normalクラスのタスク、CFS方式)
-    /// キー: (vruntime, task_id) - vruntimeでソートされ、同じvruntimeの場合はtask_idで区別
-    /// 値: タスク
-    static ref CFS_QUEUE: Mutex<BTreeMap<(u64, u64), Box<Task>>> = Mutex::new(BTreeMap::new());
+    /// 終了済みで回収待ちのタスク
+    /// 終了したタスクはswitch_context()の直前まで自身のスタック上で動作しているため、
+    /// その場では破棄せず、Reaperタスクがまとめて解放する
+    pub(super) static ref TERMINATED_TASKS: IrqSpinlock<TerminatedList> = IrqSpinlock::new(Vec::new());
+}
 
-    /// アイドルキュー (Idleクラスのタスク)
-    /// FIFO順で管理
-    static ref IDLE_QUEUE: Mutex<VecDeque<Box<Task>>> = Mutex::new(VecDeque::new());
+/// 現在のCPUのスケジューラの状態
+///
+/// 割り込み無効状態で使用すること（有効だと途中で別のCPUへ移動する可能性がある）。
+fn this_cpu() -> &'static CpuSched {
+    &CPU_SCHED[percpu::current_index()]
+}
 
-    /// 現在実行中のタスク
-    pub(super) static ref CURRENT_TASK: Mutex<Option<Box<Task>>> = Mutex::new(None);
+/// 現在のCPUで実行中のタスク
+///
+/// 割り込み無効状態で使用すること。
+pub(super) fn current_task() -> &'static IrqSpinlock<Option<Box<Task>>> {
+    &this_cpu().current
+}
+
+/// スケジューリング対象のCPU番号（BSPと起動済みのAP）
+fn online_cpus() -> impl Iterator<Item = usize> {
+    (0..smp::cpu_count().max(1))
+        .filter(|&cpu| cpu == percpu::BSP_INDEX || smp::cpu_info(cpu).is_some_and(|(_, on)| on))
+}
+
+/// タスク1件の状態を出力
+fn print_task(queue: &str, task: &Task) {
+    crate::println!(
+        "  {:>4} {:<16} {:>3} {:<8} {:?}/{:?} vruntime={} transitions={}{}",
+        task.id().as_u64(),
+        task.name(),
+        task.cpu(),
+        queue,
+        task.sched_class(),
+        task.state(),
+        task.vruntime(),
+        task.transition_count(),
+        if task.uses_fpu() { " fpu" } else { "" }
+    );
+    if let Some(usage) = crate::heap_quota::try_task_usage(task.id()) {
+        match usage.quota {
+            Some(quota) => crate::println!(
+                "       heap={}B peak={}B quota={}B",
+                usage.current,
+                usage.peak,
+                quota
+            ),
+            None => crate::println!("       heap={}B peak={}B", usage.current, usage.peak),
+        }
+    }
+}
+
+/// 全タスクの状態をシリアルに出力
+///
+/// SysRqなど割り込みコンテキストからの緊急用です。割り込まれたコードがキューのロックを
+/// 保持している可能性があるため、ロックはtry_lockで取得し、取得できないキューは省略します。
+pub fn dump_tasks() {
+    without_interrupts(|| {
+        crate::println!(
+            "  {:>4} {:<16} {:>3} {:<8} CLASS/STATE",
+            "ID",
+            "NAME",
+            "CPU",
+            "QUEUE"
+        );
+
+        for cpu in online_cpus() {
+            match CPU_SCHED[cpu].current.try_lock() {
+                Some(current) => current.iter().for_each(|t| print_task("current", t)),
+                None => crate::println!("  <cpu{} current task locked>", cpu),
+            }
+            for queue in run_queues(cpu) {
+                match queue.try_lock() {
+                    Some(queue) => {
+                        let name = queue.name();
+                        queue.for_each(&mut |t| print_task(name, t));
+                    }
+                    None => crate::println!("  <cpu{} run queue locked>", cpu),
+                }
+            }
+        }
+        match BLOCKED_TASKS.try_lock() {
+            Some(blocked) => blocked.values().for_each(|t| print_task("blocked", t)),
+            None => crate::println!("  <blocked tasks locked>"),
+        }
+    });
+}
+
+/// タスク1件のCPUバースト予測（schedtop表示用）
+#[derive(Debug, Clone)]
+pub struct TaskBurst {
+    /// タスクID
+    pub id: TaskId,
+    /// タスク名
+    pub name: String,
+    /// スケジューリングクラス
+    pub sched_class: SchedulingClass,
+    /// 現在のバーストの実行時間（ナノ秒）
+    pub burst_ns: u64,
+    /// 次のバースト長の予測値（ナノ秒）
+    pub predicted_burst_ns: u64,
+    /// プリエンプションの粒度（ナノ秒）
+    pub granularity_ns: u64,
+    /// 対話的なタスクとして扱われているか
+    pub interactive: bool,
+}
+
+impl TaskBurst {
+    fn new(task: &Task, running_ns: u64) -> Self {
+        Self {
+            id: task.id(),
+            name: String::from(task.name()),
+            sched_class: task.sched_class(),
+            burst_ns: task.burst_ns().saturating_add(running_ns),
+            predicted_burst_ns: task.predicted_burst_ns(),
+            granularity_ns: task.preempt_granularity_ns(),
+            interactive: task.is_interactive(),
+        }
+    }
+}
+
+/// 全タスクのCPUバースト予測を取得
+///
+/// 実行中のタスクの現在のバーストには、スケジュールされてからの実行時間も含めます。
+pub fn task_bursts() -> Vec<TaskBurst> {
+    let mut bursts = Vec::new();
+    for cpu in online_cpus() {
+        let sched = &CPU_SCHED[cpu];
+        let running_ns = slice_runtime_ns(sched);
+        if let Some(task) = sched.current.lock().as_ref() {
+            bursts.push(TaskBurst::new(task, running_ns));
+        }
+        for queue in run_queues(cpu) {
+            queue
+                .lock()
+                .for_each(&mut |t| bursts.push(TaskBurst::new(t, 0)));
+        }
+    }
+    bursts.extend(BLOCKED_TASKS.lock().values().map(|t| TaskBurst::new(t, 0)));
+    bursts.sort_by_key(|b| b.id.as_u64());
+    bursts
+}
+
+/// タスクの累計実行時間を取得（ナノ秒）
+///
+/// 実行中のタスクには、スケジュールされてからの実行時間も含めます。
+///
+/// # Returns
+/// タスクが存在しない場合はNone
+pub fn task_runtime_ns(task_id: TaskId) -> Option<u64> {
+    for cpu in online_cpus() {
+        let sched = &CPU_SCHED[cpu];
+        let running_ns = slice_runtime_ns(sched);
+        if let Some(task) = sched.current.lock().as_ref()
+            && task.id() == task_id
+        {
+            return Some(task.runtime_ns().saturating_add(running_ns));
+        }
+        for queue in run_queues(cpu) {
+            let mut runtime = None;
+            queue.lock().for_each(&mut |t| {
+                if t.id() == task_id {
+                    runtime = Some(t.runtime_ns());
+                }
+            });
+            if runtime.is_some() {
+                return runtime;
+            }
+        }
+    }
+    BLOCKED_TASKS
+        .lock()
+        .get(&task_id.as_u64())
+        .map(|t| t.runtime_ns())
+}
+
+/// 全CPUの実行可能キューに入っているタスク数（実行中のタスクを除く）
+pub fn runnable_count() -> usize {
+    online_cpus()
+        .map(|cpu| {
+            run_queues(cpu)
+                .iter()
+                .map(|queue| queue.lock().len())
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+/// CPUの現在のタスクがスケジュールされてからの実行時間を取得（ナノ秒）
+fn slice_runtime_ns(sched: &CpuSched) -> u64 {
+    crate::clock::monotonic_ns().saturating_sub(sched.slice_start_ns.load(Ordering::Relaxed))
 }
 
 /// タスク管理システムの初期化
@@ -53,6 +384,22 @@ pub fn init() {
     crate::info!("Task system initialized");
 }
 
+/// CPUのスケジューリングクラスに対応する実行可能キューを取得
+fn class_queue(cpu: usize, class: SchedulingClass) -> &'static IrqSpinlock<Box<dyn SchedPolicy>> {
+    let queues = &RUN_QUEUES[cpu];
+    match class {
+        SchedulingClass::Realtime => &queues.rt,
+        SchedulingClass::Normal => &queues.normal,
+        SchedulingClass::Idle => &queues.idle,
+    }
+}
+
+/// CPUの全クラスの実行可能キューを優先順位（Realtime > Normal > Idle）で取得
+fn run_queues(cpu: usize) -> [&'static IrqSpinlock<Box<dyn SchedPolicy>>; 3] {
+    let queues = &RUN_QUEUES[cpu];
+    [&queues.rt, &queues.normal, &queues.idle]
+}
+
 /// タスクを適切なキューに追加（単一キューロック版）
 ///
 /// schedule()の最適化用。必要なキューのみをロックしてエンキューします。
@@ -64,44 +411,194 @@ pub fn init() {
 /// 呼び出すとデッドロックの可能性があります。
 #[inline]
 fn enqueue_task_single(task: Box<Task>) {
-    match task.sched_class() {
-        SchedulingClass::Realtime => {
-            let key = (rt_priority::MAX - task.rt_priority(), task.id().as_u64());
-            let mut rt_queue = RT_QUEUE.lock();
-            rt_queue.insert(key, task);
-        }
-        SchedulingClass::Normal => {
-            let key = (task.vruntime(), task.id().as_u64());
-            let mut cfs_queue = CFS_QUEUE.lock();
-            cfs_queue.insert(key, task);
-        }
-        SchedulingClass::Idle => {
-            let mut idle_queue = IDLE_QUEUE.lock();
-            idle_queue.push_back(task);
-        }
+    class_queue(task.cpu(), task.sched_class())
+        .lock()
+        .enqueue(task);
+}
+
+/// タスクを属するCPUのキューに追加（blocking.rsから呼び出される）
+///
+/// # Arguments
+/// * `task` - 追加するタスク
+/// * `preempt` - trueなら、追加先のCPUで実行中のタスクの粒度を待たずに再スケジュールさせる
+///
+/// 追加先のCPUがIdleクラスのタスクを実行中の場合も、すぐに再スケジュールさせます。
+pub(super) fn enqueue_to_appropriate_queue(task: Box<Task>, preempt: bool) {
+    let cpu = task.cpu();
+    class_queue(cpu, task.sched_class()).lock().enqueue(task);
+    if preempt || CPU_SCHED[cpu].running_idle.load(Ordering::Relaxed) {
+        smp_send_reschedule(cpu);
     }
 }
 
-/// タスクを適切なキューに追加（blocking.rsから呼び出される）
-pub(super) fn enqueue_to_appropriate_queue(task: Box<Task>, sched_class: SchedulingClass) {
-    match sched_class {
-        SchedulingClass::Realtime => {
-            let mut rt = RT_QUEUE.lock();
-            let key = (rt_priority::MAX - task.rt_priority(), task.id().as_u64());
-            rt.insert(key, task);
-        }
-        SchedulingClass::Normal => {
-            let mut cfs = CFS_QUEUE.lock();
-            let key = (task.vruntime(), task.id().as_u64());
-            cfs.insert(key, task);
-        }
-        SchedulingClass::Idle => {
-            let mut idle = IDLE_QUEUE.lock();
-            idle.push_back(task);
+/// 指定したCPUに再スケジュールを要求
+///
+/// 現在のCPUならneed_reschedフラグをセットするのみで、他のCPUにはIPIを送り、
+/// 割り込みからの復帰時にスケジューラを呼び出させます。
+///
+/// # Arguments
+/// * `cpu` - CPU番号
+pub fn smp_send_reschedule(cpu: usize) {
+    CPU_SCHED[cpu].need_resched.store(true, Ordering::Release);
+    if cpu != percpu::current_index()
+        && let Some((apic_id, true)) = smp::cpu_info(cpu)
+    {
+        crate::apic::send_fixed_ipi(apic_id, smp::RESCHEDULE_VECTOR);
+    }
+}
+
+/// 最も多くのNormalタスクを待たせている他のCPUから、タスクを1つ引き取る
+///
+/// # Arguments
+/// * `cpu` - 引き取る側のCPU番号
+/// * `min_queued` - 相手のキューにこの数以上のタスクがある場合のみ引き取る（最小1）
+///
+/// # Returns
+/// 引き取ったタスク（属するCPUは `cpu` に変更済みで、キューには入れていない）
+///
+/// # Note
+/// 2つのキューのロックを同時に保持しないため、CPU間でロック順序の問題は生じません。
+/// Realtime/Idleクラスのタスクは移動しません。
+fn pull_task(cpu: usize, min_queued: usize) -> Option<Box<Task>> {
+    if !STARTED.load(Ordering::Acquire) {
+        return None;
+    }
+    let (busiest, queued) = online_cpus()
+        .filter(|&other| other != cpu)
+        .map(|other| (other, RUN_QUEUES[other].normal.lock().len()))
+        .max_by_key(|&(_, queued)| queued)?;
+    if queued < min_queued.max(1) {
+        return None;
+    }
+    let mut task = RUN_QUEUES[busiest].normal.lock().pick_next()?;
+    task.set_cpu(cpu);
+    CPU_SCHED[cpu].migrations.fetch_add(1, Ordering::Relaxed);
+    Some(task)
+}
+
+/// 負荷分散: 自CPUより2つ以上多くNormalタスクを待たせているCPUから1つ引き取る
+///
+/// タイマー割り込みから `BALANCE_INTERVAL_TICKS` ごとに呼び出されます。
+fn load_balance(cpu: usize) {
+    let queued = RUN_QUEUES[cpu].normal.lock().len();
+    if let Some(task) = pull_task(cpu, queued + 2) {
+        RUN_QUEUES[cpu].normal.lock().enqueue(task);
+        BALANCE.periodic_pulls.fetch_add(1, Ordering::Relaxed);
+        set_need_resched();
+    }
+}
+
+/// CPUで待っているRealtime/Normalタスクの数
+fn queued_tasks(cpu: usize) -> usize {
+    let queues = &RUN_QUEUES[cpu];
+    queues.rt.lock().len() + queues.normal.lock().len()
+}
+
+/// 起こすタスクを実行するCPUを決める（wake-affine）
+///
+/// Normalタスクを起こしたCPU（現在のCPU）で待っているタスクが `WAKE_AFFINE_MAX_QUEUED` 以下で、
+/// 元のCPU以下なら、起こしたCPUへ移します。元のCPUがアイドルならすぐに実行できるため移しません。
+/// 割り込みハンドラからの起床では、起こしたCPUとタスクに関係がないため移しません。
+///
+/// # Arguments
+/// * `task` - 起こすタスク（属するCPUを変更する）
+/// * `from_task` - タスクのコンテキストから起こしたか
+///
+/// # Note
+/// 割り込み無効状態で、キューのロックを保持せずに呼び出すこと。
+pub(super) fn select_wake_cpu(task: &mut Task, from_task: bool) {
+    if task.sched_class() != SchedulingClass::Normal {
+        return;
+    }
+    BALANCE.wakeups.fetch_add(1, Ordering::Relaxed);
+    let this_cpu = percpu::current_index();
+    let prev_cpu = task.cpu();
+    if prev_cpu == this_cpu {
+        BALANCE.wake_local.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let affine = from_task && STARTED.load(Ordering::Acquire) && {
+        let prev_queued = queued_tasks(prev_cpu);
+        let prev_idle =
+            CPU_SCHED[prev_cpu].running_idle.load(Ordering::Relaxed) && prev_queued == 0;
+        let this_queued = queued_tasks(this_cpu);
+        !prev_idle && this_queued <= WAKE_AFFINE_MAX_QUEUED && this_queued <= prev_queued
+    };
+    if affine {
+        task.set_cpu(this_cpu);
+        CPU_SCHED[this_cpu]
+            .migrations
+            .fetch_add(1, Ordering::Relaxed);
+        BALANCE.wake_affine.fetch_add(1, Ordering::Relaxed);
+    } else {
+        BALANCE.wake_remote.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// アイドルバランス: 他のCPUで待っているNormalタスクを引き取って実行する
+///
+/// アイドルタスクが停止（hlt）する前に呼び出します。引き取った場合はそのタスクに切り替え、
+/// アイドルタスクが再び選ばれたときに戻ります。
+pub fn idle_balance() {
+    let pulled = without_interrupts(|| {
+        let cpu = percpu::current_index();
+        if queued_tasks(cpu) > 0 {
+            return true;
         }
+        let Some(task) = pull_task(cpu, 1) else {
+            return false;
+        };
+        RUN_QUEUES[cpu].normal.lock().enqueue(task);
+        BALANCE.idle_pulls.fetch_add(1, Ordering::Relaxed);
+        true
+    });
+    if pulled {
+        schedule();
     }
 }
 
+/// タスクの配置の統計を取得
+pub fn balance_stats() -> BalanceStats {
+    BalanceStats {
+        wakeups: BALANCE.wakeups.load(Ordering::Relaxed),
+        wake_local: BALANCE.wake_local.load(Ordering::Relaxed),
+        wake_affine: BALANCE.wake_affine.load(Ordering::Relaxed),
+        wake_remote: BALANCE.wake_remote.load(Ordering::Relaxed),
+        newidle_pulls: BALANCE.newidle_pulls.load(Ordering::Relaxed),
+        idle_pulls: BALANCE.idle_pulls.load(Ordering::Relaxed),
+        periodic_pulls: BALANCE.periodic_pulls.load(Ordering::Relaxed),
+        cpus: online_cpus()
+            .map(|cpu| {
+                let queued = without_interrupts(|| queued_tasks(cpu));
+                (
+                    cpu,
+                    CPU_SCHED[cpu].migrations.load(Ordering::Relaxed),
+                    queued,
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Normalクラスのスケジューリングポリシーを切り替え
+///
+/// 全CPUのキュー内のタスクを新しいポリシーに移し替えます。実行中・ブロック中のタスクは
+/// 次にエンキューされた時点で新しいポリシーに加わります。
+pub fn set_normal_policy(kind: PolicyKind) {
+    for queues in RUN_QUEUES.iter() {
+        let mut queue = queues.normal.lock();
+        let tasks = queue.drain();
+        *queue = kind.build();
+        tasks.into_iter().for_each(|task| queue.enqueue(task));
+    }
+    crate::info!("Normal class scheduling policy: {}", kind.as_str());
+}
+
+/// Normalクラスの現在のスケジューリングポリシー名を取得
+pub fn normal_policy_name() -> &'static str {
+    RUN_QUEUES[percpu::BSP_INDEX].normal.lock().name()
+}
+
 /// 新しいタスクをタスクキューに追加（エラーハンドリング版）
 ///
 /// # Arguments
@@ -112,33 +609,19 @@ pub(super) fn enqueue_to_appropriate_queue(task: Box<Task>, sched_class: Schedul
 ///
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
-/// スケジューリングクラスに応じて、適切なキュー（RT/CFS/IDLE）に追加します。
+/// スケジューリングクラスに応じて、現在のCPUの適切なキュー（RT/CFS/IDLE）に追加します。
+/// Normalクラスのタスクは、負荷分散によって他のCPUへ移動することがあります。
 pub fn try_add_task(task: Task) -> Result<(), TaskError> {
     let task_id = task.id().as_u64();
     let sched_class = task.sched_class();
     // 名前を所有型として取得（借用を終わらせるため）
     let name = alloc::format!("{}", task.name());
 
+    // スケジューリングクラスに応じて適切なキューに追加
     without_interrupts(|| {
-        let boxed_task = Box::new(task);
-
-        // スケジューリングクラスに応じて適切なキューに追加
-        match sched_class {
-            SchedulingClass::Realtime => {
-                let mut rt = RT_QUEUE.lock();
-                let key = (rt_priority::MAX - boxed_task.rt_priority(), task_id);
-                rt.insert(key, boxed_task);
-            }
-            SchedulingClass::Normal => {
-                let mut cfs = CFS_QUEUE.lock();
-                let key = (boxed_task.vruntime(), task_id);
-                cfs.insert(key, boxed_task);
-            }
-            SchedulingClass::Idle => {
-                let mut idle = IDLE_QUEUE.lock();
-                idle.push_back(boxed_task);
-            }
-        }
+        let mut task = Box::new(task);
+        task.set_cpu(percpu::current_index());
+        enqueue_to_appropriate_queue(task, false);
     });
 
     crate::info!(
@@ -172,41 +655,64 @@ pub fn yield_now() {
 /// 現在実行中のタスクを設定
 ///
 /// カーネル初期化時に、kernel_main_innerをタスクとして登録するために使用します。
+/// アプリケーションプロセッサは、起動時の実行コンテキストをアイドルタスクとして登録します。
 ///
 /// # Arguments
 /// * `task` - 現在のタスクとして設定するタスク
 ///
 /// # Note
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
-pub fn set_current_task(task: Task) {
+pub fn set_current_task(mut task: Task) {
+    task.transition(TaskState::Running);
     without_interrupts(|| {
-        let mut current = CURRENT_TASK.lock();
-        *current = Some(Box::new(task));
+        let cpu = percpu::current_index();
+        let sched = &CPU_SCHED[cpu];
+        task.set_cpu(cpu);
+        task.on_cpu_flag().store(true, Ordering::Relaxed);
+        sched
+            .current_id
+            .store(task.id().as_u64(), Ordering::Relaxed);
+        sched.running_idle.store(
+            task.sched_class() == SchedulingClass::Idle,
+            Ordering::Relaxed,
+        );
+        sched
+            .slice_start_ns
+            .store(crate::clock::monotonic_ns(), Ordering::Relaxed);
+        let task = Box::new(task);
+        // 起動時の実行コンテキストのFPU/SIMDレジスタをこのタスクの拡張状態とする
+        context::adopt_current(cpu, task.id().as_u64(), task.extended_state());
+        *sched.current.lock() = Some(task);
+        // BSPが最初のタスクを設定した時点で、CPU間のタスクの移動を許可する
+        if cpu == percpu::BSP_INDEX {
+            STARTED.store(true, Ordering::Release);
+        }
     });
 }
 
-/// 現在実行中のタスクの実行時間を蓄積
-///
-/// タイマー割り込みハンドラから呼び出されます。
-/// 実際のvruntime更新はschedule()内で行われます。
+/// 現在のCPUにスケジューリングが必要であることを示すフラグをセット
 ///
-/// # Arguments
-/// * `delta` - 実際の実行時間（ナノ秒単位）
-///
-/// # Design
-/// ロックを取得せずにアトミック変数に蓄積することで、
-/// デッドロックを回避しつつ、確実に実行時間を記録します。
-/// schedule()が呼ばれた時に、蓄積された時間がvruntimeに反映されます。
-pub fn update_current_task_vruntime(delta: u64) {
-    ACCUMULATED_RUNTIME.fetch_add(delta, Ordering::Relaxed);
+/// 実際のスケジューリングは割り込み復帰時に行われます。
+pub fn set_need_resched() {
+    this_cpu().need_resched.store(true, Ordering::Release);
 }
 
-/// スケジューリングが必要であることを示すフラグをセット
+/// タイマーtickごとにプリエンプションが必要か判定
 ///
 /// タイマー割り込みハンドラから呼び出されます。
-/// 実際のスケジューリングは割り込み復帰時に行われます。
-pub fn set_need_resched() {
-    NEED_RESCHED.store(true, Ordering::Release);
+/// 現在のタスクがプリエンプション粒度以上実行していれば、need_reschedフラグをセットします。
+/// 粒度は予測バースト長から決まるため、バッチ的なタスクほど長く連続して実行されます。
+/// また、`BALANCE_INTERVAL_TICKS` ごとにCPU間の負荷分散を行います。
+pub fn scheduler_tick() {
+    let cpu = percpu::current_index();
+    let sched = &CPU_SCHED[cpu];
+    if slice_runtime_ns(sched) >= sched.granularity_ns.load(Ordering::Relaxed) {
+        set_need_resched();
+    }
+    let ticks = sched.ticks.fetch_add(1, Ordering::Relaxed) + 1;
+    if ticks.is_multiple_of(BALANCE_INTERVAL_TICKS) {
+        load_balance(cpu);
+    }
 }
 
 /// 割り込み復帰時にsoftirq処理とスケジューリングをチェック
@@ -231,7 +737,8 @@ pub fn set_need_resched() {
 pub fn check_resched_on_interrupt_exit() {
     // 1. softirq処理（タイマーコールバック実行）
     // schedule()の前に実行することで、unblockされたタスクが即座にスケジューリング対象になる
-    if crate::timer::softirq_pending() {
+    // タイマーはBSPのみが処理する
+    if percpu::is_bsp() && crate::timer::softirq_pending() {
         // SAFETY: STI命令は割り込みフラグを有効化するのみで安全。
         unsafe {
             core::arch::asm!("sti", options(nomem, nostack));
@@ -254,7 +761,7 @@ pub fn check_resched_on_interrupt_exit() {
     }
 
     // softirq処理でunblockされたタスクも含めてスケジューリング
-    if NEED_RESCHED.swap(false, Ordering::Acquire) {
+    if this_cpu().need_resched.swap(false, Ordering::Acquire) {
         // 割り込みは無効のままschedule()を呼び出す
         // これにより、schedule()実行中に再度タイマー割り込みが入ることを防ぐ
         schedule();
@@ -262,6 +769,92 @@ pub fn check_resched_on_interrupt_exit() {
     // iretqで元のRFLAGSが復元される
 }
 
+/// 現在のタスクを終了状態にしてスケジュール
+///
+/// タスクのTCBとスタックは、別のタスクに切り替わった後の
+/// schedule()呼び出し時に解放されます。
+pub(super) fn exit_current_task() -> ! {
+    without_interrupts(|| {
+        if let Some(task) = current_task().lock().as_mut() {
+            task.transition(TaskState::Terminated);
+        }
+    });
+
+    // schedule()内で回収待ちリストに移動し、Reaperを起床させる
+    schedule();
+
+    // 終了したタスクが再スケジュールされることはない
+    unreachable!("Terminated task was rescheduled");
+}
+
+/// 強制終了するタスクを各CPUのキューまたはブロック中のタスクから取り除く
+///
+/// # Errors
+/// * `TaskError::TaskNotFound` - 指定したタスクがキューにもブロック中のタスクにもない場合
+/// * `TaskError::NotKillable` - Idleクラスのタスクを指定した場合
+fn take_killable_task(task_id: TaskId) -> Result<Box<Task>, TaskError> {
+    let id = task_id.as_u64();
+    for queues in RUN_QUEUES.iter() {
+        let mut is_idle = false;
+        queues
+            .idle
+            .lock()
+            .for_each(&mut |t| is_idle |= t.id() == task_id);
+        if is_idle {
+            return Err(TaskError::NotKillable);
+        }
+
+        if let Some(task) = queues.rt.lock().remove(task_id) {
+            return Ok(task);
+        }
+        if let Some(task) = queues.normal.lock().remove(task_id) {
+            return Ok(task);
+        }
+    }
+
+    // ロック順序: BLOCKED_TASKS → WAKEUP_PENDING
+    let mut blocked = BLOCKED_TASKS.lock();
+    let task = blocked.remove(&id).ok_or(TaskError::TaskNotFound)?;
+    WAKEUP_PENDING.lock().remove(&id);
+    Ok(task)
+}
+
+/// 指定したタスクを強制終了
+///
+/// タスクが存在するキュー（各CPUのRT/CFS、ブロック中）から取り除き、Reaperタスクに回収させます。
+/// 現在のタスクを指定した場合は `exit()` と同じ動作になります。
+///
+/// # Arguments
+/// * `task_id` - 終了させるタスクのID
+///
+/// # Errors
+/// * `TaskError::TaskNotFound` - 指定したタスクが存在しない、または他のCPUで実行中の場合
+/// * `TaskError::NotKillable` - Idleクラスのタスク、またはReaperタスクを指定した場合
+///
+/// # Note
+/// 対象タスクが保持しているロックやWaitQueueへの登録は解放されません。
+/// ロックを保持している可能性のあるタスクには使用しないでください。
+#[allow(dead_code)]
+pub fn kill(task_id: TaskId) -> Result<(), TaskError> {
+    if task_id == current_task_id() {
+        super::kthread::exit();
+    }
+    if super::reaper::is_reaper(task_id) {
+        return Err(TaskError::NotKillable);
+    }
+
+    let id = task_id.as_u64();
+    let mut task = take_killable_task(task_id)?;
+
+    crate::info!("Task killed: ID={}, name={}", id, task.name());
+
+    task.transition(TaskState::Terminated);
+    TERMINATED_TASKS.lock().push(task);
+    super::kthread::notify_exited(task_id);
+    super::reaper::wake_reaper();
+    Ok(())
+}
+
 /// 現在のタスクIDを取得
 ///
 /// # Returns
@@ -271,33 +864,57 @@ pub fn check_resched_on_interrupt_exit() {
 /// 割り込みを無効化してからロックを取得し、デッドロックを防ぎます。
 pub fn current_task_id() -> TaskId {
     without_interrupts(|| {
-        let current = CURRENT_TASK.lock();
+        let current = current_task().lock();
         current.as_ref().map(|t| t.id()).unwrap_or_else(TaskId::new)
     })
 }
 
+/// 現在のタスクのユーザープログラムの (エントリポイント, 初期RSP)
+///
+/// カーネルタスクの場合はNone
+pub fn current_user_entry() -> Option<(u64, u64)> {
+    without_interrupts(|| current_task().lock().as_ref().and_then(|t| t.user_entry()))
+}
+
+/// 現在のタスクIDをロックを取得せずに取得
+///
+/// ヒープアロケータのように、スケジューラのロック保持中にも呼ばれうる場所で使用します。
+/// コンテキストスイッチ処理の途中では、切り替え前のタスクIDを返します。
+///
+/// # Returns
+/// 現在実行中のタスクのID。スケジューラ起動前はNone
+pub fn current_task_id_lockless() -> Option<TaskId> {
+    // CPU番号の取得から読み込みまでの間に別のCPUへ移動しないよう、割り込みを無効化する
+    match without_interrupts(|| this_cpu().current_id.load(Ordering::Relaxed)) {
+        u64::MAX => None,
+        id => Some(TaskId::from_u64(id)),
+    }
+}
+
 /// 次に実行するタスクを選択してコンテキストスイッチ
 ///
 /// マルチレベルキュースケジューリングを行います。
 /// - 優先順位: Realtime > Normal (CFS) > Idle
 /// - 上位クラスのキューが空になるまで、下位クラスのタスクは実行されません
-/// - Realtimeクラス内では優先度順、Normalクラス内ではvruntime順
+/// - Realtimeクラス内では優先度順、Normalクラス内は選択中のポリシー（デフォルトはvruntime順）
+///
+/// - 自CPUのRealtime/Normalキューが空なら、他のCPUからNormalタスクを1つ引き取ります
 ///
 /// RFLAGSの保存・復元はswitch_context()内部で自動的に行われます。
 /// switch_context()でRFLAGSのIFフラグが強制セットされるため、
 /// タスク復帰時は必ず割り込み有効状態になります。
 ///
 /// # ロック順序（段階的取得）
-/// 1. RT_QUEUE → 即解放
-/// 2. CFS_QUEUE → 即解放
-/// 3. IDLE_QUEUE → 即解放
-/// 4. CURRENT_TASK → 処理後解放
+/// 1. 自CPUのRTキュー → 即解放
+/// 2. 自CPUのNormalキュー → 即解放（空なら他のCPUのNormalキューを1つずつ一時ロック）
+/// 3. 自CPUのIdleキュー → 即解放
+/// 4. 自CPUの現在のタスク → 処理後解放（保持したまま実行時間の反映のため各キューを一時ロック）
 /// 5. BLOCKED_TASKS または 各キュー（単一）
 ///
 /// # 前提条件
 /// この関数は内部で cli を実行するため、割り込み有効状態で呼び出すこと。
-/// シングルコア環境を前提としており、cli による割り込み無効化が
-/// フェーズ間のレース条件を防いでいます。
+/// cli は自CPU上のフェーズ間の割り込みを防ぐのみで、他のCPUとの排他は各ロックと
+/// タスクの実行中フラグ（`Task::is_on_cpu()`）で行います。
 ///
 /// # Note
 /// この関数は割り込みを無効化してからロックを取得します。
@@ -310,35 +927,33 @@ pub fn schedule() {
         core::arch::asm!("cli", options(nomem, nostack));
     }
 
+    let cpu = percpu::current_index();
+    let sched = &CPU_SCHED[cpu];
+
     // ===== フェーズ1: 次タスクの選択（段階的ロック取得） =====
-    // 優先度順にキューをチェックし、見つかったらすぐにロック解放
+    // 優先度順（RT → Normal → Idle）にキューをチェックし、見つかったらすぐにロック解放
     // これにより、複数のキューを同時にロックする必要がなくなる
-    let next_task = {
-        // 1. リアルタイムキューをチェック（最優先）
-        let mut rt_queue = RT_QUEUE.lock();
-        if let Some(entry) = rt_queue.pop_first() {
-            drop(rt_queue);
-            Some(entry.1)
-        } else {
-            drop(rt_queue);
-            // 2. CFSキューをチェック
-            let mut cfs_queue = CFS_QUEUE.lock();
-            if let Some(entry) = cfs_queue.pop_first() {
-                drop(cfs_queue);
-                Some(entry.1)
-            } else {
-                drop(cfs_queue);
-                // 3. アイドルキューをチェック
-                let mut idle_queue = IDLE_QUEUE.lock();
-                let task = idle_queue.pop_front();
-                drop(idle_queue);
-                task
-            }
-        }
+    // 粒度は選択したポリシーが決めるため、同じロック内で取得する
+    let pick = |queue: &IrqSpinlock<Box<dyn SchedPolicy>>| {
+        let mut queue = queue.lock();
+        let task = queue.pick_next()?;
+        let granularity = queue.granularity_ns(&task);
+        Some((task, granularity))
     };
+    let [rt_queue, normal_queue, idle_queue] = run_queues(cpu);
+    let next = pick(rt_queue)
+        .or_else(|| pick(normal_queue))
+        .or_else(|| {
+            // 自CPUに実行可能なタスクがなければ、他のCPUから引き取る
+            let task = pull_task(cpu, 1)?;
+            BALANCE.newidle_pulls.fetch_add(1, Ordering::Relaxed);
+            let granularity = normal_queue.lock().granularity_ns(&task);
+            Some((task, granularity))
+        })
+        .or_else(|| pick(idle_queue));
 
     // タスクがない場合は早期リターン
-    let Some(mut next_task) = next_task else {
+    let Some((mut next_task, next_granularity)) = next else {
         // SAFETY: sti は割り込みフラグを有効化するのみで、メモリ安全性に影響しない。
         // cli で無効化した割り込みを復元する。
         unsafe {
@@ -347,40 +962,62 @@ pub fn schedule() {
         return;
     };
 
-    next_task.set_state(TaskState::Running);
+    // 他のCPUが切り替え元として保存中のタスクなら、保存が終わるまで待つ
+    while next_task.is_on_cpu() {
+        core::hint::spin_loop();
+    }
+    next_task.on_cpu_flag().store(true, Ordering::Relaxed);
+    next_task.transition(TaskState::Running);
+    let next_is_idle = next_task.sched_class() == SchedulingClass::Idle;
     let new_context_ptr = next_task.context() as *const Context;
+    let next_task_id = next_task.id().as_u64();
+    let next_page_table = next_task.page_table_phys();
+    let next_stack_top = next_task.kernel_stack_top();
+    // 切り替え先の拡張状態は、まだこのCPUのレジスタに残っていなければ#NMで復元する
+    let next_fpu_loaded = context::prepare_switch(cpu, next_task_id, next_task.extended_state());
 
-    // ===== フェーズ2: 現在のタスクの処理（CURRENT_TASKのみロック） =====
-    let old_context_ptr = {
-        let mut current = CURRENT_TASK.lock();
+    // ===== フェーズ2: 現在のタスクの処理（自CPUの現在のタスクのみロック） =====
+    let (old_context_ptr, old_on_cpu_ptr) = {
+        let mut current = sched.current.lock();
         if let Some(mut old_task) = current.take() {
-            // 蓄積された実行時間でvruntimeを更新（Normalクラスのみ有効）
-            // accumulatedが0でも最小値(1)を加算して、同じタスクが連続選択されることを防ぐ
-            let accumulated = ACCUMULATED_RUNTIME.swap(0, Ordering::Relaxed);
-            if old_task.sched_class() == SchedulingClass::Normal {
-                let delta = if accumulated > 0 { accumulated } else { 1 };
-                old_task.update_vruntime(delta);
+            // 実行時間をポリシーに反映（CFSではvruntimeを更新）
+            // ロック順序: 現在のタスク → 各キュー
+            let ran_ns = slice_runtime_ns(sched);
+            class_queue(cpu, old_task.sched_class())
+                .lock()
+                .put_prev(&mut old_task, ran_ns);
+
+            // バースト長を計測し、自発的なスリープならEWMAで予測値を更新
+            old_task.account_burst(ran_ns);
+            if old_task.state() == TaskState::Blocked {
+                old_task.finish_burst();
             }
 
             // 実行中だった場合は準備完了状態に変更
             if old_task.state() == TaskState::Running {
-                old_task.set_state(TaskState::Ready);
+                old_task.transition(TaskState::Ready);
             }
 
-            // 古いタスクのコンテキストへのポインタを取得
-            // （Box内のTaskは移動しても同じアドレスに留まる）
+            // 古いタスクのコンテキストと実行中フラグへのポインタを取得
+            // （Box内のTaskは移動しても同じアドレスに留まり、実行中フラグが下りるまで
+            // 他のCPUに実行されることも、Reaperに解放されることもない）
             let old_ctx_ptr = old_task.context_mut() as *mut Context;
+            let old_on_cpu_ptr = old_task.on_cpu_flag() as *const AtomicBool;
             let state = old_task.state();
 
             // 新しいタスクを現在のタスクに設定
             *current = Some(next_task);
-            drop(current); // CURRENT_TASKのロック解放
+            drop(current); // 現在のタスクのロック解放
 
             // ===== フェーズ3: 古いタスクを適切な場所に移動（単一キューロック） =====
             // 各キューを個別にロックすることで、ロック競合を最小化
             match state {
                 TaskState::Terminated => {
-                    // 終了したタスクは破棄
+                    // 終了したタスクはまだ自身のスタック上で動作中のため、回収待ちリストに移動
+                    TERMINATED_TASKS.lock().push(old_task);
+                    // リストに入れてから起床させる（先に起こすと、他のCPUで動いたReaperが
+                    // 空のリストを見て再びブロックし、このタスクが次の終了まで回収されない）
+                    super::reaper::wake_reaper();
                 }
                 TaskState::Blocked => {
                     // ブロック中のタスクはBLOCKED_TASKSに移動
@@ -397,7 +1034,7 @@ pub fn schedule() {
                         // BLOCKED_TASKSに追加せず、Ready状態に戻してキューに追加
                         drop(wakeup_pending);
                         drop(blocked);
-                        old_task.set_state(TaskState::Ready);
+                        old_task.transition(TaskState::Ready);
                         enqueue_task_single(old_task);
                     } else {
                         // 通常通りBLOCKED_TASKSに追加
@@ -412,27 +1049,58 @@ pub fn schedule() {
                 }
             }
 
-            old_ctx_ptr
+            (old_ctx_ptr, old_on_cpu_ptr)
         } else {
             // 現在のタスクがない場合（初回起動時）
             // 新しいタスクを現在のタスクに設定
             *current = Some(next_task);
             drop(current);
             // staticなダミーコンテキストを使用
-            // SAFETY: DUMMY_CONTEXTへの可変参照は、初回スケジュール時にのみ使用される。
-            // その後は使用されないため、競合は発生しない。
-            &raw mut DUMMY_CONTEXT as *mut Context
+            // SAFETY: DUMMY_CONTEXTへの書き込みは保存されるだけで読み出されることはないため、
+            // 複数のCPUが同時に使用しても問題ない。
+            (
+                &raw mut DUMMY_CONTEXT as *mut Context,
+                &DUMMY_ON_CPU as *const AtomicBool,
+            )
         }
     };
 
+    crate::trace::record(
+        crate::trace::TraceKind::ContextSwitch,
+        sched.current_id.load(Ordering::Relaxed),
+        next_task_id,
+    );
+    // ロックなしで参照されるタスクID・粒度・計測開始時刻を切り替え先に更新
+    sched.current_id.store(next_task_id, Ordering::Relaxed);
+    sched
+        .granularity_ns
+        .store(next_granularity, Ordering::Relaxed);
+    sched
+        .slice_start_ns
+        .store(crate::clock::monotonic_ns(), Ordering::Relaxed);
+    sched.running_idle.store(next_is_idle, Ordering::Relaxed);
+
+    // Ring 3からの割り込みは切り替え先のカーネルスタックで受ける
+    crate::gdt::set_kernel_stack(next_stack_top);
+    // 切り替え先のアドレス空間を読み込む
+    paging::switch_address_space(next_page_table);
+
     // コンテキストスイッチを実行
     // old_context_ptrに現在の状態を保存し、new_context_ptrの状態を復元
     // RFLAGSの保存・復元もswitch_context()内部で自動的に処理される
+    // コンテキストの保存後に切り替え元の実行中フラグを下ろし、他のCPUでの実行を許可する
+    // 拡張状態は切り替え元が使った場合だけ保存し、切り替え先の分は最初の使用時に復元する
     // SAFETY: old_context_ptrとnew_context_ptrは、それぞれ有効なContext構造体を指す。
     // old_context_ptrは現在実行中のタスクまたはDUMMY_CONTEXT、
     // new_context_ptrはキューから取得した次のタスクのコンテキスト。
+    // old_on_cpu_ptrは切り替え元のタスクの実行中フラグで、フラグが下りるまで解放されない。
     unsafe {
-        switch_context(old_context_ptr, new_context_ptr);
+        switch_context(
+            old_context_ptr,
+            new_context_ptr,
+            old_on_cpu_ptr,
+            next_fpu_loaded,
+        );
     }
 
     // ここに戻ってくるのは、このタスクが再度スケジュールされた時
@@ -0,0 +1,98 @@
+//! タスクのカーネルスタック
+//!
+//! 各タスクのスタックはフレームアロケータから連続したフレームとして確保し、
+//! 直接マッピング上でその最下位のページをアンマップしてガードページにします。
+//! スタックを使い切るとガードページへのアクセスでページフォルト（多くの場合はダブルフォルト）が
+//! 発生するため、隣接するメモリを黙って破壊することはありません。
+//!
+//! ガードページとタスクの対応はレジストリに記録し、例外ハンドラがどのタスクの
+//! スタックがあふれたかを特定できるようにします。
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
+use crate::frame_allocator;
+use crate::io::without_interrupts;
+use crate::paging::{self, PAGE_SIZE, PageTableFlags};
+
+use super::task::{TaskError, TaskId};
+
+/// スタックのページ数（16KB、ガードページを除く）
+const STACK_PAGES: usize = 4;
+
+/// ガードページの仮想アドレス → 所有するタスク
+static GUARD_OWNERS: Mutex<BTreeMap<u64, TaskId>> = Mutex::new(BTreeMap::new());
+
+/// ガードページ付きのタスクスタック
+///
+/// ドロップ時にガードページを直接マッピングに戻してからフレームを解放します。
+pub(super) struct TaskStack {
+    /// 確保した先頭フレーム（ガードページ）の物理アドレス
+    base_phys: u64,
+    /// ガードページの仮想アドレス（直接マッピング上）
+    guard: u64,
+}
+
+impl TaskStack {
+    /// スタックを確保してガードページを設定
+    ///
+    /// # Arguments
+    /// * `owner` - スタックを使うタスク（オーバーフロー時の表示用）
+    ///
+    /// # Errors
+    /// * `TaskError::StackAllocationFailed` - フレームの確保、またはガードページの設定に失敗した場合
+    pub(super) fn new(owner: TaskId) -> Result<Self, TaskError> {
+        let base_phys = frame_allocator::alloc_contiguous(STACK_PAGES + 1)
+            .ok_or(TaskError::StackAllocationFailed)?;
+        let guard = match paging::phys_to_virt(base_phys) {
+            Ok(guard) => guard,
+            Err(_) => {
+                let _ = frame_allocator::free_contiguous(base_phys, STACK_PAGES + 1);
+                return Err(TaskError::StackAllocationFailed);
+            }
+        };
+        if paging::unmap_page(guard).is_err() {
+            let _ = frame_allocator::free_contiguous(base_phys, STACK_PAGES + 1);
+            return Err(TaskError::StackAllocationFailed);
+        }
+        without_interrupts(|| GUARD_OWNERS.lock().insert(guard, owner));
+        Ok(Self { base_phys, guard })
+    }
+
+    /// スタックの最上位アドレスを取得（仮想アドレス）
+    pub(super) fn top(&self) -> u64 {
+        self.guard + ((STACK_PAGES + 1) * PAGE_SIZE) as u64
+    }
+}
+
+impl Drop for TaskStack {
+    fn drop(&mut self) {
+        without_interrupts(|| GUARD_OWNERS.lock().remove(&self.guard));
+        // 直接マッピングを元に戻してから返却する（他の用途では直接マッピング経由で参照される）
+        let flags = PageTableFlags::Writable as u64 | paging::no_execute_flag();
+        if let Err(e) = paging::map_page(self.guard, self.base_phys, flags) {
+            // 戻せなかったフレームは再利用させない
+            crate::error!(
+                "Failed to restore stack guard page 0x{:X}: {}",
+                self.guard,
+                e
+            );
+            let stack_phys = self.base_phys + PAGE_SIZE as u64;
+            let _ = frame_allocator::free_contiguous(stack_phys, STACK_PAGES);
+            return;
+        }
+        let _ = frame_allocator::free_contiguous(self.base_phys, STACK_PAGES + 1);
+    }
+}
+
+/// アドレスを含むガードページを持つタスク
+///
+/// 例外ハンドラから呼び出すため、レジストリのロックを取得できなければNoneを返します。
+///
+/// # Returns
+/// (タスクID, ガードページの先頭アドレス)
+pub fn guard_owner(addr: u64) -> Option<(TaskId, u64)> {
+    let guard = addr & !(PAGE_SIZE as u64 - 1);
+    let owners = GUARD_OWNERS.try_lock()?;
+    owners.get(&guard).map(|&owner| (owner, guard))
+}
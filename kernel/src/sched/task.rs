@@ -2,12 +2,14 @@
 //!
 //! このモジュールはタスクの基本的な構造体、状態、優先度を定義します。
 
-use alloc::boxed::Box;
-use core::sync::atomic::{AtomicU64, Ordering};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
-use crate::paging::KERNEL_VIRTUAL_BASE;
+use crate::paging::{self, AddressSpace};
+use crate::percpu;
 
-use super::context::Context;
+use super::context::{Context, ExtendedState};
+use super::stack::TaskStack;
 
 /// タスク操作のエラー型
 #[allow(dead_code)]
@@ -23,6 +25,10 @@ pub enum TaskError {
     ContextInitFailed,
     /// タスクキューが満杯
     QueueFull,
+    /// 指定したタスクが存在しない
+    TaskNotFound,
+    /// 終了させることができないタスク（Idle、Reaper）
+    NotKillable,
 }
 
 impl core::fmt::Display for TaskError {
@@ -39,6 +45,8 @@ impl core::fmt::Display for TaskError {
             TaskError::InvalidStackAddress => write!(f, "Invalid stack address"),
             TaskError::ContextInitFailed => write!(f, "Failed to initialize task context"),
             TaskError::QueueFull => write!(f, "Task queue is full"),
+            TaskError::TaskNotFound => write!(f, "Task not found"),
+            TaskError::NotKillable => write!(f, "Task cannot be killed"),
         }
     }
 }
@@ -59,6 +67,13 @@ impl TaskId {
     pub fn as_u64(&self) -> u64 {
         self.0
     }
+
+    /// as_u64()で取得した値からタスクIDを復元
+    ///
+    /// アトミック変数などに保存したIDを戻す用途に使用します。
+    pub fn from_u64(value: u64) -> Self {
+        TaskId(value)
+    }
 }
 
 /// Nice値の型（Linuxスタイル）
@@ -94,6 +109,24 @@ pub mod rt_priority {
     pub const MAX: u8 = 99;
 }
 
+/// CPUバースト予測の定数
+///
+/// バーストは、タスクが自発的にスリープ（ブロック）してから次にスリープするまでの実行時間です。
+/// 観測したバーストの指数加重移動平均（EWMA）を次のバースト長の予測値とし、
+/// 予測値に応じてプリエンプションの粒度を決めます。
+pub mod burst {
+    /// 新規タスクの予測バースト長（1tick相当）
+    pub const INITIAL_PREDICTION_NS: u64 = 4_000_000;
+    /// EWMAで新しい観測値に与える重み（1 / 2^EWMA_SHIFT）
+    pub const EWMA_SHIFT: u32 = 2;
+    /// 予測バーストがこれ未満のタスクを対話的とみなし、起床時に即座にプリエンプトさせる
+    pub const INTERACTIVE_THRESHOLD_NS: u64 = 2_000_000;
+    /// プリエンプション粒度の下限（1tick）
+    pub const MIN_GRANULARITY_NS: u64 = 4_000_000;
+    /// プリエンプション粒度の上限（4tick）
+    pub const MAX_GRANULARITY_NS: u64 = 16_000_000;
+}
+
 /// スケジューリングクラス
 ///
 /// タスクの優先度クラスを表します。上位クラスのキューが空になるまで、
@@ -159,32 +192,38 @@ pub enum TaskState {
     Terminated,
 }
 
-/// タスクスタック
-const STACK_SIZE: usize = 16384; // 16KB
-
-#[repr(align(16))]
-pub(super) struct TaskStack([u8; STACK_SIZE]);
+/// 状態の数（遷移表の大きさ）
+const TASK_STATE_COUNT: usize = 4;
 
-impl TaskStack {
-    pub(super) const fn new() -> Self {
-        Self([0; STACK_SIZE])
+/// 状態遷移表（`TRANSITIONS[from][to]` がtrueなら遷移可能、並びは `TaskState` の定義順）
+///
+/// 同じ状態への遷移（二重のunblockなど）と、終了したタスクからの遷移はすべて不正です。
+const TRANSITIONS: [[bool; TASK_STATE_COUNT]; TASK_STATE_COUNT] = [
+    // to: Running, Ready, Blocked, Terminated
+    [false, true, true, true], // from Running: プリエンプト、ブロック、終了
+    [true, false, false, true], // from Ready: ディスパッチ、kill
+    [false, true, false, true], // from Blocked: アンブロック、kill
+    [false, false, false, false], // from Terminated
+];
+
+impl TaskState {
+    /// `to` への遷移が許可されているか
+    pub fn can_transition_to(self, to: TaskState) -> bool {
+        TRANSITIONS[self as usize][to as usize]
     }
+}
 
-    /// スタックの最上位アドレスを取得（仮想アドレス）
-    pub(super) fn top(&self) -> u64 {
-        let base = self.0.as_ptr() as u64;
-        let physical_top = base + STACK_SIZE as u64;
-
-        // ヒープは物理アドレスで割り当てられるため、カーネル仮想アドレスに変換
-        // カーネルは KERNEL_VIRTUAL_BASE (0xFFFF800000000000) 以降で動作
-        if physical_top >= KERNEL_VIRTUAL_BASE {
-            // 既に仮想アドレス
-            physical_top
-        } else {
-            // 物理アドレスを仮想アドレスに変換
-            KERNEL_VIRTUAL_BASE + physical_top
-        }
-    }
+/// ユーザープログラムの実行情報
+///
+/// ユーザータスクはカーネルスタック上のトランポリンから開始し、
+/// この情報をもとにRing 3へ移行します。
+pub struct UserProgram {
+    /// プログラム専用のアドレス空間
+    pub address_space: AddressSpace,
+    /// エントリポイント（ユーザー空間の仮想アドレス）
+    pub entry: u64,
+    /// ユーザースタックの初期RSP
+    pub stack_pointer: u64,
 }
 
 /// タスク制御ブロック (Task Control Block)
@@ -208,13 +247,33 @@ pub struct Task {
     /// 仮想実行時間（CFS風スケジューリング、Normalクラスで使用）
     /// この値が小さいタスクが優先的に実行される
     vruntime: u64,
+    /// 直近のスリープ以降に実行した時間（ナノ秒、現在のバースト）
+    burst_ns: u64,
+    /// 次のバースト長の予測値（ナノ秒、観測値のEWMA）
+    predicted_burst_ns: u64,
+    /// 作成からの累計実行時間（ナノ秒）
+    runtime_ns: u64,
     /// CPUコンテキスト
     context: Context,
-    /// タスクの状態
+    /// 拡張状態（FPU/SSE/AVX）の保存領域（contextが保存先として指し、#NMで復元する）
+    extended_state: ExtendedState,
+    /// タスクの状態（`transition()` でのみ変更する）
     state: TaskState,
-    /// タスク専用スタック（ヒープに割り当て）
-    #[allow(dead_code)]
-    stack: Box<TaskStack>,
+    /// 状態遷移の回数
+    transitions: u64,
+    /// タスクが属するCPU（最後に実行した、または実行可能キューに入っているCPU）
+    cpu: usize,
+    /// いずれかのCPUで実行中か（切り替え後、コンテキストの保存が終わるまでtrueのまま）
+    on_cpu: AtomicBool,
+    /// タスク専用スタック（直下にガードページ付き）
+    stack: TaskStack,
+    /// アドレス空間（Noneならカーネル空間のみのカーネルのページテーブルを使用）
+    ///
+    /// 同じアドレス空間を複数のタスクで共有できるよう参照カウントで保持し、
+    /// 最後のタスクが回収されたときに解放します。
+    address_space: Option<Arc<AddressSpace>>,
+    /// ユーザープログラムの (エントリポイント, 初期RSP)（カーネルタスクはNone）
+    user_entry: Option<(u64, u64)>,
 }
 
 impl Task {
@@ -236,27 +295,38 @@ impl Task {
         nice: Nice,
         entry_point: extern "C" fn() -> !,
     ) -> Result<Self, TaskError> {
-        // スタックをヒープに割り当て
-        let stack = Box::new(TaskStack::new());
+        // ガードページ付きのスタックを割り当て
+        let id = TaskId::new();
+        let stack = TaskStack::new(id)?;
         let stack_top = stack.top();
 
-        let context = Context::new(entry_point as u64, stack_top)?;
+        let extended_state = ExtendedState::new()?;
+        let context = Context::new(entry_point as u64, stack_top, &extended_state)?;
 
         // nice値から重みを計算
         let clamped_nice = nice.clamp(nice::MIN, nice::MAX);
         let weight = nice_to_weight(clamped_nice);
 
         Ok(Self {
-            id: TaskId::new(),
+            id,
             name,
             sched_class: SchedulingClass::Normal,
             nice: clamped_nice,
             rt_priority: 0, // Normalクラスでは使用しない
             weight,
             vruntime: 0, // 初期値は0
+            burst_ns: 0,
+            predicted_burst_ns: burst::INITIAL_PREDICTION_NS,
+            runtime_ns: 0,
             context,
+            extended_state,
             state: TaskState::Ready,
+            transitions: 0,
+            cpu: percpu::current_index(),
+            on_cpu: AtomicBool::new(false),
             stack,
+            address_space: None,
+            user_entry: None,
         })
     }
 
@@ -281,24 +351,35 @@ impl Task {
             return Err(TaskError::InvalidPriority);
         }
 
-        // スタックをヒープに割り当て
-        let stack = Box::new(TaskStack::new());
+        // ガードページ付きのスタックを割り当て
+        let id = TaskId::new();
+        let stack = TaskStack::new(id)?;
         let stack_top = stack.top();
 
-        let context = Context::new(entry_point as u64, stack_top)?;
+        let extended_state = ExtendedState::new()?;
+        let context = Context::new(entry_point as u64, stack_top, &extended_state)?;
 
         // Realtimeクラスではweightとvruntimeは使用しない
         Ok(Self {
-            id: TaskId::new(),
+            id,
             name,
             sched_class: SchedulingClass::Realtime,
             nice: 0, // Realtimeクラスでは使用しない
             rt_priority: rt_priority.min(rt_priority::MAX),
             weight: 0,   // Realtimeクラスでは使用しない
             vruntime: 0, // Realtimeクラスでは使用しない
+            burst_ns: 0,
+            predicted_burst_ns: burst::INITIAL_PREDICTION_NS,
+            runtime_ns: 0,
             context,
+            extended_state,
             state: TaskState::Ready,
+            transitions: 0,
+            cpu: percpu::current_index(),
+            on_cpu: AtomicBool::new(false),
             stack,
+            address_space: None,
+            user_entry: None,
         })
     }
 
@@ -315,26 +396,59 @@ impl Task {
         name: &'static str,
         entry_point: extern "C" fn() -> !,
     ) -> Result<Self, TaskError> {
-        // スタックをヒープに割り当て
-        let stack = Box::new(TaskStack::new());
+        // ガードページ付きのスタックを割り当て
+        let id = TaskId::new();
+        let stack = TaskStack::new(id)?;
         let stack_top = stack.top();
 
-        let context = Context::new(entry_point as u64, stack_top)?;
+        let extended_state = ExtendedState::new()?;
+        let context = Context::new(entry_point as u64, stack_top, &extended_state)?;
 
         Ok(Self {
-            id: TaskId::new(),
+            id,
             name,
             sched_class: SchedulingClass::Idle,
             nice: nice::MAX, // Idleは最低優先度相当
             rt_priority: 0,
             weight: nice_to_weight(nice::MAX), // 参考値
             vruntime: 0,
+            burst_ns: 0,
+            predicted_burst_ns: burst::INITIAL_PREDICTION_NS,
+            runtime_ns: 0,
             context,
+            extended_state,
             state: TaskState::Ready,
+            transitions: 0,
+            cpu: percpu::current_index(),
+            on_cpu: AtomicBool::new(false),
             stack,
+            address_space: None,
+            user_entry: None,
         })
     }
 
+    /// ユーザープログラムを実行するNormalクラスのタスクを作成
+    ///
+    /// # Arguments
+    /// * `name` - タスク名
+    /// * `nice` - Nice値（-20〜+19、小さいほど高優先度）
+    /// * `program` - 実行するユーザープログラム
+    /// * `entry_point` - Ring 3へ移行するカーネル側のトランポリン
+    ///
+    /// # Errors
+    /// `Task::new` と同じ
+    pub fn new_user(
+        name: &'static str,
+        nice: Nice,
+        program: UserProgram,
+        entry_point: extern "C" fn() -> !,
+    ) -> Result<Self, TaskError> {
+        let mut task = Self::new(name, nice, entry_point)?;
+        task.address_space = Some(Arc::new(program.address_space));
+        task.user_entry = Some((program.entry, program.stack_pointer));
+        Ok(task)
+    }
+
     /// タスクIDを取得
     pub fn id(&self) -> TaskId {
         self.id
@@ -389,14 +503,162 @@ impl Task {
         self.vruntime = self.vruntime.saturating_add(increment);
     }
 
+    /// 現在のバーストと累計の実行時間に加算
+    ///
+    /// # Arguments
+    /// * `delta` - 実際の実行時間（ナノ秒単位）
+    pub fn account_burst(&mut self, delta: u64) {
+        self.burst_ns = self.burst_ns.saturating_add(delta);
+        self.runtime_ns = self.runtime_ns.saturating_add(delta);
+    }
+
+    /// 自発的なスリープでバーストが終了したことを記録し、予測値を更新
+    ///
+    /// predicted = predicted - predicted / 2^EWMA_SHIFT + burst / 2^EWMA_SHIFT
+    pub fn finish_burst(&mut self) {
+        let predicted = self.predicted_burst_ns;
+        self.predicted_burst_ns =
+            predicted - (predicted >> burst::EWMA_SHIFT) + (self.burst_ns >> burst::EWMA_SHIFT);
+        self.burst_ns = 0;
+    }
+
+    /// 現在のバーストの実行時間を取得（ナノ秒）
+    pub fn burst_ns(&self) -> u64 {
+        self.burst_ns
+    }
+
+    /// 作成からの累計実行時間を取得（ナノ秒）
+    pub fn runtime_ns(&self) -> u64 {
+        self.runtime_ns
+    }
+
+    /// 次のバースト長の予測値を取得（ナノ秒）
+    pub fn predicted_burst_ns(&self) -> u64 {
+        self.predicted_burst_ns
+    }
+
+    /// 対話的なタスク（予測バーストが短いNormalクラスのタスク）かどうか
+    pub fn is_interactive(&self) -> bool {
+        self.sched_class == SchedulingClass::Normal
+            && self.predicted_burst_ns < burst::INTERACTIVE_THRESHOLD_NS
+    }
+
+    /// プリエンプションの粒度を取得（ナノ秒）
+    ///
+    /// Normalクラスでは予測バースト長を粒度とし、短いバーストのタスクは1tickで、
+    /// バッチ的なタスクは最大4tickまで連続して実行させます。
+    /// RealtimeとIdleクラスは従来どおり毎tickスケジューリングします。
+    pub fn preempt_granularity_ns(&self) -> u64 {
+        match self.sched_class {
+            SchedulingClass::Normal => self
+                .predicted_burst_ns
+                .clamp(burst::MIN_GRANULARITY_NS, burst::MAX_GRANULARITY_NS),
+            SchedulingClass::Realtime | SchedulingClass::Idle => burst::MIN_GRANULARITY_NS,
+        }
+    }
+
     /// タスクの状態を取得
     pub fn state(&self) -> TaskState {
         self.state
     }
 
-    /// タスクの状態を設定
-    pub fn set_state(&mut self, state: TaskState) {
-        self.state = state;
+    /// タスクの状態を遷移させる
+    ///
+    /// 遷移表で許可されていない遷移は呼び出し元と両方の状態を表示し、デバッグビルドでは
+    /// パニックします。リリースビルドではエラーを記録し、状態を変更せずにfalseを返します。
+    ///
+    /// # Returns
+    /// 遷移した場合はtrue
+    #[track_caller]
+    pub fn transition(&mut self, to: TaskState) -> bool {
+        let from = self.state;
+        if !from.can_transition_to(to) {
+            let caller = core::panic::Location::caller();
+            if cfg!(debug_assertions) {
+                panic!(
+                    "Illegal task state transition: task {} ({}) {:?} -> {:?} at {}",
+                    self.id.as_u64(),
+                    self.name,
+                    from,
+                    to,
+                    caller
+                );
+            }
+            crate::error!(
+                "Illegal task state transition: task {} ({}) {:?} -> {:?} at {}",
+                self.id.as_u64(),
+                self.name,
+                from,
+                to,
+                caller
+            );
+            return false;
+        }
+        self.state = to;
+        self.transitions += 1;
+        true
+    }
+
+    /// 作成されてからの状態遷移の回数
+    pub fn transition_count(&self) -> u64 {
+        self.transitions
+    }
+
+    /// タスクが属するCPU
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// タスクが属するCPUを変更（移動先のキューに入れる前に呼ぶ）
+    pub fn set_cpu(&mut self, cpu: usize) {
+        self.cpu = cpu;
+    }
+
+    /// いずれかのCPUで実行中か
+    ///
+    /// 実行を終えたCPUがコンテキストを保存し終えるまでtrueです。
+    pub fn is_on_cpu(&self) -> bool {
+        self.on_cpu.load(Ordering::Acquire)
+    }
+
+    /// 実行中フラグ（スケジューラがコンテキストスイッチで更新する）
+    pub(super) fn on_cpu_flag(&self) -> &AtomicBool {
+        &self.on_cpu
+    }
+
+    /// 実行時にCR3へ読み込むPML4の物理アドレス
+    ///
+    /// カーネルタスクはカーネルのページテーブルを使用します。
+    pub fn page_table_phys(&self) -> u64 {
+        self.address_space
+            .as_ref()
+            .map_or_else(paging::kernel_pml4_phys, |space| space.pml4_phys())
+    }
+
+    /// タスクのアドレス空間（カーネルタスクはNone）
+    #[allow(dead_code)]
+    pub fn address_space(&self) -> Option<&Arc<AddressSpace>> {
+        self.address_space.as_ref()
+    }
+
+    /// カーネルスタックの最上位アドレス（Ring 3からの割り込み用）
+    pub fn kernel_stack_top(&self) -> u64 {
+        self.stack.top()
+    }
+
+    /// ユーザープログラムの (エントリポイント, 初期RSP)
+    pub fn user_entry(&self) -> Option<(u64, u64)> {
+        self.user_entry
+    }
+
+    /// 拡張状態の保存領域（スケジューラが#NMでの復元先として登録する）
+    pub(super) fn extended_state(&self) -> &ExtendedState {
+        &self.extended_state
+    }
+
+    /// FPU/SIMD命令を使ったことがあるか
+    pub fn uses_fpu(&self) -> bool {
+        self.extended_state.is_used()
     }
 
     /// コンテキストへの参照を取得
@@ -1,9 +1,12 @@
 // シリアルポート（COM1）ドライバ
-use crate::io::{port_read_u8, port_write_u8};
+use crate::io::{port_read_u8, port_write_u8, without_interrupts};
+use crate::irq::IrqReturn;
+use crate::sched::TaskId;
+use alloc::string::String;
 use core::fmt;
+use spin::Mutex;
 
-#[allow(dead_code)]
-const COM1: u16 = 0x3F8;
+pub const COM1: u16 = 0x3F8;
 
 pub struct SerialPort {
     base: u16,
@@ -45,6 +48,15 @@ impl SerialPort {
         }
     }
 
+    // 送信FIFOとシフトレジスタが空になるまで待つ
+    pub fn flush(&self) {
+        unsafe {
+            while (port_read_u8(self.base + 5) & 0x40) == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
     // 文字列送信
     pub fn write_str(&self, s: &str) {
         for byte in s.bytes() {
@@ -67,10 +79,165 @@ pub fn init() {
     SerialPort::new(COM1).init();
 }
 
+// 送信中のログがすべて出力されるまで待つ（パニック・再起動前など）
+pub fn flush() {
+    SerialPort::new(COM1).flush();
+}
+
+// =============================================================================
+// 受信（割り込み駆動）
+// =============================================================================
+
+// COM1受信割り込みのベクタ番号
+pub const RX_INTERRUPT_VECTOR: u8 = 36;
+
+// COM1のISA IRQ番号
+const COM1_IRQ: u8 = 4;
+
+// 受信リングバッファのサイズ
+const RX_BUFFER_SIZE: usize = 256;
+
+// 受信リングバッファ
+struct RxBuffer {
+    buffer: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    // 受信待ちでブロックしているタスク
+    waiter: Option<TaskId>,
+}
+
+static RX_BUFFER: Mutex<RxBuffer> = Mutex::new(RxBuffer {
+    buffer: [0; RX_BUFFER_SIZE],
+    head: 0,
+    len: 0,
+    waiter: None,
+});
+
+impl RxBuffer {
+    // 1バイト追加（満杯の場合は破棄）
+    fn push(&mut self, byte: u8) {
+        if self.len < RX_BUFFER_SIZE {
+            let tail = (self.head + self.len) % RX_BUFFER_SIZE;
+            self.buffer[tail] = byte;
+            self.len += 1;
+        }
+    }
+
+    // 1バイト取り出し
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+// 受信割り込みを有効化してIRQ4をI/O APICでルーティング（I/O APIC初期化後に呼ぶ）
+pub fn init_rx() {
+    unsafe {
+        // 受信データあり割り込みのみ有効化（送信はポーリングのまま）
+        port_write_u8(COM1 + 1, 0x01);
+        // OUT2をセット（割り込み信号を出力するために必要）、DTR/RTSも維持
+        port_write_u8(COM1 + 4, 0x0B);
+        // 溜まっているデータを読み捨てる
+        while (port_read_u8(COM1 + 5) & 0x01) != 0 {
+            port_read_u8(COM1);
+        }
+    }
+
+    if let Err(e) = crate::irq::request_irq(RX_INTERRUPT_VECTOR, handle_interrupt, "serial") {
+        crate::warn!("Serial RX interrupt handler not registered: {}", e);
+        return;
+    }
+    match crate::ioapic::route_isa_irq(COM1_IRQ, RX_INTERRUPT_VECTOR) {
+        Ok(gsi) => crate::info!("Serial RX interrupt enabled (GSI {})", gsi),
+        Err(e) => crate::warn!("Serial RX interrupt not routed: {}", e),
+    }
+}
+
+// 受信割り込みハンドラ（irqから呼ばれる）
+fn handle_interrupt() -> IrqReturn {
+    let waiter = {
+        let mut rx = RX_BUFFER.lock();
+        // FIFOに溜まっている分をすべて読み出す
+        unsafe {
+            while (port_read_u8(COM1 + 5) & 0x01) != 0 {
+                rx.push(port_read_u8(COM1));
+            }
+        }
+        rx.waiter.take()
+    };
+
+    // ロック解放後に待機中のタスクを起床
+    if let Some(task_id) = waiter {
+        crate::sched::unblock_task(task_id);
+    }
+    IrqReturn::Handled
+}
+
+// 受信済みの1バイトを取り出す（ノンブロッキング）
+#[allow(dead_code)]
+pub fn try_read_byte() -> Option<u8> {
+    without_interrupts(|| RX_BUFFER.lock().pop())
+}
+
+// 1バイト受信するまでブロック
+pub fn read_byte() -> u8 {
+    let me = crate::sched::current_task_id();
+    loop {
+        let byte = without_interrupts(|| {
+            let mut rx = RX_BUFFER.lock();
+            let byte = rx.pop();
+            if byte.is_none() {
+                rx.waiter = Some(me);
+            }
+            byte
+        });
+        if let Some(byte) = byte {
+            return byte;
+        }
+        // 登録とブロックの間に受信した場合は、WAKEUP_PENDINGによりブロックせずに戻る
+        crate::sched::block_current_task();
+    }
+}
+
+// 1行受信するまでブロック（エコーバックとバックスペース処理を行う）
+//
+// 改行（CRまたはLF）は戻り値に含まない。ASCII以外のバイトは無視する
+#[allow(dead_code)]
+pub fn read_line() -> String {
+    let port = SerialPort::new(COM1);
+    let mut line = String::new();
+    loop {
+        match read_byte() {
+            b'\r' | b'\n' => {
+                port.write_str("\r\n");
+                return line;
+            }
+            // Backspace / DEL（行が空なら何もしない）
+            0x08 | 0x7F if line.pop().is_some() => {
+                port.write_str("\x08 \x08");
+            }
+            byte if byte.is_ascii_graphic() || byte == b' ' => {
+                line.push(byte as char);
+                port.write_byte(byte);
+            }
+            _ => {}
+        }
+    }
+}
+
 // print系マクロの内部実装
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use fmt::Write;
+    // 対話中のシェルのコマンド出力はページ送りする
+    if crate::shell::pager::write(args) {
+        return;
+    }
     let mut serial = SerialPort::new(COM1);
     let _ = serial.write_fmt(args);
 }
@@ -88,38 +255,6 @@ macro_rules! print {
 macro_rules! println {
     () => ($crate::print!("\n"));
     ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        let mut serial = $crate::serial::SerialPort::new(0x3F8);
-        let _ = writeln!(serial, $($arg)*);
-    }};
-}
-
-// info!マクロ（白色表示）
-#[macro_export]
-macro_rules! info {
-    ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        let mut serial = $crate::serial::SerialPort::new(0x3F8);
-        let _ = writeln!(serial, "[INFO] {}", format_args!($($arg)*));
-    }};
-}
-
-// warn!マクロ（黄色表示）
-#[macro_export]
-macro_rules! warn {
-    ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        let mut serial = $crate::serial::SerialPort::new(0x3F8);
-        let _ = writeln!(serial, "\x1b[33m[WARN]\x1b[0m {}", format_args!($($arg)*));
-    }};
-}
-
-// error!マクロ（赤色表示）
-#[macro_export]
-macro_rules! error {
-    ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        let mut serial = $crate::serial::SerialPort::new(0x3F8);
-        let _ = writeln!(serial, "\x1b[31m[ERROR]\x1b[0m {}", format_args!($($arg)*));
+        $crate::serial::_print(format_args!("{}\n", format_args!($($arg)*)));
     }};
 }
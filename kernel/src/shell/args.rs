@@ -0,0 +1,292 @@
+//! コマンド引数の仕様と解析
+//!
+//! 各コマンドは引数の仕様（`ArgSpec`）とサブコマンド（`SubcommandSpec`）を登録し、
+//! ディスパッチャがそれに従って入力を検証・変換してからハンドラに `Args` を渡します。
+//! 使い方の表示（`Usage:` 行と `help <cmd>`）も同じ仕様から生成します。
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// 引数の種類
+#[allow(dead_code)]
+pub enum ArgKind {
+    /// 任意の文字列（パスなど）
+    Word,
+    /// 数値（10進数、または0x付きの16進数）
+    Number,
+    /// 数値、または列挙したキーワードのいずれか
+    NumberOr(&'static [&'static str]),
+    /// 列挙したキーワードのいずれか
+    Keyword(&'static [&'static str]),
+    /// 残りの語すべて（最後の引数にのみ使用）
+    Rest,
+}
+
+/// 引数1つの仕様
+pub struct ArgSpec {
+    /// 引数名（`Args` から値を取り出すキー）
+    pub name: &'static str,
+    /// 種類
+    pub kind: ArgKind,
+    /// 省略可能か（省略可能な引数の後に必須の引数は置けない）
+    pub optional: bool,
+    /// 1行の説明
+    pub help: &'static str,
+}
+
+impl ArgSpec {
+    /// 必須の引数
+    pub const fn required(name: &'static str, kind: ArgKind, help: &'static str) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+            help,
+        }
+    }
+
+    /// 省略可能な引数
+    pub const fn optional(name: &'static str, kind: ArgKind, help: &'static str) -> Self {
+        Self {
+            name,
+            kind,
+            optional: true,
+            help,
+        }
+    }
+
+    /// 書式（`<name>`、`on|off` など。省略可能なら `[...]` で囲む）
+    fn format(&self, out: &mut String) {
+        let mut text = String::new();
+        match self.kind {
+            ArgKind::Word | ArgKind::Number => {
+                let _ = write!(text, "<{}>", self.name);
+            }
+            ArgKind::NumberOr(keywords) => {
+                let _ = write!(text, "<{}>|{}", self.name, keywords.join("|"));
+            }
+            ArgKind::Keyword(keywords) => text.push_str(&keywords.join("|")),
+            ArgKind::Rest => {
+                let _ = write!(text, "<{}...>", self.name);
+            }
+        }
+        if self.optional {
+            let _ = write!(out, " [{}]", text);
+        } else {
+            let _ = write!(out, " {}", text);
+        }
+    }
+}
+
+/// サブコマンドの仕様
+pub struct SubcommandSpec {
+    /// サブコマンド名（コマンド名の直後の語）
+    pub name: &'static str,
+    /// サブコマンドの引数
+    pub args: &'static [ArgSpec],
+    /// 1行の説明
+    pub help: &'static str,
+}
+
+/// 解析済みの引数の値
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgValue<'a> {
+    /// 数値
+    Number(u64),
+    /// 文字列（キーワードを含む）
+    Word(&'a str),
+    /// 残りの語
+    Words(Vec<&'a str>),
+}
+
+/// 解析済みの引数
+pub struct Args<'a> {
+    /// 選ばれたサブコマンド
+    subcommand: Option<&'static str>,
+    /// 引数名と値（省略された引数は含まない）
+    values: Vec<(&'static str, ArgValue<'a>)>,
+}
+
+impl<'a> Args<'a> {
+    fn get(&self, name: &str) -> Option<&ArgValue<'a>> {
+        self.values.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// 選ばれたサブコマンド（サブコマンドなしの形式ならNone）
+    pub fn subcommand(&self) -> Option<&'static str> {
+        self.subcommand
+    }
+
+    /// 数値の引数
+    ///
+    /// # Returns
+    /// 省略された場合、またはキーワードが指定された場合（`NumberOr`）はNone
+    pub fn number(&self, name: &str) -> Option<u64> {
+        match self.get(name)? {
+            ArgValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// 文字列の引数（キーワードを含む）
+    ///
+    /// # Returns
+    /// 省略された場合、または数値が指定された場合（`Number`、`NumberOr`）はNone
+    pub fn word(&self, name: &str) -> Option<&'a str> {
+        match self.get(name)? {
+            ArgValue::Word(w) => Some(w),
+            _ => None,
+        }
+    }
+
+    /// 残りの語（`Rest`）。省略された場合は空
+    pub fn rest(&self, name: &str) -> &[&'a str] {
+        match self.get(name) {
+            Some(ArgValue::Words(words)) => words,
+            _ => &[],
+        }
+    }
+
+    /// 引数が指定されたか
+    pub fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+}
+
+/// 引数の解析エラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgError<'a> {
+    /// 必須の引数がない
+    Missing(&'static str),
+    /// 余分な引数がある
+    Unexpected(&'a str),
+    /// 数値として解釈できない
+    InvalidNumber { name: &'static str, value: &'a str },
+    /// 列挙したキーワードのいずれでもない
+    InvalidKeyword { name: &'static str, value: &'a str },
+}
+
+impl core::fmt::Display for ArgError<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ArgError::Missing(name) => write!(f, "Missing argument <{}>", name),
+            ArgError::Unexpected(value) => write!(f, "Unexpected argument '{}'", value),
+            ArgError::InvalidNumber { name, value } => {
+                write!(f, "<{}>: '{}' is not a number", name, value)
+            }
+            ArgError::InvalidKeyword { name, value } => {
+                write!(f, "<{}>: '{}' is not allowed here", name, value)
+            }
+        }
+    }
+}
+
+/// 数値を解析（10進数、または0x付きの16進数）
+pub fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// 語の列を仕様に従って解析
+///
+/// 最初の語がサブコマンド名に一致すればそのサブコマンドの仕様で、
+/// 一致しなければ `args` の仕様で残りを解析します。
+///
+/// # Errors
+/// 引数の不足・過剰、または値が種類に合わない場合
+pub fn parse<'a>(
+    words: &[&'a str],
+    args: &'static [ArgSpec],
+    subcommands: &'static [SubcommandSpec],
+) -> Result<Args<'a>, ArgError<'a>> {
+    let (subcommand, specs, words) = match words.split_first() {
+        Some((first, rest)) => match subcommands.iter().find(|s| s.name == *first) {
+            Some(sub) => (Some(sub.name), sub.args, rest),
+            None => (None, args, words),
+        },
+        None => (None, args, words),
+    };
+
+    let mut values = Vec::with_capacity(specs.len());
+    let mut remaining = words.iter();
+    for spec in specs {
+        if let ArgKind::Rest = spec.kind {
+            let rest: Vec<&str> = remaining.by_ref().copied().collect();
+            if !rest.is_empty() {
+                values.push((spec.name, ArgValue::Words(rest)));
+            } else if !spec.optional {
+                return Err(ArgError::Missing(spec.name));
+            }
+            continue;
+        }
+
+        let Some(&word) = remaining.next() else {
+            if spec.optional {
+                continue;
+            }
+            return Err(ArgError::Missing(spec.name));
+        };
+        let value = match spec.kind {
+            ArgKind::Word => ArgValue::Word(word),
+            ArgKind::Number => match parse_number(word) {
+                Some(n) => ArgValue::Number(n),
+                None => {
+                    return Err(ArgError::InvalidNumber {
+                        name: spec.name,
+                        value: word,
+                    });
+                }
+            },
+            ArgKind::NumberOr(keywords) => match parse_number(word) {
+                Some(n) => ArgValue::Number(n),
+                None if keywords.contains(&word) => ArgValue::Word(word),
+                None => {
+                    return Err(ArgError::InvalidNumber {
+                        name: spec.name,
+                        value: word,
+                    });
+                }
+            },
+            ArgKind::Keyword(keywords) => {
+                if !keywords.contains(&word) {
+                    return Err(ArgError::InvalidKeyword {
+                        name: spec.name,
+                        value: word,
+                    });
+                }
+                ArgValue::Word(word)
+            }
+            ArgKind::Rest => unreachable!(),
+        };
+        values.push((spec.name, value));
+    }
+
+    if let Some(extra) = remaining.next() {
+        return Err(ArgError::Unexpected(extra));
+    }
+    Ok(Args { subcommand, values })
+}
+
+/// 使い方の各形式（`name <arg> [opt]` など）
+///
+/// サブコマンドなしの形式に続けて、サブコマンドごとの形式を返します。
+pub fn synopses(
+    name: &str,
+    args: &'static [ArgSpec],
+    subcommands: &'static [SubcommandSpec],
+) -> Vec<String> {
+    let mut forms = Vec::with_capacity(1 + subcommands.len());
+    let mut form = String::from(name);
+    args.iter().for_each(|arg| arg.format(&mut form));
+    forms.push(form);
+    for sub in subcommands {
+        let mut form = String::from(name);
+        let _ = write!(form, " {}", sub.name);
+        sub.args.iter().for_each(|arg| arg.format(&mut form));
+        forms.push(form);
+    }
+    forms
+}
@@ -0,0 +1,1953 @@
+//! カーネルシェル（デバッグコンソール）
+//!
+//! シリアルコンソールから1行ずつコマンドを読み取り、結果をシリアルに出力します。
+//! カーネルを再ビルドせずに状態の確認や実験を行うためのものです。
+//!
+//! 各コマンドは名前・説明・引数の仕様をコマンド表（`COMMANDS`）に登録します。
+//! ディスパッチャは仕様に従って引数を検証・変換してからハンドラを呼び出し、
+//! `help <cmd>` と使い方の表示も同じ仕様から生成します。
+//!
+//! 対話的に実行したコマンドの出力は1画面ごとにページ送りします（`pager` モジュール）。
+
+mod args;
+pub mod pager;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::block::{self, ramdisk};
+use crate::fs::{self, FileType, mkfs_fat};
+use crate::graphics::compositor::{self, PacingSource};
+use crate::graphics::theme;
+use crate::graphics::window::WindowId;
+use crate::graphics::{color, font, simd};
+use crate::log::{self, Level};
+use crate::sched::{self, TaskId};
+use crate::sync::lockstat;
+use crate::trace::TraceKind;
+use crate::{
+    allocator, apic, clock, cmdline, config, datetime, dmesg_view, emergency, exctest,
+    fault_inject, frame_allocator, heap_quota, idt, iotrace, irq, klog, ktest, mce, membench,
+    metrics, paging, pci, power, print, println, serial, smp, timer, trace, watch, worker_pool,
+    workqueue, zram,
+};
+
+use args::{ArgKind, ArgSpec, Args, SubcommandSpec};
+
+/// プロンプト文字列
+const PROMPT: &str = "vitrOS> ";
+
+/// ログレベルの指定に使うキーワード
+const LOG_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+/// コマンドハンドラ（引数は仕様に従って検証済み）
+type CommandHandler = fn(&Args);
+
+/// シェルコマンドの定義
+struct Command {
+    /// コマンド名
+    name: &'static str,
+    /// 1行の説明
+    summary: &'static str,
+    /// サブコマンドなしの形式の引数
+    args: &'static [ArgSpec],
+    /// サブコマンド
+    subcommands: &'static [SubcommandSpec],
+    /// ハンドラ
+    handler: CommandHandler,
+}
+
+/// 引数なしのコマンド・サブコマンド用
+const NO_ARGS: &[ArgSpec] = &[];
+
+/// サブコマンドなしのコマンド用
+const NO_SUBCOMMANDS: &[SubcommandSpec] = &[];
+
+/// パスを1つ取るコマンドの引数
+const PATH_ARG: &[ArgSpec] = &[ArgSpec::required(
+    "path",
+    ArgKind::Word,
+    "File or directory path",
+)];
+
+/// コマンド一覧
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        summary: "List commands or describe one",
+        args: &[ArgSpec::optional(
+            "command",
+            ArgKind::Word,
+            "Command to describe",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_help,
+    },
+    Command {
+        name: "pager",
+        summary: "Show or set paging of long command output",
+        args: &[ArgSpec::optional(
+            "state",
+            ArgKind::Keyword(&["on", "off"]),
+            "Pause after each screenful (off: raw output for scripts)",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_pager,
+    },
+    Command {
+        name: "ps",
+        summary: "List tasks",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_ps,
+    },
+    Command {
+        name: "schedtop",
+        summary: "Show predicted CPU bursts and preemption granularity",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_schedtop,
+    },
+    Command {
+        name: "schedstat",
+        summary: "Show task placement heuristics and migrations per CPU",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_schedstat,
+    },
+    Command {
+        name: "trace",
+        summary: "Show recent trace events and task markers as a timeline",
+        args: &[ArgSpec::optional(
+            "count",
+            ArgKind::Number,
+            "Number of events (default 32)",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_trace,
+    },
+    Command {
+        name: "lockstat",
+        summary: "Show the most contended locks",
+        args: &[ArgSpec::optional(
+            "action",
+            ArgKind::Keyword(&["on", "off", "reset"]),
+            "Start, stop or clear profiling",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_lockstat,
+    },
+    Command {
+        name: "kill",
+        summary: "Terminate a task",
+        args: &[ArgSpec::required(
+            "task_id",
+            ArgKind::Number,
+            "Task to terminate (see ps)",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_kill,
+    },
+    Command {
+        name: "mem",
+        summary: "Show memory usage",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_mem,
+    },
+    Command {
+        name: "heap",
+        summary: "Show per-task heap usage or set a task's heap quota",
+        args: NO_ARGS,
+        subcommands: &[SubcommandSpec {
+            name: "quota",
+            args: &[
+                ArgSpec::required("task_id", ArgKind::Number, "Task to limit"),
+                ArgSpec::required(
+                    "KB",
+                    ArgKind::NumberOr(&["off"]),
+                    "Quota in KB, or off to remove it",
+                ),
+            ],
+            help: "Set or remove a task's heap quota",
+        }],
+        handler: cmd_heap,
+    },
+    Command {
+        name: "uptime",
+        summary: "Show time since boot",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_uptime,
+    },
+    Command {
+        name: "cmdline",
+        summary: "Show the kernel command line passed by the bootloader",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_cmdline,
+    },
+    Command {
+        name: "date",
+        summary: "Show the wall-clock date and time (UTC)",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_date,
+    },
+    Command {
+        name: "dmesg",
+        summary: "Show recent kernel log messages",
+        args: &[ArgSpec::optional(
+            "level",
+            ArgKind::Keyword(LOG_LEVELS),
+            "Only show messages at or above this level",
+        )],
+        subcommands: &[
+            SubcommandSpec {
+                name: "clear",
+                args: NO_ARGS,
+                help: "Discard the buffered messages",
+            },
+            SubcommandSpec {
+                name: "view",
+                args: &[ArgSpec::optional(
+                    "level",
+                    ArgKind::Keyword(LOG_LEVELS),
+                    "Only show messages at or above this level",
+                )],
+                help: "Follow the log in a scrolling console window",
+            },
+            SubcommandSpec {
+                name: "scroll",
+                args: &[
+                    ArgSpec::required(
+                        "direction",
+                        ArgKind::Keyword(&["up", "down"]),
+                        "Towards older (up) or newer (down) messages",
+                    ),
+                    ArgSpec::optional("lines", ArgKind::Number, "Lines to scroll (default: 10)"),
+                ],
+                help: "Scroll the console window through its scrollback",
+            },
+            SubcommandSpec {
+                name: "hide",
+                args: NO_ARGS,
+                help: "Close the console window",
+            },
+        ],
+        handler: cmd_dmesg,
+    },
+    Command {
+        name: "font",
+        summary: "List, load and select bitmap fonts for the console",
+        args: NO_ARGS,
+        subcommands: &[
+            SubcommandSpec {
+                name: "load",
+                args: &[
+                    ArgSpec::required("path", ArgKind::Word, "PSF1/PSF2 font file"),
+                    ArgSpec::optional(
+                        "name",
+                        ArgKind::Word,
+                        "Name to register the font as (default: file name)",
+                    ),
+                ],
+                help: "Load a PSF font (e.g. from the initrd)",
+            },
+            SubcommandSpec {
+                name: "use",
+                args: &[ArgSpec::required(
+                    "name",
+                    ArgKind::Word,
+                    "Loaded font name, or ascii-8x8 for the built-in font",
+                )],
+                help: "Use a font for console windows opened from now on",
+            },
+        ],
+        handler: cmd_font,
+    },
+    Command {
+        name: "log",
+        summary: "Show or change kernel log levels, filters and sinks",
+        args: NO_ARGS,
+        subcommands: &[
+            SubcommandSpec {
+                name: "level",
+                args: &[ArgSpec::required(
+                    "level",
+                    ArgKind::Keyword(LOG_LEVELS),
+                    "Default level",
+                )],
+                help: "Set the default level",
+            },
+            SubcommandSpec {
+                name: "filter",
+                args: &[
+                    ArgSpec::required("module", ArgKind::Word, "Module path (e.g. block)"),
+                    ArgSpec::required(
+                        "level",
+                        ArgKind::Keyword(LOG_LEVELS),
+                        "Level for the module and its submodules",
+                    ),
+                ],
+                help: "Set a per-module level",
+            },
+            SubcommandSpec {
+                name: "unfilter",
+                args: &[ArgSpec::required("module", ArgKind::Word, "Module path")],
+                help: "Remove a per-module level",
+            },
+            SubcommandSpec {
+                name: "sink",
+                args: &[
+                    ArgSpec::required("sink", ArgKind::Word, "Sink name (serial, console)"),
+                    ArgSpec::required(
+                        "level",
+                        ArgKind::Keyword(LOG_LEVELS),
+                        "Minimum level written to the sink",
+                    ),
+                ],
+                help: "Set a sink's minimum level",
+            },
+        ],
+        handler: cmd_log,
+    },
+    Command {
+        name: "watch",
+        summary: "Periodically show kernel metrics in an overlay window",
+        args: &[
+            ArgSpec::optional("ms", ArgKind::Number, "Sampling interval"),
+            ArgSpec::optional(
+                "expr",
+                ArgKind::Rest,
+                "Metrics as name[:arg][/s] (see watch list)",
+            ),
+        ],
+        subcommands: &[
+            SubcommandSpec {
+                name: "stop",
+                args: NO_ARGS,
+                help: "Close the watch window",
+            },
+            SubcommandSpec {
+                name: "list",
+                args: NO_ARGS,
+                help: "List available metrics",
+            },
+        ],
+        handler: cmd_watch,
+    },
+    Command {
+        name: "cpus",
+        summary: "List processors found in the MADT",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_cpus,
+    },
+    Command {
+        name: "timers",
+        summary: "Show timer queue state and per-class callback latency",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_timers,
+    },
+    Command {
+        name: "pci",
+        summary: "List PCI devices",
+        args: &[ArgSpec::optional(
+            "verbose",
+            ArgKind::Keyword(&["-v"]),
+            "Also show BARs and capabilities",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_pci,
+    },
+    Command {
+        name: "workers",
+        summary: "Show or resize the worker pool and show the work queue",
+        args: &[ArgSpec::optional(
+            "count",
+            ArgKind::Number,
+            "New number of pool workers",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_workers,
+    },
+    Command {
+        name: "compconf",
+        summary: "Show or change compositor pacing",
+        args: NO_ARGS,
+        subcommands: &[
+            SubcommandSpec {
+                name: "fps",
+                args: &[ArgSpec::required("n", ArgKind::Number, "Frames per second")],
+                help: "Set the target frame rate",
+            },
+            SubcommandSpec {
+                name: "pacing",
+                args: &[ArgSpec::required(
+                    "source",
+                    ArgKind::Keyword(&["sleep", "deadline", "damage", "vsync"]),
+                    "What paces frames",
+                )],
+                help: "Set the frame pacing source",
+            },
+            SubcommandSpec {
+                name: "skip",
+                args: &[ArgSpec::required(
+                    "n",
+                    ArgKind::Number,
+                    "Frames that may be skipped in a row",
+                )],
+                help: "Set the maximum frame skip",
+            },
+            SubcommandSpec {
+                name: "stats",
+                args: &[ArgSpec::optional(
+                    "reset",
+                    ArgKind::Keyword(&["reset"]),
+                    "Clear the statistics",
+                )],
+                help: "Show frame time statistics",
+            },
+        ],
+        handler: cmd_compconf,
+    },
+    Command {
+        name: "config",
+        summary: "Show or change kernel settings",
+        args: &[
+            ArgSpec::optional("key", ArgKind::Word, "Setting to show or change"),
+            ArgSpec::optional("value", ArgKind::Word, "New value"),
+        ],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_config,
+    },
+    Command {
+        name: "win",
+        summary: "List or arrange windows and workspaces",
+        args: NO_ARGS,
+        subcommands: &[
+            SubcommandSpec {
+                name: "move",
+                args: &[
+                    ArgSpec::required("id", ArgKind::Number, "Window ID"),
+                    ArgSpec::required("x", ArgKind::Number, "New left edge"),
+                    ArgSpec::required("y", ArgKind::Number, "New top edge"),
+                ],
+                help: "Move a window",
+            },
+            SubcommandSpec {
+                name: "resize",
+                args: &[
+                    ArgSpec::required("id", ArgKind::Number, "Window ID"),
+                    ArgSpec::required("w", ArgKind::Number, "New width"),
+                    ArgSpec::required("h", ArgKind::Number, "New height"),
+                ],
+                help: "Resize a window",
+            },
+            SubcommandSpec {
+                name: "raise",
+                args: &[ArgSpec::required("id", ArgKind::Number, "Window ID")],
+                help: "Bring a window to the front",
+            },
+            SubcommandSpec {
+                name: "close",
+                args: &[ArgSpec::required("id", ArgKind::Number, "Window ID")],
+                help: "Close a window",
+            },
+            SubcommandSpec {
+                name: "opacity",
+                args: &[
+                    ArgSpec::required("id", ArgKind::Number, "Window ID"),
+                    ArgSpec::required("alpha", ArgKind::Number, "0 (hidden) to 255 (opaque)"),
+                ],
+                help: "Make a window translucent",
+            },
+            SubcommandSpec {
+                name: "ws",
+                args: &[
+                    ArgSpec::required("id", ArgKind::Number, "Window ID"),
+                    ArgSpec::required("n", ArgKind::Number, "Workspace number"),
+                ],
+                help: "Move a window to a workspace",
+            },
+            SubcommandSpec {
+                name: "switch",
+                args: &[ArgSpec::required("n", ArgKind::Number, "Workspace number")],
+                help: "Switch the active workspace",
+            },
+        ],
+        handler: cmd_win,
+    },
+    Command {
+        name: "faultinject",
+        summary: "Inject synthetic faults",
+        args: NO_ARGS,
+        subcommands: &[
+            SubcommandSpec {
+                name: "alloc",
+                args: &[
+                    ArgSpec::required("size", ArgKind::Number, "Allocation size in bytes"),
+                    ArgSpec::required("count", ArgKind::Number, "Allocations to fail"),
+                ],
+                help: "Fail the next allocations of a size class",
+            },
+            SubcommandSpec {
+                name: "pagefault",
+                args: &[ArgSpec::required(
+                    "addr",
+                    ArgKind::Number,
+                    "Address the victim reads",
+                )],
+                help: "Page fault in a victim task",
+            },
+            SubcommandSpec {
+                name: "timerdelay",
+                args: &[ArgSpec::required("ms", ArgKind::Number, "Hold time")],
+                help: "Hold expired timers",
+            },
+            SubcommandSpec {
+                name: "lock",
+                args: &[ArgSpec::required("ms", ArgKind::Number, "Hold time")],
+                help: "Hold the block registry lock",
+            },
+            SubcommandSpec {
+                name: "status",
+                args: NO_ARGS,
+                help: "Show pending faults",
+            },
+            SubcommandSpec {
+                name: "clear",
+                args: NO_ARGS,
+                help: "Clear all scenarios",
+            },
+        ],
+        handler: cmd_faultinject,
+    },
+    Command {
+        name: "iotrace",
+        summary: "Trace I/O port and MMIO accesses of a device",
+        args: &[
+            ArgSpec::optional("device", ArgKind::Word, "Device to trace"),
+            ArgSpec::optional(
+                "state",
+                ArgKind::Keyword(&["on", "off"]),
+                "Enable or disable tracing",
+            ),
+        ],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_iotrace,
+    },
+    Command {
+        name: "irqs",
+        summary: "Show interrupt handlers and per-vector counts",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_irqs,
+    },
+    Command {
+        name: "exctest",
+        summary: "Trigger CPU exceptions in user tasks and check recovery",
+        args: &[ArgSpec::optional(
+            "test",
+            ArgKind::Word,
+            "Test to run, or all",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_exctest,
+    },
+    Command {
+        name: "fbcache",
+        summary: "Show or switch the framebuffer cache mode (compare with membench fb)",
+        args: &[ArgSpec::optional(
+            "mode",
+            ArgKind::Keyword(&["wc", "uc"]),
+            "Write-combining or uncacheable",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_fbcache,
+    },
+    Command {
+        name: "membench",
+        summary: "Measure memory bandwidth and latency (regular and NT stores)",
+        args: &[ArgSpec::optional(
+            "target",
+            ArgKind::Keyword(&["heap", "shadow", "fb", "all", "blit"]),
+            "Memory to measure (default: all), or blit to compare SIMD pixel copies",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_membench,
+    },
+    Command {
+        name: "ktest",
+        summary: "Run scheduler and timer regression scenarios",
+        args: &[ArgSpec::optional(
+            "scenario",
+            ArgKind::Word,
+            "Scenario to run, or all",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_ktest,
+    },
+    Command {
+        name: "zram",
+        summary: "Show or control compressed swap",
+        args: NO_ARGS,
+        subcommands: &[
+            SubcommandSpec {
+                name: "reclaim",
+                args: &[ArgSpec::required(
+                    "pages",
+                    ArgKind::Number,
+                    "Pages to evict",
+                )],
+                help: "Compress resident pages into zram",
+            },
+            SubcommandSpec {
+                name: "budget",
+                args: &[ArgSpec::required(
+                    "KB",
+                    ArgKind::Number,
+                    "Compressed size limit",
+                )],
+                help: "Set the compressed storage budget",
+            },
+            SubcommandSpec {
+                name: "selftest",
+                args: &[ArgSpec::required("pages", ArgKind::Number, "Pages to test")],
+                help: "Round-trip pages through zram",
+            },
+        ],
+        handler: cmd_zram,
+    },
+    Command {
+        name: "ls",
+        summary: "List a directory",
+        args: &[ArgSpec::optional(
+            "path",
+            ArgKind::Word,
+            "Directory (default: /)",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_ls,
+    },
+    Command {
+        name: "cat",
+        summary: "Print a file",
+        args: PATH_ARG,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_cat,
+    },
+    Command {
+        name: "touch",
+        summary: "Create an empty file",
+        args: PATH_ARG,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_touch,
+    },
+    Command {
+        name: "write",
+        summary: "Replace a file's contents with text",
+        args: &[
+            ArgSpec::required("path", ArgKind::Word, "File to write"),
+            ArgSpec::optional("text", ArgKind::Rest, "Words to write as one line"),
+        ],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_write,
+    },
+    Command {
+        name: "mkdir",
+        summary: "Create a directory",
+        args: PATH_ARG,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_mkdir,
+    },
+    Command {
+        name: "rm",
+        summary: "Remove a file or empty directory",
+        args: PATH_ARG,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_rm,
+    },
+    Command {
+        name: "mount",
+        summary: "List mounted file systems",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_mount,
+    },
+    Command {
+        name: "ramdisk",
+        summary: "List block devices or create a RAM disk",
+        args: &[ArgSpec::optional(
+            "size_kb",
+            ArgKind::Number,
+            "Size of a new RAM disk",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_ramdisk,
+    },
+    Command {
+        name: "sync",
+        summary: "Flush block device write caches in the background",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_sync,
+    },
+    Command {
+        name: "mkfs.fat",
+        summary: "Format a block device as FAT32",
+        args: &[
+            ArgSpec::required(
+                "device",
+                ArgKind::Number,
+                "Block device index (see ramdisk)",
+            ),
+            ArgSpec::optional("label", ArgKind::Word, "Volume label"),
+        ],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_mkfs_fat,
+    },
+    Command {
+        name: "exec",
+        summary: "Run an ELF program as a user task",
+        args: &[ArgSpec::required("path", ArgKind::Word, "ELF executable")],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_exec,
+    },
+    Command {
+        name: "poweroff",
+        summary: "Shut down and power off",
+        args: &[ArgSpec::optional(
+            "force",
+            ArgKind::Keyword(&["-f"]),
+            "Skip flushing file systems",
+        )],
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_poweroff,
+    },
+    Command {
+        name: "panic",
+        summary: "Trigger a kernel panic",
+        args: NO_ARGS,
+        subcommands: NO_SUBCOMMANDS,
+        handler: cmd_panic,
+    },
+];
+
+/// 1行のコマンドを実行
+///
+/// # Arguments
+/// * `line` - 入力行（空白区切り）
+pub fn execute(line: &str) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&name, rest)) = words.split_first() else {
+        return;
+    };
+
+    let Some(cmd) = find_command(name) else {
+        println!("Unknown command: {} (type 'help')", name);
+        return;
+    };
+    match args::parse(rest, cmd.args, cmd.subcommands) {
+        Ok(args) => {
+            crate::trace::marker("cmd start");
+            (cmd.handler)(&args);
+            crate::trace::marker("cmd end");
+        }
+        Err(e) => {
+            println!("{}: {}", cmd.name, e);
+            print_usage(cmd.name);
+        }
+    }
+}
+
+/// シェルタスクのエントリポイント
+pub extern "C" fn shell_task() -> ! {
+    crate::info!("[Shell] Started on serial console");
+    println!("vitrOS kernel shell. Type 'help' for commands.");
+
+    loop {
+        print!(
+            "{}{}{}",
+            theme::accent().ansi_fg(),
+            PROMPT,
+            color::ANSI_RESET
+        );
+        let line = serial::read_line();
+        // 対話的に実行したコマンドの出力はページ送りする
+        let _session = pager::session();
+        execute(&line);
+    }
+}
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|cmd| cmd.name == name)
+}
+
+/// 仕様から生成した使い方を表示
+fn print_usage(name: &str) {
+    let Some(cmd) = find_command(name) else {
+        return;
+    };
+    for (i, form) in args::synopses(cmd.name, cmd.args, cmd.subcommands)
+        .iter()
+        .enumerate()
+    {
+        println!("{} {}", if i == 0 { "Usage:" } else { "      " }, form);
+    }
+}
+
+/// 引数の説明を1行ずつ出力
+fn describe_args(specs: &[ArgSpec], indent: usize) -> bool {
+    specs.iter().all(|spec| {
+        let label = match spec.kind {
+            ArgKind::Keyword(keywords) => keywords.join("|"),
+            _ => format!("<{}>", spec.name),
+        };
+        pager::line(format_args!(
+            "{:indent$}{:<16} {}{}",
+            "",
+            label,
+            spec.help,
+            if spec.optional { " (optional)" } else { "" },
+        ))
+    })
+}
+
+fn cmd_help(args: &Args) {
+    let Some(name) = args.word("command") else {
+        for cmd in COMMANDS {
+            if !pager::line(format_args!("  {:<12} {}", cmd.name, cmd.summary)) {
+                return;
+            }
+        }
+        pager::line(format_args!("Type 'help <command>' for details."));
+        return;
+    };
+
+    let Some(cmd) = find_command(name) else {
+        println!("help: Unknown command '{}'", name);
+        return;
+    };
+    let mut lines = Vec::new();
+    lines.push(format!("{} - {}", cmd.name, cmd.summary));
+    lines.push(String::from("Usage:"));
+    for form in args::synopses(cmd.name, cmd.args, cmd.subcommands) {
+        lines.push(format!("  {}", form));
+    }
+    if !lines
+        .iter()
+        .all(|line| pager::line(format_args!("{}", line)))
+    {
+        return;
+    }
+    let args_shown = cmd.args.is_empty()
+        || (pager::line(format_args!("Arguments:")) && describe_args(cmd.args, 2));
+    if !args_shown {
+        return;
+    }
+    if !cmd.subcommands.is_empty() && pager::line(format_args!("Subcommands:")) {
+        for sub in cmd.subcommands {
+            if !(pager::line(format_args!("  {:<16} {}", sub.name, sub.help))
+                && describe_args(sub.args, 4))
+            {
+                return;
+            }
+        }
+    }
+}
+
+fn cmd_pager(args: &Args) {
+    match args.word("state") {
+        Some("on") => pager::set_enabled(true),
+        Some("off") => pager::set_enabled(false),
+        _ => {}
+    }
+    println!("Paging: {}", if pager::is_enabled() { "on" } else { "off" });
+}
+
+fn cmd_ps(_args: &Args) {
+    sched::dump_tasks();
+}
+
+fn cmd_schedtop(_args: &Args) {
+    println!("Normal class policy: {}", sched::normal_policy_name());
+    println!(
+        "  {:>4} {:<16} {:<8} {:>10} {:>10} {:>7}",
+        "ID", "NAME", "CLASS", "BURST(us)", "PRED(us)", "GRAN(ms)"
+    );
+    for task in sched::task_bursts() {
+        println!(
+            "  {:>4} {:<16} {:<8} {:>10} {:>10} {:>7}{}",
+            task.id.as_u64(),
+            task.name,
+            format!("{:?}", task.sched_class),
+            task.burst_ns / 1_000,
+            task.predicted_burst_ns / 1_000,
+            task.granularity_ns / 1_000_000,
+            if task.interactive { " interactive" } else { "" }
+        );
+    }
+}
+
+fn cmd_schedstat(_args: &Args) {
+    let stats = sched::balance_stats();
+    println!("Wakeups (normal class): {}", stats.wakeups);
+    println!("  {:<14} {:>10}", "local", stats.wake_local);
+    println!("  {:<14} {:>10}", "wake-affine", stats.wake_affine);
+    println!("  {:<14} {:>10}", "remote", stats.wake_remote);
+    println!("Pulls:");
+    println!("  {:<14} {:>10}", "newidle", stats.newidle_pulls);
+    println!("  {:<14} {:>10}", "idle balance", stats.idle_pulls);
+    println!("  {:<14} {:>10}", "periodic", stats.periodic_pulls);
+    println!("  {:>4} {:>10} {:>7}", "CPU", "MIGRATED", "QUEUED");
+    for (cpu, migrations, queued) in stats.cpus {
+        println!("  {:>4} {:>10} {:>7}", cpu, migrations, queued);
+    }
+}
+
+/// `trace` の既定の表示件数
+const TRACE_DEFAULT_EVENTS: u64 = 32;
+
+fn cmd_trace(args: &Args) {
+    let count = args.number("count").unwrap_or(TRACE_DEFAULT_EVENTS) as usize;
+    println!("{} events recorded", trace::total());
+    println!("  {:>10} {:>6} {:<8} DETAILS", "TICK", "+DT", "EVENT");
+    let mut previous = None;
+    trace::for_each_recent(count, |event| {
+        let delta = previous.map_or(0, |tick| event.tick.saturating_sub(tick));
+        previous = Some(event.tick);
+        let details = match event.kind {
+            TraceKind::ContextSwitch => format!("{} -> {}", event.args[0], event.args[1]),
+            TraceKind::Syscall => format!("task {} nr {}", event.args[0], event.args[1]),
+            TraceKind::UserFault => {
+                format!("addr 0x{:X} error 0x{:X}", event.args[0], event.args[1])
+            }
+            TraceKind::TaskExit => format!("task {}", event.args[0]),
+            TraceKind::LockContention => {
+                format!("0x{:X} waited {} us", event.args[0], event.args[1] / 1_000)
+            }
+            TraceKind::Marker => format!("task {} == {} ==", event.args[0], event.label.as_str()),
+        };
+        println!(
+            "  {:>10} {:>6} {:<8} {}",
+            event.tick,
+            delta,
+            event.kind.name(),
+            details
+        );
+    });
+}
+
+/// `lockstat` で表示するロックの数
+const LOCKSTAT_TOP: usize = 16;
+
+fn cmd_lockstat(args: &Args) {
+    match args.word("action") {
+        Some("on") => lockstat::set_enabled(true),
+        Some("off") => lockstat::set_enabled(false),
+        Some("reset") => lockstat::reset(),
+        _ => {}
+    }
+    println!(
+        "Lock profiling: {}",
+        if lockstat::is_enabled() { "on" } else { "off" }
+    );
+    println!(
+        "  {:<5} {:>8} {:>10} {:>9} {:>4} {:>9} {:>6}  SITE",
+        "KIND", "CONTEND", "WAIT(us)", "MAX(us)", "WQ", "HOLD(us)", "HOLDER"
+    );
+    for stat in lockstat::report().iter().take(LOCKSTAT_TOP) {
+        println!(
+            "  {:<5} {:>8} {:>10} {:>9} {:>4} {:>9} {:>6}  {}:{}",
+            stat.kind.name(),
+            stat.contentions,
+            stat.wait_total_ns / 1_000,
+            stat.wait_max_ns / 1_000,
+            stat.max_waiters,
+            stat.hold_max_ns / 1_000,
+            stat.last_holder
+                .map_or(String::from("-"), |id| format!("{}", id)),
+            stat.site.file(),
+            stat.site.line()
+        );
+    }
+    if lockstat::dropped() > 0 {
+        println!("  ({} records dropped: table full)", lockstat::dropped());
+    }
+}
+
+fn cmd_kill(args: &Args) {
+    let Some(id) = args.number("task_id") else {
+        return;
+    };
+    match sched::kill(TaskId::from_u64(id)) {
+        Ok(()) => println!("Killed task {}", id),
+        Err(e) => println!("kill: {}", e),
+    }
+}
+
+fn cmd_mem(_args: &Args) {
+    let frames = frame_allocator::stats();
+    let page_kb = crate::paging::PAGE_SIZE / 1024;
+    println!(
+        "Frames: {} KB free / {} KB total",
+        frames.free_frames * page_kb,
+        frames.total_frames * page_kb
+    );
+
+    let heap = allocator::stats();
+    println!(
+        "Heap: {} KB in use (peak {} KB, {} failed, {} invalid frees), large region {} / {} KB ({} allocs, {} frees ignored)",
+        heap.bytes_in_use / 1024,
+        heap.peak_bytes / 1024,
+        heap.failures,
+        heap.invalid_frees,
+        heap.large_used / 1024,
+        heap.large_total / 1024,
+        heap.large_allocs,
+        heap.large_frees
+    );
+    println!(
+        "  {:>6} {:>8} {:>8} {:>8} {:>10} {:>10} {:>12}",
+        "CLASS", "IN USE", "PEAK", "BLOCKS", "ALLOCS", "FREES", "SLABS(+/-)"
+    );
+    for class in &heap.classes {
+        println!(
+            "  {:>6} {:>8} {:>8} {:>8} {:>10} {:>10} {:>4}({}/{})",
+            class.block_size,
+            class.in_use,
+            class.peak,
+            class.capacity,
+            class.allocs,
+            class.frees,
+            class.grown_slabs,
+            class.grows,
+            class.shrinks
+        );
+    }
+
+    let gfx = compositor::memory_usage();
+    println!(
+        "Compositor buffers: {} ({} KB)",
+        gfx.buffers,
+        gfx.bytes / 1024
+    );
+
+    let swap = zram::stats();
+    println!(
+        "Zram: {} pages resident, {} pages stored ({} KB compressed)",
+        swap.resident_pages,
+        swap.stored_pages,
+        swap.compressed_bytes / 1024
+    );
+
+    let reserve = emergency::stats();
+    println!(
+        "Emergency reserve: {} / {} KB used (peak {} KB, {} allocs, {} failed)",
+        reserve.used / 1024,
+        reserve.capacity / 1024,
+        reserve.peak / 1024,
+        reserve.allocations,
+        reserve.failures
+    );
+}
+
+fn cmd_heap(args: &Args) {
+    if args.subcommand() == Some("quota") {
+        let Some(id) = args.number("task_id") else {
+            return;
+        };
+        // "off" の場合は数値にならない
+        let quota = args.number("KB").map(|kb| kb as usize * 1024);
+        if let Err(e) = heap_quota::set_quota(TaskId::from_u64(id), quota) {
+            println!("heap: {}", e);
+        }
+        return;
+    }
+
+    println!(
+        "  {:>4} {:>10} {:>10} {:>10} {:>6} {:>10}",
+        "ID", "CURRENT", "PEAK", "QUOTA", "DENIED", "ALLOCS"
+    );
+    heap_quota::for_each_usage(|usage| {
+        let id = match usage.task_id {
+            Some(id) => format!("{}", id.as_u64()),
+            None => String::from("-"),
+        };
+        let quota = match usage.quota {
+            Some(quota) => format!("{}", quota),
+            None => String::from("-"),
+        };
+        println!(
+            "  {:>4} {:>10} {:>10} {:>10} {:>6} {:>10}{}",
+            id,
+            usage.current,
+            usage.peak,
+            quota,
+            usage.denied,
+            usage.allocs,
+            if usage.exited { " (exited)" } else { "" }
+        );
+    });
+}
+
+fn cmd_cmdline(_args: &Args) {
+    let text = cmdline::as_str();
+    if text.is_empty() {
+        println!("(empty)");
+        return;
+    }
+    println!("{}", text);
+    for param in vitros_common::cmdline::Cmdline::new(text).params() {
+        match param.value {
+            Some(value) => println!("  {:<16} {}", param.key, value),
+            None => println!("  {}", param.key),
+        }
+    }
+}
+
+fn cmd_uptime(_args: &Args) {
+    let ms = clock::monotonic_ns() / 1_000_000;
+    println!(
+        "Uptime: {}.{:03}s ({} ticks, clock: {})",
+        ms / 1000,
+        ms % 1000,
+        timer::current_tick(),
+        clock::source().as_str()
+    );
+}
+
+fn cmd_date(_args: &Args) {
+    match (datetime::now(), datetime::unix_time()) {
+        (Some(now), Some(unix)) => println!("{} UTC (unix {})", now, unix),
+        _ => println!("date: Wall clock is not available"),
+    }
+}
+
+fn cmd_dmesg(args: &Args) {
+    let min_level = args
+        .word("level")
+        .and_then(Level::from_name)
+        .unwrap_or(Level::Trace);
+    match args.subcommand() {
+        Some("clear") => {
+            klog::clear();
+            return;
+        }
+        Some("view") => {
+            if let Err(e) = dmesg_view::start(min_level) {
+                println!("dmesg: {}", e);
+            }
+            return;
+        }
+        Some("scroll") => {
+            const DEFAULT_SCROLL_LINES: u64 = 10;
+            let lines = args.number("lines").unwrap_or(DEFAULT_SCROLL_LINES) as isize;
+            let lines = if args.word("direction") == Some("down") {
+                -lines
+            } else {
+                lines
+            };
+            if !dmesg_view::scroll(lines) {
+                println!("dmesg: the console window is not open (use dmesg view)");
+            }
+            return;
+        }
+        Some("hide") => {
+            if !dmesg_view::stop() {
+                println!("dmesg: the console window is not open");
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    for entry in klog::snapshot().filter(|entry| entry.level >= min_level) {
+        if !pager::line(format_args!(
+            "{} {:<5} {}: {}",
+            log::Timestamp(entry.time_ns),
+            entry.level.as_str(),
+            entry.module,
+            entry.text
+        )) {
+            break;
+        }
+    }
+}
+
+fn cmd_font(args: &Args) {
+    match args.subcommand() {
+        Some("load") => {
+            let Some(path) = args.word("path") else {
+                return;
+            };
+            // 名前の既定値はディレクトリと拡張子を除いたファイル名
+            let file_name = path.rsplit('/').next().unwrap_or(path);
+            let default_name = file_name
+                .split_once('.')
+                .map_or(file_name, |(stem, _)| stem);
+            let name = args.word("name").unwrap_or(default_name);
+            let data = match fs::read_to_vec(path) {
+                Ok(data) => data,
+                Err(e) => {
+                    println!("font: {}: {}", path, e);
+                    return;
+                }
+            };
+            match font::BitmapFont::from_psf(name, &data).and_then(font::register) {
+                Ok(loaded) => println!(
+                    "Loaded {} ({}x{}, {} glyphs)",
+                    loaded.name(),
+                    loaded.width(),
+                    loaded.height(),
+                    loaded.glyph_count()
+                ),
+                Err(e) => println!("font: {}: {}", path, e),
+            }
+        }
+        Some("use") => {
+            let Some(name) = args.word("name") else {
+                return;
+            };
+            match font::find(name) {
+                Some(typeface) => {
+                    font::set_console_typeface(typeface);
+                    println!(
+                        "Console font: {} ({}x{})",
+                        typeface.name(),
+                        typeface.cell_width(),
+                        typeface.cell_height()
+                    );
+                }
+                None => println!("font: {}: not loaded", name),
+            }
+        }
+        _ => {
+            let current = font::console_typeface();
+            let builtin = font::Typeface::Builtin;
+            let marker = |name: &str| if name == current.name() { '*' } else { ' ' };
+            println!("  {:<20} {:>7} {:>7}", "NAME", "SIZE", "GLYPHS");
+            println!(
+                "{} {:<20} {:>7} {:>7}",
+                marker(builtin.name()),
+                builtin.name(),
+                format!("{}x{}", builtin.cell_width(), builtin.cell_height()),
+                "builtin"
+            );
+            for loaded in font::loaded() {
+                println!(
+                    "{} {:<20} {:>7} {:>7}",
+                    marker(loaded.name()),
+                    loaded.name(),
+                    format!("{}x{}", loaded.width(), loaded.height()),
+                    loaded.glyph_count()
+                );
+            }
+        }
+    }
+}
+
+fn cmd_log(args: &Args) {
+    // キーワードで検証済みのため変換に失敗しない
+    let level = args.word("level").and_then(Level::from_name);
+    match (args.subcommand(), level) {
+        (Some("level"), Some(level)) => log::set_default_level(level),
+        (Some("filter"), Some(level)) => {
+            if let Some(module) = args.word("module") {
+                log::set_module_level(module, Some(level));
+            }
+        }
+        (Some("unfilter"), _) => {
+            if let Some(module) = args.word("module") {
+                log::set_module_level(module, None);
+            }
+        }
+        (Some("sink"), Some(level)) => {
+            if let Some(name) = args.word("sink")
+                && let Err(e) = log::set_sink_level(name, level)
+            {
+                println!("log: {}: {}", name, e);
+            }
+        }
+        _ => {
+            println!("Default level: {}", log::default_level().as_str());
+            println!("Module filters:");
+            let filters = log::module_levels();
+            if filters.is_empty() {
+                println!("  (none)");
+            }
+            for (module, level) in filters {
+                println!("  {:<24} {}", module, level.as_str());
+            }
+            println!("Sinks:");
+            for (name, level) in log::sinks() {
+                println!("  {:<24} {}", name, level.as_str());
+            }
+            let stats = klog::stats();
+            println!(
+                "Buffer: {} / {} bytes, {} messages ({} evicted)",
+                stats.used, stats.capacity, stats.records, stats.evicted
+            );
+        }
+    }
+}
+
+fn cmd_watch(args: &Args) {
+    match args.subcommand() {
+        Some("stop") => {
+            if !watch::stop() {
+                println!("watch: Not running");
+            }
+            return;
+        }
+        Some("list") => {
+            for metric in metrics::METRICS {
+                let name = match metric.arg {
+                    Some(arg) => format!("{}:<{}>", metric.name, arg),
+                    None => String::from(metric.name),
+                };
+                println!("  {:<20} {:<8} {}", name, metric.unit, metric.help);
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let Some(interval_ms) = args.number("ms") else {
+        match watch::current() {
+            Some(config) => {
+                let exprs: Vec<&str> = config.exprs.iter().map(|e| e.text.as_str()).collect();
+                println!(
+                    "Watching every {} ms: {}",
+                    config.interval_ms,
+                    exprs.join(" ")
+                );
+            }
+            None => println!("Not watching (see help watch)"),
+        }
+        return;
+    };
+    let mut exprs = Vec::new();
+    for text in args.rest("expr") {
+        match metrics::Expr::parse(text) {
+            Ok(expr) => exprs.push(expr),
+            Err(e) => {
+                println!("watch: {}: {}", text, e);
+                return;
+            }
+        }
+    }
+    if let Err(e) = watch::start(interval_ms, exprs) {
+        println!("watch: {}", e);
+    }
+}
+
+fn cmd_cpus(_args: &Args) {
+    let bsp_id = apic::local_apic_id();
+    println!(
+        "{} CPU(s), {} online",
+        smp::cpu_count(),
+        smp::online_count()
+    );
+    println!("CPU  APIC ID  State");
+    let mut index = 0;
+    while let Some((apic_id, online)) = smp::cpu_info(index) {
+        let state = match (apic_id == bsp_id, online) {
+            (true, _) => "online (BSP)",
+            (false, true) => "online (idle)",
+            (false, false) => "offline",
+        };
+        println!("{:<4} {:<8} {}", index, apic_id, state);
+        index += 1;
+    }
+}
+
+fn cmd_timers(_args: &Args) {
+    let stats = timer::stats();
+    let now = timer::current_tick();
+    println!("Timer frequency: {} Hz", timer::frequency_hz());
+    println!(
+        "Queued: {}, pending callbacks: {}",
+        stats.queued, stats.pending
+    );
+    match stats.next_expiry {
+        Some(at) => println!(
+            "Next expiry: tick {} (in {} ticks)",
+            at,
+            at.saturating_sub(now)
+        ),
+        None => println!("Next expiry: none"),
+    }
+    println!(
+        "  {:<12} {:>8} {:>10} {:>10}",
+        "CLASS", "PENDING", "EXECUTED", "LATE MAX"
+    );
+    for class in timer::TimerClass::ALL {
+        let i = class as usize;
+        println!(
+            "  {:<12} {:>8} {:>10} {:>10}",
+            class.as_str(),
+            stats.pending_by_class[i],
+            stats.executed[i],
+            stats.max_lateness[i]
+        );
+    }
+}
+
+fn cmd_pci(args: &Args) {
+    let verbose = args.has("verbose");
+
+    pci::for_each_device(|dev| {
+        println!(
+            "[{:02X}:{:02X}.{}] {:04X}:{:04X} - {} (Class {:02X}:{:02X})",
+            dev.bus,
+            dev.device,
+            dev.function,
+            dev.vendor_id,
+            dev.device_id,
+            dev.class_name(),
+            dev.class_code,
+            dev.subclass
+        );
+        if !verbose {
+            return;
+        }
+        for (index, bar) in dev.bars().iter().enumerate() {
+            if let Some(bar) = bar {
+                println!("    BAR{}: {}", index, bar);
+            }
+        }
+        for cap in dev.capabilities() {
+            println!("    Cap 0x{:02X}: {}", cap.offset, dev.capability_info(cap));
+        }
+    });
+}
+
+fn cmd_workers(args: &Args) {
+    if let Some(count) = args.number("count")
+        && let Err(e) = worker_pool::resize(count as usize)
+    {
+        println!("workers: {}", e);
+        return;
+    }
+
+    let stats = worker_pool::stats();
+    println!(
+        "Workers: {} target, queued H/N/L = {}/{}/{}",
+        stats.target_workers, stats.queued[0], stats.queued[1], stats.queued[2]
+    );
+    println!(
+        "Jobs: {} submitted, {} completed, {} rejected",
+        stats.submitted, stats.completed, stats.rejected
+    );
+    for worker in &stats.workers {
+        println!(
+            "  task {:>4}: {:?}, {} jobs",
+            worker.task_id, worker.state, worker.jobs_completed
+        );
+    }
+
+    let wq = workqueue::stats();
+    println!(
+        "Work queue: {} kworkers, {} queued, {} running, {} completed, {} rejected",
+        wq.workers, wq.queued, wq.running, wq.completed, wq.rejected
+    );
+}
+
+fn cmd_compconf(args: &Args) {
+    let result = match args.subcommand() {
+        Some("fps") => compositor::set_target_fps(args.number("n").unwrap_or(0) as u32),
+        Some("pacing") => {
+            if let Some(source) = args.word("source").and_then(PacingSource::from_name) {
+                compositor::set_pacing_source(source);
+            }
+            Ok(())
+        }
+        Some("skip") => compositor::set_max_frame_skip(args.number("n").unwrap_or(0) as u32),
+        Some("stats") => {
+            let stats = compositor::frame_time_stats();
+            println!(
+                "frame time: min={}us avg={}us max={}us ({} frames)",
+                stats.min_us, stats.avg_us, stats.max_us, stats.frames
+            );
+            if args.word("reset").is_some() {
+                compositor::reset_frame_time_stats();
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        println!("compconf: {}", e);
+        return;
+    }
+
+    let config = compositor::pacing_config();
+    println!(
+        "fps={} pacing={} skip={} (skipped {})",
+        config.target_fps,
+        config.source.as_str(),
+        config.max_frame_skip,
+        config.skipped_frames
+    );
+}
+
+fn cmd_config(args: &Args) {
+    match (args.word("key"), args.word("value")) {
+        (None, _) => {
+            for setting in config::SETTINGS {
+                println!(
+                    "  {:<20} {:<12} {}",
+                    setting.key,
+                    setting.value(),
+                    setting.help
+                );
+            }
+        }
+        (Some(key), None) => match config::get(key) {
+            Ok(value) => println!("{} = {}", key, value),
+            Err(e) => println!("config: {}", e),
+        },
+        (Some(key), Some(value)) => match config::set(key, value) {
+            Ok(()) => println!("{} = {}", key, config::get(key).unwrap_or_default()),
+            Err(e) => println!("config: {}", e),
+        },
+    }
+}
+
+fn cmd_win(args: &Args) {
+    // 数値の引数は検証済みのため、欠けることはない
+    let num = |name| args.number(name).unwrap_or(0);
+    let id = WindowId::from_u64(num("id"));
+    let result = match args.subcommand() {
+        Some("move") => compositor::move_window(id, num("x") as u32, num("y") as u32),
+        Some("resize") => compositor::resize_window(id, num("w") as u32, num("h") as u32),
+        Some("raise") => compositor::raise_window(id),
+        Some("close") => compositor::close_window(id),
+        Some("opacity") => compositor::set_window_opacity(id, num("alpha").min(255) as u8),
+        Some("ws") => compositor::move_window_to_workspace(id, Some(num("n") as usize)),
+        Some("switch") => compositor::switch_workspace(num("n") as usize),
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        println!("win: {}", e);
+        return;
+    }
+
+    println!("  workspace {}", compositor::active_workspace());
+    for info in compositor::windows() {
+        let workspace = match info.workspace {
+            Some(n) => char::from_digit(n as u32, 10).unwrap_or('?'),
+            None => '*',
+        };
+        println!(
+            "  {:>3} z={:<2} ws={} {:>4},{:<4} {:>4}x{:<4} a={:<3} {}",
+            info.id.as_u64(),
+            info.z_order,
+            workspace,
+            info.frame.x,
+            info.frame.y,
+            info.frame.width,
+            info.frame.height,
+            info.opacity,
+            info.title
+        );
+    }
+    for info in compositor::surfaces() {
+        println!(
+            "  surface {:>3} {:>4}x{:<4} owner={}",
+            info.id.as_u64(),
+            info.width,
+            info.height,
+            info.owner.as_u64()
+        );
+    }
+}
+
+fn cmd_faultinject(args: &Args) {
+    let num = |name| args.number(name).unwrap_or(0);
+    let result = match args.subcommand() {
+        Some("alloc") => {
+            let Ok(count) = u32::try_from(num("count")) else {
+                println!("faultinject: <count> is too large");
+                return;
+            };
+            let size = num("size") as usize;
+            fault_inject::fail_allocations(size, count);
+            println!(
+                "[FaultInject] Next {} allocations of size class for {}B will fail",
+                count, size
+            );
+            Ok(())
+        }
+        Some("pagefault") => fault_inject::trigger_page_fault(num("addr")),
+        Some("timerdelay") => {
+            let ms = num("ms");
+            fault_inject::delay_timers(ms);
+            println!("[FaultInject] Timer delivery held for {} ms", ms);
+            Ok(())
+        }
+        Some("lock") => fault_inject::contend_lock(num("ms")),
+        Some("clear") => {
+            fault_inject::clear();
+            println!("[FaultInject] All scenarios cleared");
+            Ok(())
+        }
+        _ => {
+            fault_inject::print_status();
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        println!("faultinject: {}", e);
+    }
+}
+
+fn cmd_irqs(_args: &Args) {
+    let now_ns = clock::monotonic_ns();
+    println!(
+        "  {:>6} {:>10} {:>9} {:>10}  HANDLERS",
+        "VECTOR", "COUNT", "UNHANDLED", "LAST(ms)"
+    );
+    for line in irq::stats() {
+        let last = if line.count == 0 {
+            String::from("-")
+        } else {
+            format!("{}", now_ns.saturating_sub(line.last_ns) / 1_000_000)
+        };
+        println!(
+            "  {:>#6x} {:>10} {:>9} {:>10}  {}",
+            line.vector,
+            line.count,
+            line.unhandled,
+            last,
+            line.handlers.join(",")
+        );
+    }
+    println!("  NMI: {}  MCE: {}", idt::nmi_count(), mce::count());
+}
+
+fn cmd_iotrace(args: &Args) {
+    match (args.word("device"), args.word("state")) {
+        (None, _) => {
+            for device in iotrace::DEVICES {
+                let state = if iotrace::is_enabled(device) {
+                    "on"
+                } else {
+                    "off"
+                };
+                println!("  {:<12} {}", device.name(), state);
+            }
+        }
+        (Some(name), Some(state)) => match iotrace::Device::from_name(name) {
+            Some(device) => iotrace::set_enabled(device, state == "on"),
+            None => println!("iotrace: Unknown device '{}'", name),
+        },
+        (Some(_), None) => {
+            println!("iotrace: Missing argument <state>");
+            print_usage("iotrace");
+        }
+    }
+}
+
+fn cmd_exctest(args: &Args) {
+    match args.word("test") {
+        None => {
+            for test in exctest::TESTS {
+                println!("  {:<20} {}", test.name, test.help);
+            }
+        }
+        Some("all") => {
+            let (passed, failed) = exctest::run_all();
+            println!("exctest: {} passed, {} failed", passed, failed);
+        }
+        Some(name) => {
+            if let Err(exctest::ExcTestError::UnknownTest) = exctest::run(name) {
+                println!("exctest: Unknown test '{}'", name);
+            }
+        }
+    }
+}
+
+fn cmd_fbcache(args: &Args) {
+    let Some(fb) = compositor::framebuffer() else {
+        println!("fbcache: Compositor is not running");
+        return;
+    };
+    let (base, size) = (fb.base, fb.size_bytes());
+
+    // 他のCPUのTLBに古い属性が残ってもキャッシュに書き戻す内容が生じないよう、
+    // キャッシュしない方式（wc、uc）の間でのみ切り替える
+    if let Some(mode) = args.word("mode").and_then(paging::CacheMode::from_name) {
+        if let Err(e) = paging::set_cache_mode(base, size, mode) {
+            println!("fbcache: {}", e);
+            return;
+        }
+        compositor::redraw_all();
+    }
+
+    let mode = match paging::cache_mode(base) {
+        Ok(Some(mode)) => mode.as_str(),
+        Ok(None) => "other",
+        Err(e) => {
+            println!("fbcache: {}", e);
+            return;
+        }
+    };
+    let mtrr = paging::virt_to_phys(base)
+        .map(|phys| paging::mtrr_memory_type(phys).as_str())
+        .unwrap_or("unknown");
+    println!(
+        "Framebuffer 0x{:X} ({} KB): page attribute {}, MTRR {}",
+        base,
+        size / 1024,
+        mode,
+        mtrr
+    );
+}
+
+fn cmd_membench(args: &Args) {
+    if args.word("target") == Some("blit") {
+        match membench::run_blit() {
+            Ok(results) => {
+                println!("  {:<8} {:>12} {:>12}", "SIMD", "COPY MB/s", "FILL MB/s");
+                for r in results {
+                    println!(
+                        "  {:<8} {:>12} {:>12}",
+                        r.level.as_str(),
+                        r.copy_mbps,
+                        r.fill_mbps
+                    );
+                }
+                println!("  active: {}", simd::active().as_str());
+            }
+            Err(e) => println!("membench: {}", e),
+        }
+        return;
+    }
+
+    let targets: &[membench::Target] =
+        match args.word("target").and_then(membench::Target::from_name) {
+            Some(target) => &[target][..],
+            None => &membench::Target::ALL,
+        };
+
+    println!(
+        "  {:<8} {:>8} {:>12} {:>12} {:>12} {:>10} {:>10} {:>10}",
+        "TARGET",
+        "SIZE KB",
+        "SEQ-RD MB/s",
+        "SEQ-WR MB/s",
+        "SEQ-NT MB/s",
+        "RND-RD ns",
+        "RND-WR ns",
+        "RND-NT ns"
+    );
+    for target in targets {
+        match membench::run(*target) {
+            Ok(r) => println!(
+                "  {:<8} {:>8} {:>12} {:>12} {:>12} {:>10} {:>10} {:>10}",
+                target.as_str(),
+                r.bytes / 1024,
+                r.seq_read_mbps,
+                r.seq_write_mbps,
+                r.seq_write_nt_mbps,
+                r.rand_read_ns,
+                r.rand_write_ns,
+                r.rand_write_nt_ns
+            ),
+            Err(e) => println!("  {:<8} {}", target.as_str(), e),
+        }
+    }
+}
+
+fn cmd_ktest(args: &Args) {
+    match args.word("scenario") {
+        None => {
+            for scenario in ktest::SCENARIOS {
+                println!("  {:<20} {}", scenario.name, scenario.help);
+            }
+        }
+        Some("all") => {
+            let (passed, failed) = ktest::run_all();
+            println!("ktest: {} passed, {} failed", passed, failed);
+        }
+        Some(name) => {
+            if let Err(ktest::KtestError::UnknownScenario) = ktest::run(name) {
+                println!("ktest: Unknown scenario '{}'", name);
+            }
+        }
+    }
+}
+
+fn cmd_zram(args: &Args) {
+    let num = |name| args.number(name).unwrap_or(0) as usize;
+    match args.subcommand() {
+        Some("reclaim") => println!("Reclaimed {} pages", zram::reclaim(num("pages"))),
+        Some("budget") => zram::set_budget(num("KB") * 1024),
+        Some("selftest") => match zram::self_test(num("pages")) {
+            Ok(evicted) => println!("Self-test passed ({} pages evicted)", evicted),
+            Err(e) => println!("zram: {}", e),
+        },
+        _ => {}
+    }
+
+    let stats = zram::stats();
+    println!(
+        "Resident: {} pages, stored: {} pages ({} / {} KB)",
+        stats.resident_pages,
+        stats.stored_pages,
+        stats.compressed_bytes / 1024,
+        stats.budget_bytes / 1024
+    );
+    println!(
+        "Swap-outs: {}, swap-ins: {}, rejected: {}",
+        stats.swap_outs, stats.swap_ins, stats.rejected
+    );
+}
+
+fn cmd_ls(args: &Args) {
+    let path = args.word("path").unwrap_or("/");
+    match fs::read_dir(path) {
+        Ok(entries) => {
+            for entry in entries {
+                let suffix = if entry.file_type == FileType::Directory {
+                    "/"
+                } else {
+                    ""
+                };
+                println!("  {}{}", entry.name, suffix);
+            }
+        }
+        Err(e) => println!("ls: {}: {}", path, e),
+    }
+}
+
+fn cmd_cat(args: &Args) {
+    let Some(path) = args.word("path") else {
+        return;
+    };
+    match fs::read_to_vec(path) {
+        Ok(data) => {
+            for chunk in data.utf8_chunks() {
+                print!("{}", chunk.valid());
+                if !chunk.invalid().is_empty() {
+                    print!("\u{FFFD}");
+                }
+            }
+            if !data.ends_with(b"\n") {
+                println!();
+            }
+        }
+        Err(e) => println!("cat: {}: {}", path, e),
+    }
+}
+
+fn cmd_touch(args: &Args) {
+    let Some(path) = args.word("path") else {
+        return;
+    };
+    match fs::create_file(path) {
+        Ok(()) | Err(fs::FsError::AlreadyExists) => {}
+        Err(e) => println!("touch: {}: {}", path, e),
+    }
+}
+
+fn cmd_write(args: &Args) {
+    let Some(path) = args.word("path") else {
+        return;
+    };
+    let mut text = args.rest("text").join(" ");
+    text.push('\n');
+    if let Err(e) = fs::write_all(path, text.as_bytes()) {
+        println!("write: {}: {}", path, e);
+    }
+}
+
+fn cmd_mkdir(args: &Args) {
+    let Some(path) = args.word("path") else {
+        return;
+    };
+    if let Err(e) = fs::create_dir(path) {
+        println!("mkdir: {}: {}", path, e);
+    }
+}
+
+fn cmd_rm(args: &Args) {
+    let Some(path) = args.word("path") else {
+        return;
+    };
+    if let Err(e) = fs::remove(path) {
+        println!("rm: {}: {}", path, e);
+    }
+}
+
+fn cmd_mount(_args: &Args) {
+    for mount in fs::mounts() {
+        println!("  {} on {}", mount.fs_name, mount.path);
+    }
+}
+
+fn cmd_ramdisk(args: &Args) {
+    if let Some(size_kb) = args.number("size_kb") {
+        match ramdisk::create(size_kb as usize, None) {
+            Ok(index) => println!("Created RAM disk: device {}", index),
+            Err(e) => println!("ramdisk: {}", e),
+        }
+    }
+
+    for dev in block::devices() {
+        println!(
+            "  {:>2} {:<8} {:>8} KB",
+            dev.index,
+            dev.name,
+            dev.block_count * dev.block_size as u64 / 1024
+        );
+    }
+}
+
+fn cmd_sync(_args: &Args) {
+    match block::schedule_writeback() {
+        Ok(()) => println!("Writeback queued on the worker pool"),
+        Err(e) => println!("sync: {}", e),
+    }
+}
+
+fn cmd_mkfs_fat(args: &Args) {
+    let Some(index) = args.number("device") else {
+        return;
+    };
+    let label = args.word("label").unwrap_or(mkfs_fat::DEFAULT_LABEL);
+    match mkfs_fat::format_device(index as usize, label) {
+        Ok(layout) => println!(
+            "FAT32: {} clusters x {} sectors, FAT {} sectors, data at sector {}",
+            layout.clusters, layout.sectors_per_cluster, layout.fat_sectors, layout.data_start
+        ),
+        Err(e) => println!("mkfs.fat: {}", e),
+    }
+}
+
+fn cmd_exec(args: &Args) {
+    let Some(path) = args.word("path") else {
+        return;
+    };
+    match crate::elf_loader::spawn(path) {
+        Ok(id) => println!("Started task {}", id.as_u64()),
+        Err(e) => println!("exec: {}: {}", path, e),
+    }
+}
+
+fn cmd_poweroff(args: &Args) {
+    if args.has("force") {
+        power::force_off();
+    }
+    power::shutdown();
+}
+
+fn cmd_panic(_args: &Args) {
+    panic!("Panic requested from shell");
+}
@@ -0,0 +1,211 @@
+//! コンソール出力のページ送り
+//!
+//! 対話中のシェルが実行するコマンドの出力を1画面ごとに止め、`-- More --` を表示して
+//! キー入力を待ちます（space: 次のページ、enter: 1行、q: 残りの出力を捨てる）。
+//! シリアル端末はスクロールバックを持たないことがあるため、`lspci`・`dmesg`・`help` などの
+//! 長い出力が流れ去らないようにします。
+//!
+//! ページ送りはシリアル出力の経路（`print!`/`println!`）に組み込まれているため、
+//! コマンド側の対応は不要です。対象はシェルが `session` を開始したタスクの出力のみで、
+//! 次の場合はそのまま出力します。
+//! - 他のタスク、割り込み・例外ハンドラ、パニック時の出力
+//! - `pager off` の間（ログを取るスクリプトやシリアル経由の自動操作向け）
+//! - `execute` を `session` なしで呼び出した場合
+//!
+//! 行数は端末の幅（`COLUMNS`）での折り返しとエスケープシーケンスを考慮して数えます。
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::println;
+use crate::serial::{self, COM1, SerialPort};
+
+/// 1画面の行数（プロンプトの行を除く）
+const PAGE_ROWS: usize = 20;
+
+/// 端末の幅（この文字数で折り返すものとして数える）
+const COLUMNS: usize = 80;
+
+/// セッションなし
+const NO_TASK: u64 = u64::MAX;
+
+const ESC: u8 = 0x1B;
+
+/// ページ送りが有効か（`pager on|off`）
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// ページ送りの対象のタスク
+static SESSION_TASK: AtomicU64 = AtomicU64::new(NO_TASK);
+
+/// 現在のページの状態（対象のタスクのみが更新する）
+static STATE: Mutex<PageState> = Mutex::new(PageState::new());
+
+#[derive(Clone, Copy)]
+struct PageState {
+    /// 現在のページに出力した行数
+    rows: usize,
+    /// 現在の行の桁位置
+    column: usize,
+    /// エスケープシーケンスの途中
+    in_escape: bool,
+    /// `q` で出力を打ち切った
+    quit: bool,
+}
+
+impl PageState {
+    const fn new() -> Self {
+        Self {
+            rows: 0,
+            column: 0,
+            in_escape: false,
+            quit: false,
+        }
+    }
+
+    /// 1バイト出力した後の行・桁位置を更新
+    fn advance(&mut self, byte: u8) {
+        if self.in_escape {
+            // CSIの終端（'[' 以外の0x40〜0x7E）まではカーソル移動や色の指定で、桁を進めない
+            if byte != b'[' && (0x40..=0x7E).contains(&byte) {
+                self.in_escape = false;
+            }
+            return;
+        }
+        match byte {
+            ESC => self.in_escape = true,
+            b'\n' => {
+                self.rows += 1;
+                self.column = 0;
+            }
+            b'\r' => self.column = 0,
+            // UTF-8の継続バイトとその他の制御文字は桁を進めない
+            0x80..=0xBF | 0x00..=0x1F => {}
+            _ => {
+                self.column += 1;
+                if self.column == COLUMNS {
+                    self.rows += 1;
+                    self.column = 0;
+                }
+            }
+        }
+    }
+}
+
+/// ページ送りしながらシリアルに書き込む
+struct PagedWriter<'a> {
+    state: &'a mut PageState,
+    port: SerialPort,
+}
+
+impl PagedWriter<'_> {
+    /// `-- More --` を表示してキー入力を待つ
+    fn prompt(&mut self) {
+        self.port
+            .write_str("-- More -- (space: page, enter: line, q: quit)");
+        let key = serial::read_byte();
+        // プロンプトを消す
+        self.port.write_str("\r\x1b[K");
+        match key {
+            b'q' | b'Q' => self.state.quit = true,
+            b'\r' | b'\n' => self.state.rows = PAGE_ROWS - 1,
+            _ => self.state.rows = 0,
+        }
+    }
+}
+
+impl fmt::Write for PagedWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            // 画面がいっぱいなら、次の行を書き始める前に止める
+            if self.state.rows >= PAGE_ROWS && self.state.column == 0 && !self.state.in_escape {
+                self.prompt();
+            }
+            if self.state.quit {
+                break;
+            }
+            self.port.write_byte(byte);
+            self.state.advance(byte);
+        }
+        Ok(())
+    }
+}
+
+/// ページ送りのセッション（破棄すると終了する）
+pub struct Session {
+    _private: (),
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        SESSION_TASK.store(NO_TASK, Ordering::Relaxed);
+        *STATE.lock() = PageState::new();
+    }
+}
+
+/// 現在のタスクの出力のページ送りを開始
+///
+/// シェルが対話的にコマンドを実行する間だけ保持します。
+///
+/// # Returns
+/// ページ送りが無効（`pager off`）ならNone
+pub fn session() -> Option<Session> {
+    if !is_enabled() {
+        return None;
+    }
+    *STATE.lock() = PageState::new();
+    let task = crate::sched::current_task_id().as_u64();
+    SESSION_TASK.store(task, Ordering::Relaxed);
+    Some(Session { _private: () })
+}
+
+/// 出力の経路から呼び出し、ページ送りの対象ならページ送りしながら出力する
+///
+/// # Returns
+/// 出力した場合はtrue。対象外ならfalse（呼び出し側がそのまま出力する）
+pub fn write(args: fmt::Arguments) -> bool {
+    let task = SESSION_TASK.load(Ordering::Relaxed);
+    // キー入力を待つためブロックするので、タスクのコンテキスト以外では行わない
+    if task == NO_TASK
+        || !crate::io::interrupts_enabled()
+        || crate::emergency::in_emergency()
+        || crate::sched::current_task_id_lockless().map(|id| id.as_u64()) != Some(task)
+    {
+        return false;
+    }
+    // キー入力を待つ間はロックを保持しない（状態を更新するのはこのタスクのみ）
+    let mut state = *STATE.lock();
+    let _ = PagedWriter {
+        state: &mut state,
+        port: SerialPort::new(COM1),
+    }
+    .write_fmt(args);
+    *STATE.lock() = state;
+    true
+}
+
+/// `q` で出力が打ち切られたか
+///
+/// 長い出力を生成するコマンドは、これを見て早めに終了できます。
+pub fn quit_requested() -> bool {
+    SESSION_TASK.load(Ordering::Relaxed) != NO_TASK && STATE.lock().quit
+}
+
+/// 1行出力（改行は付加される）
+///
+/// # Returns
+/// 出力を続けてよければtrue、`q` で打ち切られていればfalse
+pub fn line(args: fmt::Arguments) -> bool {
+    println!("{}", args);
+    !quit_requested()
+}
+
+/// ページ送りが有効か
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// ページ送りの有効/無効を切り替える（次のコマンドから反映）
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
@@ -0,0 +1,567 @@
+//! SMP（マルチプロセッサ）の起動
+//!
+//! MADTに記載されたアプリケーションプロセッサ（AP）をINIT/SIPIで起動します。
+//!
+//! APはリアルモードで物理アドレス `TRAMPOLINE_PHYS` から実行を開始するため、
+//! 低位メモリにトランポリンをコピーし、そこからロングモードへ直接移行して
+//! 高位アドレスのRust関数 `ap_entry` へジャンプします。各APはCPUごとのGDT/TSSと
+//! スタックを持ち、共有のIDTをロードしたあと、起動時のコンテキストをアイドルタスクとして
+//! スケジューラに登録し、Local APICタイマーを開始します。
+//!
+//! CPU番号はBSPが0で、APはMADTの順です。
+//!
+//! マッピングを変更・解除したときは `flush_tlb_others` で他のCPUのTLBを無効化します
+//! （TLBシュートダウン）。
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+use crate::irq::{self, IrqReturn};
+use crate::paging::{self, PAGE_SIZE, PageTableFlags};
+use crate::sched::{self, Task};
+use crate::{apic, clock, cpu, frame_allocator, gdt, idt, info, mce, percpu, timer, warn};
+
+/// 管理できるCPUの最大数（BSPを含む）
+pub const MAX_CPUS: usize = 16;
+
+/// 再スケジュールIPIの割り込みベクタ番号
+pub const RESCHEDULE_VECTOR: u8 = 0xFD;
+
+/// トランポリンを配置する物理アドレス（SIPIのベクタは `TRAMPOLINE_PHYS / 0x1000`）
+///
+/// カーネルのロード先（1MB）より下にあり、フレームアロケータは割り当てない。
+pub const TRAMPOLINE_PHYS: u64 = 0x8000;
+
+/// APごとのカーネルスタックのページ数
+const AP_STACK_PAGES: usize = 4;
+
+/// APが起動を報告するまでの待ち時間の上限（ミリ秒）
+const AP_BOOT_TIMEOUT_MS: u64 = 100;
+
+/// EFER: Long Mode Enable
+const EFER_LME: u64 = 1 << 8;
+
+/// EFER: No-Execute Enable
+const EFER_NXE: u64 = 1 << 11;
+
+/// APに引き継ぐEFERのビット（NXEがないとBSPが作ったNXビット付きのPTEで予約ビット違反になる）
+const EFER_INHERITED: u64 = EFER_LME | EFER_NXE;
+
+/// 登録済みCPUのLocal APIC ID（インデックス = CPU番号）
+static CPU_APIC_IDS: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(0) }; MAX_CPUS];
+
+/// CPUが起動済みか
+static CPU_ONLINE: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// 登録済みCPU数
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 起動に応答しなかったAPがあるか（あとからトランポリンを実行する可能性がある）
+static AP_BOOT_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// TLBシュートダウンで無効化する仮想アドレス範囲 [start, end)
+static SHOOTDOWN_START: AtomicU64 = AtomicU64::new(0);
+static SHOOTDOWN_END: AtomicU64 = AtomicU64::new(0);
+
+/// TLBシュートダウンの完了を待っているCPU（ビットi = CPU番号i）
+static SHOOTDOWN_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// 送信中のTLBシュートダウン（同時に1つだけ）
+static SHOOTDOWN_LOCK: Mutex<()> = Mutex::new(());
+
+// APの起動コード
+//
+// SIPIを受け取ったAPはリアルモード（CS=TRAMPOLINE_PHYS>>4, IP=0）で先頭から実行する。
+// データはDS=0からの絶対アドレスで参照するため、すべて `{base}` からのオフセットで指定する。
+// ページングを有効にした直後の命令フェッチのため、トランポリンのページは恒等マップしておく。
+// CPU番号とスタックは、自分のAPIC ID（CPUID.01H:EBX[31:24]）と一致するスロットから読む。
+// 各APが専用のスロットを使うため、タイムアウト後に遅れて起動したAPも次のAPの値を読まない。
+global_asm!(
+    ".pushsection .rodata.smp_trampoline, \"a\"",
+    ".global smp_trampoline_start",
+    ".global smp_trampoline_params",
+    ".global smp_trampoline_end",
+    ".code16",
+    "smp_trampoline_start:",
+    "    cli",
+    "    cld",
+    "    xor ax, ax",
+    "    mov ds, ax",
+    "    lgdt [smp_trampoline_gdtr_phys]",
+    // CR4.PAE
+    "    mov eax, cr4",
+    "    or eax, 0x20",
+    "    mov cr4, eax",
+    "    mov eax, dword ptr [smp_trampoline_params_phys]",
+    "    mov cr3, eax",
+    // EFER.LME（+ BSPと同じNXE）
+    "    mov ecx, 0xC0000080",
+    "    rdmsr",
+    "    or eax, dword ptr [smp_trampoline_params_phys + 8]",
+    "    wrmsr",
+    // CR0.PG | CR0.PE
+    "    mov eax, cr0",
+    "    or eax, 0x80000001",
+    "    mov cr0, eax",
+    // 64ビットコードセグメントへのfar jump（jmp far dword 0x08:smp_trampoline_long）
+    "    .byte 0x66, 0xEA",
+    "    .long {base} + (smp_trampoline_long - smp_trampoline_start)",
+    "    .word 0x08",
+    ".code64",
+    "smp_trampoline_long:",
+    "    mov ax, 0x10",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    mov ss, ax",
+    "    xor ax, ax",
+    "    mov fs, ax",
+    "    mov gs, ax",
+    "    mov ebx, offset smp_trampoline_params_phys",
+    // BSPと同じCR0/CR4（WP, SSEの有効化など）
+    "    mov rax, [rbx + 16]",
+    "    mov cr0, rax",
+    "    mov rax, [rbx + 24]",
+    "    mov cr4, rax",
+    "    mov eax, 1",
+    "    cpuid",
+    "    shr ebx, 24",
+    "    mov esi, offset smp_trampoline_slots_phys",
+    "    mov ecx, {max_cpus}",
+    // apic_idが一致し、stack_topが設定済みのスロットを探す
+    "3:  cmp [rsi], rbx",
+    "    jne 4f",
+    "    cmp qword ptr [rsi + 16], 0",
+    "    jne 5f",
+    "4:  add rsi, {slot_size}",
+    "    loop 3b",
+    "    jmp 2f",
+    // ap_entry(cpu_index) をスロットのスタック上で呼び出す
+    "5:  mov rsp, [rsi + 16]",
+    "    mov rdi, [rsi + 8]",
+    "    mov eax, offset smp_trampoline_params_phys",
+    "    mov rax, [rax + 32]",
+    "    call rax",
+    "2:  hlt",
+    "    jmp 2b",
+    ".balign 8",
+    "smp_trampoline_gdt:",
+    "    .quad 0",
+    "    .quad 0x00AF9A000000FFFF", // 0x08: 64ビットコード
+    "    .quad 0x00CF92000000FFFF", // 0x10: データ
+    "smp_trampoline_gdtr:",
+    "    .word 23",
+    "    .long {base} + (smp_trampoline_gdt - smp_trampoline_start)",
+    ".balign 8",
+    "smp_trampoline_params:",
+    "    .fill {params_quads}, 8, 0",
+    "smp_trampoline_end:",
+    // コピー先（物理アドレス）でのラベルのアドレス
+    ".set smp_trampoline_gdtr_phys, {base} + (smp_trampoline_gdtr - smp_trampoline_start)",
+    ".set smp_trampoline_params_phys, {base} + (smp_trampoline_params - smp_trampoline_start)",
+    ".set smp_trampoline_slots_phys, smp_trampoline_params_phys + 40",
+    ".popsection",
+    base = const TRAMPOLINE_PHYS,
+    max_cpus = const MAX_CPUS,
+    slot_size = const size_of::<ApSlot>(),
+    params_quads = const size_of::<TrampolineParams>() / 8,
+);
+
+unsafe extern "C" {
+    static smp_trampoline_start: u8;
+    static smp_trampoline_params: u8;
+    static smp_trampoline_end: u8;
+}
+
+/// トランポリンに渡すパラメータ（`smp_trampoline_params` のレイアウト）
+#[repr(C)]
+struct TrampolineParams {
+    /// PML4の物理アドレス（4GB未満）
+    cr3: u64,
+    /// EFERに追加で立てるビット
+    efer: u64,
+    cr0: u64,
+    cr4: u64,
+    /// ロングモード移行後に呼び出す関数（`ap_entry`）
+    entry: u64,
+    /// APごとのスロット（CPU番号の順）
+    slots: [ApSlot; MAX_CPUS],
+}
+
+// トランポリンは `smp_trampoline_params_phys + 40` をスロットの先頭として参照する
+const _: () = assert!(core::mem::offset_of!(TrampolineParams, slots) == 40);
+
+/// APごとのトランポリンのパラメータ
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ApSlot {
+    /// このスロットを使うAPのLocal APIC ID
+    apic_id: u64,
+    /// `ap_entry` に渡すCPU番号
+    cpu_index: u64,
+    /// APのスタックの最上位アドレス（0なら未使用のスロット）
+    stack_top: u64,
+}
+
+/// SMP起動のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmpError {
+    /// トランポリン用の低位メモリにアクセスできない
+    TrampolineUnavailable,
+    /// PML4が4GB以上にあり、リアルモードから参照できない
+    PageTableTooHigh,
+    /// APのスタックを確保できない
+    StackAllocationFailed,
+    /// APが時間内に起動を報告しなかった
+    Timeout,
+}
+
+impl core::fmt::Display for SmpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SmpError::TrampolineUnavailable => write!(f, "Trampoline memory unavailable"),
+            SmpError::PageTableTooHigh => write!(f, "PML4 is above 4GB"),
+            SmpError::StackAllocationFailed => write!(f, "Failed to allocate AP stack"),
+            SmpError::Timeout => write!(f, "AP did not respond"),
+        }
+    }
+}
+
+/// MADTで見つかったCPUを登録（ACPIの解析中に呼ばれる）
+///
+/// `MAX_CPUS` を超えたCPUは無視します。
+pub fn register_cpu(apic_id: u8) {
+    let index = CPU_COUNT.load(Ordering::Relaxed);
+    if index >= MAX_CPUS {
+        warn!("Too many CPUs, ignoring APIC ID {}", apic_id);
+        return;
+    }
+    CPU_APIC_IDS[index].store(apic_id, Ordering::Relaxed);
+    CPU_COUNT.store(index + 1, Ordering::Release);
+}
+
+/// 登録済みCPU数
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+/// 起動に応答しなかったAPがあるか
+///
+/// trueの間、APがあとからトランポリンでページングを有効にする可能性があるため、
+/// トランポリンの恒等マップ（カーネルのPML4の下位半分）を外してはいけません。
+pub fn ap_boot_pending() -> bool {
+    AP_BOOT_PENDING.load(Ordering::Acquire)
+}
+
+/// 起動済みCPU数（BSPを含む）
+pub fn online_count() -> usize {
+    CPU_ONLINE
+        .iter()
+        .filter(|online| online.load(Ordering::Acquire))
+        .count()
+}
+
+/// CPUの情報
+///
+/// # Returns
+/// (Local APIC ID, 起動済みか)。範囲外ならNone
+pub fn cpu_info(index: usize) -> Option<(u8, bool)> {
+    if index >= cpu_count() {
+        return None;
+    }
+    Some((
+        CPU_APIC_IDS[index].load(Ordering::Relaxed),
+        CPU_ONLINE[index].load(Ordering::Acquire),
+    ))
+}
+
+/// 全APを起動
+///
+/// ヒープ（APのGDT/TSS、アイドルタスク）とLocal APICタイマーの初期化後、
+/// 割り込み無効状態でBSPから呼び出します。
+/// 起動に失敗したAPは警告を出して無視します。
+pub fn init() {
+    if let Err(e) = irq::request_irq(RESCHEDULE_VECTOR, handle_reschedule, "reschedule") {
+        warn!("SMP: reschedule IPI handler not registered: {}", e);
+    }
+    let bsp_id = apic::local_apic_id();
+    let count = cpu_count();
+    // BSPのCPU番号を percpu::BSP_INDEX にそろえる
+    if let Some(index) = CPU_APIC_IDS
+        .iter()
+        .take(count)
+        .position(|apic_id| apic_id.load(Ordering::Relaxed) == bsp_id)
+    {
+        let other = CPU_APIC_IDS[percpu::BSP_INDEX].swap(bsp_id, Ordering::Relaxed);
+        CPU_APIC_IDS[index].store(other, Ordering::Relaxed);
+        CPU_ONLINE[percpu::BSP_INDEX].store(true, Ordering::Release);
+    }
+    if count <= 1 {
+        info!("SMP: single processor");
+        return;
+    }
+
+    if let Err(e) = boot_aps(bsp_id, count) {
+        warn!("SMP: failed to start application processors: {}", e);
+    }
+    info!("SMP: {}/{} CPU(s) online", online_count(), count);
+}
+
+/// 再スケジュールIPIハンドラ
+///
+/// 送信元のCPUがneed_reschedフラグをセット済みのため何もしません。
+/// 割り込み復帰時にスケジューラが呼び出されます。
+fn handle_reschedule() -> IrqReturn {
+    IrqReturn::Handled
+}
+
+/// 他のCPUのTLBから仮想アドレス範囲 [start, end) のエントリを無効化（TLBシュートダウン）
+///
+/// 起動済みの他のCPUへNMIを送り、全CPUが無効化を終えるまで待ちます。ページテーブルの
+/// 呼び出し元はスピンロックを保持したまま割り込みを無効にしていることが多く、通常の
+/// 割り込みでは相手が同じロックで待っているとデッドロックするため、NMIを使います。
+/// 現在のCPUのTLBは呼び出し元が無効化してください。
+///
+/// NMIハンドラからは呼び出せません。
+///
+/// # Arguments
+/// * `start` - 範囲の開始仮想アドレス
+/// * `end` - 範囲の終了仮想アドレス（この値を含まない）
+pub fn flush_tlb_others(start: u64, end: u64) {
+    if online_count() <= 1 {
+        return;
+    }
+    without_interrupts(|| {
+        let _guard = SHOOTDOWN_LOCK.lock();
+        let current = percpu::current_index();
+        let targets = (0..cpu_count())
+            .filter(|&cpu| cpu != current && CPU_ONLINE[cpu].load(Ordering::Acquire))
+            .fold(0, |mask, cpu| mask | 1 << cpu);
+        if targets == 0 {
+            return;
+        }
+
+        SHOOTDOWN_START.store(start, Ordering::Relaxed);
+        SHOOTDOWN_END.store(end, Ordering::Relaxed);
+        SHOOTDOWN_PENDING.store(targets, Ordering::Release);
+        for (cpu, apic_id) in CPU_APIC_IDS.iter().enumerate() {
+            if targets & 1 << cpu != 0 {
+                apic::send_nmi_ipi(apic_id.load(Ordering::Relaxed));
+            }
+        }
+        while SHOOTDOWN_PENDING.load(Ordering::Acquire) != 0 {
+            core::hint::spin_loop();
+        }
+    });
+}
+
+/// このCPU宛てのTLBシュートダウンがあれば処理する（NMIハンドラから呼び出す）
+///
+/// # Returns
+/// 処理した場合はtrue
+pub fn handle_tlb_shootdown() -> bool {
+    let bit = 1 << percpu::current_index();
+    if SHOOTDOWN_PENDING.load(Ordering::Acquire) & bit == 0 {
+        return false;
+    }
+    paging::flush_tlb_local(
+        SHOOTDOWN_START.load(Ordering::Relaxed),
+        SHOOTDOWN_END.load(Ordering::Relaxed),
+    );
+    SHOOTDOWN_PENDING.fetch_and(!bit, Ordering::Release);
+    true
+}
+
+/// トランポリンを準備して各APを順に起動
+fn boot_aps(bsp_id: u8, count: usize) -> Result<(), SmpError> {
+    let cr3 = paging::read_cr3();
+    if cr3 >= 1 << 32 {
+        return Err(SmpError::PageTableTooHigh);
+    }
+    let trampoline_virt =
+        paging::phys_to_virt(TRAMPOLINE_PHYS).map_err(|_| SmpError::TrampolineUnavailable)?;
+
+    // シンボルはglobal_asm!で定義したトランポリンの境界で、同じセクション内にある
+    let code_start = &raw const smp_trampoline_start as usize;
+    let params_offset = &raw const smp_trampoline_params as usize - code_start;
+    let code_len = &raw const smp_trampoline_end as usize - code_start;
+
+    // SAFETY: TRAMPOLINE_PHYSはフレームアロケータが割り当てない低位メモリで、
+    // トランポリン（1ページ未満）は直接マッピング経由で書き込める
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            code_start as *const u8,
+            trampoline_virt as *mut u8,
+            code_len,
+        );
+    }
+    // ページングを有効にした直後のAPが同じアドレスで実行を続けられるよう恒等マップ
+    paging::map_page(
+        TRAMPOLINE_PHYS,
+        TRAMPOLINE_PHYS,
+        PageTableFlags::Writable as u64,
+    )
+    .map_err(|_| SmpError::TrampolineUnavailable)?;
+
+    let params = (trampoline_virt as usize + params_offset) as *mut TrampolineParams;
+    // SAFETY: paramsはトランポリン内のパラメータ領域を指し、APはまだ起動していない。
+    // CR0/CR4の読み込みは副作用がない
+    unsafe {
+        let cr0: u64;
+        let cr4: u64;
+        asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        params.write_volatile(TrampolineParams {
+            cr3,
+            efer: read_efer() & EFER_INHERITED,
+            cr0,
+            cr4,
+            entry: ap_entry as *const () as u64,
+            slots: [ApSlot {
+                apic_id: 0,
+                cpu_index: 0,
+                stack_top: 0,
+            }; MAX_CPUS],
+        });
+    }
+
+    let mut timed_out = false;
+    for (index, apic_id) in CPU_APIC_IDS.iter().enumerate().take(count) {
+        let apic_id = apic_id.load(Ordering::Relaxed);
+        if apic_id == bsp_id {
+            continue;
+        }
+        match boot_ap(index, apic_id, params) {
+            Ok(()) => info!("SMP: CPU #{} (APIC ID {}) online", index, apic_id),
+            Err(e) => {
+                timed_out |= e == SmpError::Timeout;
+                warn!("SMP: CPU #{} (APIC ID {}): {}", index, apic_id, e);
+            }
+        }
+    }
+
+    // 応答しなかったAPがあとからトランポリンでページングを有効にする可能性があるため、
+    // その場合は恒等マップを残す
+    if timed_out {
+        AP_BOOT_PENDING.store(true, Ordering::Release);
+    } else {
+        let _ = paging::unmap_page(TRAMPOLINE_PHYS);
+    }
+    Ok(())
+}
+
+/// 1台のAPをINIT/SIPI/SIPIで起動し、起動の報告を待つ
+fn boot_ap(index: usize, apic_id: u8, params: *mut TrampolineParams) -> Result<(), SmpError> {
+    let stack_phys =
+        frame_allocator::alloc_contiguous(AP_STACK_PAGES).ok_or(SmpError::StackAllocationFailed)?;
+    let stack_virt = match paging::phys_to_virt(stack_phys) {
+        Ok(virt) => virt,
+        Err(_) => {
+            let _ = frame_allocator::free_contiguous(stack_phys, AP_STACK_PAGES);
+            return Err(SmpError::StackAllocationFailed);
+        }
+    };
+
+    // SAFETY: paramsはトランポリン内のパラメータ領域を指し、このAPのスロットは
+    // まだどのAPも使っていない（スロットはCPU番号ごとに1度だけ書き込む）
+    unsafe {
+        (&raw mut (*params).slots[index]).write_volatile(ApSlot {
+            apic_id: apic_id as u64,
+            cpu_index: index as u64,
+            stack_top: stack_virt + (AP_STACK_PAGES * PAGE_SIZE) as u64,
+        });
+    }
+
+    // SAFETY: 送信先はMADTに記載されたBSP以外のCPUで、カーネルはまだ何も実行させていない。
+    // トランポリンはTRAMPOLINE_PHYSにコピー済み
+    unsafe {
+        apic::send_init_ipi(apic_id);
+        clock::delay_us(10_000);
+        let vector = (TRAMPOLINE_PHYS / PAGE_SIZE as u64) as u8;
+        apic::send_startup_ipi(apic_id, vector);
+        clock::delay_us(200);
+        if !CPU_ONLINE[index].load(Ordering::Acquire) {
+            apic::send_startup_ipi(apic_id, vector);
+        }
+    }
+
+    for _ in 0..AP_BOOT_TIMEOUT_MS * 10 {
+        if CPU_ONLINE[index].load(Ordering::Acquire) {
+            return Ok(());
+        }
+        clock::delay_us(100);
+    }
+    // 応答しないAPがあとからスタックを使う可能性があるため、スタックは解放しない
+    Err(SmpError::Timeout)
+}
+
+/// IA32_EFERを読み込む
+fn read_efer() -> u64 {
+    const IA32_EFER: u32 = 0xC000_0080;
+    let low: u32;
+    let high: u32;
+    // SAFETY: IA32_EFERはx86_64で常に存在し、読み込みは副作用がない
+    unsafe {
+        asm!(
+            "rdmsr",
+            in("ecx") IA32_EFER,
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    ((high as u64) << 32) | low as u64
+}
+
+/// APのエントリポイント（トランポリンからAPのスタック上で、スロットのCPU番号を引数に呼ばれる）
+extern "C" fn ap_entry(index: usize) -> ! {
+    // CPU番号はヒープやスケジューラが参照するため最初に設定する
+    percpu::init_ap(index);
+    // 拡張状態はBSPと同じ設定にする（タスク切り替えの保存領域の大きさが同じになる）
+    cpu::init_extended_state();
+    gdt::init_ap();
+    idt::load();
+    mce::init();
+    // フレームバッファのWCマッピングはBSPと同じPATの設定を前提とする
+    paging::init_pat();
+    apic::enable_apic();
+
+    // 起動時のコンテキストをこのCPUのアイドルタスクとして登録
+    // （new_idleで確保したスタックは使われず、現在のスタック上で実行を続ける）
+    // 失敗した場合は起動を報告せずに停止する（BSPはタイムアウトとして扱う）
+    match Task::new_idle("ApIdle", ap_idle_task) {
+        Ok(task) => sched::set_current_task(task),
+        Err(e) => {
+            warn!("SMP: CPU #{}: failed to create idle task: {}", index, e);
+            halt_forever();
+        }
+    }
+    if let Err(e) = apic::init_timer(timer::frequency_hz() as u32) {
+        warn!("SMP: CPU #{}: failed to start APIC timer: {}", index, e);
+    }
+    CPU_ONLINE[index].store(true, Ordering::Release);
+
+    ap_idle_task()
+}
+
+/// APのアイドルループ
+extern "C" fn ap_idle_task() -> ! {
+    loop {
+        // 停止する前に、他のCPUで待っているタスクを引き取る
+        sched::idle_balance();
+        // SAFETY: sti/hltは特権命令で、Ring 0で実行している。stiの直後のhltまでは
+        // 割り込みが入らないため、起床の取りこぼしはない
+        unsafe { asm!("sti", "hlt", options(nomem, nostack)) };
+    }
+}
+
+/// 割り込みを受けずに停止
+fn halt_forever() -> ! {
+    loop {
+        // SAFETY: cli/hltは特権命令で、Ring 0で実行している
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
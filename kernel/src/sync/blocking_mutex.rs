@@ -2,9 +2,11 @@
 //!
 //! スピンロックではなく、タスクをブロックすることで排他制御を行うMutex
 
+use super::lockstat::{LockKind, LockProfile};
 use super::wait_queue::WaitQueue;
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
+use core::panic::Location;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 /// ブロッキングMutex
@@ -19,6 +21,8 @@ pub struct BlockingMutex<T: ?Sized> {
     locked: AtomicBool,
     /// 待機キュー
     wait_queue: WaitQueue,
+    /// 競合の計測
+    profile: LockProfile,
     /// 保護対象データ
     data: UnsafeCell<T>,
 }
@@ -33,6 +37,7 @@ impl<T> BlockingMutex<T> {
         Self {
             locked: AtomicBool::new(false),
             wait_queue: WaitQueue::new(),
+            profile: LockProfile::new(),
             data: UnsafeCell::new(value),
         }
     }
@@ -44,7 +49,10 @@ impl<T> BlockingMutex<T> {
     ///
     /// # Returns
     /// ロックガード
+    #[track_caller]
     pub fn lock(&self) -> MutexGuard<'_, T> {
+        let site = Location::caller();
+        let mut contention = None;
         loop {
             // 楽観的にロック取得を試みる
             if self
@@ -52,8 +60,16 @@ impl<T> BlockingMutex<T> {
                 .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
                 .is_ok()
             {
+                if let Some(contention) = contention {
+                    self.profile
+                        .contention_end(contention, self.addr(), LockKind::Mutex, site);
+                }
+                self.profile.acquired(site);
                 return MutexGuard { mutex: self };
             }
+            if contention.is_none() {
+                contention = self.profile.contention_begin();
+            }
 
             // 取得失敗時、割り込みコンテキストでないことを確認
             if crate::sched::is_interrupt_context() {
@@ -74,12 +90,14 @@ impl<T> BlockingMutex<T> {
     ///
     /// # Returns
     /// ロックが取得できた場合はSome(MutexGuard)、できなかった場合はNone
+    #[track_caller]
     pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
         if self
             .locked
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_ok()
         {
+            self.profile.acquired(Location::caller());
             Some(MutexGuard { mutex: self })
         } else {
             None
@@ -87,11 +105,19 @@ impl<T> BlockingMutex<T> {
     }
 }
 
+impl<T: ?Sized> BlockingMutex<T> {
+    /// 競合の集計に使うアドレス
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+
 /// Mutexガード（RAII）
 ///
 /// Drop時にロックを自動的に解放し、待機中のタスクを起床させます。
 pub struct MutexGuard<'a, T: ?Sized> {
-    mutex: &'a BlockingMutex<T>,
+    /// ロックを保持しているMutex（CondVarが再取得に使用する）
+    pub(super) mutex: &'a BlockingMutex<T>,
 }
 
 impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
@@ -110,6 +136,9 @@ impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
 impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
     fn drop(&mut self) {
         // ロックを解放
+        self.mutex
+            .profile
+            .releasing(self.mutex.addr(), LockKind::Mutex);
         self.mutex.locked.store(false, Ordering::Release);
         // 待機中のタスクを1つ起床
         self.mutex.wait_queue.wake_one();
@@ -0,0 +1,83 @@
+//! Channel - タスク間でメッセージを受け渡す無制限長のキュー
+//!
+//! # 設計方針
+//! 受信側は「キューが空であることの確認」と「待機者としての登録」を同じ
+//! クリティカルセクションで行います。送信側が登録済みの待機者を起床させた時点で
+//! 受信側がまだブロックしていなくても、WAKEUP_PENDINGによりブロックせずに戻るため、
+//! 起床の取りこぼし（Lost Wakeup）は発生しません。
+
+use crate::io::without_interrupts;
+use crate::sched::TaskId;
+use alloc::collections::VecDeque;
+use spin::Mutex as SpinMutex;
+
+struct ChannelInner<T> {
+    /// 未受信のメッセージ
+    queue: VecDeque<T>,
+    /// 受信待ちのタスク
+    receivers: VecDeque<TaskId>,
+}
+
+/// タスク間のメッセージチャネル
+///
+/// 複数の送信者・受信者から共有できます（`Arc<Channel<T>>`）。
+/// メッセージは送信順に受信されます。
+pub struct Channel<T> {
+    inner: SpinMutex<ChannelInner<T>>,
+}
+
+impl<T> Channel<T> {
+    /// 空のチャネルを作成
+    pub const fn new() -> Self {
+        Self {
+            inner: SpinMutex::new(ChannelInner {
+                queue: VecDeque::new(),
+                receivers: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// メッセージを送信し、受信待ちのタスクがあれば1つ起床させる
+    ///
+    /// キューに上限はないため、送信側がブロックすることはありません。
+    pub fn send(&self, value: T) {
+        let receiver = without_interrupts(|| {
+            let mut inner = self.inner.lock();
+            inner.queue.push_back(value);
+            inner.receivers.pop_front()
+        });
+
+        // ロック解放後に起床させる
+        if let Some(id) = receiver {
+            crate::sched::unblock_task(id);
+        }
+    }
+
+    /// メッセージを受信（届くまでブロック）
+    pub fn recv(&self) -> T {
+        let me = crate::sched::current_task_id();
+        loop {
+            let received = without_interrupts(|| {
+                let mut inner = self.inner.lock();
+                let value = inner.queue.pop_front();
+                match value {
+                    // 起床以外の理由で戻った場合に備え、待機者の登録を取り消す
+                    Some(_) => inner.receivers.retain(|&id| id != me),
+                    None if !inner.receivers.contains(&me) => inner.receivers.push_back(me),
+                    None => {}
+                }
+                value
+            });
+            if let Some(value) = received {
+                return value;
+            }
+            crate::sched::block_current_task();
+        }
+    }
+
+    /// メッセージがあれば受信（ブロックしない）
+    #[allow(dead_code)]
+    pub fn try_recv(&self) -> Option<T> {
+        without_interrupts(|| self.inner.lock().queue.pop_front())
+    }
+}
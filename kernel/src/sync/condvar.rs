@@ -0,0 +1,106 @@
+//! 条件変数
+//!
+//! `BlockingMutex` と組み合わせて、条件が成立するまでタスクをブロックします。
+//! 待機者の登録はMutexを解放する前に行うため、解放直後の通知を取りこぼすことはありません。
+//! 通知以外の理由で戻ることもあるため、呼び出し側はループで条件を確認してください。
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::IrqSpinlock;
+use super::blocking_mutex::MutexGuard;
+use super::waiter::Waiter;
+
+/// 条件変数
+pub struct CondVar {
+    /// 通知待ちのタスク（到着順）
+    waiters: IrqSpinlock<VecDeque<Arc<Waiter>>>,
+}
+
+impl CondVar {
+    /// 新しい条件変数を作成
+    pub const fn new() -> Self {
+        Self {
+            waiters: IrqSpinlock::new(VecDeque::new()),
+        }
+    }
+
+    /// Mutexを解放して通知を待ち、戻る前にMutexを再取得
+    ///
+    /// # Arguments
+    /// * `guard` - 保持しているMutexのガード
+    ///
+    /// # Returns
+    /// 再取得したMutexのガード
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_inner(guard, None).0
+    }
+
+    /// Mutexを解放して通知を待ち、戻る前にMutexを再取得（タイムアウト付き）
+    ///
+    /// # Arguments
+    /// * `guard` - 保持しているMutexのガード
+    /// * `timeout_ms` - タイムアウト（ミリ秒）
+    ///
+    /// # Returns
+    /// 再取得したMutexのガードと、通知された場合はtrue（タイムアウトした場合はfalse）
+    #[allow(dead_code)]
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ms: u64,
+    ) -> (MutexGuard<'a, T>, bool) {
+        self.wait_inner(guard, Some(timeout_ms))
+    }
+
+    fn wait_inner<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ms: Option<u64>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let mutex = guard.mutex;
+        let waiter = Waiter::current();
+        self.waiters.lock().push_back(Arc::clone(&waiter));
+        drop(guard);
+
+        let notified = waiter.wait(timeout_ms);
+        if !notified {
+            self.waiters.lock().retain(|w| !Arc::ptr_eq(w, &waiter));
+        }
+        (mutex.lock(), notified)
+    }
+
+    /// 待機中のタスクを1つ起床させる
+    ///
+    /// # Returns
+    /// 起床させたタスクがあればtrue
+    pub fn notify_one(&self) -> bool {
+        let woken = {
+            let mut waiters = self.waiters.lock();
+            // タイムアウト済みの待機者は飛ばす
+            core::iter::from_fn(|| waiters.pop_front()).find_map(|w| w.notify())
+        };
+
+        // ロック解放後に起床させる
+        if let Some(id) = woken {
+            crate::sched::unblock_task(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 待機中のタスクをすべて起床させる
+    #[allow(dead_code)]
+    pub fn notify_all(&self) {
+        let woken: Vec<_> = {
+            let mut waiters = self.waiters.lock();
+            waiters.drain(..).filter_map(|w| w.notify()).collect()
+        };
+
+        for id in woken {
+            crate::sched::unblock_task(id);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! 割り込み安全なスピンロック
+//!
+//! ロック取得時にRFLAGSを保存して割り込みを無効化し、解放時に元の状態へ戻すスピンロック。
+//! 割り込みハンドラと通常コンテキストの両方から使うデータを保護します。
+//!
+//! 割り込みの無効化は自CPUのハンドラとのデッドロックを防ぐためのもので、
+//! 他のCPUとの排他はスピンで行います。
+
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::lockstat::{LockKind, LockProfile};
+
+/// RFLAGSの割り込み有効フラグ（IF）
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// 割り込み安全なスピンロック
+///
+/// ガードを保持している間は割り込みが無効になります。複数のロックを入れ子で取得した場合は、
+/// 取得と逆の順序で解放してください（先に外側を解放すると、内側を保持したまま割り込みが有効になる）。
+///
+/// # Safety
+/// 内部でロックにより排他アクセスを保証します。
+pub struct IrqSpinlock<T: ?Sized> {
+    /// ロック状態（true = ロック中）
+    locked: AtomicBool,
+    /// 競合の計測
+    profile: LockProfile,
+    /// 保護対象データ
+    data: UnsafeCell<T>,
+}
+
+// Safety: 内部でロックにより排他アクセスを保証
+unsafe impl<T: ?Sized + Send> Send for IrqSpinlock<T> {}
+unsafe impl<T: ?Sized + Send> Sync for IrqSpinlock<T> {}
+
+/// 割り込みを無効化し、無効化前に割り込みが有効だったかを返す
+fn save_and_disable_interrupts() -> bool {
+    let rflags: u64;
+    // SAFETY: PUSHFQ/POPでRFLAGSを読み、CLIで割り込みを無効化する。Ring 0で実行している
+    unsafe {
+        asm!("pushfq; pop {}; cli", out(reg) rflags, options(nomem));
+    }
+    rflags & RFLAGS_IF != 0
+}
+
+/// 保存しておいた割り込みの状態を復元
+fn restore_interrupts(enabled: bool) {
+    if enabled {
+        // SAFETY: 元々割り込みが有効だった場合にのみ実行する
+        unsafe {
+            asm!("sti", options(nomem, nostack));
+        }
+    }
+}
+
+impl<T> IrqSpinlock<T> {
+    /// 新しいIrqSpinlockを作成
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            profile: LockProfile::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> IrqSpinlock<T> {
+    /// ロックを取得
+    ///
+    /// 割り込みを無効化してから、ロックが解放されるまでスピンします。
+    ///
+    /// # Returns
+    /// ロックガード（dropで解放し、割り込みの状態を復元する）
+    #[track_caller]
+    pub fn lock(&self) -> IrqSpinlockGuard<'_, T> {
+        let site = Location::caller();
+        let irq_enabled = save_and_disable_interrupts();
+        let mut contention = None;
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            if contention.is_none() {
+                contention = self.profile.contention_begin();
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+        if let Some(contention) = contention {
+            self.profile
+                .contention_end(contention, self.addr(), LockKind::Spin, site);
+        }
+        self.profile.acquired(site);
+        IrqSpinlockGuard {
+            lock: self,
+            irq_enabled,
+        }
+    }
+
+    /// try_lock: ノンブロッキングでロック取得を試みる
+    ///
+    /// # Returns
+    /// ロックが取得できた場合はSome(IrqSpinlockGuard)、できなかった場合はNone
+    /// （割り込みの状態は変更しない）
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<IrqSpinlockGuard<'_, T>> {
+        let irq_enabled = save_and_disable_interrupts();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.profile.acquired(Location::caller());
+            Some(IrqSpinlockGuard {
+                lock: self,
+                irq_enabled,
+            })
+        } else {
+            restore_interrupts(irq_enabled);
+            None
+        }
+    }
+
+    /// ロックされているか（診断用）
+    #[allow(dead_code)]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// 競合の集計に使うアドレス
+    fn addr(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+
+/// IrqSpinlockのガード
+///
+/// dropでロックを解放し、ロック取得前の割り込みの状態を復元します。
+pub struct IrqSpinlockGuard<'a, T: ?Sized> {
+    lock: &'a IrqSpinlock<T>,
+    /// ロック取得前に割り込みが有効だったか
+    irq_enabled: bool,
+}
+
+impl<T: ?Sized> Deref for IrqSpinlockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: ガードが存在する間はロックを保持しており、排他アクセスが保証される
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSpinlockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: ガードが存在する間はロックを保持しており、排他アクセスが保証される
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSpinlockGuard<'_, T> {
+    fn drop(&mut self) {
+        // ロックを解放してから割り込みを有効化する（逆だと解放前に割り込みハンドラが
+        // 同じロックを取得しようとしてデッドロックする）
+        self.lock
+            .profile
+            .releasing(self.lock.addr(), LockKind::Spin);
+        self.lock.locked.store(false, Ordering::Release);
+        restore_interrupts(self.irq_enabled);
+    }
+}
@@ -0,0 +1,254 @@
+//! ロック競合の計測
+//!
+//! `BlockingMutex` と `IrqSpinlock` の競合（取得待ち）を記録し、どのロックの分割や
+//! 置き換えを検討すべきかを `lockstat` コマンドで示します。
+//!
+//! 計測は `set_enabled` で有効にした間だけ行います。無効な間の追加コストは、
+//! ロック取得・解放時のフラグの読み出しのみです。有効な間は次を記録します。
+//! - 競合した取得: 待ち時間、待機者数、その時点の保持タスク（トレースにも記録）
+//! - 待機者がいる間の保持: 保持時間
+//!
+//! 集計はロックのアドレスごとで、名前の代わりに最初に記録した取得箇所（ソースの位置）を
+//! 表示します。集計表は `MAX_LOCKS` 個までで、あふれた分は件数のみ数えます。
+//! ロック自身の計測で再帰しないよう、集計表は計測しない `spin::Mutex` で保護します。
+
+use alloc::vec::Vec;
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+
+/// 集計するロックの最大数
+pub const MAX_LOCKS: usize = 64;
+
+/// 保持タスクなし
+const NO_TASK: u64 = u64::MAX;
+
+/// ロックの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// `BlockingMutex`
+    Mutex,
+    /// `IrqSpinlock`
+    Spin,
+}
+
+impl LockKind {
+    /// 表示名
+    pub fn name(self) -> &'static str {
+        match self {
+            LockKind::Mutex => "mutex",
+            LockKind::Spin => "spin",
+        }
+    }
+}
+
+/// 計測が有効か
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 集計表に入らなかった記録の数
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// ロックごとの集計
+#[derive(Debug, Clone, Copy)]
+pub struct LockStat {
+    /// ロックのアドレス
+    pub addr: usize,
+    pub kind: LockKind,
+    /// 最初に記録した取得箇所
+    pub site: &'static Location<'static>,
+    /// 競合した取得の回数
+    pub contentions: u64,
+    /// 待ち時間の合計（ナノ秒）
+    pub wait_total_ns: u64,
+    /// 待ち時間の最大（ナノ秒）
+    pub wait_max_ns: u64,
+    /// 観測した待機者数の最大
+    pub max_waiters: u32,
+    /// 待機者がいる間の保持の回数
+    pub contended_holds: u64,
+    /// 待機者がいる間の保持時間の最大（ナノ秒）
+    pub hold_max_ns: u64,
+    /// 最後に競合したときの保持タスク（不明ならNone）
+    pub last_holder: Option<u64>,
+}
+
+impl LockStat {
+    fn new(addr: usize, kind: LockKind, site: &'static Location<'static>) -> Self {
+        Self {
+            addr,
+            kind,
+            site,
+            contentions: 0,
+            wait_total_ns: 0,
+            wait_max_ns: 0,
+            max_waiters: 0,
+            contended_holds: 0,
+            hold_max_ns: 0,
+            last_holder: None,
+        }
+    }
+}
+
+static TABLE: Mutex<[Option<LockStat>; MAX_LOCKS]> = Mutex::new([None; MAX_LOCKS]);
+
+/// 集計表のエントリを更新（なければ追加）
+fn update(
+    addr: usize,
+    kind: LockKind,
+    site: &'static Location<'static>,
+    f: impl FnOnce(&mut LockStat),
+) {
+    // IrqSpinlockは割り込みハンドラからも記録するため、同じCPUでのデッドロックを防ぐ
+    without_interrupts(|| {
+        let mut table = TABLE.lock();
+        if let Some(stat) = table.iter_mut().flatten().find(|s| s.addr == addr) {
+            f(stat);
+            return;
+        }
+        match table.iter_mut().find(|s| s.is_none()) {
+            Some(slot) => f(slot.insert(LockStat::new(addr, kind, site))),
+            None => {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+/// 計測が有効か
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// 計測の有効/無効を切り替える
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// 集計をすべて消去
+pub fn reset() {
+    without_interrupts(|| *TABLE.lock() = [None; MAX_LOCKS]);
+    DROPPED.store(0, Ordering::Relaxed);
+}
+
+/// 集計表に入らなかった記録の数
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// 集計を待ち時間の合計の多い順に取得
+pub fn report() -> Vec<LockStat> {
+    let mut stats: Vec<LockStat> =
+        without_interrupts(|| TABLE.lock().iter().flatten().copied().collect());
+    stats.sort_unstable_by_key(|stat| core::cmp::Reverse(stat.wait_total_ns));
+    stats
+}
+
+/// ロックに埋め込む計測用の状態
+///
+/// 保持タスクと取得時刻は計測が有効な間だけ更新します。
+pub(super) struct LockProfile {
+    /// 保持しているタスクのID（`NO_TASK` なら不明）
+    holder: AtomicU64,
+    /// 取得した時刻（0なら未計測）
+    acquired_ns: AtomicU64,
+    /// 取得した箇所
+    site: AtomicPtr<Location<'static>>,
+    /// 取得を待っているタスク・CPUの数
+    waiters: AtomicU32,
+}
+
+/// 競合の開始時に記録した情報
+pub(super) struct Contention {
+    start_ns: u64,
+    holder: u64,
+    waiters: u32,
+}
+
+impl LockProfile {
+    pub(super) const fn new() -> Self {
+        Self {
+            holder: AtomicU64::new(NO_TASK),
+            acquired_ns: AtomicU64::new(0),
+            site: AtomicPtr::new(core::ptr::null_mut()),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// ロックを取得した直後に呼び出す
+    #[inline]
+    pub(super) fn acquired(&self, site: &'static Location<'static>) {
+        if !is_enabled() {
+            return;
+        }
+        let task = crate::sched::current_task_id_lockless().map_or(NO_TASK, |id| id.as_u64());
+        self.holder.store(task, Ordering::Relaxed);
+        self.site
+            .store(site as *const _ as *mut _, Ordering::Relaxed);
+        // 0は未計測を表すため、最小でも1にする
+        self.acquired_ns
+            .store(crate::clock::monotonic_ns().max(1), Ordering::Relaxed);
+    }
+
+    /// 最初の取得に失敗したときに呼び出す（計測が無効ならNone）
+    pub(super) fn contention_begin(&self) -> Option<Contention> {
+        if !is_enabled() {
+            return None;
+        }
+        let waiters = self.waiters.fetch_add(1, Ordering::Relaxed) + 1;
+        Some(Contention {
+            start_ns: crate::clock::monotonic_ns(),
+            holder: self.holder.load(Ordering::Relaxed),
+            waiters,
+        })
+    }
+
+    /// 競合の後でロックを取得したときに呼び出す（`acquired` の前）
+    pub(super) fn contention_end(
+        &self,
+        contention: Contention,
+        addr: usize,
+        kind: LockKind,
+        site: &'static Location<'static>,
+    ) {
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        let wait_ns = crate::clock::monotonic_ns().saturating_sub(contention.start_ns);
+        crate::trace::record(
+            crate::trace::TraceKind::LockContention,
+            addr as u64,
+            wait_ns,
+        );
+        update(addr, kind, site, |stat| {
+            stat.contentions += 1;
+            stat.wait_total_ns += wait_ns;
+            stat.wait_max_ns = stat.wait_max_ns.max(wait_ns);
+            stat.max_waiters = stat.max_waiters.max(contention.waiters);
+            stat.last_holder = (contention.holder != NO_TASK).then_some(contention.holder);
+        });
+    }
+
+    /// ロックを解放する直前に呼び出す
+    #[inline]
+    pub(super) fn releasing(&self, addr: usize, kind: LockKind) {
+        // 計測していない取得では読み出しのみで済ませる
+        if self.acquired_ns.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let acquired_ns = self.acquired_ns.swap(0, Ordering::Relaxed);
+        if acquired_ns == 0 || !is_enabled() || self.waiters.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let site = self.site.load(Ordering::Relaxed);
+        if site.is_null() {
+            return;
+        }
+        // SAFETY: siteは `acquired` で保存した &'static Location
+        let site: &'static Location<'static> = unsafe { &*site };
+        let hold_ns = crate::clock::monotonic_ns().saturating_sub(acquired_ns);
+        update(addr, kind, site, |stat| {
+            stat.contended_holds += 1;
+            stat.hold_max_ns = stat.hold_max_ns.max(hold_ns);
+        });
+    }
+}
@@ -1,8 +1,19 @@
 //! 同期プリミティブ
 //!
-//! このモジュールはブロッキング同期プリミティブを提供します。
+//! このモジュールはブロッキング同期プリミティブ（Mutex、セマフォ、条件変数、チャネル）と、
+//! 割り込み安全なスピンロックを提供します。ロックの競合は `lockstat` で計測できます。
 
 pub mod blocking_mutex;
+pub mod channel;
+pub mod condvar;
+pub mod irq_spinlock;
+pub mod lockstat;
+pub mod semaphore;
 pub mod wait_queue;
+mod waiter;
 
 pub use blocking_mutex::BlockingMutex;
+pub use channel::Channel;
+pub use condvar::CondVar;
+pub use irq_spinlock::IrqSpinlock;
+pub use semaphore::Semaphore;
@@ -0,0 +1,119 @@
+//! 計数セマフォ
+//!
+//! 資源の数をカウントし、取得できない場合はタスクをブロックします。
+//! 解放時に待機者がいれば、カウントを増やさず資源をそのまま待機者に渡すため、
+//! 起床したタスクが他のタスクに資源を横取りされることはありません。
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use super::IrqSpinlock;
+use super::waiter::Waiter;
+
+struct SemaphoreInner {
+    /// 取得可能な資源の数
+    count: usize,
+    /// 取得待ちのタスク（到着順）
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+/// 計数セマフォ
+///
+/// 割り込みコンテキストからは `try_acquire` と `release` のみ使用できます。
+pub struct Semaphore {
+    inner: IrqSpinlock<SemaphoreInner>,
+}
+
+impl Semaphore {
+    /// 初期カウントを指定してセマフォを作成
+    pub const fn new(count: usize) -> Self {
+        Self {
+            inner: IrqSpinlock::new(SemaphoreInner {
+                count,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// 資源を1つ取得（取得できるまでブロック）
+    pub fn acquire(&self) {
+        self.acquire_inner(None);
+    }
+
+    /// 資源を1つ取得（タイムアウト付き）
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、0ならブロックしない）
+    ///
+    /// # Returns
+    /// 取得できた場合はtrue、タイムアウトした場合はfalse
+    #[allow(dead_code)]
+    pub fn acquire_timeout(&self, timeout_ms: u64) -> bool {
+        self.acquire_inner(Some(timeout_ms))
+    }
+
+    /// 資源があれば1つ取得（ブロックしない）
+    ///
+    /// # Returns
+    /// 取得できた場合はtrue
+    #[allow(dead_code)]
+    pub fn try_acquire(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.count > 0 {
+            inner.count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn acquire_inner(&self, timeout_ms: Option<u64>) -> bool {
+        let waiter = {
+            let mut inner = self.inner.lock();
+            if inner.count > 0 {
+                inner.count -= 1;
+                return true;
+            }
+            if timeout_ms == Some(0) {
+                return false;
+            }
+            let waiter = Waiter::current();
+            inner.waiters.push_back(Arc::clone(&waiter));
+            waiter
+        };
+
+        if waiter.wait(timeout_ms) {
+            // release()から資源を直接受け取った
+            return true;
+        }
+        self.inner
+            .lock()
+            .waiters
+            .retain(|w| !Arc::ptr_eq(w, &waiter));
+        false
+    }
+
+    /// 資源を1つ解放し、待機中のタスクがあれば1つ起床させる
+    pub fn release(&self) {
+        let woken = {
+            let mut inner = self.inner.lock();
+            // タイムアウト済みの待機者は飛ばす
+            let woken = core::iter::from_fn(|| inner.waiters.pop_front()).find_map(|w| w.notify());
+            if woken.is_none() {
+                inner.count += 1;
+            }
+            woken
+        };
+
+        // ロック解放後に起床させる
+        if let Some(id) = woken {
+            crate::sched::unblock_task(id);
+        }
+    }
+
+    /// 現在取得可能な資源の数
+    #[allow(dead_code)]
+    pub fn count(&self) -> usize {
+        self.inner.lock().count
+    }
+}
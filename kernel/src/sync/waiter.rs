@@ -0,0 +1,80 @@
+//! タイムアウト付き待機の共通部品
+//!
+//! タイマーは取り消せないため、待機者ごとに状態を持たせ、起床側とタイマーのうち
+//! 先に状態を変更した側だけがタスクを起床させます。遅れて期限を迎えたタイマーは何もしません。
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::sched::TaskId;
+
+/// 待機中
+const WAITING: u8 = 0;
+/// 起床側に起こされた
+const NOTIFIED: u8 = 1;
+/// タイムアウトした
+const TIMED_OUT: u8 = 2;
+
+/// 待機中のタスク1件
+pub(super) struct Waiter {
+    /// 待機しているタスク
+    task_id: TaskId,
+    /// 待機状態（WAITING / NOTIFIED / TIMED_OUT）
+    state: AtomicU8,
+}
+
+impl Waiter {
+    /// 現在のタスクの待機者を作成
+    pub(super) fn current() -> Arc<Self> {
+        Arc::new(Self {
+            task_id: crate::sched::current_task_id(),
+            state: AtomicU8::new(WAITING),
+        })
+    }
+
+    /// 待機中なら起床済みにする
+    ///
+    /// # Returns
+    /// 起床させるべきタスク（既にタイムアウトしていればNone）。
+    /// 呼び出し側はロックを解放した後に `unblock_task` を呼び出すこと
+    pub(super) fn notify(&self) -> Option<TaskId> {
+        self.state
+            .compare_exchange(WAITING, NOTIFIED, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| self.task_id)
+    }
+
+    /// 起床されるか、タイムアウトするまでブロック
+    ///
+    /// # Arguments
+    /// * `timeout_ms` - タイムアウト（ミリ秒、Noneなら無期限）
+    ///
+    /// # Returns
+    /// 起床された場合はtrue、タイムアウトした場合はfalse
+    pub(super) fn wait(self: &Arc<Self>, timeout_ms: Option<u64>) -> bool {
+        if let Some(ms) = timeout_ms {
+            let waiter = Arc::clone(self);
+            let ticks = crate::timer::ms_to_ticks(ms).max(1);
+            crate::timer::register_timer(
+                ticks,
+                crate::timer::TimerClass::Wakeup,
+                Box::new(move || {
+                    if waiter
+                        .state
+                        .compare_exchange(WAITING, TIMED_OUT, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        crate::sched::unblock_task(waiter.task_id);
+                    }
+                }),
+            );
+        }
+
+        // 無関係な起床で戻った場合は、状態が変わるまで待ち直す
+        while self.state.load(Ordering::Acquire) == WAITING {
+            crate::sched::block_current_task();
+        }
+        self.state.load(Ordering::Acquire) == NOTIFIED
+    }
+}
@@ -0,0 +1,376 @@
+//! システムコール
+//!
+//! `int 0x80` でカーネルに入り、番号で引くテーブルから各システムコールを呼び出します。
+//! 呼び出し規約はLinuxに倣い、RAXにシステムコール番号、RDI/RSI/RDX/R10/R8/R9に引数を渡し、
+//! 戻り値はRAXに返します。エラーは負のエラー番号（-EFAULTなど）で返します。
+//!
+//! ゲートのDPLは3で、Ring 3のコードからも呼び出せます。ユーザーモードからの呼び出しでは
+//! ポインタがユーザー空間（下位アドレス）にあることを、どちらの場合もマップ済みであることを
+//! 検証してから参照するため、不正なポインタでカーネルがフォルトすることはありません。
+//!
+//! # 制限
+//! カーネル空間はすべてのタスクで共有しているため、検証後に他のタスクが
+//! ページのマッピングを解除した場合は保護されません。
+
+use alloc::collections::BTreeMap;
+use core::fmt::Write;
+
+use crate::graphics::TaskWriter;
+use crate::paging::{self, PAGE_SIZE, USER_SPACE_END};
+use crate::percpu;
+use crate::sched::{self, TaskId};
+use crate::sync::BlockingMutex;
+
+/// システムコールの割り込みベクタ
+pub const INTERRUPT_VECTOR: u8 = 0x80;
+
+/// 1回のsys_writeで書き込める最大バイト数
+const MAX_WRITE_LEN: u64 = 64 * 1024;
+
+/// システムコール番号
+pub mod number {
+    /// sys_write(fd, buf, len)
+    pub const WRITE: u64 = 0;
+    /// sys_sleep_ms(ms)
+    pub const SLEEP_MS: u64 = 1;
+    /// sys_yield()
+    pub const YIELD: u64 = 2;
+    /// sys_exit(code)
+    #[allow(dead_code)]
+    pub const EXIT: u64 = 3;
+}
+
+/// 標準出力のファイルディスクリプタ
+const STDOUT: u64 = 1;
+/// 標準エラー出力のファイルディスクリプタ
+const STDERR: u64 = 2;
+
+/// システムコールのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallError {
+    /// 不正なファイルディスクリプタ（EBADF）
+    BadFileDescriptor,
+    /// 不正なポインタ（EFAULT）
+    BadAddress,
+    /// 不正な引数（EINVAL）
+    InvalidArgument,
+    /// 存在しないシステムコール番号（ENOSYS）
+    NotImplemented,
+}
+
+impl SyscallError {
+    /// Linux互換のエラー番号
+    pub fn errno(&self) -> u64 {
+        match self {
+            SyscallError::BadFileDescriptor => 9,
+            SyscallError::BadAddress => 14,
+            SyscallError::InvalidArgument => 22,
+            SyscallError::NotImplemented => 38,
+        }
+    }
+
+    /// RAXに返す値（負のエラー番号）
+    fn as_return(&self) -> u64 {
+        self.errno().wrapping_neg()
+    }
+}
+
+impl core::fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            SyscallError::BadFileDescriptor => write!(f, "Bad file descriptor"),
+            SyscallError::BadAddress => write!(f, "Bad address"),
+            SyscallError::InvalidArgument => write!(f, "Invalid argument"),
+            SyscallError::NotImplemented => write!(f, "Function not implemented"),
+        }
+    }
+}
+
+/// システムコールの引数
+struct SyscallArgs {
+    /// RDI, RSI, RDX, R10, R8, R9
+    regs: [u64; 6],
+    /// ユーザーモード（Ring 3）からの呼び出しか
+    from_user: bool,
+}
+
+/// システムコールハンドラ
+type SyscallHandler = fn(&SyscallArgs) -> Result<u64, SyscallError>;
+
+/// システムコールの定義
+struct Syscall {
+    /// 名前（ログ用）
+    #[allow(dead_code)]
+    name: &'static str,
+    /// ハンドラ
+    handler: SyscallHandler,
+}
+
+/// システムコールテーブル（インデックスがシステムコール番号）
+const SYSCALL_TABLE: &[Syscall] = &[
+    Syscall {
+        name: "write",
+        handler: sys_write,
+    },
+    Syscall {
+        name: "sleep_ms",
+        handler: sys_sleep_ms,
+    },
+    Syscall {
+        name: "yield",
+        handler: sys_yield,
+    },
+    Syscall {
+        name: "exit",
+        handler: sys_exit,
+    },
+];
+
+/// タスクごとの出力先ウィンドウ（登録がなければシリアルに出力）
+static CONSOLES: BlockingMutex<BTreeMap<u64, TaskWriter>> = BlockingMutex::new(BTreeMap::new());
+
+/// 現在のタスクのsys_writeの出力先をウィンドウに設定
+#[allow(dead_code)]
+pub fn set_console(writer: TaskWriter) {
+    let id = sched::current_task_id().as_u64();
+    CONSOLES.lock().insert(id, writer);
+}
+
+/// 終了したタスクの出力先ウィンドウの登録を解除
+///
+/// Reaperタスクから呼び出されます。
+pub fn release_task_console(task_id: TaskId) {
+    CONSOLES.lock().remove(&task_id.as_u64());
+}
+
+/// 呼び出し元から渡されたバッファを検証してスライスとして取得
+///
+/// # Errors
+/// * `SyscallError::InvalidArgument` - 長さが上限を超える場合
+/// * `SyscallError::BadAddress` - NULL、アドレスのオーバーフロー、マップされていないページを含む場合、
+///   またはユーザーモードからの呼び出しでユーザー空間外かユーザー権限のないページを含む場合
+fn user_bytes<'a>(
+    args: &SyscallArgs,
+    ptr: u64,
+    len: u64,
+    max_len: u64,
+) -> Result<&'a [u8], SyscallError> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if len > max_len {
+        return Err(SyscallError::InvalidArgument);
+    }
+    if ptr == 0 {
+        return Err(SyscallError::BadAddress);
+    }
+    let end = ptr.checked_add(len).ok_or(SyscallError::BadAddress)?;
+    if args.from_user && end > USER_SPACE_END {
+        return Err(SyscallError::BadAddress);
+    }
+
+    // 範囲内のすべてのページがマップ済み（またはスワップイン可能）であること。
+    // ユーザーモードからの呼び出しでは、カーネルが代わりにスーパーバイザ専用のページを
+    // 読み書きしないよう、すべての階層でユーザー権限があることも確認する
+    let page_size = PAGE_SIZE as u64;
+    let mut page = ptr & !(page_size - 1);
+    while page < end {
+        let accessible = if args.from_user {
+            paging::is_user_page(page)
+        } else {
+            paging::translate(page).is_some() || paging::is_swapped_out(page)
+        };
+        if !accessible {
+            return Err(SyscallError::BadAddress);
+        }
+        page += page_size;
+    }
+
+    // SAFETY: 範囲がオーバーフローせず、すべてのページがマップ済みであることを確認した。
+    // スワップアウト済みのページはページフォルトハンドラが読み込む。
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const u8, len as usize) })
+}
+
+/// sys_write(fd, buf, len): 標準出力・標準エラー出力に書き込む
+///
+/// 出力先は `set_console` で登録したウィンドウ、登録がなければシリアルです。
+/// UTF-8として不正なバイト列は置換文字（U+FFFD）として出力します。
+///
+/// # Returns
+/// 書き込んだバイト数
+fn sys_write(args: &SyscallArgs) -> Result<u64, SyscallError> {
+    let [fd, ptr, len, ..] = args.regs;
+    if fd != STDOUT && fd != STDERR {
+        return Err(SyscallError::BadFileDescriptor);
+    }
+    let data = user_bytes(args, ptr, len, MAX_WRITE_LEN)?;
+
+    let id = sched::current_task_id().as_u64();
+    let mut consoles = CONSOLES.lock();
+    match consoles.get_mut(&id) {
+        Some(writer) => {
+            write_utf8_lossy(writer, data);
+            writer.flush();
+        }
+        None => {
+            drop(consoles);
+            write_utf8_lossy(&mut crate::serial::SerialPort::new(0x3F8), data);
+        }
+    }
+    Ok(len)
+}
+
+/// バイト列をUTF-8として出力（不正なバイト列は置換文字にする）
+fn write_utf8_lossy(writer: &mut impl Write, data: &[u8]) {
+    for chunk in data.utf8_chunks() {
+        let _ = writer.write_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            let _ = writer.write_char(char::REPLACEMENT_CHARACTER);
+        }
+    }
+}
+
+/// sys_sleep_ms(ms): 指定ミリ秒スリープ
+fn sys_sleep_ms(args: &SyscallArgs) -> Result<u64, SyscallError> {
+    sched::sleep_ms(args.regs[0]);
+    Ok(0)
+}
+
+/// sys_yield(): 他のタスクにCPUを譲る
+fn sys_yield(_args: &SyscallArgs) -> Result<u64, SyscallError> {
+    sched::schedule();
+    Ok(0)
+}
+
+/// sys_exit(code): 現在のタスクを終了（戻らない）
+fn sys_exit(args: &SyscallArgs) -> Result<u64, SyscallError> {
+    crate::info!(
+        "Task {} exited with code {}",
+        sched::current_task_id().as_u64(),
+        args.regs[0] as i64
+    );
+    sched::exit()
+}
+
+/// システムコール番号からハンドラを呼び出す
+///
+/// # Returns
+/// ハンドラの戻り値、またはエラー時は負のエラー番号
+fn dispatch(number: u64, args: &SyscallArgs) -> u64 {
+    crate::trace::record(
+        crate::trace::TraceKind::Syscall,
+        sched::current_task_id_lockless().map_or(u64::MAX, |id| id.as_u64()),
+        number,
+    );
+    let result = usize::try_from(number)
+        .ok()
+        .and_then(|n| SYSCALL_TABLE.get(n))
+        .ok_or(SyscallError::NotImplemented)
+        .and_then(|syscall| (syscall.handler)(args));
+    match result {
+        Ok(value) => value,
+        Err(e) => e.as_return(),
+    }
+}
+
+/// エントリで保存したレジスタとCPUが積んだ割り込みフレーム
+///
+/// スタック上のレイアウトを表すため、参照しないフィールドも含む
+#[allow(dead_code)]
+#[repr(C)]
+struct SyscallFrame {
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+    rcx: u64,
+    /// 入力: システムコール番号、出力: 戻り値
+    rax: u64,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+/// `int 0x80` のエントリポイント
+///
+/// caller-savedレジスタを保存して `SyscallFrame` を組み立て、ハンドラを呼び出します。
+/// CPUが積む5ワードと保存する9ワードで、call時のスタックは16バイト境界に揃います。
+#[unsafe(naked)]
+pub extern "C" fn syscall_entry() {
+    core::arch::naked_asm!(
+        // Ring 3から入った場合はカーネルのGSベースに切り替える
+        percpu::swapgs_if_user!("[rsp + 8]"),
+        "push rax",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "mov rdi, rsp",
+        "call {handler_inner}",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rax",
+        percpu::swapgs_if_user!("[rsp + 8]"),
+        "iretq",
+        handler_inner = sym syscall_handler_inner,
+    )
+}
+
+/// システムコールハンドラの実装
+extern "C" fn syscall_handler_inner(frame: &mut SyscallFrame) {
+    // 割り込みゲート経由でIF=0になっているため、スリープなどでブロックできるよう有効化する
+    // SAFETY: STI命令は割り込みフラグを有効化するのみで安全。
+    unsafe {
+        core::arch::asm!("sti", options(nomem, nostack));
+    }
+
+    let args = SyscallArgs {
+        regs: [
+            frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+        ],
+        from_user: frame.cs & 0b11 == 3,
+    };
+    frame.rax = dispatch(frame.rax, &args);
+
+    // iretqでRFLAGSが復元されるまでの間に割り込まれないよう無効化する
+    // SAFETY: CLI命令は割り込みフラグを無効化するのみで安全。
+    unsafe {
+        core::arch::asm!("cli", options(nomem, nostack));
+    }
+}
+
+/// カーネルタスクからシステムコールを呼び出す（ktest用）
+///
+/// # Returns
+/// RAXに返された値（エラー時は負のエラー番号）
+pub fn invoke(number: u64, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+    let ret: u64;
+    // SAFETY: エントリはRAX以外のレジスタを保存して復帰する。
+    // ポインタ引数はシステムコール側で検証されるため、不正な値でもメモリ安全性を損なわない。
+    unsafe {
+        core::arch::asm!(
+            "int 0x80",
+            inlateout("rax") number => ret,
+            in("rdi") arg0,
+            in("rsi") arg1,
+            in("rdx") arg2,
+        );
+    }
+    ret
+}
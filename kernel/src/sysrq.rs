@@ -0,0 +1,87 @@
+//! 緊急用SysRq機能
+//!
+//! Linuxのmagic SysRqに倣い、Ctrl+Alt+PrtScに続けて押したキーでコマンドを実行します。
+//! キーボード割り込みハンドラから直接呼ばれるため、シェルやコンポジタが停止していても
+//! 動作します。割り込みコンテキストで実行されるので、ロックはtry_lockのみを使用し、
+//! 出力はロックを持たないシリアルへ直接書き込みます。
+//!
+//! | キー | 動作 |
+//! |------|------|
+//! | b | 即座に再起動 |
+//! | o | 即座に電源を切る |
+//! | s | シリアルへのログを送信完了まで待つ |
+//! | t | タスクの状態をダンプ |
+//! | m | メモリ情報をダンプ |
+//! | c | テスト用のパニックを発生 |
+
+use crate::io::port_write_u8;
+use crate::{frame_allocator, power, println, sched, serial};
+
+/// SysRqコマンドを実行
+///
+/// # Arguments
+/// * `key` - Ctrl+Alt+PrtScの後に押されたキー（小文字）
+pub fn handle(key: u8) {
+    println!("\n[SysRq] {}", key as char);
+
+    match key {
+        b'b' => reboot(),
+        b'o' => power::force_off(),
+        b's' => {
+            serial::flush();
+            println!("[SysRq] Emergency log flush complete");
+        }
+        b't' => sched::dump_tasks(),
+        b'm' => dump_memory(),
+        b'c' => panic!("SysRq: triggered test crash"),
+        _ => {
+            println!("[SysRq] Help: b=reboot o=poweroff s=flush-log t=tasks m=memory c=crash");
+        }
+    }
+}
+
+/// メモリ情報をダンプ
+fn dump_memory() {
+    match frame_allocator::try_stats() {
+        Some(stats) => println!(
+            "[SysRq] Frames: {} free / {} total ({} MB free)",
+            stats.free_frames,
+            stats.total_frames,
+            stats.free_frames * crate::paging::PAGE_SIZE / 1024 / 1024
+        ),
+        None => println!("[SysRq] Frames: <allocator locked>"),
+    }
+
+    let gfx = crate::graphics::compositor::memory_usage();
+    println!(
+        "[SysRq] Graphics buffers: {} ({} KB)",
+        gfx.buffers,
+        gfx.bytes / 1024
+    );
+}
+
+/// システムを即座に再起動
+///
+/// 8042キーボードコントローラのリセットパルスを使用し、
+/// 失敗した場合はトリプルフォールトで再起動します。
+fn reboot() -> ! {
+    println!("[SysRq] Rebooting...");
+    serial::flush();
+
+    // SAFETY: 0x64に0xFEを書き込むと8042がCPUリセットラインをパルスする。
+    // 再起動のため以降の状態は問わない。
+    unsafe {
+        port_write_u8(0x64, 0xFE);
+    }
+
+    // リセットされなかった場合は空のIDTをロードして例外を発生させ、トリプルフォールトさせる
+    let null_idt = [0u16; 5];
+    // SAFETY: 再起動のため意図的にトリプルフォールトを発生させる
+    unsafe {
+        core::arch::asm!("lidt [{}]", "int3", in(reg) &null_idt, options(nostack));
+    }
+
+    loop {
+        core::hint::spin_loop();
+    }
+}
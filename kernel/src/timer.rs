@@ -4,13 +4,18 @@
 //! 割り込みハンドラでは期限切れタイマーの検出とsoftirqフラグのセットのみを行い、
 //! 実際のコールバック実行は割り込み復帰時のsoftirq処理で行うことで
 //! 割り込み無効時間を最小化します（Linux風 Bottom Half）。
+//!
+//! 期限切れのコールバックはクラス（`TimerClass`）ごとのキューに入り、優先度の高いクラスから
+//! 実行されます。重い低優先度のコールバック（統計の採取など）が、タスクの起床のような
+//! 遅延に敏感なコールバックを待たせないようにするためです。
 
 use alloc::boxed::Box;
 use alloc::collections::{BinaryHeap, VecDeque};
 use core::cmp::Ordering;
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
 use lazy_static::lazy_static;
-use spin::Mutex;
+
+use crate::sync::IrqSpinlock;
 
 /// グローバルタイマーカウンタ（tick数）
 static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -30,12 +35,60 @@ static IN_SOFTIRQ: AtomicBool = AtomicBool::new(false);
 /// タイマーコールバック型
 pub type TimerCallback = Box<dyn FnOnce() + Send + 'static>;
 
+/// タイマーコールバックのクラス数
+pub const TIMER_CLASS_COUNT: usize = 3;
+
+/// タイマーコールバックのクラス（優先度の高い順）
+///
+/// softirqでは優先度の高いクラスから実行し、コールバックを1つ実行するたびに
+/// より高いクラスに期限切れのものがないか確認し直します。各クラスは1巡あたり
+/// `budget()` 個まで実行でき、期限切れのあるクラスがすべて使い切ると次の巡に移ります。
+/// これにより高いクラスが大量にあっても低いクラスが飢餓状態になりません。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClass {
+    /// タスクの起床（sleep、タイムアウト）。短時間で終わること
+    Wakeup = 0,
+    /// 通常のコールバック
+    Normal = 1,
+    /// 遅れてもよい重いコールバック（統計の採取など）
+    Background = 2,
+}
+
+impl TimerClass {
+    /// 全クラス（優先度の高い順）
+    pub const ALL: [TimerClass; TIMER_CLASS_COUNT] = [
+        TimerClass::Wakeup,
+        TimerClass::Normal,
+        TimerClass::Background,
+    ];
+
+    /// 表示名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimerClass::Wakeup => "wakeup",
+            TimerClass::Normal => "normal",
+            TimerClass::Background => "background",
+        }
+    }
+
+    /// 1巡あたりに実行できるコールバック数
+    fn budget(&self) -> usize {
+        match self {
+            TimerClass::Wakeup => 64,
+            TimerClass::Normal => 16,
+            TimerClass::Background => 4,
+        }
+    }
+}
+
 /// タイマー構造体
 pub struct Timer {
     /// タイマーID
     id: u64,
     /// 期限切れ時刻（tick数）
     expires_at: u64,
+    /// コールバックのクラス
+    class: TimerClass,
     /// コールバック関数
     callback: Option<TimerCallback>,
 }
@@ -45,13 +98,15 @@ impl Timer {
     ///
     /// # Arguments
     /// * `delay_ticks` - 現在時刻からの遅延（tick数）
+    /// * `class` - コールバックのクラス
     /// * `callback` - 期限切れ時に実行するコールバック
-    pub fn new(delay_ticks: u64, callback: TimerCallback) -> Self {
+    pub fn new(delay_ticks: u64, class: TimerClass, callback: TimerCallback) -> Self {
         let id = TIMER_ID_COUNTER.fetch_add(1, AtomicOrdering::SeqCst);
         let expires_at = current_tick() + delay_ticks;
         Self {
             id,
             expires_at,
+            class,
             callback: Some(callback),
         }
     }
@@ -81,12 +136,57 @@ impl PartialEq for Timer {
 
 // タイマーキュー（期限でソートされた優先度付きキュー）
 lazy_static! {
-    static ref TIMER_QUEUE: Mutex<BinaryHeap<Timer>> = Mutex::new(BinaryHeap::new());
+    static ref TIMER_QUEUE: IrqSpinlock<BinaryHeap<Timer>> = IrqSpinlock::new(BinaryHeap::new());
 }
 
-// ペンディングキュー（割り込みハンドラから期限切れタイマーを受け取る）
-lazy_static! {
-    static ref PENDING_QUEUE: Mutex<VecDeque<Timer>> = Mutex::new(VecDeque::new());
+/// クラスごとのペンディングキュー（割り込みハンドラから期限切れタイマーを受け取る）
+struct PendingQueues {
+    queues: [VecDeque<Timer>; TIMER_CLASS_COUNT],
+    /// クラスごとの実行したコールバック数
+    executed: [u64; TIMER_CLASS_COUNT],
+    /// クラスごとの期限からコールバック開始までの最大の遅れ（tick）
+    max_lateness: [u64; TIMER_CLASS_COUNT],
+}
+
+impl PendingQueues {
+    const fn new() -> Self {
+        Self {
+            queues: [const { VecDeque::new() }; TIMER_CLASS_COUNT],
+            executed: [0; TIMER_CLASS_COUNT],
+            max_lateness: [0; TIMER_CLASS_COUNT],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// 残り予算のあるクラスのうち、最も優先度の高いクラスの先頭を取り出す
+    fn pop_next(&mut self, budgets: &[usize; TIMER_CLASS_COUNT]) -> Option<Timer> {
+        let class = (0..TIMER_CLASS_COUNT)
+            .find(|&class| budgets[class] > 0 && !self.queues[class].is_empty())?;
+        self.queues[class].pop_front()
+    }
+}
+
+static PENDING_QUEUE: IrqSpinlock<PendingQueues> = IrqSpinlock::new(PendingQueues::new());
+
+/// タイマーキューの統計情報
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimerStats {
+    /// 期限待ちのタイマー数
+    pub queued: usize,
+    /// 期限切れでコールバック実行待ちのタイマー数
+    pub pending: usize,
+    /// クラスごとのコールバック実行待ちのタイマー数
+    pub pending_by_class: [usize; TIMER_CLASS_COUNT],
+    /// クラスごとの実行したコールバック数
+    pub executed: [u64; TIMER_CLASS_COUNT],
+    /// クラスごとの期限からコールバック開始までの最大の遅れ（tick）
+    pub max_lateness: [u64; TIMER_CLASS_COUNT],
+    /// 最も近い期限（tick）。タイマーがなければNone
+    pub next_expiry: Option<u64>,
 }
 
 /// タイマーシステムを初期化
@@ -110,41 +210,35 @@ pub fn increment_tick() -> u64 {
     TICK_COUNT.fetch_add(1, AtomicOrdering::SeqCst) + 1
 }
 
+/// タイマーキューの統計情報を取得
+pub fn stats() -> TimerStats {
+    let queue = TIMER_QUEUE.lock();
+    let pending = PENDING_QUEUE.lock();
+    TimerStats {
+        queued: queue.len(),
+        pending: pending.len(),
+        pending_by_class: core::array::from_fn(|class| pending.queues[class].len()),
+        executed: pending.executed,
+        max_lateness: pending.max_lateness,
+        next_expiry: queue.peek().map(|timer| timer.expires_at),
+    }
+}
+
 /// タイマーをキューに登録
 ///
 /// # Arguments
 /// * `delay_ticks` - 現在時刻からの遅延（tick数）
+/// * `class` - コールバックのクラス（タスクの起床は `TimerClass::Wakeup`）
 /// * `callback` - 期限切れ時に実行するコールバック
 ///
 /// # Returns
 /// タイマーID
-pub fn register_timer(delay_ticks: u64, callback: TimerCallback) -> u64 {
-    let timer = Timer::new(delay_ticks, callback);
+pub fn register_timer(delay_ticks: u64, class: TimerClass, callback: TimerCallback) -> u64 {
+    let timer = Timer::new(delay_ticks, class, callback);
     let id = timer.id;
 
-    // 割り込みを無効化してからロックを取得（デッドロック回避）
-    let flags = unsafe {
-        let flags: u64;
-        core::arch::asm!(
-            "pushfq",
-            "pop {}",
-            "cli",
-            out(reg) flags,
-            options(nomem, nostack)
-        );
-        flags
-    };
-
-    let mut queue = TIMER_QUEUE.lock();
-    queue.push(timer);
-    drop(queue);
-
-    // 割り込みを復元
-    unsafe {
-        if flags & 0x200 != 0 {
-            core::arch::asm!("sti", options(nomem, nostack));
-        }
-    }
+    // ロック中は割り込みが無効になるため、タイマー割り込みとデッドロックしない
+    TIMER_QUEUE.lock().push(timer);
 
     id
 }
@@ -155,6 +249,12 @@ pub fn register_timer(delay_ticks: u64, callback: TimerCallback) -> u64 {
 /// 実際のコールバック実行は do_softirq() -> process_pending_timers() で行われます。
 pub fn check_timers() {
     let current = current_tick();
+
+    // 障害注入: 保留中は期限切れタイマーをキューに残したままにする
+    if crate::fault_inject::timer_delivery_held(current) {
+        return;
+    }
+
     let mut queue = TIMER_QUEUE.lock();
     let mut pending = PENDING_QUEUE.lock();
     let mut has_pending = false;
@@ -163,7 +263,7 @@ pub fn check_timers() {
     while let Some(timer) = queue.peek() {
         if timer.expires_at <= current {
             if let Some(timer) = queue.pop() {
-                pending.push_back(timer);
+                pending.queues[timer.class as usize].push_back(timer);
                 has_pending = true;
             }
         } else {
@@ -234,61 +334,42 @@ pub fn do_softirq() {
 /// ペンディングキューのタイマーを処理（メインループから呼ばれる）
 ///
 /// この関数は通常コンテキストで実行されるため、コールバック実行中も割り込みを受け付けられます。
+/// コールバックを1つ実行するたびにクラスを選び直すため、低いクラスの重いコールバックの
+/// 実行中に期限を迎えた起床は、そのコールバックの完了直後に実行されます。
 ///
 /// # TODO: Heavy Callback Latency Issue
-/// 現在の実装では、重いコールバックが登録されている場合、他のタイマーコールバック
-/// （sleep_msのunblock_taskなど）の実行が遅延し、sleepの精度が悪化する問題がある。
-///
-/// 改善案:
-/// 1. コールバックの優先度付け - unblock系を先に実行
-/// 2. コールバック実行の分割 - 一定数実行したらschedule()に譲る
-/// 3. 専用のワーカータスク - softirqではなく専用タスクでコールバック実行
-/// 4. コールバック内で重い処理を避ける設計指針の文書化
+/// 同じクラス内、または実行中のコールバック自体による遅延は残る。
+/// 重い処理はコールバックから `workqueue` に移すこと。
 ///
 /// See: https://github.com/jugeeeemu-tech/VitrOS/issues/6
 pub fn process_pending_timers() {
+    let mut budgets = TimerClass::ALL.map(|class| class.budget());
     loop {
-        // ペンディングキューから1つ取り出す
-        // 割り込みハンドラとのデッドロックを防ぐため、割り込みを無効化してからロック取得
+        // ロック中は割り込みが無効になり、ガードの解放で元の状態に戻る
         let timer = {
-            // 割り込みを無効化してRFLAGSを保存
-            let flags = unsafe {
-                let flags: u64;
-                core::arch::asm!(
-                    "pushfq",
-                    "pop {}",
-                    "cli",
-                    out(reg) flags,
-                    options(nomem, nostack)
-                );
-                flags
-            };
-
             let mut pending = PENDING_QUEUE.lock();
-            let timer = pending.pop_front();
-            drop(pending);
-
-            // 割り込みを元の状態に復元
-            unsafe {
-                if flags & 0x200 != 0 {
-                    core::arch::asm!("sti", options(nomem, nostack));
-                }
+            if pending.len() == 0 {
+                break;
             }
-
+            let timer = match pending.pop_next(&budgets) {
+                Some(timer) => timer,
+                None => {
+                    // 期限切れのあるクラスがすべて予算を使い切った: 次の巡へ
+                    budgets = TimerClass::ALL.map(|class| class.budget());
+                    continue;
+                }
+            };
+            let class = timer.class as usize;
+            let late = current_tick().saturating_sub(timer.expires_at);
+            pending.executed[class] += 1;
+            pending.max_lateness[class] = pending.max_lateness[class].max(late);
             timer
         };
+        budgets[timer.class as usize] -= 1;
 
-        match timer {
-            Some(mut timer) => {
-                // コールバックを実行（割り込み有効状態で実行される）
-                if let Some(callback) = timer.callback.take() {
-                    callback();
-                }
-            }
-            None => {
-                // キューが空になった
-                break;
-            }
+        // コールバックを実行（割り込み有効状態で実行される）
+        if let Some(callback) = timer.callback {
+            callback();
         }
     }
 }
@@ -0,0 +1,224 @@
+//! 軽量トレース
+//!
+//! コンテキストスイッチやシステムコールなどのイベントを固定長のリングバッファに記録します。
+//! パニック時のミニダンプから参照するため、記録・読み出しともにロックもヒープも使いません。
+//! 各スロットはシーケンス番号で検証し、書き込み途中のスロットは読み飛ばします。
+//!
+//! タスクは `marker` で任意のラベルをタイムラインに書き込めます（Compositorのフレーム、
+//! シェルのコマンドなど）。コンテキストスイッチと同じバッファに記録されるため、
+//! サブシステムをまたいだ遅延をシェルの `trace` コマンドで追えます。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// 保持するイベント数
+pub const CAPACITY: usize = 512;
+
+/// マーカーのラベルの最大バイト数（超えた分は切り捨てる）
+pub const LABEL_LEN: usize = 16;
+
+/// イベントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TraceKind {
+    /// コンテキストスイッチ（切り替え元タスクID、切り替え先タスクID）
+    ContextSwitch = 1,
+    /// システムコール（タスクID、システムコール番号）
+    Syscall = 2,
+    /// ユーザーモードのページフォルト（フォルトアドレス、エラーコード）
+    UserFault = 3,
+    /// タスクの終了（タスクID、0）
+    TaskExit = 4,
+    /// タスクが書き込んだマーカー（タスクID、0）。ラベルは `TraceEvent::label`
+    Marker = 5,
+    /// ロックの競合（ロックのアドレス、待ち時間（ナノ秒））
+    LockContention = 6,
+}
+
+impl TraceKind {
+    fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            1 => Some(TraceKind::ContextSwitch),
+            2 => Some(TraceKind::Syscall),
+            3 => Some(TraceKind::UserFault),
+            4 => Some(TraceKind::TaskExit),
+            5 => Some(TraceKind::Marker),
+            6 => Some(TraceKind::LockContention),
+            _ => None,
+        }
+    }
+
+    /// 表示名
+    pub fn name(self) -> &'static str {
+        match self {
+            TraceKind::ContextSwitch => "switch",
+            TraceKind::Syscall => "syscall",
+            TraceKind::UserFault => "ufault",
+            TraceKind::TaskExit => "exit",
+            TraceKind::Marker => "marker",
+            TraceKind::LockContention => "lock",
+        }
+    }
+}
+
+/// 記録されたイベント
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEvent {
+    /// 記録時のtick数
+    pub tick: u64,
+    pub kind: TraceKind,
+    /// 種類ごとの引数（`TraceKind` を参照）
+    pub args: [u64; 2],
+    /// マーカーのラベル（マーカー以外では空）
+    pub label: Label,
+}
+
+/// マーカーのラベル（ヒープを使わないよう固定長で保持する）
+#[derive(Clone, Copy)]
+pub struct Label {
+    bytes: [u8; LABEL_LEN],
+}
+
+impl Label {
+    const EMPTY: Self = Self {
+        bytes: [0; LABEL_LEN],
+    };
+
+    /// 文字列から作成（`LABEL_LEN` バイトを超える場合は文字の境界で切り捨てる）
+    fn new(s: &str) -> Self {
+        let mut len = s.len().min(LABEL_LEN);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; LABEL_LEN];
+        bytes[..len].copy_from_slice(&s.as_bytes()[..len]);
+        Self { bytes }
+    }
+
+    /// スロットに格納する形式に変換
+    fn to_words(self) -> [u64; 2] {
+        let (low, high) = self.bytes.split_at(8);
+        [
+            u64::from_le_bytes(low.try_into().unwrap_or_default()),
+            u64::from_le_bytes(high.try_into().unwrap_or_default()),
+        ]
+    }
+
+    fn from_words(words: [u64; 2]) -> Self {
+        let mut bytes = [0; LABEL_LEN];
+        bytes[..8].copy_from_slice(&words[0].to_le_bytes());
+        bytes[8..].copy_from_slice(&words[1].to_le_bytes());
+        Self { bytes }
+    }
+
+    /// ラベルの文字列
+    pub fn as_str(&self) -> &str {
+        let len = self.bytes.iter().position(|&b| b == 0).unwrap_or(LABEL_LEN);
+        core::str::from_utf8(&self.bytes[..len]).unwrap_or("?")
+    }
+}
+
+impl core::fmt::Debug for Label {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// リングバッファの1要素
+///
+/// `seq` は書き込み中は0、書き込み完了後は「イベント番号 + 1」になります。
+struct Slot {
+    seq: AtomicU64,
+    tick: AtomicU64,
+    kind: AtomicU64,
+    args: [AtomicU64; 2],
+    label: [AtomicU64; 2],
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            tick: AtomicU64::new(0),
+            kind: AtomicU64::new(0),
+            args: [AtomicU64::new(0), AtomicU64::new(0)],
+            label: [AtomicU64::new(0), AtomicU64::new(0)],
+        }
+    }
+}
+
+static SLOTS: [Slot; CAPACITY] = [const { Slot::new() }; CAPACITY];
+
+/// これまでに記録したイベントの総数（次のイベント番号）
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// イベントを記録
+///
+/// 割り込みハンドラを含むどこからでも呼び出せます。バッファが一周すると古いイベントを上書きします。
+pub fn record(kind: TraceKind, arg0: u64, arg1: u64) {
+    push(kind, [arg0, arg1], Label::EMPTY);
+}
+
+/// 現在のタスクのマーカーを記録
+///
+/// タイムラインに区切りを付けるためのもので、割り込みハンドラを含むどこからでも呼び出せます。
+///
+/// # Arguments
+/// * `label` - ラベル（`LABEL_LEN` バイトを超えた分は切り捨てる）
+pub fn marker(label: &str) {
+    let task_id = crate::sched::current_task_id_lockless().map_or(u64::MAX, |id| id.as_u64());
+    push(TraceKind::Marker, [task_id, 0], Label::new(label));
+}
+
+fn push(kind: TraceKind, args: [u64; 2], label: Label) {
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let slot = &SLOTS[(n % CAPACITY as u64) as usize];
+    slot.seq.store(0, Ordering::Release);
+    slot.tick
+        .store(crate::timer::current_tick(), Ordering::Relaxed);
+    slot.kind.store(kind as u64, Ordering::Relaxed);
+    slot.args[0].store(args[0], Ordering::Relaxed);
+    slot.args[1].store(args[1], Ordering::Relaxed);
+    let label = label.to_words();
+    slot.label[0].store(label[0], Ordering::Relaxed);
+    slot.label[1].store(label[1], Ordering::Relaxed);
+    slot.seq.store(n + 1, Ordering::Release);
+}
+
+/// 直近のイベントを古い順に列挙
+///
+/// # Arguments
+/// * `count` - 列挙する最大件数（`CAPACITY` まで）
+/// * `f` - 各イベントを受け取るクロージャ。書き込み途中や上書き済みのイベントは渡されません
+pub fn for_each_recent(count: usize, mut f: impl FnMut(&TraceEvent)) {
+    let end = NEXT.load(Ordering::Acquire);
+    let start = end.saturating_sub(count.min(CAPACITY) as u64);
+    for n in start..end {
+        let slot = &SLOTS[(n % CAPACITY as u64) as usize];
+        if slot.seq.load(Ordering::Acquire) != n + 1 {
+            continue;
+        }
+        let event = TraceKind::from_u64(slot.kind.load(Ordering::Relaxed)).map(|kind| TraceEvent {
+            tick: slot.tick.load(Ordering::Relaxed),
+            kind,
+            args: [
+                slot.args[0].load(Ordering::Relaxed),
+                slot.args[1].load(Ordering::Relaxed),
+            ],
+            label: Label::from_words([
+                slot.label[0].load(Ordering::Relaxed),
+                slot.label[1].load(Ordering::Relaxed),
+            ]),
+        });
+        // 読んでいる間に上書きされていなければ有効
+        if let Some(event) = event
+            && slot.seq.load(Ordering::Acquire) == n + 1
+        {
+            f(&event);
+        }
+    }
+}
+
+/// これまでに記録したイベントの総数
+pub fn total() -> u64 {
+    NEXT.load(Ordering::Relaxed)
+}
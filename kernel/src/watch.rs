@@ -0,0 +1,344 @@
+//! メトリクスの定期表示（watch）
+//!
+//! シェルの `watch <interval> <expr...>` で指定した式（`metrics`）を一定間隔で評価し、
+//! 現在値と直近の推移（スパークライン）を全ワークスペースに表示するウィンドウに描画します。
+//!
+//! 表示は専用のカーネルスレッドが行います。スレッドは最初の `start` で起動し、`stop` すると
+//! ウィンドウを閉じて終了します。描画は `Canvas` でタスク側に行い、Compositorには
+//! 1回の転送だけを渡します。
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+
+use crate::graphics::canvas::Canvas;
+use crate::graphics::compositor;
+use crate::graphics::window::Window;
+use crate::graphics::{CELL_HEIGHT, CELL_WIDTH, Region, TaskWriter, theme};
+use crate::metrics::{Expr, Sampler};
+use crate::sched::{self, kthread};
+use crate::sync::IrqSpinlock;
+
+/// 評価間隔の下限（ミリ秒）
+pub const MIN_INTERVAL_MS: u64 = 100;
+
+/// 評価間隔の上限（ミリ秒）
+pub const MAX_INTERVAL_MS: u64 = 60_000;
+
+/// 同時に表示できる式の数
+pub const MAX_EXPRS: usize = 8;
+
+/// スパークラインに表示する値の数
+const HISTORY: usize = 48;
+
+/// 1行の高さ（ピクセル）
+const ROW_HEIGHT: u32 = CELL_HEIGHT as u32 + 6;
+
+/// 式を表示する幅（文字数）
+const LABEL_COLUMNS: u32 = 18;
+
+/// 値を表示する幅（文字数）
+const VALUE_COLUMNS: u32 = 16;
+
+/// スパークラインの幅（ピクセル）
+const SPARKLINE_WIDTH: u32 = HISTORY as u32 * 2;
+
+/// 余白（ピクセル）
+const PADDING: u32 = 4;
+
+/// 画面右端からのマージン
+const MARGIN: u32 = 10;
+
+/// ウィンドウの上端（デバッグオーバーレイの下に配置）
+const TOP: u32 = 110;
+
+/// watchのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchError {
+    /// 評価間隔が範囲外
+    InvalidInterval,
+    /// 式がない、または多すぎる
+    InvalidExprCount,
+    /// 表示スレッドを起動できない
+    SpawnFailed,
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchError::InvalidInterval => write!(
+                f,
+                "Interval must be {}-{} ms",
+                MIN_INTERVAL_MS, MAX_INTERVAL_MS
+            ),
+            WatchError::InvalidExprCount => write!(f, "Specify 1-{} expressions", MAX_EXPRS),
+            WatchError::SpawnFailed => write!(f, "Failed to start the watch thread"),
+        }
+    }
+}
+
+/// 表示する内容
+#[derive(Clone)]
+pub struct WatchConfig {
+    /// 評価間隔（ミリ秒）
+    pub interval_ms: u64,
+    /// 評価する式
+    pub exprs: Vec<Expr>,
+}
+
+struct WatchState {
+    /// 表示中の内容（Noneなら停止）
+    config: Option<WatchConfig>,
+    /// 内容を変更するたびに増える番号（スレッドが変更を検出する）
+    generation: u64,
+    /// 表示スレッドが動作中か
+    running: bool,
+}
+
+static STATE: IrqSpinlock<WatchState> = IrqSpinlock::new(WatchState {
+    config: None,
+    generation: 0,
+    running: false,
+});
+
+/// 表示を開始（表示中なら内容を置き換える）
+///
+/// # Errors
+/// * `WatchError::InvalidInterval` - 評価間隔が範囲外の場合
+/// * `WatchError::InvalidExprCount` - 式がない、または `MAX_EXPRS` を超える場合
+/// * `WatchError::SpawnFailed` - 表示スレッドを起動できない場合
+pub fn start(interval_ms: u64, exprs: Vec<Expr>) -> Result<(), WatchError> {
+    if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval_ms) {
+        return Err(WatchError::InvalidInterval);
+    }
+    if exprs.is_empty() || exprs.len() > MAX_EXPRS {
+        return Err(WatchError::InvalidExprCount);
+    }
+
+    let spawn = {
+        let mut state = STATE.lock();
+        state.config = Some(WatchConfig { interval_ms, exprs });
+        state.generation += 1;
+        !core::mem::replace(&mut state.running, true)
+    };
+    if spawn && kthread::spawn("Watch", watch_thread).is_err() {
+        let mut state = STATE.lock();
+        state.config = None;
+        state.running = false;
+        return Err(WatchError::SpawnFailed);
+    }
+    Ok(())
+}
+
+/// 表示を停止（次の評価時にウィンドウを閉じる）
+///
+/// # Returns
+/// 表示中だった場合はtrue
+pub fn stop() -> bool {
+    let mut state = STATE.lock();
+    state.generation += 1;
+    state.config.take().is_some()
+}
+
+/// 表示中の内容
+pub fn current() -> Option<WatchConfig> {
+    STATE.lock().config.clone()
+}
+
+/// 式1つ分の表示状態
+struct Row {
+    sampler: Sampler,
+    /// 直近の値（古い順）。取得できなかった回は含まない
+    history: VecDeque<u64>,
+    /// 最新の値
+    latest: Option<u64>,
+}
+
+/// 表示中のウィンドウとキャンバス
+struct View {
+    window: Window,
+    writer: TaskWriter,
+    canvas: Canvas,
+    rows: Vec<Row>,
+}
+
+impl View {
+    fn create(config: &WatchConfig) -> Option<Self> {
+        let (screen_width, _) = compositor::screen_size();
+        let width =
+            (LABEL_COLUMNS + VALUE_COLUMNS) * CELL_WIDTH as u32 + SPARKLINE_WIDTH + PADDING * 3;
+        let height = config.exprs.len() as u32 * ROW_HEIGHT + PADDING;
+        let window = Window::create(
+            "watch",
+            screen_width.saturating_sub(width + MARGIN),
+            TOP,
+            width,
+            height,
+            theme::background(),
+        )
+        .ok()?;
+        // どのワークスペースでも表示する
+        let _ = window.set_workspace(None);
+        let canvas = match Canvas::create(width, height) {
+            Ok(canvas) => canvas,
+            Err(_) => {
+                let _ = window.close();
+                return None;
+            }
+        };
+        let rows = config
+            .exprs
+            .iter()
+            .map(|expr| Row {
+                sampler: Sampler::new(expr.clone()),
+                history: VecDeque::with_capacity(HISTORY),
+                latest: None,
+            })
+            .collect();
+        let writer = window.writer(theme::foreground());
+        Some(Self {
+            window,
+            writer,
+            canvas,
+            rows,
+        })
+    }
+
+    fn sample(&mut self) {
+        for row in &mut self.rows {
+            row.latest = row.sampler.sample();
+            if let Some(value) = row.latest {
+                if row.history.len() == HISTORY {
+                    row.history.pop_front();
+                }
+                row.history.push_back(value);
+            }
+        }
+    }
+
+    fn draw(&mut self) {
+        let mut text = TextBuffer::new();
+        self.canvas.clear_themed();
+        for (index, row) in self.rows.iter().enumerate() {
+            let y = PADDING + index as u32 * ROW_HEIGHT;
+            let expr = row.sampler.expr();
+
+            self.canvas
+                .draw_text(PADDING, y, &expr.text, theme::accent());
+
+            text.clear();
+            let _ = match row.latest {
+                Some(value) => write!(
+                    text,
+                    "{} {}{}",
+                    value,
+                    expr.unit(),
+                    if expr.is_rate() { "/s" } else { "" }
+                ),
+                None => write!(text, "-"),
+            };
+            let value_x = PADDING + LABEL_COLUMNS * CELL_WIDTH as u32;
+            self.canvas
+                .draw_text(value_x, y, text.as_str(), theme::foreground());
+
+            let (head, tail) = row.history.as_slices();
+            let mut samples = [0u64; HISTORY];
+            samples[..head.len()].copy_from_slice(head);
+            samples[head.len()..head.len() + tail.len()].copy_from_slice(tail);
+            let area = Region::new(
+                value_x + VALUE_COLUMNS * CELL_WIDTH as u32 + PADDING,
+                y,
+                SPARKLINE_WIDTH,
+                CELL_HEIGHT as u32,
+            );
+            self.canvas
+                .plot(area, &samples[..row.history.len()], 0, theme::foreground());
+        }
+
+        if self.canvas.present_to(&mut self.writer, 0, 0).is_ok() {
+            self.writer.flush();
+        }
+    }
+
+    fn close(self) {
+        let _ = self.canvas.destroy();
+        let _ = self.window.close();
+    }
+}
+
+/// 値の表示用の固定長バッファ（描画のたびに割り当てないため）
+struct TextBuffer {
+    buf: [u8; VALUE_COLUMNS as usize],
+    len: usize,
+}
+
+impl TextBuffer {
+    fn new() -> Self {
+        Self {
+            buf: [0; VALUE_COLUMNS as usize],
+            len: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn as_str(&self) -> &str {
+        // ASCIIのみを書き込む（数値と単位）
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for TextBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let take = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+/// 表示スレッド
+fn watch_thread() {
+    let mut view: Option<View> = None;
+    let mut generation = 0;
+    loop {
+        // 内容が変わった時だけ複製する
+        let (interval_ms, changed) = {
+            let mut state = STATE.lock();
+            let Some(config) = state.config.as_ref() else {
+                state.running = false;
+                break;
+            };
+            let changed = (state.generation != generation || view.is_none())
+                .then(|| (state.generation, config.clone()));
+            (config.interval_ms, changed)
+        };
+
+        // 行数が変わるとウィンドウの大きさも変わるため作り直す
+        if let Some((current_generation, config)) = changed {
+            if let Some(old) = view.take() {
+                old.close();
+            }
+            generation = current_generation;
+            view = View::create(&config);
+            if view.is_none() {
+                crate::warn!("watch: failed to create the window");
+                stop();
+                continue;
+            }
+        }
+        if let Some(view) = view.as_mut() {
+            view.sample();
+            view.draw();
+        }
+        sched::sleep_ms(interval_ms);
+    }
+
+    if let Some(view) = view {
+        view.close();
+    }
+}
@@ -0,0 +1,367 @@
+//! カーネルワーカープール
+//!
+//! 機能ごとに専用のカーネルタスクを作る代わりに、少数のワーカータスクで
+//! バックグラウンドジョブ（現在は `sync` コマンドによるブロックデバイスの書き戻し）を
+//! 処理します。
+//!
+//! # 設計
+//! - ジョブキューは優先度ごとの有界キュー（合計 `MAX_QUEUED_JOBS` 件まで）
+//! - 空きワーカーはブロックして待機し、ジョブ投入時に1つだけ起床させる
+//! - ワーカー数は実行時に変更可能。縮小時の余剰ワーカーは現在のジョブを
+//!   終えた後にパーク状態となり、再拡大時に再利用される
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::io::without_interrupts;
+use crate::sched::{self, Task, TaskId};
+use crate::sync::BlockingMutex;
+
+/// ワーカー数の上限
+pub const MAX_WORKERS: usize = 8;
+
+/// キューに保持できるジョブ数の上限（全優先度の合計）
+pub const MAX_QUEUED_JOBS: usize = 64;
+
+/// ワーカータスク名（Task::newが&'static strを要求するため固定で用意）
+const WORKER_NAMES: [&str; MAX_WORKERS] = [
+    "Worker0", "Worker1", "Worker2", "Worker3", "Worker4", "Worker5", "Worker6", "Worker7",
+];
+
+/// ジョブ型
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// ジョブの優先度
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobPriority {
+    /// 遅延に敏感なジョブ
+    High = 0,
+    /// 通常のジョブ
+    Normal = 1,
+    /// 空き時間に処理すればよいジョブ
+    Low = 2,
+}
+
+/// 優先度の段階数
+const PRIORITY_LEVELS: usize = 3;
+
+/// ワーカープールのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerPoolError {
+    /// ワーカープールが初期化されていない
+    NotInitialized,
+    /// ジョブキューが満杯
+    QueueFull,
+    /// ワーカー数が範囲外（1〜MAX_WORKERS）
+    InvalidWorkerCount,
+    /// ワーカータスクの作成に失敗
+    TaskCreationFailed,
+}
+
+impl core::fmt::Display for WorkerPoolError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            WorkerPoolError::NotInitialized => write!(f, "Worker pool is not initialized"),
+            WorkerPoolError::QueueFull => write!(f, "Worker pool job queue is full"),
+            WorkerPoolError::InvalidWorkerCount => {
+                write!(f, "Worker count must be between 1 and {}", MAX_WORKERS)
+            }
+            WorkerPoolError::TaskCreationFailed => write!(f, "Failed to create worker task"),
+        }
+    }
+}
+
+/// ワーカーの状態
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerState {
+    /// ジョブ待ちでブロック中
+    Idle,
+    /// ジョブ実行中（または起床処理中）
+    Busy,
+    /// プール縮小により休止中
+    Parked,
+}
+
+/// ワーカー1つ分の情報
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerInfo {
+    /// ワーカータスクのID
+    pub task_id: u64,
+    /// 現在の状態
+    pub state: WorkerState,
+    /// 完了したジョブ数
+    pub jobs_completed: u64,
+}
+
+/// ワーカープールの統計情報
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct WorkerPoolStats {
+    /// 稼働させるワーカー数（目標値）
+    pub target_workers: usize,
+    /// 優先度ごとの待機中ジョブ数（High, Normal, Low）
+    pub queued: [usize; PRIORITY_LEVELS],
+    /// 投入されたジョブ数
+    pub submitted: u64,
+    /// 完了したジョブ数
+    pub completed: u64,
+    /// キュー満杯で拒否されたジョブ数
+    pub rejected: u64,
+    /// 各ワーカーの情報
+    pub workers: Vec<WorkerInfo>,
+}
+
+struct WorkerSlot {
+    task_id: TaskId,
+    state: WorkerState,
+    jobs_completed: u64,
+}
+
+struct PoolState {
+    queues: [VecDeque<Job>; PRIORITY_LEVELS],
+    workers: Vec<WorkerSlot>,
+    target_workers: usize,
+    submitted: u64,
+    completed: u64,
+    rejected: u64,
+}
+
+impl PoolState {
+    const fn new() -> Self {
+        Self {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            workers: Vec::new(),
+            target_workers: 0,
+            submitted: 0,
+            completed: 0,
+            rejected: 0,
+        }
+    }
+
+    fn queued_jobs(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn pop_job(&mut self) -> Option<Job> {
+        self.queues.iter_mut().find_map(|q| q.pop_front())
+    }
+
+    fn slot_index(&self, task_id: TaskId) -> Option<usize> {
+        self.workers.iter().position(|w| w.task_id == task_id)
+    }
+
+    /// 稼働対象の空きワーカーを1つBusyにしてIDを返す
+    ///
+    /// ここでBusyにしておくことで、同じワーカーを二重に起床させないようにする
+    fn claim_idle_worker(&mut self) -> Option<TaskId> {
+        let active = self.target_workers.min(self.workers.len());
+        let slot = self.workers[..active]
+            .iter_mut()
+            .find(|w| w.state == WorkerState::Idle)?;
+        slot.state = WorkerState::Busy;
+        Some(slot.task_id)
+    }
+}
+
+/// グローバルワーカープール
+static POOL: Mutex<PoolState> = Mutex::new(PoolState::new());
+
+/// resize()の同時実行を防ぐロック（タスク作成中はブロック可能なMutexで保護）
+static RESIZE_LOCK: BlockingMutex<()> = BlockingMutex::new(());
+
+/// ワーカーループの次の動作
+enum WorkerAction {
+    Run(Job),
+    Sleep,
+}
+
+/// ワーカータスクのエントリポイント
+extern "C" fn worker_main() -> ! {
+    let task_id = sched::current_task_id();
+
+    loop {
+        let action = without_interrupts(|| {
+            let mut pool = POOL.lock();
+            let Some(idx) = pool.slot_index(task_id) else {
+                return WorkerAction::Sleep;
+            };
+
+            // 縮小によって稼働対象外になったワーカーは休止する
+            if idx >= pool.target_workers {
+                pool.workers[idx].state = WorkerState::Parked;
+                return WorkerAction::Sleep;
+            }
+
+            match pool.pop_job() {
+                Some(job) => {
+                    pool.workers[idx].state = WorkerState::Busy;
+                    WorkerAction::Run(job)
+                }
+                None => {
+                    pool.workers[idx].state = WorkerState::Idle;
+                    WorkerAction::Sleep
+                }
+            }
+        });
+
+        match action {
+            WorkerAction::Run(job) => {
+                job();
+                without_interrupts(|| {
+                    let mut pool = POOL.lock();
+                    pool.completed += 1;
+                    if let Some(idx) = pool.slot_index(task_id) {
+                        pool.workers[idx].jobs_completed += 1;
+                    }
+                });
+            }
+            // 状態の更新とブロックの間に起床された場合は、
+            // WAKEUP_PENDINGによりブロックせずに戻ってくる
+            WorkerAction::Sleep => sched::block_current_task(),
+        }
+    }
+}
+
+/// ワーカー数を変更
+///
+/// 拡大時はパーク中のワーカーを再利用し、足りなければ新しいワーカータスクを作成します。
+/// 縮小時は余剰ワーカーを起床させ、現在のジョブ完了後にパーク状態へ移行させます。
+///
+/// # Arguments
+/// * `count` - 新しいワーカー数（1〜MAX_WORKERS）
+///
+/// # Errors
+/// * `WorkerPoolError::InvalidWorkerCount` - ワーカー数が範囲外の場合
+/// * `WorkerPoolError::TaskCreationFailed` - ワーカータスクの作成に失敗した場合
+pub fn resize(count: usize) -> Result<(), WorkerPoolError> {
+    if count == 0 || count > MAX_WORKERS {
+        return Err(WorkerPoolError::InvalidWorkerCount);
+    }
+
+    let _resize_guard = RESIZE_LOCK.lock();
+
+    // 不足分のワーカータスクを作成（スロット登録を先に行い、起動時に自分を見つけられるようにする）
+    let existing = without_interrupts(|| POOL.lock().workers.len());
+    let mut new_tasks = Vec::new();
+    for &name in WORKER_NAMES.iter().take(count).skip(existing) {
+        let task = Task::new(name, sched::nice::DEFAULT, worker_main)
+            .map_err(|_| WorkerPoolError::TaskCreationFailed)?;
+        new_tasks.push(task);
+    }
+
+    let to_wake = without_interrupts(|| {
+        let mut pool = POOL.lock();
+        for task in &new_tasks {
+            pool.workers.push(WorkerSlot {
+                task_id: task.id(),
+                state: WorkerState::Busy,
+                jobs_completed: 0,
+            });
+        }
+        pool.target_workers = count;
+
+        // パーク中の稼働対象ワーカーと、稼働対象外になった空きワーカーを起床させる
+        let mut to_wake = Vec::new();
+        for (idx, slot) in pool.workers.iter_mut().enumerate() {
+            let wake = match slot.state {
+                WorkerState::Parked => idx < count,
+                WorkerState::Idle => idx >= count,
+                WorkerState::Busy => false,
+            };
+            if wake {
+                slot.state = WorkerState::Busy;
+                to_wake.push(slot.task_id);
+            }
+        }
+        to_wake
+    });
+
+    for task in new_tasks {
+        sched::add_task(task);
+    }
+    for task_id in to_wake {
+        sched::unblock_task(task_id);
+    }
+
+    crate::info!("Worker pool resized to {} workers", count);
+    Ok(())
+}
+
+/// ワーカープールを初期化
+///
+/// ヒープとスケジューラの初期化後に呼び出します。
+///
+/// # Arguments
+/// * `workers` - 初期ワーカー数（1〜MAX_WORKERS）
+///
+/// # Errors
+/// `resize` と同じ
+pub fn init(workers: usize) -> Result<(), WorkerPoolError> {
+    resize(workers)
+}
+
+/// ジョブを投入
+///
+/// # Arguments
+/// * `priority` - ジョブの優先度
+/// * `job` - ワーカータスク上で実行するクロージャ
+///
+/// # Errors
+/// * `WorkerPoolError::NotInitialized` - ワーカープールが初期化されていない場合
+/// * `WorkerPoolError::QueueFull` - ジョブキューが満杯の場合
+pub fn submit(priority: JobPriority, job: Job) -> Result<(), WorkerPoolError> {
+    let to_wake = without_interrupts(|| {
+        let mut pool = POOL.lock();
+        if pool.target_workers == 0 {
+            return Err(WorkerPoolError::NotInitialized);
+        }
+        if pool.queued_jobs() >= MAX_QUEUED_JOBS {
+            pool.rejected += 1;
+            return Err(WorkerPoolError::QueueFull);
+        }
+
+        pool.queues[priority as usize].push_back(job);
+        pool.submitted += 1;
+        Ok(pool.claim_idle_worker())
+    })?;
+
+    // ロック解放後に起床させる（全ワーカーがBusyなら、ジョブ完了後に拾われる）
+    if let Some(task_id) = to_wake {
+        sched::unblock_task(task_id);
+    }
+    Ok(())
+}
+
+/// ワーカープールの統計情報を取得
+#[allow(dead_code)]
+pub fn stats() -> WorkerPoolStats {
+    without_interrupts(|| {
+        let pool = POOL.lock();
+        WorkerPoolStats {
+            target_workers: pool.target_workers,
+            queued: [
+                pool.queues[0].len(),
+                pool.queues[1].len(),
+                pool.queues[2].len(),
+            ],
+            submitted: pool.submitted,
+            completed: pool.completed,
+            rejected: pool.rejected,
+            workers: pool
+                .workers
+                .iter()
+                .map(|w| WorkerInfo {
+                    task_id: w.task_id.as_u64(),
+                    state: w.state,
+                    jobs_completed: w.jobs_completed,
+                })
+                .collect(),
+        }
+    })
+}
@@ -0,0 +1,323 @@
+//! ワークキュー（Linuxのworkqueue風の遅延処理）
+//!
+//! 割り込みハンドラで行うには重い処理を、専用のカーネルワーカータスク（kworker）に
+//! 移すための仕組みです。softirqはタイマーコールバック専用のため、デバイスドライバの
+//! 後処理（キーボード入力による画面の切り替えなど）はここに投入します。
+//!
+//! # 設計
+//! - `queue_work` は割り込みコンテキストからも呼べる（状態は `IrqSpinlock` で保護）
+//! - 投入順に連番を振り、FIFOでワーカーに渡す
+//! - `flush_work` は呼び出し時点までに投入された処理がすべて完了するまでブロックする
+//! - ワーク内ではブロックしてよいが、ワーク内から `flush_work` を呼ぶことはできない
+//!
+//! 長時間かかるバックグラウンドジョブには、優先度付きの `worker_pool` を使います。
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::sched::{self, Task, TaskId};
+use crate::sync::IrqSpinlock;
+
+/// ワーカー数の上限
+pub const MAX_WORKERS: usize = 4;
+
+/// キューに保持できるワークの上限
+pub const MAX_QUEUED_WORK: usize = 256;
+
+/// ワーカータスク名（Task::newが&'static strを要求するため固定で用意）
+const WORKER_NAMES: [&str; MAX_WORKERS] = ["Kworker0", "Kworker1", "Kworker2", "Kworker3"];
+
+/// ワーク型
+pub type Work = Box<dyn FnOnce() + Send + 'static>;
+
+/// ワークキューのエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkQueueError {
+    /// ワークキューが初期化されていない
+    NotInitialized,
+    /// 既に初期化済み
+    AlreadyInitialized,
+    /// キューが満杯
+    QueueFull,
+    /// ワーカー数が範囲外（1〜MAX_WORKERS）
+    InvalidWorkerCount,
+    /// ワーカータスクの作成に失敗
+    TaskCreationFailed,
+    /// ワーカータスク自身からのflush（自分の完了を待つためデッドロックする）
+    FlushFromWorker,
+}
+
+impl core::fmt::Display for WorkQueueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            WorkQueueError::NotInitialized => write!(f, "Work queue is not initialized"),
+            WorkQueueError::AlreadyInitialized => write!(f, "Work queue is already initialized"),
+            WorkQueueError::QueueFull => write!(f, "Work queue is full"),
+            WorkQueueError::InvalidWorkerCount => {
+                write!(f, "Worker count must be between 1 and {}", MAX_WORKERS)
+            }
+            WorkQueueError::TaskCreationFailed => write!(f, "Failed to create kworker task"),
+            WorkQueueError::FlushFromWorker => write!(f, "Cannot flush from a kworker"),
+        }
+    }
+}
+
+/// ワークキューの統計情報
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct WorkQueueStats {
+    /// ワーカー数
+    pub workers: usize,
+    /// 待機中のワーク数
+    pub queued: usize,
+    /// 実行中のワーク数
+    pub running: usize,
+    /// 投入されたワーク数
+    pub queued_total: u64,
+    /// 完了したワーク数
+    pub completed: u64,
+    /// キュー満杯で拒否されたワーク数
+    pub rejected: u64,
+}
+
+struct QueuedWork {
+    /// 投入順の連番
+    seq: u64,
+    work: Work,
+}
+
+struct WorkerSlot {
+    task_id: TaskId,
+    /// 実行中のワークの連番
+    running: Option<u64>,
+    /// ワーク待ちでブロック中
+    idle: bool,
+}
+
+struct WorkQueueState {
+    queue: VecDeque<QueuedWork>,
+    workers: Vec<WorkerSlot>,
+    /// 次に振る連番
+    next_seq: u64,
+    completed: u64,
+    rejected: u64,
+    /// flush_workで完了を待っているタスク
+    flushers: Vec<TaskId>,
+}
+
+impl WorkQueueState {
+    const fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            workers: Vec::new(),
+            next_seq: 0,
+            completed: 0,
+            rejected: 0,
+            flushers: Vec::new(),
+        }
+    }
+
+    fn slot_index(&self, task_id: TaskId) -> Option<usize> {
+        self.workers.iter().position(|w| w.task_id == task_id)
+    }
+
+    /// 連番 `seq` 以前のワークがすべて完了しているか
+    fn completed_through(&self, seq: u64) -> bool {
+        // キューはFIFOなので先頭が最も古い
+        self.queue.front().is_none_or(|w| w.seq > seq)
+            && self
+                .workers
+                .iter()
+                .all(|w| w.running.is_none_or(|running| running > seq))
+    }
+}
+
+static STATE: IrqSpinlock<WorkQueueState> = IrqSpinlock::new(WorkQueueState::new());
+
+/// ワーカーループの次の動作
+enum WorkerAction {
+    Run(Work),
+    Sleep,
+}
+
+/// ワーカータスクのエントリポイント
+extern "C" fn worker_main() -> ! {
+    let task_id = sched::current_task_id();
+
+    loop {
+        let action = {
+            let mut state = STATE.lock();
+            match state.slot_index(task_id) {
+                None => WorkerAction::Sleep,
+                Some(idx) => match state.queue.pop_front() {
+                    Some(item) => {
+                        state.workers[idx].running = Some(item.seq);
+                        WorkerAction::Run(item.work)
+                    }
+                    None => {
+                        state.workers[idx].idle = true;
+                        WorkerAction::Sleep
+                    }
+                },
+            }
+        };
+
+        match action {
+            WorkerAction::Run(work) => {
+                work();
+                let flushers = {
+                    let mut state = STATE.lock();
+                    state.completed += 1;
+                    if let Some(idx) = state.slot_index(task_id) {
+                        state.workers[idx].running = None;
+                    }
+                    core::mem::take(&mut state.flushers)
+                };
+                // 起床したflush側が自分の条件を確認し直す
+                for flusher in flushers {
+                    sched::unblock_task(flusher);
+                }
+            }
+            // 状態の更新とブロックの間に起床された場合は、
+            // WAKEUP_PENDINGによりブロックせずに戻ってくる
+            WorkerAction::Sleep => sched::block_current_task(),
+        }
+    }
+}
+
+/// ワークキューを初期化してワーカータスクを起動
+///
+/// ヒープとスケジューラの初期化後に呼び出します。
+///
+/// # Arguments
+/// * `workers` - ワーカー数（1〜MAX_WORKERS）
+///
+/// # Errors
+/// * `WorkQueueError::InvalidWorkerCount` - ワーカー数が範囲外の場合
+/// * `WorkQueueError::AlreadyInitialized` - 既に初期化済みの場合
+/// * `WorkQueueError::TaskCreationFailed` - ワーカータスクの作成に失敗した場合
+pub fn init(workers: usize) -> Result<(), WorkQueueError> {
+    if workers == 0 || workers > MAX_WORKERS {
+        return Err(WorkQueueError::InvalidWorkerCount);
+    }
+    if !STATE.lock().workers.is_empty() {
+        return Err(WorkQueueError::AlreadyInitialized);
+    }
+
+    let tasks = WORKER_NAMES[..workers]
+        .iter()
+        .map(|name| Task::new(name, sched::nice::DEFAULT, worker_main))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| WorkQueueError::TaskCreationFailed)?;
+
+    // スロット登録を先に行い、起動したワーカーが自分を見つけられるようにする
+    {
+        let mut state = STATE.lock();
+        // 4KBを超えるヒープ割り当ては解放されないため、キューは最大長で一度だけ確保する
+        state.queue.reserve_exact(MAX_QUEUED_WORK);
+        for task in &tasks {
+            state.workers.push(WorkerSlot {
+                task_id: task.id(),
+                running: None,
+                idle: false,
+            });
+        }
+    }
+    for task in tasks {
+        sched::add_task(task);
+    }
+
+    crate::info!("Work queue initialized with {} kworkers", workers);
+    Ok(())
+}
+
+/// ワークを投入（割り込みコンテキストからも呼び出し可能）
+///
+/// # Arguments
+/// * `work` - ワーカータスク上で実行するクロージャ
+///
+/// # Errors
+/// * `WorkQueueError::NotInitialized` - ワークキューが初期化されていない場合
+/// * `WorkQueueError::QueueFull` - キューが満杯の場合
+pub fn queue_work(work: impl FnOnce() + Send + 'static) -> Result<(), WorkQueueError> {
+    // 割り当てはロックの外で行う
+    let work: Work = Box::new(work);
+    let to_wake = {
+        let mut state = STATE.lock();
+        if state.workers.is_empty() {
+            return Err(WorkQueueError::NotInitialized);
+        }
+        if state.queue.len() >= MAX_QUEUED_WORK {
+            state.rejected += 1;
+            return Err(WorkQueueError::QueueFull);
+        }
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.queue.push_back(QueuedWork { seq, work });
+
+        // 空きワーカーを1つ起床させる（ここでidleを外し、二重に起床させない）
+        state.workers.iter_mut().find(|w| w.idle).map(|slot| {
+            slot.idle = false;
+            slot.task_id
+        })
+    };
+
+    // ロック解放後に起床させる（全ワーカーが実行中なら、完了後に拾われる）
+    if let Some(task_id) = to_wake {
+        sched::unblock_task(task_id);
+    }
+    Ok(())
+}
+
+/// 呼び出し時点までに投入されたワークがすべて完了するまで待つ
+///
+/// 割り込みコンテキストからは呼び出せません。
+///
+/// # Errors
+/// * `WorkQueueError::NotInitialized` - ワークキューが初期化されていない場合
+/// * `WorkQueueError::FlushFromWorker` - ワーカータスク（ワーク内）から呼び出した場合
+pub fn flush_work() -> Result<(), WorkQueueError> {
+    let task_id = sched::current_task_id();
+    let target = {
+        let state = STATE.lock();
+        if state.workers.is_empty() {
+            return Err(WorkQueueError::NotInitialized);
+        }
+        if state.slot_index(task_id).is_some() {
+            return Err(WorkQueueError::FlushFromWorker);
+        }
+        match state.next_seq.checked_sub(1) {
+            Some(target) => target,
+            None => return Ok(()),
+        }
+    };
+
+    loop {
+        {
+            let mut state = STATE.lock();
+            if state.completed_through(target) {
+                return Ok(());
+            }
+            state.flushers.push(task_id);
+        }
+        // 登録とブロックの間に起床された場合は、WAKEUP_PENDINGによりすぐに戻る
+        sched::block_current_task();
+    }
+}
+
+/// ワークキューの統計情報を取得
+#[allow(dead_code)]
+pub fn stats() -> WorkQueueStats {
+    let state = STATE.lock();
+    WorkQueueStats {
+        workers: state.workers.len(),
+        queued: state.queue.len(),
+        running: state.workers.iter().filter(|w| w.running.is_some()).count(),
+        queued_total: state.next_seq,
+        completed: state.completed,
+        rejected: state.rejected,
+    }
+}
@@ -0,0 +1,451 @@
+//! 圧縮メモリスワップ（zram相当）
+//!
+//! 専用の仮想アドレス領域から「追い出し可能なページ」を払い出し、空き物理フレームが
+//! 少なくなると、しばらくアクセスされていないページをLZ4で圧縮してRAM上のストアへ
+//! 移します。追い出したページのPTEは非Presentになり、次にアクセスされたときの
+//! ページフォルトで透過的に展開・再マップされます。
+//!
+//! ブロックキャッシュやトレースバッファのように、内容を失ってはいけないが
+//! 常にアクセスされるわけではないデータの置き場所として使用します。
+//!
+//! # 制約
+//! - 追い出し可能ページはページフォルトハンドラ内で復元されるため、ZRAMのロックや
+//!   ヒープアロケータのロックを保持したままアクセスしてはいけません。
+//! - 仮想アドレス領域はバンプ方式で払い出し、解放しても再利用しません。
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+use vitros_common::lz4;
+
+use crate::io::without_interrupts;
+use crate::paging::{self, PAGE_SIZE, PageTableFlags, PagingError};
+use crate::{frame_allocator, info, sched, warn};
+
+/// 追い出し可能ページ用の仮想アドレス領域の先頭（PML4[288]）
+const REGION_BASE: u64 = 0xFFFF_9000_0000_0000;
+
+/// 追い出し可能ページ用の仮想アドレス領域のページ数（256MB）
+const REGION_PAGES: u64 = 64 * 1024;
+
+/// 圧縮後のサイズがこれを超えるページは追い出さない（圧縮の効果が薄いため）
+const MAX_COMPRESSED_SIZE: usize = PAGE_SIZE * 3 / 4;
+
+/// 圧縮ストアの既定の上限（バイト）
+const DEFAULT_BUDGET_BYTES: usize = 8 * 1024 * 1024;
+
+/// 空きフレームがこれを下回ると回収デーモンが追い出しを行う（4MB）
+const LOW_WATERMARK_FRAMES: usize = 1024;
+
+/// 回収デーモンが1回に追い出すページ数
+const RECLAIM_BATCH: usize = 64;
+
+/// 回収デーモンの起床間隔（ミリ秒）
+const RECLAIM_INTERVAL_MS: u64 = 1000;
+
+/// ZRAM操作のエラー型
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZramError {
+    /// ページ数が0
+    InvalidSize,
+    /// 追い出し可能ページ用の仮想アドレス領域を使い切った
+    OutOfVirtualSpace,
+    /// 物理フレームを確保できない
+    OutOfFrames,
+    /// 指定したアドレスは追い出し可能ページではない
+    NotEvictable,
+    /// 圧縮しても小さくならない
+    Incompressible,
+    /// 圧縮ストアの上限に達した
+    BudgetExceeded,
+    /// 自己テストで内容の不一致を検出
+    Corrupted,
+    /// ページテーブル操作に失敗
+    Paging(PagingError),
+}
+
+impl core::fmt::Display for ZramError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ZramError::InvalidSize => write!(f, "Page count must be non-zero"),
+            ZramError::OutOfVirtualSpace => write!(f, "Evictable region exhausted"),
+            ZramError::OutOfFrames => write!(f, "Out of physical frames"),
+            ZramError::NotEvictable => write!(f, "Address is not an evictable page"),
+            ZramError::Incompressible => write!(f, "Page is not compressible"),
+            ZramError::BudgetExceeded => write!(f, "Compressed store is full"),
+            ZramError::Corrupted => write!(f, "Page contents corrupted"),
+            ZramError::Paging(e) => write!(f, "Paging error: {}", e),
+        }
+    }
+}
+
+impl From<PagingError> for ZramError {
+    fn from(e: PagingError) -> Self {
+        ZramError::Paging(e)
+    }
+}
+
+/// 圧縮済みページ
+struct CompressedPage {
+    /// LZ4ブロック
+    data: Vec<u8>,
+    /// 追い出し前のPTEフラグ（復元時に使用）
+    flags: u64,
+}
+
+/// ZRAMの統計情報
+#[derive(Debug, Clone, Copy)]
+pub struct ZramStats {
+    /// 物理フレームに載っている追い出し可能ページ数
+    pub resident_pages: usize,
+    /// 圧縮ストアにあるページ数
+    pub stored_pages: usize,
+    /// 圧縮ストアの使用量（バイト）
+    pub compressed_bytes: usize,
+    /// 圧縮ストアの上限（バイト）
+    pub budget_bytes: usize,
+    /// 追い出した回数
+    pub swap_outs: u64,
+    /// フォルトで復元した回数
+    pub swap_ins: u64,
+    /// 圧縮が効かず追い出しを見送った回数
+    pub rejected: u64,
+}
+
+struct ZramState {
+    /// 次に払い出す仮想アドレス
+    next_virt: u64,
+    /// 物理フレームに載っているページ（Second-Chanceの巡回順）
+    resident: VecDeque<u64>,
+    /// 仮想アドレス → 圧縮済みページ
+    store: BTreeMap<u64, CompressedPage>,
+    /// 圧縮用の作業バッファ（タスクスタックを圧迫しないよう静的に確保）
+    scratch: [u8; lz4::compress_bound(PAGE_SIZE)],
+    compressed_bytes: usize,
+    budget_bytes: usize,
+    swap_outs: u64,
+    swap_ins: u64,
+    rejected: u64,
+}
+
+static ZRAM: Mutex<ZramState> = Mutex::new(ZramState {
+    next_virt: REGION_BASE,
+    resident: VecDeque::new(),
+    store: BTreeMap::new(),
+    scratch: [0; lz4::compress_bound(PAGE_SIZE)],
+    compressed_bytes: 0,
+    budget_bytes: DEFAULT_BUDGET_BYTES,
+    swap_outs: 0,
+    swap_ins: 0,
+    rejected: 0,
+});
+
+/// アドレスが追い出し可能ページの領域内かどうか
+fn in_region(virt: u64) -> bool {
+    (REGION_BASE..REGION_BASE + REGION_PAGES * PAGE_SIZE as u64).contains(&virt)
+}
+
+impl ZramState {
+    /// 1ページを圧縮してストアへ移し、物理フレームを解放
+    ///
+    /// 呼び出し元は割り込みを無効にしている必要がある（圧縮中に内容が変わらないように）
+    fn swap_out(&mut self, virt: u64) -> Result<(), ZramError> {
+        // SAFETY: virtはこのモジュールがマップした追い出し可能ページで、Presentである
+        let page = unsafe { core::slice::from_raw_parts(virt as *const u8, PAGE_SIZE) };
+        let size = match lz4::compress(page, &mut self.scratch) {
+            Some(size) if size <= MAX_COMPRESSED_SIZE => size,
+            _ => {
+                self.rejected += 1;
+                return Err(ZramError::Incompressible);
+            }
+        };
+        if self.compressed_bytes + size > self.budget_bytes {
+            return Err(ZramError::BudgetExceeded);
+        }
+
+        let data = self.scratch[..size].to_vec();
+        let (phys, flags) = paging::unmap_to_swap(virt)?;
+        if let Err(e) = frame_allocator::free_frame(phys) {
+            warn!("[ZRAM] Failed to free frame 0x{:X}: {}", phys, e);
+        }
+
+        self.compressed_bytes += size;
+        self.store.insert(virt, CompressedPage { data, flags });
+        self.swap_outs += 1;
+        Ok(())
+    }
+
+    /// ストアから1ページを展開して再マップ
+    fn swap_in(&mut self, virt: u64) -> Result<(), ZramError> {
+        let page = self.store.remove(&virt).ok_or(ZramError::NotEvictable)?;
+        let Some(frame) = frame_allocator::alloc_frame() else {
+            self.store.insert(virt, page);
+            return Err(ZramError::OutOfFrames);
+        };
+
+        // SAFETY: 確保直後のフレームは直接マッピング経由で排他的にアクセスできる
+        let dest = unsafe {
+            core::slice::from_raw_parts_mut(paging::phys_to_virt(frame)? as *mut u8, PAGE_SIZE)
+        };
+        // 自分で圧縮したデータなので展開に失敗することはない
+        if let Err(e) = lz4::decompress(&page.data, dest) {
+            panic!("[ZRAM] Corrupted page at 0x{:X}: {}", virt, e);
+        }
+
+        // Accessed/Dirtyは追い出し前の状態を引き継がない
+        let stale = PageTableFlags::Accessed as u64 | PageTableFlags::Dirty as u64;
+        paging::map_page(virt, frame, page.flags & !stale)?;
+
+        self.compressed_bytes -= page.data.len();
+        self.resident.push_back(virt);
+        self.swap_ins += 1;
+        Ok(())
+    }
+}
+
+/// 追い出し可能なページを確保
+///
+/// 確保したページはゼロクリアされ、書き込み可能でマップされます。
+///
+/// # Arguments
+/// * `count` - ページ数
+///
+/// # Returns
+/// 先頭の仮想アドレス
+///
+/// # Errors
+/// * `ZramError::InvalidSize` - countが0の場合
+/// * `ZramError::OutOfVirtualSpace` - 仮想アドレス領域が足りない場合
+/// * `ZramError::OutOfFrames` - 物理フレームが足りない場合
+#[allow(dead_code)]
+pub fn alloc_pages(count: usize) -> Result<u64, ZramError> {
+    if count == 0 {
+        return Err(ZramError::InvalidSize);
+    }
+    let flags = PageTableFlags::Writable as u64;
+
+    without_interrupts(|| {
+        let mut zram = ZRAM.lock();
+        let base = zram.next_virt;
+        let end = base + (count * PAGE_SIZE) as u64;
+        if !in_region(end - 1) {
+            return Err(ZramError::OutOfVirtualSpace);
+        }
+
+        for i in 0..count {
+            let virt = base + (i * PAGE_SIZE) as u64;
+            let mapped = frame_allocator::alloc_frame()
+                .ok_or(ZramError::OutOfFrames)
+                .and_then(|frame| {
+                    paging::map_page(virt, frame, flags).map_err(|e| {
+                        let _ = frame_allocator::free_frame(frame);
+                        ZramError::from(e)
+                    })
+                });
+            if let Err(e) = mapped {
+                // 途中まで確保したページを戻す（next_virtは進めないので仮想アドレスは再利用される）
+                for j in 0..i {
+                    let virt = base + (j * PAGE_SIZE) as u64;
+                    zram.resident.retain(|&v| v != virt);
+                    if let Ok(phys) = paging::unmap_page(virt) {
+                        let _ = frame_allocator::free_frame(phys);
+                    }
+                }
+                return Err(e);
+            }
+            // SAFETY: 今マップしたページなので書き込み可能
+            unsafe { core::ptr::write_bytes(virt as *mut u8, 0, PAGE_SIZE) };
+            zram.resident.push_back(virt);
+        }
+
+        zram.next_virt = end;
+        Ok(base)
+    })
+}
+
+/// 追い出し可能なページを解放
+///
+/// 圧縮ストアにあるページはストアから破棄されます。
+///
+/// # Arguments
+/// * `virt` - `alloc_pages` が返したアドレス
+/// * `count` - ページ数
+///
+/// # Errors
+/// * `ZramError::NotEvictable` - 領域外のアドレス、または確保されていないページを含む場合
+#[allow(dead_code)]
+pub fn free_pages(virt: u64, count: usize) -> Result<(), ZramError> {
+    if !virt.is_multiple_of(PAGE_SIZE as u64) || !in_region(virt) {
+        return Err(ZramError::NotEvictable);
+    }
+
+    without_interrupts(|| {
+        let mut zram = ZRAM.lock();
+        for i in 0..count {
+            let page = virt + (i * PAGE_SIZE) as u64;
+            if page >= zram.next_virt {
+                return Err(ZramError::NotEvictable);
+            }
+            if let Some(stored) = zram.store.remove(&page) {
+                zram.compressed_bytes -= stored.data.len();
+                continue;
+            }
+            zram.resident.retain(|&v| v != page);
+            let phys = paging::unmap_page(page)?;
+            if let Err(e) = frame_allocator::free_frame(phys) {
+                warn!("[ZRAM] Failed to free frame 0x{:X}: {}", phys, e);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// コールドなページを圧縮ストアへ追い出す
+///
+/// 常駐ページを巡回し、前回の巡回以降にアクセスされたページは
+/// Accessedビットをクリアして後回しにします（Second-Chance）。
+///
+/// # Arguments
+/// * `target` - 追い出すページ数の目標
+///
+/// # Returns
+/// 実際に追い出したページ数
+pub fn reclaim(target: usize) -> usize {
+    without_interrupts(|| {
+        let mut zram = ZRAM.lock();
+        let mut reclaimed = 0;
+        // 全ページを2周すればAccessedビットは一度クリアされている
+        let mut budget = zram.resident.len() * 2;
+
+        while reclaimed < target && budget > 0 {
+            budget -= 1;
+            let Some(virt) = zram.resident.pop_front() else {
+                break;
+            };
+            match paging::test_and_clear_accessed(virt) {
+                Ok(true) => {
+                    zram.resident.push_back(virt);
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("[ZRAM] Dropping page 0x{:X} from reclaim list: {}", virt, e);
+                    continue;
+                }
+            }
+
+            match zram.swap_out(virt) {
+                Ok(()) => reclaimed += 1,
+                Err(ZramError::BudgetExceeded) => {
+                    zram.resident.push_front(virt);
+                    break;
+                }
+                Err(_) => zram.resident.push_back(virt),
+            }
+        }
+        reclaimed
+    })
+}
+
+/// ページフォルト時に追い出したページを復元（IDTから呼ばれる）
+///
+/// # Arguments
+/// * `fault_addr` - CR2の値
+///
+/// # Returns
+/// 復元してアクセスを再開できる場合はtrue
+pub fn handle_page_fault(fault_addr: u64) -> bool {
+    let virt = fault_addr & !(PAGE_SIZE as u64 - 1);
+    if !in_region(virt) || !paging::is_swapped_out(virt) {
+        return false;
+    }
+
+    // 例外ハンドラ内（割り込み無効）。ロック保持中のアクセスはデッドロックするので検出する
+    let Some(mut zram) = ZRAM.try_lock() else {
+        warn!("[ZRAM] Fault at 0x{:X} while store is locked", fault_addr);
+        return false;
+    };
+    match zram.swap_in(virt) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("[ZRAM] Failed to restore page 0x{:X}: {}", virt, e);
+            false
+        }
+    }
+}
+
+/// 圧縮ストアの上限を設定
+///
+/// 既にストアにあるページは上限を超えていても破棄されません。
+pub fn set_budget(bytes: usize) {
+    without_interrupts(|| ZRAM.lock().budget_bytes = bytes);
+}
+
+/// 統計情報を取得
+pub fn stats() -> ZramStats {
+    without_interrupts(|| {
+        let zram = ZRAM.lock();
+        ZramStats {
+            resident_pages: zram.resident.len(),
+            stored_pages: zram.store.len(),
+            compressed_bytes: zram.compressed_bytes,
+            budget_bytes: zram.budget_bytes,
+            swap_outs: zram.swap_outs,
+            swap_ins: zram.swap_ins,
+            rejected: zram.rejected,
+        }
+    })
+}
+
+/// 追い出しと透過的な復元を確認する自己テスト
+///
+/// ページを確保してパターンを書き込み、全ページを追い出したあと読み戻して検証します。
+///
+/// # Returns
+/// 追い出されたページ数
+///
+/// # Errors
+/// * `ZramError::Corrupted` - 読み戻した内容が一致しない場合
+/// * その他 `alloc_pages` と同じ
+pub fn self_test(count: usize) -> Result<usize, ZramError> {
+    let base = alloc_pages(count)?;
+    let words = PAGE_SIZE / 8;
+
+    for i in 0..count * words {
+        // SAFETY: alloc_pagesで確保した範囲内
+        unsafe { ((base as *mut u64).add(i)).write_volatile((i / 64) as u64) };
+    }
+
+    // 書き込みでAccessedが立っているので、2周目で追い出される
+    let evicted = reclaim(count);
+
+    let mut result = Ok(evicted);
+    for i in 0..count * words {
+        // SAFETY: alloc_pagesで確保した範囲内（追い出されていればフォルトで復元される）
+        let value = unsafe { ((base as *const u64).add(i)).read_volatile() };
+        if value != (i / 64) as u64 {
+            result = Err(ZramError::Corrupted);
+            break;
+        }
+    }
+
+    free_pages(base, count)?;
+    result
+}
+
+/// 回収デーモンのエントリポイント
+///
+/// 空きフレームが少なくなったらコールドなページを圧縮ストアへ追い出します。
+pub extern "C" fn zramd_task() -> ! {
+    info!("[ZRAM] Reclaim daemon started");
+    loop {
+        sched::sleep_ms(RECLAIM_INTERVAL_MS);
+        if frame_allocator::stats().free_frames < LOW_WATERMARK_FRAMES {
+            let reclaimed = reclaim(RECLAIM_BATCH);
+            if reclaimed > 0 {
+                info!("[ZRAM] Reclaimed {} pages", reclaimed);
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+#!/bin/bash -e
+# カーネルのシンボル表を .ksyms セクションに埋め込む（パニック時のバックトレース用）
+#
+# 形式: "<16桁の16進アドレス> <関数名>\n" をアドレス順に並べ、セクションの残りを0で埋める
+# （kernel/src/ksyms.rs を参照）
+ELF="$1"
+if [ -z "$ELF" ]; then
+    echo "usage: $0 <kernel.elf>" >&2
+    exit 1
+fi
+
+# 予約領域の大きさ（セクションの大きさを変えないよう、ちょうどこの大きさで書き込む）
+SIZE_HEX=$(objdump -h "$ELF" | awk '$2 == ".ksyms" { print $3 }')
+if [ -z "$SIZE_HEX" ]; then
+    echo "  $ELF has no .ksyms section, skipping symbol table" >&2
+    exit 0
+fi
+SIZE=$((16#$SIZE_HEX))
+
+SYMS=$(mktemp)
+trap 'rm -f "$SYMS"' EXIT
+
+# コードのシンボルのみ（デマングルし、Rustのハッシュ接尾辞は除く）
+nm -n -C --defined-only "$ELF" \
+    | sed -n 's/^\([0-9a-f]\{16\}\) [tT] \(.*\)$/\1 \2/p' \
+    | sed 's/::h[0-9a-f]\{16\}$//' > "$SYMS"
+
+COUNT=$(wc -l < "$SYMS")
+BYTES=$(wc -c < "$SYMS")
+if [ "$BYTES" -ge "$SIZE" ]; then
+    echo "  warning: symbol table truncated ($BYTES > $SIZE bytes)" >&2
+fi
+# 収まらない分は捨て、残りを0で埋める（途中で切れた最後の行はカーネルが無視する）
+head -c $((SIZE - 1)) "$SYMS" > "$SYMS.blob"
+truncate -s "$SIZE" "$SYMS.blob"
+objcopy --update-section .ksyms="$SYMS.blob" "$ELF"
+rm -f "$SYMS.blob"
+echo "  Embedded $COUNT symbols into $ELF"
@@ -27,6 +27,22 @@ cp ${PATH_TO_EFI} mnt/EFI/BOOT/BOOTX64.EFI
 # カーネルをコピー（将来的にブートローダが読み込む）
 cp target/x86_64-unknown-none/debug/vitros-kernel mnt/kernel.elf
 
+# パニック時のバックトレース用にシンボル表を埋め込む
+scripts/embed_ksyms.sh mnt/kernel.elf
+
+# initrd/ ディレクトリがあれば cpio (newc) にまとめて initrd.img として配置
+if [ -d initrd ]; then
+    echo "Packing initrd/ into initrd.img..."
+    (cd initrd && find . | cpio -o -H newc --quiet) > mnt/initrd.img
+fi
+
+# カーネルコマンドライン（CMDLINE環境変数、なければ cmdline.txt があれば）を配置
+if [ -n "$CMDLINE" ]; then
+    echo "$CMDLINE" > mnt/cmdline.txt
+elif [ -f cmdline.txt ]; then
+    cp cmdline.txt mnt/cmdline.txt
+fi
+
 # QEMU起動
 echo "Launching QEMU..."
 
@@ -62,13 +78,34 @@ else
     fi
 fi
 
+# ネットワークブート（NETBOOT=1）: ESPを接続せず、mnt/ をTFTPで配信してPXEで起動
+BOOT_OPTS="-drive format=raw,file=fat:rw:mnt"
+if [ "$NETBOOT" = "1" ]; then
+    echo "  Network boot enabled (PXE/TFTP from mnt/)"
+    BOOT_OPTS="-netdev user,id=net0,tftp=mnt,bootfile=EFI/BOOT/BOOTX64.EFI -device virtio-net-pci,netdev=net0 -boot n"
+fi
+
+# レガシーIDEディスク（ATA_DISK=none で無効化）
+# q35のICH9はAHCIのみなので、0x1F0/IRQ14を持つpiix3-ideにディスクを接続する
+ATA_DISK="${ATA_DISK:-ata.img}"
+ATA_OPTS=""
+if [ "$ATA_DISK" != "none" ]; then
+    if [ ! -f "$ATA_DISK" ]; then
+        echo "  Creating 64MiB IDE disk image: $ATA_DISK"
+        truncate -s 64M "$ATA_DISK"
+    fi
+    echo "  IDE disk attached: $ATA_DISK"
+    ATA_OPTS="-device piix3-ide,id=ide -drive id=ata0,file=$ATA_DISK,format=raw,if=none -device ide-hd,drive=ata0,bus=ide.0"
+fi
+
 qemu-system-x86_64 \
     -machine q35,accel=kvm:tcg \
     -m 4G \
     -no-reboot \
     -no-shutdown \
     -bios /usr/share/ovmf/OVMF.fd \
-    -drive format=raw,file=fat:rw:mnt \
+    $BOOT_OPTS \
+    $ATA_OPTS \
     -device isa-debug-exit,iobase=0xf4,iosize=0x01 \
     -chardev stdio,id=char_com1,mux=on,logfile=serial.log \
     -serial chardev:char_com1 \